@@ -0,0 +1,116 @@
+//! Benchmarks of the code the initial sync spends its time in, run with `cargo bench`.
+//! The payloads come from the synthetic chain of the sim module, so they don't need peers.
+
+use btc_node::{
+    blockchain::{txs::Txs, utxo_set::UtxoSet},
+    merkle_tree::merkle_tree_root,
+    message::{block::BlockMessage, Message},
+    raw_transaction::{Outpoint, RawTransaction, TxIn, TxOut},
+    script::trace_script,
+    sim::{generate_blocks, SimConfig},
+};
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+
+// Transactions of the large block given to the utxo set, about the size of a full testnet block
+const LARGE_BLOCK_TXS: usize = 4000;
+// Txids hashed into a merkle root
+const MERKLE_TXIDS: usize = 4096;
+
+fn sim_blocks() -> Vec<BlockMessage> {
+    generate_blocks(&SimConfig {
+        height: 50,
+        txs_per_block: 20,
+        ..SimConfig::default()
+    })
+    .unwrap()
+}
+
+fn read_messages(c: &mut Criterion) {
+    let mut payloads = vec![];
+    for block in sim_blocks() {
+        block.write_to(&mut payloads).unwrap();
+    }
+
+    c.bench_function("Message::read_from 50 blocks", |b| {
+        b.iter(|| {
+            let mut reader = &payloads[..];
+            while !reader.is_empty() {
+                black_box(Message::read_from(&mut reader).unwrap());
+            }
+        })
+    });
+}
+
+fn append_large_block(c: &mut Criterion) {
+    // Every transaction spends an output of the set and creates two
+    let txns: Vec<RawTransaction> = (0..LARGE_BLOCK_TXS)
+        .map(|i| {
+            let mut spent = [0u8; 32];
+            spent[..8].copy_from_slice(&(i as u64).to_le_bytes());
+            let input = TxIn::new(Outpoint::new(spent, 0), vec![0; 107]);
+            let outputs = vec![
+                TxOut::new(1_000, vec![0; 25]),
+                TxOut::new(2_000, vec![0; 25]),
+            ];
+            RawTransaction::new(vec![input], outputs)
+        })
+        .collect();
+    let setup = || {
+        let mut utxo = UtxoSet::default();
+        utxo.append(&Txs::from_raw_txs(txns.clone()));
+        (utxo, Txs::from_raw_txs(txns.clone()))
+    };
+
+    c.bench_function("UtxoSet::append 4000 txs", |b| {
+        b.iter_batched(
+            setup,
+            |(mut utxo, txs)| utxo.append(&txs),
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+fn merkle_root(c: &mut Criterion) {
+    let txids: Vec<[u8; 32]> = (0..MERKLE_TXIDS as u32)
+        .map(|i| {
+            let mut txid = [0u8; 32];
+            txid[..4].copy_from_slice(&i.to_le_bytes());
+            txid
+        })
+        .collect();
+
+    c.bench_function("merkle_tree_root 4096 txids", |b| {
+        b.iter(|| merkle_tree_root(black_box(txids.clone())))
+    });
+}
+
+fn evaluate_scripts(c: &mut Criterion) {
+    let blocks = sim_blocks();
+    // The first payment of the chain, every input of the sim spends a P2PKH output
+    let tx = blocks
+        .iter()
+        .flat_map(|block| block.txns.iter().skip(1))
+        .next()
+        .unwrap();
+    let spent = &tx.tx_in[0].previous_output;
+    let script: Vec<u8> = blocks
+        .iter()
+        .flat_map(|block| block.txns.iter())
+        .flat_map(|tx| tx.get_utxos())
+        .find(|(outpoint, _)| *outpoint == (spent.hash, spent.index))
+        .map(|(_, out)| out.pk_script)
+        .unwrap();
+
+    c.bench_function("trace_script P2PKH spend", |b| {
+        b.iter(|| trace_script(black_box(&script), black_box(tx), 0))
+    });
+}
+
+criterion_group!(
+    benches,
+    read_messages,
+    append_large_block,
+    merkle_root,
+    evaluate_scripts
+);
+criterion_main!(benches);
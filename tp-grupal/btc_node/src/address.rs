@@ -0,0 +1,217 @@
+use bitcoin_hashes::{sha256d, Hash};
+
+use crate::{
+    constants::{
+        GENESIS_BLOCK_HASH_VALUE, MAINNET_GENESIS_BLOCK_HASH_VALUE, MAINNET_START_STRING,
+        P2PKH_BYTE, P2SH_BYTE, START_STRING, WIF_BYTE,
+    },
+    message_header::network,
+    protocol_error::ProtocolError,
+};
+
+// Version bytes of the mainnet addresses
+const MAINNET_P2PKH_BYTE: u8 = 0x00;
+const MAINNET_P2SH_BYTE: u8 = 0x05;
+const MAINNET_WIF_BYTE: u8 = 0x80;
+// Version byte, 20 bytes of hash and 4 of checksum
+const ADDRESS_LEN: usize = 25;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressType {
+    P2PKH,
+    P2SH,
+}
+
+/// Network whose version bytes prefix the addresses and private keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Network {
+    /// The network the node runs on
+    #[default]
+    Testnet,
+    Mainnet,
+}
+
+impl Network {
+    pub const ALL: [Network; 2] = [Network::Testnet, Network::Mainnet];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Network::Testnet => "testnet",
+            Network::Mainnet => "mainnet",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Network> {
+        Network::ALL
+            .into_iter()
+            .find(|network| network.name() == name)
+    }
+
+    /// Magic bytes that start every message of the network.
+    pub fn start_string(&self) -> [u8; 4] {
+        match self {
+            Network::Testnet => START_STRING,
+            Network::Mainnet => MAINNET_START_STRING,
+        }
+    }
+
+    pub fn from_start_string(start_string: [u8; 4]) -> Option<Network> {
+        Network::ALL
+            .into_iter()
+            .find(|network| network.start_string() == start_string)
+    }
+
+    pub fn p2pkh_byte(&self) -> u8 {
+        match self {
+            Network::Testnet => P2PKH_BYTE,
+            Network::Mainnet => MAINNET_P2PKH_BYTE,
+        }
+    }
+
+    pub fn p2sh_byte(&self) -> u8 {
+        match self {
+            Network::Testnet => P2SH_BYTE,
+            Network::Mainnet => MAINNET_P2SH_BYTE,
+        }
+    }
+
+    pub fn wif_byte(&self) -> u8 {
+        match self {
+            Network::Testnet => WIF_BYTE,
+            Network::Mainnet => MAINNET_WIF_BYTE,
+        }
+    }
+
+    /// Hash of the first block of the chain, in hex.
+    pub fn genesis_hash(&self) -> &'static str {
+        match self {
+            Network::Testnet => GENESIS_BLOCK_HASH_VALUE,
+            Network::Mainnet => MAINNET_GENESIS_BLOCK_HASH_VALUE,
+        }
+    }
+
+    /// Nonce of the genesis block, the rest of its header is the same in both networks.
+    pub fn genesis_nonce(&self) -> u32 {
+        match self {
+            Network::Testnet => 0x18aea41a,
+            Network::Mainnet => 0x7c2bac1d,
+        }
+    }
+}
+
+/// Checks that the address is a base58 address of the node's network with a valid checksum.
+pub fn validate_address(address: &str) -> Result<AddressType, ProtocolError> {
+    let lowercase = address.to_lowercase();
+    if lowercase.starts_with("tb1") || lowercase.starts_with("bc1") {
+        return Err(ProtocolError::Error(
+            "Bech32 addresses are not supported yet".to_string(),
+        ));
+    }
+
+    let decoded = bs58::decode(address)
+        .into_vec()
+        .map_err(|_| ProtocolError::Error("Address has invalid characters".to_string()))?;
+    if decoded.len() != ADDRESS_LEN {
+        return Err(ProtocolError::Error(
+            "Address has an invalid length".to_string(),
+        ));
+    }
+
+    let checksum = &sha256d::Hash::hash(&decoded[..ADDRESS_LEN - 4]).to_byte_array()[0..4];
+    if checksum != &decoded[ADDRESS_LEN - 4..] {
+        return Err(ProtocolError::Error(
+            "Address has invalid checksum".to_string(),
+        ));
+    }
+
+    let network = network();
+    if decoded[0] == network.p2pkh_byte() {
+        return Ok(AddressType::P2PKH);
+    }
+    if decoded[0] == network.p2sh_byte() {
+        return Ok(AddressType::P2SH);
+    }
+    match Network::ALL
+        .into_iter()
+        .find(|other| other.p2pkh_byte() == decoded[0] || other.p2sh_byte() == decoded[0])
+    {
+        Some(other) => Err(ProtocolError::Error(format!(
+            "Address is for {}, the node runs on {}",
+            other.name(),
+            network.name()
+        ))),
+        None => Err(ProtocolError::Error(
+            "Address has an unknown prefix".to_string(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::decode_hex;
+
+    fn encode(prefix: u8, hash: [u8; 20]) -> String {
+        let mut bytes = vec![prefix];
+        bytes.extend(hash);
+        let checksum = sha256d::Hash::hash(&bytes).to_byte_array();
+        bytes.extend(&checksum[0..4]);
+        bs58::encode(bytes).into_string()
+    }
+
+    fn error(address: &str) -> String {
+        match validate_address(address) {
+            Err(ProtocolError::Error(e)) => e,
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_testnet_addresses_are_valid() {
+        assert_eq!(
+            validate_address("mnJvq7mbGiPNNhUne4FAqq27Q8xZrAsVun").unwrap(),
+            AddressType::P2PKH
+        );
+        assert_eq!(
+            validate_address(&encode(P2SH_BYTE, [7; 20])).unwrap(),
+            AddressType::P2SH
+        );
+    }
+
+    #[test]
+    fn test_invalid_addresses_explain_the_problem() {
+        assert_eq!(
+            error("mnJvq7mbGiPNNhUne4FAqq27Q8xZrAsVum"),
+            "Address has invalid checksum"
+        );
+        assert_eq!(
+            error("mnJvq7mbGiPNNhUne4F"),
+            "Address has an invalid length"
+        );
+        assert_eq!(
+            error("mnJvq7mbGiPNNhUne4FAqq27Q8xZrAsV0l"),
+            "Address has invalid characters"
+        );
+        assert_eq!(
+            error(&encode(MAINNET_P2PKH_BYTE, [7; 20])),
+            "Address is for mainnet, the node runs on testnet"
+        );
+        assert_eq!(
+            error("tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx"),
+            "Bech32 addresses are not supported yet"
+        );
+    }
+
+    #[test]
+    fn test_mainnet_genesis_hash_is_the_one_of_its_header() {
+        let header = crate::block_header::BlockHeader {
+            version: 1,
+            prev_block_hash: [0; 32],
+            merkle_root_hash: decode_hex(crate::constants::GENESIS_BLOCK_MERKLE_ROOT_HASH_VALUE),
+            timestamp: 1231006505,
+            bits: 0x1d00ffff,
+            nonce: Network::Mainnet.genesis_nonce(),
+        };
+        assert_eq!(header.hash(), decode_hex(Network::Mainnet.genesis_hash()));
+    }
+}
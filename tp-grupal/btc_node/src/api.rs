@@ -1,23 +1,307 @@
-use crate::blockchain::txs::Tx;
+use std::{
+    cell::Cell,
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc, Arc, Mutex, PoisonError,
+    },
+    time::SystemTime,
+};
+
+use glib::Sender;
+
+use crate::bandwidth::Traffic;
+use crate::block_template::BlockTemplate;
+use crate::blockchain::{
+    history::HistoryEntry,
+    stats::{BlockStats, ChainStats, Supply},
+    txs::Tx,
+};
+use crate::coin_control::{Coin, CoinId};
+use crate::download_progress::PeerDownload;
+use crate::log_file::LogLine;
+use crate::mempool::{FeeBucket, MempoolTxInfo};
+use crate::payment_requests::PaymentRequest;
 use crate::protocol_error::ProtocolError;
+use crate::raw_transaction::TxOptions;
+use crate::register::InboundCounts;
+use crate::script::ScriptTrace;
+use crate::sync_state::SyncState;
+use crate::wallet_file::WalletEntry;
+use crate::wallet_warnings::WalletWarning;
+
+/// Periodic report of the node state for the Overview page.
+#[derive(Debug, Clone)]
+pub struct NodeStats {
+    pub chain: ChainStats,
+    pub peers: usize,
+    /// Connections accepted by the listener and the ones refused or evicted
+    pub inbound: InboundCounts,
+    pub mempool_size: usize,
+    pub sync_progress: f64,
+    /// Bytes sent to and received from the registered peers
+    pub traffic: Traffic,
+    /// Size and fees of the last blocks, for the charts
+    pub recent_blocks: Vec<BlockStats>,
+}
+
+/// How a payment is built, besides its payee, amount and fee.
+#[derive(Debug, Clone, Default)]
+pub struct PaymentOptions {
+    /// Coins to spend, picked by the node when None
+    pub selected_coins: Option<Vec<CoinId>>,
+    /// Data of an OP_RETURN output
+    pub data: Option<Vec<u8>>,
+    /// Also spend the change of the unconfirmed payments of the payer
+    pub spend_unconfirmed: bool,
+    pub tx: TxOptions,
+}
+
+/// Execution of the scripts of a transaction input, for the script debugger.
+#[derive(Debug, Clone)]
+pub struct InputTrace {
+    pub previous_output: CoinId,
+    /// Public key script of the spent output, empty when it is unknown
+    pub pubkey_script: Vec<u8>,
+    /// None when the spent output is unknown, like the one of a coinbase
+    pub trace: Option<ScriptTrace>,
+}
+
+/// Whole state of a wallet address, so the wallet can replace what it built from the events.
+#[derive(Debug, Clone)]
+pub struct WalletSnapshot {
+    pub address: String,
+    pub balance: i64,
+    /// Net effect of the unconfirmed transactions on the balance
+    pub pending_balance: i64,
+    pub coins: Vec<Coin>,
+    pub history: Vec<HistoryEntry>,
+    /// Unconfirmed transactions with their payer and payee, like in NewTx
+    pub pending_txs: Vec<(Tx, String, String)>,
+}
 
 pub enum NodeApi {
     NewTx(Tx, String, String),
     ConfirmedTx([u8; 32], String),
+    /// Height, hash, number of transactions and timestamp of a block that extended the chain
+    /// after the initial download, and whether it has transactions of the wallet
+    NewBlock(i32, [u8; 32], usize, u32, bool),
     Balance(i64, String),
     AddPendingBalance(i64, String),
     AddConfirmedBalance(i64, String),
+    /// Confirmed and pending balances recomputed by the node, they replace the wallet ones
+    ReconciledBalance(i64, i64, String),
+    /// Number of confirmed unspent outputs of the address
+    UtxoCount(usize, String),
+    /// Unspent outputs of the address, for the coin control
+    Coins(Vec<Coin>, String),
     PaymentConfirmation(Tx, String, String, i64),
+    /// The wallet requests are being handled, sent before the sync starts
+    WalletReady,
+    /// The headers and blocks are synced
     NodeReady,
-    History(Vec<Tx>, String),
+    History(Vec<HistoryEntry>, String),
+    ChainStats(ChainStats),
+    /// Answer of GetSupply
+    Supply(Supply),
+    Stats(NodeStats),
+    MempoolUpdate(Vec<MempoolTxInfo>),
+    /// Answer of GetMempool: the transactions with their sizes and fees, the fee histogram and
+    /// the stats of the last blocks for the fee estimation
+    Mempool(Vec<MempoolTxInfo>, Vec<FeeBucket>, Vec<BlockStats>),
+    /// Old txid, the transaction that replaced it and the payer address
+    FeeBumped([u8; 32], Tx, String),
+    /// Txid of the stuck transaction, the child that pays its fee and the address that received it
+    FeeBumpedByChild([u8; 32], Tx, String),
     Error(ProtocolError),
     Loading(f64),
+    /// Blocks downloaded from each peer, sent along with Loading
+    DownloadProgress(Vec<PeerDownload>),
+    /// Height of the header chain and the best height announced by the peer
+    HeaderSyncProgress(i32, i32),
+    /// Blocks checked by the reindex and the blocks in the file
+    ReindexProgress(usize, usize),
+    /// The block download was paused (true) or resumed (false)
+    SyncPaused(bool),
+    /// The sync moved to the next phase
+    SyncStateChanged(SyncState),
     FinishedConnectingToPeers,
+    /// Name of the wallet file opened, its accounts and its labels, the balances follow
+    WalletOpened(String, Vec<WalletEntry>, BTreeMap<String, String>),
+    WalletClosed,
+    /// Path where the open wallet was backed up
+    WalletBackedUp(String),
+    /// Accounts added from a backup to the open wallet and every label of the merged wallet,
+    /// their balances follow
+    WalletRestored(Vec<WalletEntry>, BTreeMap<String, String>),
+    /// Txid and the execution of the scripts of each of its inputs
+    ScriptTraces([u8; 32], Vec<InputTrace>),
+    Snapshot(WalletSnapshot),
+    BlockTemplate(BlockTemplate),
+    /// Privacy warning about the use of the address
+    Warning(WalletWarning, String),
+    /// The configuration file was read again, with the settings that changed
+    ConfigReloaded(Vec<String>),
+    /// Every payment request, sent when one is created, paid or expired
+    PaymentRequests(Vec<PaymentRequest>),
+    /// Address, message and the hex proof that the wallet controls the address
+    OwnershipProof(String, String, String),
+    /// Address and message of a checked proof, and whether it proves the ownership
+    OwnershipVerified(String, String, bool),
+    /// Answer of GetLog: the recent log lines after the one asked for
+    Log(Vec<LogLine>),
+}
+
+/// Number of a wallet request, echoed in the events sent while the node handles it.
+pub type RequestId = u64;
+
+/// Message of the node numbered in the order it was sent, so the wallet can tell when it missed one.
+#[derive(Debug, Clone)]
+pub struct Event<T> {
+    pub seq: u64,
+    pub time: SystemTime,
+    /// Request that the event answers, None for the ones the node sends on its own
+    pub reply_to: Option<RequestId>,
+    pub body: T,
+}
+
+impl<T> Event<T> {
+    /// Whether this event comes right after the one numbered `last`, None before the first one.
+    pub fn follows(&self, last: Option<u64>) -> bool {
+        match last {
+            Some(last) => self.seq == last + 1,
+            None => self.seq == 0,
+        }
+    }
+}
+
+thread_local! {
+    static CURRENT_REQUEST: Cell<Option<RequestId>> = const { Cell::new(None) };
+}
+
+/// Sends the messages of the node to the wallet in events.
+#[derive(Debug)]
+pub struct EventSender {
+    sender: Sender<Event<NodeApi>>,
+    next_seq: Mutex<u64>,
+}
+
+impl EventSender {
+    pub fn new(sender: Sender<Event<NodeApi>>) -> EventSender {
+        EventSender {
+            sender,
+            next_seq: Mutex::new(0),
+        }
+    }
+
+    pub fn send(&self, body: NodeApi) -> Result<(), ProtocolError> {
+        // Numbered and sent under the lock, so the events of different threads arrive in order
+        let mut next_seq = self.next_seq.lock().unwrap_or_else(PoisonError::into_inner);
+        self.sender
+            .send(Event {
+                seq: *next_seq,
+                time: SystemTime::now(),
+                reply_to: CURRENT_REQUEST.with(Cell::get),
+                body,
+            })
+            .map_err(|_| ProtocolError::Error("Wallet sender error".to_string()))?;
+        *next_seq += 1;
+        Ok(())
+    }
+
+    /// Runs the handler of the request, the events it sends from this thread reply to it.
+    pub fn replying_to<R>(&self, id: RequestId, handler: impl FnOnce() -> R) -> R {
+        let previous = CURRENT_REQUEST.with(|current| current.replace(Some(id)));
+        let res = handler();
+        CURRENT_REQUEST.with(|current| current.set(previous));
+        res
+    }
+}
+
+/// Wallet request with the number given by the sender.
+#[derive(Debug)]
+pub struct Request<T> {
+    pub id: RequestId,
+    pub body: T,
+}
+
+/// Sends the wallet requests to the node, numbering them so the answers can be told apart.
+#[derive(Debug, Clone)]
+pub struct RequestSender {
+    sender: mpsc::Sender<Request<WalletApi>>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl RequestSender {
+    pub fn new(sender: mpsc::Sender<Request<WalletApi>>) -> RequestSender {
+        RequestSender {
+            sender,
+            next_id: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Returns the number of the request, the events that answer it carry it in `reply_to`.
+    pub fn send(&self, body: WalletApi) -> Result<RequestId, ProtocolError> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.sender
+            .send(Request { id, body })
+            .map_err(|_| ProtocolError::Error("Node receiver error".to_string()))?;
+        Ok(id)
+    }
 }
 
+#[derive(Debug)]
 pub enum WalletApi {
     GetBalance(String),
     GetHistory(String),
-    PayTo(String, String, i64, i64),
+    /// Payer wif, payee address, amount, fee and how to build the transaction
+    PayTo(String, String, i64, i64, PaymentOptions),
+    GetCoins(String),
+    /// Address of the coin, the coin and whether to leave it out of the automatic coin selection
+    SetCoinFrozen(String, CoinId, bool),
     AddAddress(String),
+    GetChainStats,
+    /// Sends the satoshis issued up to the last block and the current subsidy
+    GetSupply,
+    /// Downloads the missing blocks since the timestamp and sends the history and balance of the address again
+    Rescan(String, u32),
+    PauseSync,
+    ResumeSync,
+    /// Payer wif, txid and the satoshis to add to the fee
+    BumpFee(String, [u8; 32], i64),
+    /// Receiver wif, txid of the unconfirmed transaction and the fee of a child that spends its output
+    BumpFeeWithChild(String, [u8; 32], i64),
+    /// Closes the open wallet and opens the one at the path, creating it if it doesn't exist
+    OpenWallet(String),
+    CloseWallet,
+    /// Copies the accounts and labels of the open wallet to the path
+    BackupWallet(String),
+    /// Adds the accounts and labels of the backup at the path that the open wallet doesn't have
+    RestoreWallet(String),
+    /// Saves an account in the open wallet file, replacing the one with the same address
+    SaveAccount(WalletEntry),
+    /// Stops tracking the address and removes it from the open wallet file
+    RemoveAddress(String),
+    /// Saves the label of a txid in hex or of an address in the open wallet file, an empty one removes it
+    SetLabel(String, String),
+    /// Runs the scripts of every input of a mempool or confirmed transaction
+    TraceScripts([u8; 32]),
+    /// Sends the snapshot of the address
+    GetSnapshot(String),
+    /// Sends the mempool transactions and how many pay each fee rate
+    GetMempool,
+    /// Sends a candidate block on top of the chain whose coinbase pays to the address
+    GetBlockTemplate(String),
+    /// Sends the snapshot of every address and the mempool again, after the wallet missed some events
+    Resync,
+    /// Applies the log destination, max_listen_peers and max_download_rate of the configuration file again
+    ReloadConfig,
+    /// Address, amount, label and seconds until it expires of a request to be paid
+    CreatePaymentRequest(String, i64, String, u32),
+    /// Signs the message with the wif to prove the ownership of its address
+    ProveOwnership(String, String),
+    /// Checks the proof of an address and message
+    VerifyOwnership(String, String, String),
+    /// Sends the recent log lines numbered after the one given, 0 for all of them
+    GetLog(u64),
 }
@@ -0,0 +1,90 @@
+use std::{cell::RefCell, collections::HashMap, sync::Mutex, thread};
+
+use crate::rate_limiter::RateLimiter;
+
+/// Bytes sent and received, in total and per message command.
+#[derive(Debug, Clone, Default)]
+pub struct Traffic {
+    pub sent: u64,
+    pub received: u64,
+    /// Sent and received bytes of every command
+    pub by_command: HashMap<String, (u64, u64)>,
+}
+
+impl Traffic {
+    pub fn record_sent(&mut self, command: &str, bytes: u64) {
+        self.sent += bytes;
+        self.by_command.entry(command.to_string()).or_default().0 += bytes;
+    }
+
+    pub fn record_received(&mut self, command: &str, bytes: u64) {
+        self.received += bytes;
+        self.by_command.entry(command.to_string()).or_default().1 += bytes;
+    }
+
+    pub fn merge(&mut self, other: &Traffic) {
+        self.sent += other.sent;
+        self.received += other.received;
+        for (command, (sent, received)) in other.by_command.iter() {
+            let entry = self.by_command.entry(command.clone()).or_default();
+            entry.0 += sent;
+            entry.1 += received;
+        }
+    }
+}
+
+thread_local! {
+    static THREAD_SENT: RefCell<Traffic> = RefCell::new(Traffic::default());
+}
+
+/// Called for every message written. The bytes stay in the writing thread
+/// until `take_thread_traffic` hands them to the peer they were sent to.
+pub fn record_thread_sent(command: &str, bytes: u64) {
+    THREAD_SENT.with(|traffic| traffic.borrow_mut().record_sent(command, bytes));
+}
+
+/// Returns the bytes sent by this thread since the last call.
+pub fn take_thread_traffic() -> Traffic {
+    THREAD_SENT.with(|traffic| traffic.take())
+}
+
+static DOWNLOAD_LIMITER: Mutex<Option<RateLimiter>> = Mutex::new(None);
+
+/// Caps the bytes per second read from all of the peers together, None removes the cap.
+/// It replaces the previous cap, like when the configuration is reloaded.
+pub fn set_download_cap(bytes_per_second: Option<u32>) {
+    if let Ok(mut limiter) = DOWNLOAD_LIMITER.lock() {
+        *limiter = bytes_per_second.map(|rate| RateLimiter::new(u32::MAX, rate));
+    }
+}
+
+/// Sleeps the reading thread while the download is over the cap.
+pub fn throttle_download(bytes: usize) {
+    let wait = match DOWNLOAD_LIMITER.lock() {
+        Ok(mut limiter) => match limiter.as_mut() {
+            Some(limiter) => limiter.consume(bytes),
+            None => return,
+        },
+        Err(_) => return,
+    };
+    thread::sleep(wait);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_thread_traffic_is_taken_once() {
+        record_thread_sent("ping", 32);
+        record_thread_sent("tx", 250);
+
+        let mut total = Traffic::default();
+        total.record_received("ping", 32);
+        total.merge(&take_thread_traffic());
+
+        assert_eq!(total.sent, 282);
+        assert_eq!(total.by_command["ping"], (32, 32));
+        assert_eq!(take_thread_traffic().sent, 0);
+    }
+}
@@ -1,79 +1,183 @@
+use chrono::Utc;
 use glib::Sender;
 
 use crate::{
-    api::{NodeApi, WalletApi},
-    blockchain::{utxo_set::Output, Blockchain},
-    config::Config,
+    api::{
+        Event, EventSender, InputTrace, NodeApi, NodeStats, PaymentOptions, Request, WalletApi,
+        WalletSnapshot,
+    },
+    bandwidth::set_download_cap,
+    block_template::{tx_fee, BlockTemplate},
+    blockchain::{txs::Tx, utxo_set::Output, Blockchain},
+    coin_control::{select_coins, Coin, CoinId},
+    config::{Config, Tunables},
+    constants::{
+        BALANCE_REFRESH_INTERVAL, BLOCK_DOWNLOAD_BATCH, DIAL_ATTEMPTS, DIAL_BACKOFF_BASE,
+        DIAL_BACKOFF_MAX, DUST_LIMIT, GETDATA_TIMEOUT, HANDSHAKE_TIMEOUT, LOADING_REPORT_INTERVAL,
+        NODE_NETWORK_LIMITED, NODE_NETWORK_LIMITED_BLOCKS, NODE_P2P_V2,
+        PAYMENT_REQUEST_CHECK_INTERVAL, PEER_CHECK_INTERVAL, REBROADCAST_INITIAL_INTERVAL,
+        REBROADCAST_MAX_INTERVAL, REBROADCAST_TICK, RECENT_TXIDS_CAPACITY, SIGHASH_ANYONECANPAY,
+        SIGHASH_SINGLE, STATS_INTERVAL, STATS_WINDOW, TX_TRICKLE_INTERVAL,
+    },
+    datadir::{DataDir, WALLETS_DIR},
+    dial_schedule::DialSchedule,
+    download_progress::{ProgressReporter, WorkerProgress, WorkerSender},
+    ibd::IbdState,
+    in_flight::InFlight,
+    local_address::{display_ip, LocalAddress},
+    log_file::{log, log_error, set_node_log_destination, set_node_logger, Logger},
+    mempool::{check_min_relay_fee, Mempool, MempoolTxInfo},
     message::{
-        addr::AddrMessage, block::BlockMessage, get_data::GetDataMessage,
-        get_headers::GetHeadersMessage, inventory::TypeIdentifier, tx::TxMessage,
-        version::VersionMessage, Message,
+        addr::{AddrMessage, NetworkAddr},
+        block::BlockMessage,
+        compact_size::CompactSize,
+        get_data::GetDataMessage,
+        get_headers::GetHeadersMessage,
+        inv::InvMessage,
+        inventory::{Inventory, TypeIdentifier},
+        pong::PongMessage,
+        version::VersionMessage,
+        Message,
     },
-    message_handlers::{handle_handshake_messages, handle_messages},
-    message_header::MessageHeader,
+    message_header::{set_network, MessageHeader},
+    payment_requests::PaymentRequests,
+    peer::Peer,
+    peer_discovery::{interleave_families, query_dns_seeds, read_peers_file, save_peers_file},
     protocol_error::ProtocolError,
-    raw_transaction::{RawTransaction, TxOut},
-    register::Register,
-    script::PubKeyScript,
-    utils::wif_to_pkhash,
-    wallet_handlers::handle_wallet_messages,
+    raw_transaction::{RawTransaction, TxOptions, TxOut},
+    rebroadcast::RebroadcastSchedule,
+    recent_txids::RecentTxids,
+    register::{Direction, PeerId, PeerKey, PeerState, Register},
+    script::{is_valid_sighash_type, trace_script, PubKeyScript},
+    session::{read_session, SessionRecorder},
+    sim::{generate, SimConfig},
+    sync_control::SyncControl,
+    sync_state::{SyncMachine, SyncState},
+    transport::PeerStream,
+    utils::{
+        bitcoin_address_to_pkhash, bytes_to_hex_string, resolve_peer, wif_to_bitcoin_address,
+        wif_to_pkhash,
+    },
+    wallet_effects::{net_effects, WalletEffects},
+    wallet_file::WalletFile,
+    wallet_handlers::{handle_wallet_messages, refresh_wallet_addresses},
+    worker_pool::WorkerPool,
 };
 
+use rand::{seq::SliceRandom, Rng};
 use std::{
-    collections::HashMap,
-    net::{Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
-    str::FromStr,
+    collections::{HashMap, HashSet},
+    net::{SocketAddr, TcpListener, TcpStream},
     sync::{mpsc::Receiver, Arc, Mutex, RwLock},
     thread::{self, JoinHandle},
+    time::{Duration, Instant},
 };
 
 #[derive(Debug)]
 pub struct Node {
+    /// Configuration the node started with, see tunables for the settings that can be reloaded
     pub config: Config,
-    pub version_message: VersionMessage,
+    /// Current value of the settings that Node::reload_config takes again from the file
+    pub tunables: RwLock<Tunables>,
+    pub version_message: RwLock<VersionMessage>,
     pub register: Arc<RwLock<Register>>,
     pub blockchain: Arc<Mutex<Blockchain>>,
-    pub addrs: Vec<Ipv6Addr>,
-    pub mempool: Arc<RwLock<HashMap<[u8; 32], RawTransaction>>>,
+    pub addrs: Vec<SocketAddr>,
+    pub mempool: Arc<RwLock<Mempool>>,
     pub wallet_txs: Arc<RwLock<HashMap<[u8; 32], String>>>,
     pub wallet_addresses: RwLock<Vec<String>>,
-    pub sender: Sender<NodeApi>,
+    pub sender: EventSender,
+    pub sync_control: Arc<SyncControl>,
+    pub in_flight: Mutex<InFlight>,
+    pub recently_rejected: Mutex<RecentTxids>,
+    pub recently_confirmed: Mutex<RecentTxids>,
+    pub rebroadcast: Mutex<RebroadcastSchedule>,
+    pub pending_announcements: Mutex<Vec<[u8; 32]>>,
+    /// Balance deltas of the unconfirmed wallet transactions, per address
+    pub pending_effects: Mutex<HashMap<[u8; 32], WalletEffects>>,
+    /// Payment requests created by the wallet, fulfilled by the transactions that pay them
+    pub payment_requests: Mutex<PaymentRequests>,
+    /// Our external address according to the version messages of the peers
+    pub local_address: Mutex<LocalAddress>,
+    pub open_wallet: RwLock<Option<WalletFile>>,
+    /// Locked for as long as the node runs, None if the configuration has no datadir
+    pub datadir: Option<DataDir>,
+    /// Coins left out of the automatic coin selection
+    pub frozen_coins: RwLock<HashSet<CoinId>>,
+    /// Records the messages of the peers if the configuration has a record_session file
+    pub recorder: Option<SessionRecorder>,
+    /// While it is active the relayed transactions are ignored and the blocks don't refresh
+    /// the wallet, it is refreshed once at the end
+    pub ibd: IbdState,
+    /// Phase of the sync, it decides which messages of the peers are handled
+    pub sync: SyncMachine,
+    /// Threads that read the messages of the listened and the inbound peers, one peer each.
+    /// Sized for max_listen_peers and max_inbound when the node starts
+    pub peer_workers: WorkerPool,
 }
 
 impl Node {
-    pub fn new(mut config: Config, sender: Sender<NodeApi>) -> Result<Node, ProtocolError> {
-        let version_message = VersionMessage::new(&config)?;
-
-        let mut addrs: Vec<Ipv6Addr> = Vec::new();
-        if let Some(host) = config.host.clone() {
-            addrs.push(Ipv4Addr::from_str(&host).unwrap().to_ipv6_mapped());
-            config.max_listen_peers = 1;
-            config.block_downloading_threads = 1;
-        } else {
-            for addr in config.endpoint.to_socket_addrs()? {
-                match addr {
-                    SocketAddr::V4(ip) => addrs.push(ip.ip().to_ipv6_mapped()),
-                    SocketAddr::V6(ip) => addrs.push(ip.ip().to_owned()),
-                }
-            }
+    pub fn new(mut config: Config, sender: Sender<Event<NodeApi>>) -> Result<Node, ProtocolError> {
+        let sender = EventSender::new(sender);
+        let datadir = match &config.datadir {
+            Some(root) => Some(DataDir::open(root)?),
+            None => None,
+        };
+        if let Some(datadir) = &datadir {
+            datadir.apply(&mut config);
         }
-
-        let blockchain = match Blockchain::read_from_file(config.blockchain_file.clone()) {
-            Ok(chain) => chain,
-            Err(e) => {
-                eprintln!("ERROR READING BLOCKCHAIN FILE: {}", e);
-                Blockchain::new()
+        let logger = Logger::new(config.log_file.clone(), config.log_destination);
+        set_node_logger(logger.clone());
+        // Before the chain is loaded, it starts from the genesis block of the network
+        set_network(config.network);
+
+        // The simulated chain doesn't come from peers, so none are looked for
+        let (addrs, blockchain) = match &config.simulation {
+            Some(sim) => (vec![], simulated_chain(sim)?),
+            None => {
+                let blockchain = match load_blockchain(&config, &datadir, &sender) {
+                    Ok(chain) => chain,
+                    Err(e) => {
+                        log_error(format!("Couldn't read the blockchain file: {}", e));
+                        Blockchain::new()
+                    }
+                };
+                // A replayed session comes from its file instead of the peers
+                let addrs = match config.replay_session {
+                    Some(_) => vec![],
+                    None => find_peers(&config)?,
+                };
+                (addrs, blockchain)
             }
         };
 
-        let register = Arc::new(RwLock::new(Register::new(config.log_file.clone())));
-        let mempool = Arc::new(RwLock::new(HashMap::new()));
+        let recorder = match &config.record_session {
+            Some(path) => Some(SessionRecorder::create(path)?),
+            None => None,
+        };
+
+        let mut version_message = VersionMessage::new(&config, blockchain.get_height())?;
+        // The node server answers getdata and getheaders for the recent blocks, unless it observes
+        if !config.readonly {
+            version_message.enable_services(NODE_NETWORK_LIMITED);
+        }
+        if config.v2transport {
+            version_message.enable_services(NODE_P2P_V2);
+        }
+
+        set_download_cap(config.max_download_rate);
+
+        let register = Arc::new(RwLock::new(Register::new(logger)));
+        let peer_workers =
+            WorkerPool::new(config.max_listen_peers + config.inbound_limits.max_inbound);
+        let mempool = Arc::new(RwLock::new(Mempool::new()));
         let wallet_txs = Arc::new(RwLock::new(HashMap::new()));
         let wallet_addresses = RwLock::new(Vec::new());
 
         Ok(Node {
+            tunables: RwLock::new(config.tunables()),
             config,
-            version_message,
+            version_message: RwLock::new(version_message),
             register,
             blockchain: Arc::new(Mutex::new(blockchain)),
             addrs,
@@ -81,45 +185,193 @@ impl Node {
             wallet_txs,
             wallet_addresses,
             sender,
+            sync_control: Arc::new(SyncControl::new()),
+            in_flight: Mutex::new(InFlight::new(Duration::from_secs(GETDATA_TIMEOUT))),
+            recently_rejected: Mutex::new(RecentTxids::new(RECENT_TXIDS_CAPACITY)),
+            recently_confirmed: Mutex::new(RecentTxids::new(RECENT_TXIDS_CAPACITY)),
+            rebroadcast: Mutex::new(RebroadcastSchedule::new(
+                Duration::from_secs(REBROADCAST_INITIAL_INTERVAL),
+                Duration::from_secs(REBROADCAST_MAX_INTERVAL),
+            )),
+            pending_announcements: Mutex::new(vec![]),
+            pending_effects: Mutex::new(HashMap::new()),
+            payment_requests: Mutex::new(PaymentRequests::new()),
+            local_address: Mutex::new(LocalAddress::new()),
+            open_wallet: RwLock::new(None),
+            datadir,
+            frozen_coins: RwLock::new(HashSet::new()),
+            recorder,
+            ibd: IbdState::new(),
+            sync: SyncMachine::new(),
+            peer_workers,
         })
     }
 
-    /// Performs handshake with all of the nodes and initializes the blockchain
-    pub fn initialize(&mut self) -> Result<(), ProtocolError> {
-        for addr in self.addrs.clone().iter() {
-            if let Err(e) = self.initialize_connection(*addr) {
-                eprintln!("Initialization Error: {}", e);
-            };
+    /// Reads the configuration file again and applies the settings that can change while
+    /// the node runs, the sync goes on. Returns the settings that changed.
+    pub fn reload_config(&self) -> Result<Vec<String>, ProtocolError> {
+        let path = self.config.file.as_ref().ok_or_else(|| {
+            ProtocolError::Error("The configuration wasn't read from a file".to_string())
+        })?;
+        let new = Config::new(path)?.tunables();
+
+        let mut tunables = self.tunables.write()?;
+        let changes = tunables.changes(&new);
+        set_node_log_destination(new.log_destination);
+        set_download_cap(new.max_download_rate);
+        *tunables = new;
+        drop(tunables);
+
+        for change in &changes {
+            log(format!("Configuration reloaded, {}", change));
         }
+        Ok(changes)
+    }
+
+    /// Path of a wallet file, relative paths go to the wallets directory of the datadir.
+    pub fn wallet_path(&self, path: &str) -> String {
+        match &self.datadir {
+            Some(datadir) => datadir.resolve(WALLETS_DIR, path),
+            None => path.to_string(),
+        }
+    }
+
+    /// Performs handshake with the nodes and initializes the blockchain
+    /// Dialing stops once `min_peers` completed the handshake or `connect_budget` is spent,
+    /// so dead addresses don't delay the startup for long. Unreachable ones are retried with backoff.
+    pub fn initialize(&self) -> Result<(), ProtocolError> {
+        if self.config.simulation.is_some() {
+            self.sender
+                .send(NodeApi::FinishedConnectingToPeers)
+                .unwrap();
+            return Ok(());
+        }
+
+        self.dial_peers()?;
 
         self.sender
             .send(NodeApi::FinishedConnectingToPeers)
             .unwrap();
 
+        let peers: Vec<SocketAddr> = self
+            .register
+            .read()?
+            .get_all_streams()
+            .iter()
+            .filter_map(|stream| stream.peer_addr().ok())
+            .collect();
+        if !peers.is_empty() {
+            save_peers_file(&self.config.peers_file, &peers)
+                .unwrap_or_else(|e| log_error(format!("Couldn't save the peers file: {}", e)));
+        }
+
         //Send the change label message to the wallet
         let blockchain = self.blockchain.lock()?;
 
         blockchain
             .save_to_file(self.config.blockchain_file.clone())
-            .unwrap_or_else(|e| eprintln!("ERROR SAVING BLOCKCHAIN TO FILE: {}", e));
+            .unwrap_or_else(|e| log_error(format!("Couldn't save the blockchain file: {}", e)));
 
         drop(blockchain);
 
+        self.advance_sync(SyncState::BlockSync)?;
         self.multi_threaded_block_download(self.config.block_downloading_threads)?;
 
         Ok(())
     }
 
-    /// Connects to a peer, performs the handshake and the headers synchronization with it
-    fn initialize_connection(&mut self, addr: Ipv6Addr) -> Result<(), ProtocolError> {
-        let socket = SocketAddr::new(std::net::IpAddr::V6(addr), 18333);
+    /// Dials the addresses until `min_peers` complete the handshake, within the connect budget.
+    /// Only the dialing and the handshakes spend the budget, not the headers sync with the peers.
+    fn dial_peers(&self) -> Result<(), ProtocolError> {
+        let start = Instant::now();
+        let mut deadline = start + self.config.connect_budget;
+        let mut schedule = DialSchedule::new(
+            &self.addrs,
+            start,
+            DIAL_ATTEMPTS,
+            Duration::from_secs(DIAL_BACKOFF_BASE),
+            Duration::from_secs(DIAL_BACKOFF_MAX),
+        );
+        let mut rng = rand::thread_rng();
+        let mut connected = 0;
+
+        while let Some(entry) = schedule.pop() {
+            if connected >= self.config.min_peers {
+                log(format!(
+                    "Connected to {} peers, skipping the remaining addresses",
+                    connected
+                ));
+                break;
+            }
+            if entry.due >= deadline {
+                log("Connection budget spent, skipping the remaining peers".to_string());
+                break;
+            }
+            thread::sleep(entry.due.saturating_duration_since(Instant::now()));
+
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let timeout = remaining.min(self.config.tcp_timeout);
+            match self.initialize_connection(entry.addr, timeout) {
+                Ok(syncing) => {
+                    connected += 1;
+                    deadline += syncing;
+                }
+                // Only the addresses that couldn't be reached are dialed again
+                Err(ProtocolError::IOError(e)) => {
+                    log_error(format!("Initialization Error: {}", e));
+                    if let Some(due) = schedule.retry(entry, Instant::now(), &mut rng) {
+                        log(format!(
+                            "Dialing {} again in {:?}",
+                            entry.addr,
+                            due.saturating_duration_since(Instant::now())
+                        ));
+                    }
+                }
+                Err(e) => log_error(format!("Initialization Error: {}", e)),
+            }
+        }
+        Ok(())
+    }
 
-        let mut stream = TcpStream::connect_timeout(&socket, self.config.tcp_timeout)?;
-        stream.set_read_timeout(Some(self.config.tcp_timeout))?;
-        stream.set_write_timeout(Some(self.config.tcp_timeout))?;
+    /// Connects to a peer, performs the handshake and the headers synchronization with it.
+    /// Peers that are already connected aren't dialed again.
+    /// Returns the time spent syncing the headers.
+    fn initialize_connection(
+        &self,
+        socket: SocketAddr,
+        timeout: Duration,
+    ) -> Result<Duration, ProtocolError> {
+        self.check_peer_allowed(&socket)?;
+
+        let key = PeerKey::new(socket, Direction::Outbound);
+        let id = self.register.write()?.reserve(key)?;
+        let res = self.open_connection(id, socket, timeout);
+        if res.is_err() {
+            self.register
+                .write()?
+                .set_state(id, PeerState::Disconnected);
+        }
+        res
+    }
 
-        println!("\x1b[33m== CONNECTED address: {} ==\x1b[0m", addr);
+    fn open_connection(
+        &self,
+        id: PeerId,
+        socket: SocketAddr,
+        timeout: Duration,
+    ) -> Result<Duration, ProtocolError> {
+        let mut stream = self.dial(socket, timeout)?;
+
+        log(format!(
+            "CONNECTED address: {} ({})",
+            socket,
+            stream.transport()
+        ));
+        self.register.write()?.set_state(id, PeerState::Handshaking);
+        let height = self.blockchain.lock()?.get_height();
+        self.version_message.write()?.set_start_height(height);
         let recv_version = self.handshake(&mut stream)?;
+        self.advance_sync(SyncState::HeaderSync)?;
 
         let blockchain = self.blockchain.lock()?;
 
@@ -127,44 +379,40 @@ impl Node {
         get_headers.write_to(&mut stream)?;
         drop(blockchain);
 
-        handle_handshake_messages(&self.blockchain, &mut stream, &self.register)?;
+        let syncing = Instant::now();
+        let mut peer = Peer::new(stream, recv_version.start_height())?;
+        peer.sync_headers(self)?;
+        let syncing = syncing.elapsed();
+        let stream = match peer.into_stream() {
+            Some(stream) => stream,
+            None => return Ok(syncing),
+        };
 
         self.register
             .write()?
-            .save_connection(stream, recv_version)?;
+            .save_connection(id, stream, recv_version)?;
 
-        Ok(())
+        Ok(syncing)
     }
 
-    /// Creates a thread for every peer and listens for new messages in all of them
-    pub fn listen(self, rcv_node: Receiver<WalletApi>) -> Result<(), ProtocolError> {
-        let mut streams = self
-            .register
-            .read()?
-            .get_n_streams(self.config.max_listen_peers);
-
-        let mut handlers = vec![];
+    /// Starts handling the wallet requests right away and syncs with the peers in the background.
+    /// Once the sync finishes a thread is created for every peer to listen for new messages.
+    pub fn start(self, rcv_node: Receiver<Request<WalletApi>>) -> Result<(), ProtocolError> {
         let node = Arc::new(self);
-
-        println!("\x1b[33m== LISTENING STREAMS ==\x1b[0m");
-        for stream in streams.drain(..) {
-            let n = Arc::clone(&node);
-            let handle = thread::spawn(move || {
-                if let Err(e) = handle_messages(stream, n) {
-                    eprintln!("Thread broke: {}", e);
-                };
-            });
-            handlers.push(handle);
-        }
-
-        if node.config.host.is_none() {
-            let server_handler = node_server_handler(Arc::clone(&node));
-            handlers.push(server_handler);
-        }
+        let handlers = vec![
+            stats_reporter(Arc::clone(&node)),
+            rebroadcaster(Arc::clone(&node)),
+            balance_refresher(Arc::clone(&node)),
+            announcer(Arc::clone(&node)),
+            peer_reaper(Arc::clone(&node)),
+            request_expirer(Arc::clone(&node)),
+            node_server_handler(Arc::clone(&node)),
+            sync_and_listen(Arc::clone(&node)),
+        ];
 
         let n = Arc::clone(&node);
         if let Err(e) = handle_wallet_messages(rcv_node, n) {
-            eprintln!("Wallet communication error: {}", e);
+            log_error(format!("Wallet communication error: {}", e));
         };
 
         for handle in handlers {
@@ -176,28 +424,236 @@ impl Node {
         Ok(())
     }
 
-    /// It receives a transaction and sends it to every connected peer.
-    /// returns the number of peers that received it succesfully.
-    pub fn broadcast_transaction(&self, tx: RawTransaction) -> Result<usize, ProtocolError> {
-        self.mempool.write()?.insert(tx.get_tx_id(), tx.clone());
+    /// Computes the net effect of an unconfirmed transaction on the given addresses and
+    /// sends it to the wallet as a pending balance delta, one per address touched.
+    /// The inputs are looked up in the utxo set and, for chains of unconfirmed txs, in the mempool.
+    pub fn add_pending_effects(
+        &self,
+        tx: &RawTransaction,
+        addresses: &[String],
+    ) -> Result<WalletEffects, ProtocolError> {
+        let mut spent = vec![];
+        {
+            let blockchain = self.blockchain.lock()?;
+            let mempool = self.mempool.read()?;
+            for txin in tx.tx_in.iter() {
+                let prev = &txin.previous_output;
+                if let Some(out) = blockchain.utxo.get(prev.hash, prev.index) {
+                    spent.push((out.pkscript.to_vec(), out.value));
+                } else if let Some(out) = mempool
+                    .get(&prev.hash)
+                    .and_then(|parent| parent.tx_out.get(prev.index as usize))
+                {
+                    spent.push((out.pk_script.clone(), out.value));
+                }
+            }
+        }
+        let created: Vec<(Vec<u8>, i64)> = tx
+            .tx_out
+            .iter()
+            .map(|out| (out.pk_script.clone(), out.value))
+            .collect();
+
+        let effects = net_effects(&spent, &created, addresses)?;
+        for (addr, delta) in effects.iter() {
+            self.sender
+                .send(NodeApi::AddPendingBalance(*delta, addr.to_string()))?;
+        }
+        self.pending_effects
+            .lock()?
+            .entry(tx.get_tx_id())
+            .or_default()
+            .extend(effects.iter().cloned());
+
+        Ok(effects)
+    }
+
+    /// Moves the pending deltas of a transaction to the confirmed balances.
+    /// Counts what the transaction pays to the addresses with a pending payment request,
+    /// the requests are sent again when one of them got paid.
+    pub fn record_request_payments(
+        &self,
+        txid: [u8; 32],
+        effects: &WalletEffects,
+    ) -> Result<(), ProtocolError> {
+        let mut requests = self.payment_requests.lock()?;
+        let mut paid = false;
+        for (addr, delta) in effects.iter().filter(|(_, delta)| *delta > 0) {
+            paid |= requests.received(addr, txid, *delta).is_some();
+        }
+        if paid {
+            self.sender.send(NodeApi::PaymentRequests(requests.all()))?;
+        }
+        Ok(())
+    }
+
+    /// Expires the pending payment requests past their deadline, returns how many.
+    pub fn expire_payment_requests(&self) -> Result<usize, ProtocolError> {
+        let mut requests = self.payment_requests.lock()?;
+        let expired = requests.expire(Utc::now().timestamp() as u32);
+        if expired > 0 {
+            self.sender.send(NodeApi::PaymentRequests(requests.all()))?;
+        }
+        Ok(expired)
+    }
 
-        let tx_message = TxMessage::new(tx);
-        let streams = self.register.read()?.get_all_streams();
+    pub fn confirm_pending_effects(&self, txid: &[u8; 32]) -> Result<(), ProtocolError> {
+        let effects = self.pending_effects.lock()?.remove(txid);
+        for (addr, delta) in effects.unwrap_or_default() {
+            self.sender
+                .send(NodeApi::AddConfirmedBalance(delta, addr))?;
+        }
+        Ok(())
+    }
 
-        let mut peers_sent = 0;
-        for mut stream in streams {
-            if tx_message.write_to(&mut stream).is_ok() {
-                peers_sent += 1;
+    /// Reverts the pending deltas of a transaction that won't confirm, like a replaced one.
+    pub fn drop_pending_effects(&self, txid: &[u8; 32]) -> Result<(), ProtocolError> {
+        let effects = self.pending_effects.lock()?.remove(txid);
+        for (addr, delta) in effects.unwrap_or_default() {
+            self.sender.send(NodeApi::AddPendingBalance(-delta, addr))?;
+        }
+        Ok(())
+    }
+
+    /// Stops tracking the address, dropping its wallet transactions and pending deltas.
+    pub fn forget_address(&self, addr: &str) -> Result<(), ProtocolError> {
+        self.wallet_addresses
+            .write()?
+            .retain(|address| address != addr);
+        self.wallet_txs
+            .write()?
+            .retain(|_, address| address != addr);
+
+        let mut pending_effects = self.pending_effects.lock()?;
+        for effects in pending_effects.values_mut() {
+            effects.retain(|(address, _)| address != addr);
+        }
+        pending_effects.retain(|_, effects| !effects.is_empty());
+        Ok(())
+    }
+
+    /// Recomputes the confirmed balance of every wallet address from the utxo set and its
+    /// pending balance from the unconfirmed transactions, so the wallet can correct the
+    /// balances it built from the deltas. Returns the number of addresses sent.
+    pub fn reconcile_balances(&self) -> Result<usize, ProtocolError> {
+        let addresses = self.wallet_addresses.read()?.clone();
+        let mut balances = vec![];
+        {
+            let blockchain = self.blockchain.lock()?;
+            let pending_effects = self.pending_effects.lock()?;
+            for addr in addresses {
+                let pkhash = bitcoin_address_to_pkhash(&addr)?;
+                let balance = blockchain.utxo.get_balance(pkhash);
+                let pending: i64 = pending_effects
+                    .values()
+                    .flatten()
+                    .filter(|(address, _)| *address == addr)
+                    .map(|(_, delta)| delta)
+                    .sum();
+                balances.push((balance, pending, addr));
+            }
+        }
+
+        let sent = balances.len();
+        for (balance, pending, addr) in balances {
+            self.sender
+                .send(NodeApi::ReconciledBalance(balance, pending, addr))?;
+        }
+        Ok(sent)
+    }
+
+    /// Announces again, with an inv, the wallet transactions whose rebroadcast is due.
+    /// The ones that are no longer unconfirmed in the mempool leave the schedule.
+    /// Returns the number of transactions announced.
+    pub fn rebroadcast_wallet_transactions(&self) -> Result<usize, ProtocolError> {
+        let due = self.rebroadcast.lock()?.due(Instant::now());
+        if due.is_empty() {
+            return Ok(0);
+        }
+
+        let mut to_announce = vec![];
+        {
+            // Locked in the order of the block handler, the wallet transactions first
+            let wallet_txs = self.wallet_txs.read()?;
+            let mempool = self.mempool.read()?;
+            let mut rebroadcast = self.rebroadcast.lock()?;
+            for txid in due {
+                if mempool.contains_key(&txid) && wallet_txs.contains_key(&txid) {
+                    to_announce.push(txid);
+                } else {
+                    rebroadcast.remove(&txid);
+                }
             }
+            to_announce = mempool.parents_first(to_announce);
         }
 
-        if peers_sent == 0 {
+        self.announce_transactions(to_announce)
+    }
+
+    /// It receives a transaction, adds it to the mempool and queues its announcement.
+    /// Peers get an inv on the next trickle and ask for the tx with getdata.
+    /// returns the number of connected peers that will be announced the tx.
+    pub fn broadcast_transaction(&self, tx: RawTransaction) -> Result<usize, ProtocolError> {
+        if self.config.readonly {
             return Err(ProtocolError::Error(
-                "Couldn't send the tx to any peer".to_string(),
+                "The node is read-only, it doesn't broadcast transactions".to_string(),
             ));
         }
+        let txid = tx.get_tx_id();
+        self.mempool.write()?.insert(txid, tx);
+        self.announce_transaction(txid)
+    }
 
-        Ok(peers_sent)
+    /// Queues a mempool transaction to be announced to the peers, returns how many there are.
+    /// A read-only node doesn't announce anything.
+    pub fn announce_transaction(&self, txid: [u8; 32]) -> Result<usize, ProtocolError> {
+        if self.config.readonly {
+            return Ok(0);
+        }
+        let mut pending = self.pending_announcements.lock()?;
+        if !pending.contains(&txid) {
+            pending.push(txid);
+        }
+        drop(pending);
+
+        let peers = self.register.read()?.tx_relays_len();
+        if peers == 0 {
+            return Err(ProtocolError::Error(
+                "There are no peers to announce the tx".to_string(),
+            ));
+        }
+
+        Ok(peers)
+    }
+
+    /// Sends an inv with every queued transaction, in random order, to all of the peers.
+    /// Returns the number of transactions announced.
+    pub fn trickle_announcements(&self) -> Result<usize, ProtocolError> {
+        let mut txids = std::mem::take(&mut *self.pending_announcements.lock()?);
+        txids.shuffle(&mut rand::thread_rng());
+        let txids = self.mempool.read()?.parents_first(txids);
+        self.announce_transactions(txids)
+    }
+
+    fn announce_transactions(&self, txids: Vec<[u8; 32]>) -> Result<usize, ProtocolError> {
+        if txids.is_empty() || self.config.readonly {
+            return Ok(0);
+        }
+
+        let inventory: Vec<Inventory> = txids
+            .into_iter()
+            .map(|txid| Inventory::new(TypeIdentifier::MsgTx, txid))
+            .collect();
+        let announced = inventory.len();
+        let inv_message = InvMessage {
+            count: CompactSize::new_from_usize(announced),
+            inventory,
+        };
+        let mut bytes = vec![];
+        inv_message.write_to(&mut bytes)?;
+        self.register.write()?.broadcast_to_tx_relays(bytes);
+
+        Ok(announced)
     }
 
     pub fn create_transaction(
@@ -206,112 +662,381 @@ impl Node {
         payee_bitcoin_address: &str,
         amount: i64,
         fee: i64,
+        options: &PaymentOptions,
     ) -> Result<RawTransaction, ProtocolError> {
+        if amount < DUST_LIMIT {
+            return Err(ProtocolError::Error(format!(
+                "Transaction is not valid: the amount is below the dust limit of {} satoshis",
+                DUST_LIMIT
+            )));
+        }
         let pkhash = wif_to_pkhash(payer_wif)?;
-        let (outs_to_spend, sum) = self.get_outs_to_spend(&pkhash, amount + fee)?;
+        let mut utxo = self.blockchain.lock()?.get_utxo(pkhash.to_vec());
+        // Hand-picked coins may be unconfirmed change, listed in the Coins page
+        let unconfirmed = if options.spend_unconfirmed || options.selected_coins.is_some() {
+            self.unconfirmed_change(&wif_to_bitcoin_address(payer_wif)?)?
+        } else {
+            vec![]
+        };
+        utxo.extend(unconfirmed.iter().cloned());
+        let frozen = self.frozen_coins.read()?;
+        let selected = options.selected_coins.as_deref();
+        let (outs_to_spend, sum) = select_coins(utxo, amount + fee, selected, &frozen)?;
+        drop(frozen);
 
         let mut outputs = vec![TxOut::new(
             amount,
             PubKeyScript::from_address(payee_bitcoin_address)?.to_vec(),
         )];
+        if let Some(data) = &options.data {
+            outputs.push(TxOut::new(0, PubKeyScript::op_return(data)?.to_vec()));
+        }
 
-        if amount + fee < sum {
+        // Change below the dust limit is left to the fee
+        if sum - amount - fee >= DUST_LIMIT {
             outputs.push(TxOut::new(
                 sum - amount - fee,
                 PubKeyScript::P2PKH(pkhash.to_vec()).to_vec(),
             ));
         }
 
-        let tx = RawTransaction::create_transaction(outs_to_spend, outputs, payer_wif);
+        let sighash_type = options.tx.sighash_type;
+        if !is_valid_sighash_type(sighash_type) {
+            return Err(ProtocolError::Error("Unknown sighash type".to_string()));
+        }
+        if sighash_type & !SIGHASH_ANYONECANPAY == SIGHASH_SINGLE
+            && outs_to_spend.len() > outputs.len()
+        {
+            return Err(ProtocolError::Error(
+                "SIGHASH_SINGLE needs an output for every input".to_string(),
+            ));
+        }
+
+        let tx =
+            RawTransaction::create_transaction(outs_to_spend, outputs, payer_wif, &options.tx)?;
 
-        if !self.blockchain.lock()?.is_valid_tx(&tx) {
-            return Err(ProtocolError::Error("Transaction is not valid".to_string()));
+        let blockchain = self.blockchain.lock()?;
+        if let Err(e) = blockchain.validate_tx_spending(&tx, &unconfirmed) {
+            log(format!(
+                "Rejected the payment {}: {}",
+                bytes_to_hex_string(&tx.get_tx_id()),
+                e
+            ));
+            return Err(ProtocolError::Error(format!(
+                "Transaction is not valid: {}",
+                e
+            )));
         };
+        // The peers only relay transactions that can be mined in the next block
+        let next_height = blockchain.get_height() as u32 + 1;
+        if !tx.is_final(next_height, blockchain.get_last_timestamp()) {
+            return Err(ProtocolError::Error(
+                "The lock time hasn't been reached yet".to_string(),
+            ));
+        }
+        drop(blockchain);
+        self.check_chain_limits(&tx)?;
+        self.check_min_relay_fee(&tx)
+            .map_err(|e| ProtocolError::Error(format!("Transaction is not valid: {}", e)))?;
 
         Ok(tx)
     }
 
+    /// Rejects our transactions that make too long chains of unconfirmed ones, the peers would drop them.
+    fn check_chain_limits(&self, tx: &RawTransaction) -> Result<(), ProtocolError> {
+        self.mempool
+            .read()?
+            .check_chain_limits(tx)
+            .map_err(|e| ProtocolError::Error(format!("Transaction is not valid: {}", e)))
+    }
+
+    /// Rejects the transactions paying less than the min relay fee, neither our mempool nor the
+    /// one of the peers would take them, and the ones whose inputs can't be found.
+    pub fn check_min_relay_fee(&self, tx: &RawTransaction) -> Result<(), ProtocolError> {
+        let blockchain = self.blockchain.lock()?;
+        let mempool = self.mempool.read()?;
+        let fee = tx_fee(tx, &blockchain, &mempool);
+        check_min_relay_fee(fee, tx.vsize(), self.config.min_relay_fee)
+    }
+
+    /// Outputs paying to the address from its unconfirmed payments that no other mempool
+    /// transaction spends. They can be spent before the payments confirm.
+    pub fn unconfirmed_change(&self, addr: &str) -> Result<Vec<([u8; 32], Output)>, ProtocolError> {
+        let pkhash = bitcoin_address_to_pkhash(addr)?;
+        // Released before locking the mempool, the snapshot locks them the other way around
+        let payments: Vec<[u8; 32]> = self
+            .pending_effects
+            .lock()?
+            .iter()
+            .filter(|(_, effects)| {
+                effects
+                    .iter()
+                    .any(|(address, delta)| address == addr && *delta < 0)
+            })
+            .map(|(txid, _)| *txid)
+            .collect();
+        Ok(self.mempool.read()?.unspent_outputs(&payments, &pkhash))
+    }
+
     /// It downloads all the blocks since the configurable `block_downloading_timestamp` in the number of threads passed as parameters
     fn multi_threaded_block_download(&self, nthreads: usize) -> Result<(), ProtocolError> {
-        let hashes_to_download = self
-            .blockchain
-            .lock()?
-            .get_hashes_since(self.config.block_downloading_timestamp);
+        let (hashes_to_download, recent) = {
+            let blockchain = self.blockchain.lock()?;
+            let hashes = blockchain.get_hashes_since(self.config.block_downloading_timestamp);
+            let recent = blockchain.are_among_last(&hashes, NODE_NETWORK_LIMITED_BLOCKS);
+            (hashes, recent)
+        };
+
+        let streams = self.register.read()?.get_n_block_streams(nthreads, recent);
+
+        let blocks = self.download_blocks_from(streams, hashes_to_download)?;
+
+        let mut blockchain = self.blockchain.lock()?;
 
-        let mut streams = self.register.read()?.get_n_streams(nthreads);
+        for b in blocks {
+            blockchain.add_block_txs(b)?;
+        }
+
+        Ok(())
+    }
 
-        let chunk_size = (hashes_to_download.len() + nthreads - 1) / nthreads;
+    /// Downloads the blocks splitting them between the streams, one thread per stream.
+    /// The progress is reported to the wallet with NodeApi::Loading and NodeApi::DownloadProgress,
+    /// until every thread finishes or fails.
+    fn download_blocks_from(
+        &self,
+        mut streams: Vec<PeerStream>,
+        hashes_to_download: Vec<[u8; 32]>,
+    ) -> Result<Vec<BlockMessage>, ProtocolError> {
+        if hashes_to_download.is_empty() {
+            return Ok(vec![]);
+        }
+        if streams.is_empty() {
+            return Err(ProtocolError::ConnectionError(
+                "No connected peer serves full blocks".to_string(),
+            ));
+        }
+        let nthreads = streams.len();
+
+        let chunk_size = hashes_to_download.len().div_ceil(nthreads);
         let mut results: Vec<_> = hashes_to_download
             .chunks(chunk_size)
             .rev()
             .take(nthreads)
             .collect();
 
-        let loading_state_mutex = Arc::new(RwLock::new(0f64));
+        let mut workers = vec![];
+        while let Some(hashes) = results.pop() {
+            workers.push((streams.pop().unwrap(), hashes.to_vec()));
+        }
+        let mut peers = vec![];
+        for (stream, hashes) in workers.iter() {
+            peers.push((stream.peer_addr()?, hashes.len()));
+        }
+        let (reporter, progress_senders) =
+            ProgressReporter::new(peers, Duration::from_secs(LOADING_REPORT_INTERVAL));
 
         let mut threads: Vec<JoinHandle<Result<Vec<BlockMessage>, ProtocolError>>> = vec![];
-        for _ in 0..nthreads {
-            let b = streams.pop().unwrap();
-            let hashes = results.pop().unwrap().to_vec();
-            let l = loading_state_mutex.clone();
+        for ((b, hashes), progress) in workers.into_iter().zip(progress_senders) {
+            let control = Arc::clone(&self.sync_control);
+            let register = Arc::clone(&self.register);
             let thread = thread::spawn(move || -> Result<Vec<BlockMessage>, ProtocolError> {
-                let n = Node::download_blocks(b, hashes, l)?;
-                Ok(n)
+                let requested = hashes.len();
+                let res = Node::download_blocks(b, hashes, &progress, &control, &register);
+                progress.send(match &res {
+                    Ok(blocks) if blocks.len() == requested => WorkerProgress::Finished,
+                    _ => WorkerProgress::Failed,
+                });
+                res
             });
             threads.push(thread);
         }
 
-        let mut a: f64 = 0.0;
-        while a < 0.98 {
-            a = *loading_state_mutex.read()? / hashes_to_download.len() as f64;
-            std::thread::sleep(std::time::Duration::from_secs(1));
-            self.sender.send(NodeApi::Loading(a)).unwrap();
-        }
+        reporter.run(|fraction, downloads| {
+            let _ = self.sender.send(NodeApi::Loading(fraction));
+            let _ = self
+                .sender
+                .send(NodeApi::DownloadProgress(downloads.to_vec()));
+        });
 
         let mut blocks = vec![];
         for t in threads {
-            blocks.extend_from_slice(&t.join().unwrap()?);
+            blocks.extend_from_slice(
+                &t.join().map_err(|_| {
+                    ProtocolError::Error("Joining the handle of thread".to_string())
+                })??,
+            );
         }
 
-        let mut blockchain = self.blockchain.lock()?;
+        Ok(blocks)
+    }
 
-        for b in blocks {
-            blockchain.add_block_txs(b)?;
+    /// Downloads the blocks since `timestamp` that don't have their transactions yet and rebuilds the utxo set.
+    /// A new connection is opened to a registered peer, so the listening threads keep their streams.
+    pub fn rescan(&self, timestamp: u32) -> Result<(), ProtocolError> {
+        let (missing, recent) = {
+            let blockchain = self.blockchain.lock()?;
+            let missing = blockchain.get_missing_blocks_since(timestamp);
+            let recent = blockchain.are_among_last(&missing, NODE_NETWORK_LIMITED_BLOCKS);
+            (missing, recent)
+        };
+
+        if !missing.is_empty() {
+            let peer = self
+                .register
+                .read()?
+                .get_n_block_streams(1, recent)
+                .pop()
+                .ok_or_else(|| {
+                    ProtocolError::ConnectionError(
+                        "No connected peer serves full blocks".to_string(),
+                    )
+                })?
+                .peer_addr()?;
+            let stream = self.connect_to(peer)?;
+
+            let blocks = self.download_blocks_from(vec![stream], missing)?;
+
+            let mut blockchain = self.blockchain.lock()?;
+            for b in blocks {
+                blockchain.add_block_txs(b)?;
+            }
         }
 
+        self.blockchain.lock()?.rebuild_utxo();
+        self.sender.send(NodeApi::Loading(1.0))?;
         Ok(())
     }
 
+    /// Leaves the initial block download if the chain is close to the best peer, true if it just
+    /// left it.
+    pub fn update_ibd(&self) -> Result<bool, ProtocolError> {
+        let height = self.blockchain.lock()?.get_height();
+        if !self.ibd.update(height) {
+            return Ok(false);
+        }
+        log(format!(
+            "Initial block download finished at height {}, the best peer is at {}",
+            height,
+            self.ibd.best_peer_height()
+        ));
+        self.advance_sync(SyncState::Synced)?;
+        Ok(true)
+    }
+
+    /// Moves the sync to the phase, telling the wallet when it changed.
+    pub fn advance_sync(&self, to: SyncState) -> Result<(), ProtocolError> {
+        if self.sync.advance(to)? {
+            log(format!("Sync phase: {}", to));
+            self.sender.send(NodeApi::SyncStateChanged(to))?;
+        }
+        Ok(())
+    }
+
+    /// Asks the peers that relay transactions for their mempool, once the node is synced.
+    pub fn request_mempools(&self) -> Result<usize, ProtocolError> {
+        if self.config.blocksonly {
+            return Ok(0);
+        }
+        let mut request = vec![];
+        MessageHeader::new("mempool".to_string(), vec![])?.write_to(&mut request)?;
+        Ok(self.register.write()?.broadcast_to_tx_relays(request))
+    }
+
+    /// Whether the transaction was recently rejected or confirmed, so it isn't requested or handled again.
+    pub fn is_known_txid(&self, txid: &[u8; 32]) -> Result<bool, ProtocolError> {
+        Ok(self.recently_rejected.lock()?.contains(txid)
+            || self.recently_confirmed.lock()?.contains(txid))
+    }
+
+    /// Fails if the peer is out of the allowlist or in the denylist of the configuration.
+    fn check_peer_allowed(&self, socket: &SocketAddr) -> Result<(), ProtocolError> {
+        if !self.config.peer_filter.is_allowed(&socket.ip()) {
+            return Err(ProtocolError::ConnectionError(format!(
+                "Peer {} is not allowed by the configuration",
+                socket.ip()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Opens a connection to `socket`, encrypted if v2transport is enabled and the peer
+    /// supports it. The peers that don't are dialed again in plaintext.
+    fn dial(&self, socket: SocketAddr, timeout: Duration) -> Result<PeerStream, ProtocolError> {
+        let connect = || -> Result<TcpStream, ProtocolError> {
+            let tcp = TcpStream::connect_timeout(&socket, timeout)?;
+            tcp.set_read_timeout(Some(self.config.tcp_timeout))?;
+            tcp.set_write_timeout(Some(self.config.tcp_timeout))?;
+            Ok(tcp)
+        };
+        if !self.config.v2transport {
+            return Ok(PeerStream::plain(connect()?));
+        }
+
+        match PeerStream::initiate_v2(connect()?) {
+            Ok(stream) => Ok(stream),
+            Err(e) => {
+                log(format!(
+                    "V2 HANDSHAKE FAILED with {}: {}. Reconnecting in plaintext",
+                    socket, e
+                ));
+                Ok(PeerStream::plain(connect()?))
+            }
+        }
+    }
+
+    /// Opens a new connection to `socket` and performs the handshake.
+    fn connect_to(&self, socket: SocketAddr) -> Result<PeerStream, ProtocolError> {
+        self.check_peer_allowed(&socket)?;
+        let mut stream = self.dial(socket, self.config.tcp_timeout)?;
+
+        self.handshake(&mut stream)?;
+        self.register.write()?.record_traffic(&stream, None);
+        Ok(stream)
+    }
+
+    /// Requests the blocks in batches of BLOCK_DOWNLOAD_BATCH, waiting before each batch while the sync is paused.
     fn download_blocks(
-        mut stream: TcpStream,
+        mut stream: PeerStream,
         hashes: Vec<[u8; 32]>,
-        loading_state: Arc<RwLock<f64>>,
+        progress: &WorkerSender,
+        control: &SyncControl,
+        register: &RwLock<Register>,
     ) -> Result<Vec<BlockMessage>, ProtocolError> {
         stream.set_read_timeout(None)?;
-        let mut requested_blocks = hashes.len();
-        if requested_blocks == 0 {
-            return Ok(vec![]);
-        }
-        let getdata = GetDataMessage::new(hashes, TypeIdentifier::MsgBlock);
-        getdata.write_to(&mut stream)?;
 
         let mut blocks = vec![];
-        while let Ok(i) = stream.peek(&mut [0u8; 1]) {
-            if i == 0 {
-                break;
-            }
+        for batch in hashes.chunks(BLOCK_DOWNLOAD_BATCH) {
+            control.wait_while_paused()?;
 
-            let m = Message::read_from(&mut stream)?;
+            let mut requested_blocks = batch.len();
+            let mut getdata = vec![];
+            GetDataMessage::new(batch.to_vec(), TypeIdentifier::MsgBlock).write_to(&mut getdata)?;
+            register.write()?.send_to(&stream, getdata)?;
 
-            dbg!(requested_blocks);
-            if let Message::Block(block) = m {
-                blocks.push(block);
-                requested_blocks -= 1;
-                *loading_state.write()? += 1.0;
-            }
+            while let Ok(i) = stream.peek(&mut [0u8; 1]) {
+                if i == 0 {
+                    return Ok(blocks);
+                }
 
-            if requested_blocks == 0 {
-                break;
+                let (m, size) = Message::read_from_with_size(&mut stream)?;
+                register.write()?.record_traffic(&stream, Some((&m, size)));
+
+                match m {
+                    Message::Block(block) => {
+                        blocks.push(block);
+                        requested_blocks -= 1;
+                        progress.send(WorkerProgress::Block);
+                    }
+                    Message::Ping(ping) => {
+                        PongMessage::new(ping.get_nonce()).write_to(&mut stream)?
+                    }
+                    _ => {}
+                }
+
+                if requested_blocks == 0 {
+                    break;
+                }
             }
         }
 
@@ -319,105 +1044,806 @@ impl Node {
     }
 
     /// It performs the bitcoin protocol handshake and header sync with `stream`
-    pub fn handshake(&mut self, stream: &mut TcpStream) -> Result<VersionMessage, ProtocolError> {
-        self.version_message.write_to(stream)?;
+    /// The peer has HANDSHAKE_TIMEOUT seconds to answer, the read timeout is restored afterwards.
+    pub fn handshake(&self, stream: &mut PeerStream) -> Result<VersionMessage, ProtocolError> {
+        let deadline = Instant::now() + Duration::from_secs(HANDSHAKE_TIMEOUT);
+        let read_timeout = stream.read_timeout()?;
+        self.version_message.read()?.write_to(stream)?;
 
-        let recv_version_message = match Message::read_from(stream)? {
+        let recv_version_message = match read_before(stream, deadline)? {
             Message::Version(v) => v,
             _ => return Err(ProtocolError::Error("Expected version message".to_string())),
         };
+        self.version_message
+            .read()?
+            .validate_peer(&recv_version_message)?;
+        self.record_local_address(stream.peer_addr()?, &recv_version_message)?;
+        self.ibd.peer_height(recv_version_message.start_height());
 
         let verack = MessageHeader::new("verack".to_string(), Vec::new())?;
         verack.write_to(stream)?;
 
-        match Message::read_from(stream)? {
+        match read_before(stream, deadline)? {
             Message::Verack => {}
             _ => return Err(ProtocolError::Error("Expected verack message".to_string())),
         };
 
+        stream.set_read_timeout(read_timeout)?;
         Ok(recv_version_message)
     }
 
-    fn _get_addresses(&self, stream: &mut TcpStream) -> Result<Vec<Ipv6Addr>, ProtocolError> {
+    /// Counts the address the peer sees us at, our version message advertises the one most
+    /// peers agree on instead of 127.0.0.1.
+    fn record_local_address(
+        &self,
+        peer: SocketAddr,
+        version: &VersionMessage,
+    ) -> Result<(), ProtocolError> {
+        let mut local_address = self.local_address.lock()?;
+        if !local_address.record(peer.ip(), version.addr_recv_ip) {
+            return Ok(());
+        }
+        if let Some(ip) = local_address.best() {
+            log(format!(
+                "EXTERNAL ADDRESS {} reported by the peers",
+                display_ip(&ip)
+            ));
+            self.version_message.write()?.addr_trans_ip = ip;
+        }
+        Ok(())
+    }
+
+    /// Addr message with our external address, None until the peers agree on one.
+    fn local_address_message(&self) -> Result<Option<AddrMessage>, ProtocolError> {
+        let ip = match self.local_address.lock()?.best() {
+            Some(ip) => ip,
+            None => return Ok(None),
+        };
+        let services = self.version_message.read()?.services;
+        let now = Utc::now().timestamp() as u32;
+        Ok(Some(AddrMessage::new(vec![NetworkAddr::new(
+            now,
+            services,
+            ip,
+            self.config.port,
+        )])))
+    }
+
+    fn _get_addresses(&self, stream: &mut PeerStream) -> Result<Vec<SocketAddr>, ProtocolError> {
         let getaddr = MessageHeader::new("getaddr".to_string(), Vec::new())?;
         getaddr.write_to(stream)?;
 
         let addr_message = AddrMessage::read_from(stream)?;
 
-        let mut ips: Vec<Ipv6Addr> = vec![];
-        for i in addr_message.ip_addresses {
-            ips.push(i.ip);
+        Ok(addr_message
+            .ip_addresses
+            .iter()
+            .map(|addr| addr.socket_addr())
+            .collect())
+    }
+
+    /// Collects the chain, peers and mempool info shown in the Overview page.
+    pub fn get_stats(&self) -> Result<NodeStats, ProtocolError> {
+        let now = Utc::now().timestamp() as u32;
+        let (chain, sync_progress, recent_blocks) = {
+            let blockchain = self.blockchain.lock()?;
+            (
+                blockchain.get_chain_stats(STATS_WINDOW),
+                blockchain.sync_progress(now),
+                blockchain.recent_blocks(),
+            )
+        };
+
+        let register = self.register.read()?;
+        Ok(NodeStats {
+            chain,
+            peers: register.len(),
+            inbound: register.inbound_counts(),
+            mempool_size: self.mempool.read()?.len(),
+            sync_progress,
+            traffic: register.total_traffic(),
+            recent_blocks,
+        })
+    }
+
+    /// Balances, coins, history and pending transactions of a wallet address, all taken
+    /// under the blockchain and mempool locks so they agree with each other.
+    pub fn wallet_snapshot(&self, addr: &str) -> Result<WalletSnapshot, ProtocolError> {
+        let pkhash = bitcoin_address_to_pkhash(addr)?;
+        // Taken before the locks below, it locks the pending effects and the mempool itself
+        let unconfirmed = self.unconfirmed_change(addr)?;
+        let blockchain = self.blockchain.lock()?;
+        let mempool = self.mempool.read()?;
+        let pending_effects = self.pending_effects.lock()?;
+
+        let mut pending_balance = 0;
+        let mut pending_txs = vec![];
+        for (txid, effects) in pending_effects.iter() {
+            let delta = match effects.iter().find(|(address, _)| address == addr) {
+                Some((_, delta)) => *delta,
+                None => continue,
+            };
+            pending_balance += delta;
+
+            let tx = match mempool.get(txid) {
+                Some(raw_tx) => {
+                    let mut tx = Tx::from_raw_tx(raw_tx);
+                    tx.fee = tx_fee(raw_tx, &blockchain, &mempool);
+                    tx
+                }
+                None => continue,
+            };
+            let payer = blockchain
+                .utxo
+                .get_outpoint_address(&tx.tx_in[0].previous_output);
+            // The payments of the address are shown with the address they pay to
+            let payee = tx
+                .tx_out
+                .iter()
+                .map(|out| out.pkscript.get_address())
+                .find(|address| delta < 0 && address != addr)
+                .unwrap_or_else(|| addr.to_string());
+            pending_txs.push((tx, payer, payee));
         }
 
-        Ok(ips)
+        let mut coins = blockchain.get_coins(pkhash.clone());
+        coins.extend(unconfirmed.into_iter().map(|(txid, out)| (txid, out, 0)));
+        let frozen = self.frozen_coins.read()?;
+        Ok(WalletSnapshot {
+            address: addr.to_string(),
+            balance: blockchain.utxo.get_balance(pkhash.clone()),
+            pending_balance,
+            coins: Coin::from_outputs(coins, addr, &frozen),
+            history: blockchain.get_tx_history(pkhash),
+            pending_txs,
+        })
+    }
+
+    /// Candidate block on top of the last header with the best paying mempool transactions.
+    pub fn block_template(&self, payout_address: &str) -> Result<BlockTemplate, ProtocolError> {
+        let blockchain = self.blockchain.lock()?;
+        let mempool = self.mempool.read()?;
+        BlockTemplate::new(
+            &blockchain,
+            &mempool,
+            payout_address,
+            Utc::now().timestamp() as u32,
+        )
     }
 
-    fn get_outs_to_spend(
+    /// Converts a mempool transaction for the wallet, with its fee if its previous outputs are known.
+    pub fn tx_with_fee(&self, raw_tx: &RawTransaction) -> Result<Tx, ProtocolError> {
+        let mut tx = Tx::from_raw_tx(raw_tx);
+        let blockchain = self.blockchain.lock()?;
+        let mempool = self.mempool.read()?;
+        tx.fee = tx_fee(raw_tx, &blockchain, &mempool);
+        Ok(tx)
+    }
+
+    /// Describes every transaction of the mempool for the wallet.
+    pub fn mempool_snapshot(&self) -> Result<Vec<MempoolTxInfo>, ProtocolError> {
+        let wallet_txs: Vec<[u8; 32]> = self.wallet_txs.read()?.keys().copied().collect();
+        let addresses = self.wallet_addresses.read()?.clone();
+        let blockchain = self.blockchain.lock()?;
+        let mempool = self.mempool.read()?;
+
+        let belongs_to_wallet = |pk_script: &[u8]| {
+            addresses.iter().any(|addr| {
+                PubKeyScript::can_be_spent_by_address(&pk_script.to_vec(), addr).unwrap_or(false)
+            })
+        };
+
+        let mut snapshot = vec![];
+        for (txid, entry) in mempool.entries() {
+            let mut inputs_value = Some(0);
+            let mut own = false;
+            for txin in entry.tx.tx_in.iter() {
+                let prev = &txin.previous_output;
+                let prev_out = match blockchain.utxo.get(prev.hash, prev.index) {
+                    Some(out) => Some((out.value, out.pkscript.to_vec())),
+                    None => mempool
+                        .get(&prev.hash)
+                        .and_then(|parent| parent.tx_out.get(prev.index as usize))
+                        .map(|out| (out.value, out.pk_script.clone())),
+                };
+
+                match prev_out {
+                    Some((value, pk_script)) => {
+                        own |= belongs_to_wallet(&pk_script);
+                        inputs_value = inputs_value.map(|sum| sum + value);
+                    }
+                    None => inputs_value = None,
+                }
+            }
+
+            let outputs_value: i64 = entry.tx.tx_out.iter().map(|out| out.value).sum();
+            let wallet_relevant = own
+                || wallet_txs.contains(txid)
+                || entry
+                    .tx
+                    .tx_out
+                    .iter()
+                    .any(|out| belongs_to_wallet(&out.pk_script));
+
+            snapshot.push(MempoolTxInfo {
+                txid: *txid,
+                vsize: entry.tx.vsize(),
+                fee: inputs_value.map(|value| value - outputs_value),
+                time: entry.time,
+                wallet_relevant,
+                own,
+            });
+        }
+
+        Ok(snapshot)
+    }
+
+    /// Replaces one of our mempool transactions by a copy paying `extra_fee` more, taken from the change output.
+    /// Like BIP125 asks, the original has to signal the replacement and the fee has to grow by at
+    /// least the min relay fee of the copy. A change left as dust goes to the fee too.
+    /// The new transaction is broadcasted and returned.
+    pub fn bump_fee(
         &self,
-        pkhash: &[u8; 20],
-        amount: i64,
-    ) -> Result<(Vec<([u8; 32], Output)>, i64), ProtocolError> {
-        let mut utxo = self.blockchain.lock()?.get_utxo(pkhash.to_vec());
-        utxo.sort_by(|a, b| b.1.value.partial_cmp(&a.1.value).unwrap());
-
-        let mut out_to_spend = vec![];
-        let mut sum = 0;
-        for output in utxo {
-            sum += output.1.value;
-            out_to_spend.push(output);
-            if sum >= amount {
-                break;
+        payer_wif: &str,
+        txid: [u8; 32],
+        extra_fee: i64,
+    ) -> Result<RawTransaction, ProtocolError> {
+        let pkhash = wif_to_pkhash(payer_wif)?;
+        let old_tx =
+            self.mempool.read()?.get(&txid).cloned().ok_or_else(|| {
+                ProtocolError::Error("Transaction is not in the mempool".to_string())
+            })?;
+        if !old_tx.signals_rbf() {
+            return Err(ProtocolError::Error(
+                "The transaction doesn't signal that it can be replaced".to_string(),
+            ));
+        }
+        // Replacing it would drop the payments that spend its change
+        if self.mempool.read()?.has_children(&txid) {
+            return Err(ProtocolError::Error(
+                "The change of the transaction was already spent".to_string(),
+            ));
+        }
+
+        let mut outs_to_spend = vec![];
+        {
+            let blockchain = self.blockchain.lock()?;
+            let mempool = self.mempool.read()?;
+            for txin in old_tx.tx_in.iter() {
+                let prev = &txin.previous_output;
+                // The outputs of the unconfirmed parents are in the mempool
+                let out = blockchain.utxo.get(prev.hash, prev.index).or_else(|| {
+                    let txout = mempool.get(&prev.hash)?.tx_out.get(prev.index as usize)?;
+                    Some(Output::new(
+                        prev.index,
+                        txout.value,
+                        txout.pk_script.clone(),
+                    ))
+                });
+                match out {
+                    Some(out) if out.pkscript.can_be_spent_by(&pkhash.to_vec()) => {
+                        outs_to_spend.push((prev.hash, out))
+                    }
+                    _ => {
+                        return Err(ProtocolError::Error(
+                            "Only transactions paid by this account can be bumped".to_string(),
+                        ))
+                    }
+                }
+            }
+        }
+
+        let change_script = PubKeyScript::P2PKH(pkhash.to_vec()).to_vec();
+        let mut outputs = old_tx.tx_out.clone();
+        let change = outputs
+            .iter()
+            .position(|out| out.pk_script == change_script)
+            .ok_or_else(|| ProtocolError::Error("Transaction has no change output".to_string()))?;
+        let not_enough = || ProtocolError::Error("Change is not enough to pay the fee".to_string());
+        let change_value = outputs[change].value - extra_fee;
+        if change_value < 0 {
+            return Err(not_enough());
+        }
+        if change_value < DUST_LIMIT {
+            outputs.remove(change);
+            if outputs.is_empty() {
+                return Err(not_enough());
             }
+        } else {
+            outputs[change].value = change_value;
         }
+        let outputs: Vec<TxOut> = outputs
+            .into_iter()
+            .map(|out| TxOut::new(out.value, out.pk_script))
+            .collect();
+        let added_fee = old_tx.get_tx_value() - outputs.iter().map(|out| out.value).sum::<i64>();
+
+        // The replacement keeps the lock time and the sequences of the original, so it can be
+        // replaced too
+        let options = TxOptions {
+            lock_time: old_tx.lock_time,
+            rbf: true,
+            sequences: old_tx.tx_in.iter().map(|txin| txin.sequence).collect(),
+            ..TxOptions::default()
+        };
+        let tx = RawTransaction::create_transaction(outs_to_spend, outputs, payer_wif, &options)?;
+
+        let min_added_fee = (self.config.min_relay_fee * tx.vsize() as f64).ceil() as i64;
+        if added_fee < min_added_fee {
+            return Err(ProtocolError::Error(format!(
+                "The fee has to grow by at least {} satoshis to replace the transaction",
+                min_added_fee
+            )));
+        }
+        self.check_min_relay_fee(&tx)
+            .map_err(|e| ProtocolError::Error(format!("Transaction is not valid: {}", e)))?;
+
+        self.drop_pending_effects(&txid)?;
+        self.mempool.write()?.remove(&txid);
+        let addresses = self.wallet_addresses.read()?.clone();
+        self.add_pending_effects(&tx, &addresses)?;
+        let mut wallet_txs = self.wallet_txs.write()?;
+        if let Some(addr) = wallet_txs.remove(&txid) {
+            wallet_txs.insert(tx.get_tx_id(), addr);
+        }
+        drop(wallet_txs);
+        let mut rebroadcast = self.rebroadcast.lock()?;
+        rebroadcast.remove(&txid);
+        rebroadcast.add(tx.get_tx_id(), Instant::now());
+        drop(rebroadcast);
+        self.broadcast_transaction(tx.clone())?;
+
+        Ok(tx)
+    }
 
-        if sum < amount {
-            return Err(ProtocolError::Error("Insufficient balance".to_string()));
+    /// Spends the outputs that an unconfirmed transaction pays to the payer in a child that pays
+    /// `fee`, so miners take both for the fee of the child (child pays for parent).
+    /// The child pays the rest back to the payer, it is broadcasted and returned.
+    pub fn pay_fee_with_child(
+        &self,
+        payer_wif: &str,
+        parent_txid: [u8; 32],
+        fee: i64,
+    ) -> Result<RawTransaction, ProtocolError> {
+        let pkhash = wif_to_pkhash(payer_wif)?;
+        let outs_to_spend = self
+            .mempool
+            .read()?
+            .unspent_outputs(&[parent_txid], &pkhash.to_vec());
+        if outs_to_spend.is_empty() {
+            return Err(ProtocolError::Error(
+                "The transaction has no unspent output of the account".to_string(),
+            ));
+        }
+        let value: i64 = outs_to_spend.iter().map(|(_, out)| out.value).sum();
+        if value - fee < DUST_LIMIT {
+            return Err(ProtocolError::Error(
+                "The outputs of the transaction are not enough to pay the fee".to_string(),
+            ));
         }
 
-        Ok((out_to_spend, sum))
+        let outputs = vec![TxOut::new(
+            value - fee,
+            PubKeyScript::P2PKH(pkhash.to_vec()).to_vec(),
+        )];
+        let tx = RawTransaction::create_transaction(
+            outs_to_spend.clone(),
+            outputs,
+            payer_wif,
+            &TxOptions::default(),
+        )?;
+        self.blockchain
+            .lock()?
+            .validate_tx_spending(&tx, &outs_to_spend)
+            .map_err(|e| ProtocolError::Error(format!("Transaction is not valid: {}", e)))?;
+        self.check_chain_limits(&tx)?;
+        self.check_min_relay_fee(&tx)
+            .map_err(|e| ProtocolError::Error(format!("Transaction is not valid: {}", e)))?;
+
+        let payer_address = wif_to_bitcoin_address(payer_wif)?;
+        self.wallet_txs
+            .write()?
+            .insert(tx.get_tx_id(), payer_address);
+        self.rebroadcast.lock()?.add(tx.get_tx_id(), Instant::now());
+        let addresses = self.wallet_addresses.read()?.clone();
+        // The effects look up the spent outputs in the mempool, before the child is in it
+        self.add_pending_effects(&tx, &addresses)?;
+        self.broadcast_transaction(tx.clone())?;
+
+        Ok(tx)
+    }
+
+    /// Runs the scripts of every input of a mempool or confirmed transaction.
+    /// The spent outputs are looked up in the UTXO set, the mempool and the downloaded blocks.
+    pub fn trace_scripts(&self, txid: [u8; 32]) -> Result<Vec<InputTrace>, ProtocolError> {
+        let blockchain = self.blockchain.lock()?;
+        let mempool = self.mempool.read()?;
+        let tx = match mempool.get(&txid) {
+            Some(tx) => RawTransaction::clone(tx),
+            None => blockchain
+                .get_tx(txid)
+                .map(|tx| tx.to_raw_tx())
+                .ok_or_else(|| ProtocolError::Error("Transaction not found".to_string()))?,
+        };
+
+        let traces = tx
+            .tx_in
+            .iter()
+            .enumerate()
+            .map(|(i, txin)| {
+                let prev = &txin.previous_output;
+                let pubkey_script = match blockchain.utxo.get(prev.hash, prev.index) {
+                    Some(out) => Some(out.pkscript.to_vec()),
+                    None => mempool
+                        .get(&prev.hash)
+                        .and_then(|prev_tx| prev_tx.tx_out.get(prev.index as usize))
+                        .map(|out| out.pk_script.clone())
+                        .or_else(|| {
+                            blockchain
+                                .get_tx(prev.hash)?
+                                .tx_out
+                                .get(prev.index as usize)
+                                .map(|out| out.pkscript.to_vec())
+                        }),
+                };
+                InputTrace {
+                    previous_output: (prev.hash, prev.index),
+                    trace: pubkey_script
+                        .as_ref()
+                        .map(|script| trace_script(script, &tx, i)),
+                    pubkey_script: pubkey_script.unwrap_or_default(),
+                }
+            })
+            .collect();
+        Ok(traces)
     }
 }
 
-fn node_server_handler(node: Arc<Node>) -> std::thread::JoinHandle<()> {
+/// Reads the blockchain file, reindexing it first if the configuration asks to.
+fn load_blockchain(
+    config: &Config,
+    datadir: &Option<DataDir>,
+    sender: &EventSender,
+) -> Result<Blockchain, ProtocolError> {
+    if !config.reindex {
+        return Blockchain::read_from_file(config.blockchain_file.clone());
+    }
+
+    log(format!("REINDEX: {}", config.blockchain_file));
+    if let Some(datadir) = datadir {
+        datadir.wipe_chainstate()?;
+    }
+    let start = Instant::now();
+    let blockchain = Blockchain::reindex_file(config.blockchain_file.clone(), |done, total| {
+        let _ = sender.send(NodeApi::ReindexProgress(done, total));
+    })?;
+    log(format!(
+        "REINDEX: kept {} blocks in {:?}",
+        blockchain.get_height(),
+        start.elapsed()
+    ));
+    Ok(blockchain)
+}
+
+/// Addresses of the hosts and the dns seeds, or of the peers file when every seed fails.
+fn find_peers(config: &Config) -> Result<Vec<SocketAddr>, ProtocolError> {
+    let mut addrs: Vec<SocketAddr> = Vec::new();
+    for host in config.hosts.iter() {
+        match resolve_peer(host, config.port) {
+            Ok(peers) => addrs.extend(peers),
+            Err(e) => log(format!("Skipping host: {}", e)),
+        }
+    }
+    let mut seeded = query_dns_seeds(&config.dns_seeds, config.port, config.tcp_timeout);
+    if seeded.is_empty() {
+        log(format!(
+            "Every dns seed failed, using the peers in {}",
+            config.peers_file
+        ));
+        seeded = read_peers_file(&config.peers_file).unwrap_or_default();
+    }
+    for peer in interleave_families(seeded) {
+        if !addrs.contains(&peer) {
+            addrs.push(peer);
+        }
+    }
+    if addrs.is_empty() {
+        return Err(ProtocolError::ConnectionError(
+            "No peers found in the dns seeds, the hosts or the peers file".to_string(),
+        ));
+    }
+    Ok(addrs)
+}
+
+/// Generates the synthetic chain, logging the keys to import in the wallet to see its payments.
+fn simulated_chain(config: &SimConfig) -> Result<Blockchain, ProtocolError> {
+    let start = Instant::now();
+    let sim = generate(config)?;
+    log(format!(
+        "SIMULATION: generated {} blocks in {:?}",
+        sim.blockchain.get_height(),
+        start.elapsed()
+    ));
+    for key in sim.wallet_keys.iter() {
+        log(format!(
+            "SIMULATION: wallet key {} of {}",
+            key.wif, key.address
+        ));
+    }
+    Ok(sim.blockchain)
+}
+
+/// Replays a recorded session in place of the sync with the peers.
+fn replay(node: &Arc<Node>, path: &str) -> Result<(), ProtocolError> {
+    node.sender.send(NodeApi::FinishedConnectingToPeers)?;
+    let records = read_session(path)?;
+    let total = records.len();
+    // The recorded messages are handled like the ones of a synced node
+    node.advance_sync(SyncState::Synced)?;
+    let handled = Peer::replay(node, records);
+    log(format!(
+        "REPLAY: handled {} of the {} messages of {}",
+        handled, total, path
+    ));
+    Ok(())
+}
+
+/// Syncs with the peers, or replays the recorded session, refreshes the wallet accounts added
+/// meanwhile and listens to the peers.
+fn sync_and_listen(node: Arc<Node>) -> JoinHandle<()> {
     thread::spawn(move || {
-        let listener = TcpListener::bind("127.0.0.1:18333").unwrap();
-        println!("\x1b[33m== LISTENING FOR NEW CONNECTIONS IN PORT 18333 ==\x1b[0m");
+        let res = match node.config.replay_session.clone() {
+            Some(path) => replay(&node, &path),
+            None => node.initialize(),
+        };
+        if let Err(e) = res {
+            log_error(format!("Initialization Error: {}", e));
+        }
+        if let Err(e) = node.update_ibd() {
+            log_error(format!("Initialization Error: {}", e));
+        }
+        if let Err(e) = refresh_wallet_addresses(&node) {
+            log_error(format!("Wallet communication error: {}", e));
+        }
+        let _ = node.sender.send(NodeApi::NodeReady);
+
+        let streams = match node.register.read() {
+            Ok(register) => match node.tunables.read() {
+                Ok(tunables) => register.get_n_streams(tunables.max_listen_peers),
+                Err(_) => return,
+            },
+            Err(_) => return,
+        };
 
-        let mut handlers = vec![];
-        for stream in listener.incoming() {
-            println!("NEW CONNECTION");
+        log("LISTENING STREAMS".to_string());
+        for stream in streams {
             let n = Arc::clone(&node);
-            let mut stream = stream.unwrap();
-            let handle = thread::spawn(move || -> Result<(), ProtocolError> {
-                match Message::read_from(&mut stream)? {
-                    Message::Version(_) => {}
-                    _ => return Err(ProtocolError::Error("Expected version message".to_string())),
+            let job = node.peer_workers.execute(move || {
+                if let Err(e) = Peer::registered(stream, &n).and_then(|peer| peer.listen(n)) {
+                    log_error(format!("Thread broke: {}", e));
                 };
+            });
+            if let Err(e) = job {
+                log_error(format!("Couldn't listen to the peer: {}", e));
+            }
+        }
+    })
+}
 
-                n.version_message.write_to(&mut stream)?;
+/// Sends the node stats to the wallet every STATS_INTERVAL seconds, until the wallet is closed.
+/// The mempool is sent too, only when it changed since the last time.
+fn stats_reporter(node: Arc<Node>) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let mut mempool_version = None;
+        loop {
+            match node.get_stats() {
+                Ok(stats) => {
+                    if node.sender.send(NodeApi::Stats(stats)).is_err() {
+                        return;
+                    }
+                }
+                Err(e) => log_error(format!("Error collecting node stats: {}", e)),
+            }
 
-                match Message::read_from(&mut stream)? {
-                    Message::Verack => {}
-                    _ => return Err(ProtocolError::Error("Expected verack message".to_string())),
-                };
+            let version = node.mempool.read().map(|m| m.version()).ok();
+            if version != mempool_version {
+                match node.mempool_snapshot() {
+                    Ok(snapshot) => {
+                        if node.sender.send(NodeApi::MempoolUpdate(snapshot)).is_err() {
+                            return;
+                        }
+                        mempool_version = version;
+                    }
+                    Err(e) => log_error(format!("Error collecting the mempool: {}", e)),
+                }
+            }
 
-                let verack = MessageHeader::new("verack".to_string(), Vec::new())?;
-                verack.write_to(&mut stream).unwrap();
+            thread::sleep(Duration::from_secs(STATS_INTERVAL));
+        }
+    })
+}
 
-                if let Err(e) = handle_messages(stream, n) {
-                    eprintln!("Thread broke: {}", e);
-                };
-                Ok(())
-            });
-            handlers.push(handle)
+fn rebroadcaster(node: Arc<Node>) -> JoinHandle<()> {
+    thread::spawn(move || loop {
+        match node.rebroadcast_wallet_transactions() {
+            Ok(0) => {}
+            Ok(n) => log(format!(
+                "Announced again {} unconfirmed wallet transactions",
+                n
+            )),
+            Err(e) => log_error(format!(
+                "Error rebroadcasting the wallet transactions: {}",
+                e
+            )),
         }
+        thread::sleep(Duration::from_secs(REBROADCAST_TICK));
+    })
+}
 
-        for handle in handlers {
-            handle
-                .join()
-                .map_err(|_| ProtocolError::Error("Joining the handle of thread".to_string()))
-                .unwrap()
-                .unwrap();
+/// Sends the recomputed wallet balances every BALANCE_REFRESH_INTERVAL seconds.
+/// The utxo set is incomplete during the initial block download, so it waits for it.
+fn balance_refresher(node: Arc<Node>) -> JoinHandle<()> {
+    thread::spawn(move || loop {
+        thread::sleep(Duration::from_secs(BALANCE_REFRESH_INTERVAL));
+        if node.ibd.is_active() {
+            continue;
+        }
+        if let Err(e) = node.reconcile_balances() {
+            log_error(format!("Error refreshing the wallet balances: {}", e));
+        }
+    })
+}
+
+/// Disconnects the listened peers that stopped responding, every PEER_CHECK_INTERVAL seconds.
+fn peer_reaper(node: Arc<Node>) -> JoinHandle<()> {
+    thread::spawn(move || loop {
+        thread::sleep(Duration::from_secs(PEER_CHECK_INTERVAL));
+        match node.register.write() {
+            Ok(mut register) => {
+                register.reap_stale(Instant::now());
+            }
+            Err(e) => log_error(format!("Error checking the inactive peers: {}", e)),
+        }
+    })
+}
+
+/// Expires the payment requests whose deadline passed, every PAYMENT_REQUEST_CHECK_INTERVAL seconds.
+fn request_expirer(node: Arc<Node>) -> JoinHandle<()> {
+    thread::spawn(move || loop {
+        thread::sleep(Duration::from_secs(PAYMENT_REQUEST_CHECK_INTERVAL));
+        if let Err(e) = node.expire_payment_requests() {
+            log_error(format!("Error expiring the payment requests: {}", e));
+        }
+    })
+}
+
+/// Flushes the queued tx announcements after random delays, so the peers can't tell
+/// which transactions were created here by the time they get announced.
+fn announcer(node: Arc<Node>) -> JoinHandle<()> {
+    thread::spawn(move || loop {
+        thread::sleep(trickle_delay(Duration::from_millis(TX_TRICKLE_INTERVAL)));
+        if let Err(e) = node.trickle_announcements() {
+            log_error(format!("Error announcing the transactions: {}", e));
+        }
+    })
+}
+
+/// Exponentially distributed delay with the given mean.
+fn trickle_delay(mean: Duration) -> Duration {
+    let uniform: f64 = rand::thread_rng().gen_range(f64::EPSILON..1.0);
+    mean.mul_f64(-uniform.ln())
+}
+
+/// Reads the next message of a handshake, failing once the deadline passed.
+fn read_before(stream: &mut PeerStream, deadline: Instant) -> Result<Message, ProtocolError> {
+    let remaining = deadline.saturating_duration_since(Instant::now());
+    if remaining.is_zero() {
+        return Err(ProtocolError::ConnectionError(
+            "The peer didn't complete the handshake in time".to_string(),
+        ));
+    }
+    stream.set_read_timeout(Some(remaining))?;
+    Message::read_from(stream)
+}
+
+/// Answers the handshake of an inbound peer, in the transport it started, and registers it.
+fn accept_connection(
+    node: &Arc<Node>,
+    id: PeerId,
+    tcp: TcpStream,
+) -> Result<PeerStream, ProtocolError> {
+    node.register.write()?.set_state(id, PeerState::Handshaking);
+    let deadline = Instant::now() + Duration::from_secs(HANDSHAKE_TIMEOUT);
+    tcp.set_write_timeout(Some(Duration::from_secs(HANDSHAKE_TIMEOUT)))?;
+    tcp.set_read_timeout(Some(Duration::from_secs(HANDSHAKE_TIMEOUT)))?;
+    let mut stream = PeerStream::accept(tcp, node.config.v2transport)?;
+    let peer_version = match read_before(&mut stream, deadline)? {
+        Message::Version(v) => v,
+        _ => return Err(ProtocolError::Error("Expected version message".to_string())),
+    };
+    node.version_message.read()?.validate_peer(&peer_version)?;
+    node.ibd.peer_height(peer_version.start_height());
+    node.record_local_address(stream.peer_addr()?, &peer_version)?;
+
+    node.version_message.read()?.write_to(&mut stream)?;
+
+    match read_before(&mut stream, deadline)? {
+        Message::Verack => {}
+        _ => return Err(ProtocolError::Error("Expected verack message".to_string())),
+    };
+
+    let verack = MessageHeader::new("verack".to_string(), Vec::new())?;
+    verack.write_to(&mut stream)?;
+
+    // Gossiped by the inbound peers to theirs, so others can dial us
+    if let Some(addr) = node.local_address_message()? {
+        addr.write_to(&mut stream)?;
+    }
+
+    node.register
+        .write()?
+        .save_connection(id, stream.try_clone()?, peer_version)?;
+    Ok(stream)
+}
+
+fn node_server_handler(node: Arc<Node>) -> std::thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let listener = match TcpListener::bind("127.0.0.1:18333") {
+            Ok(listener) => listener,
+            Err(e) => {
+                log_error(format!("Not listening for new connections: {}", e));
+                return;
+            }
+        };
+        log("LISTENING FOR NEW CONNECTIONS IN PORT 18333".to_string());
+
+        for stream in listener.incoming() {
+            let stream = stream.unwrap();
+            let peer = match stream.peer_addr() {
+                Ok(peer) if node.check_peer_allowed(&peer).is_ok() => peer,
+                Ok(peer) => {
+                    log(format!("REJECTED CONNECTION FROM {}", peer.ip()));
+                    continue;
+                }
+                Err(_) => continue,
+            };
+            // Reserved here, so the slots are counted before the next connection is accepted
+            let reserved = match node.register.write() {
+                Ok(mut register) => register.reserve_inbound(peer, node.config.inbound_limits),
+                Err(_) => continue,
+            };
+            let id = match reserved {
+                Ok(id) => id,
+                Err(e) => {
+                    log(format!("REJECTED CONNECTION FROM {}: {}", peer.ip(), e));
+                    continue;
+                }
+            };
+
+            log("NEW CONNECTION".to_string());
+            let n = Arc::clone(&node);
+            let job = node.peer_workers.execute(move || {
+                let stream = match accept_connection(&n, id, stream) {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        log_error(format!("Couldn't accept the connection: {}", e));
+                        if let Ok(mut register) = n.register.write() {
+                            register.set_state(id, PeerState::Disconnected);
+                        }
+                        return;
+                    }
+                };
+
+                if let Err(e) = Peer::registered(stream, &n).and_then(|peer| peer.listen(n)) {
+                    log_error(format!("Thread broke: {}", e));
+                };
+            });
+            if let Err(e) = job {
+                log_error(format!("Couldn't accept the connection: {}", e));
+                if let Ok(mut register) = node.register.write() {
+                    register.set_state(id, PeerState::Disconnected);
+                }
+            }
         }
     })
 }
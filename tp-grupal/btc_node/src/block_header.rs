@@ -2,6 +2,9 @@ use crate::protocol_error::ProtocolError;
 use bitcoin_hashes::{sha256d, Hash};
 use std::io::Read;
 
+// Bytes taken by a serialized header
+pub const BLOCK_HEADER_SIZE: usize = 80;
+
 pub mod block_header_builder {
     use super::*;
     pub struct BlockHeaderBuilder {
@@ -123,16 +126,19 @@ impl BlockHeader {
     }
 
     pub fn to_bytes(&self) -> Vec<u8> {
-        let mut bytes = Vec::new();
+        let mut bytes = Vec::with_capacity(BLOCK_HEADER_SIZE);
+        self.append_to(&mut bytes);
+        bytes
+    }
 
+    /// Serializes the header at the end of `bytes`.
+    pub fn append_to(&self, bytes: &mut Vec<u8>) {
         bytes.extend_from_slice(&self.version.to_le_bytes());
         bytes.extend_from_slice(&self.prev_block_hash);
         bytes.extend_from_slice(&self.merkle_root_hash);
         bytes.extend_from_slice(&self.timestamp.to_le_bytes());
         bytes.extend_from_slice(&self.bits.to_le_bytes());
         bytes.extend_from_slice(&self.nonce.to_le_bytes());
-
-        bytes
     }
 
     pub fn hash(&self) -> [u8; 32] {
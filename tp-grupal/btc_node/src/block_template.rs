@@ -0,0 +1,262 @@
+use std::collections::HashSet;
+
+use crate::{
+    block_header::BlockHeader,
+    blockchain::Blockchain,
+    constants::{
+        COINBASE_RESERVED_WEIGHT, INITIAL_SUBSIDY, MAX_BLOCK_WEIGHT, SUBSIDY_HALVING_INTERVAL,
+    },
+    mempool::Mempool,
+    merkle_tree::merkle_tree_root,
+    message::{block::BlockMessage, compact_size::CompactSize},
+    protocol_error::ProtocolError,
+    raw_transaction::{Outpoint, RawTransaction, TxIn, TxOut},
+    script::PubKeyScript,
+};
+
+// Version of the templates, with the BIP 9 top bits and no deployment signaled
+const TEMPLATE_VERSION: i32 = 0x20000000;
+// Previous output of the coinbase input
+const COINBASE_OUTPOINT_INDEX: u32 = u32::MAX;
+
+/// Candidate block on top of the last header, for the miners to search its nonce.
+#[derive(Debug, Clone)]
+pub struct BlockTemplate {
+    /// Header with the merkle root of the transactions below and a zero nonce
+    pub header: BlockHeader,
+    pub height: i32,
+    pub coinbase: RawTransaction,
+    /// Mempool transactions of the block, every one after the ones it spends
+    pub txns: Vec<RawTransaction>,
+    /// Fees of the transactions, paid to the payout address with the subsidy
+    pub fees: i64,
+    pub weight: usize,
+}
+
+impl BlockTemplate {
+    /// Builds the template from the mempool, taking the transactions with the best fee rate
+    /// that fit in the block. The coinbase pays the subsidy and the fees to `payout_address`.
+    pub fn new(
+        blockchain: &Blockchain,
+        mempool: &Mempool,
+        payout_address: &str,
+        timestamp: u32,
+    ) -> Result<BlockTemplate, ProtocolError> {
+        let height = blockchain.get_height() + 1;
+        // Headers older than the last one are rejected by the nodes
+        let timestamp = timestamp.max(blockchain.get_last_timestamp() + 1);
+
+        let (txns, fees, weight) = select_transactions(blockchain, mempool, height, timestamp);
+        let payout_script = PubKeyScript::from_address(payout_address)?.to_vec();
+        let coinbase = coinbase(height, payout_script, block_subsidy(height) + fees);
+
+        let mut txids = vec![coinbase.get_tx_id()];
+        txids.extend(txns.iter().map(RawTransaction::get_tx_id));
+        let header = BlockHeader {
+            version: TEMPLATE_VERSION,
+            prev_block_hash: blockchain.get_last_header_hash(),
+            merkle_root_hash: merkle_tree_root(txids),
+            timestamp,
+            bits: blockchain.next_bits(timestamp),
+            nonce: 0,
+        };
+
+        Ok(BlockTemplate {
+            weight: weight + coinbase.weight(),
+            header,
+            height,
+            coinbase,
+            txns,
+            fees,
+        })
+    }
+
+    /// Block with the nonce found by the miner.
+    pub fn into_block(self, nonce: u32) -> BlockMessage {
+        let mut txns = vec![self.coinbase];
+        txns.extend(self.txns);
+        BlockMessage {
+            block_header: BlockHeader {
+                nonce,
+                ..self.header
+            },
+            txn_count: CompactSize::new_from_usize(txns.len()),
+            txns,
+        }
+    }
+}
+
+/// Satoshis created by the block at the height.
+pub fn block_subsidy(height: i32) -> i64 {
+    let halvings = height / SUBSIDY_HALVING_INTERVAL;
+    if halvings >= 64 {
+        return 0;
+    }
+    INITIAL_SUBSIDY >> halvings
+}
+
+/// Satoshis created by the blocks up to the height, included, following the halving schedule.
+pub fn total_supply(height: i32) -> i64 {
+    let mut supply = 0;
+    let mut era_start = 0;
+    while era_start <= height {
+        let subsidy = block_subsidy(era_start);
+        if subsidy == 0 {
+            break;
+        }
+        let era_end = (era_start + SUBSIDY_HALVING_INTERVAL - 1).min(height);
+        supply += subsidy * (era_end - era_start + 1) as i64;
+        era_start += SUBSIDY_HALVING_INTERVAL;
+    }
+    supply
+}
+
+/// Coinbase of the block at the height, which has to start its script with the height (BIP 34).
+pub(crate) fn coinbase(height: i32, payout_script: Vec<u8>, value: i64) -> RawTransaction {
+    let mut height_bytes = height.to_le_bytes().to_vec();
+    while height_bytes.len() > 1 && height_bytes[height_bytes.len() - 1] == 0 {
+        height_bytes.pop();
+    }
+    // The top bit is the sign of the number
+    if height_bytes[height_bytes.len() - 1] & 0x80 != 0 {
+        height_bytes.push(0);
+    }
+    let mut script = vec![height_bytes.len() as u8];
+    script.extend(height_bytes);
+
+    let input = TxIn::new(Outpoint::new([0; 32], COINBASE_OUTPOINT_INDEX), script);
+    RawTransaction::new(vec![input], vec![TxOut::new(value, payout_script)])
+}
+
+/// Picks the mempool transactions by fee rate while they fit in the block weight.
+/// A transaction goes in only after the mempool transactions it spends, the ones spending
+/// unknown outputs or not final at the height are left out.
+fn select_transactions(
+    blockchain: &Blockchain,
+    mempool: &Mempool,
+    height: i32,
+    timestamp: u32,
+) -> (Vec<RawTransaction>, i64, usize) {
+    let mut candidates: Vec<(&[u8; 32], &RawTransaction, i64)> = mempool
+        .entries()
+        .filter(|(_, entry)| entry.tx.is_final(height as u32, timestamp))
+        .filter_map(|(txid, entry)| {
+            Some((txid, &*entry.tx, tx_fee(&entry.tx, blockchain, mempool)?))
+        })
+        .collect();
+    // Fee rates compared without divisions: a / b > c / d is a * d > c * b
+    candidates.sort_by(|(_, a, a_fee), (_, b, b_fee)| {
+        (*b_fee as i128 * a.vsize() as i128).cmp(&(*a_fee as i128 * b.vsize() as i128))
+    });
+
+    let max_weight = MAX_BLOCK_WEIGHT - COINBASE_RESERVED_WEIGHT;
+    let mut included: HashSet<[u8; 32]> = HashSet::new();
+    let mut selected = vec![];
+    let mut fees = 0;
+    let mut weight = 0;
+    let mut added = true;
+    // Every pass adds the transactions whose mempool parents got in during the previous ones
+    while added {
+        added = false;
+        for (txid, tx, fee) in candidates.iter() {
+            if included.contains(*txid) || weight + tx.weight() > max_weight {
+                continue;
+            }
+            let parents_included = tx.tx_in.iter().all(|txin| {
+                let parent = &txin.previous_output.hash;
+                !mempool.contains_key(parent) || included.contains(parent)
+            });
+            if !parents_included {
+                continue;
+            }
+
+            included.insert(**txid);
+            selected.push((*tx).clone());
+            fees += fee;
+            weight += tx.weight();
+            added = true;
+        }
+    }
+    (selected, fees, weight)
+}
+
+/// Fee of a mempool transaction, None if it spends an output neither confirmed nor in the mempool.
+pub fn tx_fee(tx: &RawTransaction, blockchain: &Blockchain, mempool: &Mempool) -> Option<i64> {
+    let mut inputs_value = 0;
+    for txin in tx.tx_in.iter() {
+        let prev = &txin.previous_output;
+        inputs_value += match blockchain.utxo.get(prev.hash, prev.index) {
+            Some(out) => out.value,
+            None => {
+                mempool
+                    .get(&prev.hash)?
+                    .tx_out
+                    .get(prev.index as usize)?
+                    .value
+            }
+        };
+    }
+    let outputs_value: i64 = tx.tx_out.iter().map(|out| out.value).sum();
+    Some(inputs_value - outputs_value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::utxo_set::Output;
+
+    const PAYOUT_ADDRESS: &str = "mnJvq7mbGiPNNhUne4FAqq27Q8xZrAsVun";
+
+    fn spend(parent: [u8; 32], value: i64) -> RawTransaction {
+        let input = TxIn::new(Outpoint::new(parent, 0), vec![]);
+        RawTransaction::new(vec![input], vec![TxOut::new(value, vec![])])
+    }
+
+    #[test]
+    fn test_subsidy_halves_every_interval() {
+        assert_eq!(block_subsidy(0), INITIAL_SUBSIDY);
+        assert_eq!(block_subsidy(SUBSIDY_HALVING_INTERVAL), INITIAL_SUBSIDY / 2);
+        assert_eq!(block_subsidy(SUBSIDY_HALVING_INTERVAL * 64), 0);
+
+        assert_eq!(total_supply(0), INITIAL_SUBSIDY);
+        assert_eq!(
+            total_supply(SUBSIDY_HALVING_INTERVAL),
+            INITIAL_SUBSIDY * SUBSIDY_HALVING_INTERVAL as i64 + INITIAL_SUBSIDY / 2
+        );
+        // Just under 21 million bitcoins are ever created
+        assert_eq!(
+            total_supply(SUBSIDY_HALVING_INTERVAL * 70),
+            2_099_999_997_690_000
+        );
+    }
+
+    #[test]
+    fn test_children_follow_their_parents_and_unknown_spends_are_left_out() {
+        let mut blockchain = Blockchain::new();
+        blockchain
+            .utxo
+            .set
+            .insert([1; 32], vec![Output::new(0, 12_000, vec![])]);
+        let mut mempool = Mempool::new();
+
+        // The child pays the best fee rate but can't go before its parent
+        let parent = spend([1; 32], 10_000);
+        let parent_id = parent.get_tx_id();
+        mempool.insert(parent_id, parent);
+        let child = spend(parent_id, 1_000);
+        mempool.insert(child.get_tx_id(), child.clone());
+        let orphan = spend([2; 32], 1_000);
+        mempool.insert(orphan.get_tx_id(), orphan);
+
+        let template = BlockTemplate::new(&blockchain, &mempool, PAYOUT_ADDRESS, 0).unwrap();
+        let txids: Vec<[u8; 32]> = template.txns.iter().map(|tx| tx.get_tx_id()).collect();
+        assert_eq!(txids, vec![parent_id, child.get_tx_id()]);
+        assert_eq!(template.height, 1);
+        assert_eq!(template.fees, 11_000);
+        assert_eq!(template.coinbase.tx_out[0].value, INITIAL_SUBSIDY + 11_000);
+
+        let block = template.into_block(5);
+        assert_eq!(block.block_header.nonce, 5);
+        assert!(block.verify_merkle_root().is_ok());
+    }
+}
@@ -1,21 +1,36 @@
 mod block;
+pub mod block_file;
+pub mod difficulty;
+pub mod history;
+pub mod stats;
 pub mod txs;
 pub mod utxo_set;
 
-use block::{Block, SIZE_BLOCKS};
+use block::Block;
+use block::SIZE_BLOCKS;
+use block_file::{read_records, write_records};
+use history::HistoryEntry;
+use stats::RecentBlocks;
 use txs::Txs;
 use utxo_set::UtxoSet;
 
-use std::collections::LinkedList;
-use std::fs::{File, OpenOptions};
-use std::io::{BufRead, BufReader};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::sync::Arc;
 
+use crate::log_file::log;
 use crate::message::compact_size::CompactSize;
 use crate::raw_transaction::RawTransaction;
-use crate::utils::decode_hex;
+use crate::script::{batch_verify, trace_script, PubKeyScript, SignatureCheck};
+use crate::utils::{bytes_to_hex_string, decode_hex};
 use crate::{
-    block_header::BlockHeader, constants::GENESIS_BLOCK_HASH_VALUE, merkle_tree::merkle_tree_root,
-    message::block::BlockMessage, protocol_error::ProtocolError,
+    block_header::{BlockHeader, BLOCK_HEADER_SIZE},
+    block_template::block_subsidy,
+    constants::{DAMAGED_FILE_SUFFIX, REINDEX_PROGRESS_INTERVAL},
+    merkle_tree::merkle_tree_root,
+    message::block::BlockMessage,
+    message_header::network,
+    protocol_error::ProtocolError,
 };
 
 use self::txs::Tx;
@@ -23,39 +38,52 @@ use self::utxo_set::Output;
 
 #[derive(Debug, Default)]
 pub struct Blockchain {
-    chain: LinkedList<Block>,
+    /// Blocks by height, the genesis block first
+    chain: Vec<Block>,
+    /// Height of every block of the chain by its hash
+    heights: HashMap<[u8; 32], usize>,
     pub utxo: UtxoSet,
+    /// Stats of the last blocks added with their transactions
+    recent_blocks: RecentBlocks,
+    /// Transactions of the blocks of the chain that have them
+    tx_count: usize,
 }
 
 impl Blockchain {
     pub fn new() -> Blockchain {
-        let mut chain = LinkedList::new();
-        chain.push_front(Block::default());
+        let genesis = Block::default();
         Blockchain {
-            chain,
+            heights: HashMap::from([(genesis.hash, 0)]),
+            chain: vec![genesis],
             utxo: UtxoSet::default(),
+            recent_blocks: RecentBlocks::default(),
+            tx_count: 0,
         }
     }
 
     fn push_block(&mut self, block: Block, prev_hash: [u8; 32]) -> Result<(), ProtocolError> {
-        let head = self.chain.front().unwrap();
-        if head.hash == prev_hash {
-            self.chain.push_front(block);
+        if self.get_last_header_hash() == prev_hash {
+            self.heights.insert(block.hash, self.chain.len());
+            self.chain.push(block);
             return Ok(());
         }
 
-        for b in self.chain.iter().take(100) {
-            if b.hash == block.hash {
-                println!("YA TENGO ESE BLOQUE");
-                return Ok(());
-            } else if b.hash == prev_hash {
-                println!("FORK");
-                return Ok(());
-            }
+        if self.heights.contains_key(&block.hash) {
+            log("YA TENGO ESE BLOQUE".to_string());
+        } else if self.heights.contains_key(&prev_hash) {
+            log("FORK".to_string());
         }
         Ok(())
     }
 
+    /// Hash of the block before the one at `height`, zeros for the genesis block.
+    fn prev_hash(&self, height: usize) -> [u8; 32] {
+        match height {
+            0 => [0u8; 32],
+            height => self.chain[height - 1].hash,
+        }
+    }
+
     pub fn push(&mut self, new_header: BlockHeader) -> Result<(), ProtocolError> {
         let prev_hash = new_header.prev_block_hash;
         let block = Block::from_block_header(new_header);
@@ -65,158 +93,260 @@ impl Blockchain {
 
     pub fn push_full_block(&mut self, new_block: BlockMessage) -> Result<Block, ProtocolError> {
         let prev_hash = new_block.block_header.prev_block_hash;
+        if let Some(prev_height) = self.heights.get(&prev_hash) {
+            self.check_coinbase(*prev_height as i32 + 1, &new_block.txns)?;
+        }
         let mut block = Block::from_block_header(new_block.block_header);
         let txs = Txs::from_raw_txs(new_block.txns);
+        let height = self.chain.len();
+        let stats = self.block_stats(height as i32, block.timestamp, &txs);
+        let tx_count = txs.txns.len();
 
         self.utxo.append(&txs);
         block.add_txs(txs);
 
         self.push_block(block.clone(), prev_hash)?;
+        if self.chain.len() > height {
+            self.recent_blocks.push(stats);
+            self.tx_count += tx_count;
+        }
         Ok(block)
     }
 
     pub fn add_block_txs(&mut self, block_message: BlockMessage) -> Result<(), ProtocolError> {
         let hash = block_message.block_header.hash();
-        let txs = Txs::from_raw_txs(block_message.txns);
-
-        for block in self.chain.iter_mut() {
-            if block.hash == hash {
-                let merkle_root = merkle_tree_root(txs.get_tx_ids());
-                if merkle_root == block.merkle_root_hash {
-                    self.utxo.append(&txs);
-                    block.add_txs(txs);
-                    return Ok(());
+
+        if let Some(height) = self.heights.get(&hash).copied() {
+            self.check_coinbase(height as i32, &block_message.txns)?;
+            let txs = Txs::from_raw_txs(block_message.txns);
+            let merkle_root = merkle_tree_root(txs.get_tx_ids());
+            if merkle_root == self.chain[height].merkle_root_hash {
+                let stats = self.block_stats(height as i32, self.chain[height].timestamp, &txs);
+                self.recent_blocks.push(stats);
+                let block = &mut self.chain[height];
+                self.tx_count += txs.txns.len();
+                if let Some(old) = &block.txs {
+                    self.tx_count -= old.txns.len();
                 }
-                return Err(ProtocolError::Error(
-                    "Merkle root doesn't match".to_string(),
-                ));
+                self.utxo.append(&txs);
+                block.add_txs(txs);
+                return Ok(());
             }
+            return Err(ProtocolError::Error(
+                "Merkle root doesn't match".to_string(),
+            ));
         }
 
         Ok(())
     }
 
     pub fn get_last_header_hash(&self) -> [u8; 32] {
-        self.chain.front().unwrap().hash
+        self.chain.last().unwrap().hash
     }
 
     pub fn get_size(&self) -> usize {
         self.chain.len()
     }
 
-    pub fn get_tx(&self, txid: [u8; 32]) -> Option<Tx> {
-        for block in self.chain.iter() {
-            let tx = block.get_tx(txid);
-            if tx.is_some() {
-                return tx;
-            };
-        }
-        None
+    /// Height of the last header, the genesis block being at height 0.
+    pub fn get_height(&self) -> i32 {
+        self.chain.len() as i32 - 1
     }
 
-    pub fn read_from_file(filepath: String) -> Result<Blockchain, ProtocolError> {
-        let file = File::open(filepath)?;
-        let mut reader = BufReader::with_capacity(SIZE_BLOCKS, file);
+    /// Timestamp of the last header.
+    pub fn get_last_timestamp(&self) -> u32 {
+        self.chain.last().map_or(0, |block| block.timestamp)
+    }
 
-        let mut blockchain = Blockchain::new();
+    pub fn get_tx(&self, txid: [u8; 32]) -> Option<Arc<Tx>> {
+        self.chain.iter().rev().find_map(|block| block.get_tx(txid))
+    }
 
-        let mut last_hash = decode_hex(GENESIS_BLOCK_HASH_VALUE);
-        let mut tmp;
-        loop {
-            let buf = {
-                reader.fill_buf()?;
-                reader.buffer()
-            };
-            if buf.len() != SIZE_BLOCKS {
-                break;
-            }
+    /// Reads the headers saved by save_to_file.
+    /// A damaged file is rolled back to the last block before the damage, the rest are downloaded
+    /// again. The headers of the files without checksums have their proof of work checked instead.
+    pub fn read_from_file(filepath: String) -> Result<Blockchain, ProtocolError> {
+        let file = read_records(File::open(&filepath)?)?;
+        let (blockchain, bad_block) =
+            Blockchain::from_records(file.records, !file.checksummed, |_, _| {})?;
+
+        let damage = match (&file.damage, bad_block) {
+            (_, Some(height)) => format!("the block at height {} fails its proof of work", height),
+            (Some(damage), None) => damage.to_string(),
+            (None, None) => return Ok(blockchain),
+        };
+        blockchain.recover_file(&filepath, &damage)?;
+        Ok(blockchain)
+    }
+
+    /// Reads the blocks from the file checking the proof of work of every header, unlike
+    /// read_from_file, and writes the file again in the current format. The blocks from the first
+    /// one that fails are dropped to be downloaded again. The utxo set starts empty and is built
+    /// again as the transactions are downloaded.
+    /// `progress` gets the blocks checked and the blocks in the file.
+    pub fn reindex_file(
+        filepath: String,
+        progress: impl FnMut(usize, usize),
+    ) -> Result<Blockchain, ProtocolError> {
+        let file = read_records(File::open(&filepath)?)?;
+        if let Some(damage) = &file.damage {
+            log(format!("Blockchain file damaged, {}", damage));
+        }
 
-            let block = Block::from_bytes(buf.clone().try_into().unwrap(), last_hash)?;
-            tmp = block.hash;
-            blockchain.push_block(block, last_hash).unwrap();
-            last_hash = tmp;
-            reader.consume(buf.len());
+        let (blockchain, bad_block) = Blockchain::from_records(file.records, true, progress)?;
+        if let Some(height) = bad_block {
+            log(format!(
+                "Reindex: the block at height {} fails its proof of work, dropping the blocks from it",
+                height
+            ));
         }
 
+        blockchain.save_to_file(filepath)?;
         Ok(blockchain)
     }
 
-    pub fn save_to_file(&self, filepath: String) -> Result<(), ProtocolError> {
-        let mut file = OpenOptions::new().create(true).write(true).open(filepath)?;
+    /// Chains the records from the genesis block. With `check_pow` it stops at the first header
+    /// that fails its proof of work, returning its height along with the blocks before it.
+    fn from_records(
+        records: Vec<[u8; SIZE_BLOCKS]>,
+        check_pow: bool,
+        mut progress: impl FnMut(usize, usize),
+    ) -> Result<(Blockchain, Option<usize>), ProtocolError> {
+        let total = records.len();
+        let mut blockchain = Blockchain::new();
+        let mut last_hash = decode_hex(network().genesis_hash());
+        for (i, record) in records.into_iter().enumerate() {
+            let block = Block::from_bytes(record, last_hash)?;
+            if check_pow
+                && !Block::to_block_header(block.clone(), last_hash).validate_proof_of_work()
+            {
+                progress(i, total);
+                return Ok((blockchain, Some(i + 1)));
+            }
+            let hash = block.hash;
+            blockchain.push_block(block, last_hash)?;
+            last_hash = hash;
 
-        for block in self.chain.iter().rev().skip(1) {
-            std::io::Write::write(&mut file, &block.to_bytes())?;
+            if (i + 1) % REINDEX_PROGRESS_INTERVAL == 0 {
+                progress(i + 1, total);
+            }
         }
+        progress(total, total);
+        Ok((blockchain, None))
+    }
 
+    /// Keeps a copy of the damaged file next to it and writes the blocks kept, so the file is
+    /// whole again before anything else is appended.
+    fn recover_file(&self, filepath: &str, damage: &str) -> Result<(), ProtocolError> {
+        let backup = format!("{}{}", filepath, DAMAGED_FILE_SUFFIX);
+        fs::copy(filepath, &backup)?;
+        self.save_to_file(filepath.to_string())?;
+        log(format!(
+            "RECOVERY: the blockchain file {} is damaged, {}. Rolled back to the block {} at height {}, \
+             the blocks after it are downloaded again. The damaged file was kept as {}",
+            filepath,
+            damage,
+            bytes_to_hex_string(&self.get_last_header_hash()),
+            self.get_height(),
+            backup
+        ));
         Ok(())
     }
 
-    pub fn get_headers(&self, hash: [u8; 32]) -> Vec<BlockHeader> {
-        let mut headers = vec![];
-        let mut blocks_left = 2000;
-        let mut b = false;
-        let mut last_hash = hash;
+    pub fn save_to_file(&self, filepath: String) -> Result<(), ProtocolError> {
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(filepath)?;
 
-        for block in self.chain.iter().rev() {
-            if block.hash == last_hash {
-                b = true;
-                continue;
-            }
+        write_records(file, self.chain.iter().skip(1).map(Block::to_bytes))
+    }
 
-            if b {
-                headers.push(Block::to_block_header(block.clone(), last_hash));
-                last_hash = block.hash;
-                blocks_left -= 1;
-                if blocks_left == 0 {
-                    return headers;
-                }
-            }
+    /// Up to 2000 headers of the blocks after the one with the hash, none if it isn't known.
+    pub fn get_headers(&self, hash: [u8; 32]) -> Vec<BlockHeader> {
+        let start = match self.heights.get(&hash) {
+            Some(height) => height + 1,
+            None => return vec![],
+        };
+
+        let mut last_hash = hash;
+        let mut headers = vec![];
+        for block in self.chain[start..].iter().take(2000) {
+            headers.push(Block::to_block_header(block.clone(), last_hash));
+            last_hash = block.hash;
         }
         headers
     }
 
-    pub fn get_blocks(&self, mut hashes: Vec<[u8; 32]>) -> Vec<BlockMessage> {
-        if hashes.is_empty() {
-            return vec![];
-        }
-        hashes.reverse();
+    /// Payloads of the block messages of the requested blocks, built from the stored bytes of
+    /// their transactions.
+    /// Blocks that are unknown or whose transactions weren't downloaded are skipped.
+    pub fn get_block_payloads(&self, hashes: Vec<[u8; 32]>) -> Vec<Vec<u8>> {
         let mut blocks = vec![];
+        for hash in hashes {
+            let height = match self.heights.get(&hash) {
+                Some(height) => *height,
+                None => continue,
+            };
+            let block = &self.chain[height];
+            let txs = match &block.txs {
+                Some(txs) => txs,
+                None => continue,
+            };
 
-        while !hashes.is_empty() {
-            let mut hash = hashes.pop().unwrap();
-            let mut last_hash = [0u8; 32];
-
-            for block in self.chain.iter().rev() {
-                if block.hash == hash {
-                    if let Some(txs) = &block.txs {
-                        let txns = txs.to_raw_txs();
-                        blocks.push(BlockMessage {
-                            block_header: Block::to_block_header(block.clone(), last_hash),
-                            txn_count: CompactSize::new_from_usize(txns.len()),
-                            txns,
-                        });
-                    }
-                    if hashes.is_empty() {
-                        return blocks;
-                    }
-                    hash = hashes.pop().unwrap();
-                }
-                last_hash = block.hash;
+            let txn_count = CompactSize::new_from_usize(txs.txns.len());
+            let size = BLOCK_HEADER_SIZE
+                + txn_count.size()
+                + txs.txns.iter().map(|tx| tx.bytes().len()).sum::<usize>();
+
+            let mut payload = Vec::with_capacity(size);
+            Block::to_block_header(block.clone(), self.prev_hash(height)).append_to(&mut payload);
+            txn_count.append_to(&mut payload);
+            for tx in txs.txns.iter() {
+                payload.extend_from_slice(tx.bytes());
             }
+            blocks.push(payload);
         }
-
         blocks
     }
 
-    pub fn get_hashes_since(&self, date: u32) -> Vec<[u8; 32]> {
-        let mut hashes = vec![];
+    /// Returns the hashes of the blocks since `date` whose transactions weren't downloaded, oldest first.
+    pub fn get_missing_blocks_since(&self, date: u32) -> Vec<[u8; 32]> {
+        self.chain
+            .iter()
+            .filter(|block| block.timestamp >= date && block.txs.is_none())
+            .map(|block| block.hash)
+            .collect()
+    }
+
+    /// Builds the utxo set again walking the downloaded blocks from the oldest one.
+    /// Needed when the transactions of older blocks are added after newer ones.
+    pub fn rebuild_utxo(&mut self) {
+        let mut utxo = UtxoSet::default();
         for block in self.chain.iter() {
-            if block.timestamp >= date {
-                hashes.push(block.hash);
+            if let Some(txs) = &block.txs {
+                utxo.append(txs);
             }
         }
-        hashes.reverse();
-        hashes
+        self.utxo = utxo;
+    }
+
+    /// Whether all the blocks are among the last `n` of the chain.
+    pub fn are_among_last(&self, hashes: &[[u8; 32]], n: usize) -> bool {
+        hashes.iter().all(|hash| {
+            self.heights
+                .get(hash)
+                .is_some_and(|height| self.chain.len() - height <= n)
+        })
+    }
+
+    pub fn get_hashes_since(&self, date: u32) -> Vec<[u8; 32]> {
+        self.chain
+            .iter()
+            .filter(|block| block.timestamp >= date)
+            .map(|block| block.hash)
+            .collect()
     }
 
     /// It returns every unspent output in the blockchain that is related to a public key hash.
@@ -224,53 +354,285 @@ impl Blockchain {
         self.utxo.by_pkhash(pkhash)
     }
 
-    /// It returns every transaction in the blockchain that is related to a public key hash.
-    pub fn get_tx_history(&self, pkhash: Vec<u8>) -> Vec<Tx> {
-        let mut history: Vec<Tx> = vec![];
-        for block in self.chain.iter() {
-            history.extend_from_slice(&block.get_tx_history(&pkhash));
+    /// It returns the unspent outputs of a public key hash with the confirmations of their transactions.
+    pub fn get_coins(&self, pkhash: Vec<u8>) -> Vec<([u8; 32], Output, u32)> {
+        let mut confirmations: HashMap<[u8; 32], u32> = HashMap::new();
+        for (depth, block) in self.chain.iter().rev().enumerate() {
+            if let Some(txs) = &block.txs {
+                for tx in txs.txns.iter() {
+                    confirmations.insert(tx.tx_id, depth as u32 + 1);
+                }
+            }
+        }
+
+        self.get_utxo(pkhash)
+            .into_iter()
+            .map(|(txid, out)| {
+                let confirmations = confirmations.get(&txid).copied().unwrap_or_default();
+                (txid, out, confirmations)
+            })
+            .collect()
+    }
+
+    /// It returns every transaction in the blockchain that is related to a public key hash,
+    /// either by paying to it or by spending one of its outputs.
+    /// The fee is only known when every previous output is in the downloaded blocks.
+    pub fn get_tx_history(&self, pkhash: Vec<u8>) -> Vec<HistoryEntry> {
+        let txs_by_id = self.txs_by_id();
+
+        let mut history: Vec<HistoryEntry> = vec![];
+        for (depth, block) in self.chain.iter().rev().enumerate() {
+            let txs = match &block.txs {
+                None => continue,
+                Some(i) => i,
+            };
+
+            for tx in txs.txns.iter() {
+                let mut sent = 0;
+                let mut inputs_value = if tx.tx_in.is_empty() { None } else { Some(0) };
+                for txin in tx.tx_in.iter() {
+                    let prev_output = txs_by_id.get(&txin.previous_output.hash).and_then(|prev| {
+                        prev.tx_out
+                            .iter()
+                            .find(|out| out.index == txin.previous_output.index)
+                    });
+
+                    match prev_output {
+                        Some(out) => {
+                            if out.pkscript.can_be_spent_by(&pkhash) {
+                                sent += out.value;
+                            }
+                            inputs_value = inputs_value.map(|value| value + out.value);
+                        }
+                        None => inputs_value = None,
+                    }
+                }
+
+                if sent == 0 && !tx.has_pkhash(&pkhash) {
+                    continue;
+                }
+
+                let received = tx.value_payed_to_pkhash(&pkhash);
+                let fee = inputs_value.map(|value| value - tx.get_tx_value());
+
+                history.push(HistoryEntry::new(
+                    Tx::clone(tx),
+                    block.timestamp,
+                    received,
+                    sent,
+                    fee,
+                    depth as u32 + 1,
+                ));
+            }
         }
         history
     }
 
+    /// Indexes every downloaded transaction by its txid.
+    fn txs_by_id(&self) -> HashMap<[u8; 32], &Tx> {
+        let mut txs_by_id: HashMap<[u8; 32], &Tx> = HashMap::new();
+        for block in self.chain.iter().rev() {
+            if let Some(txs) = &block.txs {
+                for tx in txs.txns.iter() {
+                    txs_by_id.insert(tx.tx_id, tx);
+                }
+            }
+        }
+        txs_by_id
+    }
+
+    /// Checks every P2PKH signature of the transactions of a block in one pass.
+    /// Inputs whose previous output isn't in the utxo set or in the same block are skipped, like
+    /// the ones whose signature script isn't push only.
+    pub fn verify_block_signatures(&self, txns: &[RawTransaction]) -> bool {
+        self.check_block_signatures(txns).is_ok()
+    }
+
+    /// Like `verify_block_signatures`, with the input whose signature failed as the error.
+    pub fn check_block_signatures(&self, txns: &[RawTransaction]) -> Result<(), ProtocolError> {
+        let mut checks = vec![];
+        // Transaction and input of every check, to tell which one failed
+        let mut checked_inputs = vec![];
+        let mut block_outputs: HashMap<([u8; 32], u32), Vec<u8>> = HashMap::new();
+
+        for (t, tx) in txns.iter().enumerate() {
+            for (i, txin) in tx.tx_in.iter().enumerate() {
+                let outpoint = (txin.previous_output.hash, txin.previous_output.index);
+                let pkscript = match self.utxo.get(outpoint.0, outpoint.1) {
+                    Some(out) => out.pkscript,
+                    None => match block_outputs.get(&outpoint) {
+                        Some(script) => PubKeyScript::from_bytes(script.clone()),
+                        None => continue,
+                    },
+                };
+
+                match SignatureCheck::from_p2pkh_input(&pkscript, tx, i) {
+                    Ok(Some(check)) => {
+                        checks.push(check);
+                        checked_inputs.push((t, i));
+                    }
+                    Ok(None) => {}
+                    Err(e) => return Err(block_signature_error(t, i, &e.to_string())),
+                }
+            }
+
+            for (outpoint, out) in tx.get_utxos() {
+                block_outputs.insert(outpoint, out.pk_script);
+            }
+        }
+
+        if batch_verify(&checks) {
+            return Ok(());
+        }
+        // The batch only tells that a signature is wrong, they are checked again one by one
+        match checks
+            .iter()
+            .position(|check| !batch_verify(std::slice::from_ref(check)))
+        {
+            Some(failed) => {
+                let (t, i) = checked_inputs[failed];
+                Err(block_signature_error(t, i, "the signature doesn't match"))
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Checks that the coinbase of the block at the height doesn't pay more than the subsidy
+    /// of the height plus the fees. Blocks spending outputs that weren't downloaded can't
+    /// have their fees computed, so they aren't checked.
+    pub fn check_coinbase(
+        &self,
+        height: i32,
+        txns: &[RawTransaction],
+    ) -> Result<(), ProtocolError> {
+        let coinbase = match txns.first() {
+            Some(coinbase) => coinbase,
+            None => return Ok(()),
+        };
+
+        let mut block_outputs: HashMap<([u8; 32], u32), i64> = HashMap::new();
+        let mut fees = 0;
+        for tx in txns.iter().skip(1) {
+            let mut inputs_value = 0;
+            for txin in tx.tx_in.iter() {
+                let prev = &txin.previous_output;
+                let value = match self.utxo.get(prev.hash, prev.index) {
+                    Some(out) => out.value,
+                    None => match block_outputs.get(&(prev.hash, prev.index)) {
+                        Some(value) => *value,
+                        None => return Ok(()),
+                    },
+                };
+                inputs_value += value;
+            }
+            fees += inputs_value - tx.tx_out.iter().map(|out| out.value).sum::<i64>();
+
+            for (outpoint, out) in tx.get_utxos() {
+                block_outputs.insert(outpoint, out.value);
+            }
+        }
+
+        let paid: i64 = coinbase.tx_out.iter().map(|out| out.value).sum();
+        let allowed = block_subsidy(height) + fees;
+        if paid > allowed {
+            return Err(ProtocolError::Error(format!(
+                "The coinbase of the block at height {} pays {} satoshis, more than the {} of the subsidy and the fees",
+                height, paid, allowed
+            )));
+        }
+        Ok(())
+    }
+
     /// Checks if a RawTransaction is valid or not.
+    /// The transaction must be standard, see RawTransaction::is_standard.
     /// The inputs of the transaction are valid if they spend outputs in the utxo set.
     /// The amount spendable must not be greater than the amount spent.
     pub fn is_valid_tx(&self, tx: &RawTransaction) -> bool {
-        let mut spendable: i64 = 0;
+        self.validate_tx(tx).is_ok()
+    }
+
+    /// Like `is_valid_tx`, with the reason why the transaction isn't valid as the error.
+    pub fn validate_tx(&self, tx: &RawTransaction) -> Result<(), ProtocolError> {
+        self.validate_tx_spending(tx, &[])
+    }
+
+    /// Like `validate_tx`, the inputs can also spend the given unconfirmed outputs.
+    pub fn validate_tx_spending(
+        &self,
+        tx: &RawTransaction,
+        unconfirmed: &[([u8; 32], Output)],
+    ) -> Result<(), ProtocolError> {
         if tx.tx_in_count.into_inner() == 0 {
-            return false;
+            return Err(ProtocolError::Error(
+                "The transaction has no inputs".to_string(),
+            ));
         }
+        tx.check_standard()?;
 
+        let mut spendable: i64 = 0;
         for (i, txin) in tx.tx_in.iter().enumerate() {
-            let a = self
+            let prev = &txin.previous_output;
+            let unconfirmed_output = || {
+                unconfirmed
+                    .iter()
+                    .find(|(txid, out)| *txid == prev.hash && out.index == prev.index)
+                    .map(|(_, out)| out.clone())
+            };
+            let output = match self
                 .utxo
-                .get(txin.previous_output.hash, txin.previous_output.index);
-            if let Some(output) = a {
-                if !output.pkscript.evaluate(tx.clone(), i) {
-                    return false;
+                .get(prev.hash, prev.index)
+                .or_else(unconfirmed_output)
+            {
+                Some(output) => output,
+                None => {
+                    return Err(ProtocolError::Error(format!(
+                        "Input {} spends {}:{}, which isn't an unspent output",
+                        i,
+                        bytes_to_hex_string(&prev.hash),
+                        prev.index
+                    )))
                 }
-                spendable += output.value;
-            } else {
-                return false;
             };
+            if !matches!(output.pkscript, PubKeyScript::P2PKH(_)) {
+                return Err(ProtocolError::Error(format!(
+                    "Input {} spends an output that isn't P2PKH",
+                    i
+                )));
+            }
+            if let Err(e) = trace_script(&output.pkscript.to_vec(), tx, i).result {
+                return Err(ProtocolError::Error(format!(
+                    "The script of input {} failed with {}",
+                    i,
+                    e.code()
+                )));
+            }
+            spendable += output.value;
         }
 
-        let mut spent: i64 = 0;
-        for txout in tx.tx_out.iter() {
-            spent += txout.value;
+        let spent: i64 = tx.tx_out.iter().map(|txout| txout.value).sum();
+        if spent > spendable {
+            return Err(ProtocolError::Error(format!(
+                "The outputs spend {} satoshis but the inputs only have {}, the fee can't be negative",
+                spent, spendable
+            )));
         }
-
-        spent <= spendable
+        Ok(())
     }
 }
 
+fn block_signature_error(tx: usize, input: usize, reason: &str) -> ProtocolError {
+    ProtocolError::Error(format!(
+        "Block has invalid signatures: input {} of transaction {}, {}",
+        input, tx, reason
+    ))
+}
+
 #[cfg(test)]
 mod tests {
     use crate::{
-        message::compact_size::CompactSize,
+        message::{compact_size::CompactSize, Serializable},
         raw_transaction::{Outpoint, TxIn},
-        raw_transaction::{RawTransaction, TxOut},
+        raw_transaction::{RawTransaction, TxOptions, TxOut},
     };
 
     use super::*;
@@ -335,6 +697,36 @@ mod tests {
         // assert_eq!(blockchain.heads.len(), 1);
     }
 
+    #[test]
+    fn test_headers_after_a_known_block() {
+        let mut blockchain = Blockchain::new();
+        let first_hash = blockchain.get_last_header_hash();
+
+        let mut hashes = vec![first_hash];
+        for nonce in 0..3 {
+            let header = BlockHeader {
+                version: 1,
+                prev_block_hash: *hashes.last().unwrap(),
+                merkle_root_hash: [0; 32],
+                timestamp: 1234567890,
+                bits: 0x1d00ffff,
+                nonce,
+            };
+            hashes.push(header.hash());
+            assert!(blockchain.push(header).is_ok());
+        }
+
+        let headers = blockchain.get_headers(hashes[1]);
+        let headers: Vec<[u8; 32]> = headers.iter().map(|header| header.hash()).collect();
+        assert_eq!(headers, hashes[2..]);
+        assert!(blockchain.get_headers(hashes[3]).is_empty());
+        assert!(blockchain.get_headers([7; 32]).is_empty());
+        assert_eq!(blockchain.get_hashes_since(0), hashes);
+        assert!(blockchain.are_among_last(&hashes[2..], 2));
+        assert!(!blockchain.are_among_last(&hashes[1..], 2));
+        assert!(!blockchain.are_among_last(&[[7; 32]], 2));
+    }
+
     // #[test]
     // fn test_forks_in_blockchain() {
     //     let mut blockchain = Blockchain::new();
@@ -572,6 +964,60 @@ mod tests {
         assert_eq!(blockchain.utxo.get_total_balance(), 33);
     }
 
+    #[test]
+    fn test_coinbases_pay_at_most_the_subsidy_and_the_fees() {
+        let mut blockchain = Blockchain::new();
+        let subsidy = crate::constants::INITIAL_SUBSIDY;
+        let funding = RawTransaction::new(vec![], vec![TxOut::new(1_000, vec![])]);
+        blockchain
+            .utxo
+            .append(&Txs::from_raw_txs(vec![funding.clone()]));
+
+        // The payment leaves a fee of 100
+        let txin = TxIn::new(Outpoint::new(funding.get_tx_id(), 0), vec![]);
+        let payment = RawTransaction::new(vec![txin], vec![TxOut::new(900, vec![])]);
+        let coinbase = |value| RawTransaction::new(vec![], vec![TxOut::new(value, vec![])]);
+
+        let txns = vec![coinbase(subsidy + 100), payment.clone()];
+        assert!(blockchain.check_coinbase(1, &txns).is_ok());
+        let txns = vec![coinbase(subsidy + 101), payment.clone()];
+        assert!(blockchain.check_coinbase(1, &txns).is_err());
+        // After the first halving the same coinbase pays too much
+        let txns = vec![coinbase(subsidy), payment];
+        let halving = crate::constants::SUBSIDY_HALVING_INTERVAL;
+        assert!(blockchain.check_coinbase(halving, &txns).is_err());
+    }
+
+    #[test]
+    fn test_served_blocks_are_the_stored_bytes() {
+        let mut blockchain = Blockchain::new();
+        let first_hash = blockchain.get_last_header_hash();
+
+        let tx1 = RawTransaction::new(vec![], vec![TxOut::new(10, vec![7; 30])]);
+        let tx2 = RawTransaction::new(vec![], vec![TxOut::new(20, vec![])]);
+        let block1 = BlockHeader {
+            version: 1,
+            prev_block_hash: first_hash,
+            merkle_root_hash: merkle_tree_root(vec![tx1.get_tx_id(), tx2.get_tx_id()]),
+            timestamp: 1234567890,
+            bits: 0x1d00ffff,
+            nonce: 0xabcdef,
+        };
+        let block_message1 = BlockMessage {
+            block_header: block1.clone(),
+            txn_count: CompactSize::U8(2),
+            txns: vec![tx1, tx2],
+        };
+        let expected = block_message1.to_bytes();
+
+        assert!(blockchain.push(block1.clone()).is_ok());
+        assert!(blockchain.add_block_txs(block_message1).is_ok());
+        assert_eq!(
+            blockchain.get_block_payloads(vec![block1.hash()]),
+            vec![expected]
+        );
+    }
+
     #[test]
     fn testing_spending_utxo_two_tx() {
         let mut blockchain = Blockchain::new();
@@ -763,7 +1209,13 @@ mod tests {
             ],
         );
 
-        let tx2 = RawTransaction::create_transaction(outs_to_spend, vec![txout2], private_key);
+        let tx2 = RawTransaction::create_transaction(
+            outs_to_spend,
+            vec![txout2],
+            private_key,
+            &TxOptions::default(),
+        )
+        .unwrap();
         let tx2_id = tx2.get_tx_id();
         assert!(blockchain.is_valid_tx(&tx2));
 
@@ -845,7 +1297,13 @@ mod tests {
             ],
         );
 
-        let tx2 = RawTransaction::create_transaction(outs_to_spend, vec![txout2], private_key);
+        let tx2 = RawTransaction::create_transaction(
+            outs_to_spend,
+            vec![txout2],
+            private_key,
+            &TxOptions::default(),
+        )
+        .unwrap();
         let tx2_id = tx2.get_tx_id();
         assert!(blockchain.is_valid_tx(&tx2));
 
@@ -871,4 +1329,327 @@ mod tests {
 
         assert_eq!(blockchain.utxo.get_total_balance(), 25);
     }
+
+    #[test]
+    fn test_tx_history_fee_and_direction() {
+        let bitcoin_address = "mgkPm4UebNCJSRGs2Kp2aVE69G8hUEf4d7";
+        let private_key = "cSnB7AwCEDKrdq1x2XmHu8f1BHPh6KeuBjeXgssDe2cMpeGDM7oB";
+
+        let mut blockchain = Blockchain::new();
+        let first_hash = blockchain.get_last_header_hash();
+
+        let pkhash = crate::utils::bitcoin_address_to_pkhash(bitcoin_address).unwrap();
+
+        let txout1 = TxOut::new(10, [&[118, 169, 20], &pkhash[..], &[54, 136, 172]].concat());
+        let tx1 = RawTransaction::new(vec![], vec![txout1]);
+        let tx1_id = tx1.get_tx_id();
+
+        let block1 = BlockHeader {
+            version: 1,
+            prev_block_hash: first_hash,
+            merkle_root_hash: merkle_tree_root(vec![tx1_id]),
+            timestamp: 1234567890,
+            bits: 0x1d00ffff,
+            nonce: 0xabcdef,
+        };
+        let block_message1 = BlockMessage {
+            block_header: block1.clone(),
+            txn_count: CompactSize::U8(1),
+            txns: vec![tx1],
+        };
+        let hash_block1 = block1.hash();
+        assert!(blockchain.push(block1).is_ok());
+        assert!(blockchain.add_block_txs(block_message1).is_ok());
+
+        let out = blockchain.utxo.get(tx1_id, 0).unwrap();
+        let txout2 = TxOut::new(
+            8,
+            vec![
+                118, 169, 20, 11, 139, 32, 119, 74, 146, 223, 9, 212, 72, 207, 66, 73, 35, 72, 27,
+                52, 87, 236, 54, 136, 172,
+            ],
+        );
+        let tx2 = RawTransaction::create_transaction(
+            vec![(tx1_id, out)],
+            vec![txout2],
+            private_key,
+            &TxOptions::default(),
+        )
+        .unwrap();
+        let tx2_id = tx2.get_tx_id();
+
+        let block2 = BlockHeader {
+            version: 1,
+            prev_block_hash: hash_block1,
+            merkle_root_hash: merkle_tree_root(vec![tx2_id]),
+            timestamp: 1234577012,
+            bits: 0x1d00f0ff,
+            nonce: 0xacceef,
+        };
+        let block_message2 = BlockMessage {
+            block_header: block2.clone(),
+            txn_count: CompactSize::U8(1),
+            txns: vec![tx2],
+        };
+        assert!(blockchain.push(block2).is_ok());
+        assert!(blockchain.add_block_txs(block_message2).is_ok());
+
+        let history = blockchain.get_tx_history(pkhash);
+        assert_eq!(history.len(), 2);
+
+        assert_eq!(history[0].tx.tx_id, tx2_id);
+        assert_eq!(history[0].direction, history::TxDirection::Sent);
+        assert_eq!(history[0].amount, -10);
+        assert_eq!(history[0].fee, Some(2));
+        assert_eq!(history[0].confirmations, 1);
+        assert_eq!(history[0].timestamp, 1234577012);
+
+        assert_eq!(history[1].tx.tx_id, tx1_id);
+        assert_eq!(history[1].direction, history::TxDirection::Received);
+        assert_eq!(history[1].amount, 10);
+        assert_eq!(history[1].fee, None);
+        assert_eq!(history[1].confirmations, 2);
+    }
+
+    #[test]
+    fn test_tx_with_duplicate_inputs_is_not_valid() {
+        let bitcoin_address = "mgkPm4UebNCJSRGs2Kp2aVE69G8hUEf4d7";
+        let private_key = "cSnB7AwCEDKrdq1x2XmHu8f1BHPh6KeuBjeXgssDe2cMpeGDM7oB";
+
+        let mut blockchain = Blockchain::new();
+        let first_hash = blockchain.get_last_header_hash();
+
+        let pkhash = &crate::utils::bitcoin_address_to_pkhash(bitcoin_address).unwrap()[..];
+
+        let txout1 = TxOut::new(10, [&[118, 169, 20], pkhash, &[54, 136, 172]].concat());
+        let tx1 = RawTransaction::new(vec![], vec![txout1]);
+        let tx1_id = tx1.get_tx_id();
+
+        let block1 = BlockHeader {
+            version: 1,
+            prev_block_hash: first_hash,
+            merkle_root_hash: merkle_tree_root(vec![tx1_id]),
+            timestamp: 1234567890,
+            bits: 0x1d00ffff,
+            nonce: 0xabcdef,
+        };
+        let block_message1 = BlockMessage {
+            block_header: block1.clone(),
+            txn_count: CompactSize::U8(1),
+            txns: vec![tx1],
+        };
+        assert!(blockchain.push(block1).is_ok());
+        assert!(blockchain.add_block_txs(block_message1).is_ok());
+
+        let out = blockchain.utxo.get(tx1_id, 0).unwrap();
+        let txout2 = TxOut::new(20, [&[118, 169, 20], pkhash, &[54, 136, 172]].concat());
+        let tx2 = RawTransaction::create_transaction(
+            vec![(tx1_id, out.clone()), (tx1_id, out)],
+            vec![txout2],
+            private_key,
+            &TxOptions::default(),
+        )
+        .unwrap();
+
+        assert!(tx2.has_duplicate_inputs());
+        assert!(!blockchain.is_valid_tx(&tx2));
+    }
+
+    #[test]
+    fn test_validate_tx_tells_why_the_transaction_is_rejected() {
+        let bitcoin_address = "mgkPm4UebNCJSRGs2Kp2aVE69G8hUEf4d7";
+        let private_key = "cSnB7AwCEDKrdq1x2XmHu8f1BHPh6KeuBjeXgssDe2cMpeGDM7oB";
+        let pkhash = &crate::utils::bitcoin_address_to_pkhash(bitcoin_address).unwrap()[..];
+        let pkscript = [&[118, 169, 20], pkhash, &[54, 136, 172]].concat();
+
+        let mut blockchain = Blockchain::new();
+        blockchain
+            .utxo
+            .set
+            .insert([1; 32], vec![Output::new(0, 10, pkscript.clone())]);
+        let out = blockchain.utxo.get([1; 32], 0).unwrap();
+
+        let reason = |tx: &RawTransaction| match blockchain.validate_tx(tx) {
+            Err(ProtocolError::Error(reason)) => reason,
+            other => panic!("{:?}", other),
+        };
+
+        let overspent = RawTransaction::create_transaction(
+            vec![([1; 32], out.clone())],
+            vec![TxOut::new(20, pkscript.clone())],
+            private_key,
+            &TxOptions::default(),
+        )
+        .unwrap();
+        assert!(reason(&overspent).contains("the fee can't be negative"));
+
+        let unknown = RawTransaction::create_transaction(
+            vec![([2; 32], out.clone())],
+            vec![TxOut::new(8, pkscript.clone())],
+            private_key,
+            &TxOptions::default(),
+        )
+        .unwrap();
+        assert!(reason(&unknown).starts_with("Input 0 spends"));
+
+        let mut tampered = RawTransaction::create_transaction(
+            vec![([1; 32], out)],
+            vec![TxOut::new(8, pkscript)],
+            private_key,
+            &TxOptions::default(),
+        )
+        .unwrap();
+        tampered.tx_out[0].value = 9;
+        assert_eq!(
+            reason(&tampered),
+            "The script of input 0 failed with EVAL_FALSE"
+        );
+    }
+
+    #[test]
+    fn test_verify_block_signatures() {
+        let bitcoin_address = "mgkPm4UebNCJSRGs2Kp2aVE69G8hUEf4d7";
+        let private_key = "cSnB7AwCEDKrdq1x2XmHu8f1BHPh6KeuBjeXgssDe2cMpeGDM7oB";
+
+        let blockchain = Blockchain::new();
+        let pkhash = &crate::utils::bitcoin_address_to_pkhash(bitcoin_address).unwrap()[..];
+
+        let txout1 = TxOut::new(10, [&[118, 169, 20], pkhash, &[54, 136, 172]].concat());
+        let tx1 = RawTransaction::new(vec![], vec![txout1]);
+        let tx1_id = tx1.get_tx_id();
+
+        let out = Output::new(0, 10, [&[118, 169, 20], pkhash, &[54, 136, 172]].concat());
+        let txout2 = TxOut::new(8, [&[118, 169, 20], pkhash, &[54, 136, 172]].concat());
+        let tx2 = RawTransaction::create_transaction(
+            vec![(tx1_id, out)],
+            vec![txout2],
+            private_key,
+            &TxOptions::default(),
+        )
+        .unwrap();
+
+        // tx2 spends an output created by tx1 in the same block
+        assert!(blockchain.verify_block_signatures(&[tx1.clone(), tx2.clone()]));
+
+        let mut tampered = tx2;
+        tampered.tx_out[0].value = 9;
+        assert!(!blockchain.verify_block_signatures(&[tx1, tampered]));
+    }
+
+    #[test]
+    fn test_rebuild_utxo_after_downloading_out_of_order() {
+        let mut blockchain = Blockchain::new();
+
+        let tx1 = RawTransaction::new(vec![], vec![TxOut::new(10, vec![])]);
+        let tx1_id = tx1.get_tx_id();
+        let txin = TxIn::new(Outpoint::new(tx1_id, 0), vec![]);
+        let tx2 = RawTransaction::new(vec![txin], vec![TxOut::new(8, vec![])]);
+
+        let block1 = BlockHeader {
+            version: 1,
+            prev_block_hash: blockchain.get_last_header_hash(),
+            merkle_root_hash: merkle_tree_root(vec![tx1_id]),
+            timestamp: 1234567890,
+            bits: 0x1d00ffff,
+            nonce: 0,
+        };
+        let block2 = BlockHeader {
+            version: 1,
+            prev_block_hash: block1.hash(),
+            merkle_root_hash: merkle_tree_root(vec![tx2.get_tx_id()]),
+            timestamp: 1234568490,
+            bits: 0x1d00ffff,
+            nonce: 0,
+        };
+        assert!(blockchain.push(block1.clone()).is_ok());
+        assert!(blockchain.push(block2.clone()).is_ok());
+
+        let block_message2 = BlockMessage {
+            block_header: block2,
+            txn_count: CompactSize::U8(1),
+            txns: vec![tx2],
+        };
+        assert!(blockchain.add_block_txs(block_message2).is_ok());
+        assert_eq!(
+            blockchain.get_missing_blocks_since(1234567890),
+            vec![block1.hash()]
+        );
+
+        let block_message1 = BlockMessage {
+            block_header: block1,
+            txn_count: CompactSize::U8(1),
+            txns: vec![tx1],
+        };
+        assert!(blockchain.add_block_txs(block_message1).is_ok());
+        assert!(blockchain.get_missing_blocks_since(1234567890).is_empty());
+        assert_eq!(blockchain.utxo.get_total_balance(), 18);
+
+        blockchain.rebuild_utxo();
+        assert_eq!(blockchain.utxo.get_total_balance(), 8);
+    }
+
+    #[test]
+    fn test_reindex_drops_the_blocks_from_the_first_bad_proof_of_work() {
+        let filepath = std::env::temp_dir()
+            .join(format!("reindex_test_{}", std::process::id()))
+            .to_string_lossy()
+            .into_owned();
+        let config = crate::sim::SimConfig {
+            height: 5,
+            ..Default::default()
+        };
+        let mut records: Vec<Vec<u8>> = crate::sim::generate_blocks(&config)
+            .unwrap()
+            .into_iter()
+            .map(|block| Block::to_bytes(&Block::from_block_header(block.block_header)))
+            .collect();
+        // The fourth block claims the hardest bits of testnet without the work
+        records[3][40..44].copy_from_slice(&crate::constants::POW_LIMIT_BITS.to_le_bytes());
+        write_records(File::create(&filepath).unwrap(), records.into_iter()).unwrap();
+
+        let mut reported = vec![];
+        let blockchain =
+            Blockchain::reindex_file(filepath.clone(), |done, total| reported.push((done, total)))
+                .unwrap();
+        assert_eq!(blockchain.get_height(), 3);
+        assert_eq!(reported.last(), Some(&(3, 5)));
+        assert!(blockchain.utxo.is_empty());
+        assert_eq!(
+            Blockchain::read_from_file(filepath.clone())
+                .unwrap()
+                .get_height(),
+            3
+        );
+        std::fs::remove_file(&filepath).unwrap();
+    }
+
+    #[test]
+    fn test_a_truncated_file_is_rolled_back_and_kept_aside() {
+        let filepath = std::env::temp_dir()
+            .join(format!("recovery_test_{}", std::process::id()))
+            .to_string_lossy()
+            .into_owned();
+        let config = crate::sim::SimConfig {
+            height: 4,
+            ..Default::default()
+        };
+        let mut saved = Blockchain::new();
+        for block in crate::sim::generate_blocks(&config).unwrap() {
+            saved.push(block.block_header).unwrap();
+        }
+        saved.save_to_file(filepath.clone()).unwrap();
+        let mut bytes = fs::read(&filepath).unwrap();
+        bytes.truncate(bytes.len() - 10);
+        fs::write(&filepath, &bytes).unwrap();
+
+        let blockchain = Blockchain::read_from_file(filepath.clone()).unwrap();
+        assert_eq!(blockchain.get_height(), 3);
+        let backup = format!("{}{}", filepath, DAMAGED_FILE_SUFFIX);
+        assert_eq!(fs::read(&backup).unwrap(), bytes);
+        let file = read_records(File::open(&filepath).unwrap()).unwrap();
+        assert_eq!((file.records.len(), file.damage), (3, None));
+
+        fs::remove_file(&filepath).unwrap();
+        fs::remove_file(&backup).unwrap();
+    }
 }
@@ -1,9 +1,11 @@
 use super::txs::{Tx, Txs};
 use bitcoin_hashes::{sha256d, Hash};
+use std::sync::Arc;
 //use std::mem;
 
 use crate::block_header::BlockHeader;
-use crate::constants::{GENESIS_BLOCK_HASH_VALUE, GENESIS_BLOCK_MERKLE_ROOT_HASH_VALUE};
+use crate::constants::GENESIS_BLOCK_MERKLE_ROOT_HASH_VALUE;
+use crate::message_header::network;
 use crate::protocol_error::ProtocolError;
 use crate::utils::decode_hex;
 pub const SIZE_BLOCKS: usize = 48;
@@ -20,9 +22,11 @@ pub struct Block {
 }
 
 impl Block {
+    /// Genesis block of the network the node runs on.
     pub fn default() -> Block {
+        let network = network();
         let merkle_root_hash = decode_hex(GENESIS_BLOCK_MERKLE_ROOT_HASH_VALUE);
-        let hash = decode_hex(GENESIS_BLOCK_HASH_VALUE);
+        let hash = decode_hex(network.genesis_hash());
 
         Block {
             version: 1,
@@ -30,7 +34,7 @@ impl Block {
             merkle_root_hash,
             timestamp: 1231006505,
             bits: 0x1d00ffff,
-            nonce: 0x18aea41a,
+            nonce: network.genesis_nonce(),
             txs: None,
         }
     }
@@ -98,17 +102,10 @@ impl Block {
         })
     }
 
-    pub fn get_tx(&self, txid: [u8; 32]) -> Option<Tx> {
+    pub fn get_tx(&self, txid: [u8; 32]) -> Option<Arc<Tx>> {
         if let Some(i) = &self.txs {
             return i.get_tx(txid);
         }
         None
     }
-
-    pub fn get_tx_history(&self, pkhash: &Vec<u8>) -> Vec<Tx> {
-        if let Some(tx) = &self.txs {
-            return tx.get_txs_by_pkhash(pkhash);
-        }
-        vec![]
-    }
 }
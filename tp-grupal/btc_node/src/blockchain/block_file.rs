@@ -0,0 +1,172 @@
+use std::{
+    fmt,
+    io::{BufReader, BufWriter, ErrorKind, Read, Write},
+};
+
+use bitcoin_hashes::{sha256d, Hash};
+
+use super::block::SIZE_BLOCKS;
+use crate::protocol_error::ProtocolError;
+
+// First bytes of the files whose records end in a checksum, older files have bare records
+const MAGIC: [u8; 4] = *b"BLK1";
+const CHECKSUM_SIZE: usize = 4;
+// Bytes read from or written to the file at once
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Why the records of a block file end before the file does.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileDamage {
+    /// The record at this position is incomplete, the file was cut while being written
+    TruncatedRecord(usize),
+    /// The checksum of the record at this position doesn't match its bytes
+    BadChecksum(usize),
+}
+
+impl fmt::Display for FileDamage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FileDamage::TruncatedRecord(at) => write!(f, "record {} is truncated", at),
+            FileDamage::BadChecksum(at) => write!(f, "record {} has a wrong checksum", at),
+        }
+    }
+}
+
+/// Records of a block file, one per block after the genesis one.
+/// The records before a damaged one are kept, the blocks after it are downloaded again.
+#[derive(Debug, Default)]
+pub struct BlockFile {
+    pub records: Vec<[u8; SIZE_BLOCKS]>,
+    pub damage: Option<FileDamage>,
+    /// False for the files written before the checksums, their damage can't be detected here
+    pub checksummed: bool,
+}
+
+/// Reads the records in chunks, checking the checksum of each one.
+/// Files written before the checksums were added are read as bare records.
+pub fn read_records(source: impl Read) -> Result<BlockFile, ProtocolError> {
+    let mut reader = BufReader::with_capacity(CHUNK_SIZE, source);
+    let mut magic = [0u8; MAGIC.len()];
+    let read = read_full(&mut reader, &mut magic)?;
+    let checksummed = magic == MAGIC;
+    let record_size = if checksummed {
+        SIZE_BLOCKS + CHECKSUM_SIZE
+    } else {
+        SIZE_BLOCKS
+    };
+
+    // The first bytes of an older file belong to its first record
+    let mut record = vec![0u8; record_size];
+    let mut filled = 0;
+    if !checksummed {
+        record[..read].copy_from_slice(&magic[..read]);
+        filled = read;
+    }
+
+    let mut file = BlockFile {
+        checksummed,
+        ..BlockFile::default()
+    };
+    loop {
+        filled += read_full(&mut reader, &mut record[filled..])?;
+        if filled == 0 {
+            break;
+        }
+
+        let at = file.records.len();
+        if filled < record_size {
+            file.damage = Some(FileDamage::TruncatedRecord(at));
+            break;
+        }
+        let (bytes, checksum) = record.split_at(SIZE_BLOCKS);
+        if checksummed && checksum != record_checksum(bytes) {
+            file.damage = Some(FileDamage::BadChecksum(at));
+            break;
+        }
+
+        file.records.push(bytes.try_into().unwrap());
+        filled = 0;
+    }
+    Ok(file)
+}
+
+/// Writes the records in the checksummed format.
+pub fn write_records(
+    sink: impl Write,
+    records: impl Iterator<Item = Vec<u8>>,
+) -> Result<(), ProtocolError> {
+    let mut writer = BufWriter::with_capacity(CHUNK_SIZE, sink);
+    writer.write_all(&MAGIC)?;
+    for record in records {
+        writer.write_all(&record)?;
+        writer.write_all(&record_checksum(&record))?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn record_checksum(record: &[u8]) -> [u8; CHECKSUM_SIZE] {
+    let hash = sha256d::Hash::hash(record).to_byte_array();
+    [hash[0], hash[1], hash[2], hash[3]]
+}
+
+/// Fills as much of `buf` as the reader has, the count is short only at the end of the file.
+fn read_full(reader: &mut impl Read, buf: &mut [u8]) -> Result<usize, ProtocolError> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn records() -> Vec<Vec<u8>> {
+        vec![
+            vec![1; SIZE_BLOCKS],
+            vec![2; SIZE_BLOCKS],
+            vec![3; SIZE_BLOCKS],
+        ]
+    }
+
+    #[test]
+    fn test_records_round_trip_and_older_files_are_read() {
+        let mut bytes = vec![];
+        write_records(&mut bytes, records().into_iter()).unwrap();
+
+        let file = read_records(&bytes[..]).unwrap();
+        assert_eq!(file.records.len(), 3);
+        assert_eq!(file.records[2], [3; SIZE_BLOCKS]);
+        assert_eq!(file.damage, None);
+
+        let older = records().concat();
+        let file = read_records(&older[..]).unwrap();
+        assert_eq!(file.records.len(), 3);
+        assert_eq!(file.damage, None);
+        assert!(!file.checksummed);
+    }
+
+    #[test]
+    fn test_records_before_the_damage_are_kept() {
+        let mut bytes = vec![];
+        write_records(&mut bytes, records().into_iter()).unwrap();
+
+        let truncated = &bytes[..bytes.len() - 1];
+        let file = read_records(truncated).unwrap();
+        assert_eq!(file.records.len(), 2);
+        assert_eq!(file.damage, Some(FileDamage::TruncatedRecord(2)));
+
+        let mut corrupted = bytes.clone();
+        corrupted[MAGIC.len() + SIZE_BLOCKS + CHECKSUM_SIZE + 5] ^= 1;
+        let file = read_records(&corrupted[..]).unwrap();
+        assert_eq!(file.records.len(), 1);
+        assert_eq!(file.damage, Some(FileDamage::BadChecksum(1)));
+    }
+}
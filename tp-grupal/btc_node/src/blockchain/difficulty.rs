@@ -0,0 +1,95 @@
+use super::Blockchain;
+use crate::constants::{
+    DIFFICULTY_ADJUSTMENT_INTERVAL, POW_LIMIT_BITS, TARGET_SPACING, TARGET_TIMESPAN,
+};
+
+impl Blockchain {
+    /// Bits the block after the last header needs, if it has the timestamp.
+    /// Follows the rules of the test network: blocks found 20 minutes after the previous one
+    /// may use the easiest target, the rest use the one of the last adjustment.
+    pub fn next_bits(&self, timestamp: u32) -> u32 {
+        let next_height = self.chain.len();
+        let last = match self.chain.last() {
+            Some(block) => block,
+            None => return POW_LIMIT_BITS,
+        };
+
+        if next_height.is_multiple_of(DIFFICULTY_ADJUSTMENT_INTERVAL) {
+            let first = &self.chain[next_height - DIFFICULTY_ADJUSTMENT_INTERVAL];
+            return retarget(last.bits, last.timestamp.saturating_sub(first.timestamp));
+        }
+
+        if timestamp > last.timestamp + 2 * TARGET_SPACING {
+            return POW_LIMIT_BITS;
+        }
+        // The easy blocks don't count, the target is the one of the last block that isn't one
+        self.chain
+            .iter()
+            .enumerate()
+            .rev()
+            .find(|(height, block)| {
+                height.is_multiple_of(DIFFICULTY_ADJUSTMENT_INTERVAL)
+                    || block.bits != POW_LIMIT_BITS
+            })
+            .map_or(POW_LIMIT_BITS, |(_, block)| block.bits)
+    }
+}
+
+/// Scales the target of `bits` by how long the last interval took against TARGET_TIMESPAN.
+/// The change is at most 4 times in either direction and never under the easiest target.
+pub fn retarget(bits: u32, timespan: u32) -> u32 {
+    let timespan = timespan.clamp(TARGET_TIMESPAN / 4, TARGET_TIMESPAN * 4) as u128;
+    let (mantissa, exponent) = split_bits(bits);
+
+    // Three more bytes than the mantissa keep the precision the compact form can hold
+    let mut scaled = (mantissa << 24) * timespan / TARGET_TIMESPAN as u128;
+    let mut exponent = exponent - 3;
+    while scaled > 0x7fffff {
+        scaled >>= 8;
+        exponent += 1;
+    }
+    let new_bits = join_bits(scaled as u32, exponent);
+
+    let (limit_mantissa, limit_exponent) = split_bits(POW_LIMIT_BITS);
+    if (exponent, scaled) > (limit_exponent, limit_mantissa) {
+        return POW_LIMIT_BITS;
+    }
+    new_bits
+}
+
+fn split_bits(bits: u32) -> (u128, i32) {
+    ((bits & 0x007fffff) as u128, (bits >> 24) as i32)
+}
+
+fn join_bits(mantissa: u32, exponent: i32) -> u32 {
+    ((exponent as u32) << 24) | mantissa
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retarget_follows_the_timespan() {
+        // Blocks twice as fast halve the target
+        assert_eq!(retarget(0x1d00ffff, TARGET_TIMESPAN / 2), 0x1c7fff80);
+        // The target can't grow over the limit
+        assert_eq!(retarget(0x1d00ffff, TARGET_TIMESPAN * 2), POW_LIMIT_BITS);
+        // Nor change more than 4 times
+        assert_eq!(
+            retarget(0x1c05a3f4, 1),
+            retarget(0x1c05a3f4, TARGET_TIMESPAN / 4)
+        );
+    }
+
+    #[test]
+    fn test_late_blocks_may_use_the_easiest_target() {
+        let mut blockchain = Blockchain::new();
+        let last = blockchain.chain.last_mut().unwrap();
+        last.bits = 0x1c05a3f4;
+        let timestamp = last.timestamp;
+
+        assert_eq!(blockchain.next_bits(timestamp + 60), 0x1c05a3f4);
+        assert_eq!(blockchain.next_bits(timestamp + 30 * 60), POW_LIMIT_BITS);
+    }
+}
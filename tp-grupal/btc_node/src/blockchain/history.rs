@@ -0,0 +1,65 @@
+use std::fmt;
+
+use super::txs::Tx;
+use crate::utils::timestamp_to_date;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TxDirection {
+    Sent,
+    Received,
+}
+
+impl fmt::Display for TxDirection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TxDirection::Sent => write!(f, "Sent"),
+            TxDirection::Received => write!(f, "Received"),
+        }
+    }
+}
+
+/// A confirmed transaction seen from the point of view of a single wallet address.
+#[derive(Debug, Clone)]
+pub struct HistoryEntry {
+    pub tx: Tx,
+    pub timestamp: u32,
+    pub direction: TxDirection,
+    /// Received minus sent by the address, in satoshis.
+    pub amount: i64,
+    /// None when some previous output could not be found in the chain.
+    pub fee: Option<i64>,
+    pub confirmations: u32,
+}
+
+impl HistoryEntry {
+    /// Builds an entry from the value the address received and sent in the transaction.
+    pub fn new(
+        tx: Tx,
+        timestamp: u32,
+        received: i64,
+        sent: i64,
+        fee: Option<i64>,
+        confirmations: u32,
+    ) -> HistoryEntry {
+        let amount = received - sent;
+        let direction = if amount < 0 {
+            TxDirection::Sent
+        } else {
+            TxDirection::Received
+        };
+
+        HistoryEntry {
+            tx,
+            timestamp,
+            direction,
+            amount,
+            fee,
+            confirmations,
+        }
+    }
+
+    /// Returns the block timestamp formatted as a UTC date.
+    pub fn date(&self) -> String {
+        timestamp_to_date(self.timestamp)
+    }
+}
@@ -0,0 +1,359 @@
+use std::collections::{HashMap, VecDeque};
+
+use super::{txs::Tx, txs::Txs, Blockchain};
+use crate::{
+    block_header::BLOCK_HEADER_SIZE,
+    block_template::{block_subsidy, total_supply},
+    constants::{BLOCK_STATS_CAPACITY, SUBSIDY_HALVING_INTERVAL},
+    message::compact_size::CompactSize,
+};
+
+const SECONDS_PER_DAY: u32 = 24 * 60 * 60;
+
+/// Summary of the chain used by the wallet to show something more than the balances.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChainStats {
+    pub height: i32,
+    pub last_block_time: u32,
+    /// Blocks mined in the day before the last block.
+    pub blocks_per_day: usize,
+    /// Average seconds between the blocks of the window, if it has more than one block.
+    pub average_block_interval: Option<f64>,
+    /// Transactions in the downloaded blocks.
+    pub tx_count: usize,
+    pub utxo_count: usize,
+    pub utxo_value: i64,
+    /// Sum of the fees that are known in the window.
+    pub window_fees: i64,
+    pub window: usize,
+    /// Satoshis created up to the last block by the halving schedule.
+    pub supply: i64,
+}
+
+/// Issuance of the chain at its last block.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Supply {
+    pub height: i32,
+    /// Satoshis created by the blocks up to the height.
+    pub issued: i64,
+    /// Satoshis created by the next block.
+    pub subsidy: i64,
+    /// Height of the next block whose subsidy is halved.
+    pub next_halving: i32,
+}
+
+/// Size and fees of a block, kept for the charts of the Overview page and the fee estimation.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BlockStats {
+    pub height: i32,
+    pub time: u32,
+    /// Serialized size in bytes.
+    pub size: usize,
+    pub tx_count: usize,
+    /// Sum of the fees that are known, the transactions spending unknown outputs are left out.
+    pub total_fees: i64,
+    /// Sat/vB of the cheapest transaction with a known fee, None if there isn't any.
+    pub min_fee_rate: Option<f64>,
+    pub median_fee_rate: Option<f64>,
+}
+
+/// Stats of the last BLOCK_STATS_CAPACITY blocks ordered by height, the lowest is dropped
+/// when a new one doesn't fit. The blocks can arrive in any order during the download.
+#[derive(Debug, Clone, Default)]
+pub struct RecentBlocks {
+    stats: VecDeque<BlockStats>,
+}
+
+impl RecentBlocks {
+    pub fn push(&mut self, stats: BlockStats) {
+        let full = self.stats.len() >= BLOCK_STATS_CAPACITY;
+        if full && self.stats.front().is_some_and(|s| stats.height <= s.height) {
+            return;
+        }
+
+        let position = self.stats.partition_point(|s| s.height < stats.height);
+        if self
+            .stats
+            .get(position)
+            .is_some_and(|s| s.height == stats.height)
+        {
+            self.stats[position] = stats;
+            return;
+        }
+        self.stats.insert(position, stats);
+        if self.stats.len() > BLOCK_STATS_CAPACITY {
+            self.stats.pop_front();
+        }
+    }
+
+    /// The stats from the lowest height to the highest.
+    pub fn to_vec(&self) -> Vec<BlockStats> {
+        self.stats.iter().cloned().collect()
+    }
+}
+
+impl Blockchain {
+    /// Computes the statistics of the chain, using the last `window` blocks for the averages and fees.
+    pub fn get_chain_stats(&self, window: usize) -> ChainStats {
+        let last_block_time = self.chain.last().map(|b| b.timestamp).unwrap_or(0);
+
+        ChainStats {
+            height: self.get_height(),
+            last_block_time,
+            blocks_per_day: self.blocks_per_day(),
+            average_block_interval: self.average_block_interval(window),
+            tx_count: self.tx_count(),
+            utxo_count: self.utxo.len(),
+            utxo_value: self.utxo.get_total_balance(),
+            window_fees: self.fees_in_window(window),
+            window,
+            supply: total_supply(self.get_height()),
+        }
+    }
+
+    pub fn supply(&self) -> Supply {
+        let height = self.get_height();
+        Supply {
+            height,
+            issued: total_supply(height),
+            subsidy: block_subsidy(height + 1),
+            next_halving: (height / SUBSIDY_HALVING_INTERVAL + 1) * SUBSIDY_HALVING_INTERVAL,
+        }
+    }
+
+    /// Estimates how much of the chain we have from the timestamp of the last header.
+    /// The result goes from 0 (only the genesis block) to 1 (the last header is from `now`).
+    pub fn sync_progress(&self, now: u32) -> f64 {
+        let genesis = match self.chain.first() {
+            Some(block) => block.timestamp,
+            None => return 0.0,
+        };
+        let last = self.chain.last().map(|b| b.timestamp).unwrap_or(genesis);
+
+        if now <= genesis {
+            return 1.0;
+        }
+        ((last.saturating_sub(genesis)) as f64 / (now - genesis) as f64).min(1.0)
+    }
+
+    /// Counts the blocks whose timestamp is within a day of the last block.
+    pub fn blocks_per_day(&self) -> usize {
+        let last = match self.chain.last() {
+            Some(block) => block.timestamp,
+            None => return 0,
+        };
+
+        self.chain
+            .iter()
+            .rev()
+            .take_while(|block| block.timestamp + SECONDS_PER_DAY > last)
+            .count()
+    }
+
+    pub fn average_block_interval(&self, window: usize) -> Option<f64> {
+        let blocks: Vec<u32> = self
+            .chain
+            .iter()
+            .rev()
+            .take(window)
+            .map(|b| b.timestamp)
+            .collect();
+        if blocks.len() < 2 {
+            return None;
+        }
+
+        let newest = blocks[0] as f64;
+        let oldest = blocks[blocks.len() - 1] as f64;
+        Some((newest - oldest) / (blocks.len() - 1) as f64)
+    }
+
+    /// Transactions in the downloaded blocks, counted as they are added.
+    pub fn tx_count(&self) -> usize {
+        self.tx_count
+    }
+
+    /// Sums the fees of the transactions in the last `window` blocks, up to BLOCK_STATS_CAPACITY,
+    /// from the stats taken as they were added.
+    /// Transactions that spend outputs that weren't downloaded are left out.
+    pub fn fees_in_window(&self, window: usize) -> i64 {
+        let lowest = self.get_height() - window as i32;
+        self.recent_blocks
+            .stats
+            .iter()
+            .filter(|stats| stats.height > lowest)
+            .map(|stats| stats.total_fees)
+            .sum()
+    }
+
+    /// Stats of a block that is about to be added, its previous outputs have to be in the
+    /// utxo set or earlier in the block.
+    pub(super) fn block_stats(&self, height: i32, time: u32, txs: &Txs) -> BlockStats {
+        let mut created: HashMap<[u8; 32], &Tx> = HashMap::new();
+        let mut size = BLOCK_HEADER_SIZE + CompactSize::new_from_usize(txs.txns.len()).size();
+        let mut total_fees = 0;
+        let mut rates = vec![];
+        for tx in txs.txns.iter() {
+            size += tx.vsize();
+            if let Some(fee) = self.block_tx_fee(tx, &created) {
+                total_fees += fee;
+                rates.push(fee as f64 / tx.vsize() as f64);
+            }
+            created.insert(tx.tx_id, tx);
+        }
+        rates.sort_by(|a, b| a.total_cmp(b));
+
+        BlockStats {
+            height,
+            time,
+            size,
+            tx_count: txs.txns.len(),
+            total_fees,
+            min_fee_rate: rates.first().copied(),
+            median_fee_rate: median(&rates),
+        }
+    }
+
+    fn block_tx_fee(&self, tx: &Tx, created: &HashMap<[u8; 32], &Tx>) -> Option<i64> {
+        if tx.tx_in.is_empty() {
+            return None;
+        }
+
+        let mut inputs_value = 0;
+        for txin in tx.tx_in.iter() {
+            let prev = &txin.previous_output;
+            let value = match created.get(&prev.hash) {
+                Some(parent) => {
+                    parent
+                        .tx_out
+                        .iter()
+                        .find(|out| out.index == prev.index)?
+                        .value
+                }
+                None => self.utxo.get(prev.hash, prev.index)?.value,
+            };
+            inputs_value += value;
+        }
+        Some(inputs_value - tx.get_tx_value())
+    }
+
+    /// Stats of the last blocks added with their transactions.
+    pub fn recent_blocks(&self) -> Vec<BlockStats> {
+        self.recent_blocks.to_vec()
+    }
+}
+
+/// Middle value of the sorted rates, the average of the two middle ones for an even count.
+fn median(sorted: &[f64]) -> Option<f64> {
+    let middle = sorted.len() / 2;
+    match sorted.len() {
+        0 => None,
+        n if n % 2 == 0 => Some((sorted[middle - 1] + sorted[middle]) / 2.0),
+        _ => Some(sorted[middle]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        block_header::BlockHeader,
+        merkle_tree::merkle_tree_root,
+        message::{block::BlockMessage, compact_size::CompactSize},
+        raw_transaction::{Outpoint, RawTransaction, TxIn, TxOut},
+    };
+
+    fn push_block(blockchain: &mut Blockchain, timestamp: u32, txns: Vec<RawTransaction>) {
+        let header = BlockHeader {
+            version: 1,
+            prev_block_hash: blockchain.get_last_header_hash(),
+            merkle_root_hash: merkle_tree_root(txns.iter().map(|tx| tx.get_tx_id()).collect()),
+            timestamp,
+            bits: 0x1d00ffff,
+            nonce: 0,
+        };
+        let block_message = BlockMessage {
+            block_header: header.clone(),
+            txn_count: CompactSize::new_from_usize(txns.len()),
+            txns,
+        };
+        blockchain.push(header).unwrap();
+        blockchain.add_block_txs(block_message).unwrap();
+    }
+
+    #[test]
+    fn test_chain_stats() {
+        let mut blockchain = Blockchain::new();
+
+        let tx1 = RawTransaction::new(vec![], vec![TxOut::new(50, vec![])]);
+        let tx1_id = tx1.get_tx_id();
+        push_block(&mut blockchain, 1_700_000_000, vec![tx1]);
+
+        let txin = TxIn::new(Outpoint::new(tx1_id, 0), vec![]);
+        let tx2 = RawTransaction::new(vec![txin], vec![TxOut::new(45, vec![])]);
+        let coinbase = RawTransaction::new(vec![], vec![TxOut::new(1, vec![])]);
+        push_block(&mut blockchain, 1_700_000_600, vec![coinbase, tx2]);
+
+        let coinbase = RawTransaction::new(vec![], vec![TxOut::new(2, vec![])]);
+        push_block(&mut blockchain, 1_700_001_200, vec![coinbase]);
+
+        let stats = blockchain.get_chain_stats(3);
+        assert_eq!(stats.height, 3);
+        assert_eq!(stats.last_block_time, 1_700_001_200);
+        assert_eq!(stats.blocks_per_day, 3);
+        assert_eq!(stats.average_block_interval, Some(600.0));
+        assert_eq!(stats.tx_count, 4);
+        assert_eq!(stats.window_fees, 5);
+        assert_eq!(stats.supply, total_supply(3));
+
+        let supply = blockchain.supply();
+        assert_eq!(supply.issued, 4 * crate::constants::INITIAL_SUBSIDY);
+        assert_eq!(supply.next_halving, SUBSIDY_HALVING_INTERVAL);
+
+        assert_eq!(blockchain.fees_in_window(1), 0);
+
+        let genesis = 1231006505;
+        let half = genesis + 2 * (1_700_001_200 - genesis);
+        assert!((blockchain.sync_progress(half) - 0.5).abs() < 1e-9);
+        assert_eq!(blockchain.sync_progress(1_700_001_200), 1.0);
+        assert_eq!(blockchain.average_block_interval(1), None);
+
+        let recent = blockchain.recent_blocks();
+        assert_eq!(
+            recent.iter().map(|b| b.height).collect::<Vec<_>>(),
+            [1, 2, 3]
+        );
+        let second = &recent[1];
+        assert_eq!((second.tx_count, second.total_fees), (2, 5));
+        assert_eq!(
+            second.size,
+            BLOCK_HEADER_SIZE + 1 + recent_tx_sizes(&blockchain, 2)
+        );
+        assert_eq!(second.min_fee_rate, second.median_fee_rate);
+        assert!(second.min_fee_rate.unwrap() > 0.0);
+        assert_eq!(recent[2].min_fee_rate, None);
+    }
+
+    fn recent_tx_sizes(blockchain: &Blockchain, height: usize) -> usize {
+        let txs = blockchain.chain[height].txs.as_ref().unwrap();
+        txs.txns.iter().map(|tx| tx.vsize()).sum()
+    }
+
+    #[test]
+    fn test_the_recent_blocks_keep_the_highest() {
+        let mut recent = RecentBlocks::default();
+        let block = |height: i32| BlockStats {
+            height,
+            ..Default::default()
+        };
+        for height in (0..BLOCK_STATS_CAPACITY as i32 + 10).rev() {
+            recent.push(block(height));
+        }
+        recent.push(block(3));
+
+        let heights: Vec<i32> = recent.to_vec().iter().map(|b| b.height).collect();
+        assert_eq!(heights.len(), BLOCK_STATS_CAPACITY);
+        assert_eq!(heights[0], 10);
+        assert!(heights.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(median(&[1.0, 2.0, 4.0, 8.0]), Some(3.0));
+    }
+}
@@ -1,4 +1,9 @@
+use std::sync::Arc;
+
+use bitcoin_hashes::{sha256d, Hash};
+
 use crate::{
+    constants::LOCKTIME_SEQUENCE,
     message::compact_size::CompactSize,
     raw_transaction::{RawTransaction, TxIn, TxOut},
     script::PubKeyScript,
@@ -8,7 +13,8 @@ use super::utxo_set::Output;
 
 #[derive(Debug, Clone)]
 pub struct Txs {
-    pub txns: Vec<Tx>,
+    /// Shared with the wallet messages and the block stats, cloning a block doesn't copy them
+    pub txns: Vec<Arc<Tx>>,
 }
 
 #[derive(Debug, Clone)]
@@ -18,6 +24,11 @@ pub struct Tx {
     pub tx_out: Vec<Output>,
     pub lock_time: u32,
     pub tx_id: [u8; 32],
+    /// Satoshis paid in fees by an unconfirmed transaction, None if its previous outputs
+    /// weren't found or it is in a block
+    pub fee: Option<i64>,
+    /// The serialized transaction, relayed as is
+    bytes: Arc<[u8]>,
 }
 
 impl Tx {
@@ -49,20 +60,52 @@ impl Tx {
     }
 
     pub fn from_raw_tx(tx: &RawTransaction) -> Tx {
-        let mut outs: Vec<Output> = vec![];
-        for (i, out) in tx.tx_out.iter().enumerate() {
-            outs.push(Output::new(i as u32, out.value, out.pk_script.clone()));
+        let bytes = tx.to_bytes();
+        let tx_id = sha256d::Hash::hash(&bytes).to_byte_array();
+        Tx::with_bytes(tx.clone(), tx_id, Arc::from(bytes))
+    }
+
+    /// Moves the inputs and scripts of the raw transaction instead of copying them.
+    fn with_bytes(tx: RawTransaction, tx_id: [u8; 32], bytes: Arc<[u8]>) -> Tx {
+        let mut outs: Vec<Output> = Vec::with_capacity(tx.tx_out.len());
+        for (i, out) in tx.tx_out.into_iter().enumerate() {
+            outs.push(Output::new(i as u32, out.value, out.pk_script));
         }
 
         Tx {
             version: tx.version,
-            tx_in: tx.tx_in.clone(),
+            tx_in: tx.tx_in,
             tx_out: outs,
             lock_time: tx.lock_time,
-            tx_id: tx.get_tx_id(),
+            tx_id,
+            fee: None,
+            bytes,
         }
     }
 
+    /// Virtual size, the transactions carry no witnesses so it is the serialized size.
+    pub fn vsize(&self) -> usize {
+        self.bytes.len()
+    }
+
+    /// Fee in satoshis per virtual byte, when the fee is known.
+    pub fn fee_rate(&self) -> Option<f64> {
+        self.fee.map(|fee| fee as f64 / self.vsize() as f64)
+    }
+
+    /// Replaceable transactions (BIP 125) have an input with a sequence below LOCKTIME_SEQUENCE,
+    /// until they confirm a payment in them can be taken back.
+    pub fn signals_rbf(&self) -> bool {
+        self.tx_in
+            .iter()
+            .any(|txin| txin.sequence < LOCKTIME_SEQUENCE)
+    }
+
+    /// The serialized transaction, without serializing it again.
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
     pub fn to_raw_tx(&self) -> RawTransaction {
         let mut tx_out: Vec<TxOut> = vec![];
 
@@ -95,14 +138,30 @@ impl Tx {
 
         value
     }
+
+    pub fn value_payed_to_pkhash(&self, pkhash: &Vec<u8>) -> i64 {
+        let mut value = 0;
+
+        for out in &self.tx_out {
+            if out.pkscript.can_be_spent_by(pkhash) {
+                value += out.value;
+            }
+        }
+
+        value
+    }
 }
 
 impl Txs {
+    /// The transactions are serialized in a single scratch buffer, and copied from it to their
+    /// exactly sized bytes.
     pub fn from_raw_txs(raw_txs: Vec<RawTransaction>) -> Txs {
-        let mut txns: Vec<Tx> = vec![];
+        let mut scratch = Vec::new();
+        let mut txns: Vec<Arc<Tx>> = Vec::with_capacity(raw_txs.len());
 
         for tx in raw_txs {
-            txns.push(Tx::from_raw_tx(&tx));
+            let tx_id = tx.tx_id_with(&mut scratch);
+            txns.push(Arc::new(Tx::with_bytes(tx, tx_id, Arc::from(&scratch[..]))));
         }
 
         Txs { txns }
@@ -126,20 +185,20 @@ impl Txs {
         txids
     }
 
-    pub fn get_tx(&self, txid: [u8; 32]) -> Option<Tx> {
+    pub fn get_tx(&self, txid: [u8; 32]) -> Option<Arc<Tx>> {
         for tx in self.txns.iter() {
             if tx.tx_id == txid {
-                return Some(tx.clone());
+                return Some(Arc::clone(tx));
             }
         }
         None
     }
 
-    pub fn get_txs_by_pkhash(&self, pkhash: &Vec<u8>) -> Vec<Tx> {
+    pub fn get_txs_by_pkhash(&self, pkhash: &Vec<u8>) -> Vec<Arc<Tx>> {
         let mut vec = vec![];
         for tx in self.txns.iter() {
             if tx.has_pkhash(pkhash) {
-                vec.push(tx.clone());
+                vec.push(Arc::clone(tx));
             };
         }
         vec
@@ -0,0 +1,178 @@
+use std::collections::HashSet;
+
+use crate::{blockchain::utxo_set::Output, protocol_error::ProtocolError};
+
+/// Txid and output index of a coin.
+pub type CoinId = ([u8; 32], u32);
+
+/// Outputs to spend, with the txid of their transactions, and the sum of their values.
+pub type SelectedOutputs = (Vec<([u8; 32], Output)>, i64);
+
+/// Unspent output of a wallet address, as listed in the Coins page.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Coin {
+    pub txid: [u8; 32],
+    pub index: u32,
+    pub value: i64,
+    pub confirmations: u32,
+    pub address: String,
+    /// Frozen coins are never spent
+    pub frozen: bool,
+}
+
+impl Coin {
+    /// Coins of the address from its unspent outputs and their confirmations.
+    pub fn from_outputs(
+        outputs: Vec<([u8; 32], Output, u32)>,
+        address: &str,
+        frozen: &HashSet<CoinId>,
+    ) -> Vec<Coin> {
+        outputs
+            .into_iter()
+            .map(|(txid, out, confirmations)| Coin {
+                txid,
+                index: out.index,
+                value: out.value,
+                confirmations,
+                address: address.to_string(),
+                frozen: frozen.contains(&(txid, out.index)),
+            })
+            .collect()
+    }
+}
+
+/// Picks the outputs that pay the amount and returns them with their sum.
+/// With a selection every selected output is spent, otherwise the biggest unfrozen ones are used.
+pub fn select_coins(
+    mut utxo: Vec<([u8; 32], Output)>,
+    amount: i64,
+    selected: Option<&[CoinId]>,
+    frozen: &HashSet<CoinId>,
+) -> Result<SelectedOutputs, ProtocolError> {
+    if let Some(selected) = selected {
+        return spend_selected(utxo, amount, selected, frozen);
+    }
+
+    utxo.retain(|(txid, out)| !frozen.contains(&(*txid, out.index)));
+    utxo.sort_by_key(|(_, out)| std::cmp::Reverse(out.value));
+
+    let mut out_to_spend = vec![];
+    let mut sum = 0;
+    for output in utxo {
+        sum += output.1.value;
+        out_to_spend.push(output);
+        if sum >= amount {
+            break;
+        }
+    }
+
+    if sum < amount {
+        return Err(ProtocolError::Error("Insufficient balance".to_string()));
+    }
+
+    Ok((out_to_spend, sum))
+}
+
+fn spend_selected(
+    utxo: Vec<([u8; 32], Output)>,
+    amount: i64,
+    selected: &[CoinId],
+    frozen: &HashSet<CoinId>,
+) -> Result<SelectedOutputs, ProtocolError> {
+    if selected.is_empty() {
+        return Err(ProtocolError::Error("No coins were selected".to_string()));
+    }
+
+    let mut out_to_spend = vec![];
+    for outpoint in selected {
+        if frozen.contains(outpoint) {
+            return Err(ProtocolError::Error(
+                "A selected coin is frozen".to_string(),
+            ));
+        }
+        if out_to_spend
+            .iter()
+            .any(|(txid, out): &([u8; 32], Output)| (*txid, out.index) == *outpoint)
+        {
+            continue;
+        }
+        match utxo
+            .iter()
+            .find(|(txid, out)| (*txid, out.index) == *outpoint)
+        {
+            Some(output) => out_to_spend.push(output.clone()),
+            None => {
+                return Err(ProtocolError::Error(
+                    "A selected coin is not an unspent output of the account".to_string(),
+                ))
+            }
+        }
+    }
+
+    let sum = out_to_spend.iter().map(|(_, out)| out.value).sum();
+    if sum < amount {
+        return Err(ProtocolError::Error(
+            "The selected coins are not enough to pay the amount and the fee".to_string(),
+        ));
+    }
+
+    Ok((out_to_spend, sum))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::script::PubKeyScript;
+
+    fn utxo() -> Vec<([u8; 32], Output)> {
+        [(1, 0, 10), (2, 1, 50), (3, 0, 30)]
+            .into_iter()
+            .map(|(id, index, value)| {
+                let out = Output {
+                    index,
+                    value,
+                    pkscript: PubKeyScript::P2PKH(vec![0; 20]),
+                };
+                ([id; 32], out)
+            })
+            .collect()
+    }
+
+    fn outpoints(coins: &[([u8; 32], Output)]) -> Vec<CoinId> {
+        coins.iter().map(|(txid, out)| (*txid, out.index)).collect()
+    }
+
+    #[test]
+    fn test_automatic_selection_skips_frozen_coins() {
+        let (coins, sum) = select_coins(utxo(), 40, None, &HashSet::new()).unwrap();
+        assert_eq!(outpoints(&coins), vec![([2; 32], 1)]);
+        assert_eq!(sum, 50);
+
+        let frozen = HashSet::from([([2; 32], 1)]);
+        let (coins, sum) = select_coins(utxo(), 40, None, &frozen).unwrap();
+        assert_eq!(outpoints(&coins), vec![([3; 32], 0), ([1; 32], 0)]);
+        assert_eq!(sum, 40);
+
+        assert!(select_coins(utxo(), 41, None, &frozen).is_err());
+    }
+
+    #[test]
+    fn test_selected_coins_are_all_spent() {
+        let selected = [([1; 32], 0), ([3; 32], 0)];
+        let (coins, sum) = select_coins(utxo(), 5, Some(&selected), &HashSet::new()).unwrap();
+        assert_eq!(outpoints(&coins), selected.to_vec());
+        assert_eq!(sum, 40);
+
+        assert!(select_coins(utxo(), 41, Some(&selected), &HashSet::new()).is_err());
+    }
+
+    #[test]
+    fn test_invalid_selections_are_rejected() {
+        let unknown = [([1; 32], 1)];
+        assert!(select_coins(utxo(), 5, Some(&unknown), &HashSet::new()).is_err());
+        assert!(select_coins(utxo(), 5, Some(&[]), &HashSet::new()).is_err());
+
+        let frozen = HashSet::from([([1; 32], 0)]);
+        assert!(select_coins(utxo(), 5, Some(&[([1; 32], 0)]), &frozen).is_err());
+    }
+}
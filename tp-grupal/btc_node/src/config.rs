@@ -1,4 +1,5 @@
 use std::{
+    env,
     error::Error,
     fmt,
     fs::File,
@@ -6,6 +7,18 @@ use std::{
     time::Duration,
 };
 
+use crate::{
+    address::Network,
+    constants::{
+        DEFAULT_CONNECT_BUDGET, DEFAULT_MAX_INBOUND, DEFAULT_MAX_INBOUND_PER_IP, DEFAULT_MIN_PEERS,
+        DEFAULT_MIN_RELAY_FEE, DEFAULT_PEERS_FILE, DEFAULT_USER_AGENT,
+    },
+    log_file::LogDestination,
+    peer_filter::{PeerFilter, Subnet},
+    register::InboundLimits,
+    sim::SimConfig,
+};
+
 #[derive(Debug)]
 pub enum ConfigError {
     ConfigFileError(std::io::Error),
@@ -42,15 +55,35 @@ impl From<std::io::Error> for ConfigError {
 }
 
 pub struct ConfigBuilder {
-    dns: Option<String>,
+    dns_seeds: Vec<String>,
     port: Option<u16>,
     tcp_timeout: Option<Duration>,
+    connect_budget: Option<Duration>,
+    min_peers: Option<usize>,
+    max_download_rate: Option<u32>,
     blockchain_file: Option<String>,
     log_file: Option<String>,
+    log_destination: Option<LogDestination>,
+    datadir: Option<String>,
     block_downloading_timestamp: Option<u32>,
     block_downloading_threads: Option<usize>,
     max_listen_peers: Option<usize>,
-    host: Option<String>,
+    max_inbound: Option<usize>,
+    max_inbound_per_ip: Option<usize>,
+    hosts: Vec<String>,
+    peers_file: Option<String>,
+    user_agent: Option<String>,
+    services: Option<u64>,
+    blocksonly: Option<bool>,
+    v2transport: Option<bool>,
+    readonly: Option<bool>,
+    min_relay_fee: Option<f64>,
+    network: Option<Network>,
+    allowlist: Vec<Subnet>,
+    denylist: Vec<Subnet>,
+    simulation: Option<SimConfig>,
+    record_session: Option<String>,
+    replay_session: Option<String>,
 }
 
 impl Default for ConfigBuilder {
@@ -62,15 +95,35 @@ impl Default for ConfigBuilder {
 impl ConfigBuilder {
     pub fn new() -> ConfigBuilder {
         ConfigBuilder {
-            dns: None,
+            dns_seeds: Vec::new(),
             port: None,
             tcp_timeout: None,
+            connect_budget: None,
+            min_peers: None,
+            max_download_rate: None,
             blockchain_file: None,
             log_file: None,
+            log_destination: None,
+            datadir: None,
             block_downloading_timestamp: None,
             block_downloading_threads: None,
             max_listen_peers: None,
-            host: None,
+            max_inbound: None,
+            max_inbound_per_ip: None,
+            hosts: Vec::new(),
+            peers_file: None,
+            user_agent: None,
+            services: None,
+            blocksonly: None,
+            v2transport: None,
+            readonly: None,
+            min_relay_fee: None,
+            network: None,
+            allowlist: Vec::new(),
+            denylist: Vec::new(),
+            simulation: None,
+            record_session: None,
+            replay_session: None,
         }
     }
 
@@ -84,6 +137,17 @@ impl ConfigBuilder {
         self
     }
 
+    /// Connections the listener accepts, the oldest ones are evicted to make room.
+    pub fn max_inbound(mut self, max_inbound: usize) -> ConfigBuilder {
+        self.max_inbound = Some(max_inbound);
+        self
+    }
+
+    pub fn max_inbound_per_ip(mut self, max_inbound_per_ip: usize) -> ConfigBuilder {
+        self.max_inbound_per_ip = Some(max_inbound_per_ip);
+        self
+    }
+
     pub fn block_downloading_timestamp(
         mut self,
         block_downloading_timestamp: u32,
@@ -102,8 +166,24 @@ impl ConfigBuilder {
         self
     }
 
-    pub fn dns(mut self, dns: String) -> ConfigBuilder {
-        self.dns = Some(dns);
+    pub fn log_destination(mut self, log_destination: LogDestination) -> ConfigBuilder {
+        self.log_destination = Some(log_destination);
+        self
+    }
+
+    pub fn datadir(mut self, datadir: String) -> ConfigBuilder {
+        self.datadir = Some(datadir);
+        self
+    }
+
+    /// Adds dns seeds, they are all queried when the node starts.
+    pub fn dns_seeds(mut self, dns_seeds: Vec<String>) -> ConfigBuilder {
+        self.dns_seeds.extend(dns_seeds);
+        self
+    }
+
+    pub fn peers_file(mut self, peers_file: String) -> ConfigBuilder {
+        self.peers_file = Some(peers_file);
         self
     }
 
@@ -117,15 +197,102 @@ impl ConfigBuilder {
         self
     }
 
-    pub fn host(mut self, host: String) -> ConfigBuilder {
-        self.host = Some(host);
+    pub fn connect_budget(mut self, connect_budget: Duration) -> ConfigBuilder {
+        self.connect_budget = Some(connect_budget);
+        self
+    }
+
+    /// Stops dialing once this many peers completed the handshake.
+    pub fn min_peers(mut self, min_peers: usize) -> ConfigBuilder {
+        self.min_peers = Some(min_peers);
+        self
+    }
+
+    pub fn max_download_rate(mut self, max_download_rate: u32) -> ConfigBuilder {
+        self.max_download_rate = Some(max_download_rate);
+        self
+    }
+
+    /// Adds manual peers to connect to besides the ones found through the dns.
+    pub fn hosts(mut self, hosts: Vec<String>) -> ConfigBuilder {
+        self.hosts.extend(hosts);
+        self
+    }
+
+    pub fn user_agent(mut self, user_agent: String) -> ConfigBuilder {
+        self.user_agent = Some(user_agent);
+        self
+    }
+
+    pub fn services(mut self, services: u64) -> ConfigBuilder {
+        self.services = Some(services);
+        self
+    }
+
+    /// Asks the peers not to relay transactions and ignores the ones they announce.
+    pub fn blocksonly(mut self, blocksonly: bool) -> ConfigBuilder {
+        self.blocksonly = Some(blocksonly);
+        self
+    }
+
+    /// Dials the peers in the encrypted transport of BIP 324 and accepts it from them.
+    pub fn v2transport(mut self, v2transport: bool) -> ConfigBuilder {
+        self.v2transport = Some(v2transport);
+        self
+    }
+
+    /// Observes the network without broadcasting transactions nor serving data to the peers.
+    pub fn readonly(mut self, readonly: bool) -> ConfigBuilder {
+        self.readonly = Some(readonly);
+        self
+    }
+
+    /// Fee rate in sat/vB the transactions pay to get into the mempool, the peers are told
+    /// about it in a feefilter message.
+    pub fn min_relay_fee(mut self, min_relay_fee: f64) -> ConfigBuilder {
+        self.min_relay_fee = Some(min_relay_fee);
+        self
+    }
+
+    /// Network whose start string the messages carry, the peers of other networks are dropped.
+    /// It also picks the genesis block and the prefix of the addresses and keys.
+    pub fn network(mut self, network: Network) -> ConfigBuilder {
+        self.network = Some(network);
+        self
+    }
+
+    pub fn allowlist(mut self, allowlist: Vec<Subnet>) -> ConfigBuilder {
+        self.allowlist = allowlist;
+        self
+    }
+
+    pub fn denylist(mut self, denylist: Vec<Subnet>) -> ConfigBuilder {
+        self.denylist = denylist;
+        self
+    }
+
+    /// Runs the node on a synthetic chain instead of syncing with the peers.
+    pub fn simulation(mut self, simulation: SimConfig) -> ConfigBuilder {
+        self.simulation = Some(simulation);
+        self
+    }
+
+    /// Records the messages read from the peers to the file, see session::SessionRecorder.
+    pub fn record_session(mut self, record_session: String) -> ConfigBuilder {
+        self.record_session = Some(record_session);
+        self
+    }
+
+    /// Replays the messages of a recorded session instead of connecting to the peers.
+    pub fn replay_session(mut self, replay_session: String) -> ConfigBuilder {
+        self.replay_session = Some(replay_session);
         self
     }
 
     pub fn build(self) -> Result<Config, ConfigError> {
-        let endpoint = self
-            .dns
-            .ok_or_else(|| ConfigError::MissingFieldError("endpoint".to_string()))?;
+        if self.dns_seeds.is_empty() {
+            return Err(ConfigError::MissingFieldError("dns".to_string()));
+        }
 
         let port = self
             .port
@@ -156,55 +323,189 @@ impl ConfigBuilder {
             .ok_or_else(|| ConfigError::MissingFieldError("max_listen_peers".to_string()))?;
 
         Ok(Config {
-            endpoint,
+            dns_seeds: self.dns_seeds,
             port,
             tcp_timeout,
+            max_download_rate: self.max_download_rate,
+            connect_budget: self
+                .connect_budget
+                .unwrap_or(Duration::from_secs(DEFAULT_CONNECT_BUDGET)),
+            min_peers: self.min_peers.unwrap_or(DEFAULT_MIN_PEERS),
             blockchain_file,
             log_file,
+            log_destination: self.log_destination.unwrap_or_default(),
+            datadir: self.datadir,
             block_downloading_timestamp,
             block_downloading_threads,
             max_listen_peers,
-            host: self.host,
+            inbound_limits: InboundLimits {
+                max_inbound: self.max_inbound.unwrap_or(DEFAULT_MAX_INBOUND),
+                max_per_ip: self
+                    .max_inbound_per_ip
+                    .unwrap_or(DEFAULT_MAX_INBOUND_PER_IP),
+            },
+            hosts: self.hosts,
+            peers_file: self
+                .peers_file
+                .unwrap_or_else(|| DEFAULT_PEERS_FILE.to_string()),
+            user_agent: self
+                .user_agent
+                .unwrap_or_else(|| DEFAULT_USER_AGENT.to_string()),
+            services: self.services.unwrap_or(0),
+            blocksonly: self.blocksonly.unwrap_or(false),
+            v2transport: self.v2transport.unwrap_or(false),
+            readonly: self.readonly.unwrap_or(false),
+            min_relay_fee: self.min_relay_fee.unwrap_or(DEFAULT_MIN_RELAY_FEE),
+            network: self.network.unwrap_or_default(),
+            peer_filter: PeerFilter::new(self.allowlist, self.denylist),
+            simulation: self.simulation,
+            record_session: self.record_session,
+            replay_session: self.replay_session,
+            reindex: false,
+            file: None,
         })
     }
 }
 
 #[derive(Debug)]
 pub struct Config {
-    pub endpoint: String,
+    pub dns_seeds: Vec<String>,
     pub port: u16,
     pub tcp_timeout: Duration,
+    /// Total time spent dialing peers at startup
+    pub connect_budget: Duration,
+    /// Peers that have to complete the handshake before the sync starts, the rest of the
+    /// addresses aren't dialed
+    pub min_peers: usize,
+    /// Bytes per second read from all of the peers together, no cap if None
+    pub max_download_rate: Option<u32>,
     pub blockchain_file: String,
     pub log_file: String,
+    /// Where the log is written, the log file by default
+    pub log_destination: LogDestination,
+    /// Directory with the blocks, wallets, logs and peers of the node, see DataDir.
+    /// The files are relative to the working directory if None
+    pub datadir: Option<String>,
     pub block_downloading_timestamp: u32,
     pub block_downloading_threads: usize,
     pub max_listen_peers: usize,
-    pub host: Option<String>,
+    /// Connections accepted by the listener, in total and from the same ip
+    pub inbound_limits: InboundLimits,
+    /// Manual peers, as `ip`, `ip:port` or `hostname:port`
+    pub hosts: Vec<String>,
+    /// Where the known peers are saved, used when every dns seed fails
+    pub peers_file: String,
+    pub user_agent: String,
+    /// Service bits advertised in our version message
+    pub services: u64,
+    /// Only the blocks are downloaded: our version message sets relay to 0 and the
+    /// transactions announced by the peers are ignored. The wallet ones are still sent
+    pub blocksonly: bool,
+    /// The peers are dialed in the encrypted transport, again in plaintext if they don't support
+    /// it, and NODE_P2P_V2 is advertised. Both transports are accepted from the inbound peers
+    pub v2transport: bool,
+    /// Pure observer: the transactions of the wallet and the peers are not broadcast, and the
+    /// getdata, getheaders and mempool messages are not answered. No service is advertised
+    pub readonly: bool,
+    /// Fee rate in sat/vB below which the transactions of the peers and of the wallet are
+    /// rejected, sent to the peers in a feefilter message. 0 accepts every fee
+    pub min_relay_fee: f64,
+    /// Network of the peers, testnet unless the file says otherwise
+    pub network: Network,
+    /// Peers the node may dial or accept
+    pub peer_filter: PeerFilter,
+    /// Synthetic chain the node runs on, without peers nor files, see sim::generate
+    pub simulation: Option<SimConfig>,
+    /// Session file where the messages read from the peers are recorded
+    pub record_session: Option<String>,
+    /// Session file fed to the message handlers instead of connecting to the peers.
+    /// The node has to start from the blockchain file the session was recorded with
+    pub replay_session: Option<String>,
+    /// Checks the blockchain file again and rebuilds what is derived from it, set by the
+    /// --reindex option of the command line
+    pub reindex: bool,
+    /// File the configuration was read from, read again by Node::reload_config
+    pub file: Option<String>,
+}
+
+/// Settings that can change while the node runs, taken again from the configuration
+/// file without restarting the sync.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tunables {
+    pub log_destination: LogDestination,
+    pub max_listen_peers: usize,
+    pub max_download_rate: Option<u32>,
+}
+
+impl Tunables {
+    /// Describes the settings that differ in `new`, one line each.
+    pub fn changes(&self, new: &Tunables) -> Vec<String> {
+        let mut changes = vec![];
+        if self.log_destination != new.log_destination {
+            changes.push(format!(
+                "log_destination: {:?} -> {:?}",
+                self.log_destination, new.log_destination
+            ));
+        }
+        if self.max_listen_peers != new.max_listen_peers {
+            changes.push(format!(
+                "max_listen_peers: {} -> {}",
+                self.max_listen_peers, new.max_listen_peers
+            ));
+        }
+        if self.max_download_rate != new.max_download_rate {
+            changes.push(format!(
+                "max_download_rate: {:?} -> {:?}",
+                self.max_download_rate, new.max_download_rate
+            ));
+        }
+        changes
+    }
 }
 
 const SEPARATOR: char = '=';
+// Prefix of the environment variables that override the keys of the file, as BTC_NODE_PORT
+const ENV_PREFIX: &str = "BTC_NODE_";
 
 impl Config {
+    /// Reads the configuration file, the BTC_NODE_ environment variables override its keys.
     pub fn new(config_file_path: &String) -> Result<Config, ConfigError> {
-        let mut builder = ConfigBuilder::new();
         let file = File::open(config_file_path)?;
-        let reader = BufReader::new(file);
-
-        for line in reader.lines() {
-            let line = line?.to_lowercase();
+        let mut settings = vec![];
+        for line in BufReader::new(file).lines() {
+            let line = line?;
             let parts: Vec<&str> = line.splitn(2, SEPARATOR).collect();
 
             if parts.len() < 2 {
                 continue;
             }
 
-            let value = match parts[1].split_whitespace().next() {
-                None => continue,
-                Some(i) => i,
-            };
+            if let Some(value) = normalize_value(parts[1]) {
+                settings.push((parts[0].to_lowercase(), value));
+            }
+        }
+
+        let mut config = Config::from_settings(override_with(settings, env::vars()))?;
+        config.file = Some(config_file_path.clone());
+        Ok(config)
+    }
+
+    /// Takes every key from the BTC_NODE_ environment variables, for running without a file.
+    pub fn from_env() -> Result<Config, ConfigError> {
+        Config::from_settings(override_with(vec![], env::vars()))
+    }
 
-            builder = match parts[0] {
-                "dns" => builder.dns(value.to_string()),
+    /// Builds the configuration from the `key`, `value` pairs, in order.
+    fn from_settings(settings: Vec<(String, String)>) -> Result<Config, ConfigError> {
+        let mut builder = ConfigBuilder::new();
+        // Any of the sim_ keys turns the simulation on, the missing ones take their default
+        let mut simulation: Option<SimConfig> = None;
+
+        for (key, value) in settings {
+            let value = value.as_str();
+            builder = match key.as_str() {
+                "dns" => builder.dns_seeds(split_list(value)),
+                "peers_file" => builder.peers_file(value.to_string()),
                 "port" => {
                     let port = u16::from_str_radix(value, 10)
                         .map_err(|_| ConfigError::ParsingError("port".to_string()))?;
@@ -215,8 +516,37 @@ impl Config {
                         .map_err(|_| ConfigError::ParsingError("tcp_timeout".to_string()))?;
                     builder.tcp_timeout(Duration::from_secs(duration))
                 }
+                "max_download_rate" => {
+                    let rate = value
+                        .parse::<u32>()
+                        .map_err(|_| ConfigError::ParsingError("max_download_rate".to_string()))?;
+                    builder.max_download_rate(rate)
+                }
+                "connect_budget" => {
+                    let budget = value
+                        .parse::<u64>()
+                        .map_err(|_| ConfigError::ParsingError("connect_budget".to_string()))?;
+                    builder.connect_budget(Duration::from_secs(budget))
+                }
+                "min_peers" => {
+                    let peers = value
+                        .parse::<usize>()
+                        .ok()
+                        .filter(|peers| *peers > 0)
+                        .ok_or_else(|| ConfigError::ParsingError("min_peers".to_string()))?;
+                    builder.min_peers(peers)
+                }
                 "blockchain_file" => builder.blockchain_file(value.to_string()),
                 "log_file" => builder.log_file(value.to_string()),
+                "log_destination" => {
+                    let destination = value
+                        .parse::<LogDestination>()
+                        .map_err(|_| ConfigError::ParsingError("log_destination".to_string()))?;
+                    builder.log_destination(destination)
+                }
+                "datadir" => builder.datadir(value.to_string()),
+                "record_session" => builder.record_session(value.to_string()),
+                "replay_session" => builder.replay_session(value.to_string()),
                 "block_downloading_timestamp" => {
                     let timestamp = u32::from_str_radix(value, 10).map_err(|_| {
                         ConfigError::ParsingError("block_downloading_timestamp".to_string())
@@ -234,13 +564,182 @@ impl Config {
                         .map_err(|_| ConfigError::ParsingError("max_listen_peers".to_string()))?;
                     builder.max_listen_peers(peers)
                 }
-                "host" => builder.host(value.to_string()),
+                "max_inbound" => {
+                    let peers = value
+                        .parse::<usize>()
+                        .map_err(|_| ConfigError::ParsingError("max_inbound".to_string()))?;
+                    builder.max_inbound(peers)
+                }
+                "max_inbound_per_ip" => {
+                    let peers = value
+                        .parse::<usize>()
+                        .map_err(|_| ConfigError::ParsingError("max_inbound_per_ip".to_string()))?;
+                    builder.max_inbound_per_ip(peers)
+                }
+                "host" => builder.hosts(split_list(value)),
+                "user_agent" => builder.user_agent(value.to_string()),
+                "services" => {
+                    let services = match value.strip_prefix("0x") {
+                        Some(hex) => u64::from_str_radix(hex, 16),
+                        None => value.parse::<u64>(),
+                    }
+                    .map_err(|_| ConfigError::ParsingError("services".to_string()))?;
+                    builder.services(services)
+                }
+                "blocksonly" => match value {
+                    "1" | "true" => builder.blocksonly(true),
+                    "0" | "false" => builder.blocksonly(false),
+                    _ => return Err(ConfigError::ParsingError("blocksonly".to_string())),
+                },
+                "network" => match Network::from_name(value) {
+                    Some(network) => builder.network(network),
+                    None => return Err(ConfigError::ParsingError("network".to_string())),
+                },
+                "v2transport" => match value {
+                    "1" | "true" => builder.v2transport(true),
+                    "0" | "false" => builder.v2transport(false),
+                    _ => return Err(ConfigError::ParsingError("v2transport".to_string())),
+                },
+                "readonly" => match value {
+                    "1" | "true" => builder.readonly(true),
+                    "0" | "false" => builder.readonly(false),
+                    _ => return Err(ConfigError::ParsingError("readonly".to_string())),
+                },
+                "min_relay_fee" => {
+                    let rate = value
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|rate| rate.is_finite() && *rate >= 0.0)
+                        .ok_or_else(|| ConfigError::ParsingError("min_relay_fee".to_string()))?;
+                    builder.min_relay_fee(rate)
+                }
+                "allowlist" => builder.allowlist(PeerFilter::parse_list(value)?),
+                "denylist" => builder.denylist(PeerFilter::parse_list(value)?),
+                "sim_seed" => {
+                    simulation.get_or_insert_with(SimConfig::default).seed =
+                        value
+                            .parse::<u64>()
+                            .map_err(|_| ConfigError::ParsingError("sim_seed".to_string()))?;
+                    builder
+                }
+                "sim_height" => {
+                    simulation.get_or_insert_with(SimConfig::default).height = value
+                        .parse::<usize>()
+                        .map_err(|_| ConfigError::ParsingError("sim_height".to_string()))?;
+                    builder
+                }
+                "sim_txs_per_block" => {
+                    simulation
+                        .get_or_insert_with(SimConfig::default)
+                        .txs_per_block = value
+                        .parse::<usize>()
+                        .map_err(|_| ConfigError::ParsingError("sim_txs_per_block".to_string()))?;
+                    builder
+                }
+                "sim_wallet_ratio" => {
+                    let ratio = value
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|ratio| (0.0..=1.0).contains(ratio))
+                        .ok_or_else(|| ConfigError::ParsingError("sim_wallet_ratio".to_string()))?;
+                    simulation
+                        .get_or_insert_with(SimConfig::default)
+                        .wallet_ratio = ratio;
+                    builder
+                }
                 _ => {
                     continue;
                 }
             }
         }
 
+        if let Some(simulation) = simulation {
+            builder = builder.simulation(simulation);
+        }
         builder.build()
     }
+
+    pub fn tunables(&self) -> Tunables {
+        Tunables {
+            log_destination: self.log_destination,
+            max_listen_peers: self.max_listen_peers,
+            max_download_rate: self.max_download_rate,
+        }
+    }
+}
+
+/// Replaces the settings of the keys that have a BTC_NODE_ variable, the variable name
+/// without the prefix and in lowercase is the key. A repeated key, like dns, is replaced whole.
+fn override_with(
+    mut settings: Vec<(String, String)>,
+    vars: impl Iterator<Item = (String, String)>,
+) -> Vec<(String, String)> {
+    for (name, value) in vars {
+        let key = match name.strip_prefix(ENV_PREFIX) {
+            Some(key) => key.to_lowercase(),
+            None => continue,
+        };
+        let value = match normalize_value(&value) {
+            Some(value) => value,
+            None => continue,
+        };
+        settings.retain(|(other, _)| *other != key);
+        settings.push((key, value));
+    }
+    settings
+}
+
+/// Value of a setting in lowercase, None if it is empty. What follows the value is a comment.
+fn normalize_value(value: &str) -> Option<String> {
+    value
+        .split_whitespace()
+        .next()
+        .map(|value| value.to_lowercase())
+}
+
+/// Splits a comma separated value of the configuration file.
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .filter(|item| !item.is_empty())
+        .map(|item| item.to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setting(key: &str, value: &str) -> (String, String) {
+        (key.to_string(), value.to_string())
+    }
+
+    #[test]
+    fn test_environment_overrides_the_file() {
+        let file = vec![
+            setting("dns", "seed1"),
+            setting("dns", "seed2"),
+            setting("port", "18333"),
+            setting("tcp_timeout", "5"),
+            setting("blockchain_file", "blockchain"),
+            setting("log_file", "logs"),
+            setting("block_downloading_timestamp", "1689470631"),
+            setting("block_downloading_threads", "1"),
+            setting("max_listen_peers", "1"),
+        ];
+        let vars = vec![
+            setting("BTC_NODE_PORT", "18444"),
+            setting("BTC_NODE_DNS", "SEED3 # the new seed"),
+            setting("BTC_NODE_MAX_DOWNLOAD_RATE", " 1000 "),
+            setting("BTC_NODE_LOG_FILE", ""),
+            setting("HOME", "/root"),
+        ];
+
+        let config = Config::from_settings(override_with(file, vars.into_iter())).unwrap();
+        assert_eq!(config.port, 18444);
+        assert_eq!(config.dns_seeds, vec!["seed3".to_string()]);
+        assert_eq!(config.max_download_rate, Some(1000));
+        assert_eq!(config.log_file, "logs");
+        assert_eq!(config.tcp_timeout, Duration::from_secs(5));
+    }
 }
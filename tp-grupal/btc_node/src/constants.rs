@@ -2,18 +2,161 @@ pub const PATH_CONFIG: &str = "config/node.conf";
 
 // TESTNET header start string (magic string)
 pub const START_STRING: [u8; 4] = [11, 17, 9, 7];
+pub const MAINNET_START_STRING: [u8; 4] = [0xf9, 0xbe, 0xb4, 0xd9];
+
+pub const MESSAGE_HEADER_SIZE: usize = 24;
+pub const MAX_PAYLOAD_SIZE: u32 = 32 * 1024 * 1024;
+pub const MAX_BLOCK_SIZE: u32 = 4_000_000;
+
+pub const PROTOCOL_VERSION: i32 = 70015;
+pub const DEFAULT_USER_AGENT: &str = "/rustico:0.1/";
+pub const DEFAULT_PEERS_FILE: &str = "peers.dat";
+// Seconds spent dialing peers at startup
+pub const DEFAULT_CONNECT_BUDGET: u64 = 30;
+// Peers that have to complete the handshake before the dialing stops and the sync starts
+pub const DEFAULT_MIN_PEERS: usize = 8;
+// Connections accepted by the listener, in total and from the same ip
+pub const DEFAULT_MAX_INBOUND: usize = 16;
+pub const DEFAULT_MAX_INBOUND_PER_IP: usize = 4;
+// Times an unreachable address is dialed at startup
+pub const DIAL_ATTEMPTS: u32 = 3;
+// Seconds before dialing an unreachable address again, doubled after every failure
+pub const DIAL_BACKOFF_BASE: u64 = 1;
+pub const DIAL_BACKOFF_MAX: u64 = 8;
+// Peers that don't support sendheaders and pong nonces are dropped after the version message
+pub const MIN_PEER_PROTOCOL_VERSION: i32 = 70012;
+// First protocol version that supports the feefilter message (BIP133)
+pub const FEE_FILTER_VERSION: i32 = 70013;
+
+// Service bits advertised in the version message
+pub const NODE_NETWORK: u64 = 1;
+pub const NODE_NETWORK_LIMITED: u64 = 1 << 10;
+pub const NODE_P2P_V2: u64 = 1 << 11;
+// Last blocks the NODE_NETWORK_LIMITED peers serve at least (BIP159)
+pub const NODE_NETWORK_LIMITED_BLOCKS: usize = 288;
+
+// Per peer limits of the messages read once the node is listening
+pub const MAX_MESSAGES_PER_SECOND: u32 = 500;
+pub const MAX_BYTES_PER_SECOND: u32 = 8 * 1024 * 1024;
+// Misbehavior points after which a peer is disconnected, and the points of each offense
+pub const BAN_THRESHOLD: u32 = 100;
+pub const INVALID_BLOCK_SCORE: u32 = 100;
+pub const INVALID_HEADERS_SCORE: u32 = 20;
+pub const MALFORMED_MESSAGE_SCORE: u32 = 10;
+// Seconds a peer has to take a queued message before its writer thread gives up
+pub const PEER_WRITE_TIMEOUT: u64 = 30;
+// Seconds a peer has to complete the handshake
+pub const HANDSHAKE_TIMEOUT: u64 = 30;
+// Seconds without messages from a listened peer before it is pinged
+pub const PEER_INACTIVITY_TIMEOUT: u64 = 20 * 60;
+// Seconds a pinged peer has to send any message before it is disconnected
+pub const PING_TIMEOUT: u64 = 2 * 60;
+// Seconds between the checks of the inactive peers
+pub const PEER_CHECK_INTERVAL: u64 = 30;
+// Seconds before an item requested with getdata can be requested again to another peer
+pub const GETDATA_TIMEOUT: u64 = 60;
+// Txids remembered by the recently rejected and recently confirmed filters
+pub const RECENT_TXIDS_CAPACITY: usize = 50000;
+// Peers that have to report the same address before it is advertised as ours
+pub const LOCAL_ADDRESS_MIN_VOTES: usize = 2;
+// Seconds before an unconfirmed wallet transaction is announced again, doubled after every announcement
+pub const REBROADCAST_INITIAL_INTERVAL: u64 = 5 * 60;
+pub const REBROADCAST_MAX_INTERVAL: u64 = 2 * 60 * 60;
+// Seconds between the checks of the rebroadcast schedule
+pub const REBROADCAST_TICK: u64 = 30;
+// Seconds between the recomputations of the wallet balances from the utxo set
+pub const BALANCE_REFRESH_INTERVAL: u64 = 60;
+// Seconds between the checks of the deadlines of the payment requests
+pub const PAYMENT_REQUEST_CHECK_INTERVAL: u64 = 30;
+// Mean milliseconds between the inv announcing the new transactions to the peers
+pub const TX_TRICKLE_INTERVAL: u64 = 5000;
+
+// Blocks between the difficulty adjustments and the seconds they should take
+pub const DIFFICULTY_ADJUSTMENT_INTERVAL: usize = 2016;
+pub const TARGET_TIMESPAN: u32 = 14 * 24 * 60 * 60;
+pub const TARGET_SPACING: u32 = 10 * 60;
+// Easiest target, in compact form. The test network accepts it for blocks found 20 minutes after the previous one
+pub const POW_LIMIT_BITS: u32 = 0x1d00ffff;
+// Satoshis created by the first blocks, halved every SUBSIDY_HALVING_INTERVAL blocks
+pub const INITIAL_SUBSIDY: i64 = 50 * 100_000_000;
+pub const SUBSIDY_HALVING_INTERVAL: i32 = 210_000;
+pub const MAX_BLOCK_WEIGHT: usize = 4_000_000;
+// Weight left out of the block templates for the coinbase
+pub const COINBASE_RESERVED_WEIGHT: usize = 4000;
 
 //Gensis block
 //Contains the hash value of the bitcoin test network:
 pub const GENESIS_BLOCK_HASH_VALUE: &str =
     "000000000933ea01ad0ee984209779baaec3ced90fa3f408719526f8d77f4943";
+//And the one of the main network:
+pub const MAINNET_GENESIS_BLOCK_HASH_VALUE: &str =
+    "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f";
 pub const GENESIS_BLOCK_MERKLE_ROOT_HASH_VALUE: &str =
     "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b";
 
+// Blocks checked by the reindex between its progress events
+pub const REINDEX_PROGRESS_INTERVAL: usize = 10_000;
+// Added to the name of the copy of a damaged blockchain file, kept to look into the damage
+pub const DAMAGED_FILE_SUFFIX: &str = ".damaged";
+
 pub const BLOCK_DOWNLOADING_START_TIMESTAMP: u32 = 1680318000; // 1/4/2023
 
 pub const P2PKH_BYTE: u8 = 0x6f;
 pub const P2SH_BYTE: u8 = 0xc4;
+pub const WIF_BYTE: u8 = 0xef;
 
 pub const SIGHASH_ALL: u8 = 1u8;
+pub const SIGHASH_NONE: u8 = 2;
+pub const SIGHASH_SINGLE: u8 = 3;
+pub const SIGHASH_ANYONECANPAY: u8 = 0x80;
 pub const TX_VERSION: i32 = 1;
+// Input sequences: final, final but enforcing the lock time, and replaceable (BIP 125)
+pub const FINAL_SEQUENCE: u32 = 0xffffffff;
+pub const LOCKTIME_SEQUENCE: u32 = 0xfffffffe;
+pub const RBF_SEQUENCE: u32 = 0xfffffffd;
+// Lock times below this are block heights, the rest are unix timestamps
+pub const LOCKTIME_THRESHOLD: u32 = 500_000_000;
+// Biggest OP_RETURN payload relayed by the nodes
+pub const MAX_OP_RETURN_SIZE: usize = 80;
+// Weight units per byte outside of the witnesses (BIP 141), the witness bytes weigh one each
+pub const WITNESS_SCALE_FACTOR: usize = 4;
+// Heaviest transaction relayed by the nodes
+pub const MAX_STANDARD_TX_WEIGHT: usize = 400_000;
+// Smallest P2PKH output relayed by the nodes, smaller ones cost more to spend than they are worth
+pub const DUST_LIMIT: i64 = 546;
+// Fee rate in sat/vB the transactions pay to get into the mempool, the one of Bitcoin Core
+pub const DEFAULT_MIN_RELAY_FEE: f64 = 1.0;
+// Longest chains of unconfirmed transactions relayed by the nodes: the transactions of a chain
+// counting the last one and their virtual size, looking at its ancestors and at the descendants
+// of each of them
+pub const MAX_MEMPOOL_ANCESTORS: usize = 25;
+pub const MAX_MEMPOOL_ANCESTORS_VSIZE: usize = 101_000;
+pub const MAX_MEMPOOL_DESCENDANTS: usize = 25;
+pub const MAX_MEMPOOL_DESCENDANTS_VSIZE: usize = 101_000;
+// Lower bounds in sat/vB of the buckets of the mempool fee histogram
+pub const FEE_HISTOGRAM_BOUNDS: [f64; 12] = [
+    1.0, 2.0, 3.0, 5.0, 8.0, 10.0, 15.0, 20.0, 30.0, 50.0, 100.0, 200.0,
+];
+// Transactions received by an address before the wallet is warned about reusing it
+pub const ADDRESS_REUSE_WARNING: usize = 5;
+
+pub const SIGNATURE_CACHE_SIZE: usize = 50000;
+
+// Blocks behind the best height of the peers that still count as synced, about a day of them
+pub const IBD_MAX_BLOCKS_BEHIND: i32 = 144;
+
+// Seconds between the reports of the block download progress to the wallet
+pub const LOADING_REPORT_INTERVAL: u64 = 1;
+
+// Blocks used for the averages and fee totals of the chain statistics (about a day)
+pub const STATS_WINDOW: usize = 144;
+// Blocks whose size and fees are kept for the charts and the fee estimation (about a day)
+pub const BLOCK_STATS_CAPACITY: usize = 144;
+// Last blocks whose cheapest fee rates are a floor for the suggested fee
+pub const FEE_ESTIMATE_BLOCKS: usize = 6;
+// Seconds between the node stats sent to the wallet
+pub const STATS_INTERVAL: u64 = 10;
+// Seconds between the summaries of the ignored and dropped messages written to the log
+pub const IGNORED_SUMMARY_INTERVAL: u64 = 10 * 60;
+// Blocks requested per getdata while downloading, the download can only be paused between batches
+pub const BLOCK_DOWNLOAD_BATCH: usize = 16;
@@ -0,0 +1,108 @@
+use std::{
+    fs::{self, File, OpenOptions, TryLockError},
+    path::{Path, PathBuf},
+};
+
+use crate::{config::Config, protocol_error::ProtocolError};
+
+// Locked by the node that uses the data directory, the lock goes away with the process
+const LOCK_FILE: &str = ".lock";
+pub const BLOCKS_DIR: &str = "blocks";
+// Reserved for the utxo set, it is rebuilt from the blocks for now
+pub const CHAINSTATE_DIR: &str = "chainstate";
+pub const WALLETS_DIR: &str = "wallets";
+pub const LOGS_DIR: &str = "logs";
+
+/// Data directory of the node, with the blocks, chainstate, wallets and logs directories and the
+/// peers file. The directory is locked while the value lives, so two nodes can't use it at once.
+#[derive(Debug)]
+pub struct DataDir {
+    root: PathBuf,
+    _lock: File,
+}
+
+impl DataDir {
+    /// Creates the layout of the directory if it is missing and takes its lock.
+    pub fn open(root: impl AsRef<Path>) -> Result<DataDir, ProtocolError> {
+        let root = root.as_ref().to_path_buf();
+        for dir in [BLOCKS_DIR, CHAINSTATE_DIR, WALLETS_DIR, LOGS_DIR] {
+            fs::create_dir_all(root.join(dir))?;
+        }
+
+        let lock = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(false)
+            .open(root.join(LOCK_FILE))?;
+        match lock.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                return Err(ProtocolError::Error(format!(
+                    "The data directory {} is in use by another node",
+                    root.display()
+                )))
+            }
+            Err(TryLockError::Error(e)) => return Err(e.into()),
+        }
+
+        Ok(DataDir { root, _lock: lock })
+    }
+
+    /// Path of a file in a directory of the layout, absolute paths are left as they are.
+    pub fn resolve(&self, dir: &str, file: &str) -> String {
+        if Path::new(file).is_absolute() {
+            return file.to_string();
+        }
+        self.root
+            .join(dir)
+            .join(file)
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    /// Empties the chainstate directory, its contents are built again from the blocks.
+    pub fn wipe_chainstate(&self) -> Result<(), ProtocolError> {
+        let chainstate = self.root.join(CHAINSTATE_DIR);
+        fs::remove_dir_all(&chainstate)?;
+        fs::create_dir_all(&chainstate)?;
+        Ok(())
+    }
+
+    /// Moves the files of the configuration into the directory.
+    pub fn apply(&self, config: &mut Config) {
+        config.blockchain_file = self.resolve(BLOCKS_DIR, &config.blockchain_file);
+        config.log_file = self.resolve(LOGS_DIR, &config.log_file);
+        config.peers_file = self.resolve("", &config.peers_file);
+        for session in [&mut config.record_session, &mut config.replay_session]
+            .into_iter()
+            .flatten()
+        {
+            *session = self.resolve(LOGS_DIR, session);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_a_second_node_cant_open_the_directory() {
+        let root = std::env::temp_dir().join(format!("datadir_test_{}", std::process::id()));
+
+        let datadir = DataDir::open(&root).unwrap();
+        assert!(root.join(BLOCKS_DIR).is_dir());
+        assert!(root.join(CHAINSTATE_DIR).is_dir());
+        assert!(DataDir::open(&root).is_err());
+
+        assert_eq!(
+            datadir.resolve(BLOCKS_DIR, "blockchain"),
+            root.join("blocks/blockchain").to_string_lossy()
+        );
+        assert_eq!(datadir.resolve(LOGS_DIR, "/var/log/node"), "/var/log/node");
+
+        drop(datadir);
+        assert!(DataDir::open(&root).is_ok());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,125 @@
+use std::{
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+use rand::Rng;
+
+/// Address to dial, how many times it failed and when it can be dialed again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DialEntry {
+    pub addr: SocketAddr,
+    pub failures: u32,
+    pub due: Instant,
+}
+
+/// Order in which the addresses are dialed at startup. An address that can't be reached
+/// is tried again later, the wait doubles after every failure, up to `max_backoff`, and
+/// is shortened by a random jitter so the retries of many addresses don't line up.
+#[derive(Debug)]
+pub struct DialSchedule {
+    entries: Vec<DialEntry>,
+    max_attempts: u32,
+    base_backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl DialSchedule {
+    /// Every address is due now, in the given order.
+    pub fn new(
+        addrs: &[SocketAddr],
+        now: Instant,
+        max_attempts: u32,
+        base_backoff: Duration,
+        max_backoff: Duration,
+    ) -> DialSchedule {
+        let entries = addrs
+            .iter()
+            .map(|addr| DialEntry {
+                addr: *addr,
+                failures: 0,
+                due: now,
+            })
+            .collect();
+        DialSchedule {
+            entries,
+            max_attempts,
+            base_backoff,
+            max_backoff,
+        }
+    }
+
+    /// Takes the address that is due first, the first added one among the ties.
+    pub fn pop(&mut self) -> Option<DialEntry> {
+        let (i, _) = self
+            .entries
+            .iter()
+            .enumerate()
+            .min_by_key(|(i, entry)| (entry.due, *i))?;
+        Some(self.entries.remove(i))
+    }
+
+    /// Schedules another attempt of the address that failed, unless it used all of them.
+    /// Returns when it is due.
+    pub fn retry(&mut self, entry: DialEntry, now: Instant, rng: &mut impl Rng) -> Option<Instant> {
+        let failures = entry.failures + 1;
+        if failures >= self.max_attempts {
+            return None;
+        }
+        let due = now + self.backoff(failures, rng);
+        self.entries.push(DialEntry {
+            addr: entry.addr,
+            failures,
+            due,
+        });
+        Some(due)
+    }
+
+    /// Wait after the failures, between half and all of the exponential backoff.
+    fn backoff(&self, failures: u32, rng: &mut impl Rng) -> Duration {
+        let exponential = self
+            .base_backoff
+            .saturating_mul(1 << (failures - 1).min(16))
+            .min(self.max_backoff);
+        exponential.mul_f64(rng.gen_range(0.5..=1.0))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_failed_addresses_back_off_until_out_of_attempts() {
+        let first: SocketAddr = "10.0.0.1:18333".parse().unwrap();
+        let second: SocketAddr = "10.0.0.2:18333".parse().unwrap();
+        let base = Duration::from_secs(1);
+        let start = Instant::now();
+        let mut schedule = DialSchedule::new(&[first, second], start, 3, base, base * 3);
+        let mut rng = rand::thread_rng();
+
+        let entry = schedule.pop().unwrap();
+        assert_eq!(entry.addr, first);
+        let due = schedule.retry(entry, start, &mut rng).unwrap();
+        assert!(due >= start + base / 2 && due <= start + base);
+
+        // The second address is due before the retry of the first one
+        assert_eq!(schedule.pop().unwrap().addr, second);
+        let entry = schedule.pop().unwrap();
+        assert_eq!((entry.addr, entry.failures), (first, 1));
+
+        let due = schedule.retry(entry, start, &mut rng).unwrap();
+        assert!(due >= start + base && due <= start + base * 2);
+        let entry = schedule.pop().unwrap();
+        assert!(schedule.retry(entry, start, &mut rng).is_none());
+        assert!(schedule.is_empty());
+    }
+}
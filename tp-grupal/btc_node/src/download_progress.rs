@@ -0,0 +1,186 @@
+use std::{
+    net::SocketAddr,
+    sync::mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    time::{Duration, Instant},
+};
+
+/// What a download worker tells the reporter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WorkerProgress {
+    Block,
+    Finished,
+    Failed,
+}
+
+/// Blocks downloaded from a peer during the block download.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerDownload {
+    pub peer: SocketAddr,
+    pub downloaded: usize,
+    pub total: usize,
+    /// The worker of the peer stopped before downloading all of its blocks
+    pub failed: bool,
+}
+
+/// Channel end of a worker, the index is its position in the peers of the reporter.
+#[derive(Debug, Clone)]
+pub struct WorkerSender {
+    worker: usize,
+    sender: Sender<(usize, WorkerProgress)>,
+}
+
+impl WorkerSender {
+    /// A reporter that is gone isn't an error of the download.
+    pub fn send(&self, progress: WorkerProgress) {
+        let _ = self.sender.send((self.worker, progress));
+    }
+}
+
+/// Gathers the progress of the download workers, each with its own sender.
+/// The reporter ends once every sender is dropped, so a worker that fails or panics can't keep
+/// it waiting.
+#[derive(Debug)]
+pub struct ProgressReporter {
+    downloads: Vec<PeerDownload>,
+    /// Workers that sent Finished, the others failed even if they panicked without saying it
+    finished: Vec<bool>,
+    receiver: Receiver<(usize, WorkerProgress)>,
+    interval: Duration,
+}
+
+impl ProgressReporter {
+    /// Reporter of the peers with the blocks each has to download, and the senders of their
+    /// workers in the same order.
+    pub fn new(
+        peers: Vec<(SocketAddr, usize)>,
+        interval: Duration,
+    ) -> (ProgressReporter, Vec<WorkerSender>) {
+        let (sender, receiver) = mpsc::channel();
+        let peers_len = peers.len();
+        let senders = (0..peers_len)
+            .map(|worker| WorkerSender {
+                worker,
+                sender: sender.clone(),
+            })
+            .collect();
+        let downloads = peers
+            .into_iter()
+            .map(|(peer, total)| PeerDownload {
+                peer,
+                downloaded: 0,
+                total,
+                failed: false,
+            })
+            .collect();
+
+        let reporter = ProgressReporter {
+            finished: vec![false; peers_len],
+            downloads,
+            receiver,
+            interval,
+        };
+        (reporter, senders)
+    }
+
+    /// Fraction of all the blocks downloaded.
+    pub fn fraction(&self) -> f64 {
+        let total: usize = self.downloads.iter().map(|d| d.total).sum();
+        if total == 0 {
+            return 1.0;
+        }
+        let downloaded: usize = self.downloads.iter().map(|d| d.downloaded).sum();
+        downloaded as f64 / total as f64
+    }
+
+    /// Waits for the workers, calling `report` at most once per interval while there is progress
+    /// and once more at the end, so a slow consumer isn't flooded with updates.
+    /// Returns the downloads of every peer.
+    pub fn run(mut self, mut report: impl FnMut(f64, &[PeerDownload])) -> Vec<PeerDownload> {
+        let mut changed = false;
+        let mut next_report = Instant::now() + self.interval;
+        loop {
+            let timeout = next_report.saturating_duration_since(Instant::now());
+            match self.receiver.recv_timeout(timeout) {
+                Ok((worker, progress)) => {
+                    self.update(worker, progress);
+                    changed = true;
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+
+            if Instant::now() >= next_report {
+                if changed {
+                    report(self.fraction(), &self.downloads);
+                    changed = false;
+                }
+                next_report = Instant::now() + self.interval;
+            }
+        }
+
+        for (download, finished) in self.downloads.iter_mut().zip(self.finished.iter()) {
+            download.failed |= !finished;
+        }
+        report(self.fraction(), &self.downloads);
+        self.downloads
+    }
+
+    fn update(&mut self, worker: usize, progress: WorkerProgress) {
+        let download = match self.downloads.get_mut(worker) {
+            Some(download) => download,
+            None => return,
+        };
+        match progress {
+            WorkerProgress::Block => download.downloaded += 1,
+            WorkerProgress::Finished => self.finished[worker] = true,
+            WorkerProgress::Failed => download.failed = true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_the_reporter_ends_when_the_workers_fail_or_panic() {
+        let peers = vec![
+            ("10.0.0.1:18333".parse().unwrap(), 3),
+            ("10.0.0.2:18333".parse().unwrap(), 2),
+            ("10.0.0.3:18333".parse().unwrap(), 2),
+        ];
+        let (reporter, mut senders) = ProgressReporter::new(peers, Duration::from_millis(10));
+
+        let panicking = senders.pop().unwrap();
+        let failing = senders.pop().unwrap();
+        let finishing = senders.pop().unwrap();
+        let workers = vec![
+            thread::spawn(move || {
+                for _ in 0..3 {
+                    finishing.send(WorkerProgress::Block);
+                }
+                finishing.send(WorkerProgress::Finished);
+            }),
+            thread::spawn(move || {
+                failing.send(WorkerProgress::Block);
+                failing.send(WorkerProgress::Failed);
+            }),
+            thread::spawn(move || {
+                panicking.send(WorkerProgress::Block);
+                panic!("worker panicked");
+            }),
+        ];
+
+        let mut reports = vec![];
+        let downloads = reporter.run(|fraction, _| reports.push(fraction));
+        for worker in workers {
+            let _ = worker.join();
+        }
+
+        let downloaded: Vec<(usize, bool)> =
+            downloads.iter().map(|d| (d.downloaded, d.failed)).collect();
+        assert_eq!(downloaded, vec![(3, false), (1, true), (1, true)]);
+        assert_eq!(reports.last(), Some(&(5.0 / 7.0)));
+    }
+}
@@ -0,0 +1,72 @@
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+
+use crate::constants::IBD_MAX_BLOCKS_BEHIND;
+
+/// Whether the node is in its initial block download, far behind the best height announced by
+/// the peers in their version messages. The node starts in it, and leaves it for good once the
+/// chain gets close to that height, as falling behind later is handled block by block.
+#[derive(Debug)]
+pub struct IbdState {
+    active: AtomicBool,
+    best_peer_height: AtomicI32,
+}
+
+impl Default for IbdState {
+    fn default() -> Self {
+        IbdState::new()
+    }
+}
+
+impl IbdState {
+    pub fn new() -> IbdState {
+        IbdState {
+            active: AtomicBool::new(true),
+            best_peer_height: AtomicI32::new(0),
+        }
+    }
+
+    /// Start height of the version message of a peer.
+    pub fn peer_height(&self, height: i32) {
+        self.best_peer_height.fetch_max(height, Ordering::Relaxed);
+    }
+
+    pub fn best_peer_height(&self) -> i32 {
+        self.best_peer_height.load(Ordering::Relaxed)
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active.load(Ordering::Relaxed)
+    }
+
+    /// Leaves the initial block download if the chain at `height` is close to the best height of
+    /// the peers. True only for the call that left it.
+    pub fn update(&self, height: i32) -> bool {
+        if height + IBD_MAX_BLOCKS_BEHIND < self.best_peer_height() {
+            return false;
+        }
+        self.active.swap(false, Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_the_download_ends_once_close_to_the_best_peer() {
+        let ibd = IbdState::new();
+        ibd.peer_height(1_000);
+        ibd.peer_height(400);
+        assert_eq!(ibd.best_peer_height(), 1_000);
+
+        assert!(!ibd.update(500));
+        assert!(ibd.is_active());
+        assert!(ibd.update(1_000 - IBD_MAX_BLOCKS_BEHIND));
+        assert!(!ibd.is_active());
+
+        // A peer far ahead doesn't bring the node back to it
+        ibd.peer_height(5_000);
+        assert!(!ibd.update(1_000));
+        assert!(!ibd.is_active());
+    }
+}
@@ -0,0 +1,72 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+/// Blocks and transactions requested with getdata that didn't arrive yet, with the time of each request.
+/// Once a request is older than the timeout, the next peer that announces the item is asked for it.
+#[derive(Debug)]
+pub struct InFlight {
+    requests: HashMap<[u8; 32], Instant>,
+    timeout: Duration,
+}
+
+impl InFlight {
+    pub fn new(timeout: Duration) -> InFlight {
+        InFlight {
+            requests: HashMap::new(),
+            timeout,
+        }
+    }
+
+    /// Records the request of `hash` and returns true if nobody is already being asked for it.
+    pub fn try_request(&mut self, hash: [u8; 32], now: Instant) -> bool {
+        if let Some(requested_at) = self.requests.get(&hash) {
+            if now.duration_since(*requested_at) < self.timeout {
+                return false;
+            }
+        }
+        self.requests.insert(hash, now);
+        true
+    }
+
+    pub fn received(&mut self, hash: &[u8; 32]) {
+        self.requests.remove(hash);
+    }
+
+    /// Forgets the timed out requests, so the map doesn't grow with items that never arrive.
+    pub fn expire(&mut self, now: Instant) {
+        let timeout = self.timeout;
+        self.requests
+            .retain(|_, requested_at| now.duration_since(*requested_at) < timeout);
+    }
+
+    pub fn len(&self) -> usize {
+        self.requests.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.requests.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_items_are_requested_once_until_they_time_out() {
+        let mut in_flight = InFlight::new(Duration::from_secs(60));
+        let now = Instant::now();
+
+        assert!(in_flight.try_request([1; 32], now));
+        assert!(!in_flight.try_request([1; 32], now + Duration::from_secs(10)));
+        assert!(in_flight.try_request([1; 32], now + Duration::from_secs(61)));
+
+        in_flight.received(&[1; 32]);
+        assert!(in_flight.try_request([1; 32], now + Duration::from_secs(62)));
+
+        in_flight.expire(now + Duration::from_secs(200));
+        assert!(in_flight.is_empty());
+    }
+}
@@ -0,0 +1,254 @@
+use std::{error::Error, fmt};
+
+use bitcoin_hashes::{sha256d, Hash};
+use rand::{rngs::OsRng, RngCore};
+use secp256k1::{PublicKey, Secp256k1, SecretKey};
+
+use crate::{address::Network, protocol_error::ProtocolError, utils::hash160};
+
+// Suffix of the WIF of a private key whose public key is compressed
+const COMPRESSED_KEY_FLAG: u8 = 0x01;
+// Version byte, 32 bytes of key and 4 of checksum, the compressed keys add their flag
+const WIF_LEN: usize = 37;
+
+/// Reasons a WIF isn't a private key of the network.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WifError {
+    InvalidCharacters,
+    InvalidLength(usize),
+    InvalidChecksum,
+    /// The version byte is the one of another known network
+    WrongNetwork(Network),
+    UnknownVersion(u8),
+    /// The byte after the key isn't the flag of the compressed keys
+    InvalidCompressionFlag(u8),
+    /// The key is zero or not below the curve order
+    InvalidKey,
+}
+
+impl Error for WifError {}
+
+impl fmt::Display for WifError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WifError::InvalidCharacters => write!(f, "Private key has invalid characters"),
+            WifError::InvalidLength(len) => {
+                write!(f, "Private key has an invalid length: {} bytes", len)
+            }
+            WifError::InvalidChecksum => write!(f, "Private key has invalid checksum"),
+            WifError::WrongNetwork(network) => {
+                write!(f, "Private key is for another network: {}", network.name())
+            }
+            WifError::UnknownVersion(byte) => {
+                write!(f, "Private key has an unknown prefix: {:#04x}", byte)
+            }
+            WifError::InvalidCompressionFlag(byte) => {
+                write!(
+                    f,
+                    "Private key has an invalid compression flag: {:#04x}",
+                    byte
+                )
+            }
+            WifError::InvalidKey => write!(f, "Private key is out of range"),
+        }
+    }
+}
+
+/// Private key decoded from a WIF, which tells whether its address hashes the compressed or
+/// the uncompressed public key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PrivateKey {
+    pub secret_key: SecretKey,
+    pub compressed: bool,
+    pub network: Network,
+}
+
+impl PrivateKey {
+    /// Decodes and checks the WIF, its version byte has to be the one of `network`.
+    pub fn from_wif(wif: &str, network: Network) -> Result<PrivateKey, WifError> {
+        let decoded = bs58::decode(wif)
+            .into_vec()
+            .map_err(|_| WifError::InvalidCharacters)?;
+        let len = decoded.len();
+        if len != WIF_LEN && len != WIF_LEN + 1 {
+            return Err(WifError::InvalidLength(len));
+        }
+        let checksum = &sha256d::Hash::hash(&decoded[..len - 4]).to_byte_array()[0..4];
+        if checksum != &decoded[len - 4..] {
+            return Err(WifError::InvalidChecksum);
+        }
+
+        if decoded[0] != network.wif_byte() {
+            let other = Network::ALL
+                .into_iter()
+                .find(|other| other.wif_byte() == decoded[0]);
+            return Err(match other {
+                Some(other) => WifError::WrongNetwork(other),
+                None => WifError::UnknownVersion(decoded[0]),
+            });
+        }
+        let compressed = len == WIF_LEN + 1;
+        if compressed && decoded[33] != COMPRESSED_KEY_FLAG {
+            return Err(WifError::InvalidCompressionFlag(decoded[33]));
+        }
+
+        let secret_key =
+            SecretKey::from_slice(&decoded[1..33]).map_err(|_| WifError::InvalidKey)?;
+        Ok(PrivateKey {
+            secret_key,
+            compressed,
+            network,
+        })
+    }
+
+    /// Public key in the format the WIF asks for, the one the signatures carry.
+    pub fn public_key(&self) -> Vec<u8> {
+        let secp = Secp256k1::signing_only();
+        let public_key = PublicKey::from_secret_key(&secp, &self.secret_key);
+        match self.compressed {
+            true => public_key.serialize().to_vec(),
+            false => public_key.serialize_uncompressed().to_vec(),
+        }
+    }
+
+    pub fn pkhash(&self) -> [u8; 20] {
+        hash160(&self.public_key())
+    }
+
+    /// P2PKH address of the key.
+    pub fn address(&self) -> String {
+        base58check([&[self.network.p2pkh_byte()], &self.pkhash()[..]].concat())
+    }
+}
+
+/// Private key in WIF and the P2PKH address of its compressed public key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NewKey {
+    pub wif: String,
+    pub address: String,
+}
+
+/// Generates a fresh keypair with the operating system random number generator.
+pub fn generate_key(network: Network) -> NewKey {
+    let mut secret = [0u8; 32];
+    loop {
+        OsRng.fill_bytes(&mut secret);
+        // Almost every 32 bytes are a valid key, the others are out of the curve order
+        if let Ok(key) = key_from_secret(&secret, network) {
+            return key;
+        }
+    }
+}
+
+/// Encodes the secret as a WIF and derives its address, with the version bytes of the network.
+pub fn key_from_secret(secret: &[u8; 32], network: Network) -> Result<NewKey, ProtocolError> {
+    let secp = Secp256k1::signing_only();
+    let secret_key = SecretKey::from_slice(secret)
+        .map_err(|_| ProtocolError::Error("Invalid private key".to_string()))?;
+    let public_key = PublicKey::from_secret_key(&secp, &secret_key).serialize();
+
+    let wif = [&[network.wif_byte()], &secret[..], &[COMPRESSED_KEY_FLAG]].concat();
+    let address = [&[network.p2pkh_byte()], &hash160(&public_key)[..]].concat();
+
+    Ok(NewKey {
+        wif: base58check(wif),
+        address: base58check(address),
+    })
+}
+
+fn base58check(mut bytes: Vec<u8>) -> String {
+    let checksum = sha256d::Hash::hash(&bytes).to_byte_array();
+    bytes.extend_from_slice(&checksum[0..4]);
+    bs58::encode(bytes).into_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{address::validate_address, utils::wif_to_bitcoin_address};
+
+    #[test]
+    fn test_known_secret_gives_its_wif_and_address() {
+        let wif = "cSnB7AwCEDKrdq1x2XmHu8f1BHPh6KeuBjeXgssDe2cMpeGDM7oB";
+        let secret = PrivateKey::from_wif(wif, Network::Testnet)
+            .unwrap()
+            .secret_key
+            .secret_bytes();
+
+        let key = key_from_secret(&secret, Network::Testnet).unwrap();
+        assert_eq!(key.wif, wif);
+        assert_eq!(key.address, wif_to_bitcoin_address(wif).unwrap());
+
+        let mainnet = key_from_secret(&secret, Network::Mainnet).unwrap();
+        assert!(mainnet.address.starts_with('1'));
+        assert!(mainnet.wif.starts_with('K') || mainnet.wif.starts_with('L'));
+        assert!(key_from_secret(&[0; 32], Network::Testnet).is_err());
+    }
+
+    #[test]
+    fn test_generated_keys_are_fresh_and_valid() {
+        let key = generate_key(Network::Testnet);
+        assert!(validate_address(&key.address).is_ok());
+        assert_eq!(wif_to_bitcoin_address(&key.wif).unwrap(), key.address);
+        assert_ne!(generate_key(Network::Testnet), key);
+    }
+
+    #[test]
+    fn test_wif_tells_the_network_and_the_compression() {
+        let uncompressed = PrivateKey::from_wif(
+            "91avARGdfge8E4tZfYLoxeJ5sGBdNJQH4kvjJoQFacbgwmaKkrx",
+            Network::Testnet,
+        )
+        .unwrap();
+        let compressed = PrivateKey::from_wif(
+            "cMahea7zqjxrtgAbB7LSGbcQUr1uX1ojuat9jZodMN87JcbXMTcA",
+            Network::Testnet,
+        )
+        .unwrap();
+        assert_eq!(uncompressed.secret_key, compressed.secret_key);
+        assert!(!uncompressed.compressed && compressed.compressed);
+        assert_eq!(uncompressed.public_key().len(), 65);
+        assert_eq!(uncompressed.address(), "mtoKs9V381UAhUia3d7Vb9GNak8Qvmcsme");
+        assert_eq!(compressed.address(), "mrCDrCybB6J1vRfbwM5hemdJz73FwDBC8r");
+
+        let mainnet = "5HpHagT65TZzG1PH3CSu63k8DbpvD8s5ip4nEB3kEsreAnchuDf";
+        assert_eq!(
+            PrivateKey::from_wif(mainnet, Network::Mainnet)
+                .unwrap()
+                .address(),
+            "1EHNa6Q4Jz2uvNExL497mE43ikXhwF6kZm"
+        );
+    }
+
+    #[test]
+    fn test_invalid_wifs_explain_the_problem() {
+        let error = |wif: &str| PrivateKey::from_wif(wif, Network::Testnet).unwrap_err();
+        assert_eq!(
+            error("5HpHagT65TZzG1PH3CSu63k8DbpvD8s5ip4nEB3kEsreAnchuDf"),
+            WifError::WrongNetwork(Network::Mainnet)
+        );
+        assert_eq!(
+            error("cMahea7zqjxrtgAbB7LSGbcQUr1uX1ojuat9jZodMN87JcbXMTcB"),
+            WifError::InvalidChecksum
+        );
+        assert_eq!(
+            error("cMahea7zqjxrtgAbB7LSGbcQUr1uX1ojuat9jZodMN87JcgKTQmd"),
+            WifError::InvalidCompressionFlag(0x02)
+        );
+        assert_eq!(error("cMahea0"), WifError::InvalidCharacters);
+        assert_eq!(
+            error(&base58check(vec![Network::Testnet.wif_byte(); 10])),
+            WifError::InvalidLength(14)
+        );
+        assert_eq!(
+            error(&base58check([&[0x12], &[1; 32][..]].concat())),
+            WifError::UnknownVersion(0x12)
+        );
+        assert_eq!(
+            error(&base58check(
+                [&[Network::Testnet.wif_byte()], &[0; 32][..]].concat()
+            )),
+            WifError::InvalidKey
+        );
+    }
+}
@@ -1,18 +1,48 @@
+pub mod address;
 pub mod bitcoin_node;
 pub mod block_header;
+pub mod block_template;
 pub mod blockchain;
+pub mod coin_control;
 
 pub mod api;
+pub mod bandwidth;
 pub mod config;
 pub mod constants;
+pub mod datadir;
+pub mod dial_schedule;
+pub mod download_progress;
+pub mod ibd;
+pub mod in_flight;
+pub mod keys;
+pub mod local_address;
 pub mod log_file;
+pub mod mempool;
 pub mod merkle_tree;
 pub mod message;
-mod message_handlers;
 pub mod message_header;
+pub mod ownership_proof;
+pub mod payment_requests;
+pub mod peer;
+pub mod peer_discovery;
+pub mod peer_filter;
+pub mod peer_writer;
 pub mod protocol_error;
+pub mod rate_limiter;
 pub mod raw_transaction;
+pub mod rebroadcast;
+pub mod recent_txids;
 pub mod register;
 pub mod script;
+pub mod session;
+pub mod signature_cache;
+pub mod sim;
+pub mod sync_control;
+pub mod sync_state;
+pub mod transport;
 pub mod utils;
+pub mod wallet_effects;
+pub mod wallet_file;
 mod wallet_handlers;
+pub mod wallet_warnings;
+pub mod worker_pool;
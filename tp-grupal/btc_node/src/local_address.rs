@@ -0,0 +1,131 @@
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv6Addr},
+};
+
+use crate::constants::LOCAL_ADDRESS_MIN_VOTES;
+
+/// Our external address as the peers see it, the addr_recv of their version messages.
+/// Every peer ip has a single vote, its last report, so one peer reconnecting can't choose
+/// the address the node advertises.
+#[derive(Debug, Default)]
+pub struct LocalAddress {
+    reports: HashMap<IpAddr, Ipv6Addr>,
+}
+
+impl LocalAddress {
+    pub fn new() -> LocalAddress {
+        LocalAddress::default()
+    }
+
+    /// Counts the address `peer` reported, the ones that can't be reached from the internet
+    /// are ignored. Returns whether the best address changed.
+    pub fn record(&mut self, peer: IpAddr, reported: Ipv6Addr) -> bool {
+        if !is_routable(&reported) {
+            return false;
+        }
+
+        let before = self.best();
+        self.reports.insert(peer, reported);
+        self.best() != before
+    }
+
+    /// Address reported by the most peers, once LOCAL_ADDRESS_MIN_VOTES of them agree.
+    /// The ties go to the lowest address, so it doesn't change with the order of the reports.
+    pub fn best(&self) -> Option<Ipv6Addr> {
+        let mut votes: HashMap<Ipv6Addr, usize> = HashMap::new();
+        for reported in self.reports.values() {
+            *votes.entry(*reported).or_default() += 1;
+        }
+
+        votes
+            .into_iter()
+            .filter(|(_, count)| *count >= LOCAL_ADDRESS_MIN_VOTES)
+            .max_by(|(a, a_count), (b, b_count)| a_count.cmp(b_count).then(b.cmp(a)))
+            .map(|(ip, _)| ip)
+    }
+}
+
+/// Whether the address can be reached from the internet: it isn't loopback, private,
+/// link local nor unspecified.
+pub fn is_routable(ip: &Ipv6Addr) -> bool {
+    match ip.to_ipv4_mapped() {
+        Some(v4) => {
+            !(v4.is_unspecified()
+                || v4.is_loopback()
+                || v4.is_private()
+                || v4.is_link_local()
+                || v4.is_broadcast()
+                || v4.is_documentation())
+        }
+        None => {
+            let first = ip.segments()[0];
+            // Unique local (fc00::/7) and link local (fe80::/10) addresses
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || first & 0xfe00 == 0xfc00
+                || first & 0xffc0 == 0xfe80)
+        }
+    }
+}
+
+/// The address as it is shown to the user, ipv4 instead of mapped ipv6.
+pub fn display_ip(ip: &Ipv6Addr) -> String {
+    match ip.to_ipv4_mapped() {
+        Some(v4) => v4.to_string(),
+        None => ip.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    fn v4(a: u8, b: u8, c: u8, d: u8) -> Ipv6Addr {
+        Ipv4Addr::new(a, b, c, d).to_ipv6_mapped()
+    }
+
+    fn peer(n: u8) -> IpAddr {
+        IpAddr::V4(Ipv4Addr::new(203, 0, 113, n))
+    }
+
+    #[test]
+    fn test_the_address_most_peers_report_wins() {
+        let mut local = LocalAddress::new();
+        assert!(!local.record(peer(1), v4(8, 8, 4, 4)));
+        assert_eq!(local.best(), None);
+
+        assert!(local.record(peer(2), v4(8, 8, 4, 4)));
+        assert_eq!(local.best(), Some(v4(8, 8, 4, 4)));
+
+        // The same peer only has its last vote
+        local.record(peer(3), v4(1, 1, 1, 1));
+        local.record(peer(3), v4(1, 1, 1, 1));
+        assert_eq!(local.best(), Some(v4(8, 8, 4, 4)));
+
+        local.record(peer(4), v4(1, 1, 1, 1));
+        assert_eq!(local.best(), Some(v4(1, 1, 1, 1)));
+        assert!(!local.record(peer(5), v4(1, 1, 1, 1)));
+    }
+
+    #[test]
+    fn test_unreachable_addresses_are_ignored() {
+        let mut local = LocalAddress::new();
+        for (n, ip) in [
+            v4(127, 0, 0, 1),
+            v4(192, 168, 0, 10),
+            v4(0, 0, 0, 0),
+            Ipv6Addr::LOCALHOST,
+            "fe80::1".parse().unwrap(),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            local.record(peer(n as u8), ip);
+            local.record(peer(n as u8 + 100), ip);
+        }
+        assert_eq!(local.best(), None);
+        assert!(is_routable(&"2001:4860::8888".parse().unwrap()));
+    }
+}
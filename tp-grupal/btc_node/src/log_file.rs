@@ -1,47 +1,159 @@
 use chrono::Utc;
 
-use std::{fs::OpenOptions, io::Write};
+use std::{
+    collections::VecDeque,
+    fs::OpenOptions,
+    io::{self, Write},
+    str::FromStr,
+    sync::{Arc, Mutex, OnceLock, PoisonError, RwLock},
+};
 
-#[derive(Debug)]
+// Last lines kept in memory for the log page of the wallet
+const RECENT_LINES: usize = 1000;
+
+/// Where the lines of the logger are written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogDestination {
+    #[default]
+    File,
+    Stdout,
+    Both,
+}
+
+impl LogDestination {
+    fn to_file(self) -> bool {
+        self != LogDestination::Stdout
+    }
+
+    fn to_stdout(self) -> bool {
+        self != LogDestination::File
+    }
+}
+
+impl FromStr for LogDestination {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "file" => Ok(LogDestination::File),
+            "stdout" => Ok(LogDestination::Stdout),
+            "both" => Ok(LogDestination::Both),
+            _ => Err(format!("Unknown log destination: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogLevel {
+    Info,
+    Error,
+}
+
+/// Line of the log, numbered so the wallet can ask for the ones after the last it has.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogLine {
+    pub seq: u64,
+    pub time: String,
+    pub level: LogLevel,
+    pub message: String,
+}
+
+#[derive(Debug, Default)]
+struct RecentLines {
+    lines: VecDeque<LogLine>,
+    next_seq: u64,
+}
+
+/// The clones share the destination, so changing it moves all of them, and the recent lines.
+#[derive(Debug, Clone)]
 pub struct Logger {
     filepath: String,
+    destination: Arc<RwLock<LogDestination>>,
+    recent: Arc<Mutex<RecentLines>>,
 }
 
 impl Logger {
-    pub fn new(filepath: String) -> Logger {
-        if let Err(e) = OpenOptions::new()
-            .create(true)
-            .write(true)
-            .truncate(true)
-            .open(&filepath)
-        {
-            println!("ERROR OPENING LOGFILE: {}", e);
-        };
+    pub fn new(filepath: String, destination: LogDestination) -> Logger {
+        if destination.to_file() {
+            if let Err(e) = OpenOptions::new()
+                .create(true)
+                .write(true)
+                .truncate(true)
+                .open(&filepath)
+            {
+                eprintln!("ERROR OPENING LOGFILE: {}", e);
+            };
+        }
+
+        Logger {
+            filepath,
+            destination: Arc::new(RwLock::new(destination)),
+            recent: Arc::new(Mutex::new(RecentLines::default())),
+        }
+    }
+
+    /// Writes the next lines to the new destination, the file is appended to.
+    pub fn set_destination(&self, destination: LogDestination) {
+        *self
+            .destination
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = destination;
+    }
 
-        Logger { filepath }
+    fn destination(&self) -> LogDestination {
+        *self
+            .destination
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
     }
 
     pub fn log(&self, message: String) {
-        let res = OpenOptions::new()
-            .append(true)
-            .create(true)
-            .open(&self.filepath);
+        self.write(LogLevel::Info, &message);
+    }
 
-        let mut file = match res {
-            Ok(f) => f,
-            Err(e) => {
-                eprintln!("LOGGING ERROR: Couldn't open the log file: {}", e);
-                return;
-            }
-        };
+    pub fn log_error(&self, error: String) {
+        self.write(LogLevel::Error, &format!("ERROR: {}", error));
+    }
 
-        let msg = format!("{}: {}", Utc::now().format("%y-%m-%d %H:%M:%S"), message);
-        if let Err(e) = writeln!(file, "{}", msg) {
-            eprintln!("LOGGING ERROR: Couldn't write to file: {}", e);
+    /// The recent lines numbered after `seq`, oldest first.
+    pub fn lines_after(&self, seq: u64) -> Vec<LogLine> {
+        let recent = self.recent.lock().unwrap_or_else(PoisonError::into_inner);
+        recent
+            .lines
+            .iter()
+            .filter(|line| line.seq > seq)
+            .cloned()
+            .collect()
+    }
+
+    fn remember(&self, time: String, level: LogLevel, message: &str) {
+        let mut recent = self.recent.lock().unwrap_or_else(PoisonError::into_inner);
+        // The first line is 1, so asking for the lines after 0 returns all of them
+        recent.next_seq += 1;
+        let seq = recent.next_seq;
+        recent.lines.push_back(LogLine {
+            seq,
+            time,
+            level,
+            message: message.to_string(),
+        });
+        if recent.lines.len() > RECENT_LINES {
+            recent.lines.pop_front();
         }
     }
 
-    pub fn log_error(&self, error: String) {
+    fn write(&self, level: LogLevel, message: &str) {
+        let time = Utc::now().format("%y-%m-%d %H:%M:%S").to_string();
+        let line = format!("{}: {}", time, message);
+        self.remember(time, level, message);
+        let destination = self.destination();
+        if destination.to_stdout() {
+            let _ = writeln!(io::stdout(), "{}", line);
+        }
+        if !destination.to_file() {
+            return;
+        }
+
         let res = OpenOptions::new()
             .append(true)
             .create(true)
@@ -55,9 +167,96 @@ impl Logger {
             }
         };
 
-        let msg = format!("ERROR: {}", error);
-        if let Err(e) = writeln!(file, "{}", msg) {
+        if let Err(e) = writeln!(file, "{}", line) {
             eprintln!("LOGGING ERROR: Couldn't write to file: {}", e);
         }
     }
 }
+
+static NODE_LOGGER: OnceLock<Logger> = OnceLock::new();
+
+/// Sets the logger used by `log` and `log_error`. It can only be set once.
+pub fn set_node_logger(logger: Logger) {
+    let _ = NODE_LOGGER.set(logger);
+}
+
+/// Changes the destination of the node logger and of its clones.
+pub fn set_node_log_destination(destination: LogDestination) {
+    if let Some(logger) = NODE_LOGGER.get() {
+        logger.set_destination(destination);
+    }
+}
+
+/// Logs a diagnostic of the node, to stderr while no logger is set.
+pub fn log(message: String) {
+    match NODE_LOGGER.get() {
+        Some(logger) => logger.log(message),
+        None => eprintln!("{}", message),
+    }
+}
+
+pub fn log_error(error: String) {
+    match NODE_LOGGER.get() {
+        Some(logger) => logger.log_error(error),
+        None => eprintln!("ERROR: {}", error),
+    }
+}
+
+/// The recent lines of the node logger numbered after `seq`, none while no logger is set.
+pub fn node_log_lines_after(seq: u64) -> Vec<LogLine> {
+    match NODE_LOGGER.get() {
+        Some(logger) => logger.lines_after(seq),
+        None => vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stdout_destination_does_not_touch_the_file() {
+        let filepath = std::env::temp_dir()
+            .join(format!("log_file_test_{}", std::process::id()))
+            .to_string_lossy()
+            .into_owned();
+
+        Logger::new(filepath.clone(), LogDestination::Stdout).log("line".to_string());
+        assert!(std::fs::metadata(&filepath).is_err());
+
+        Logger::new(filepath.clone(), LogDestination::Both).log("line".to_string());
+        let logged = std::fs::read_to_string(&filepath).unwrap();
+        assert!(logged.ends_with(": line\n"));
+        std::fs::remove_file(&filepath).unwrap();
+
+        let logger = Logger::new(filepath.clone(), LogDestination::Stdout);
+        logger.clone().set_destination(LogDestination::File);
+        logger.log("moved".to_string());
+        let logged = std::fs::read_to_string(&filepath).unwrap();
+        assert!(logged.ends_with(": moved\n"));
+        std::fs::remove_file(&filepath).unwrap();
+
+        assert_eq!("both".parse(), Ok(LogDestination::Both));
+        assert!("syslog".parse::<LogDestination>().is_err());
+    }
+
+    #[test]
+    fn test_recent_lines_are_kept_in_memory() {
+        let logger = Logger::new(String::new(), LogDestination::Stdout);
+        logger.log("first".to_string());
+        logger.clone().log_error("second".to_string());
+
+        let lines = logger.lines_after(0);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1].level, LogLevel::Error);
+        assert_eq!(lines[1].message, "ERROR: second");
+        assert_eq!(logger.lines_after(lines[0].seq), lines[1..].to_vec());
+
+        for i in 0..RECENT_LINES {
+            logger.log(i.to_string());
+        }
+        let lines = logger.lines_after(0);
+        assert_eq!(lines.len(), RECENT_LINES);
+        assert_eq!(lines[0].message, "0");
+    }
+}
@@ -0,0 +1,515 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use chrono::Utc;
+
+use crate::{
+    blockchain::{stats::BlockStats, utxo_set::Output},
+    constants::{
+        FEE_ESTIMATE_BLOCKS, FEE_HISTOGRAM_BOUNDS, MAX_BLOCK_WEIGHT, MAX_MEMPOOL_ANCESTORS,
+        MAX_MEMPOOL_ANCESTORS_VSIZE, MAX_MEMPOOL_DESCENDANTS, MAX_MEMPOOL_DESCENDANTS_VSIZE,
+        WITNESS_SCALE_FACTOR,
+    },
+    protocol_error::ProtocolError,
+    raw_transaction::RawTransaction,
+    utils::bytes_to_hex_string,
+};
+
+/// A transaction waiting to be mined, with the time it got to the node.
+/// The transaction is shared with the handlers that read it and its bytes are relayed as is.
+#[derive(Debug, Clone)]
+pub struct MempoolEntry {
+    pub tx: Arc<RawTransaction>,
+    pub bytes: Arc<[u8]>,
+    pub time: u32,
+}
+
+/// Transactions that are not in a block yet, keyed by txid.
+/// Every change bumps `version`, so readers can tell if the contents changed since they last looked.
+#[derive(Debug, Default)]
+pub struct Mempool {
+    entries: HashMap<[u8; 32], MempoolEntry>,
+    version: u64,
+}
+
+impl Mempool {
+    pub fn new() -> Mempool {
+        Mempool::default()
+    }
+
+    /// Serializes the transaction once, returns the shared copy kept in the mempool.
+    pub fn insert(&mut self, txid: [u8; 32], tx: RawTransaction) -> Arc<RawTransaction> {
+        let time = Utc::now().timestamp() as u32;
+        let tx = Arc::new(tx);
+        let entry = MempoolEntry {
+            tx: Arc::clone(&tx),
+            bytes: Arc::from(tx.to_bytes()),
+            time,
+        };
+        self.entries.insert(txid, entry);
+        self.version += 1;
+        tx
+    }
+
+    pub fn remove(&mut self, txid: &[u8; 32]) -> Option<Arc<RawTransaction>> {
+        let entry = self.entries.remove(txid)?;
+        self.version += 1;
+        Some(entry.tx)
+    }
+
+    pub fn contains_key(&self, txid: &[u8; 32]) -> bool {
+        self.entries.contains_key(txid)
+    }
+
+    pub fn get(&self, txid: &[u8; 32]) -> Option<&Arc<RawTransaction>> {
+        self.entries.get(txid).map(|entry| &entry.tx)
+    }
+
+    /// The serialized transaction, to relay it without serializing it again.
+    pub fn get_bytes(&self, txid: &[u8; 32]) -> Option<Arc<[u8]>> {
+        self.entries.get(txid).map(|entry| Arc::clone(&entry.bytes))
+    }
+
+    pub fn get_entry(&self, txid: &[u8; 32]) -> Option<&MempoolEntry> {
+        self.entries.get(txid)
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &[u8; 32]> {
+        self.entries.keys()
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &Arc<RawTransaction>> {
+        self.entries.values().map(|entry| &entry.tx)
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = (&[u8; 32], &MempoolEntry)> {
+        self.entries.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// Checks that adding the transaction keeps the chains of unconfirmed transactions within
+    /// the ancestor and descendant limits of the nodes, which wouldn't relay it otherwise.
+    pub fn check_chain_limits(&self, tx: &RawTransaction) -> Result<(), ProtocolError> {
+        let ancestors = self.ancestors(tx);
+        let vsize = tx.vsize();
+
+        let count = ancestors.len() + 1;
+        if count > MAX_MEMPOOL_ANCESTORS {
+            return Err(ProtocolError::Error(format!(
+                "Too long chain of unconfirmed transactions: {} with its ancestors, at most {}",
+                count, MAX_MEMPOOL_ANCESTORS
+            )));
+        }
+        let ancestors_vsize = vsize + self.total_vsize(&ancestors);
+        if ancestors_vsize > MAX_MEMPOOL_ANCESTORS_VSIZE {
+            return Err(ProtocolError::Error(format!(
+                "Too long chain of unconfirmed transactions: {} vB with its ancestors, at most {}",
+                ancestors_vsize, MAX_MEMPOOL_ANCESTORS_VSIZE
+            )));
+        }
+
+        for ancestor in ancestors.iter() {
+            let descendants = self.descendants(ancestor);
+            // The ancestor, its descendants and the new transaction
+            let count = descendants.len() + 2;
+            if count > MAX_MEMPOOL_DESCENDANTS {
+                return Err(ProtocolError::Error(format!(
+                    "Too long chain of unconfirmed transactions: {} descending from {}, at most {}",
+                    count,
+                    bytes_to_hex_string(ancestor),
+                    MAX_MEMPOOL_DESCENDANTS
+                )));
+            }
+            let descendants_vsize = vsize + self.total_vsize(&descendants) + self.vsize(ancestor);
+            if descendants_vsize > MAX_MEMPOOL_DESCENDANTS_VSIZE {
+                return Err(ProtocolError::Error(format!(
+                    "Too long chain of unconfirmed transactions: {} vB descending from {}, at most {}",
+                    descendants_vsize,
+                    bytes_to_hex_string(ancestor),
+                    MAX_MEMPOOL_DESCENDANTS_VSIZE
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Mempool transactions that the transaction spends from, directly or through others.
+    fn ancestors(&self, tx: &RawTransaction) -> HashSet<[u8; 32]> {
+        let mut ancestors = HashSet::new();
+        let mut to_visit: Vec<[u8; 32]> = tx
+            .tx_in
+            .iter()
+            .map(|txin| txin.previous_output.hash)
+            .collect();
+        while let Some(txid) = to_visit.pop() {
+            let parent = match self.get(&txid) {
+                Some(parent) => parent,
+                None => continue,
+            };
+            if ancestors.insert(txid) {
+                to_visit.extend(parent.tx_in.iter().map(|txin| txin.previous_output.hash));
+            }
+        }
+        ancestors
+    }
+
+    /// Mempool transactions that spend from the txid, directly or through others.
+    fn descendants(&self, txid: &[u8; 32]) -> HashSet<[u8; 32]> {
+        let mut descendants = HashSet::new();
+        let mut to_visit = vec![*txid];
+        while let Some(parent) = to_visit.pop() {
+            for (child_id, entry) in self.entries.iter() {
+                let spends_parent = entry
+                    .tx
+                    .tx_in
+                    .iter()
+                    .any(|txin| txin.previous_output.hash == parent);
+                if spends_parent && descendants.insert(*child_id) {
+                    to_visit.push(*child_id);
+                }
+            }
+        }
+        descendants
+    }
+
+    fn vsize(&self, txid: &[u8; 32]) -> usize {
+        self.get(txid).map_or(0, |tx| tx.vsize())
+    }
+
+    fn total_vsize(&self, txids: &HashSet<[u8; 32]>) -> usize {
+        txids.iter().map(|txid| self.vsize(txid)).sum()
+    }
+
+    /// Whether a transaction of the mempool spends an output of the txid.
+    pub fn has_children(&self, txid: &[u8; 32]) -> bool {
+        self.values().any(|tx| {
+            tx.tx_in
+                .iter()
+                .any(|txin| txin.previous_output.hash == *txid)
+        })
+    }
+
+    /// Outputs of the given transactions that pay to the public key hash and that
+    /// no mempool transaction spends, with the txid of their transactions.
+    pub fn unspent_outputs(&self, txids: &[[u8; 32]], pkhash: &Vec<u8>) -> Vec<([u8; 32], Output)> {
+        let mut outputs = vec![];
+        for txid in txids {
+            let tx = match self.get(txid) {
+                Some(tx) => tx,
+                None => continue,
+            };
+            for (index, txout) in tx.tx_out.iter().enumerate() {
+                let output = Output::new(index as u32, txout.value, txout.pk_script.clone());
+                if output.pkscript.can_be_spent_by(pkhash) && !self.is_spent(txid, index as u32) {
+                    outputs.push((*txid, output));
+                }
+            }
+        }
+        outputs
+    }
+
+    fn is_spent(&self, txid: &[u8; 32], index: u32) -> bool {
+        self.values().any(|tx| {
+            tx.tx_in.iter().any(|txin| {
+                txin.previous_output.hash == *txid && txin.previous_output.index == index
+            })
+        })
+    }
+
+    /// Orders the txids so the mempool parents of a transaction come before it,
+    /// peers drop the transactions whose inputs they don't know yet.
+    pub fn parents_first(&self, txids: Vec<[u8; 32]>) -> Vec<[u8; 32]> {
+        let mut ordered: Vec<[u8; 32]> = Vec::with_capacity(txids.len());
+        let mut remaining = txids;
+        while !remaining.is_empty() {
+            let (ready, waiting): (Vec<[u8; 32]>, Vec<[u8; 32]>) =
+                remaining.iter().copied().partition(|txid| {
+                    !remaining
+                        .iter()
+                        .any(|other| other != txid && self.spends_from(txid, other))
+                });
+            if ready.is_empty() {
+                // Can't happen with valid transactions, a cycle needs a hash collision
+                ordered.extend(waiting);
+                break;
+            }
+            ordered.extend(ready);
+            remaining = waiting;
+        }
+        ordered
+    }
+
+    fn spends_from(&self, txid: &[u8; 32], parent: &[u8; 32]) -> bool {
+        self.get(txid).is_some_and(|tx| {
+            tx.tx_in
+                .iter()
+                .any(|txin| txin.previous_output.hash == *parent)
+        })
+    }
+}
+
+/// What the wallet shows about a mempool transaction.
+#[derive(Debug, Clone)]
+pub struct MempoolTxInfo {
+    pub txid: [u8; 32],
+    pub vsize: usize,
+    /// None when some previous output isn't in the utxo set or the mempool.
+    pub fee: Option<i64>,
+    pub time: u32,
+    /// Pays to or spends from one of the wallet addresses.
+    pub wallet_relevant: bool,
+    /// Spends outputs of the wallet, so its fee can be bumped.
+    pub own: bool,
+}
+
+impl MempoolTxInfo {
+    /// Fee in satoshis per virtual byte.
+    pub fn fee_rate(&self) -> Option<f64> {
+        self.fee.map(|fee| fee as f64 / self.vsize as f64)
+    }
+
+    /// Seconds since the transaction got to the node.
+    pub fn age(&self, now: u32) -> u32 {
+        now.saturating_sub(self.time)
+    }
+}
+
+/// Mempool transactions paying at least `min_rate` sat/vB, and less than the next bucket.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeeBucket {
+    pub min_rate: f64,
+    pub txs: usize,
+    pub vsize: usize,
+}
+
+/// Groups the transactions by fee rate in the FEE_HISTOGRAM_BOUNDS buckets, the ones paying
+/// less than the first bound or with an unknown fee are left out.
+pub fn fee_histogram(txs: &[MempoolTxInfo]) -> Vec<FeeBucket> {
+    let mut histogram: Vec<FeeBucket> = FEE_HISTOGRAM_BOUNDS
+        .iter()
+        .map(|min_rate| FeeBucket {
+            min_rate: *min_rate,
+            txs: 0,
+            vsize: 0,
+        })
+        .collect();
+
+    for tx in txs {
+        let rate = match tx.fee_rate() {
+            Some(rate) => rate,
+            None => continue,
+        };
+        if let Some(bucket) = histogram.iter_mut().rev().find(|b| rate >= b.min_rate) {
+            bucket.txs += 1;
+            bucket.vsize += tx.vsize;
+        }
+    }
+    histogram
+}
+
+/// Fee rate that gets a transaction in the next block: the lowest bucket that still fits in a
+/// block after the ones paying more. The lowest bound when the whole mempool fits in one.
+pub fn competitive_fee_rate(histogram: &[FeeBucket]) -> f64 {
+    let block_vsize = MAX_BLOCK_WEIGHT / WITNESS_SCALE_FACTOR;
+    let mut ahead = 0;
+    for (i, bucket) in histogram.iter().enumerate().rev() {
+        ahead += bucket.vsize;
+        if ahead >= block_vsize {
+            // Paying the bound of the next bucket goes ahead of this one
+            return histogram
+                .get(i + 1)
+                .map_or(bucket.min_rate, |next| next.min_rate);
+        }
+    }
+    histogram.first().map_or(1.0, |bucket| bucket.min_rate)
+}
+
+/// Suggested fee rate: the competitive one, but not below the median of the cheapest rates
+/// of the last FEE_ESTIMATE_BLOCKS blocks, in case the mempool of the node is missing some.
+pub fn estimate_fee_rate(histogram: &[FeeBucket], recent_blocks: &[BlockStats]) -> f64 {
+    let mut floors: Vec<f64> = recent_blocks
+        .iter()
+        .rev()
+        .take(FEE_ESTIMATE_BLOCKS)
+        .filter_map(|block| block.min_fee_rate)
+        .collect();
+    floors.sort_by(|a, b| a.total_cmp(b));
+
+    let rate = competitive_fee_rate(histogram);
+    match floors.get(floors.len() / 2) {
+        Some(floor) => rate.max(*floor),
+        None => rate,
+    }
+}
+
+/// Fails if the transaction pays less than `min_relay_fee` sat/vB, or if its fee is unknown
+/// because it spends outputs neither confirmed nor in the mempool.
+pub fn check_min_relay_fee(
+    fee: Option<i64>,
+    vsize: usize,
+    min_relay_fee: f64,
+) -> Result<(), ProtocolError> {
+    let fee = fee.ok_or_else(|| {
+        ProtocolError::Error(
+            "The fee is unknown, an input is neither confirmed nor in the mempool".to_string(),
+        )
+    })?;
+    let rate = fee as f64 / vsize.max(1) as f64;
+    if rate < min_relay_fee {
+        return Err(ProtocolError::Error(format!(
+            "The fee rate of {:.2} sat/vB is below the minimum relay fee of {} sat/vB",
+            rate, min_relay_fee
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        raw_transaction::{Outpoint, TxIn, TxOut},
+        script::PubKeyScript,
+    };
+
+    #[test]
+    fn test_the_competitive_fee_rate_skips_the_full_blocks() {
+        let info = |vsize: usize, fee: Option<i64>| MempoolTxInfo {
+            txid: [0; 32],
+            vsize,
+            fee,
+            time: 0,
+            wallet_relevant: false,
+            own: false,
+        };
+        // 10 sat/vB, 4 sat/vB, under the first bound and an unknown fee
+        let txs = vec![
+            info(100_000, Some(1_000_000)),
+            info(200_000, Some(800_000)),
+            info(100, Some(50)),
+            info(100, None),
+        ];
+
+        let histogram = fee_histogram(&txs);
+        assert_eq!(histogram.len(), FEE_HISTOGRAM_BOUNDS.len());
+        assert_eq!(histogram.iter().map(|b| b.txs).sum::<usize>(), 2);
+        let ten = histogram.iter().find(|b| b.min_rate == 10.0).unwrap();
+        assert_eq!((ten.txs, ten.vsize), (1, 100_000));
+        assert_eq!(competitive_fee_rate(&histogram), 1.0);
+
+        let full = fee_histogram(&[
+            info(900_000, Some(3_600_000)),
+            info(200_000, Some(1_000_000)),
+        ]);
+        assert_eq!(competitive_fee_rate(&full), 5.0);
+    }
+
+    #[test]
+    fn test_the_recent_blocks_are_a_floor_for_the_estimate() {
+        let block = |min_fee_rate: Option<f64>| BlockStats {
+            min_fee_rate,
+            ..Default::default()
+        };
+        let histogram = fee_histogram(&[]);
+        assert_eq!(estimate_fee_rate(&histogram, &[]), 1.0);
+
+        // Only the last blocks count, the old expensive one is left out
+        let mut recent = vec![block(Some(50.0))];
+        recent.extend((0..FEE_ESTIMATE_BLOCKS).map(|i| block(Some(i as f64 + 2.0))));
+        recent.push(block(None));
+        assert_eq!(estimate_fee_rate(&histogram, &recent), 5.0);
+    }
+
+    #[test]
+    fn test_transactions_below_the_min_relay_fee_are_rejected() {
+        assert!(check_min_relay_fee(Some(200), 200, 1.0).is_ok());
+        assert!(check_min_relay_fee(Some(199), 200, 1.0).is_err());
+        assert!(check_min_relay_fee(Some(0), 200, 0.0).is_ok());
+        assert!(check_min_relay_fee(None, 200, 0.0).is_err());
+    }
+
+    #[test]
+    fn test_changes_bump_the_version() {
+        let mut mempool = Mempool::new();
+        let tx = RawTransaction::new(vec![], vec![TxOut::new(10, vec![])]);
+        let txid = tx.get_tx_id();
+        let tx_bytes = tx.to_bytes();
+
+        mempool.insert(txid, tx);
+        assert_eq!(mempool.version(), 1);
+        assert!(mempool.contains_key(&txid));
+
+        assert!(mempool.remove(&[0; 32]).is_none());
+        assert_eq!(mempool.version(), 1);
+
+        assert_eq!(mempool.get_bytes(&txid).unwrap()[..], tx_bytes[..]);
+        assert!(mempool.remove(&txid).is_some());
+        assert_eq!(mempool.version(), 2);
+        assert!(mempool.is_empty());
+    }
+
+    fn spend(txid: [u8; 32], index: u32, pkhash: &[u8]) -> RawTransaction {
+        let input = TxIn::new(Outpoint::new(txid, index), vec![]);
+        let change = TxOut::new(500, PubKeyScript::P2PKH(pkhash.to_vec()).to_vec());
+        let payment = TxOut::new(1000, PubKeyScript::P2PKH(vec![9; 20]).to_vec());
+        RawTransaction::new(vec![input], vec![payment, change])
+    }
+
+    #[test]
+    fn test_chains_longer_than_the_limits_are_rejected() {
+        let pkhash = vec![1; 20];
+        let mut mempool = Mempool::new();
+        let mut last = spend([7; 32], 0, &pkhash);
+        for _ in 0..MAX_MEMPOOL_ANCESTORS {
+            assert!(mempool.check_chain_limits(&last).is_ok());
+            let txid = last.get_tx_id();
+            mempool.insert(txid, last);
+            last = spend(txid, 1, &pkhash);
+        }
+
+        let error = mempool.check_chain_limits(&last).unwrap_err();
+        assert!(matches!(error, ProtocolError::Error(e) if e.contains("ancestors")));
+        // A new chain is fine
+        assert!(mempool
+            .check_chain_limits(&spend([8; 32], 0, &pkhash))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_unspent_change_and_parents_first() {
+        let pkhash = vec![1; 20];
+        let mut mempool = Mempool::new();
+        let parent = spend([7; 32], 0, &pkhash);
+        let parent_id = parent.get_tx_id();
+        mempool.insert(parent_id, parent);
+
+        let change = mempool.unspent_outputs(&[parent_id], &pkhash);
+        assert_eq!(change.len(), 1);
+        assert_eq!((change[0].0, change[0].1.index), (parent_id, 1));
+        assert!(!mempool.has_children(&parent_id));
+
+        let child = spend(parent_id, 1, &pkhash);
+        let child_id = child.get_tx_id();
+        mempool.insert(child_id, child);
+
+        assert!(mempool.unspent_outputs(&[parent_id], &pkhash).is_empty());
+        assert!(mempool.has_children(&parent_id));
+        assert_eq!(
+            mempool.parents_first(vec![child_id, [3; 32], parent_id]),
+            vec![[3; 32], parent_id, child_id]
+        );
+    }
+}
@@ -24,7 +24,7 @@ use crate::message::{
     sendcompact::SendCompactMessage, tx::TxMessage, version::VersionMessage,
 };
 
-use crate::utils::bytes_to_hex_string;
+use crate::{bandwidth, utils::bytes_to_hex_string};
 use crate::{message_header::MessageHeader, protocol_error::ProtocolError};
 
 #[derive(Debug)]
@@ -61,16 +61,56 @@ use crate::constants;
 ///it just encapsulates the match from bitcoin node
 impl Message {
     pub fn read_from(stream: &mut dyn Read) -> Result<Message, ProtocolError> {
-        let header = MessageHeader::read_from(stream)?;
-        if header.start_string != constants::START_STRING {
-            return Err(ProtocolError::Error(
-                "Header's start string is not valid".to_string(),
-            ));
-        };
+        Ok(Message::read_from_with_size(stream)?.0)
+    }
 
+    /// Reads a message and returns it along with its size on the wire.
+    /// The payload is rejected before being read if it is over the limit of its command.
+    pub fn read_from_with_size(stream: &mut dyn Read) -> Result<(Message, usize), ProtocolError> {
+        let header = MessageHeader::read_from(stream)?;
         let name = header.command_name()?;
+        if header.payload_size > max_payload_size(&name) {
+            return Err(ProtocolError::ConnectionError(format!(
+                "Payload of {} bytes is too large for a {} message",
+                header.payload_size, name
+            )));
+        }
+
+        bandwidth::throttle_download(constants::MESSAGE_HEADER_SIZE + header.payload_size as usize);
+        let mut payload = vec![0u8; header.payload_size as usize];
+        stream.read_exact(&mut payload)?;
+
+        let message = Message::parse_payload(&name, &header, &mut &payload[..])?;
+        Ok((message, constants::MESSAGE_HEADER_SIZE + payload.len()))
+    }
 
-        match &name[..] {
+    /// The command of the message, the bandwidth is accounted by it.
+    pub fn command(&self) -> &str {
+        match self {
+            Message::Ping(_) => "ping",
+            Message::SendCompact(_) => "sendcmpct",
+            Message::Addr(_) => "addr",
+            Message::Block(_) => "block",
+            Message::GetData(_) => "getdata",
+            Message::GetHeaders(_) => "getheaders",
+            Message::Headers(_) => "headers",
+            Message::Inv(_) => "inv",
+            Message::Version(_) => "version",
+            Message::FeeFilter(_) => "feefilter",
+            Message::Tx(_) => "tx",
+            Message::Mempool => "mempool",
+            Message::Verack => "verack",
+            Message::SendHeaders => "sendheaders",
+            Message::UnknownMessage(name) => name,
+        }
+    }
+
+    fn parse_payload(
+        name: &str,
+        header: &MessageHeader,
+        stream: &mut dyn Read,
+    ) -> Result<Message, ProtocolError> {
+        match name {
             "sendcmpct" => {
                 let send_compact = SendCompactMessage::read_from(stream)?;
                 if !valid_checksum(&send_compact, header.checksum) {
@@ -112,7 +152,7 @@ impl Message {
                 Ok(Message::GetHeaders(get_headers))
             }
             "block" => {
-                let block = BlockMessage::read_from(stream)?;
+                let block = BlockMessage::read_with_limit(stream, header.payload_size as usize)?;
                 if !valid_checksum(&block, header.checksum) {
                     return Err(ProtocolError::Error("Checksum is not valid".to_string()));
                 }
@@ -165,7 +205,7 @@ impl Message {
                 Ok(Message::Version(version))
             }
             "tx" => {
-                let tx = TxMessage::read_from(stream)?;
+                let tx = TxMessage::read_with_limit(stream, header.payload_size as usize)?;
                 if !valid_checksum(&tx, header.checksum) {
                     return Err(ProtocolError::Error("Checksum is not valid".to_string()));
                 }
@@ -185,6 +225,22 @@ impl Message {
     }
 }
 
+/// Returns the largest payload accepted for a command.
+fn max_payload_size(command: &str) -> u32 {
+    match command {
+        "verack" | "mempool" | "sendheaders" | "getaddr" => 0,
+        "ping" | "pong" | "feefilter" => 8,
+        "sendcmpct" => 9,
+        "version" => 1024,
+        "addr" => 9 + 1000 * 30,
+        "getheaders" | "getblocks" => 4 + 9 + 101 * 32 + 32,
+        "headers" => 3 + 2000 * 81,
+        "inv" | "getdata" | "notfound" => 9 + 50000 * 36,
+        "tx" | "block" => constants::MAX_BLOCK_SIZE,
+        _ => constants::MAX_PAYLOAD_SIZE,
+    }
+}
+
 impl fmt::Display for Message {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
@@ -206,3 +262,42 @@ impl fmt::Display for Message {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_oversized_payload_is_rejected_before_reading_it() {
+        let mut header = MessageHeader::new("ping".to_string(), vec![0; 8]).unwrap();
+        header.payload_size = u32::MAX;
+
+        let mut bytes = vec![];
+        header.write_to(&mut bytes).unwrap();
+
+        let result = Message::read_from(&mut Cursor::new(bytes));
+        assert!(matches!(result, Err(ProtocolError::ConnectionError(_))));
+    }
+
+    #[test]
+    fn test_unknown_message_payload_is_consumed() {
+        let mut bytes = vec![];
+        let unknown = MessageHeader::new("wtxidrelay2".to_string(), vec![1, 2, 3]).unwrap();
+        unknown.write_to(&mut bytes).unwrap();
+        bytes.extend_from_slice(&[1, 2, 3]);
+
+        let ping = PingMessage::new(42);
+        ping.write_to(&mut bytes).unwrap();
+
+        let mut cursor = Cursor::new(bytes);
+        let (message, size) = Message::read_from_with_size(&mut cursor).unwrap();
+        assert!(matches!(message, Message::UnknownMessage(_)));
+        assert_eq!(size, 27);
+
+        match Message::read_from(&mut cursor).unwrap() {
+            Message::Ping(ping) => assert_eq!(ping.get_nonce(), 42),
+            m => panic!("Expected ping, got {}", m),
+        }
+    }
+}
@@ -1,6 +1,12 @@
-use std::{io::Read, net::Ipv6Addr};
+use std::{
+    io::{Read, Write},
+    net::{IpAddr, Ipv6Addr, SocketAddr},
+};
 
-use crate::{message::compact_size::CompactSize, protocol_error::ProtocolError};
+use crate::{
+    message::compact_size::CompactSize, message_header::MessageHeader,
+    protocol_error::ProtocolError,
+};
 
 use super::Serializable;
 
@@ -13,6 +19,15 @@ pub struct NetworkAddr {
 }
 
 impl NetworkAddr {
+    pub fn new(time: u32, services: u64, ip: Ipv6Addr, port: u16) -> NetworkAddr {
+        NetworkAddr {
+            time,
+            services,
+            ip,
+            port,
+        }
+    }
+
     pub fn read_from(stream: &mut dyn Read) -> Result<NetworkAddr, ProtocolError> {
         let mut time_bytes = [0u8; 4];
         stream.read_exact(&mut time_bytes)?;
@@ -35,6 +50,17 @@ impl NetworkAddr {
     }
 }
 
+impl NetworkAddr {
+    /// The advertised address with its port, ipv4 peers are returned as ipv4 instead of mapped ipv6.
+    pub fn socket_addr(&self) -> SocketAddr {
+        let ip = match self.ip.to_ipv4_mapped() {
+            Some(v4) => IpAddr::V4(v4),
+            None => IpAddr::V6(self.ip),
+        };
+        SocketAddr::new(ip, self.port)
+    }
+}
+
 impl Serializable for NetworkAddr {
     fn to_bytes(&self) -> Vec<u8> {
         let mut bytes = Vec::new();
@@ -55,6 +81,13 @@ pub struct AddrMessage {
 }
 
 impl AddrMessage {
+    pub fn new(ip_addresses: Vec<NetworkAddr>) -> AddrMessage {
+        AddrMessage {
+            count: CompactSize::new_from_usize(ip_addresses.len()),
+            ip_addresses,
+        }
+    }
+
     pub fn read_from(stream: &mut dyn Read) -> Result<AddrMessage, ProtocolError> {
         let count = CompactSize::read_from(stream)?;
 
@@ -69,6 +102,16 @@ impl AddrMessage {
             ip_addresses,
         })
     }
+
+    pub fn write_to(&self, stream: &mut dyn Write) -> Result<(), ProtocolError> {
+        let payload = self.to_bytes();
+
+        let header = MessageHeader::new("addr".to_string(), payload.clone())?;
+        header.write_to(stream)?;
+
+        stream.write_all(&payload[..])?;
+        Ok(())
+    }
 }
 
 impl Serializable for AddrMessage {
@@ -83,3 +126,29 @@ impl Serializable for AddrMessage {
         bytes
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::Message;
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn test_addr_message_round_trip() {
+        let ip = Ipv4Addr::new(8, 8, 4, 4).to_ipv6_mapped();
+        let mut bytes = vec![];
+        AddrMessage::new(vec![NetworkAddr::new(1700000000, 1, ip, 18333)])
+            .write_to(&mut bytes)
+            .unwrap();
+
+        let addr = match Message::read_from(&mut &bytes[..]).unwrap() {
+            Message::Addr(addr) => addr,
+            _ => panic!("Expected an addr message"),
+        };
+        assert_eq!(addr.ip_addresses.len(), 1);
+        assert_eq!(
+            addr.ip_addresses[0].socket_addr(),
+            "8.8.4.4:18333".parse().unwrap()
+        );
+    }
+}
@@ -1,9 +1,17 @@
 use crate::{
-    block_header::BlockHeader, message::compact_size::CompactSize, message_header::MessageHeader,
-    protocol_error::ProtocolError, raw_transaction::RawTransaction,
+    block_header::{BlockHeader, BLOCK_HEADER_SIZE},
+    constants::MAX_BLOCK_SIZE,
+    merkle_tree::merkle_tree_root,
+    message::compact_size::CompactSize,
+    message_header::MessageHeader,
+    protocol_error::ProtocolError,
+    raw_transaction::{read_count, RawTransaction, MIN_TX_SIZE},
 };
 
-use std::io::{Read, Write};
+use std::{
+    collections::HashSet,
+    io::{Read, Write},
+};
 
 use super::Serializable;
 
@@ -16,11 +24,11 @@ pub struct BlockMessage {
 
 impl Serializable for BlockMessage {
     fn to_bytes(&self) -> Vec<u8> {
-        let mut bytes = Vec::new();
-        bytes.extend_from_slice(&self.block_header.to_bytes());
-        bytes.extend_from_slice(&self.txn_count.to_le_bytes());
+        let mut bytes = Vec::with_capacity(self.serialized_size());
+        self.block_header.append_to(&mut bytes);
+        self.txn_count.append_to(&mut bytes);
         for txn in &self.txns {
-            bytes.extend_from_slice(&txn.to_bytes());
+            txn.append_to(&mut bytes);
         }
         bytes
     }
@@ -28,29 +36,74 @@ impl Serializable for BlockMessage {
 
 impl BlockMessage {
     pub fn read_from(stream: &mut dyn Read) -> Result<BlockMessage, ProtocolError> {
+        BlockMessage::read_with_limit(stream, MAX_BLOCK_SIZE as usize)
+    }
+
+    /// Reads a block of at most `max_size` bytes, the size of the payload it comes in.
+    pub fn read_with_limit(
+        stream: &mut dyn Read,
+        max_size: usize,
+    ) -> Result<BlockMessage, ProtocolError> {
         let block_header = BlockHeader::read_from(stream)?;
-        let txn_count = CompactSize::read_from(stream)?;
+        let txn_count = read_count(stream, MIN_TX_SIZE, max_size, "Transaction")?;
         let mut txns: Vec<RawTransaction> = Vec::new();
 
         for _ in 0..txn_count.into_inner() {
-            txns.push(RawTransaction::read_from(stream)?);
+            txns.push(RawTransaction::read_with_limit(stream, max_size)?);
         }
 
-        Ok(BlockMessage {
+        let block = BlockMessage {
             block_header,
             txn_count,
             txns,
-        })
+        };
+        block.verify_merkle_root()?;
+
+        Ok(block)
+    }
+
+    /// Checks that the transactions match the merkle root of the header.
+    /// Repeated txids are rejected too, since they can produce the same root as a valid block.
+    /// The error is a connection error, the peer that sent the block should be dropped.
+    pub fn verify_merkle_root(&self) -> Result<(), ProtocolError> {
+        let hashes = self.get_txns_hashes();
+
+        let mut seen = HashSet::with_capacity(hashes.len());
+        if !hashes.iter().all(|hash| seen.insert(*hash)) {
+            return Err(ProtocolError::ConnectionError(
+                "Block has repeated transactions".to_string(),
+            ));
+        }
+
+        if hashes.is_empty() || merkle_tree_root(hashes) != self.block_header.merkle_root_hash {
+            return Err(ProtocolError::ConnectionError(
+                "Merkle root doesn't match".to_string(),
+            ));
+        }
+
+        Ok(())
     }
 
     pub fn get_txns_hashes(&self) -> Vec<[u8; 32]> {
-        let mut txns_hashes = Vec::new();
+        let mut scratch = Vec::new();
+        let mut txns_hashes = Vec::with_capacity(self.txns.len());
         for txn in &self.txns {
-            txns_hashes.push(txn.get_tx_id());
+            txns_hashes.push(txn.tx_id_with(&mut scratch));
         }
         txns_hashes
     }
 
+    /// Bytes taken by the serialized block.
+    pub fn serialized_size(&self) -> usize {
+        BLOCK_HEADER_SIZE
+            + self.txn_count.size()
+            + self
+                .txns
+                .iter()
+                .map(RawTransaction::serialized_size)
+                .sum::<usize>()
+    }
+
     pub fn write_to(&self, stream: &mut dyn Write) -> Result<(), ProtocolError> {
         let payload = self.to_bytes();
 
@@ -61,3 +114,91 @@ impl BlockMessage {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raw_transaction::TxOut;
+
+    fn block_with(txns: Vec<RawTransaction>, merkle_root_hash: [u8; 32]) -> BlockMessage {
+        let mut block_header = BlockHeader {
+            version: 1,
+            prev_block_hash: [0; 32],
+            merkle_root_hash,
+            timestamp: 1234567890,
+            bits: 0x207fffff,
+            nonce: 0,
+        };
+        while !block_header.validate_proof_of_work() {
+            block_header.nonce += 1;
+        }
+
+        BlockMessage {
+            block_header,
+            txn_count: CompactSize::new_from_usize(txns.len()),
+            txns,
+        }
+    }
+
+    #[test]
+    fn test_read_block_with_valid_merkle_root() {
+        let tx1 = RawTransaction::new(vec![], vec![TxOut::new(10, vec![])]);
+        let tx2 = RawTransaction::new(vec![], vec![TxOut::new(20, vec![])]);
+        let root = merkle_tree_root(vec![tx1.get_tx_id(), tx2.get_tx_id()]);
+
+        let bytes = block_with(vec![tx1, tx2], root).to_bytes();
+        let block = BlockMessage::read_from(&mut &bytes[..]).unwrap();
+        assert_eq!(block.txns.len(), 2);
+    }
+
+    #[test]
+    fn test_read_block_with_wrong_merkle_root_fails() {
+        let tx1 = RawTransaction::new(vec![], vec![TxOut::new(10, vec![])]);
+
+        let bytes = block_with(vec![tx1], [1; 32]).to_bytes();
+        assert!(matches!(
+            BlockMessage::read_from(&mut &bytes[..]),
+            Err(ProtocolError::ConnectionError(_))
+        ));
+    }
+
+    #[test]
+    fn test_block_with_repeated_transactions_fails() {
+        let tx1 = RawTransaction::new(vec![], vec![TxOut::new(10, vec![])]);
+        let tx2 = RawTransaction::new(vec![], vec![TxOut::new(20, vec![])]);
+        // [tx1, tx2, tx2] has the same merkle root as [tx1, tx2, tx2, tx2]
+        let root = merkle_tree_root(vec![tx1.get_tx_id(), tx2.get_tx_id(), tx2.get_tx_id()]);
+
+        let block = block_with(vec![tx1, tx2.clone(), tx2], root);
+        assert!(block.verify_merkle_root().is_err());
+    }
+
+    #[test]
+    fn test_transaction_count_over_the_payload_size_fails() {
+        let tx1 = RawTransaction::new(vec![], vec![TxOut::new(10, vec![])]);
+        let root = merkle_tree_root(vec![tx1.get_tx_id()]);
+
+        let mut bytes = block_with(vec![tx1], root).to_bytes();
+        // A count of u32::MAX transactions, with the bytes of a single one behind it
+        bytes.splice(80..81, [254, 255, 255, 255, 255]);
+        assert!(matches!(
+            BlockMessage::read_with_limit(&mut &bytes[..], bytes.len()),
+            Err(ProtocolError::ConnectionError(_))
+        ));
+    }
+
+    #[test]
+    fn test_serialized_size_is_exact() {
+        let script = vec![7; 300];
+        let tx1 = RawTransaction::new(vec![], vec![TxOut::new(10, script.clone())]);
+        let tx2 = RawTransaction::new(vec![], vec![TxOut::new(20, vec![]); 260]);
+        let root = merkle_tree_root(vec![tx1.get_tx_id(), tx2.get_tx_id()]);
+
+        let block = block_with(vec![tx1, tx2], root);
+        for tx in &block.txns {
+            assert_eq!(tx.serialized_size(), tx.to_bytes().len());
+            assert_eq!(tx.tx_id_with(&mut script.clone()), tx.get_tx_id());
+        }
+        assert_eq!(block.serialized_size(), block.to_bytes().len());
+    }
+}
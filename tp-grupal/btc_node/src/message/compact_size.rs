@@ -73,7 +73,13 @@ impl CompactSize {
     }
 
     pub fn to_le_bytes(&self) -> Vec<u8> {
-        let mut bytes: Vec<u8> = Vec::new();
+        let mut bytes: Vec<u8> = Vec::with_capacity(self.size());
+        self.append_to(&mut bytes);
+        bytes
+    }
+
+    /// Serializes the count at the end of `bytes`, like to_le_bytes without allocating.
+    pub fn append_to(&self, bytes: &mut Vec<u8>) {
         match self {
             CompactSize::U8(i) => bytes.extend_from_slice(&i.to_le_bytes()),
             CompactSize::U16(i) => {
@@ -89,7 +95,16 @@ impl CompactSize {
                 bytes.extend_from_slice(&i.to_le_bytes());
             }
         };
-        bytes
+    }
+
+    /// Bytes taken by the serialized count.
+    pub fn size(&self) -> usize {
+        match self {
+            CompactSize::U8(_) => 1,
+            CompactSize::U16(_) => 3,
+            CompactSize::U32(_) => 5,
+            CompactSize::U64(_) => 9,
+        }
     }
 
     pub fn into_inner(&self) -> usize {
@@ -139,6 +154,18 @@ mod tests {
         assert_eq!(cs.to_be_bytes()[2], 0x7b);
     }
 
+    #[test]
+    fn test_size_is_the_length_of_the_bytes() {
+        for cs in [
+            CompactSize::U8(42),
+            CompactSize::U16(300),
+            CompactSize::U32(70000),
+            CompactSize::U64(1 << 40),
+        ] {
+            assert_eq!(cs.size(), cs.to_le_bytes().len());
+        }
+    }
+
     #[test]
     fn test_into_inner() {
         let compact_size = CompactSize::U32(123);
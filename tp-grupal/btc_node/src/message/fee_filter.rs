@@ -3,12 +3,27 @@ use std::io::{Read, Write};
 
 use super::Serializable;
 
+/// Fee rate in satoshis per 1000 virtual bytes below which the sender doesn't want the
+/// transactions announced (BIP133).
 #[derive(Debug)]
 pub struct FeeFilterMessage {
     feerate: u64,
 }
 
 impl FeeFilterMessage {
+    pub fn new(feerate: u64) -> FeeFilterMessage {
+        FeeFilterMessage { feerate }
+    }
+
+    /// Filter of a fee rate in sat/vB.
+    pub fn from_fee_rate(rate: f64) -> FeeFilterMessage {
+        FeeFilterMessage::new((rate * 1000.0).round() as u64)
+    }
+
+    pub fn feerate(&self) -> u64 {
+        self.feerate
+    }
+
     pub fn read_from(stream: &mut dyn Read) -> Result<FeeFilterMessage, ProtocolError> {
         let mut feerate = [0u8; 8];
         stream.read_exact(&mut feerate)?;
@@ -41,6 +56,7 @@ impl Serializable for FeeFilterMessage {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::constants::MESSAGE_HEADER_SIZE;
     use std::io::Cursor;
     #[test]
     fn test_fee_filter_message_read_from() {
@@ -55,4 +71,16 @@ mod tests {
 
         assert_eq!(fee_filter_message.feerate, 578437695752307201);
     }
+
+    #[test]
+    fn test_fee_filter_message_is_written_in_sat_per_kvb() {
+        let mut bytes = vec![];
+        FeeFilterMessage::from_fee_rate(2.5)
+            .write_to(&mut bytes)
+            .unwrap();
+
+        let mut cursor = Cursor::new(&bytes[MESSAGE_HEADER_SIZE..]);
+        let read = FeeFilterMessage::read_from(&mut cursor).unwrap();
+        assert_eq!(read.feerate(), 2500);
+    }
 }
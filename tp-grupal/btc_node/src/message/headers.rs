@@ -1,7 +1,9 @@
 use std::io::{Read, Write};
 
 use crate::{
-    block_header::BlockHeader, message::compact_size::CompactSize, message_header::MessageHeader,
+    block_header::{BlockHeader, BLOCK_HEADER_SIZE},
+    message::compact_size::CompactSize,
+    message_header::MessageHeader,
     protocol_error::ProtocolError,
 };
 
@@ -94,12 +96,14 @@ impl HeadersMessage {
 
 impl Serializable for HeadersMessage {
     fn to_bytes(&self) -> Vec<u8> {
-        let mut bytes = Vec::new();
+        // Every header is followed by an empty transaction count
+        let mut bytes =
+            Vec::with_capacity(self.count.size() + self.headers.len() * (BLOCK_HEADER_SIZE + 1));
 
-        bytes.extend_from_slice(&self.count.to_le_bytes());
+        self.count.append_to(&mut bytes);
         for header in &self.headers {
-            bytes.extend_from_slice(&header.to_bytes());
-            bytes.extend_from_slice(&[0u8]);
+            header.append_to(&mut bytes);
+            bytes.push(0u8);
         }
 
         bytes
@@ -12,6 +12,8 @@ pub enum TypeIdentifier {
     MsgBlock,
     MsgFilteredBlock,
     MsgCmptBlock,
+    /// Transaction announced by its wtxid (BIP339)
+    MsgWtx,
 }
 
 impl TypeIdentifier {
@@ -21,6 +23,7 @@ impl TypeIdentifier {
             TypeIdentifier::MsgBlock => 2u32,
             TypeIdentifier::MsgFilteredBlock => 3u32,
             TypeIdentifier::MsgCmptBlock => 4u32,
+            TypeIdentifier::MsgWtx => 5u32,
         }
     }
 
@@ -31,8 +34,9 @@ impl TypeIdentifier {
             2 => Ok(TypeIdentifier::MsgBlock),
             3 => Ok(TypeIdentifier::MsgFilteredBlock),
             4 => Ok(TypeIdentifier::MsgCmptBlock),
+            5 => Ok(TypeIdentifier::MsgWtx),
             _ => Err(ProtocolError::BuildingError(
-                "Tipo invalido parseando inventario.".to_string(),
+                "Invalid inventory type".to_string(),
             )),
         }
     }
@@ -86,6 +90,7 @@ impl fmt::Display for TypeIdentifier {
             TypeIdentifier::MsgBlock => write!(f, "Block"),
             TypeIdentifier::MsgFilteredBlock => write!(f, "FilteredBlock"),
             TypeIdentifier::MsgCmptBlock => write!(f, "CmptBlock"),
+            TypeIdentifier::MsgWtx => write!(f, "Wtx"),
         }
     }
 }
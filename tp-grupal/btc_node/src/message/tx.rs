@@ -22,6 +22,16 @@ impl TxMessage {
         Ok(TxMessage { tx })
     }
 
+    /// Reads a transaction of at most `max_size` bytes, the size of the payload it comes in.
+    pub fn read_with_limit(
+        stream: &mut dyn Read,
+        max_size: usize,
+    ) -> Result<TxMessage, ProtocolError> {
+        let tx = RawTransaction::read_with_limit(stream, max_size)?;
+
+        Ok(TxMessage { tx })
+    }
+
     pub fn write_to(&self, stream: &mut dyn Write) -> Result<(), ProtocolError> {
         let payload = self.to_bytes();
 
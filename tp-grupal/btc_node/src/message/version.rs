@@ -1,5 +1,8 @@
 use crate::{
-    config::Config, message::compact_size::CompactSize, message_header::MessageHeader,
+    config::Config,
+    constants::{FEE_FILTER_VERSION, MIN_PEER_PROTOCOL_VERSION, PROTOCOL_VERSION},
+    message::compact_size::CompactSize,
+    message_header::MessageHeader,
     protocol_error::ProtocolError,
 };
 use std::{
@@ -252,10 +255,12 @@ impl VersionMessage {
         Ok(())
     }
 
-    pub fn new(config: &Config) -> Result<VersionMessage, String> {
+    /// Builds our version message with the services and user agent of the configuration.
+    pub fn new(config: &Config, start_height: i32) -> Result<VersionMessage, String> {
+        let user_agent = config.user_agent.as_bytes().to_vec();
         VersionMessageBuilder::new()
-            .version(70015)
-            .services(0)
+            .version(PROTOCOL_VERSION)
+            .services(config.services)
             .timestamp(Utc::now().timestamp())
             .addr_recv_services(1)
             .addr_recv_ip(Ipv4Addr::new(127, 0, 0, 1).to_ipv6_mapped())
@@ -264,12 +269,65 @@ impl VersionMessage {
             .addr_trans_ip(Ipv4Addr::new(127, 0, 0, 1).to_ipv6_mapped())
             .addr_trans_port(config.port)
             .nonce(rand::thread_rng().gen())
-            .user_agent_bytes(CompactSize::U8(0))
-            .user_agent(Vec::new())
-            .start_height(1)
-            .relay(1)
+            .user_agent_bytes(CompactSize::new_from_usize(user_agent.len()))
+            .user_agent(user_agent)
+            .start_height(start_height)
+            .relay(u8::from(!config.blocksonly))
             .build()
     }
+
+    /// Advertises the `services` bits on top of the ones already set.
+    pub fn enable_services(&mut self, services: u64) {
+        self.services |= services;
+    }
+
+    pub fn set_start_height(&mut self, start_height: i32) {
+        self.start_height = start_height;
+    }
+
+    pub fn nonce(&self) -> u64 {
+        self.nonce
+    }
+
+    pub fn user_agent(&self) -> String {
+        String::from_utf8_lossy(&self.user_agent).to_string()
+    }
+
+    pub fn start_height(&self) -> i32 {
+        self.start_height
+    }
+
+    /// Whether the peer wants the transactions announced, false if it set relay to 0.
+    pub fn relays_txs(&self) -> bool {
+        self.relay != 0
+    }
+
+    pub fn has_services(&self, services: u64) -> bool {
+        self.services & services == services
+    }
+
+    pub fn supports_fee_filter(&self) -> bool {
+        self.version >= FEE_FILTER_VERSION
+    }
+
+    /// Checks the version message received from a peer against our own.
+    /// It fails if the peer is ourselves (same nonce) or if its protocol version is too old.
+    pub fn validate_peer(&self, peer: &VersionMessage) -> Result<(), ProtocolError> {
+        if peer.nonce == self.nonce {
+            return Err(ProtocolError::ConnectionError(
+                "Connected to self".to_string(),
+            ));
+        }
+
+        if peer.version < MIN_PEER_PROTOCOL_VERSION {
+            return Err(ProtocolError::ConnectionError(format!(
+                "Peer protocol version {} is lower than {}",
+                peer.version, MIN_PEER_PROTOCOL_VERSION
+            )));
+        }
+
+        Ok(())
+    }
 }
 
 impl Serializable for VersionMessage {
@@ -318,3 +376,67 @@ impl fmt::Display for VersionMessage {
         writeln!(f, "relay: {:?}", self.relay)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::NODE_NETWORK_LIMITED;
+
+    fn version_message(version: i32, services: u64, nonce: u64) -> VersionMessage {
+        VersionMessageBuilder::new()
+            .version(version)
+            .services(services)
+            .timestamp(0)
+            .addr_recv_services(0)
+            .addr_recv_ip(Ipv4Addr::new(127, 0, 0, 1).to_ipv6_mapped())
+            .addr_recv_port(18333)
+            .addr_trans_services(0)
+            .addr_trans_ip(Ipv4Addr::new(127, 0, 0, 1).to_ipv6_mapped())
+            .addr_trans_port(18333)
+            .nonce(nonce)
+            .user_agent_bytes(CompactSize::U8(0))
+            .user_agent(Vec::new())
+            .start_height(0)
+            .relay(1)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_validate_peer() {
+        let ours = version_message(PROTOCOL_VERSION, 0, 1);
+
+        assert!(ours
+            .validate_peer(&version_message(PROTOCOL_VERSION, 0, 2))
+            .is_ok());
+        assert!(ours
+            .validate_peer(&version_message(PROTOCOL_VERSION, 0, 1))
+            .is_err());
+        assert!(ours
+            .validate_peer(&version_message(MIN_PEER_PROTOCOL_VERSION - 1, 0, 2))
+            .is_err());
+    }
+
+    #[test]
+    fn test_advertised_services() {
+        let peer = version_message(FEE_FILTER_VERSION, NODE_NETWORK_LIMITED | 1, 2);
+        assert!(peer.has_services(NODE_NETWORK_LIMITED));
+        assert!(peer.has_services(1));
+        assert!(peer.supports_fee_filter());
+
+        let old_peer = version_message(MIN_PEER_PROTOCOL_VERSION, 1, 2);
+        assert!(!old_peer.has_services(NODE_NETWORK_LIMITED | 1));
+        assert!(!old_peer.supports_fee_filter());
+    }
+
+    #[test]
+    fn test_relay_flag_survives_the_round_trip() {
+        let mut blocks_only = version_message(PROTOCOL_VERSION, 0, 2);
+        blocks_only.relay = 0;
+        let payload = blocks_only.to_bytes();
+
+        let read = VersionMessage::read_from(&mut &payload[..]).unwrap();
+        assert!(!read.relays_txs());
+        assert!(version_message(PROTOCOL_VERSION, 0, 2).relays_txs());
+    }
+}
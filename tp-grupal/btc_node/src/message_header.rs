@@ -1,6 +1,9 @@
-use crate::{constants, protocol_error::ProtocolError};
+use crate::{address::Network, bandwidth, constants, protocol_error::ProtocolError};
 use bitcoin_hashes::{sha256d, Hash};
-use std::io::{Read, Write};
+use std::{
+    io::{Read, Write},
+    sync::RwLock,
+};
 
 pub mod message_header_error {
     use std::error::Error;
@@ -13,6 +16,9 @@ pub mod message_header_error {
         ChecksumFailed(String),
         IOError(std::io::Error),
         InvalidCommand(FromUtf8Error),
+        /// The start string is the one of another network, the peer can't be talked to
+        WrongNetwork(String),
+        InvalidStartString(String),
     }
 
     impl Error for MessageHeaderError {}
@@ -24,6 +30,8 @@ pub mod message_header_error {
                 MessageHeaderError::ChecksumFailed(s) => write!(f, "{}", s),
                 MessageHeaderError::IOError(s) => write!(f, "{}", s),
                 MessageHeaderError::InvalidCommand(s) => write!(f, "{}", s),
+                MessageHeaderError::WrongNetwork(s) => write!(f, "{}", s),
+                MessageHeaderError::InvalidStartString(s) => write!(f, "{}", s),
             }
         }
     }
@@ -43,6 +51,41 @@ pub mod message_header_error {
 
 use message_header_error::MessageHeaderError;
 
+// Network of the peers, its start string prefixes every message
+static NETWORK: RwLock<Network> = RwLock::new(Network::Testnet);
+
+/// Sets the network whose start string the messages are sent and expected with.
+pub fn set_network(network: Network) {
+    if let Ok(mut current) = NETWORK.write() {
+        *current = network;
+    }
+}
+
+pub fn network() -> Network {
+    NETWORK.read().map(|network| *network).unwrap_or_default()
+}
+
+/// Fails if the start string isn't the one of `network`, telling apart the peers of another
+/// known network from the ones that sent garbage.
+pub fn check_start_string(
+    start_string: [u8; 4],
+    network: Network,
+) -> Result<(), MessageHeaderError> {
+    if start_string == network.start_string() {
+        return Ok(());
+    }
+    match Network::from_start_string(start_string) {
+        Some(other) => Err(MessageHeaderError::WrongNetwork(format!(
+            "The peer is on {} and the node on {}",
+            other.name(),
+            network.name()
+        ))),
+        None => Err(MessageHeaderError::InvalidStartString(
+            "Header's start string is not valid".to_string(),
+        )),
+    }
+}
+
 #[derive(Debug)]
 pub struct MessageHeader {
     pub start_string: [u8; 4],
@@ -70,7 +113,7 @@ impl MessageHeader {
             ));
         }
 
-        let start_string = constants::START_STRING;
+        let start_string = network().start_string();
 
         let mut command_name = [0u8; 12];
         command_name[..command.len()].copy_from_slice(command.as_bytes());
@@ -103,12 +146,19 @@ impl MessageHeader {
         stream.write_all(&self.command_name)?;
         stream.write_all(&self.payload_size.to_le_bytes())?;
         stream.write_all(&self.checksum)?;
+
+        if let Ok(command) = self.command_name() {
+            let size = constants::MESSAGE_HEADER_SIZE as u64 + self.payload_size as u64;
+            bandwidth::record_thread_sent(&command, size);
+        }
         Ok(())
     }
 
     pub fn read_from(stream: &mut dyn Read) -> Result<MessageHeader, MessageHeaderError> {
         let mut start_string = [0u8; 4];
         stream.read_exact(&mut start_string)?;
+        // Checked before the rest, the peers of another network are dropped right away
+        check_start_string(start_string, network())?;
 
         let mut command_name = [0u8; 12];
         stream.read_exact(&mut command_name)?;
@@ -136,3 +186,41 @@ impl MessageHeader {
         Ok(s)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message_header_round_trip() {
+        let header = MessageHeader::new("ping".to_string(), vec![1, 2, 3]).unwrap();
+        let mut bytes = vec![];
+        header.write_to(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), constants::MESSAGE_HEADER_SIZE);
+
+        let read = MessageHeader::read_from(&mut &bytes[..]).unwrap();
+        assert_eq!(read.start_string, network().start_string());
+        assert_eq!(read.command_name().unwrap(), "ping");
+        assert_eq!(read.payload_size, 3);
+        assert_eq!(read.checksum, header.checksum);
+    }
+
+    #[test]
+    fn test_start_strings_of_other_networks_are_told_apart() {
+        let testnet = Network::Testnet.start_string();
+        let mainnet = Network::Mainnet.start_string();
+        assert!(check_start_string(testnet, Network::Testnet).is_ok());
+        assert!(matches!(
+            check_start_string(mainnet, Network::Testnet),
+            Err(MessageHeaderError::WrongNetwork(_))
+        ));
+        assert!(matches!(
+            check_start_string(testnet, Network::Mainnet),
+            Err(MessageHeaderError::WrongNetwork(_))
+        ));
+        assert!(matches!(
+            check_start_string([1, 2, 3, 4], Network::Testnet),
+            Err(MessageHeaderError::InvalidStartString(_))
+        ));
+    }
+}
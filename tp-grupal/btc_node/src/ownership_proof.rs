@@ -0,0 +1,120 @@
+use bitcoin_hashes::{sha256, Hash, HashEngine};
+
+use crate::{
+    constants::SIGHASH_ALL,
+    message::compact_size::CompactSize,
+    protocol_error::ProtocolError,
+    raw_transaction::{unhexlify, Outpoint, RawTransaction, TxIn, TxOut},
+    script::PubKeyScript,
+    utils::{bytes_to_hex_string, wif_to_bitcoin_address},
+};
+
+// Tag of the hash of the signed message (BIP 322)
+const MESSAGE_TAG: &[u8] = b"BIP0322-signed-message";
+const OP_0: u8 = 0;
+const OP_RETURN: u8 = 106;
+
+/// Tagged hash of the message: sha256(sha256(tag) || sha256(tag) || message).
+pub fn message_hash(message: &str) -> [u8; 32] {
+    let tag = sha256::Hash::hash(MESSAGE_TAG).to_byte_array();
+    let mut engine = sha256::Hash::engine();
+    engine.input(&tag);
+    engine.input(&tag);
+    engine.input(message.as_bytes());
+    sha256::Hash::from_engine(engine).to_byte_array()
+}
+
+/// Virtual transaction whose only output, paying to the address, commits to the message.
+/// It is never broadcasted, the proof spends its output.
+fn to_spend(pubkey_script: &PubKeyScript, message: &str) -> RawTransaction {
+    let signature_script = [&[OP_0, 32][..], &message_hash(message)].concat();
+    let mut txin = TxIn::new(Outpoint::new([0; 32], 0xffffffff), signature_script);
+    txin.sequence = 0;
+
+    let mut tx = RawTransaction::new(vec![txin], vec![TxOut::new(0, pubkey_script.to_vec())]);
+    tx.version = 0;
+    tx
+}
+
+/// Unsigned transaction that spends the output of `to_spend` to an OP_RETURN.
+fn to_sign(to_spend: &RawTransaction) -> RawTransaction {
+    let mut txin = TxIn::new(Outpoint::new(to_spend.get_tx_id(), 0), vec![]);
+    txin.sequence = 0;
+
+    let mut tx = RawTransaction::new(vec![txin], vec![TxOut::new(0, vec![OP_RETURN])]);
+    tx.version = 0;
+    tx
+}
+
+/// Proves that the key controls its P2PKH address by signing the message, in the full format of
+/// BIP 322: the signed `to_sign` transaction, in hex.
+pub fn prove_ownership(wif: &str, message: &str) -> Result<(String, String), ProtocolError> {
+    let address = wif_to_bitcoin_address(wif)?;
+    let pubkey_script = PubKeyScript::from_address(&address)?;
+
+    let mut proof = to_sign(&to_spend(&pubkey_script, message));
+    proof.sign_p2pkh_input(0, pubkey_script.to_vec(), wif, SIGHASH_ALL)?;
+    Ok((address, bytes_to_hex_string(&proof.to_bytes())))
+}
+
+/// Whether the proof spends the output that pays to the address and commits to the message.
+/// Only P2PKH addresses can be proven.
+pub fn verify_ownership(address: &str, message: &str, proof: &str) -> Result<bool, ProtocolError> {
+    let proof = proof.trim();
+    if !proof.len().is_multiple_of(2) || !proof.is_ascii() {
+        return Err(ProtocolError::Error("The proof isn't hex".to_string()));
+    }
+    let bytes =
+        unhexlify(proof).map_err(|_| ProtocolError::Error("The proof isn't hex".to_string()))?;
+    let tx = RawTransaction::read_from(&mut &bytes[..])?;
+
+    let pubkey_script = PubKeyScript::from_address(address)?;
+    if !matches!(pubkey_script, PubKeyScript::P2PKH(_)) {
+        return Err(ProtocolError::Error(
+            "Only P2PKH addresses can prove their ownership".to_string(),
+        ));
+    }
+
+    // The proof of another address or message spends another output
+    let mut unsigned = tx.clone();
+    for txin in unsigned.tx_in.iter_mut() {
+        txin.signature_script.clear();
+        txin.script_bytes = CompactSize::new_from_usize(0);
+    }
+    if unsigned.to_bytes() != to_sign(&to_spend(&pubkey_script, message)).to_bytes() {
+        return Ok(false);
+    }
+
+    Ok(pubkey_script.evaluate(tx, 0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{address::Network, keys::generate_key};
+
+    #[test]
+    fn test_message_hash_matches_the_bip322_vectors() {
+        assert_eq!(
+            bytes_to_hex_string(&message_hash("")),
+            "c90c269c4f8fcbe6880f72a721ddfbf1914268a794cbb21cfafee13770ae19f1"
+        );
+        assert_eq!(
+            bytes_to_hex_string(&message_hash("Hello World")),
+            "f0eb03b1a75ac6d9847f55c624a99169b5dccba2a31f5b23bea77ba270de0a7a"
+        );
+    }
+
+    #[test]
+    fn test_a_proof_only_verifies_its_address_and_message() {
+        let key = generate_key(Network::default());
+        let other = generate_key(Network::default());
+
+        let (address, proof) = prove_ownership(&key.wif, "audit 2024").unwrap();
+        assert_eq!(address, key.address);
+        assert!(verify_ownership(&key.address, "audit 2024", &proof).unwrap());
+        assert!(!verify_ownership(&key.address, "audit 2025", &proof).unwrap());
+        assert!(!verify_ownership(&other.address, "audit 2024", &proof).unwrap());
+        assert!(verify_ownership(&key.address, "audit 2024", "zz").is_err());
+    }
+}
@@ -0,0 +1,158 @@
+use crate::protocol_error::ProtocolError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestStatus {
+    Pending,
+    /// The address received the whole amount
+    Fulfilled,
+    /// The deadline passed before the amount was received
+    Expired,
+}
+
+/// Amount asked to be paid to a fresh address before a deadline.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PaymentRequest {
+    pub id: u64,
+    pub address: String,
+    pub amount: i64,
+    pub label: String,
+    pub created: u32,
+    /// Timestamp after which the request expires
+    pub expiry: u32,
+    /// Satoshis received by the address since the request was created
+    pub received: i64,
+    /// Transaction that completed the amount
+    pub txid: Option<[u8; 32]>,
+    pub status: RequestStatus,
+}
+
+/// Payment requests created while the node runs, the pending ones are fulfilled by the
+/// transactions that pay to their addresses.
+#[derive(Debug, Default)]
+pub struct PaymentRequests {
+    requests: Vec<PaymentRequest>,
+    next_id: u64,
+}
+
+impl PaymentRequests {
+    pub fn new() -> PaymentRequests {
+        PaymentRequests::default()
+    }
+
+    /// Adds a request for the amount that expires `expires_in` seconds after `now`.
+    /// An address has one pending request at most, so every payment goes to a single one.
+    pub fn add(
+        &mut self,
+        address: String,
+        amount: i64,
+        label: String,
+        now: u32,
+        expires_in: u32,
+    ) -> Result<PaymentRequest, ProtocolError> {
+        if amount <= 0 {
+            return Err(ProtocolError::Error(
+                "The requested amount has to be positive".to_string(),
+            ));
+        }
+        if self
+            .requests
+            .iter()
+            .any(|r| r.address == address && r.status == RequestStatus::Pending)
+        {
+            return Err(ProtocolError::Error(format!(
+                "The address {} already has a pending payment request",
+                address
+            )));
+        }
+
+        let request = PaymentRequest {
+            id: self.next_id,
+            address,
+            amount,
+            label,
+            created: now,
+            expiry: now.saturating_add(expires_in),
+            received: 0,
+            txid: None,
+            status: RequestStatus::Pending,
+        };
+        self.next_id += 1;
+        self.requests.push(request.clone());
+        Ok(request)
+    }
+
+    /// Adds a payment to the pending request of the address, fulfilling it once the received
+    /// satoshis reach the amount. Returns the request if there was one.
+    pub fn received(
+        &mut self,
+        address: &str,
+        txid: [u8; 32],
+        amount: i64,
+    ) -> Option<&PaymentRequest> {
+        let request = self
+            .requests
+            .iter_mut()
+            .find(|r| r.address == address && r.status == RequestStatus::Pending)?;
+        request.received += amount;
+        if request.received >= request.amount {
+            request.status = RequestStatus::Fulfilled;
+            request.txid = Some(txid);
+        }
+        Some(request)
+    }
+
+    /// Expires the pending requests whose deadline passed, returns how many.
+    pub fn expire(&mut self, now: u32) -> usize {
+        let mut expired = 0;
+        for request in self.requests.iter_mut() {
+            if request.status == RequestStatus::Pending && request.expiry <= now {
+                request.status = RequestStatus::Expired;
+                expired += 1;
+            }
+        }
+        expired
+    }
+
+    pub fn all(&self) -> Vec<PaymentRequest> {
+        self.requests.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_requests_are_fulfilled_or_expired() {
+        let mut requests = PaymentRequests::new();
+        let rent = requests
+            .add("addr1".to_string(), 1000, "rent".to_string(), 100, 60)
+            .unwrap();
+        assert_eq!(rent.expiry, 160);
+        requests
+            .add("addr2".to_string(), 500, "tip".to_string(), 100, 30)
+            .unwrap();
+        assert!(requests
+            .add("addr1".to_string(), 10, String::new(), 100, 60)
+            .is_err());
+        assert!(requests
+            .add("addr3".to_string(), 0, String::new(), 100, 60)
+            .is_err());
+
+        // Two payments complete the amount
+        let partial = requests.received("addr1", [1; 32], 600).unwrap();
+        assert_eq!(partial.status, RequestStatus::Pending);
+        let paid = requests.received("addr1", [2; 32], 400).unwrap();
+        assert_eq!(paid.status, RequestStatus::Fulfilled);
+        assert_eq!(paid.txid, Some([2; 32]));
+        assert!(requests.received("addr1", [3; 32], 400).is_none());
+
+        assert_eq!(requests.expire(129), 0);
+        assert_eq!(requests.expire(130), 1);
+        let statuses: Vec<RequestStatus> = requests.all().iter().map(|r| r.status).collect();
+        assert_eq!(
+            statuses,
+            vec![RequestStatus::Fulfilled, RequestStatus::Expired]
+        );
+    }
+}
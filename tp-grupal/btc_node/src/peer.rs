@@ -0,0 +1,691 @@
+use std::{
+    io::Write,
+    mem,
+    net::{Shutdown, SocketAddr},
+    sync::Arc,
+    thread,
+    time::Instant,
+};
+
+use crate::{
+    api::NodeApi,
+    bitcoin_node::Node,
+    blockchain::txs::Tx,
+    constants::{
+        BAN_THRESHOLD, INVALID_BLOCK_SCORE, INVALID_HEADERS_SCORE, MALFORMED_MESSAGE_SCORE,
+        MAX_BYTES_PER_SECOND, MAX_MESSAGES_PER_SECOND,
+    },
+    log_file::{log, log_error},
+    message::{
+        block::BlockMessage,
+        compact_size::CompactSize,
+        fee_filter::FeeFilterMessage,
+        get_data::GetDataMessage,
+        get_headers::GetHeadersMessage,
+        headers::HeadersMessage,
+        inv::InvMessage,
+        inventory::{Inventory, TypeIdentifier},
+        pong::PongMessage,
+        tx::TxMessage,
+        Message,
+    },
+    message_header::{message_header_error::MessageHeaderError, MessageHeader},
+    protocol_error::ProtocolError,
+    rate_limiter::RateLimiter,
+    session::{Capture, SessionRecord},
+    transport::PeerStream,
+    utils::{bitcoin_address_to_pkhash, bytes_to_hex_string},
+};
+
+/// Stage of the message loop of a peer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PeerPhase {
+    /// Right after the handshake, only the headers and pings are answered
+    SyncingHeaders,
+    /// Every message is handled
+    Listening,
+    /// The connection was closed, or the peer misbehaved too much
+    Disconnected,
+}
+
+/// Connection with a peer whose messages are handled: its stream, the start height of its
+/// version message, the answers queued for it and how much it misbehaved.
+/// The handlers queue the answers, they are sent together after every message.
+#[derive(Debug)]
+pub struct Peer {
+    /// None for the peers of a replayed session
+    stream: Option<PeerStream>,
+    addr: SocketAddr,
+    height: i32,
+    send_queue: Vec<u8>,
+    ban_score: u32,
+    phase: PeerPhase,
+    limiter: RateLimiter,
+}
+
+impl Peer {
+    /// Peer that completed the handshake, `height` is the start height of its version message.
+    pub fn new(stream: PeerStream, height: i32) -> Result<Peer, ProtocolError> {
+        let addr = stream.peer_addr()?;
+        Ok(Peer {
+            stream: Some(stream),
+            addr,
+            height,
+            send_queue: vec![],
+            ban_score: 0,
+            phase: PeerPhase::SyncingHeaders,
+            limiter: RateLimiter::new(MAX_MESSAGES_PER_SECOND, MAX_BYTES_PER_SECOND),
+        })
+    }
+
+    /// Registered peer, with the start height it sent in the handshake.
+    pub fn registered(stream: PeerStream, node: &Node) -> Result<Peer, ProtocolError> {
+        let height = node
+            .register
+            .read()?
+            .peer_version(&stream)
+            .map(|version| version.start_height())
+            .unwrap_or(0);
+        Peer::new(stream, height)
+    }
+
+    /// Peer of a recorded session, its answers are dropped.
+    fn replayed(addr: SocketAddr, height: i32) -> Peer {
+        Peer {
+            stream: None,
+            addr,
+            height,
+            send_queue: vec![],
+            ban_score: 0,
+            phase: PeerPhase::Listening,
+            limiter: RateLimiter::new(u32::MAX, u32::MAX),
+        }
+    }
+
+    /// Gives back the stream, to register the peer once the headers are synced.
+    pub fn into_stream(self) -> Option<PeerStream> {
+        self.stream
+    }
+
+    pub fn phase(&self) -> PeerPhase {
+        self.phase
+    }
+
+    pub fn ban_score(&self) -> u32 {
+        self.ban_score
+    }
+
+    /// Answers the headers of the peer after the getheaders of the handshake, until it sends
+    /// less than 2000 or it pinged twice.
+    pub fn sync_headers(&mut self, node: &Node) -> Result<(), ProtocolError> {
+        let mut pings_available = 2;
+        loop {
+            let (m, _) = self.read_message(node)?;
+
+            if let Some(stream) = &self.stream {
+                node.register.read()?.log_message(stream, &m);
+            }
+
+            match m {
+                Message::Headers(msg) => {
+                    let size = self.handle_headers(node, msg)?;
+                    self.flush(node)?;
+                    if size < 2000 {
+                        break;
+                    }
+                }
+                Message::Ping(ping) => {
+                    PongMessage::new(ping.get_nonce()).write_to(&mut self.send_queue)?;
+                    self.flush(node)?;
+                    pings_available -= 1;
+                    if pings_available == 0 {
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Handles the messages of the registered peer until the connection is closed.
+    pub fn listen(mut self, node: Arc<Node>) -> Result<(), ProtocolError> {
+        let stream = match &self.stream {
+            Some(stream) => stream.try_clone()?,
+            None => return Ok(()),
+        };
+        stream.set_read_timeout(None)?;
+        // Without transaction relay there are no fees to filter nor mempool to fill, and until
+        // the node is synced the mempool is requested when the sync ends
+        if !node.config.blocksonly {
+            self.send_fee_filter(&node)?;
+            if node.sync.state()?.relays_txs() {
+                self.request_mempool(&node)?;
+            }
+        }
+
+        node.register.write()?.start_listening(&stream);
+        self.phase = PeerPhase::Listening;
+
+        while self.phase == PeerPhase::Listening {
+            let m = match self.read_message(&node) {
+                Err(e) if is_disconnection(&e) => {
+                    if let Ok(mut r) = node.register.write() {
+                        r.log_error(&stream, e);
+                        r.disconnect(&stream);
+                    };
+                    self.phase = PeerPhase::Disconnected;
+                    return Err(ProtocolError::ConnectionError(
+                        "The connection with the peer was closed".to_string(),
+                    ));
+                }
+                Err(_) => {
+                    if let Ok(mut r) = node.register.write() {
+                        r.record_dropped(&stream);
+                        r.log_ignored_summary(Instant::now());
+                    };
+                    self.misbehaved(&node, MALFORMED_MESSAGE_SCORE, "malformed message");
+                    continue;
+                }
+                Ok((m, size)) => {
+                    thread::sleep(self.limiter.consume(size));
+                    (m, size)
+                }
+            };
+            let (m, size) = m;
+
+            // The answers to the previous message are accounted along with this one
+            if let Ok(mut r) = node.register.write() {
+                r.log_message(&stream, &m);
+                r.record_traffic(&stream, Some((&m, size)));
+                r.log_ignored_summary(Instant::now());
+            };
+
+            // The answers are queued as a whole in the writer of the peer
+            let mut res = match self.dispatch(&node, m) {
+                Ok(Some(other)) => {
+                    node.register
+                        .write()?
+                        .record_ignored(&stream, other.command());
+                    Ok(())
+                }
+                Ok(None) => Ok(()),
+                Err(e) => Err(e),
+            };
+
+            if res.is_ok() {
+                res = self.flush(&node);
+            }
+            self.send_queue.clear();
+
+            if let Err(e) = res {
+                if let Ok(r) = node.register.write() {
+                    r.log_error(&stream, e);
+                };
+            };
+        }
+        Err(ProtocolError::ConnectionError(
+            "The peer was disconnected for misbehaving".to_string(),
+        ))
+    }
+
+    /// Feeds the messages of a recorded session to the handlers, in the order they were read.
+    /// The answers are dropped, as there are no peers to send them to.
+    /// Returns how many of the messages were handled without errors.
+    pub fn replay(node: &Arc<Node>, records: Vec<SessionRecord>) -> usize {
+        let mut handled = 0;
+        for (i, record) in records.into_iter().enumerate() {
+            let mut peer = Peer::replayed(record.peer, record.peer_height);
+            let res =
+                Message::read_from(&mut &record.bytes[..]).and_then(|m| peer.dispatch(node, m));
+            match res {
+                Ok(_) => handled += 1,
+                Err(e) => log_error(format!(
+                    "REPLAY: message {} of {} at {:?}: {}",
+                    i, record.peer, record.elapsed, e
+                )),
+            }
+        }
+        handled
+    }
+
+    /// Reads a message of the peer, recording its bytes if the node records the session.
+    fn read_message(&mut self, node: &Node) -> Result<(Message, usize), ProtocolError> {
+        let stream = match &mut self.stream {
+            Some(stream) => stream,
+            None => {
+                return Err(ProtocolError::ConnectionError(
+                    "The peer has no connection".to_string(),
+                ))
+            }
+        };
+        let recorder = match &node.recorder {
+            Some(recorder) => recorder,
+            None => return Message::read_from_with_size(stream),
+        };
+
+        let mut capture = Capture::new(&mut *stream);
+        let res = Message::read_from_with_size(&mut capture);
+        let bytes = capture.into_bytes();
+        if !bytes.is_empty() {
+            recorder.record(self.addr, self.height, &bytes);
+        }
+        res
+    }
+
+    /// Sends the queued answers. While syncing the headers the peer isn't registered yet,
+    /// so they are written directly to the stream.
+    fn flush(&mut self, node: &Node) -> Result<(), ProtocolError> {
+        let queued = mem::take(&mut self.send_queue);
+        match &mut self.stream {
+            Some(stream) if !queued.is_empty() => match self.phase {
+                PeerPhase::SyncingHeaders => Ok(stream.write_all(&queued)?),
+                _ => node.register.write()?.send_to(stream, queued),
+            },
+            _ => Ok(()),
+        }
+    }
+
+    /// Adds the points to the ban score, past BAN_THRESHOLD the peer is disconnected.
+    fn misbehaved(&mut self, node: &Node, points: u32, reason: &str) {
+        self.ban_score += points;
+        log(format!(
+            "peer {} misbehaved ({}), ban score {}",
+            self.addr, reason, self.ban_score
+        ));
+        if self.ban_score < BAN_THRESHOLD {
+            return;
+        }
+
+        let registered = self.phase == PeerPhase::Listening;
+        self.phase = PeerPhase::Disconnected;
+        if let Some(stream) = &self.stream {
+            let _ = stream.shutdown(Shutdown::Both);
+            if registered {
+                if let Ok(mut register) = node.register.write() {
+                    register.disconnect(stream);
+                }
+            }
+        }
+    }
+
+    /// Handles a message of the peer, queueing the answers.
+    /// The message is given back if there is no handler for it.
+    fn dispatch(&mut self, node: &Arc<Node>, m: Message) -> Result<Option<Message>, ProtocolError> {
+        if !node.sync.state()?.expects(m.command()) {
+            return Ok(Some(m));
+        }
+        match m {
+            Message::Headers(h) => self.handle_headers(node, h).map(|_| ())?,
+            Message::GetData(g) => self.handle_get_data(node, g)?,
+            Message::Ping(ping) => {
+                PongMessage::new(ping.get_nonce()).write_to(&mut self.send_queue)?
+            }
+            Message::Inv(inv) => self.handle_inv(node, inv)?,
+            Message::Block(block) => self.handle_block(node, block)?,
+            // In blocksonly mode the transactions are ignored like the unhandled messages
+            Message::Tx(tx_msg) if !node.config.blocksonly => self.handle_tx(node, tx_msg)?,
+            Message::GetHeaders(gh) => self.handle_get_headers(node, gh)?,
+            Message::Mempool => self.handle_mempool(node)?,
+            other => return Ok(Some(other)),
+        }
+        Ok(None)
+    }
+
+    /// Tells the peer our min relay fee, so it doesn't announce the transactions we would reject.
+    fn send_fee_filter(&mut self, node: &Node) -> Result<(), ProtocolError> {
+        let supported = match &self.stream {
+            Some(stream) => node
+                .register
+                .read()?
+                .peer_version(stream)
+                .map(|version| version.supports_fee_filter())
+                .unwrap_or(false),
+            None => false,
+        };
+        if !supported {
+            return Ok(());
+        }
+
+        FeeFilterMessage::from_fee_rate(node.config.min_relay_fee)
+            .write_to(&mut self.send_queue)?;
+        if self.flush(node).is_err() {
+            log_error("Error sending feefilter message".to_string());
+        };
+        Ok(())
+    }
+
+    fn request_mempool(&mut self, node: &Node) -> Result<(), ProtocolError> {
+        MessageHeader::new("mempool".to_string(), vec![])?.write_to(&mut self.send_queue)?;
+        if self.flush(node).is_err() {
+            log_error("Error sending mempool message".to_string());
+        };
+        Ok(())
+    }
+
+    fn handle_mempool(&mut self, node: &Node) -> Result<(), ProtocolError> {
+        if node.config.readonly {
+            return Ok(());
+        }
+        let mut inventory = vec![];
+        for hash in node.mempool.read()?.keys() {
+            inventory.push(Inventory::new(TypeIdentifier::MsgTx, *hash));
+        }
+        let inv_message = InvMessage {
+            count: CompactSize::new_from_usize(inventory.len()),
+            inventory,
+        };
+        inv_message.write_to(&mut self.send_queue)
+    }
+
+    fn handle_get_headers(
+        &mut self,
+        node: &Node,
+        getheaders: GetHeadersMessage,
+    ) -> Result<(), ProtocolError> {
+        if getheaders.block_header_hashes.is_empty() || node.config.readonly {
+            return Ok(());
+        }
+        let headers = node
+            .blockchain
+            .lock()?
+            .get_headers(getheaders.block_header_hashes[0]);
+        HeadersMessage::new(headers).write_to(&mut self.send_queue)
+    }
+
+    fn handle_tx(&mut self, node: &Arc<Node>, tx_msg: TxMessage) -> Result<(), ProtocolError> {
+        let txid = tx_msg.tx.get_tx_id();
+        node.in_flight.lock()?.received(&txid);
+        // The inputs of the relayed transactions can't be checked until the chain is synced
+        if node.ibd.is_active() || node.is_known_txid(&txid)? {
+            return Ok(());
+        }
+        let accepted = tx_msg
+            .tx
+            .check_standard()
+            .and_then(|_| node.check_min_relay_fee(&tx_msg.tx));
+        if let Err(e) = accepted {
+            log(format!(
+                "Rejected the transaction {}: {}",
+                bytes_to_hex_string(&txid),
+                e
+            ));
+            node.recently_rejected.lock()?.insert(txid);
+            return Ok(());
+        }
+
+        let mut mempool = node.mempool.write()?;
+        if mempool.contains_key(&txid) {
+            return Ok(());
+        }
+        if let Err(e) = mempool.check_chain_limits(&tx_msg.tx) {
+            log(format!(
+                "Rejected the transaction {}: {}",
+                bytes_to_hex_string(&txid),
+                e
+            ));
+            drop(mempool);
+            node.recently_rejected.lock()?.insert(txid);
+            return Ok(());
+        }
+        let tx = mempool.insert(txid, tx_msg.tx);
+        drop(mempool);
+        if let Err(e) = node.announce_transaction(txid) {
+            log_error(format!("Couldn't re-broadcast the transaction: {:?}", e));
+        };
+
+        let addresses = node.wallet_addresses.read()?.clone();
+        let effects = node.add_pending_effects(&tx, &addresses)?;
+        if effects.is_empty() {
+            return Ok(());
+        }
+        node.record_request_payments(txid, &effects)?;
+
+        let transaction = node.tx_with_fee(&tx)?;
+        let payer_addr = node
+            .blockchain
+            .lock()?
+            .utxo
+            .get_outpoint_address(&transaction.tx_in[0].previous_output);
+        for (addr, _) in effects {
+            node.sender.send(NodeApi::NewTx(
+                transaction.clone(),
+                payer_addr.clone(),
+                addr.clone(),
+            ))?;
+
+            node.wallet_txs.write()?.insert(txid, addr);
+        }
+
+        Ok(())
+    }
+
+    /// Adds the headers to the chain and reports the height reached against the best height known of the peer.
+    fn handle_headers(
+        &mut self,
+        node: &Node,
+        mut msg: HeadersMessage,
+    ) -> Result<usize, ProtocolError> {
+        let mut blockchain = node.blockchain.lock()?;
+
+        for query in msg.headers.drain(..) {
+            if let Err(e) = blockchain.push(query) {
+                drop(blockchain);
+                self.misbehaved(node, INVALID_HEADERS_SCORE, "invalid headers");
+                return Err(e);
+            }
+        }
+
+        if msg.count.into_inner() > 0 {
+            let height = blockchain.get_height();
+            node.sender
+                .send(NodeApi::HeaderSyncProgress(height, self.height.max(height)))?;
+        }
+
+        if msg.count.into_inner() == 2000 {
+            let get_headers = GetHeadersMessage::new(blockchain.get_last_header_hash());
+            get_headers.write_to(&mut self.send_queue)?;
+        }
+
+        Ok(msg.count.into_inner())
+    }
+
+    fn handle_get_data(
+        &mut self,
+        node: &Node,
+        getdata: GetDataMessage,
+    ) -> Result<(), ProtocolError> {
+        // An observer doesn't serve the transactions nor the blocks
+        if node.config.readonly {
+            return Ok(());
+        }
+        let mut requested_blocks = vec![];
+        for inv in getdata.inventory {
+            match inv.type_identifier {
+                // Witness data is not parsed, so the wtxid of every transaction in the mempool is its txid
+                TypeIdentifier::MsgTx | TypeIdentifier::MsgWtx => {
+                    let bytes = node.mempool.read()?.get_bytes(&inv.hash);
+                    if let Some(bytes) = bytes {
+                        self.queue_payload("tx", &bytes)?;
+                    };
+                }
+                TypeIdentifier::MsgBlock => requested_blocks.push(inv.hash),
+                TypeIdentifier::MsgCmptBlock => {}
+                TypeIdentifier::MsgFilteredBlock => {}
+            }
+        }
+
+        if !requested_blocks.is_empty() {
+            let payloads = node.blockchain.lock()?.get_block_payloads(requested_blocks);
+            for payload in payloads {
+                self.queue_payload("block", &payload)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Queues a message whose payload was serialized before.
+    fn queue_payload(&mut self, command: &str, payload: &[u8]) -> Result<(), ProtocolError> {
+        MessageHeader::new(command.to_string(), payload.to_vec())?
+            .write_to(&mut self.send_queue)?;
+        self.send_queue.extend_from_slice(payload);
+        Ok(())
+    }
+
+    /// Requests the advertised items that aren't known yet, skipping the ones already requested to another peer.
+    fn handle_inv(&mut self, node: &Node, inv: InvMessage) -> Result<(), ProtocolError> {
+        let mut to_request: Vec<Inventory> = vec![];
+        let now = Instant::now();
+        let mut in_flight = node.in_flight.lock()?;
+        in_flight.expire(now);
+
+        for inv in inv.inventory {
+            let wanted = match inv.type_identifier {
+                TypeIdentifier::MsgTx | TypeIdentifier::MsgWtx => {
+                    !node.config.blocksonly
+                        && !node.ibd.is_active()
+                        && !node.mempool.read()?.contains_key(&inv.hash)
+                        && !node.is_known_txid(&inv.hash)?
+                }
+                TypeIdentifier::MsgBlock => true,
+                _ => false,
+            };
+
+            if wanted && in_flight.try_request(inv.hash, now) {
+                to_request.push(Inventory::new(inv.type_identifier, inv.hash));
+            }
+        }
+        drop(in_flight);
+
+        if !to_request.is_empty() {
+            return GetDataMessage::new_from_inventory(to_request).write_to(&mut self.send_queue);
+        };
+
+        Ok(())
+    }
+
+    fn handle_block(
+        &mut self,
+        node: &Arc<Node>,
+        block_msg: BlockMessage,
+    ) -> Result<(), ProtocolError> {
+        node.in_flight
+            .lock()?
+            .received(&block_msg.block_header.hash());
+        let (block, height) = {
+            let mut blockchain = node.blockchain.lock()?;
+            if let Err(e) = blockchain.check_block_signatures(&block_msg.txns) {
+                log(format!(
+                    "Rejected the block {}: {}",
+                    bytes_to_hex_string(&block_msg.block_header.hash()),
+                    e
+                ));
+                drop(blockchain);
+                self.misbehaved(node, INVALID_BLOCK_SCORE, "invalid block");
+                return Err(e);
+            }
+            let block = blockchain.push_full_block(block_msg)?;
+            // Only the blocks that extended the chain are announced, not forks nor repeated ones
+            let height =
+                (blockchain.get_last_header_hash() == block.hash).then(|| blockchain.get_height());
+            (block, height)
+        };
+
+        let mut wallet_tx = node.wallet_txs.write()?;
+        let mut mempool = node.mempool.write()?;
+
+        let txs = block.txs.clone().unwrap().txns;
+
+        let mut confirmed = node.recently_confirmed.lock()?;
+        let mut rebroadcast = node.rebroadcast.lock()?;
+        for tx in txs.iter() {
+            confirmed.insert(tx.tx_id);
+            rebroadcast.remove(&tx.tx_id);
+        }
+        drop(rebroadcast);
+        drop(confirmed);
+
+        let mut updated_addresses = vec![];
+        for tx in txs.iter() {
+            if let Some(addr) = wallet_tx.remove(&tx.tx_id) {
+                node.sender
+                    .send(NodeApi::ConfirmedTx(tx.tx_id, addr.clone()))?;
+                if !updated_addresses.contains(&addr) {
+                    updated_addresses.push(addr);
+                }
+            }
+
+            node.confirm_pending_effects(&tx.tx_id)?;
+            mempool.remove(&tx.tx_id);
+        }
+        drop(wallet_tx);
+        drop(mempool);
+
+        // The wallet is refreshed once when the initial block download ends, not after every block
+        if node.ibd.is_active() {
+            if node.update_ibd()? {
+                node.request_mempools()?;
+                crate::wallet_handlers::refresh_wallet_addresses(node)?;
+            }
+            return Ok(());
+        }
+
+        if let Some(height) = height {
+            let wallet_relevant = !updated_addresses.is_empty() || pays_wallet(node, &txs)?;
+            node.sender.send(NodeApi::NewBlock(
+                height,
+                block.hash,
+                txs.len(),
+                block.timestamp,
+                wallet_relevant,
+            ))?;
+        }
+
+        for addr in updated_addresses {
+            crate::wallet_handlers::get_coins(addr.clone(), node)?;
+            crate::wallet_handlers::get_history(addr, node)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Whether any of the transactions pays to an address of the wallet.
+fn pays_wallet(node: &Node, txs: &[Arc<Tx>]) -> Result<bool, ProtocolError> {
+    let mut pkhashes = vec![];
+    for addr in node.wallet_addresses.read()?.iter() {
+        pkhashes.push(bitcoin_address_to_pkhash(addr)?);
+    }
+    Ok(txs.iter().any(|tx| {
+        tx.tx_out.iter().any(|out| {
+            pkhashes
+                .iter()
+                .any(|hash| out.pkscript.can_be_spent_by(hash))
+        })
+    }))
+}
+
+/// Whether the error means that the stream can't be read anymore, like when the peer closed it.
+fn is_disconnection(error: &ProtocolError) -> bool {
+    matches!(
+        error,
+        ProtocolError::ConnectionError(_)
+            | ProtocolError::IOError(_)
+            | ProtocolError::MessageHeaderError(MessageHeaderError::IOError(_))
+            | ProtocolError::MessageHeaderError(MessageHeaderError::WrongNetwork(_))
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replayed_peers_start_listening() {
+        let peer = Peer::replayed("10.0.0.1:18333".parse().unwrap(), 100);
+        assert_eq!(peer.phase(), PeerPhase::Listening);
+        assert_eq!(peer.ban_score(), 0);
+    }
+}
@@ -0,0 +1,129 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Write},
+    net::SocketAddr,
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use rand::seq::SliceRandom;
+
+use crate::{log_file::log, protocol_error::ProtocolError, utils::resolve_peer};
+
+/// Resolves every dns seed in its own thread and returns the peers found before `timeout`, shuffled.
+/// Seeds that fail or take too long are skipped.
+pub fn query_dns_seeds(seeds: &[String], default_port: u16, timeout: Duration) -> Vec<SocketAddr> {
+    let (tx, rx) = mpsc::channel();
+    for seed in seeds {
+        let tx = tx.clone();
+        let seed = seed.clone();
+        thread::spawn(move || {
+            let result = resolve_peer(&seed, default_port);
+            let _ = tx.send((seed, result));
+        });
+    }
+    drop(tx);
+
+    let deadline = Instant::now() + timeout;
+    let mut peers: Vec<SocketAddr> = Vec::new();
+    for _ in seeds {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        match rx.recv_timeout(remaining) {
+            Ok((_, Ok(found))) => {
+                for peer in found {
+                    if !peers.contains(&peer) {
+                        peers.push(peer);
+                    }
+                }
+            }
+            Ok((seed, Err(e))) => log(format!("DNS seed {} failed: {}", seed, e)),
+            Err(_) => {
+                log("Timed out waiting for the dns seeds".to_string());
+                break;
+            }
+        }
+    }
+
+    peers.shuffle(&mut rand::thread_rng());
+    peers
+}
+
+/// Alternates ipv4 and ipv6 addresses, so a broken network family doesn't stall the first attempts.
+pub fn interleave_families(peers: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let (v4, v6): (Vec<SocketAddr>, Vec<SocketAddr>) =
+        peers.into_iter().partition(|peer| peer.is_ipv4());
+
+    let mut interleaved = Vec::with_capacity(v4.len() + v6.len());
+    let mut v4 = v4.into_iter();
+    let mut v6 = v6.into_iter();
+    loop {
+        match (v4.next(), v6.next()) {
+            (None, None) => break,
+            (a, b) => interleaved.extend(a.into_iter().chain(b)),
+        }
+    }
+    interleaved
+}
+
+/// Reads the peers saved by `save_peers_file`, one address per line.
+pub fn read_peers_file(path: &str) -> Result<Vec<SocketAddr>, ProtocolError> {
+    let reader = BufReader::new(File::open(path)?);
+
+    let mut peers = Vec::new();
+    for line in reader.lines() {
+        if let Ok(peer) = line?.trim().parse::<SocketAddr>() {
+            peers.push(peer);
+        }
+    }
+    Ok(peers)
+}
+
+pub fn save_peers_file(path: &str, peers: &[SocketAddr]) -> Result<(), ProtocolError> {
+    let mut file = File::create(path)?;
+    for peer in peers {
+        writeln!(file, "{}", peer)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interleave_families() {
+        let peers: Vec<SocketAddr> = ["10.0.0.1:1", "10.0.0.2:1", "[::1]:1", "10.0.0.3:1"]
+            .iter()
+            .map(|peer| peer.parse().unwrap())
+            .collect();
+
+        let interleaved = interleave_families(peers.clone());
+        assert_eq!(interleaved, vec![peers[0], peers[2], peers[1], peers[3]]);
+    }
+
+    #[test]
+    fn test_peers_file_roundtrip_and_literal_seeds() {
+        let path = std::env::temp_dir().join("btc_node_test_peers.dat");
+        let path = path.to_str().unwrap();
+        let peers: Vec<SocketAddr> = vec![
+            "127.0.0.1:18333".parse().unwrap(),
+            "[::1]:18444".parse().unwrap(),
+        ];
+
+        save_peers_file(path, &peers).unwrap();
+        assert_eq!(read_peers_file(path).unwrap(), peers);
+        std::fs::remove_file(path).unwrap();
+
+        let seeds = vec!["10.0.0.1".to_string(), "10.0.0.2:18444".to_string()];
+        let mut found = query_dns_seeds(&seeds, 18333, Duration::from_secs(1));
+        found.sort();
+        assert_eq!(
+            found,
+            vec![
+                "10.0.0.1:18333".parse::<SocketAddr>().unwrap(),
+                "10.0.0.2:18444".parse().unwrap()
+            ]
+        );
+    }
+}
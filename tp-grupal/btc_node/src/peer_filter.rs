@@ -0,0 +1,128 @@
+use std::net::IpAddr;
+
+use crate::config::ConfigError;
+
+/// An ip address with a prefix length, written as `10.0.0.0/8` or as a plain address.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Subnet {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl Subnet {
+    pub fn parse(value: &str) -> Result<Subnet, ConfigError> {
+        let error = || ConfigError::ParsingError(format!("subnet {}", value));
+
+        let (addr, prefix) = match value.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (value, None),
+        };
+        let addr: IpAddr = addr.parse().map_err(|_| error())?;
+        let addr = normalize(addr);
+
+        let max_prefix = match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        let prefix = match prefix {
+            Some(prefix) => prefix.parse::<u8>().map_err(|_| error())?,
+            None => max_prefix,
+        };
+        if prefix > max_prefix {
+            return Err(error());
+        }
+
+        Ok(Subnet { addr, prefix })
+    }
+
+    pub fn contains(&self, addr: &IpAddr) -> bool {
+        match (self.addr, normalize(*addr)) {
+            (IpAddr::V4(net), IpAddr::V4(addr)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(addr) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(addr)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(addr) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// The node stores ipv4 peers as mapped ipv6 addresses, so they are compared as ipv4.
+fn normalize(addr: IpAddr) -> IpAddr {
+    match addr {
+        IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+            Some(v4) => IpAddr::V4(v4),
+            None => IpAddr::V6(v6),
+        },
+        v4 => v4,
+    }
+}
+
+/// Decides which peers the node may dial or accept.
+/// When the allowlist is not empty only the peers in it are allowed, and the denylist always wins.
+#[derive(Debug, Clone, Default)]
+pub struct PeerFilter {
+    allowlist: Vec<Subnet>,
+    denylist: Vec<Subnet>,
+}
+
+impl PeerFilter {
+    pub fn new(allowlist: Vec<Subnet>, denylist: Vec<Subnet>) -> PeerFilter {
+        PeerFilter {
+            allowlist,
+            denylist,
+        }
+    }
+
+    /// Parses a comma separated list of subnets.
+    pub fn parse_list(value: &str) -> Result<Vec<Subnet>, ConfigError> {
+        value
+            .split(',')
+            .filter(|s| !s.is_empty())
+            .map(Subnet::parse)
+            .collect()
+    }
+
+    pub fn is_allowed(&self, addr: &IpAddr) -> bool {
+        if self.denylist.iter().any(|subnet| subnet.contains(addr)) {
+            return false;
+        }
+        self.allowlist.is_empty() || self.allowlist.iter().any(|subnet| subnet.contains(addr))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    #[test]
+    fn test_subnet_contains_mapped_addresses() {
+        let subnet = Subnet::parse("192.168.0.0/16").unwrap();
+        let inside = IpAddr::V6(Ipv4Addr::new(192, 168, 3, 4).to_ipv6_mapped());
+        let outside = IpAddr::V4(Ipv4Addr::new(192, 169, 0, 1));
+
+        assert!(subnet.contains(&inside));
+        assert!(!subnet.contains(&outside));
+        assert!(Subnet::parse("10.0.0.0/33").is_err());
+        assert!(Subnet::parse("::/0")
+            .unwrap()
+            .contains(&IpAddr::V6(Ipv6Addr::LOCALHOST)));
+    }
+
+    #[test]
+    fn test_denylist_wins_over_allowlist() {
+        let filter = PeerFilter::new(
+            PeerFilter::parse_list("10.0.0.0/8").unwrap(),
+            PeerFilter::parse_list("10.0.0.1,10.0.0.2").unwrap(),
+        );
+
+        assert!(filter.is_allowed(&"10.1.2.3".parse().unwrap()));
+        assert!(!filter.is_allowed(&"10.0.0.2".parse().unwrap()));
+        assert!(!filter.is_allowed(&"8.8.8.8".parse().unwrap()));
+        assert!(PeerFilter::default().is_allowed(&"8.8.8.8".parse().unwrap()));
+    }
+}
@@ -0,0 +1,72 @@
+use std::{
+    io::Write,
+    sync::mpsc::{self, Sender},
+    thread,
+    time::Duration,
+};
+
+use crate::{log_file::log_error, protocol_error::ProtocolError, transport::PeerStream};
+
+/// Owns the writing half of a peer connection.
+/// Every sender queues whole serialized messages and a dedicated thread writes them in order,
+/// so messages from different threads never interleave and a slow peer only blocks its own thread.
+#[derive(Debug)]
+pub struct PeerWriter {
+    queue: Sender<Vec<u8>>,
+}
+
+impl PeerWriter {
+    /// The writer thread stops when a write fails or takes longer than `write_timeout`.
+    pub fn spawn(
+        mut stream: PeerStream,
+        write_timeout: Duration,
+    ) -> Result<PeerWriter, ProtocolError> {
+        stream.set_write_timeout(Some(write_timeout))?;
+        let (queue, rx) = mpsc::channel::<Vec<u8>>();
+
+        thread::spawn(move || {
+            for bytes in rx {
+                if let Err(e) = stream.write_all(&bytes) {
+                    log_error(format!(
+                        "Stopped writing to peer {:?}: {}",
+                        stream.peer_addr(),
+                        e
+                    ));
+                    return;
+                }
+            }
+        });
+
+        Ok(PeerWriter { queue })
+    }
+
+    pub fn send(&self, bytes: Vec<u8>) -> Result<(), ProtocolError> {
+        self.queue
+            .send(bytes)
+            .map_err(|_| ProtocolError::ConnectionError("The peer writer stopped".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io::Read,
+        net::{TcpListener, TcpStream},
+    };
+
+    #[test]
+    fn test_queued_messages_arrive_in_order() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (mut remote, _) = listener.accept().unwrap();
+
+        let writer = PeerWriter::spawn(PeerStream::plain(stream), Duration::from_secs(1)).unwrap();
+        writer.send(vec![1, 2]).unwrap();
+        writer.send(vec![3]).unwrap();
+
+        let mut received = [0u8; 3];
+        remote.read_exact(&mut received).unwrap();
+        assert_eq!(received, [1, 2, 3]);
+    }
+}
@@ -1,4 +1,6 @@
-use crate::{config::ConfigError, message_header::message_header_error::MessageHeaderError};
+use crate::{
+    config::ConfigError, keys::WifError, message_header::message_header_error::MessageHeaderError,
+};
 
 use std::{
     error::Error,
@@ -72,3 +74,9 @@ impl From<ConfigError> for ProtocolError {
         ProtocolError::ConfigError(error)
     }
 }
+
+impl From<WifError> for ProtocolError {
+    fn from(error: WifError) -> Self {
+        ProtocolError::Error(error.to_string())
+    }
+}
@@ -0,0 +1,82 @@
+use std::time::{Duration, Instant};
+
+/// Token buckets for the messages and bytes per second read from a peer.
+/// Each bucket holds up to one second of budget and refills continuously.
+#[derive(Debug)]
+pub struct RateLimiter {
+    messages_per_second: f64,
+    bytes_per_second: f64,
+    messages: f64,
+    bytes: f64,
+    last_refill: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(messages_per_second: u32, bytes_per_second: u32) -> RateLimiter {
+        RateLimiter {
+            messages_per_second: messages_per_second as f64,
+            bytes_per_second: bytes_per_second as f64,
+            messages: messages_per_second as f64,
+            bytes: bytes_per_second as f64,
+            last_refill: Instant::now(),
+        }
+    }
+
+    /// Takes the budget of a message of `size` bytes.
+    /// It returns how long the caller has to wait before handling it, zero if the peer is within its limits.
+    pub fn consume(&mut self, size: usize) -> Duration {
+        self.refill(Instant::now());
+
+        self.messages -= 1.0;
+        self.bytes -= size as f64;
+
+        let messages_wait = -self.messages / self.messages_per_second;
+        let bytes_wait = -self.bytes / self.bytes_per_second;
+        let wait = messages_wait.max(bytes_wait);
+
+        if wait > 0.0 {
+            Duration::from_secs_f64(wait)
+        } else {
+            Duration::ZERO
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.last_refill = now;
+
+        self.messages =
+            (self.messages + elapsed * self.messages_per_second).min(self.messages_per_second);
+        self.bytes = (self.bytes + elapsed * self.bytes_per_second).min(self.bytes_per_second);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_within_limits_does_not_wait() {
+        let mut limiter = RateLimiter::new(10, 1000);
+        for _ in 0..10 {
+            assert_eq!(limiter.consume(100), Duration::ZERO);
+        }
+    }
+
+    #[test]
+    fn test_too_many_messages_has_to_wait() {
+        let mut limiter = RateLimiter::new(10, 1000);
+        for _ in 0..10 {
+            limiter.consume(0);
+        }
+        let wait = limiter.consume(0);
+        assert!(wait > Duration::from_millis(90) && wait <= Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_too_many_bytes_has_to_wait() {
+        let mut limiter = RateLimiter::new(10, 1000);
+        let wait = limiter.consume(3000);
+        assert!(wait > Duration::from_millis(1990) && wait <= Duration::from_secs(2));
+    }
+}
@@ -1,15 +1,100 @@
 use crate::{
     blockchain::utxo_set::Output,
-    constants::{SIGHASH_ALL, TX_VERSION},
+    constants::{
+        FINAL_SEQUENCE, LOCKTIME_SEQUENCE, LOCKTIME_THRESHOLD, MAX_BLOCK_SIZE,
+        MAX_STANDARD_TX_WEIGHT, RBF_SEQUENCE, SIGHASH_ALL, SIGHASH_ANYONECANPAY, SIGHASH_NONE,
+        SIGHASH_SINGLE, TX_VERSION, WITNESS_SCALE_FACTOR,
+    },
+    keys::PrivateKey,
     message::compact_size::CompactSize,
+    message_header::network,
     protocol_error::ProtocolError,
-    utils::wif_to_private_key,
+    script::{is_standard_signature_script, PubKeyScript},
+    utils::hash160,
 };
 
 use bitcoin_hashes::{sha256d, Hash};
-use secp256k1::{ecdsa, Message, PublicKey, Secp256k1, SecretKey};
+use secp256k1::{ecdsa, Message, Secp256k1};
+
+use std::{collections::HashSet, io::Read, num::ParseIntError};
+
+// Smallest serialized input: the outpoint, an empty script and the sequence
+const MIN_TX_IN_SIZE: usize = 41;
+// Smallest serialized output: the value and an empty script
+const MIN_TX_OUT_SIZE: usize = 9;
+// Smallest serialized transaction: the version, both counts and the lock time
+pub const MIN_TX_SIZE: usize = 10;
+// Signed P2PKH input with a compressed key and a P2PKH output
+const P2PKH_TX_IN_SIZE: usize = 148;
+const P2PKH_TX_OUT_SIZE: usize = 34;
+
+/// Virtual size of a transaction with P2PKH inputs and outputs, to estimate its fee before it is built.
+pub fn estimated_p2pkh_vsize(inputs: usize, outputs: usize) -> usize {
+    MIN_TX_SIZE + inputs * P2PKH_TX_IN_SIZE + outputs * P2PKH_TX_OUT_SIZE
+}
+
+/// Reads the count of a list whose items take at least `item_size` bytes each.
+/// Counts of more items than fit in `max_size` bytes are a connection error, the peer that
+/// sent them should be dropped.
+pub fn read_count(
+    stream: &mut dyn Read,
+    item_size: usize,
+    max_size: usize,
+    name: &str,
+) -> Result<CompactSize, ProtocolError> {
+    let count = CompactSize::read_from(stream)?;
+    if count.into_inner() > max_size / item_size {
+        return Err(ProtocolError::ConnectionError(format!(
+            "{} count of {} doesn't fit in {} bytes",
+            name, count, max_size
+        )));
+    }
+    Ok(count)
+}
+
+/// Lock time, input sequences and sighash type of a new transaction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TxOptions {
+    /// Block height or unix timestamp before which the transaction can't be mined
+    pub lock_time: u32,
+    /// Signals that the transaction can be replaced by one paying a bigger fee
+    pub rbf: bool,
+    /// Sequence of each input, in order, overriding the one implied by rbf and the lock time
+    pub sequences: Vec<u32>,
+    /// SIGHASH_ALL, SIGHASH_NONE or SIGHASH_SINGLE, optionally with SIGHASH_ANYONECANPAY
+    pub sighash_type: u8,
+}
+
+impl Default for TxOptions {
+    fn default() -> Self {
+        TxOptions {
+            lock_time: 0,
+            rbf: false,
+            sequences: vec![],
+            sighash_type: SIGHASH_ALL,
+        }
+    }
+}
 
-use std::{io::Read, num::ParseIntError};
+impl TxOptions {
+    pub fn sequence(&self, input: usize) -> u32 {
+        match self.sequences.get(input) {
+            Some(sequence) => *sequence,
+            None if self.rbf => RBF_SEQUENCE,
+            // A final sequence in every input disables the lock time
+            None if self.lock_time > 0 => LOCKTIME_SEQUENCE,
+            None => FINAL_SEQUENCE,
+        }
+    }
+}
+
+/// Hashes of a transaction that the BIP143 sighash of every input reuses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SegwitMidstate {
+    pub hash_prevouts: [u8; 32],
+    pub hash_sequence: [u8; 32],
+    pub hash_outputs: [u8; 32],
+}
 
 #[derive(Debug, Clone)]
 pub struct RawTransaction {
@@ -34,16 +119,24 @@ impl RawTransaction {
     }
 
     pub fn read_from(stream: &mut dyn Read) -> Result<RawTransaction, ProtocolError> {
+        RawTransaction::read_with_limit(stream, MAX_BLOCK_SIZE as usize)
+    }
+
+    /// Reads a transaction of at most `max_size` bytes, the size of the payload it comes in.
+    pub fn read_with_limit(
+        stream: &mut dyn Read,
+        max_size: usize,
+    ) -> Result<RawTransaction, ProtocolError> {
         let mut version: [u8; 4] = [0; 4];
         stream.read_exact(&mut version)?;
 
-        let tx_in_count = CompactSize::read_from(stream)?;
+        let tx_in_count = read_count(stream, MIN_TX_IN_SIZE, max_size, "Input")?;
         let mut tx_in = Vec::new();
         for _i in 0..tx_in_count.into_inner() {
             tx_in.push(TxIn::read_from(stream)?);
         }
 
-        let tx_out_count = CompactSize::read_from(stream)?;
+        let tx_out_count = read_count(stream, MIN_TX_OUT_SIZE, max_size, "Output")?;
         let mut tx_out = Vec::new();
         for _i in 0..tx_out_count.into_inner() {
             tx_out.push(TxOut::read_from(stream)?);
@@ -63,28 +156,66 @@ impl RawTransaction {
     }
 
     pub fn to_bytes(&self) -> Vec<u8> {
-        let mut bytes = Vec::new();
-        bytes.extend_from_slice(&self.version.to_le_bytes());
-        bytes.extend_from_slice(&self.tx_in_count.to_le_bytes());
+        let mut bytes = Vec::with_capacity(self.serialized_size());
+        self.append_to(&mut bytes);
+        bytes
+    }
 
+    /// Serializes the transaction at the end of `bytes`.
+    pub fn append_to(&self, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(&self.version.to_le_bytes());
+        self.tx_in_count.append_to(bytes);
         for i in 0..self.tx_in_count.into_inner() {
-            bytes.extend_from_slice(&self.tx_in[i].to_bytes());
+            self.tx_in[i].append_to(bytes);
         }
 
-        bytes.extend_from_slice(&self.tx_out_count.to_le_bytes());
+        self.tx_out_count.append_to(bytes);
         for i in 0..self.tx_out_count.into_inner() {
-            bytes.extend_from_slice(&self.tx_out[i].to_bytes());
+            self.tx_out[i].append_to(bytes);
         }
 
         bytes.extend_from_slice(&self.lock_time.to_le_bytes());
+    }
 
-        bytes
+    /// Bytes taken by the serialized transaction.
+    pub fn serialized_size(&self) -> usize {
+        8 + self.tx_in_count.size()
+            + self.tx_in.iter().map(TxIn::serialized_size).sum::<usize>()
+            + self.tx_out_count.size()
+            + self
+                .tx_out
+                .iter()
+                .map(TxOut::serialized_size)
+                .sum::<usize>()
+    }
+
+    /// Bytes of the serialized transaction, witnesses included.
+    pub fn size(&self) -> usize {
+        self.serialized_size()
+    }
+
+    /// Weight of the transaction (BIP 141). The transactions carry no witnesses yet,
+    /// so every byte is scaled.
+    pub fn weight(&self) -> usize {
+        self.serialized_size() * WITNESS_SCALE_FACTOR
+    }
+
+    /// Virtual size, the weight in bytes rounded up. Fee rates are per virtual byte.
+    pub fn vsize(&self) -> usize {
+        self.weight().div_ceil(WITNESS_SCALE_FACTOR)
     }
 
     pub fn get_tx_id(&self) -> [u8; 32] {
         sha256d::Hash::hash(&self.to_bytes()[..]).to_byte_array()
     }
 
+    /// Txid serialized in `scratch`, so the transactions of a block can share one buffer.
+    pub fn tx_id_with(&self, scratch: &mut Vec<u8>) -> [u8; 32] {
+        scratch.clear();
+        self.append_to(scratch);
+        sha256d::Hash::hash(&scratch[..]).to_byte_array()
+    }
+
     pub fn get_tx_value(&self) -> i64 {
         let mut value = 0;
 
@@ -113,44 +244,320 @@ impl RawTransaction {
         inputs
     }
 
-    pub fn serialize(&self, input: usize, pubkey_script: Vec<u8>) -> Vec<u8> {
+    /// Checks if the same outpoint is spent more than once by the inputs.
+    pub fn has_duplicate_inputs(&self) -> bool {
+        let mut seen = HashSet::new();
+        for txin in &self.tx_in {
+            if !seen.insert((txin.get_outpoint_hash(), txin.get_outpoint_index())) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Checks the standardness rules that don't need the previous outputs: not too heavy,
+    /// no duplicated inputs and push only signature scripts with canonical signatures.
+    pub fn is_standard(&self) -> bool {
+        self.check_standard().is_ok()
+    }
+
+    /// Like `is_standard`, with the rule broken by the transaction as the error.
+    pub fn check_standard(&self) -> Result<(), ProtocolError> {
+        if self.weight() > MAX_STANDARD_TX_WEIGHT {
+            return Err(ProtocolError::Error(format!(
+                "The weight {} is over the limit of {}",
+                self.weight(),
+                MAX_STANDARD_TX_WEIGHT
+            )));
+        }
+        if self.has_duplicate_inputs() {
+            return Err(ProtocolError::Error("An output is spent twice".to_string()));
+        }
+
+        match self
+            .tx_in
+            .iter()
+            .position(|txin| !is_standard_signature_script(&txin.signature_script))
+        {
+            Some(i) => Err(ProtocolError::Error(format!(
+                "Input {} has a non standard signature script",
+                i
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Serialization of the transaction signed by an input with the sighash type.
+    /// ANYONECANPAY keeps only the input, NONE drops the outputs and SINGLE keeps the ones up to
+    /// the input, blanking the previous ones. With NONE and SINGLE the other sequences are zeroed.
+    pub fn serialize(&self, input: usize, pubkey_script: Vec<u8>, sighash_type: u8) -> Vec<u8> {
+        let base_type = sighash_type & !SIGHASH_ANYONECANPAY;
+        let anyone_can_pay = sighash_type & SIGHASH_ANYONECANPAY != 0;
         let mut s: Vec<u8> = vec![];
 
         s.extend_from_slice(&self.version.to_le_bytes());
-        s.extend_from_slice(&self.tx_in_count.to_le_bytes());
+        if anyone_can_pay {
+            s.push(1);
+        } else {
+            s.extend_from_slice(&self.tx_in_count.to_le_bytes());
+        }
         for (i, txin) in self.tx_in.iter().enumerate() {
+            if anyone_can_pay && i != input {
+                continue;
+            }
             s.extend_from_slice(&txin.previous_output.to_bytes());
             if i == input {
                 let len_pubkey = CompactSize::new_from_usize(pubkey_script.len());
                 s.extend_from_slice(&len_pubkey.to_le_bytes());
                 s.extend_from_slice(&pubkey_script);
+                s.extend_from_slice(&txin.sequence.to_le_bytes());
             } else {
                 s.push(0);
+                let sequence = match base_type {
+                    SIGHASH_NONE | SIGHASH_SINGLE => 0u32,
+                    _ => txin.sequence,
+                };
+                s.extend_from_slice(&sequence.to_le_bytes());
             }
-            s.extend_from_slice(&txin.sequence.to_le_bytes());
         }
-        s.extend_from_slice(&self.tx_out_count.to_le_bytes());
-        for txout in &self.tx_out {
-            s.extend_from_slice(&txout.to_bytes());
+
+        match base_type {
+            SIGHASH_NONE => s.push(0),
+            SIGHASH_SINGLE => {
+                s.extend_from_slice(&CompactSize::new_from_usize(input + 1).to_le_bytes());
+                for _ in 0..input {
+                    s.extend_from_slice(&(-1i64).to_le_bytes());
+                    s.push(0);
+                }
+                if let Some(txout) = self.tx_out.get(input) {
+                    s.extend_from_slice(&txout.to_bytes());
+                }
+            }
+            _ => {
+                s.extend_from_slice(&self.tx_out_count.to_le_bytes());
+                for txout in &self.tx_out {
+                    s.extend_from_slice(&txout.to_bytes());
+                }
+            }
         }
         s.extend_from_slice(&self.lock_time.to_le_bytes());
-        s.extend_from_slice(&(1u32).to_le_bytes());
+        s.extend_from_slice(&(sighash_type as u32).to_le_bytes());
+
+        s
+    }
+
+    /// Hash signed by an input with the sighash type.
+    pub fn signature_hash(
+        &self,
+        input: usize,
+        pubkey_script: Vec<u8>,
+        sighash_type: u8,
+    ) -> [u8; 32] {
+        let base_type = sighash_type & !SIGHASH_ANYONECANPAY;
+        if base_type == SIGHASH_SINGLE && input >= self.tx_out.len() {
+            // Quirk of the original client: the hash is the number one
+            let mut one = [0u8; 32];
+            one[0] = 1;
+            return one;
+        }
+        sha256d::Hash::hash(&self.serialize(input, pubkey_script, sighash_type)).to_byte_array()
+    }
+
+    /// Hashes of the prevouts, sequences and outputs shared by the BIP143 sighash of every input.
+    pub fn segwit_midstate(&self) -> SegwitMidstate {
+        let prevouts: Vec<u8> = self
+            .tx_in
+            .iter()
+            .flat_map(|txin| txin.previous_output.to_bytes())
+            .collect();
+        let sequences: Vec<u8> = self
+            .tx_in
+            .iter()
+            .flat_map(|txin| txin.sequence.to_le_bytes())
+            .collect();
+        let outputs: Vec<u8> = self
+            .tx_out
+            .iter()
+            .flat_map(|txout| txout.to_bytes())
+            .collect();
+
+        SegwitMidstate {
+            hash_prevouts: sha256d::Hash::hash(&prevouts).to_byte_array(),
+            hash_sequence: sha256d::Hash::hash(&sequences).to_byte_array(),
+            hash_outputs: sha256d::Hash::hash(&outputs).to_byte_array(),
+        }
+    }
+
+    /// BIP143 serialization signed by a segwit input that spends the amount with the script code.
+    /// ANYONECANPAY, NONE and SINGLE blank the hashes of the midstate they leave out.
+    pub fn segwit_serialize(
+        &self,
+        midstate: &SegwitMidstate,
+        input: usize,
+        script_code: &[u8],
+        amount: i64,
+        sighash_type: u8,
+    ) -> Vec<u8> {
+        let base_type = sighash_type & !SIGHASH_ANYONECANPAY;
+        let anyone_can_pay = sighash_type & SIGHASH_ANYONECANPAY != 0;
+        let txin = &self.tx_in[input];
+
+        let hash_prevouts = if anyone_can_pay {
+            [0; 32]
+        } else {
+            midstate.hash_prevouts
+        };
+        let hash_sequence = if anyone_can_pay || base_type != SIGHASH_ALL {
+            [0; 32]
+        } else {
+            midstate.hash_sequence
+        };
+        let hash_outputs = match (base_type, self.tx_out.get(input)) {
+            (SIGHASH_NONE, _) | (SIGHASH_SINGLE, None) => [0; 32],
+            (SIGHASH_SINGLE, Some(txout)) => sha256d::Hash::hash(&txout.to_bytes()).to_byte_array(),
+            _ => midstate.hash_outputs,
+        };
+
+        let mut s: Vec<u8> = vec![];
+        s.extend_from_slice(&self.version.to_le_bytes());
+        s.extend_from_slice(&hash_prevouts);
+        s.extend_from_slice(&hash_sequence);
+        s.extend_from_slice(&txin.previous_output.to_bytes());
+        s.extend_from_slice(&CompactSize::new_from_usize(script_code.len()).to_le_bytes());
+        s.extend_from_slice(script_code);
+        s.extend_from_slice(&amount.to_le_bytes());
+        s.extend_from_slice(&txin.sequence.to_le_bytes());
+        s.extend_from_slice(&hash_outputs);
+        s.extend_from_slice(&self.lock_time.to_le_bytes());
+        s.extend_from_slice(&(sighash_type as u32).to_le_bytes());
 
         s
     }
 
+    /// Hash signed by a segwit input, the midstate is computed once for all of them.
+    pub fn segwit_signature_hash(
+        &self,
+        midstate: &SegwitMidstate,
+        input: usize,
+        script_code: &[u8],
+        amount: i64,
+        sighash_type: u8,
+    ) -> [u8; 32] {
+        let preimage = self.segwit_serialize(midstate, input, script_code, amount, sighash_type);
+        sha256d::Hash::hash(&preimage).to_byte_array()
+    }
+
+    /// Signs an input that spends a P2WPKH output of the amount.
+    /// Returns the witness of the input: the signature with its sighash byte and the public key.
+    pub fn sign_p2wpkh_input(
+        &self,
+        midstate: &SegwitMidstate,
+        input: usize,
+        amount: i64,
+        wif_private_key: &str,
+        sighash_type: u8,
+    ) -> Result<Vec<Vec<u8>>, ProtocolError> {
+        if input >= self.tx_in.len() {
+            return Err(ProtocolError::Error("Input out of range".to_string()));
+        }
+
+        let key = PrivateKey::from_wif(wif_private_key, network())?;
+        // The witness programs only take compressed keys
+        if !key.compressed {
+            return Err(ProtocolError::Error(
+                "Segwit inputs can't be signed with an uncompressed key".to_string(),
+            ));
+        }
+        let secp = Secp256k1::signing_only();
+        let public_key = key.public_key();
+        let script_code = PubKeyScript::P2PKH(hash160(&public_key).to_vec()).to_vec();
+
+        let sighash =
+            self.segwit_signature_hash(midstate, input, &script_code, amount, sighash_type);
+        let message = Message::from_digest_slice(&sighash).unwrap();
+        let mut signature = secp
+            .sign_ecdsa(&message, &key.secret_key)
+            .serialize_der()
+            .to_vec();
+        signature.push(sighash_type);
+
+        Ok(vec![signature, public_key])
+    }
+
+    /// Signs an input that spends the P2PKH output with the public key script, its signature
+    /// script gets the signature with its sighash byte and the public key.
+    pub fn sign_p2pkh_input(
+        &mut self,
+        input: usize,
+        pubkey_script: Vec<u8>,
+        wif_private_key: &str,
+        sighash_type: u8,
+    ) -> Result<(), ProtocolError> {
+        if input >= self.tx_in.len() {
+            return Err(ProtocolError::Error("Input out of range".to_string()));
+        }
+
+        let key = PrivateKey::from_wif(wif_private_key, network())?;
+        let secp = Secp256k1::signing_only();
+        let public_key = key.public_key();
+
+        let sighash = self.signature_hash(input, pubkey_script, sighash_type);
+        let message = Message::from_digest_slice(&sighash).unwrap();
+        let signature = secp.sign_ecdsa(&message, &key.secret_key).serialize_der();
+
+        let signature_script = [
+            &CompactSize::new_from_usize(signature.len() + 1).to_le_bytes()[..],
+            &signature[..],
+            &[sighash_type],
+            &CompactSize::new_from_usize(public_key.len()).to_le_bytes()[..],
+            &public_key,
+        ]
+        .concat();
+        self.tx_in[input].script_bytes = CompactSize::new_from_usize(signature_script.len());
+        self.tx_in[input].signature_script = signature_script;
+        Ok(())
+    }
+
+    /// Whether the transaction can be mined in a block of the height and timestamp.
+    pub fn is_final(&self, height: u32, timestamp: u32) -> bool {
+        if self.lock_time == 0
+            || self
+                .tx_in
+                .iter()
+                .all(|txin| txin.sequence == FINAL_SEQUENCE)
+        {
+            return true;
+        }
+        if self.lock_time < LOCKTIME_THRESHOLD {
+            self.lock_time < height
+        } else {
+            self.lock_time < timestamp
+        }
+    }
+
+    /// Replaceable transactions (BIP 125) have an input with a sequence below LOCKTIME_SEQUENCE.
+    pub fn signals_rbf(&self) -> bool {
+        self.tx_in
+            .iter()
+            .any(|txin| txin.sequence < LOCKTIME_SEQUENCE)
+    }
+
     pub fn create_transaction(
         out_to_spend: Vec<([u8; 32], Output)>,
         tx_out: Vec<TxOut>,
         wif_private_key: &str,
-    ) -> RawTransaction {
+        options: &TxOptions,
+    ) -> Result<RawTransaction, ProtocolError> {
+        let key = PrivateKey::from_wif(wif_private_key, network())?;
         let mut tx_in = vec![];
-        for (hash, out) in out_to_spend.iter() {
+        for (i, (hash, out)) in out_to_spend.iter().enumerate() {
             let previous_output = Outpoint {
                 hash: *hash,
                 index: out.index,
             };
-            tx_in.push(TxIn::new(previous_output, vec![]));
+            let mut txin = TxIn::new(previous_output, vec![]);
+            txin.sequence = options.sequence(i);
+            tx_in.push(txin);
         }
 
         let tx_in_count = CompactSize::new_from_usize(tx_in.len());
@@ -162,33 +569,36 @@ impl RawTransaction {
             tx_in,
             tx_out_count,
             tx_out,
-            lock_time: 0,
+            lock_time: options.lock_time,
         };
 
-        let private_key = wif_to_private_key(wif_private_key);
-
         let secp = Secp256k1::signing_only();
-        let secret_key = SecretKey::from_slice(&private_key).unwrap();
-        let public_key = PublicKey::from_secret_key(&secp, &secret_key).serialize();
+        let public_key = key.public_key();
         let public_key_len = &CompactSize::new_from_usize(public_key.len()).to_le_bytes()[..];
 
         for i in 0..tx_in_count.into_inner() {
-            let signature = tx.serialize(i, out_to_spend[i].1.pkscript.to_vec());
-            let signature_hash = sha256d::Hash::hash(&signature).to_byte_array();
+            let signature_hash =
+                tx.signature_hash(i, out_to_spend[i].1.pkscript.to_vec(), options.sighash_type);
 
-            let message = Message::from_slice(&signature_hash).unwrap();
-            let _sig = secp.sign_ecdsa(&message, &secret_key);
+            let message = Message::from_digest_slice(&signature_hash).unwrap();
+            let _sig = secp.sign_ecdsa(&message, &key.secret_key);
             let sig = &ecdsa::Signature::serialize_der(&_sig).to_vec()[..];
             let len_sig = &CompactSize::new_from_usize(sig.len() + 1).to_le_bytes()[..];
 
-            let signature_script =
-                [len_sig, sig, &[SIGHASH_ALL], public_key_len, &public_key].concat();
+            let signature_script = [
+                len_sig,
+                sig,
+                &[options.sighash_type],
+                public_key_len,
+                &public_key,
+            ]
+            .concat();
 
             tx.tx_in[i].script_bytes = CompactSize::new_from_usize(signature_script.len());
             tx.tx_in[i].signature_script = signature_script;
         }
 
-        tx
+        Ok(tx)
     }
 }
 
@@ -217,12 +627,14 @@ impl Outpoint {
     }
 
     pub fn to_bytes(&self) -> Vec<u8> {
-        let mut bytes = Vec::new();
+        let mut bytes = Vec::with_capacity(36);
+        self.append_to(&mut bytes);
+        bytes
+    }
 
+    pub fn append_to(&self, bytes: &mut Vec<u8>) {
         bytes.extend_from_slice(&self.hash[..]);
         bytes.extend_from_slice(&self.index.to_le_bytes());
-
-        bytes
     }
 }
 
@@ -240,7 +652,7 @@ impl TxIn {
             previous_output,
             script_bytes: CompactSize::new_from_usize(signature_script.len()),
             signature_script,
-            sequence: 0xffffffff,
+            sequence: FINAL_SEQUENCE,
         }
     }
 
@@ -266,13 +678,20 @@ impl TxIn {
     }
 
     pub fn to_bytes(&self) -> Vec<u8> {
-        let mut bytes = Vec::new();
-        bytes.extend_from_slice(&self.previous_output.to_bytes());
-        bytes.extend_from_slice(&self.script_bytes.to_le_bytes());
+        let mut bytes = Vec::with_capacity(self.serialized_size());
+        self.append_to(&mut bytes);
+        bytes
+    }
+
+    pub fn append_to(&self, bytes: &mut Vec<u8>) {
+        self.previous_output.append_to(bytes);
+        self.script_bytes.append_to(bytes);
         bytes.extend_from_slice(&self.signature_script[..]);
         bytes.extend_from_slice(&self.sequence.to_le_bytes());
+    }
 
-        bytes
+    pub fn serialized_size(&self) -> usize {
+        40 + self.script_bytes.size() + self.signature_script.len()
     }
 
     fn get_outpoint_index(&self) -> u32 {
@@ -320,12 +739,19 @@ impl TxOut {
     }
 
     pub fn to_bytes(&self) -> Vec<u8> {
-        let mut bytes = Vec::new();
+        let mut bytes = Vec::with_capacity(self.serialized_size());
+        self.append_to(&mut bytes);
+        bytes
+    }
+
+    pub fn append_to(&self, bytes: &mut Vec<u8>) {
         bytes.extend_from_slice(&self.value.to_le_bytes());
-        bytes.extend_from_slice(&self.pk_script_bytes.to_le_bytes());
+        self.pk_script_bytes.append_to(bytes);
         bytes.extend_from_slice(&self.pk_script[..]);
+    }
 
-        bytes
+    pub fn serialized_size(&self) -> usize {
+        8 + self.pk_script_bytes.size() + self.pk_script.len()
     }
 
     pub fn get_pk(&self) -> Vec<u8> {
@@ -426,3 +852,117 @@ fn test_tx_id() {
 
     println!("{:?}", tx.get_tx_id());
 }
+
+#[test]
+fn test_lock_time_and_rbf_options() {
+    let out = Output {
+        index: 0,
+        value: 10,
+        pkscript: crate::script::PubKeyScript::P2PKH(vec![0; 20]),
+    };
+    let wif = "cSnB7AwCEDKrdq1x2XmHu8f1BHPh6KeuBjeXgssDe2cMpeGDM7oB";
+    let spend = vec![([1; 32], out.clone()), ([2; 32], out)];
+
+    let tx = RawTransaction::create_transaction(spend.clone(), vec![], wif, &TxOptions::default())
+        .unwrap();
+    assert_eq!(tx.lock_time, 0);
+    assert!(!tx.signals_rbf());
+    assert!(tx.is_final(0, 0));
+
+    let options = TxOptions {
+        lock_time: 100,
+        rbf: true,
+        sequences: vec![7],
+        ..TxOptions::default()
+    };
+    let tx = RawTransaction::create_transaction(spend.clone(), vec![], wif, &options).unwrap();
+    assert_eq!(tx.tx_in[0].sequence, 7);
+    assert_eq!(tx.tx_in[1].sequence, RBF_SEQUENCE);
+    assert!(tx.signals_rbf());
+    assert!(!tx.is_final(100, u32::MAX));
+    assert!(tx.is_final(101, 0));
+
+    let options = TxOptions {
+        lock_time: LOCKTIME_THRESHOLD + 10,
+        ..TxOptions::default()
+    };
+    let tx = RawTransaction::create_transaction(spend, vec![], wif, &options).unwrap();
+    assert_eq!(tx.tx_in[0].sequence, LOCKTIME_SEQUENCE);
+    assert!(!tx.signals_rbf());
+    assert!(!tx.is_final(u32::MAX, LOCKTIME_THRESHOLD + 10));
+    assert!(tx.is_final(0, LOCKTIME_THRESHOLD + 11));
+}
+
+#[test]
+fn test_bip143_native_p2wpkh_sighash() {
+    // Native P2WPKH example of BIP143, the second input spends 6 BTC
+    let unsigned = unhexlify("0100000002fff7f7881a8099afa6940d42d1e7f6362bec38171ea3edf433541db4e4ad969f0000000000eeffffffef51e1b804cc89d182d279655c3aa89e815b1b309fe287d9b2b55d57b90ec68a0100000000ffffffff02202cb206000000001976a9148280b37df378db99f66f85c95a783a76ac7a6d5988ac9093510d000000001976a9143bde42dbee7e4dbe6a21b2d50ce2f0167faa815988ac11000000").unwrap();
+    let tx = RawTransaction::read_from(&mut &unsigned[..]).unwrap();
+    let midstate = tx.segwit_midstate();
+
+    let hash = |hex: &str| -> [u8; 32] { unhexlify(hex).unwrap().try_into().unwrap() };
+    assert_eq!(
+        midstate.hash_prevouts,
+        hash("96b827c8483d4e9b96712b6713a7b68d6e8003a781feba36c31143470b4efd37")
+    );
+    assert_eq!(
+        midstate.hash_sequence,
+        hash("52b0a642eea2fb7ae638c36f6252b6750293dbe574a806984b8e4d8548339a3b")
+    );
+    assert_eq!(
+        midstate.hash_outputs,
+        hash("863ef3e1a92afbfdb97f31ad0fc7683ee943e9abcf2501590ff8f6551f47e5e5")
+    );
+
+    let script_code = unhexlify("76a9141d0f172a0ecb48aee1be1f2687d2963ae33f71a188ac").unwrap();
+    let sighash = tx.segwit_signature_hash(&midstate, 1, &script_code, 600_000_000, SIGHASH_ALL);
+    assert_eq!(
+        sighash,
+        hash("c37af31116d1b27caf68aae9e3ac82f1477929014d5b917657d0eb49478cb670")
+    );
+}
+
+#[test]
+fn test_input_and_output_counts_over_the_size_limit_fail() {
+    let tx = RawTransaction::new(
+        vec![TxIn::new(Outpoint::new([1; 32], 0), vec![])],
+        vec![TxOut::new(10, vec![])],
+    );
+    let bytes = tx.to_bytes();
+    assert!(RawTransaction::read_with_limit(&mut &bytes[..], bytes.len()).is_ok());
+
+    // One input is 41 bytes, two don't fit in the size of the transaction
+    let mut too_many_inputs = bytes.clone();
+    too_many_inputs[4] = 2;
+    assert!(matches!(
+        RawTransaction::read_with_limit(&mut &too_many_inputs[..], bytes.len()),
+        Err(ProtocolError::ConnectionError(_))
+    ));
+
+    let mut too_many_outputs = bytes.clone();
+    too_many_outputs.splice(46..47, [255, 255, 255, 255, 255, 255, 255, 255, 255]);
+    assert!(matches!(
+        RawTransaction::read_from(&mut &too_many_outputs[..]),
+        Err(ProtocolError::ConnectionError(_))
+    ));
+}
+
+#[test]
+fn test_transactions_without_witnesses_weigh_four_times_their_size() {
+    let txin = TxIn {
+        previous_output: Outpoint {
+            hash: [1; 32],
+            index: 0,
+        },
+        script_bytes: CompactSize::U8(0),
+        signature_script: vec![],
+        sequence: FINAL_SEQUENCE,
+    };
+    let tx = RawTransaction::new(vec![txin], vec![]);
+
+    assert_eq!(tx.size(), tx.to_bytes().len());
+    assert_eq!(tx.weight(), 4 * tx.size());
+    assert_eq!(tx.vsize(), tx.size());
+    assert!(tx.is_standard());
+    assert_eq!(estimated_p2pkh_vsize(1, 2), 226);
+}
@@ -0,0 +1,79 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+/// When to announce again each unconfirmed transaction of the wallet.
+/// The wait doubles after every announcement, up to `max_interval`.
+#[derive(Debug)]
+pub struct RebroadcastSchedule {
+    entries: HashMap<[u8; 32], (Instant, Duration)>,
+    initial_interval: Duration,
+    max_interval: Duration,
+}
+
+impl RebroadcastSchedule {
+    pub fn new(initial_interval: Duration, max_interval: Duration) -> RebroadcastSchedule {
+        RebroadcastSchedule {
+            entries: HashMap::new(),
+            initial_interval,
+            max_interval,
+        }
+    }
+
+    /// Schedules a transaction that was just broadcast.
+    pub fn add(&mut self, txid: [u8; 32], now: Instant) {
+        self.entries
+            .insert(txid, (now + self.initial_interval, self.initial_interval));
+    }
+
+    /// Stops announcing the transaction, called when it gets confirmed or replaced.
+    pub fn remove(&mut self, txid: &[u8; 32]) {
+        self.entries.remove(txid);
+    }
+
+    /// Returns the transactions to announce now and schedules their next announcement.
+    pub fn due(&mut self, now: Instant) -> Vec<[u8; 32]> {
+        let mut due = vec![];
+        for (txid, (next, interval)) in self.entries.iter_mut() {
+            if *next <= now {
+                due.push(*txid);
+                *interval = (*interval * 2).min(self.max_interval);
+                *next = now + *interval;
+            }
+        }
+        due
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_announcements_back_off_until_removed() {
+        let minute = Duration::from_secs(60);
+        let mut schedule = RebroadcastSchedule::new(minute, minute * 3);
+        let start = Instant::now();
+        schedule.add([1; 32], start);
+
+        assert!(schedule.due(start).is_empty());
+        assert_eq!(schedule.due(start + minute), vec![[1; 32]]);
+        // The next one is two minutes later, then the interval stays at the maximum
+        assert!(schedule.due(start + minute * 2).is_empty());
+        assert_eq!(schedule.due(start + minute * 3), vec![[1; 32]]);
+        assert!(schedule.due(start + minute * 5).is_empty());
+        assert_eq!(schedule.due(start + minute * 6), vec![[1; 32]]);
+
+        schedule.remove(&[1; 32]);
+        assert!(schedule.due(start + minute * 60).is_empty());
+    }
+}
@@ -0,0 +1,64 @@
+use std::collections::{HashSet, VecDeque};
+
+/// Rolling set of the last `capacity` txids inserted, the oldest one is forgotten when it is full.
+/// The node keeps one for the transactions it rejected and one for the ones confirmed in a block.
+#[derive(Debug)]
+pub struct RecentTxids {
+    capacity: usize,
+    txids: HashSet<[u8; 32]>,
+    order: VecDeque<[u8; 32]>,
+}
+
+impl RecentTxids {
+    pub fn new(capacity: usize) -> RecentTxids {
+        RecentTxids {
+            capacity,
+            txids: HashSet::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    pub fn insert(&mut self, txid: [u8; 32]) {
+        if self.capacity == 0 || !self.txids.insert(txid) {
+            return;
+        }
+
+        self.order.push_back(txid);
+        if self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.txids.remove(&oldest);
+            }
+        }
+    }
+
+    pub fn contains(&self, txid: &[u8; 32]) -> bool {
+        self.txids.contains(txid)
+    }
+
+    pub fn len(&self) -> usize {
+        self.txids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.txids.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_oldest_txid_is_forgotten() {
+        let mut recent = RecentTxids::new(2);
+        recent.insert([1; 32]);
+        recent.insert([2; 32]);
+        recent.insert([2; 32]);
+        assert_eq!(recent.len(), 2);
+
+        recent.insert([3; 32]);
+        assert!(!recent.contains(&[1; 32]));
+        assert!(recent.contains(&[2; 32]));
+        assert!(recent.contains(&[3; 32]));
+    }
+}
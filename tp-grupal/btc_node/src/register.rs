@@ -1,25 +1,191 @@
 use crate::{
+    bandwidth::{take_thread_traffic, Traffic},
+    constants::{
+        IGNORED_SUMMARY_INTERVAL, NODE_NETWORK, NODE_NETWORK_LIMITED, PEER_INACTIVITY_TIMEOUT,
+        PEER_WRITE_TIMEOUT, PING_TIMEOUT,
+    },
     log_file::Logger,
-    message::{version::VersionMessage, Message},
+    message::{ping::PingMessage, version::VersionMessage, Message},
+    peer_writer::PeerWriter,
     protocol_error::ProtocolError,
+    transport::PeerStream,
 };
 
 use std::{
+    cmp::Reverse,
     collections::HashMap,
-    net::{Ipv6Addr, SocketAddr, TcpStream},
+    fmt,
+    io::Write,
+    net::{Ipv6Addr, Shutdown, SocketAddr},
+    time::{Duration, Instant},
 };
 
+/// Messages that were read but not handled, per command, and the ones that couldn't be parsed.
+#[derive(Debug, Clone, Default)]
+pub struct IgnoredMessages {
+    pub by_command: HashMap<String, u64>,
+    pub dropped: u64,
+}
+
+impl IgnoredMessages {
+    pub fn record_ignored(&mut self, command: &str) {
+        *self.by_command.entry(command.to_string()).or_default() += 1;
+    }
+
+    pub fn record_dropped(&mut self) {
+        self.dropped += 1;
+    }
+
+    pub fn merge(&mut self, other: &IgnoredMessages) {
+        for (command, count) in other.by_command.iter() {
+            *self.by_command.entry(command.clone()).or_default() += count;
+        }
+        self.dropped += other.dropped;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_command.is_empty() && self.dropped == 0
+    }
+}
+
+impl fmt::Display for IgnoredMessages {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut commands: Vec<_> = self.by_command.iter().collect();
+        commands.sort();
+        for (command, count) in commands {
+            write!(f, "{}: {}, ", command, count)?;
+        }
+        write!(f, "dropped: {}", self.dropped)
+    }
+}
+
+/// Side that opened the connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    /// Dialed by the node
+    Outbound,
+    /// Accepted by the listener
+    Inbound,
+}
+
+/// Identity of a connection: the address of the peer and who opened it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PeerKey {
+    pub ip: Ipv6Addr,
+    pub port: u16,
+    pub direction: Direction,
+}
+
+impl PeerKey {
+    pub fn new(addr: SocketAddr, direction: Direction) -> PeerKey {
+        PeerKey {
+            ip: to_ipaddr(addr),
+            port: addr.port(),
+            direction,
+        }
+    }
+}
+
+impl fmt::Display for PeerKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let direction = match self.direction {
+            Direction::Outbound => "outbound",
+            Direction::Inbound => "inbound",
+        };
+        write!(f, "[{}]:{} {}", self.ip, self.port, direction)
+    }
+}
+
+/// Number given to every connection, never reused while the node runs.
+pub type PeerId = u64;
+
+/// Stage of a connection, every change is logged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PeerState {
+    /// Reserved before dialing, so the peer isn't dialed twice
+    Connecting,
+    Handshaking,
+    /// Registered, its messages are handled
+    Active,
+    Disconnected,
+}
+
 #[derive(Debug)]
 struct Status {
-    _version: VersionMessage,
-    stream: TcpStream,
+    version: VersionMessage,
+    stream: PeerStream,
+    writer: PeerWriter,
+    traffic: Traffic,
+    /// Ignored since the last summary
+    ignored: IgnoredMessages,
+    /// When its last message was read, None while nobody reads its messages
+    last_message: Option<Instant>,
+    /// When it was pinged for being inactive
+    pinged: Option<Instant>,
+}
+
+impl Status {
+    fn heard_from(&mut self, now: Instant) {
+        if self.last_message.is_some() {
+            self.last_message = Some(now);
+            self.pinged = None;
+        }
+    }
+
+    /// Whether the peer has to be disconnected, pinging it when it became inactive.
+    fn is_stale(&mut self, now: Instant) -> bool {
+        let last_message = match self.last_message {
+            Some(last_message) => last_message,
+            None => return false,
+        };
+        if let Some(pinged) = self.pinged {
+            return now.duration_since(pinged) >= Duration::from_secs(PING_TIMEOUT);
+        }
+        if now.duration_since(last_message) < Duration::from_secs(PEER_INACTIVITY_TIMEOUT) {
+            return false;
+        }
+
+        let mut ping = vec![];
+        if PingMessage::new(rand::random())
+            .write_to(&mut ping)
+            .is_err()
+        {
+            return false;
+        }
+        self.pinged = Some(now);
+        // A peer whose writer stopped can't answer
+        self.writer.send(ping).is_err()
+    }
+}
+
+/// Limits of the connections accepted by the listener.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InboundLimits {
+    pub max_inbound: usize,
+    pub max_per_ip: usize,
+}
+
+/// Inbound connections open, and the ones refused or evicted since the node started.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct InboundCounts {
+    pub connected: usize,
+    pub rejected: u64,
+    pub evicted: u64,
 }
 
 #[derive(Debug)]
 pub struct Register {
-    entries: HashMap<Ipv6Addr, Status>,
-    active_nodes: usize,
+    /// Key and state of every connection that isn't disconnected
+    peers: HashMap<PeerId, (PeerKey, PeerState)>,
+    /// Connections that completed the handshake
+    entries: HashMap<PeerId, Status>,
+    next_id: PeerId,
     logger: Logger,
+    /// Ignored by every peer since the last summary, including the ones that disconnected
+    ignored: IgnoredMessages,
+    last_ignored_summary: Instant,
+    /// Inbound connections refused or evicted, `connected` is counted from the peers
+    inbound: InboundCounts,
 }
 
 fn to_ipaddr(ip: SocketAddr) -> Ipv6Addr {
@@ -33,37 +199,250 @@ fn to_ipaddr(ip: SocketAddr) -> Ipv6Addr {
     }
 }
 
+fn same_connection(a: &PeerStream, b: &PeerStream) -> bool {
+    a.peer_addr().ok() == b.peer_addr().ok() && a.local_addr().ok() == b.local_addr().ok()
+}
+
 impl Register {
-    pub fn new(filepath: String) -> Register {
+    pub fn new(logger: Logger) -> Register {
         Register {
+            peers: HashMap::new(),
             entries: HashMap::new(),
-            active_nodes: 0,
-            logger: Logger::new(filepath),
+            next_id: 0,
+            logger,
+            ignored: IgnoredMessages::default(),
+            last_ignored_summary: Instant::now(),
+            inbound: InboundCounts::default(),
         }
     }
 
-    pub fn save_connection(
+    /// Gives an id to a connection about to be opened or accepted.
+    /// Fails when there is already a connection with the same key.
+    pub fn reserve(&mut self, key: PeerKey) -> Result<PeerId, ProtocolError> {
+        if let Some(id) = self.id_of(&key) {
+            return Err(ProtocolError::ConnectionError(format!(
+                "Already connected to {} as peer {}",
+                key, id
+            )));
+        }
+
+        let id = self.next_id;
+        self.next_id += 1;
+        self.peers.insert(id, (key, PeerState::Connecting));
+        self.logger.log(format!(
+            "peer {} ({}) is {:?}",
+            id,
+            key,
+            PeerState::Connecting
+        ));
+        Ok(id)
+    }
+
+    /// Reserves a connection accepted by the listener, within the limits. When the slots are
+    /// full the peer picked by `inbound_to_evict` is disconnected to make room.
+    pub fn reserve_inbound(
         &mut self,
-        stream: TcpStream,
-        _version: VersionMessage,
-    ) -> Result<(), ProtocolError> {
-        let ip = to_ipaddr(stream.peer_addr()?);
+        addr: SocketAddr,
+        limits: InboundLimits,
+    ) -> Result<PeerId, ProtocolError> {
+        let key = PeerKey::new(addr, Direction::Inbound);
+        if self.inbound_from(key.ip) >= limits.max_per_ip {
+            self.inbound.rejected += 1;
+            return Err(ProtocolError::ConnectionError(format!(
+                "Too many connections from {}",
+                key.ip
+            )));
+        }
 
-        let status = Status { _version, stream };
+        if self.inbound_len() >= limits.max_inbound {
+            match self.inbound_to_evict() {
+                Some(id) => self.evict(id),
+                None => {
+                    self.inbound.rejected += 1;
+                    return Err(ProtocolError::ConnectionError(
+                        "The inbound slots are full".to_string(),
+                    ));
+                }
+            }
+        }
+        self.reserve(key)
+    }
 
-        self.entries.insert(ip, status);
-        self.active_nodes += 1;
+    pub fn inbound_counts(&self) -> InboundCounts {
+        InboundCounts {
+            connected: self.inbound_len(),
+            ..self.inbound
+        }
+    }
+
+    /// Inbound connections, including the ones still in the handshake.
+    fn inbound_len(&self) -> usize {
+        self.peers
+            .values()
+            .filter(|(key, _)| key.direction == Direction::Inbound)
+            .count()
+    }
+
+    fn inbound_from(&self, ip: Ipv6Addr) -> usize {
+        self.peers
+            .values()
+            .filter(|(key, _)| key.direction == Direction::Inbound && key.ip == ip)
+            .count()
+    }
 
+    /// Registered inbound peer to disconnect when the slots are full: of the address with
+    /// the most connections, the one heard from the longest ago. Peers in the handshake stay.
+    fn inbound_to_evict(&self) -> Option<PeerId> {
+        let now = Instant::now();
+        self.entries
+            .iter()
+            .filter_map(|(id, status)| {
+                let (key, _) = self.peers.get(id)?;
+                let last_message = status.last_message.unwrap_or(now);
+                (key.direction == Direction::Inbound).then_some((*id, key.ip, last_message))
+            })
+            .max_by_key(|(id, ip, last_message)| {
+                (self.inbound_from(*ip), Reverse(*last_message), *id)
+            })
+            .map(|(id, _, _)| id)
+    }
+
+    /// Shuts the stream of the peer down, so the thread reading it stops.
+    fn evict(&mut self, id: PeerId) {
+        if let Some(status) = self.entries.get(&id) {
+            let _ = status.stream.shutdown(Shutdown::Both);
+        }
         self.logger.log(format!(
-            "peer with IP {} is now registered. Handshake completed.",
-            ip
+            "peer {} evicted to make room for an inbound connection",
+            id
         ));
+        self.inbound.evicted += 1;
+        self.set_state(id, PeerState::Disconnected);
+    }
+
+    /// Moves the peer to the state, disconnected peers are forgotten.
+    pub fn set_state(&mut self, id: PeerId, state: PeerState) {
+        let (key, old) = match self.peers.get_mut(&id) {
+            Some((key, old)) => (*key, std::mem::replace(old, state)),
+            None => return,
+        };
+        if state == PeerState::Disconnected {
+            self.peers.remove(&id);
+            self.entries.remove(&id);
+        }
+        self.logger
+            .log(format!("peer {} ({}): {:?} -> {:?}", id, key, old, state));
+    }
+
+    /// Disconnects the peer of the stream, if it is registered.
+    pub fn disconnect(&mut self, stream: &PeerStream) {
+        if let Some(id) = self.stream_id(stream) {
+            self.set_state(id, PeerState::Disconnected);
+        }
+    }
+
+    /// Starts checking the inactivity of the peer of the stream, once its messages are read.
+    pub fn start_listening(&mut self, stream: &PeerStream) {
+        if let Some(status) = self.peer_status(stream) {
+            status.last_message = Some(Instant::now());
+        }
+    }
+
+    /// Pings the listened peers without messages in `PEER_INACTIVITY_TIMEOUT` seconds and
+    /// disconnects the ones that didn't send anything `PING_TIMEOUT` seconds after the ping.
+    /// Their streams are shut down, so the threads reading them stop. Returns their ids.
+    pub fn reap_stale(&mut self, now: Instant) -> Vec<PeerId> {
+        let stale: Vec<PeerId> = self
+            .entries
+            .iter_mut()
+            .filter_map(|(id, status)| status.is_stale(now).then_some(*id))
+            .collect();
+
+        for id in stale.iter() {
+            if let Some(status) = self.entries.get(id) {
+                let _ = status.stream.shutdown(Shutdown::Both);
+            }
+            self.logger.log(format!("peer {} stopped responding", id));
+            self.set_state(*id, PeerState::Disconnected);
+        }
+        stale
+    }
+
+    /// Key and state of every connection, sorted by id.
+    pub fn peer_states(&self) -> Vec<(PeerId, PeerKey, PeerState)> {
+        let mut states: Vec<_> = self
+            .peers
+            .iter()
+            .map(|(id, (key, state))| (*id, *key, *state))
+            .collect();
+        states.sort_by_key(|(id, _, _)| *id);
+        states
+    }
+
+    fn id_of(&self, key: &PeerKey) -> Option<PeerId> {
+        self.peers
+            .iter()
+            .find(|(_, (other, _))| other == key)
+            .map(|(id, _)| *id)
+    }
+
+    fn stream_id(&self, stream: &PeerStream) -> Option<PeerId> {
+        self.entries
+            .iter()
+            .find(|(_, status)| same_connection(&status.stream, stream))
+            .map(|(id, _)| *id)
+    }
+
+    /// Registers the connection of the reserved peer once its handshake is completed.
+    pub fn save_connection(
+        &mut self,
+        id: PeerId,
+        stream: PeerStream,
+        version: VersionMessage,
+    ) -> Result<(), ProtocolError> {
+        let key = match self.peers.get(&id) {
+            Some((key, _)) => *key,
+            None => {
+                return Err(ProtocolError::ConnectionError(format!(
+                    "Peer {} isn't reserved",
+                    id
+                )))
+            }
+        };
+
+        self.logger.log(format!(
+            "peer {} ({}) is now registered. Handshake completed over the {} transport. Version: {}, services: {:#x}, user agent: {:?}",
+            id,
+            key,
+            stream.transport(),
+            version.version,
+            version.services,
+            version.user_agent()
+        ));
+
+        // What was sent during the handshake is still accounted in this thread
+        let mut traffic = Traffic::default();
+        traffic.merge(&take_thread_traffic());
+        let writer =
+            PeerWriter::spawn(stream.try_clone()?, Duration::from_secs(PEER_WRITE_TIMEOUT))?;
+        let status = Status {
+            version,
+            stream,
+            writer,
+            traffic,
+            ignored: IgnoredMessages::default(),
+            last_message: None,
+            pinged: None,
+        };
+
+        self.entries.insert(id, status);
+        self.set_state(id, PeerState::Active);
 
         Ok(())
     }
 
-    pub fn get_n_streams(&self, n: usize) -> Vec<TcpStream> {
-        let mut vec: Vec<TcpStream> = vec![];
+    pub fn get_n_streams(&self, n: usize) -> Vec<PeerStream> {
+        let mut vec: Vec<PeerStream> = vec![];
         for status in self.entries.values() {
             if vec.len() == n {
                 return vec;
@@ -76,7 +455,32 @@ impl Register {
         vec
     }
 
-    pub fn get_all_streams(&self) -> Vec<TcpStream> {
+    /// Returns up to `n` streams of peers that serve blocks, the ones with the whole chain first.
+    /// The pruned peers (NODE_NETWORK_LIMITED) are only taken for `recent` blocks.
+    pub fn get_n_block_streams(&self, n: usize, recent: bool) -> Vec<PeerStream> {
+        let mut peers: Vec<&Status> = self
+            .entries
+            .values()
+            .filter(|status| {
+                status.version.has_services(NODE_NETWORK)
+                    || (recent && status.version.has_services(NODE_NETWORK_LIMITED))
+            })
+            .collect();
+        peers.sort_by_key(|status| !status.version.has_services(NODE_NETWORK));
+        peers
+            .into_iter()
+            .filter_map(|status| status.stream.try_clone().ok())
+            .take(n)
+            .collect()
+    }
+
+    /// Returns the version message the peer sent in the handshake, if it is registered.
+    pub fn peer_version(&self, stream: &PeerStream) -> Option<&VersionMessage> {
+        let id = self.stream_id(stream)?;
+        self.entries.get(&id).map(|status| &status.version)
+    }
+
+    pub fn get_all_streams(&self) -> Vec<PeerStream> {
         self.get_n_streams(self.entries.len())
     }
 
@@ -84,7 +488,141 @@ impl Register {
         self.entries.len()
     }
 
-    pub fn log_message(&self, stream: &TcpStream, message: &Message) {
+    /// Queues `bytes` in the writer of the connection of `stream`.
+    /// Connections that aren't registered, like the ones opened for a rescan, are written directly.
+    pub fn send_to(&mut self, stream: &PeerStream, bytes: Vec<u8>) -> Result<(), ProtocolError> {
+        let sent = take_thread_traffic();
+
+        match self.peer_status(stream) {
+            Some(status) => {
+                status.traffic.merge(&sent);
+                status.writer.send(bytes)
+            }
+            None => Ok(stream.try_clone()?.write_all(&bytes)?),
+        }
+    }
+
+    /// Queues `bytes` for every registered peer and returns how many of them accepted it.
+    pub fn broadcast(&mut self, bytes: Vec<u8>) -> usize {
+        let sent = take_thread_traffic();
+
+        let mut peers_sent = 0;
+        for status in self.entries.values_mut() {
+            if status.writer.send(bytes.clone()).is_ok() {
+                status.traffic.merge(&sent);
+                peers_sent += 1;
+            }
+        }
+        peers_sent
+    }
+
+    /// Like broadcast, skipping the peers that asked not to be announced transactions.
+    pub fn broadcast_to_tx_relays(&mut self, bytes: Vec<u8>) -> usize {
+        let sent = take_thread_traffic();
+
+        let mut peers_sent = 0;
+        for status in self.entries.values_mut() {
+            if !status.version.relays_txs() {
+                continue;
+            }
+            if status.writer.send(bytes.clone()).is_ok() {
+                status.traffic.merge(&sent);
+                peers_sent += 1;
+            }
+        }
+        peers_sent
+    }
+
+    /// Number of registered peers that want the transactions announced.
+    pub fn tx_relays_len(&self) -> usize {
+        self.entries
+            .values()
+            .filter(|status| status.version.relays_txs())
+            .count()
+    }
+
+    /// Adds the bytes received in `message` and the ones sent by this thread to the peer of `stream`.
+    pub fn record_traffic(&mut self, stream: &PeerStream, message: Option<(&Message, usize)>) {
+        let sent = take_thread_traffic();
+
+        if let Some(status) = self.peer_status(stream) {
+            status.traffic.merge(&sent);
+            if let Some((message, size)) = message {
+                status
+                    .traffic
+                    .record_received(message.command(), size as u64);
+                status.heard_from(Instant::now());
+            }
+        }
+    }
+
+    /// Traffic of every registered peer.
+    pub fn peers_traffic(&self) -> Vec<(PeerId, Traffic)> {
+        self.entries
+            .iter()
+            .map(|(id, status)| (*id, status.traffic.clone()))
+            .collect()
+    }
+
+    pub fn total_traffic(&self) -> Traffic {
+        let mut total = Traffic::default();
+        for status in self.entries.values() {
+            total.merge(&status.traffic);
+        }
+        total
+    }
+
+    /// Counts a message of the peer of `stream` that was read but not handled.
+    pub fn record_ignored(&mut self, stream: &PeerStream, command: &str) {
+        self.ignored.record_ignored(command);
+        if let Some(status) = self.peer_status(stream) {
+            status.ignored.record_ignored(command);
+        }
+    }
+
+    /// Counts a message of the peer of `stream` that couldn't be parsed.
+    pub fn record_dropped(&mut self, stream: &PeerStream) {
+        self.ignored.record_dropped();
+        if let Some(status) = self.peer_status(stream) {
+            status.ignored.record_dropped();
+        }
+    }
+
+    fn peer_status(&mut self, stream: &PeerStream) -> Option<&mut Status> {
+        let id = self.stream_id(stream)?;
+        self.entries.get_mut(&id)
+    }
+
+    /// Logs the ignored and dropped messages, in total and per peer, once every
+    /// `IGNORED_SUMMARY_INTERVAL` seconds. The counts start again after every summary.
+    pub fn log_ignored_summary(&mut self, now: Instant) {
+        if now.duration_since(self.last_ignored_summary)
+            < Duration::from_secs(IGNORED_SUMMARY_INTERVAL)
+        {
+            return;
+        }
+        self.last_ignored_summary = now;
+        if self.ignored.is_empty() {
+            return;
+        }
+
+        self.logger.log(format!(
+            "Messages ignored in the last {} seconds: {}",
+            IGNORED_SUMMARY_INTERVAL, self.ignored
+        ));
+        self.ignored = IgnoredMessages::default();
+        for (id, status) in self.entries.iter_mut() {
+            if !status.ignored.is_empty() {
+                self.logger.log(format!(
+                    "Messages ignored from peer {}: {}",
+                    id, status.ignored
+                ));
+                status.ignored = IgnoredMessages::default();
+            }
+        }
+    }
+
+    pub fn log_message(&self, stream: &PeerStream, message: &Message) {
         let ip = match stream.peer_addr() {
             Ok(i) => to_ipaddr(i).to_string(),
             Err(_) => String::from("NONE"),
@@ -93,7 +631,7 @@ impl Register {
         self.logger.log(format!("{} sent {}", ip, message));
     }
 
-    pub fn log_error(&self, stream: &TcpStream, error: ProtocolError) {
+    pub fn log_error(&self, stream: &PeerStream, error: ProtocolError) {
         let ip = match stream.peer_addr() {
             Ok(i) => to_ipaddr(i).to_string(),
             Err(_) => String::from("NONE"),
@@ -105,3 +643,135 @@ impl Register {
         ));
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        log_file::LogDestination,
+        message::{
+            compact_size::CompactSize, version::version_message_builder::VersionMessageBuilder,
+        },
+    };
+    use std::{
+        io::Read,
+        net::{Ipv4Addr, TcpListener, TcpStream},
+    };
+
+    fn register() -> Register {
+        Register::new(Logger::new(String::new(), LogDestination::Stdout))
+    }
+
+    #[test]
+    fn test_a_peer_is_reserved_once_per_direction() {
+        let mut register = register();
+        let addr: SocketAddr = "10.0.0.1:18333".parse().unwrap();
+
+        let id = register
+            .reserve(PeerKey::new(addr, Direction::Outbound))
+            .unwrap();
+        assert!(register
+            .reserve(PeerKey::new(addr, Direction::Outbound))
+            .is_err());
+        let inbound = register
+            .reserve(PeerKey::new(addr, Direction::Inbound))
+            .unwrap();
+        assert_ne!(id, inbound);
+
+        register.set_state(id, PeerState::Handshaking);
+        assert_eq!(register.peer_states()[0].2, PeerState::Handshaking);
+
+        // A disconnected peer can be dialed again, with a new id
+        register.set_state(id, PeerState::Disconnected);
+        let again = register
+            .reserve(PeerKey::new(addr, Direction::Outbound))
+            .unwrap();
+        assert!(again > inbound);
+        assert_eq!(register.peer_states().len(), 2);
+    }
+
+    #[test]
+    fn test_inbound_connections_are_limited() {
+        let mut register = register();
+        let limits = InboundLimits {
+            max_inbound: 3,
+            max_per_ip: 2,
+        };
+        let addr = |ip: &str, port| SocketAddr::new(ip.parse().unwrap(), port);
+
+        register
+            .reserve_inbound(addr("10.0.0.1", 1), limits)
+            .unwrap();
+        register
+            .reserve_inbound(addr("10.0.0.1", 2), limits)
+            .unwrap();
+        assert!(register
+            .reserve_inbound(addr("10.0.0.1", 3), limits)
+            .is_err());
+        register
+            .reserve_inbound(addr("10.0.0.2", 1), limits)
+            .unwrap();
+        // Peers still in the handshake aren't evicted
+        assert!(register
+            .reserve_inbound(addr("10.0.0.3", 1), limits)
+            .is_err());
+        register
+            .reserve(PeerKey::new(addr("10.0.0.3", 1), Direction::Outbound))
+            .unwrap();
+
+        let counts = register.inbound_counts();
+        assert_eq!(
+            (counts.connected, counts.rejected, counts.evicted),
+            (3, 2, 0)
+        );
+    }
+
+    #[test]
+    fn test_inactive_peers_are_pinged_and_then_disconnected() {
+        let mut register = register();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let stream = PeerStream::plain(stream);
+        let (mut remote, _) = listener.accept().unwrap();
+
+        let key = PeerKey::new(stream.peer_addr().unwrap(), Direction::Outbound);
+        let id = register.reserve(key).unwrap();
+        let localhost = Ipv4Addr::LOCALHOST.to_ipv6_mapped();
+        let version = VersionMessageBuilder::new()
+            .version(70015)
+            .services(0)
+            .timestamp(0)
+            .addr_recv_services(0)
+            .addr_recv_ip(localhost)
+            .addr_recv_port(18333)
+            .addr_trans_services(0)
+            .addr_trans_ip(localhost)
+            .addr_trans_port(18333)
+            .nonce(0)
+            .user_agent_bytes(CompactSize::U8(0))
+            .user_agent(Vec::new())
+            .start_height(0)
+            .relay(1)
+            .build()
+            .unwrap();
+        register
+            .save_connection(id, stream.try_clone().unwrap(), version)
+            .unwrap();
+
+        // Peers that nobody reads aren't checked
+        let inactive = Duration::from_secs(PEER_INACTIVITY_TIMEOUT);
+        assert!(register.reap_stale(Instant::now() + inactive).is_empty());
+
+        register.start_listening(&stream);
+        let later = Instant::now() + inactive;
+        assert!(register.reap_stale(later).is_empty());
+        let mut header = [0u8; 16];
+        remote.read_exact(&mut header).unwrap();
+        assert!(header[4..].starts_with(b"ping"));
+
+        let unanswered = later + Duration::from_secs(PING_TIMEOUT);
+        assert_eq!(register.reap_stale(unanswered), vec![id]);
+        assert_eq!(register.len(), 0);
+        assert!(register.peer_states().is_empty());
+    }
+}
@@ -1,17 +1,25 @@
 use bitcoin_hashes::{sha256d, Hash};
-use secp256k1::{ecdsa, Message, PublicKey, Secp256k1};
+use secp256k1::{ecdsa, Message, PublicKey, Secp256k1, VerifyOnly};
+use std::sync::OnceLock;
 
 use crate::{
-    constants::{P2PKH_BYTE, P2SH_BYTE, SIGHASH_ALL},
+    address::{validate_address, AddressType},
+    constants::{
+        MAX_OP_RETURN_SIZE, SIGHASH_ALL, SIGHASH_ANYONECANPAY, SIGHASH_NONE, SIGHASH_SINGLE,
+    },
+    message_header::network,
     protocol_error::ProtocolError,
-    raw_transaction::RawTransaction,
-    utils::hash160,
+    raw_transaction::{RawTransaction, SegwitMidstate},
+    signature_cache::shared_signature_cache,
+    utils::{bytes_to_hex_string, hash160},
 };
 
 #[derive(Debug, Default, Clone)]
 pub enum PubKeyScript {
     P2PKH(Vec<u8>),
     P2SH(Vec<u8>),
+    /// Version 0 witness program of a public key hash
+    P2WPKH(Vec<u8>),
     SCRIPT(Vec<u8>),
     #[default]
     EMPTY,
@@ -23,8 +31,14 @@ const OP_DUP: u8 = 118;
 const OP_HASH160: u8 = 169;
 const OP_CHECKSIG: u8 = 172;
 const OP_HASH256: u8 = 170;
+const OP_RETURN: u8 = 106;
+const OP_PUSHDATA1: u8 = 76;
+const OP_PUSHDATA2: u8 = 77;
+const OP_PUSHDATA4: u8 = 78;
+const OP_1NEGATE: u8 = 79;
 const OP_0: u8 = 0;
-const OP_1: u8 = 1;
+const OP_1: u8 = 81;
+const OP_16: u8 = 96;
 
 impl PubKeyScript {
     pub fn from_bytes(bytes: Vec<u8>) -> PubKeyScript {
@@ -40,27 +54,53 @@ impl PubKeyScript {
                 let reedeem_script_hash = &bytes[2..22];
                 PubKeyScript::P2SH(reedeem_script_hash.to_vec())
             }
+            [OP_0, 20, pkhash @ ..] if pkhash.len() == 20 => PubKeyScript::P2WPKH(pkhash.to_vec()),
             _ => PubKeyScript::SCRIPT(bytes),
         }
     }
 
     pub fn from_address(address: &str) -> Result<PubKeyScript, ProtocolError> {
+        let address_type = validate_address(address)?;
         let address_decoded = bs58::decode(address)
             .into_vec()
             .map_err(|_| ProtocolError::Error("Error parsing base 58".to_string()))?;
 
-        let len = address_decoded.len();
-        let checksum = &address_decoded[(len - 4)..len];
-        let check = &sha256d::Hash::hash(&address_decoded[..(len - 4)]).to_byte_array()[0..4];
+        match address_type {
+            AddressType::P2PKH => Ok(PubKeyScript::P2PKH(address_decoded[1..21].to_vec())),
+            AddressType::P2SH => Ok(PubKeyScript::P2SH(address_decoded[1..21].to_vec())),
+        }
+    }
+
+    /// Unspendable script that carries the data, up to MAX_OP_RETURN_SIZE bytes.
+    pub fn op_return(data: &[u8]) -> Result<PubKeyScript, ProtocolError> {
+        if data.is_empty() || data.len() > MAX_OP_RETURN_SIZE {
+            return Err(ProtocolError::Error(format!(
+                "OP_RETURN data must have between 1 and {} bytes",
+                MAX_OP_RETURN_SIZE
+            )));
+        }
 
-        if check != checksum {
-            return Err(ProtocolError::Error("Invalid address checksum".to_string()));
+        let mut script = vec![OP_RETURN];
+        if data.len() >= OP_PUSHDATA1 as usize {
+            script.push(OP_PUSHDATA1);
         }
+        script.push(data.len() as u8);
+        script.extend_from_slice(data);
+        Ok(PubKeyScript::SCRIPT(script))
+    }
 
-        match address_decoded[0] {
-            P2PKH_BYTE => Ok(PubKeyScript::P2PKH(address_decoded[1..21].to_vec())),
-            P2SH_BYTE => Ok(PubKeyScript::P2SH(address_decoded[1..21].to_vec())),
-            _ => Ok(PubKeyScript::SCRIPT(address_decoded[1..21].to_vec())),
+    /// Data carried by an OP_RETURN script, None for any other script.
+    pub fn op_return_data(&self) -> Option<Vec<u8>> {
+        let script = match self {
+            PubKeyScript::SCRIPT(script) => script,
+            _ => return None,
+        };
+        match &script[..] {
+            [OP_RETURN, OP_PUSHDATA1, len, data @ ..] if *len as usize == data.len() => {
+                Some(data.to_vec())
+            }
+            [OP_RETURN, len, data @ ..] if *len as usize == data.len() => Some(data.to_vec()),
+            _ => None,
         }
     }
 
@@ -113,6 +153,7 @@ impl PubKeyScript {
             ]
             .concat(),
             PubKeyScript::P2SH(rhash) => [&[OP_HASH160, 20], &rhash[..], &[OP_EQUAL]].concat(),
+            PubKeyScript::P2WPKH(pkhash) => [&[OP_0, 20], &pkhash[..]].concat(),
             PubKeyScript::SCRIPT(b) => b.to_vec(),
             _ => vec![],
         }
@@ -121,13 +162,13 @@ impl PubKeyScript {
     pub fn get_address(&self) -> String {
         match self {
             PubKeyScript::P2PKH(pkhash) => {
-                let mut addr = [&[P2PKH_BYTE], &pkhash[..]].concat();
+                let mut addr = [&[network().p2pkh_byte()], &pkhash[..]].concat();
                 let checksum = &sha256d::Hash::hash(&addr).to_byte_array()[0..4];
                 addr.extend_from_slice(checksum);
                 bs58::encode(addr).into_string()
             }
             PubKeyScript::P2SH(pkhash) => {
-                let mut addr = [&[P2SH_BYTE], &pkhash[..]].concat();
+                let mut addr = [&[network().p2sh_byte()], &pkhash[..]].concat();
                 let checksum = &sha256d::Hash::hash(&addr).to_byte_array()[0..4];
                 addr.extend_from_slice(checksum);
                 bs58::encode(addr).into_string()
@@ -137,107 +178,471 @@ impl PubKeyScript {
     }
 }
 
+/// A signature of an input, without its sighash byte, to be checked against a public key.
+#[derive(Debug, Clone)]
+pub struct SignatureCheck {
+    pub signature: Vec<u8>,
+    pub pubkey: Vec<u8>,
+    pub sighash: [u8; 32],
+}
+
+impl SignatureCheck {
+    /// Extracts the signature check of an input that spends a P2PKH output, from the last two
+    /// pushes of its signature script like the script would take them.
+    /// Returns None when the output isn't P2PKH, the signature script isn't push only or the
+    /// sighash type of the input is unknown, those inputs aren't checked.
+    pub fn from_p2pkh_input(
+        pubkey_script: &PubKeyScript,
+        tx: &RawTransaction,
+        input: usize,
+    ) -> Result<Option<SignatureCheck>, ProtocolError> {
+        let pkhash = match pubkey_script {
+            PubKeyScript::P2PKH(pkhash) => pkhash,
+            _ => return Ok(None),
+        };
+        let txin = tx
+            .tx_in
+            .get(input)
+            .ok_or_else(|| ProtocolError::Error("Input out of range".to_string()))?;
+
+        let pushes = match parse_pushes(&txin.signature_script) {
+            Some(pushes) => pushes,
+            None => return Ok(None),
+        };
+        let (signature, pubkey) = match &pushes[..] {
+            [.., signature, pubkey] if !signature.is_empty() => (signature, pubkey),
+            _ => {
+                return Err(ProtocolError::Error(
+                    "Signature script is not P2PKH".to_string(),
+                ))
+            }
+        };
+
+        if hash160(pubkey)[..] != pkhash[..] {
+            return Err(ProtocolError::Error(
+                "Public key doesn't match the public key hash".to_string(),
+            ));
+        }
+        let sighash_type = signature[signature.len() - 1];
+        if !is_valid_sighash_type(sighash_type) {
+            return Ok(None);
+        }
+
+        let sighash = tx.signature_hash(input, pubkey_script.to_vec(), sighash_type);
+
+        Ok(Some(SignatureCheck {
+            signature: signature[..(signature.len() - 1)].to_vec(),
+            pubkey: pubkey.to_vec(),
+            sighash,
+        }))
+    }
+
+    /// Extracts the signature check of the witness of an input that spends a P2WPKH output
+    /// of the amount, with the BIP143 sighash. The midstate of the transaction is reused.
+    /// Returns None when the output isn't P2WPKH or the sighash type of the input is unknown.
+    pub fn from_p2wpkh_witness(
+        pubkey_script: &PubKeyScript,
+        tx: &RawTransaction,
+        midstate: &SegwitMidstate,
+        input: usize,
+        amount: i64,
+        witness: &[Vec<u8>],
+    ) -> Result<Option<SignatureCheck>, ProtocolError> {
+        let pkhash = match pubkey_script {
+            PubKeyScript::P2WPKH(pkhash) => pkhash,
+            _ => return Ok(None),
+        };
+        if input >= tx.tx_in.len() {
+            return Err(ProtocolError::Error("Input out of range".to_string()));
+        }
+        if !tx.tx_in[input].signature_script.is_empty() {
+            return Err(ProtocolError::Error(
+                "Signature script of a witness input must be empty".to_string(),
+            ));
+        }
+        let (signature, pubkey) = match witness {
+            [signature, pubkey] if !signature.is_empty() => (signature, pubkey),
+            _ => return Err(ProtocolError::Error("Witness is not P2WPKH".to_string())),
+        };
+
+        if hash160(pubkey)[..] != pkhash[..] {
+            return Err(ProtocolError::Error(
+                "Public key doesn't match the public key hash".to_string(),
+            ));
+        }
+        let sighash_type = signature[signature.len() - 1];
+        if !is_valid_sighash_type(sighash_type) {
+            return Ok(None);
+        }
+
+        // The script code of P2WPKH is the P2PKH script of the same hash
+        let script_code = PubKeyScript::P2PKH(pkhash.to_vec()).to_vec();
+        let sighash = tx.segwit_signature_hash(midstate, input, &script_code, amount, sighash_type);
+
+        Ok(Some(SignatureCheck {
+            signature: signature[..(signature.len() - 1)].to_vec(),
+            pubkey: pubkey.to_vec(),
+            sighash,
+        }))
+    }
+}
+
+/// Returns the verification context shared by every signature check, creating it only once.
+pub fn verification_context() -> &'static Secp256k1<VerifyOnly> {
+    static CONTEXT: OnceLock<Secp256k1<VerifyOnly>> = OnceLock::new();
+    CONTEXT.get_or_init(Secp256k1::verification_only)
+}
+
+/// Verifies every signature in one pass with the shared context.
+/// It stops at the first invalid signature.
+pub fn batch_verify(checks: &[SignatureCheck]) -> bool {
+    checks
+        .iter()
+        .all(|check| verify_signature(&check.signature, &check.pubkey, &check.sighash))
+}
+
+/// Verifies a DER signature, without its sighash byte, using the signature cache.
+fn verify_signature(signature: &[u8], pubkey: &[u8], sighash: &[u8; 32]) -> bool {
+    let cache = shared_signature_cache();
+    if let Ok(mut cache) = cache.lock() {
+        if cache.contains(signature, pubkey, sighash) {
+            return true;
+        }
+    }
+
+    let m = match Message::from_digest_slice(sighash) {
+        Ok(m) => m,
+        Err(_) => return false,
+    };
+    let mut s = match ecdsa::Signature::from_der(signature) {
+        Ok(s) => s,
+        Err(_) => return false,
+    };
+    let p = match PublicKey::from_slice(pubkey) {
+        Ok(p) => p,
+        Err(_) => return false,
+    };
+    // High S values are valid by consensus, they are only rejected as non standard.
+    s.normalize_s();
+
+    if verification_context().verify_ecdsa(&m, &s, &p).is_err() {
+        return false;
+    }
+
+    if let Ok(mut cache) = cache.lock() {
+        cache.insert(signature, pubkey, sighash);
+    }
+    true
+}
+
+/// Data pushed by every operation of a push only script, the numbers of OP_1NEGATE and
+/// OP_1 to OP_16 included. None if it has another opcode or a push goes past the end.
+/// OP_RESERVED isn't a push, it fails the script when it runs.
+fn parse_pushes(script: &[u8]) -> Option<Vec<Vec<u8>>> {
+    let mut pushes = vec![];
+    let mut i = 0;
+    while i < script.len() {
+        let op = script[i];
+        i += 1;
+        let len = match op {
+            op if op < OP_PUSHDATA1 => op as usize,
+            OP_PUSHDATA1 | OP_PUSHDATA2 | OP_PUSHDATA4 => {
+                // The length takes 1, 2 or 4 little endian bytes
+                let width = 1 << (op - OP_PUSHDATA1);
+                let bytes = script.get(i..i + width)?;
+                i += width;
+                bytes
+                    .iter()
+                    .rev()
+                    .fold(0, |len, byte| (len << 8) | *byte as usize)
+            }
+            OP_1NEGATE => {
+                pushes.push(vec![0x81]);
+                continue;
+            }
+            OP_1..=OP_16 => {
+                pushes.push(vec![op - OP_1 + 1]);
+                continue;
+            }
+            _ => return None,
+        };
+        pushes.push(script.get(i..i.checked_add(len)?)?.to_vec());
+        i += len;
+    }
+    Some(pushes)
+}
+
+/// Checks that a signature script only pushes data and, when it has the P2PKH shape,
+/// that its signature is strict DER, has a low S value and a defined sighash type.
+pub fn is_standard_signature_script(script: &[u8]) -> bool {
+    let pushes = match parse_pushes(script) {
+        None => return false,
+        Some(pushes) => pushes,
+    };
+
+    match &pushes[..] {
+        [signature, pubkey] if is_pubkey_encoding(pubkey) => {
+            is_strict_der_signature(signature) && is_low_s_signature(signature)
+        }
+        _ => true,
+    }
+}
+
+fn is_pubkey_encoding(pubkey: &[u8]) -> bool {
+    match pubkey.first() {
+        Some(2) | Some(3) => pubkey.len() == 33,
+        Some(4) => pubkey.len() == 65,
+        _ => false,
+    }
+}
+
+/// Checks that a signature followed by its sighash byte follows the strict DER encoding of BIP66.
+pub fn is_strict_der_signature(sig: &[u8]) -> bool {
+    let len = sig.len();
+    if !(9..=73).contains(&len) || sig[0] != 0x30 || sig[1] as usize != len - 3 {
+        return false;
+    }
+
+    let len_r = sig[3] as usize;
+    if 5 + len_r >= len {
+        return false;
+    }
+    let len_s = sig[5 + len_r] as usize;
+    if len_r + len_s + 7 != len {
+        return false;
+    }
+
+    if sig[2] != 0x02 || len_r == 0 || sig[4] & 0x80 != 0 {
+        return false;
+    }
+    if len_r > 1 && sig[4] == 0 && sig[5] & 0x80 == 0 {
+        return false;
+    }
+
+    if sig[len_r + 4] != 0x02 || len_s == 0 || sig[len_r + 6] & 0x80 != 0 {
+        return false;
+    }
+    if len_s > 1 && sig[len_r + 6] == 0 && sig[len_r + 7] & 0x80 == 0 {
+        return false;
+    }
+
+    is_valid_sighash_type(sig[len - 1])
+}
+
+/// SIGHASH_ALL, SIGHASH_NONE or SIGHASH_SINGLE, with or without SIGHASH_ANYONECANPAY.
+pub fn is_valid_sighash_type(sighash_type: u8) -> bool {
+    matches!(
+        sighash_type & !SIGHASH_ANYONECANPAY,
+        SIGHASH_ALL | SIGHASH_NONE | SIGHASH_SINGLE
+    )
+}
+
+/// Checks that the S value of a signature followed by its sighash byte is in the lower half of the curve order.
+pub fn is_low_s_signature(sig: &[u8]) -> bool {
+    if sig.is_empty() {
+        return false;
+    }
+    match ecdsa::Signature::from_der(&sig[..(sig.len() - 1)]) {
+        Ok(signature) => {
+            let mut normalized = signature;
+            normalized.normalize_s();
+            normalized == signature
+        }
+        Err(_) => false,
+    }
+}
+
 fn evaluate_script(pubkey_script: Vec<u8>, tx: RawTransaction, input: usize) -> bool {
-    let mut stack: Vec<Vec<u8>> = vec![];
-    let mut script = pubkey_script.clone();
-    script.reverse();
-    let mut tmp = tx.tx_in[input].signature_script.clone();
-    tmp.reverse();
-    script.extend_from_slice(&tmp[..]);
-
-    while let Some(op) = script.pop() {
-        match op {
-            1..=75 => {
-                let mut v: Vec<u8> = vec![];
-                for _ in 0..op {
-                    v.push(script.pop().unwrap());
+    trace_script(&pubkey_script, &tx, input).result.is_ok()
+}
+
+/// Why a script failed, named like the script errors of Bitcoin Core.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScriptError {
+    UnknownError,
+    EvalFalse,
+    OpReturn,
+    BadOpcode,
+    InvalidStackOperation,
+    EqualVerify,
+}
+
+impl ScriptError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            ScriptError::UnknownError => "UNKNOWN_ERROR",
+            ScriptError::EvalFalse => "EVAL_FALSE",
+            ScriptError::OpReturn => "OP_RETURN",
+            ScriptError::BadOpcode => "BAD_OPCODE",
+            ScriptError::InvalidStackOperation => "INVALID_STACK_OPERATION",
+            ScriptError::EqualVerify => "EQUALVERIFY",
+        }
+    }
+}
+
+/// An operation of a script and the stack it left, the top of the stack is the last item.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceStep {
+    pub operation: String,
+    pub stack: Vec<Vec<u8>>,
+}
+
+/// Every operation run by the signature script and then by the public key script of an input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptTrace {
+    pub steps: Vec<TraceStep>,
+    pub result: Result<(), ScriptError>,
+}
+
+/// Runs the signature script of the input and then the public key script on the same stack,
+/// recording the stack after each operation. The failing operation isn't recorded.
+pub fn trace_script(pubkey_script: &[u8], tx: &RawTransaction, input: usize) -> ScriptTrace {
+    let mut steps = vec![];
+    let signature_script = match tx.tx_in.get(input) {
+        Some(txin) => &txin.signature_script,
+        None => {
+            return ScriptTrace {
+                steps,
+                result: Err(ScriptError::UnknownError),
+            }
+        }
+    };
+
+    let mut stack = vec![];
+    let result = run_script(signature_script, tx, input, &mut stack, &mut steps)
+        .and_then(|_| run_script(pubkey_script, tx, input, &mut stack, &mut steps))
+        .and_then(|_| match stack.last() {
+            Some(top) if cast_to_bool(top) => Ok(()),
+            _ => Err(ScriptError::EvalFalse),
+        });
+
+    ScriptTrace { steps, result }
+}
+
+fn run_script(
+    script: &[u8],
+    tx: &RawTransaction,
+    input: usize,
+    stack: &mut Vec<Vec<u8>>,
+    steps: &mut Vec<TraceStep>,
+) -> Result<(), ScriptError> {
+    let mut i = 0;
+    while i < script.len() {
+        let op = script[i];
+        i += 1;
+
+        let operation = match op {
+            OP_0..=OP_PUSHDATA1 => {
+                let len = match op {
+                    OP_PUSHDATA1 => {
+                        let len = *script.get(i).ok_or(ScriptError::BadOpcode)? as usize;
+                        i += 1;
+                        len
+                    }
+                    _ => op as usize,
+                };
+                let data = script.get(i..i + len).ok_or(ScriptError::BadOpcode)?;
+                i += len;
+                stack.push(data.to_vec());
+                if data.is_empty() {
+                    "0".to_string()
+                } else {
+                    bytes_to_hex_string(data)
                 }
-                stack.push(v);
+            }
+            OP_1..=OP_16 => {
+                stack.push(vec![op - OP_1 + 1]);
+                (op - OP_1 + 1).to_string()
             }
             OP_DUP => {
-                let a = stack.len();
-                if a < 1 {
-                    return false;
-                }
-                stack.push(stack[stack.len() - 1].clone());
+                let top = stack.last().ok_or(ScriptError::InvalidStackOperation)?;
+                stack.push(top.clone());
+                "OP_DUP".to_string()
             }
             OP_HASH160 => {
-                match stack.pop() {
-                    None => return false,
-                    Some(h) => stack.push(hash160(&h).to_vec()),
-                };
+                let top = stack.pop().ok_or(ScriptError::InvalidStackOperation)?;
+                stack.push(hash160(&top).to_vec());
+                "OP_HASH160".to_string()
             }
-            OP_EQUAL => {
-                if stack.len() < 2 {
-                    return false;
-                }
-                let a = stack.pop().unwrap();
-                let b = stack.pop().unwrap();
-
-                if a != b {
-                    stack.push(vec![OP_1]);
-                } else {
-                    stack.push(vec![OP_0]);
-                }
+            OP_HASH256 => {
+                let top = stack.pop().ok_or(ScriptError::InvalidStackOperation)?;
+                stack.push(sha256d::Hash::hash(&top).to_byte_array().to_vec());
+                "OP_HASH256".to_string()
             }
-            OP_EQUALVERIFY => {
+            OP_EQUAL | OP_EQUALVERIFY => {
                 if stack.len() < 2 {
-                    return false;
+                    return Err(ScriptError::InvalidStackOperation);
                 }
-                let a = stack.pop().unwrap();
-                let b = stack.pop().unwrap();
-
-                if a != b {
-                    return false;
+                let a = stack.pop();
+                let b = stack.pop();
+                if op == OP_EQUALVERIFY {
+                    if a != b {
+                        return Err(ScriptError::EqualVerify);
+                    }
+                    "OP_EQUALVERIFY".to_string()
+                } else {
+                    stack.push(script_bool(a == b));
+                    "OP_EQUAL".to_string()
                 }
             }
             OP_CHECKSIG => {
                 if stack.len() < 2 {
-                    return false;
+                    return Err(ScriptError::InvalidStackOperation);
                 }
-                let pk = stack.pop().unwrap();
-                let mut signature = stack.pop().unwrap();
-                if let Some(flag) = signature.pop() {
-                    if flag != SIGHASH_ALL {
-                        return false;
-                    }
-                } else {
-                    return false;
-                };
+                let pubkey = stack.pop().unwrap_or_default();
+                let signature = stack.pop().unwrap_or_default();
+                stack.push(script_bool(check_signature(
+                    &signature, &pubkey, script, tx, input,
+                )));
+                "OP_CHECKSIG".to_string()
+            }
+            OP_RETURN => return Err(ScriptError::OpReturn),
+            _ => return Err(ScriptError::BadOpcode),
+        };
 
-                let serialization =
-                    sha256d::Hash::hash(&tx.serialize(input, pubkey_script)).to_byte_array();
+        steps.push(TraceStep {
+            operation,
+            stack: stack.clone(),
+        });
+    }
+    Ok(())
+}
 
-                let secp = Secp256k1::verification_only();
-                let m = Message::from_slice(&serialization).unwrap();
-                let s = ecdsa::Signature::from_der(&signature).unwrap();
-                let p = PublicKey::from_slice(&pk).unwrap();
+/// Checks a signature followed by its sighash byte against the script being run.
+fn check_signature(
+    signature: &[u8],
+    pubkey: &[u8],
+    script: &[u8],
+    tx: &RawTransaction,
+    input: usize,
+) -> bool {
+    let (flag, signature) = match signature.split_last() {
+        Some((flag, signature)) if is_valid_sighash_type(*flag) => (*flag, signature),
+        _ => return false,
+    };
+    let sighash = tx.signature_hash(input, script.to_vec(), flag);
+    verify_signature(signature, pubkey, &sighash)
+}
 
-                if secp.verify_ecdsa(&m, &s, &p).is_ok() {
-                    return true;
-                } else {
-                    return false;
-                };
-            }
-            OP_HASH256 => {
-                let h = match stack.pop() {
-                    None => return false,
-                    Some(i) => i,
-                };
-                let hash = sha256d::Hash::hash(&h).to_byte_array();
-                stack.push(hash.to_vec());
-            }
-            _ => return false,
-        }
+fn script_bool(value: bool) -> Vec<u8> {
+    if value {
+        vec![1]
+    } else {
+        vec![]
     }
+}
 
-    if let Some(a) = stack.last() {
-        a != &vec![OP_0]
-    } else {
-        false
+/// Stack items are false when every byte is zero, allowing a negative zero in the last one.
+fn cast_to_bool(item: &[u8]) -> bool {
+    match item.split_last() {
+        Some((last, rest)) => rest.iter().any(|byte| *byte != 0) || (*last & 0x7f) != 0,
+        None => false,
     }
 }
 
+#[cfg(test)]
+mod vectors;
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -389,4 +794,235 @@ mod tests {
 
         assert!(evaluate_script(public_key_script, raw_tx, 0,));
     }
+
+    fn signature_from_test_vector() -> Vec<u8> {
+        vec![
+            48, 68, 2, 32, 16, 114, 141, 101, 111, 81, 134, 112, 25, 141, 89, 61, 144, 91, 193, 39,
+            43, 188, 8, 179, 216, 73, 62, 109, 94, 216, 171, 164, 229, 102, 5, 230, 2, 32, 122, 7,
+            193, 75, 159, 19, 39, 164, 212, 47, 175, 242, 219, 186, 222, 61, 116, 88, 135, 4, 230,
+            2, 145, 176, 153, 168, 111, 90, 9, 176, 63, 19, 1,
+        ]
+    }
+
+    #[test]
+    fn test_strict_der_signature() {
+        let signature = signature_from_test_vector();
+        assert!(is_strict_der_signature(&signature));
+        assert!(is_low_s_signature(&signature));
+
+        let mut wrong_header = signature.clone();
+        wrong_header[0] = 0x31;
+        assert!(!is_strict_der_signature(&wrong_header));
+
+        let mut wrong_length = signature.clone();
+        wrong_length[1] += 1;
+        assert!(!is_strict_der_signature(&wrong_length));
+
+        let mut undefined_sighash = signature.clone();
+        *undefined_sighash.last_mut().unwrap() = 0x05;
+        assert!(!is_strict_der_signature(&undefined_sighash));
+    }
+
+    #[test]
+    fn test_high_s_signature_is_not_standard() {
+        let signature = signature_from_test_vector();
+        let der = &signature[..(signature.len() - 1)];
+        let compact = ecdsa::Signature::from_der(der).unwrap().serialize_compact();
+
+        // n - s, where n is the order of the curve
+        let order: [u8; 32] = [
+            255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 254, 186,
+            174, 220, 230, 175, 72, 160, 59, 191, 210, 94, 140, 208, 54, 65, 65,
+        ];
+        let mut high_s = [0u8; 32];
+        let mut borrow = 0i16;
+        for i in (0..32).rev() {
+            let diff = order[i] as i16 - compact[32 + i] as i16 - borrow;
+            borrow = if diff < 0 { 1 } else { 0 };
+            high_s[i] = diff.rem_euclid(256) as u8;
+        }
+
+        let high_s_signature =
+            ecdsa::Signature::from_compact(&[&compact[..32], &high_s[..]].concat()).unwrap();
+        let mut high_s_der = high_s_signature.serialize_der().to_vec();
+        high_s_der.push(SIGHASH_ALL);
+
+        assert!(is_strict_der_signature(&high_s_der));
+        assert!(!is_low_s_signature(&high_s_der));
+
+        let pubkey = [2u8; 33];
+        let script = [
+            &[high_s_der.len() as u8],
+            &high_s_der[..],
+            &[33],
+            &pubkey[..],
+        ]
+        .concat();
+        assert!(!is_standard_signature_script(&script));
+    }
+
+    #[test]
+    fn test_signature_script_must_be_push_only() {
+        let signature = signature_from_test_vector();
+        let pubkey = [2u8; 33];
+        let script = [&[signature.len() as u8], &signature[..], &[33], &pubkey[..]].concat();
+        assert!(is_standard_signature_script(&script));
+
+        let with_opcode = [&script[..], &[OP_DUP]].concat();
+        assert!(!is_standard_signature_script(&with_opcode));
+
+        let long_pushes = [
+            &[OP_PUSHDATA1, signature.len() as u8],
+            &signature[..],
+            &[OP_PUSHDATA2, 33, 0],
+            &pubkey[..],
+        ]
+        .concat();
+        assert!(is_standard_signature_script(&long_pushes));
+        let numbers = [OP_0, OP_1NEGATE, OP_1, OP_16, OP_PUSHDATA4, 1, 0, 0, 0, 7];
+        assert!(is_standard_signature_script(&numbers));
+        let past_the_end = [OP_PUSHDATA4, 2, 0, 0, 0, 7];
+        assert!(!is_standard_signature_script(&past_the_end));
+        assert!(!is_standard_signature_script(&[OP_PUSHDATA2, 1]));
+        // OP_RESERVED
+        assert!(!is_standard_signature_script(&[OP_1NEGATE + 1]));
+    }
+
+    #[test]
+    fn test_op_return_script_carries_the_data() {
+        let script = PubKeyScript::op_return(b"hello").unwrap();
+        assert_eq!(script.to_vec(), [&[OP_RETURN, 5], &b"hello"[..]].concat());
+        let parsed = PubKeyScript::from_bytes(script.to_vec());
+        assert_eq!(parsed.op_return_data(), Some(b"hello".to_vec()));
+
+        let long = [7u8; MAX_OP_RETURN_SIZE];
+        let script = PubKeyScript::op_return(&long).unwrap();
+        assert_eq!(script.to_vec()[..3], [OP_RETURN, OP_PUSHDATA1, 80]);
+        assert_eq!(script.op_return_data(), Some(long.to_vec()));
+
+        assert!(PubKeyScript::op_return(&[7u8; MAX_OP_RETURN_SIZE + 1]).is_err());
+        assert!(PubKeyScript::op_return(&[]).is_err());
+        assert_eq!(PubKeyScript::P2PKH(vec![0; 20]).op_return_data(), None);
+    }
+
+    #[test]
+    fn test_every_sighash_type_is_signed_and_verified() {
+        let wif = "cSnB7AwCEDKrdq1x2XmHu8f1BHPh6KeuBjeXgssDe2cMpeGDM7oB";
+        let pkhash = crate::utils::wif_to_pkhash(wif).unwrap().to_vec();
+        let out = crate::blockchain::utxo_set::Output {
+            index: 0,
+            value: 10,
+            pkscript: PubKeyScript::P2PKH(pkhash.clone()),
+        };
+        let spend = vec![([1; 32], out.clone()), ([2; 32], out.clone())];
+        let outputs = || {
+            vec![
+                TxOut::new(4, PubKeyScript::P2PKH(pkhash.clone()).to_vec()),
+                TxOut::new(5, PubKeyScript::P2PKH(pkhash.clone()).to_vec()),
+            ]
+        };
+
+        for sighash_type in [
+            SIGHASH_ALL,
+            SIGHASH_NONE,
+            SIGHASH_SINGLE,
+            SIGHASH_ALL | SIGHASH_ANYONECANPAY,
+            SIGHASH_NONE | SIGHASH_ANYONECANPAY,
+            SIGHASH_SINGLE | SIGHASH_ANYONECANPAY,
+        ] {
+            let options = crate::raw_transaction::TxOptions {
+                sighash_type,
+                ..Default::default()
+            };
+            let mut tx =
+                RawTransaction::create_transaction(spend.clone(), outputs(), wif, &options)
+                    .unwrap();
+            assert!(tx.is_standard());
+            for input in 0..2 {
+                assert!(out.pkscript.evaluate(tx.clone(), input));
+                let check = SignatureCheck::from_p2pkh_input(&out.pkscript, &tx, input).unwrap();
+                assert!(batch_verify(&[check.unwrap()]));
+            }
+
+            // The same pushes, not minimally encoded, are valid too
+            let pushes = parse_pushes(&tx.tx_in[0].signature_script).unwrap();
+            let mut non_minimal = tx.clone();
+            non_minimal.tx_in[0].signature_script = [
+                &[OP_PUSHDATA1, pushes[0].len() as u8],
+                &pushes[0][..],
+                &[OP_PUSHDATA1, pushes[1].len() as u8],
+                &pushes[1][..],
+            ]
+            .concat();
+            let check = SignatureCheck::from_p2pkh_input(&out.pkscript, &non_minimal, 0).unwrap();
+            assert!(batch_verify(&[check.unwrap()]));
+
+            // Only SIGHASH_NONE leaves every output out of the signatures
+            tx.tx_out[1].value = 1;
+            let base_type = sighash_type & !SIGHASH_ANYONECANPAY;
+            assert_eq!(
+                out.pkscript.evaluate(tx.clone(), 0),
+                base_type != SIGHASH_ALL
+            );
+            assert_eq!(
+                out.pkscript.evaluate(tx.clone(), 1),
+                base_type == SIGHASH_NONE
+            );
+        }
+    }
+
+    #[test]
+    fn test_unknown_sighash_types_are_invalid() {
+        assert!(is_valid_sighash_type(SIGHASH_SINGLE | SIGHASH_ANYONECANPAY));
+        assert!(!is_valid_sighash_type(0));
+        assert!(!is_valid_sighash_type(4));
+        assert!(!is_valid_sighash_type(SIGHASH_ALL | 0x40));
+    }
+
+    #[test]
+    fn test_p2wpkh_inputs_are_signed_and_verified() {
+        let wif = "cSnB7AwCEDKrdq1x2XmHu8f1BHPh6KeuBjeXgssDe2cMpeGDM7oB";
+        let pkhash = crate::utils::wif_to_pkhash(wif).unwrap().to_vec();
+        let pkscript = PubKeyScript::P2WPKH(pkhash.clone());
+        assert!(matches!(
+            PubKeyScript::from_bytes(pkscript.to_vec()),
+            PubKeyScript::P2WPKH(_)
+        ));
+
+        let tx_in = vec![
+            TxIn::new(Outpoint::new([1; 32], 0), vec![]),
+            TxIn::new(Outpoint::new([2; 32], 1), vec![]),
+        ];
+        let tx_out = vec![TxOut::new(7, PubKeyScript::P2PKH(pkhash).to_vec())];
+        let mut tx = RawTransaction::new(tx_in, tx_out);
+        let midstate = tx.segwit_midstate();
+
+        for sighash_type in [SIGHASH_ALL, SIGHASH_NONE | SIGHASH_ANYONECANPAY] {
+            let witness = tx
+                .sign_p2wpkh_input(&midstate, 1, 10, wif, sighash_type)
+                .unwrap();
+            let check =
+                SignatureCheck::from_p2wpkh_witness(&pkscript, &tx, &midstate, 1, 10, &witness)
+                    .unwrap()
+                    .unwrap();
+            assert!(batch_verify(&[check]));
+
+            // The amount is always signed
+            let check =
+                SignatureCheck::from_p2wpkh_witness(&pkscript, &tx, &midstate, 1, 11, &witness)
+                    .unwrap()
+                    .unwrap();
+            assert!(!batch_verify(&[check]));
+        }
+
+        let witness = tx
+            .sign_p2wpkh_input(&midstate, 0, 10, wif, SIGHASH_ALL)
+            .unwrap();
+        tx.tx_out[0].value = 8;
+        let tampered = tx.segwit_midstate();
+        let check = SignatureCheck::from_p2wpkh_witness(&pkscript, &tx, &tampered, 0, 10, &witness)
+            .unwrap()
+            .unwrap();
+        assert!(!batch_verify(&[check]));
+    }
 }
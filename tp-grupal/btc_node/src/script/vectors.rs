@@ -0,0 +1,128 @@
+use super::*;
+use crate::raw_transaction::{unhexlify, Outpoint, TxIn, TxOut};
+
+const SCRIPT_TESTS: &str = include_str!("script_tests.json");
+
+/// Reads the rows of a JSON array of arrays of strings.
+fn parse_rows(json: &str) -> Vec<Vec<String>> {
+    let mut rows = vec![];
+    let mut row = vec![];
+    let mut chars = json.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                let mut item = String::new();
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => item.extend(chars.next()),
+                        _ => item.push(c),
+                    }
+                }
+                row.push(item);
+            }
+            ']' if !row.is_empty() => rows.push(std::mem::take(&mut row)),
+            _ => {}
+        }
+    }
+    rows
+}
+
+fn push(data: &[u8]) -> Vec<u8> {
+    let mut script = vec![];
+    match data.len() {
+        0 => script.push(OP_0),
+        len if len < OP_PUSHDATA1 as usize => script.push(len as u8),
+        len => script.extend([OP_PUSHDATA1, len as u8]),
+    }
+    script.extend_from_slice(data);
+    script
+}
+
+/// Assembles the script notation of the vectors, None when it has an opcode the engine doesn't run.
+fn assemble(asm: &str) -> Option<Vec<u8>> {
+    let mut script = vec![];
+    for token in asm.split_whitespace() {
+        if let Some(hex) = token.strip_prefix("0x") {
+            script.extend(unhexlify(hex).expect("Invalid hex in a script vector"));
+        } else if let Some(text) = token.strip_prefix('\'') {
+            script.extend(push(text.trim_end_matches('\'').as_bytes()));
+        } else if let Ok(number) = token.parse::<u8>() {
+            match number {
+                0 => script.push(OP_0),
+                1..=16 => script.push(OP_1 + number - 1),
+                _ => script.extend(push(&[number])),
+            }
+        } else {
+            let opcode = match token.strip_prefix("OP_").unwrap_or(token) {
+                "PUSHDATA1" => OP_PUSHDATA1,
+                "DUP" => OP_DUP,
+                "HASH160" => OP_HASH160,
+                "HASH256" => OP_HASH256,
+                "EQUAL" => OP_EQUAL,
+                "EQUALVERIFY" => OP_EQUALVERIFY,
+                "CHECKSIG" => OP_CHECKSIG,
+                "RETURN" => OP_RETURN,
+                _ => return None,
+            };
+            script.push(opcode);
+        }
+    }
+    Some(script)
+}
+
+fn spending_tx(signature_script: Vec<u8>) -> RawTransaction {
+    let txin = TxIn::new(Outpoint::new([0; 32], 0), signature_script);
+    RawTransaction::new(vec![txin], vec![TxOut::new(0, vec![])])
+}
+
+#[test]
+fn test_script_vectors() {
+    let mut run = 0;
+    for row in parse_rows(SCRIPT_TESTS) {
+        // Rows of one item are comments
+        if row.len() < 4 {
+            continue;
+        }
+        let (signature_script, pubkey_script) = match (assemble(&row[0]), assemble(&row[1])) {
+            (Some(signature_script), Some(pubkey_script)) => (signature_script, pubkey_script),
+            _ => continue,
+        };
+
+        let trace = trace_script(&pubkey_script, &spending_tx(signature_script), 0);
+        let code = match trace.result {
+            Ok(()) => "OK",
+            Err(e) => e.code(),
+        };
+        assert_eq!(code, row[3], "Script vector {:?}", row);
+        run += 1;
+    }
+    assert!(run >= 40);
+}
+
+#[test]
+fn test_trace_records_the_stack_of_every_operation() {
+    let tx = spending_tx(assemble("1 2").unwrap());
+    let trace = trace_script(&assemble("2 EQUALVERIFY 1 EQUAL").unwrap(), &tx, 0);
+
+    assert_eq!(trace.result, Ok(()));
+    let operations: Vec<&str> = trace
+        .steps
+        .iter()
+        .map(|step| step.operation.as_str())
+        .collect();
+    assert_eq!(
+        operations,
+        vec!["1", "2", "2", "OP_EQUALVERIFY", "1", "OP_EQUAL"]
+    );
+    assert_eq!(trace.steps[2].stack, vec![vec![1], vec![2], vec![2]]);
+    assert_eq!(trace.steps[5].stack, vec![vec![1]]);
+
+    let trace = trace_script(&assemble("0x01 0xab DUP RETURN").unwrap(), &tx, 0);
+    assert_eq!(trace.result, Err(ScriptError::OpReturn));
+    assert_eq!(trace.steps.last().unwrap().operation, "OP_DUP");
+    assert_eq!(
+        trace.steps.last().unwrap().stack[2..],
+        [vec![0xab], vec![0xab]]
+    );
+}
@@ -0,0 +1,200 @@
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, ErrorKind, Read, Write},
+    net::{IpAddr, Ipv6Addr, SocketAddr},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::{log_file::log_error, protocol_error::ProtocolError};
+
+// First bytes of a session file
+const MAGIC: [u8; 4] = *b"SES1";
+// Milliseconds, address, port, peer height and length of the bytes of a record
+const RECORD_HEADER_SIZE: usize = 8 + 16 + 2 + 4 + 4;
+
+/// Bytes of a message as read from a peer, with what the handlers need to know about the peer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionRecord {
+    /// Time since the recording started
+    pub elapsed: Duration,
+    pub peer: SocketAddr,
+    /// Start height of the version message of the peer
+    pub peer_height: i32,
+    /// Header and payload of the message, cut short if the peer disconnected while sending it
+    pub bytes: Vec<u8>,
+}
+
+/// Appends every message read from the peers to a session file, to replay them later.
+#[derive(Debug)]
+pub struct SessionRecorder {
+    file: Mutex<BufWriter<File>>,
+    start: Instant,
+}
+
+impl SessionRecorder {
+    /// Truncates the file, a node records only its own session.
+    pub fn create(path: &str) -> Result<SessionRecorder, ProtocolError> {
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(&MAGIC)?;
+        file.flush()?;
+        Ok(SessionRecorder {
+            file: Mutex::new(file),
+            start: Instant::now(),
+        })
+    }
+
+    /// Writes the record right away, so the session survives a crash of the node.
+    /// A failed write is logged, recording never interrupts the node.
+    pub fn record(&self, peer: SocketAddr, peer_height: i32, bytes: &[u8]) {
+        let record = SessionRecord {
+            elapsed: self.start.elapsed(),
+            peer,
+            peer_height,
+            bytes: bytes.to_vec(),
+        };
+        let res = match self.file.lock() {
+            Ok(mut file) => write_record(&mut file, &record),
+            Err(e) => Err(e.into()),
+        };
+        if let Err(e) = res {
+            log_error(format!("Couldn't record the message of {}: {}", peer, e));
+        }
+    }
+}
+
+fn write_record(file: &mut BufWriter<File>, record: &SessionRecord) -> Result<(), ProtocolError> {
+    let ip = match record.peer.ip() {
+        IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+        IpAddr::V6(ip) => ip,
+    };
+    let mut bytes = Vec::with_capacity(RECORD_HEADER_SIZE + record.bytes.len());
+    bytes.extend_from_slice(&(record.elapsed.as_millis() as u64).to_le_bytes());
+    bytes.extend_from_slice(&ip.octets());
+    bytes.extend_from_slice(&record.peer.port().to_le_bytes());
+    bytes.extend_from_slice(&record.peer_height.to_le_bytes());
+    bytes.extend_from_slice(&(record.bytes.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&record.bytes);
+    file.write_all(&bytes)?;
+    file.flush()?;
+    Ok(())
+}
+
+/// Reads the records of a session file. A record cut by a crash of the recording node ends the
+/// session without an error.
+pub fn read_session(path: &str) -> Result<Vec<SessionRecord>, ProtocolError> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut magic = [0u8; MAGIC.len()];
+    reader.read_exact(&mut magic)?;
+    if magic != MAGIC {
+        return Err(ProtocolError::Error(format!(
+            "{} is not a session file",
+            path
+        )));
+    }
+
+    let mut records = vec![];
+    loop {
+        let mut header = [0u8; RECORD_HEADER_SIZE];
+        if !read_or_end(&mut reader, &mut header)? {
+            break;
+        }
+        let len = u32::from_le_bytes(header[30..34].try_into().unwrap()) as usize;
+        let mut bytes = vec![0u8; len];
+        if !read_or_end(&mut reader, &mut bytes)? {
+            break;
+        }
+
+        let ip: [u8; 16] = header[8..24].try_into().unwrap();
+        records.push(SessionRecord {
+            elapsed: Duration::from_millis(u64::from_le_bytes(header[0..8].try_into().unwrap())),
+            peer: SocketAddr::new(
+                IpAddr::V6(Ipv6Addr::from(ip)).to_canonical(),
+                u16::from_le_bytes(header[24..26].try_into().unwrap()),
+            ),
+            peer_height: i32::from_le_bytes(header[26..30].try_into().unwrap()),
+            bytes,
+        });
+    }
+    Ok(records)
+}
+
+/// Fills the buffer, false if the file ends before.
+fn read_or_end(reader: &mut impl Read, buf: &mut [u8]) -> Result<bool, ProtocolError> {
+    match reader.read_exact(buf) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Reader that keeps a copy of the bytes read, to record a message as it came from the peer.
+pub struct Capture<R> {
+    inner: R,
+    bytes: Vec<u8>,
+}
+
+impl<R: Read> Capture<R> {
+    pub fn new(inner: R) -> Capture<R> {
+        Capture {
+            inner,
+            bytes: vec![],
+        }
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+impl<R: Read> Read for Capture<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.bytes.extend_from_slice(&buf[..read]);
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::{ping::PingMessage, Message};
+
+    #[test]
+    fn test_recorded_messages_are_read_back() {
+        let path = std::env::temp_dir()
+            .join(format!("session_test_{}", std::process::id()))
+            .to_string_lossy()
+            .into_owned();
+        let mut ping = vec![];
+        PingMessage::new(7).write_to(&mut ping).unwrap();
+
+        let mut capture = Capture::new(&ping[..]);
+        assert!(matches!(
+            Message::read_from(&mut capture),
+            Ok(Message::Ping(_))
+        ));
+        let captured = capture.into_bytes();
+        assert_eq!(captured, ping);
+
+        let recorder = SessionRecorder::create(&path).unwrap();
+        let v4: SocketAddr = "10.0.0.1:18333".parse().unwrap();
+        let v6: SocketAddr = "[2001:db8::1]:18444".parse().unwrap();
+        recorder.record(v4, 100, &captured);
+        recorder.record(v6, 200, &captured[..5]);
+        drop(recorder);
+
+        let records = read_session(&path).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!((records[0].peer, records[0].peer_height), (v4, 100));
+        assert_eq!(records[0].bytes, ping);
+        assert_eq!((records[1].peer, records[1].peer_height), (v6, 200));
+
+        // A record cut while being written is left out
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes.truncate(bytes.len() - 1);
+        std::fs::write(&path, bytes).unwrap();
+        assert_eq!(read_session(&path).unwrap().len(), 1);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
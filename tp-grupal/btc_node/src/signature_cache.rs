@@ -0,0 +1,127 @@
+use bitcoin_hashes::{sha256, Hash};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Mutex, OnceLock},
+};
+
+use crate::constants::SIGNATURE_CACHE_SIZE;
+
+/// Least recently used cache of (signature, public key, sighash) triples that were already verified.
+/// Only valid signatures are stored, so a hit means the verification can be skipped.
+#[derive(Debug)]
+pub struct SignatureCache {
+    capacity: usize,
+    entries: HashMap<[u8; 32], u64>,
+    recency: BTreeMap<u64, [u8; 32]>,
+    counter: u64,
+}
+
+impl SignatureCache {
+    pub fn new(capacity: usize) -> SignatureCache {
+        SignatureCache {
+            capacity,
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            counter: 0,
+        }
+    }
+
+    /// Checks if the triple was already verified, marking it as the most recently used.
+    pub fn contains(&mut self, signature: &[u8], pubkey: &[u8], sighash: &[u8; 32]) -> bool {
+        let key = Self::key(signature, pubkey, sighash);
+        match self.entries.get(&key).copied() {
+            Some(last_used) => {
+                self.recency.remove(&last_used);
+                self.touch(key);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Saves a triple that was verified, evicting the least recently used one if the cache is full.
+    pub fn insert(&mut self, signature: &[u8], pubkey: &[u8], sighash: &[u8; 32]) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let key = Self::key(signature, pubkey, sighash);
+        if let Some(last_used) = self.entries.get(&key).copied() {
+            self.recency.remove(&last_used);
+        } else if self.entries.len() >= self.capacity {
+            if let Some((_, oldest)) = self.recency.pop_first() {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.touch(key);
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn touch(&mut self, key: [u8; 32]) {
+        self.counter += 1;
+        self.entries.insert(key, self.counter);
+        self.recency.insert(self.counter, key);
+    }
+
+    fn key(signature: &[u8], pubkey: &[u8], sighash: &[u8; 32]) -> [u8; 32] {
+        let mut preimage = Vec::with_capacity(signature.len() + pubkey.len() + 34);
+        preimage.push(signature.len() as u8);
+        preimage.extend_from_slice(signature);
+        preimage.push(pubkey.len() as u8);
+        preimage.extend_from_slice(pubkey);
+        preimage.extend_from_slice(sighash);
+        sha256::Hash::hash(&preimage).to_byte_array()
+    }
+}
+
+/// Returns the cache shared by every script evaluation of the node.
+pub fn shared_signature_cache() -> &'static Mutex<SignatureCache> {
+    static CACHE: OnceLock<Mutex<SignatureCache>> = OnceLock::new();
+    CACHE.get_or_init(|| Mutex::new(SignatureCache::new(SIGNATURE_CACHE_SIZE)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_hit_and_miss() {
+        let mut cache = SignatureCache::new(10);
+        assert!(!cache.contains(&[1, 2], &[3], &[0; 32]));
+
+        cache.insert(&[1, 2], &[3], &[0; 32]);
+        assert!(cache.contains(&[1, 2], &[3], &[0; 32]));
+        assert!(!cache.contains(&[1, 2], &[3], &[1; 32]));
+        assert!(!cache.contains(&[1], &[2, 3], &[0; 32]));
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn test_least_recently_used_is_evicted() {
+        let mut cache = SignatureCache::new(2);
+        cache.insert(&[1], &[1], &[1; 32]);
+        cache.insert(&[2], &[2], &[2; 32]);
+
+        assert!(cache.contains(&[1], &[1], &[1; 32]));
+
+        cache.insert(&[3], &[3], &[3; 32]);
+        assert_eq!(cache.len(), 2);
+        assert!(cache.contains(&[1], &[1], &[1; 32]));
+        assert!(!cache.contains(&[2], &[2], &[2; 32]));
+        assert!(cache.contains(&[3], &[3], &[3; 32]));
+    }
+
+    #[test]
+    fn test_cache_without_capacity_stores_nothing() {
+        let mut cache = SignatureCache::new(0);
+        cache.insert(&[1], &[1], &[1; 32]);
+        assert!(cache.is_empty());
+    }
+}
@@ -0,0 +1,249 @@
+use rand::{rngs::StdRng, Rng, RngCore, SeedableRng};
+
+use crate::{
+    address::Network,
+    block_header::BlockHeader,
+    block_template::{block_subsidy, coinbase},
+    blockchain::{utxo_set::Output, Blockchain},
+    constants::{DUST_LIMIT, TARGET_SPACING, TX_VERSION},
+    keys::{key_from_secret, NewKey},
+    merkle_tree::merkle_tree_root,
+    message::{block::BlockMessage, compact_size::CompactSize},
+    protocol_error::ProtocolError,
+    raw_transaction::{RawTransaction, TxOptions, TxOut},
+    script::PubKeyScript,
+    utils::wif_to_pkhash,
+};
+
+// Keys that pay and get paid by the wallet keys in the synthetic chain
+const OTHER_KEYS: usize = 8;
+// Fee of every synthetic payment, in satoshis
+const SIM_FEE: i64 = 1000;
+// Easiest target, the one of regtest, so half of the nonces give a valid proof of work
+const SIM_BITS: u32 = 0x207fffff;
+
+/// Shape of a synthetic chain, the same configuration always gives the same chain.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimConfig {
+    pub seed: u64,
+    /// Blocks after the genesis one
+    pub height: usize,
+    /// Mean payments per block besides the coinbase
+    pub txs_per_block: usize,
+    /// Fraction of the payments and coinbases that pay a wallet key
+    pub wallet_ratio: f64,
+    /// Keys of the wallet, their transactions are the ones the wallet shows
+    pub wallet_keys: usize,
+    /// Time of the first block, the next ones follow every TARGET_SPACING seconds
+    pub start_timestamp: u32,
+}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        SimConfig {
+            seed: 0,
+            height: 200,
+            txs_per_block: 5,
+            wallet_ratio: 0.2,
+            wallet_keys: 2,
+            start_timestamp: 1_700_000_000,
+        }
+    }
+}
+
+/// Synthetic chain and the keys to import in the wallet to see its transactions.
+#[derive(Debug)]
+pub struct SimChain {
+    pub blockchain: Blockchain,
+    pub wallet_keys: Vec<NewKey>,
+}
+
+/// Spendable output of the synthetic chain and the key that can spend it.
+struct SimCoin {
+    txid: [u8; 32],
+    output: Output,
+    key: usize,
+}
+
+/// Generates the chain into a Blockchain. The blocks have a valid proof of work for their bits,
+/// which are too easy for the difficulty checks of the headers.
+pub fn generate(config: &SimConfig) -> Result<SimChain, ProtocolError> {
+    let mut blockchain = Blockchain::new();
+    let wallet_keys = build_blocks(config, blockchain.get_last_header_hash(), |block| {
+        blockchain.push_full_block(block).map(|_| ())
+    })?;
+    Ok(SimChain {
+        blockchain,
+        wallet_keys,
+    })
+}
+
+/// Blocks of the synthetic chain on top of the genesis one, to feed them to the code under test.
+pub fn generate_blocks(config: &SimConfig) -> Result<Vec<BlockMessage>, ProtocolError> {
+    let mut blocks = Vec::with_capacity(config.height);
+    build_blocks(config, Blockchain::new().get_last_header_hash(), |block| {
+        blocks.push(block);
+        Ok(())
+    })?;
+    Ok(blocks)
+}
+
+/// Builds the blocks after `prev_block_hash`, handing them to `push` in order. Every payment
+/// spends one coin of a previous block, pays part of it to a random key and sends the change back,
+/// signed like a real one. Returns the wallet keys.
+fn build_blocks(
+    config: &SimConfig,
+    mut prev_block_hash: [u8; 32],
+    mut push: impl FnMut(BlockMessage) -> Result<(), ProtocolError>,
+) -> Result<Vec<NewKey>, ProtocolError> {
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let keys: Vec<NewKey> = (0..config.wallet_keys + OTHER_KEYS)
+        .map(|_| random_key(&mut rng))
+        .collect();
+    let scripts = keys
+        .iter()
+        .map(|key| Ok(PubKeyScript::P2PKH(wif_to_pkhash(&key.wif)?.to_vec()).to_vec()))
+        .collect::<Result<Vec<Vec<u8>>, ProtocolError>>()?;
+
+    let mut coins: Vec<SimCoin> = vec![];
+    for height in 1..=config.height as i32 {
+        let mut txns = vec![];
+        let miner = pick_key(&mut rng, config);
+        let reward = coinbase(height, scripts[miner].clone(), block_subsidy(height));
+        let mut created = new_coins(&reward, miner, &scripts);
+        txns.push(reward);
+
+        for _ in 0..rng.gen_range(0..=2 * config.txs_per_block) {
+            if coins.is_empty() {
+                break;
+            }
+            let coin = coins.swap_remove(rng.gen_range(0..coins.len()));
+            let value = coin.output.value - SIM_FEE;
+            if value < DUST_LIMIT {
+                continue;
+            }
+
+            let payee = pick_key(&mut rng, config);
+            let amount = (value * rng.gen_range(10..=90i64) / 100).max(DUST_LIMIT);
+            let mut outputs = vec![TxOut::new(amount, scripts[payee].clone())];
+            if value - amount >= DUST_LIMIT {
+                outputs.push(TxOut::new(value - amount, scripts[coin.key].clone()));
+            }
+
+            let tx = RawTransaction::create_transaction(
+                vec![(coin.txid, coin.output)],
+                outputs,
+                &keys[coin.key].wif,
+                &TxOptions::default(),
+            )?;
+            created.extend(new_coins(&tx, coin.key, &scripts));
+            txns.push(tx);
+        }
+
+        let mut header = BlockHeader {
+            version: TX_VERSION,
+            prev_block_hash,
+            merkle_root_hash: merkle_tree_root(txns.iter().map(|tx| tx.get_tx_id()).collect()),
+            timestamp: config.start_timestamp + (height as u32 - 1) * TARGET_SPACING,
+            bits: SIM_BITS,
+            nonce: 0,
+        };
+        while !header.validate_proof_of_work() {
+            header.nonce += 1;
+        }
+        prev_block_hash = header.hash();
+        push(BlockMessage {
+            block_header: header,
+            txn_count: CompactSize::new_from_usize(txns.len()),
+            txns,
+        })?;
+        // The coins are spent from the next block on
+        coins.extend(created);
+    }
+
+    Ok(keys.into_iter().take(config.wallet_keys).collect())
+}
+
+fn random_key(rng: &mut StdRng) -> NewKey {
+    let mut secret = [0u8; 32];
+    loop {
+        rng.fill_bytes(&mut secret);
+        if let Ok(key) = key_from_secret(&secret, Network::Testnet) {
+            return key;
+        }
+    }
+}
+
+/// A wallet key with probability `wallet_ratio`, one of the others otherwise.
+fn pick_key(rng: &mut StdRng, config: &SimConfig) -> usize {
+    if config.wallet_keys > 0 && rng.gen_bool(config.wallet_ratio.clamp(0.0, 1.0)) {
+        rng.gen_range(0..config.wallet_keys)
+    } else {
+        config.wallet_keys + rng.gen_range(0..OTHER_KEYS)
+    }
+}
+
+/// Outputs of the transaction with the key of the script they pay to.
+fn new_coins(tx: &RawTransaction, payer: usize, scripts: &[Vec<u8>]) -> Vec<SimCoin> {
+    tx.get_utxos()
+        .into_iter()
+        .map(|((txid, index), out)| SimCoin {
+            txid,
+            key: scripts
+                .iter()
+                .position(|script| *script == out.pk_script)
+                .unwrap_or(payer),
+            output: Output::new(index, out.value, out.pk_script),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> SimConfig {
+        SimConfig {
+            height: 20,
+            wallet_ratio: 0.5,
+            ..SimConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_the_same_seed_gives_the_same_chain() {
+        let chain = generate(&config()).unwrap();
+        let again = generate(&config()).unwrap();
+        assert_eq!(chain.blockchain.get_height(), 20);
+        assert_eq!(
+            chain.blockchain.get_last_header_hash(),
+            again.blockchain.get_last_header_hash()
+        );
+        assert_eq!(chain.wallet_keys, again.wallet_keys);
+
+        let other = generate(&SimConfig {
+            seed: 1,
+            ..config()
+        })
+        .unwrap();
+        assert_ne!(
+            chain.blockchain.get_last_header_hash(),
+            other.blockchain.get_last_header_hash()
+        );
+    }
+
+    #[test]
+    fn test_wallet_keys_have_a_history_of_signed_payments() {
+        let chain = generate(&config()).unwrap();
+        let blockchain = &chain.blockchain;
+        let history: usize = chain
+            .wallet_keys
+            .iter()
+            .map(|key| {
+                let pkhash = wif_to_pkhash(&key.wif).unwrap().to_vec();
+                blockchain.get_tx_history(pkhash).len()
+            })
+            .sum();
+        assert!(history > 0);
+    }
+}
@@ -0,0 +1,63 @@
+use std::sync::{Condvar, Mutex};
+
+use crate::protocol_error::ProtocolError;
+
+/// Lets the wallet pause the block download.
+/// The download threads call `wait_while_paused` before requesting each batch of blocks.
+#[derive(Debug, Default)]
+pub struct SyncControl {
+    paused: Mutex<bool>,
+    resumed: Condvar,
+}
+
+impl SyncControl {
+    pub fn new() -> SyncControl {
+        SyncControl::default()
+    }
+
+    pub fn pause(&self) -> Result<(), ProtocolError> {
+        *self.paused.lock()? = true;
+        Ok(())
+    }
+
+    pub fn resume(&self) -> Result<(), ProtocolError> {
+        *self.paused.lock()? = false;
+        self.resumed.notify_all();
+        Ok(())
+    }
+
+    pub fn is_paused(&self) -> Result<bool, ProtocolError> {
+        Ok(*self.paused.lock()?)
+    }
+
+    /// Blocks the calling thread until the download is resumed.
+    pub fn wait_while_paused(&self) -> Result<(), ProtocolError> {
+        let mut paused = self.paused.lock()?;
+        while *paused {
+            paused = self.resumed.wait(paused)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{sync::Arc, thread, time::Duration};
+
+    #[test]
+    fn test_waiting_thread_continues_after_resume() {
+        let control = Arc::new(SyncControl::new());
+        control.pause().unwrap();
+
+        let c = Arc::clone(&control);
+        let waiter = thread::spawn(move || c.wait_while_paused().unwrap());
+
+        thread::sleep(Duration::from_millis(50));
+        assert!(!waiter.is_finished());
+
+        control.resume().unwrap();
+        waiter.join().unwrap();
+        assert!(!control.is_paused().unwrap());
+    }
+}
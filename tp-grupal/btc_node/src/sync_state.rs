@@ -0,0 +1,112 @@
+use std::{fmt, sync::Mutex};
+
+use crate::protocol_error::ProtocolError;
+
+/// Phase of the sync of the node with its peers, they only go forward.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SyncState {
+    /// Dialing the peers and doing the handshakes
+    Connecting,
+    /// Downloading the headers from the peers that completed the handshake
+    HeaderSync,
+    /// Downloading the blocks, until the chain gets close to the best peer
+    BlockSync,
+    /// Following the chain block by block, the transactions are relayed
+    Synced,
+}
+
+impl SyncState {
+    /// Whether a message with the command is handled in this phase, the rest are ignored.
+    /// Transactions aren't expected until the chain is synced, as their inputs can't be checked.
+    pub fn expects(&self, command: &str) -> bool {
+        match self {
+            SyncState::Connecting => matches!(command, "ping" | "pong"),
+            SyncState::HeaderSync => {
+                matches!(command, "ping" | "pong" | "headers" | "getheaders")
+            }
+            SyncState::BlockSync => matches!(
+                command,
+                "ping" | "pong" | "headers" | "getheaders" | "block" | "getdata" | "inv"
+            ),
+            SyncState::Synced => true,
+        }
+    }
+
+    /// Whether the node asks its peers for their transactions in this phase.
+    pub fn relays_txs(&self) -> bool {
+        *self == SyncState::Synced
+    }
+}
+
+impl fmt::Display for SyncState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            SyncState::Connecting => "Connecting",
+            SyncState::HeaderSync => "HeaderSync",
+            SyncState::BlockSync => "BlockSync",
+            SyncState::Synced => "Synced",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Current phase of the sync, shared by the threads of the node.
+#[derive(Debug)]
+pub struct SyncMachine {
+    state: Mutex<SyncState>,
+}
+
+impl Default for SyncMachine {
+    fn default() -> Self {
+        SyncMachine::new()
+    }
+}
+
+impl SyncMachine {
+    pub fn new() -> SyncMachine {
+        SyncMachine {
+            state: Mutex::new(SyncState::Connecting),
+        }
+    }
+
+    pub fn state(&self) -> Result<SyncState, ProtocolError> {
+        Ok(*self.state.lock()?)
+    }
+
+    /// Moves to a later phase, phases can be skipped like the block download of a simulated chain.
+    /// False if the machine was already in it, going back to an earlier phase is an error.
+    pub fn advance(&self, to: SyncState) -> Result<bool, ProtocolError> {
+        let mut state = self.state.lock()?;
+        if *state > to {
+            return Err(ProtocolError::Error(format!(
+                "Invalid sync transition from {} to {}",
+                *state, to
+            )));
+        }
+        let changed = *state != to;
+        *state = to;
+        Ok(changed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_the_sync_only_goes_forward() {
+        let sync = SyncMachine::new();
+        assert!(!sync.state().unwrap().expects("block"));
+
+        assert!(sync.advance(SyncState::HeaderSync).unwrap());
+        assert!(!sync.advance(SyncState::HeaderSync).unwrap());
+        assert!(sync.state().unwrap().expects("headers"));
+        assert!(!sync.state().unwrap().expects("tx"));
+
+        assert!(sync.advance(SyncState::Synced).unwrap());
+        assert!(sync.advance(SyncState::BlockSync).is_err());
+        assert_eq!(sync.state().unwrap(), SyncState::Synced);
+        assert!(sync.state().unwrap().expects("tx"));
+        assert!(sync.state().unwrap().relays_txs());
+    }
+}
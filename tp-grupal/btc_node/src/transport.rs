@@ -0,0 +1,291 @@
+//! Connection with a peer, in plaintext or encrypted with the transport of BIP 324.
+//! The rest of the node reads and writes the plaintext serialization of the messages either way,
+//! the encrypted connections translate it to and from their packets.
+
+pub mod cipher;
+pub mod v2;
+
+use std::{
+    io::{self, Read, Write},
+    net::{Shutdown, SocketAddr, TcpStream},
+    sync::{Arc, Mutex, MutexGuard},
+    time::Duration,
+};
+
+use crate::{
+    constants::MESSAGE_HEADER_SIZE, protocol_error::ProtocolError, utils::bytes_to_hex_string,
+};
+use v2::{Role, Session};
+
+#[derive(Debug)]
+struct SendHalf {
+    cipher: v2::SendCipher,
+    /// Bytes of a message that isn't completely written yet
+    pending: Vec<u8>,
+}
+
+#[derive(Debug)]
+struct RecvHalf {
+    /// None for the plaintext connections
+    cipher: Option<v2::RecvCipher>,
+    /// Decrypted bytes not read yet, or the ones read to tell the transport of the peer
+    buffer: Vec<u8>,
+}
+
+/// State shared by the clones of a connection.
+#[derive(Debug)]
+struct Shared {
+    send: Mutex<Option<SendHalf>>,
+    recv: Mutex<RecvHalf>,
+    session_id: Option<[u8; 32]>,
+}
+
+/// Stream of a peer connection. Its clones share the ciphers, like the ones of a TcpStream
+/// share the socket.
+#[derive(Debug)]
+pub struct PeerStream {
+    tcp: TcpStream,
+    shared: Arc<Shared>,
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> io::Result<MutexGuard<'_, T>> {
+    mutex
+        .lock()
+        .map_err(|_| io::Error::other("The connection state was poisoned"))
+}
+
+fn to_io_error(error: ProtocolError) -> io::Error {
+    match error {
+        ProtocolError::IOError(e) => e,
+        other => io::Error::new(io::ErrorKind::InvalidData, other.to_string()),
+    }
+}
+
+impl PeerStream {
+    fn new(tcp: TcpStream, session: Option<Session>, unread: Vec<u8>) -> PeerStream {
+        let (send, recv, session_id) = match session {
+            Some(session) => (
+                Some(SendHalf {
+                    cipher: session.send,
+                    pending: vec![],
+                }),
+                Some(session.recv),
+                Some(session.id),
+            ),
+            None => (None, None, None),
+        };
+        PeerStream {
+            tcp,
+            shared: Arc::new(Shared {
+                send: Mutex::new(send),
+                recv: Mutex::new(RecvHalf {
+                    cipher: recv,
+                    buffer: unread,
+                }),
+                session_id,
+            }),
+        }
+    }
+
+    /// Plaintext connection, the transport of the peers that don't support BIP 324.
+    pub fn plain(tcp: TcpStream) -> PeerStream {
+        PeerStream::new(tcp, None, vec![])
+    }
+
+    /// Dials the encrypted handshake, the peers that don't support it usually close the connection.
+    pub fn initiate_v2(mut tcp: TcpStream) -> Result<PeerStream, ProtocolError> {
+        let session = v2::handshake(&mut tcp, Role::Initiator, &[])?;
+        Ok(PeerStream::new(tcp, Some(session), vec![]))
+    }
+
+    /// Answers an inbound connection in the transport the peer started, plaintext if
+    /// `v2_enabled` is false.
+    pub fn accept(mut tcp: TcpStream, v2_enabled: bool) -> Result<PeerStream, ProtocolError> {
+        if !v2_enabled {
+            return Ok(PeerStream::plain(tcp));
+        }
+
+        let mut first = [0u8; 16];
+        tcp.read_exact(&mut first)?;
+        if v2::is_v1_prefix(&first) {
+            return Ok(PeerStream::new(tcp, None, first.to_vec()));
+        }
+        let session = v2::handshake(&mut tcp, Role::Responder, &first)?;
+        Ok(PeerStream::new(tcp, Some(session), vec![]))
+    }
+
+    pub fn try_clone(&self) -> io::Result<PeerStream> {
+        Ok(PeerStream {
+            tcp: self.tcp.try_clone()?,
+            shared: Arc::clone(&self.shared),
+        })
+    }
+
+    pub fn is_v2(&self) -> bool {
+        self.shared.session_id.is_some()
+    }
+
+    /// Id of the encrypted session, the same in both ends unless someone is in the middle.
+    pub fn session_id(&self) -> Option<String> {
+        self.shared.session_id.map(|id| bytes_to_hex_string(&id))
+    }
+
+    /// Name of the transport, for the logs.
+    pub fn transport(&self) -> &'static str {
+        if self.is_v2() {
+            "v2"
+        } else {
+            "v1"
+        }
+    }
+
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.tcp.peer_addr()
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.tcp.local_addr()
+    }
+
+    pub fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        self.tcp.shutdown(how)
+    }
+
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.tcp.set_read_timeout(timeout)
+    }
+
+    pub fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.tcp.set_write_timeout(timeout)
+    }
+
+    pub fn read_timeout(&self) -> io::Result<Option<Duration>> {
+        self.tcp.read_timeout()
+    }
+
+    /// Whether there is something to read, 0 once the peer closed the connection.
+    pub fn peek(&self, buf: &mut [u8]) -> io::Result<usize> {
+        let recv = lock(&self.shared.recv)?;
+        if !recv.buffer.is_empty() {
+            let n = buf.len().min(recv.buffer.len());
+            buf[..n].copy_from_slice(&recv.buffer[..n]);
+            return Ok(n);
+        }
+        // The encrypted bytes can't be shown before they are read
+        self.tcp.peek(buf)
+    }
+}
+
+impl Read for PeerStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut recv = lock(&self.shared.recv)?;
+        if recv.cipher.is_none() && recv.buffer.is_empty() {
+            drop(recv);
+            return self.tcp.read(buf);
+        }
+
+        while recv.buffer.is_empty() {
+            let RecvHalf { cipher, buffer } = &mut *recv;
+            if let Some(cipher) = cipher {
+                if let Some(contents) = cipher.read_packet(&mut self.tcp).map_err(to_io_error)? {
+                    *buffer = v2::decode_message(&contents).map_err(to_io_error)?;
+                }
+            }
+        }
+
+        let n = buf.len().min(recv.buffer.len());
+        buf[..n].copy_from_slice(&recv.buffer[..n]);
+        recv.buffer.drain(..n);
+        Ok(n)
+    }
+}
+
+impl Write for PeerStream {
+    /// The encrypted connections send a packet once a whole message was written.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut send = lock(&self.shared.send)?;
+        if let Some(half) = send.as_mut() {
+            half.pending.extend_from_slice(buf);
+            while half.pending.len() >= MESSAGE_HEADER_SIZE {
+                let size = u32::from_le_bytes([
+                    half.pending[16],
+                    half.pending[17],
+                    half.pending[18],
+                    half.pending[19],
+                ]) as usize;
+                if half.pending.len() < MESSAGE_HEADER_SIZE + size {
+                    break;
+                }
+
+                let message: Vec<u8> = half.pending.drain(..MESSAGE_HEADER_SIZE + size).collect();
+                let packet = half.cipher.encrypt(&v2::encode_message(&message), false);
+                self.tcp.write_all(&packet)?;
+            }
+            return Ok(buf.len());
+        }
+
+        drop(send);
+        self.tcp.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.tcp.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        message::{ping::PingMessage, Message},
+        message_header::network,
+    };
+    use bitcoin_hashes::{sha256d, Hash};
+    use std::{net::TcpListener, thread};
+
+    #[test]
+    fn test_messages_cross_both_transports() {
+        for v2_enabled in [true, false] {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let tcp = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+            let accepting = thread::spawn(move || {
+                let (remote, _) = listener.accept().unwrap();
+                PeerStream::accept(remote, true).unwrap()
+            });
+            let mut stream = match v2_enabled {
+                true => PeerStream::initiate_v2(tcp).unwrap(),
+                false => PeerStream::plain(tcp),
+            };
+
+            // The plaintext peers are told apart by their version message
+            let mut version = network().start_string().to_vec();
+            version.extend_from_slice(b"version\0\0\0\0\0");
+            version.extend_from_slice(&0u32.to_le_bytes());
+            version.extend_from_slice(&sha256d::Hash::hash(&[])[..4]);
+            stream.write_all(&version).unwrap();
+
+            // Written in pieces by a clone, like the messages of the peer writers
+            let mut ping = vec![];
+            PingMessage::new(42).write_to(&mut ping).unwrap();
+            let mut clone = stream.try_clone().unwrap();
+            clone.write_all(&ping[..10]).unwrap();
+            clone.write_all(&ping[10..]).unwrap();
+            PingMessage::new(43).write_to(&mut stream).unwrap();
+
+            let mut remote = accepting.join().unwrap();
+            assert_eq!(stream.is_v2(), v2_enabled);
+            assert_eq!(remote.is_v2(), v2_enabled);
+            assert_eq!(stream.session_id(), remote.session_id());
+
+            let mut received = [0u8; MESSAGE_HEADER_SIZE];
+            remote.read_exact(&mut received).unwrap();
+            assert_eq!(received.to_vec(), version);
+            for nonce in [42, 43] {
+                match Message::read_from(&mut remote).unwrap() {
+                    Message::Ping(ping) => assert_eq!(ping.get_nonce(), nonce),
+                    _ => panic!("Expected a ping"),
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,390 @@
+//! ChaCha20, Poly1305 and their AEAD (RFC 8439), with the forward secure wrappers of BIP 324
+//! that rekey every REKEY_INTERVAL messages.
+
+// Messages encrypted with a key before it is replaced by one taken from its keystream
+pub const REKEY_INTERVAL: u64 = 224;
+pub const TAG_LEN: usize = 16;
+
+const BLOCK_LEN: usize = 64;
+const CONSTANTS: [u32; 4] = [0x61707865, 0x3320646e, 0x79622d32, 0x6b206574];
+const LIMB_MASK: u32 = 0x3ffffff;
+
+fn le_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+fn quarter_round(state: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(16);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(12);
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(8);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(7);
+}
+
+/// Keystream block number `counter` of the key and nonce.
+pub fn chacha20_block(key: &[u8; 32], nonce: &[u8; 12], counter: u32) -> [u8; BLOCK_LEN] {
+    let mut state = [0u32; 16];
+    state[..4].copy_from_slice(&CONSTANTS);
+    for i in 0..8 {
+        state[4 + i] = le_u32(&key[4 * i..]);
+    }
+    state[12] = counter;
+    for i in 0..3 {
+        state[13 + i] = le_u32(&nonce[4 * i..]);
+    }
+
+    let mut working = state;
+    for _ in 0..10 {
+        quarter_round(&mut working, 0, 4, 8, 12);
+        quarter_round(&mut working, 1, 5, 9, 13);
+        quarter_round(&mut working, 2, 6, 10, 14);
+        quarter_round(&mut working, 3, 7, 11, 15);
+        quarter_round(&mut working, 0, 5, 10, 15);
+        quarter_round(&mut working, 1, 6, 11, 12);
+        quarter_round(&mut working, 2, 7, 8, 13);
+        quarter_round(&mut working, 3, 4, 9, 14);
+    }
+
+    let mut block = [0u8; BLOCK_LEN];
+    for i in 0..16 {
+        block[4 * i..4 * i + 4].copy_from_slice(&working[i].wrapping_add(state[i]).to_le_bytes());
+    }
+    block
+}
+
+/// Encrypts or decrypts the data with the keystream that starts at the block `counter`.
+pub fn chacha20_xor(key: &[u8; 32], nonce: &[u8; 12], counter: u32, data: &mut [u8]) {
+    for (i, chunk) in data.chunks_mut(BLOCK_LEN).enumerate() {
+        let block = chacha20_block(key, nonce, counter.wrapping_add(i as u32));
+        for (byte, k) in chunk.iter_mut().zip(block.iter()) {
+            *byte ^= k;
+        }
+    }
+}
+
+/// One-time authenticator of the message, with 26 bit limbs for the arithmetic modulo 2^130 - 5.
+pub fn poly1305(key: &[u8; 32], message: &[u8]) -> [u8; TAG_LEN] {
+    let r0 = le_u32(&key[0..]) & 0x3ffffff;
+    let r1 = (le_u32(&key[3..]) >> 2) & 0x3ffff03;
+    let r2 = (le_u32(&key[6..]) >> 4) & 0x3ffc0ff;
+    let r3 = (le_u32(&key[9..]) >> 6) & 0x3f03fff;
+    let r4 = (le_u32(&key[12..]) >> 8) & 0x00fffff;
+    let (s1, s2, s3, s4) = (r1 * 5, r2 * 5, r3 * 5, r4 * 5);
+    let mut h = [0u32; 5];
+
+    for chunk in message.chunks(16) {
+        // The one after the last byte is the 2^128 bit of the full blocks
+        let mut block = [0u8; 17];
+        block[..chunk.len()].copy_from_slice(chunk);
+        block[chunk.len()] = 1;
+
+        h[0] += le_u32(&block[0..]) & LIMB_MASK;
+        h[1] += (le_u32(&block[3..]) >> 2) & LIMB_MASK;
+        h[2] += (le_u32(&block[6..]) >> 4) & LIMB_MASK;
+        h[3] += (le_u32(&block[9..]) >> 6) & LIMB_MASK;
+        h[4] += (le_u32(&block[12..]) >> 8) | ((block[16] as u32) << 24);
+
+        let m = |a: u32, b: u32| a as u64 * b as u64;
+        let d = [
+            m(h[0], r0) + m(h[1], s4) + m(h[2], s3) + m(h[3], s2) + m(h[4], s1),
+            m(h[0], r1) + m(h[1], r0) + m(h[2], s4) + m(h[3], s3) + m(h[4], s2),
+            m(h[0], r2) + m(h[1], r1) + m(h[2], r0) + m(h[3], s4) + m(h[4], s3),
+            m(h[0], r3) + m(h[1], r2) + m(h[2], r1) + m(h[3], r0) + m(h[4], s4),
+            m(h[0], r4) + m(h[1], r3) + m(h[2], r2) + m(h[3], r1) + m(h[4], r0),
+        ];
+
+        let mut carry = 0u64;
+        for i in 0..5 {
+            let limb = d[i] + carry;
+            h[i] = limb as u32 & LIMB_MASK;
+            carry = limb >> 26;
+        }
+        h[0] += carry as u32 * 5;
+        h[1] += h[0] >> 26;
+        h[0] &= LIMB_MASK;
+    }
+
+    let mut carry = 0;
+    for limb in h.iter_mut().skip(1) {
+        *limb += carry;
+        carry = *limb >> 26;
+        *limb &= LIMB_MASK;
+    }
+    h[0] += carry * 5;
+    h[1] += h[0] >> 26;
+    h[0] &= LIMB_MASK;
+
+    // h - p, kept when h isn't below p
+    let mut g = [0u32; 5];
+    let mut carry = 5;
+    for i in 0..4 {
+        g[i] = h[i] + carry;
+        carry = g[i] >> 26;
+        g[i] &= LIMB_MASK;
+    }
+    g[4] = (h[4] + carry).wrapping_sub(1 << 26);
+    let keep_g = (g[4] >> 31).wrapping_sub(1);
+    for i in 0..5 {
+        h[i] = (h[i] & !keep_g) | (g[i] & keep_g);
+    }
+
+    let words = [
+        h[0] | (h[1] << 26),
+        (h[1] >> 6) | (h[2] << 20),
+        (h[2] >> 12) | (h[3] << 14),
+        (h[3] >> 18) | (h[4] << 8),
+    ];
+    let mut tag = [0u8; TAG_LEN];
+    let mut carry = 0u64;
+    for i in 0..4 {
+        let sum = words[i] as u64 + le_u32(&key[16 + 4 * i..]) as u64 + carry;
+        tag[4 * i..4 * i + 4].copy_from_slice(&(sum as u32).to_le_bytes());
+        carry = sum >> 32;
+    }
+    tag
+}
+
+fn pad16(data: &mut Vec<u8>) {
+    let padding = (16 - data.len() % 16) % 16;
+    data.resize(data.len() + padding, 0);
+}
+
+/// Tag of the AEAD over the associated data and the ciphertext.
+fn aead_tag(key: &[u8; 32], nonce: &[u8; 12], aad: &[u8], ciphertext: &[u8]) -> [u8; TAG_LEN] {
+    let mut poly_key = [0u8; 32];
+    poly_key.copy_from_slice(&chacha20_block(key, nonce, 0)[..32]);
+
+    let mut mac_data = aad.to_vec();
+    pad16(&mut mac_data);
+    mac_data.extend_from_slice(ciphertext);
+    pad16(&mut mac_data);
+    mac_data.extend_from_slice(&(aad.len() as u64).to_le_bytes());
+    mac_data.extend_from_slice(&(ciphertext.len() as u64).to_le_bytes());
+    poly1305(&poly_key, &mac_data)
+}
+
+/// ChaCha20-Poly1305 encryption, the ciphertext followed by its tag.
+pub fn aead_encrypt(key: &[u8; 32], nonce: &[u8; 12], aad: &[u8], plaintext: &[u8]) -> Vec<u8> {
+    let mut ciphertext = plaintext.to_vec();
+    chacha20_xor(key, nonce, 1, &mut ciphertext);
+    let tag = aead_tag(key, nonce, aad, &ciphertext);
+    ciphertext.extend_from_slice(&tag);
+    ciphertext
+}
+
+/// ChaCha20-Poly1305 decryption, None if the tag doesn't authenticate the ciphertext.
+pub fn aead_decrypt(
+    key: &[u8; 32],
+    nonce: &[u8; 12],
+    aad: &[u8],
+    sealed: &[u8],
+) -> Option<Vec<u8>> {
+    if sealed.len() < TAG_LEN {
+        return None;
+    }
+    let (ciphertext, tag) = sealed.split_at(sealed.len() - TAG_LEN);
+    let expected = aead_tag(key, nonce, aad, ciphertext);
+    // Compared in constant time, the mismatch isn't found sooner for closer tags
+    if expected
+        .iter()
+        .zip(tag)
+        .fold(0, |diff, (a, b)| diff | (a ^ b))
+        != 0
+    {
+        return None;
+    }
+
+    let mut plaintext = ciphertext.to_vec();
+    chacha20_xor(key, nonce, 1, &mut plaintext);
+    Some(plaintext)
+}
+
+fn nonce(first: u32, second: u64) -> [u8; 12] {
+    let mut nonce = [0u8; 12];
+    nonce[..4].copy_from_slice(&first.to_le_bytes());
+    nonce[4..].copy_from_slice(&second.to_le_bytes());
+    nonce
+}
+
+/// Stream cipher of the packet lengths: a single keystream crypts the consecutive lengths
+/// and every REKEY_INTERVAL of them the key is replaced by the next 32 bytes of it.
+#[derive(Debug, Clone)]
+pub struct FsChaCha20 {
+    key: [u8; 32],
+    chunk_counter: u64,
+    rekey_counter: u64,
+    block_counter: u32,
+    keystream: Vec<u8>,
+}
+
+impl FsChaCha20 {
+    pub fn new(key: [u8; 32]) -> FsChaCha20 {
+        FsChaCha20 {
+            key,
+            chunk_counter: 0,
+            rekey_counter: 0,
+            block_counter: 0,
+            keystream: vec![],
+        }
+    }
+
+    fn keystream_bytes(&mut self, n: usize) -> Vec<u8> {
+        while self.keystream.len() < n {
+            let block =
+                chacha20_block(&self.key, &nonce(0, self.rekey_counter), self.block_counter);
+            self.keystream.extend_from_slice(&block);
+            self.block_counter += 1;
+        }
+        self.keystream.drain(..n).collect()
+    }
+
+    pub fn crypt(&mut self, chunk: &mut [u8]) {
+        let keystream = self.keystream_bytes(chunk.len());
+        for (byte, k) in chunk.iter_mut().zip(keystream) {
+            *byte ^= k;
+        }
+
+        self.chunk_counter += 1;
+        if self.chunk_counter == REKEY_INTERVAL {
+            let key = self.keystream_bytes(32);
+            self.key.copy_from_slice(&key);
+            self.keystream.clear();
+            self.block_counter = 0;
+            self.chunk_counter = 0;
+            self.rekey_counter += 1;
+        }
+    }
+}
+
+/// AEAD of the packet contents, the nonce counts the packets and every REKEY_INTERVAL of them
+/// the key is replaced by the encryption of 32 zeros with a nonce no packet uses.
+#[derive(Debug, Clone)]
+pub struct FsChaCha20Poly1305 {
+    key: [u8; 32],
+    packet_counter: u32,
+    rekey_counter: u64,
+}
+
+impl FsChaCha20Poly1305 {
+    pub fn new(key: [u8; 32]) -> FsChaCha20Poly1305 {
+        FsChaCha20Poly1305 {
+            key,
+            packet_counter: 0,
+            rekey_counter: 0,
+        }
+    }
+
+    fn next_packet(&mut self) {
+        self.packet_counter += 1;
+        if self.packet_counter as u64 == REKEY_INTERVAL {
+            // The AEAD encrypts from the block 1, the block 0 is the Poly1305 key
+            let block = chacha20_block(&self.key, &nonce(0xffffffff, self.rekey_counter), 1);
+            self.key.copy_from_slice(&block[..32]);
+            self.packet_counter = 0;
+            self.rekey_counter += 1;
+        }
+    }
+
+    pub fn encrypt(&mut self, aad: &[u8], plaintext: &[u8]) -> Vec<u8> {
+        let nonce = nonce(self.packet_counter, self.rekey_counter);
+        let sealed = aead_encrypt(&self.key, &nonce, aad, plaintext);
+        self.next_packet();
+        sealed
+    }
+
+    /// None if the packet wasn't authenticated, the connection can't go on after it.
+    pub fn decrypt(&mut self, aad: &[u8], sealed: &[u8]) -> Option<Vec<u8>> {
+        let nonce = nonce(self.packet_counter, self.rekey_counter);
+        let plaintext = aead_decrypt(&self.key, &nonce, aad, sealed);
+        self.next_packet();
+        plaintext
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[test]
+    fn test_chacha20_block_matches_rfc8439() {
+        let key: Vec<u8> = (0..32).collect();
+        let nonce = [0, 0, 0, 0x09, 0, 0, 0, 0x4a, 0, 0, 0, 0];
+        let block = chacha20_block(&key.try_into().unwrap(), &nonce, 1);
+        assert_eq!(
+            hex(&block),
+            "10f1e7e4d13b5915500fdd1fa32071c4c7d1f4c733c068030422aa9ac3d46c4e\
+             d2826446079faa0914c2d705d98b02a2b5129cd1de164eb9cbd083e8a2503c4e"
+        );
+    }
+
+    #[test]
+    fn test_poly1305_matches_rfc8439() {
+        let key = [
+            0x85, 0xd6, 0xbe, 0x78, 0x57, 0x55, 0x6d, 0x33, 0x7f, 0x44, 0x52, 0xfe, 0x42, 0xd5,
+            0x06, 0xa8, 0x01, 0x03, 0x80, 0x8a, 0xfb, 0x0d, 0xb2, 0xfd, 0x4a, 0xbf, 0xf6, 0xaf,
+            0x41, 0x49, 0xf5, 0x1b,
+        ];
+        let tag = poly1305(&key, b"Cryptographic Forum Research Group");
+        assert_eq!(hex(&tag), "a8061dc1305136c6c22b8baf0c0127a9");
+    }
+
+    #[test]
+    fn test_aead_matches_rfc8439() {
+        let key: Vec<u8> = (0x80..0xa0).collect();
+        let nonce = [7, 0, 0, 0, 0x40, 0x41, 0x42, 0x43, 0x44, 0x45, 0x46, 0x47];
+        let aad = [
+            0x50, 0x51, 0x52, 0x53, 0xc0, 0xc1, 0xc2, 0xc3, 0xc4, 0xc5, 0xc6, 0xc7,
+        ];
+        let plaintext = b"Ladies and Gentlemen of the class of '99: If I could offer you only one \
+                          tip for the future, sunscreen would be it.";
+        let key = key.try_into().unwrap();
+
+        let sealed = aead_encrypt(&key, &nonce, &aad, plaintext);
+        assert_eq!(hex(&sealed[..16]), "d31a8d34648e60db7b86afbc53ef7ec2");
+        assert_eq!(
+            hex(&sealed[plaintext.len()..]),
+            "1ae10b594f09e26a7e902ecbd0600691"
+        );
+        assert_eq!(
+            aead_decrypt(&key, &nonce, &aad, &sealed).unwrap(),
+            plaintext.to_vec()
+        );
+    }
+
+    #[test]
+    fn test_forward_secure_ciphers_rekey_on_both_ends() {
+        let mut sender = FsChaCha20Poly1305::new([7; 32]);
+        let mut receiver = FsChaCha20Poly1305::new([7; 32]);
+        let mut lengths = FsChaCha20::new([9; 32]);
+        let mut lengths_receiver = FsChaCha20::new([9; 32]);
+
+        let mut first = vec![];
+        for i in 0..(REKEY_INTERVAL * 2 + 3) {
+            let message = (i as u32).to_le_bytes();
+            let sealed = sender.encrypt(b"aad", &message);
+            if i == 0 {
+                first = sealed.clone();
+            } else {
+                // Same plaintext, another nonce or key
+                assert_ne!(sealed, first);
+            }
+            assert_eq!(receiver.decrypt(b"aad", &sealed).unwrap(), message);
+
+            let mut length = [i as u8, 0, 1];
+            lengths.crypt(&mut length);
+            lengths_receiver.crypt(&mut length);
+            assert_eq!(length, [i as u8, 0, 1]);
+        }
+
+        let mut tampered = sender.encrypt(b"aad", b"block");
+        tampered[0] ^= 1;
+        assert!(receiver.decrypt(b"aad", &tampered).is_none());
+    }
+}
@@ -0,0 +1,472 @@
+//! Handshake and packets of the encrypted transport of BIP 324.
+
+use std::{io::Read, io::Write, net::TcpStream};
+
+use bitcoin_hashes::{hmac, sha256, sha256d, Hash, HashEngine};
+use secp256k1::{
+    ellswift::{ElligatorSwift, ElligatorSwiftParty},
+    Secp256k1, SecretKey,
+};
+
+use super::cipher::{FsChaCha20, FsChaCha20Poly1305, TAG_LEN};
+use crate::{
+    constants::{MAX_BLOCK_SIZE, MESSAGE_HEADER_SIZE},
+    message_header::network,
+    protocol_error::ProtocolError,
+};
+
+pub const ELLSWIFT_LEN: usize = 64;
+pub const LENGTH_LEN: usize = 3;
+pub const HEADER_LEN: usize = 1;
+const MAX_GARBAGE_LEN: usize = 4095;
+const GARBAGE_TERMINATOR_LEN: usize = 16;
+// Header bit of the packets that have to be ignored, the decoys
+const IGNORE_BIT: u8 = 0x80;
+const V1_COMMAND_LEN: usize = 12;
+// Contents of the largest message, a block with the long encoding of its command
+const MAX_CONTENTS_LEN: usize = 1 + V1_COMMAND_LEN + MAX_BLOCK_SIZE as usize;
+// Commands with a one byte id, the position in the list is the id minus one
+const SHORT_IDS: [&str; 28] = [
+    "addr",
+    "block",
+    "blocktxn",
+    "cmpctblock",
+    "feefilter",
+    "filteradd",
+    "filterclear",
+    "filterload",
+    "getblocks",
+    "getblocktxn",
+    "getdata",
+    "getheaders",
+    "headers",
+    "inv",
+    "mempool",
+    "merkleblock",
+    "notfound",
+    "ping",
+    "pong",
+    "sendcmpct",
+    "tx",
+    "getcfilters",
+    "cfilter",
+    "getcfheaders",
+    "cfheaders",
+    "getcfcheckpt",
+    "cfcheckpt",
+    "addrv2",
+];
+
+/// Side of the connection, the initiator dialed the responder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Initiator,
+    Responder,
+}
+
+/// Encrypts the packets sent to the peer.
+#[derive(Debug)]
+pub struct SendCipher {
+    length: FsChaCha20,
+    packet: FsChaCha20Poly1305,
+    /// Authenticated with the first packet, the garbage sent before it
+    aad: Vec<u8>,
+}
+
+impl SendCipher {
+    pub fn encrypt(&mut self, contents: &[u8], ignore: bool) -> Vec<u8> {
+        let mut length = (contents.len() as u32).to_le_bytes()[..LENGTH_LEN].to_vec();
+        self.length.crypt(&mut length);
+
+        let header = if ignore { IGNORE_BIT } else { 0 };
+        let plaintext = [&[header][..], contents].concat();
+        let aad = std::mem::take(&mut self.aad);
+        [length, self.packet.encrypt(&aad, &plaintext)].concat()
+    }
+}
+
+/// Decrypts the packets received from the peer.
+#[derive(Debug)]
+pub struct RecvCipher {
+    length: FsChaCha20,
+    packet: FsChaCha20Poly1305,
+    /// Authenticated with the first packet, the garbage received before it
+    aad: Vec<u8>,
+}
+
+impl RecvCipher {
+    /// Reads the next packet, None for the decoys.
+    pub fn read_packet(&mut self, stream: &mut dyn Read) -> Result<Option<Vec<u8>>, ProtocolError> {
+        let mut length = [0u8; LENGTH_LEN];
+        stream.read_exact(&mut length)?;
+        self.length.crypt(&mut length);
+        let length = u32::from_le_bytes([length[0], length[1], length[2], 0]) as usize;
+        if length > MAX_CONTENTS_LEN {
+            return Err(ProtocolError::ConnectionError(format!(
+                "The peer sent a packet of {} bytes",
+                length
+            )));
+        }
+
+        let mut sealed = vec![0u8; HEADER_LEN + length + TAG_LEN];
+        stream.read_exact(&mut sealed)?;
+        let aad = std::mem::take(&mut self.aad);
+        let plaintext = self.packet.decrypt(&aad, &sealed).ok_or_else(|| {
+            ProtocolError::ConnectionError(
+                "The peer sent a packet that isn't authentic".to_string(),
+            )
+        })?;
+
+        if plaintext[0] & IGNORE_BIT != 0 {
+            return Ok(None);
+        }
+        Ok(Some(plaintext[HEADER_LEN..].to_vec()))
+    }
+}
+
+/// Ciphers of an encrypted connection, and the id both ends can compare to detect
+/// a man in the middle.
+#[derive(Debug)]
+pub struct Session {
+    pub send: SendCipher,
+    pub recv: RecvCipher,
+    pub id: [u8; 32],
+}
+
+/// Whether the bytes a peer sent first are the start of a plaintext version message.
+pub fn is_v1_prefix(bytes: &[u8]) -> bool {
+    let mut prefix = network().start_string().to_vec();
+    prefix.extend_from_slice(b"version\0\0\0\0\0");
+    bytes.starts_with(&prefix)
+}
+
+/// HKDF-SHA256 of the shared secret, only 32 bytes are ever expanded.
+fn expand(prk: &[u8], info: &[u8]) -> [u8; 32] {
+    let mut engine = hmac::HmacEngine::<sha256::Hash>::new(prk);
+    engine.input(info);
+    engine.input(&[1]);
+    hmac::Hmac::<sha256::Hash>::from_engine(engine).to_byte_array()
+}
+
+fn extract(secret: &[u8]) -> [u8; 32] {
+    let salt = [&b"bitcoin_v2_shared_secret"[..], &network().start_string()].concat();
+    let mut engine = hmac::HmacEngine::<sha256::Hash>::new(&salt);
+    engine.input(secret);
+    hmac::Hmac::<sha256::Hash>::from_engine(engine).to_byte_array()
+}
+
+/// Keys of both directions and the garbage terminator of each side.
+struct Keys {
+    send: SendCipher,
+    recv: RecvCipher,
+    send_terminator: [u8; 16],
+    recv_terminator: [u8; 16],
+    session_id: [u8; 32],
+}
+
+fn derive_keys(secret: &[u8; 32], role: Role) -> Keys {
+    let prk = extract(secret);
+    let terminators = expand(&prk, b"garbage_terminators");
+    let mut initiator_terminator = [0u8; 16];
+    let mut responder_terminator = [0u8; 16];
+    initiator_terminator.copy_from_slice(&terminators[..16]);
+    responder_terminator.copy_from_slice(&terminators[16..]);
+
+    let initiator = (
+        FsChaCha20::new(expand(&prk, b"initiator_L")),
+        FsChaCha20Poly1305::new(expand(&prk, b"initiator_P")),
+    );
+    let responder = (
+        FsChaCha20::new(expand(&prk, b"responder_L")),
+        FsChaCha20Poly1305::new(expand(&prk, b"responder_P")),
+    );
+    let (send, recv, send_terminator, recv_terminator) = match role {
+        Role::Initiator => (
+            initiator,
+            responder,
+            initiator_terminator,
+            responder_terminator,
+        ),
+        Role::Responder => (
+            responder,
+            initiator,
+            responder_terminator,
+            initiator_terminator,
+        ),
+    };
+
+    Keys {
+        send: SendCipher {
+            length: send.0,
+            packet: send.1,
+            aad: vec![],
+        },
+        recv: RecvCipher {
+            length: recv.0,
+            packet: recv.1,
+            aad: vec![],
+        },
+        send_terminator,
+        recv_terminator,
+        session_id: expand(&prk, b"session_id"),
+    }
+}
+
+/// Ephemeral key of the handshake, the initiator can't send an encoding that looks like a
+/// plaintext version message.
+fn ephemeral_key(role: Role) -> Result<(SecretKey, ElligatorSwift), ProtocolError> {
+    let secp = Secp256k1::new();
+    loop {
+        let secret = SecretKey::from_slice(&rand::random::<[u8; 32]>())
+            .map_err(|e| ProtocolError::Error(format!("Invalid ephemeral key: {}", e)))?;
+        let ellswift = ElligatorSwift::from_seckey(&secp, secret, Some(rand::random()));
+        if role == Role::Responder || !is_v1_prefix(&ellswift.to_array()) {
+            return Ok((secret, ellswift));
+        }
+    }
+}
+
+/// Reads until the garbage terminator, returns the garbage before it.
+fn read_garbage(stream: &mut TcpStream, terminator: &[u8; 16]) -> Result<Vec<u8>, ProtocolError> {
+    let mut garbage = vec![];
+    let mut byte = [0u8; 1];
+    while garbage.len() < MAX_GARBAGE_LEN + GARBAGE_TERMINATOR_LEN {
+        stream.read_exact(&mut byte)?;
+        garbage.push(byte[0]);
+        if garbage.ends_with(terminator) {
+            garbage.truncate(garbage.len() - GARBAGE_TERMINATOR_LEN);
+            return Ok(garbage);
+        }
+    }
+    Err(ProtocolError::ConnectionError(
+        "The peer didn't send the garbage terminator".to_string(),
+    ))
+}
+
+/// Performs the handshake of the encrypted transport. `received` are the bytes of the peer
+/// already read, the responder reads some to tell the plaintext peers apart.
+pub fn handshake(
+    stream: &mut TcpStream,
+    role: Role,
+    received: &[u8],
+) -> Result<Session, ProtocolError> {
+    let (secret, ours) = ephemeral_key(role)?;
+    let garbage: Vec<u8> = (0..rand::random::<usize>() % (MAX_GARBAGE_LEN + 1))
+        .map(|_| rand::random())
+        .collect();
+    stream.write_all(&[&ours.to_array()[..], &garbage].concat())?;
+
+    let mut theirs = [0u8; ELLSWIFT_LEN];
+    theirs[..received.len()].copy_from_slice(received);
+    stream.read_exact(&mut theirs[received.len()..])?;
+    let theirs = ElligatorSwift::from_array(theirs);
+
+    let shared = match role {
+        Role::Initiator => {
+            ElligatorSwift::shared_secret(ours, theirs, secret, ElligatorSwiftParty::A, None)
+        }
+        Role::Responder => {
+            ElligatorSwift::shared_secret(theirs, ours, secret, ElligatorSwiftParty::B, None)
+        }
+    };
+    let mut keys = derive_keys(&shared.to_secret_bytes(), role);
+
+    // The contents of the version packet are reserved for future extensions
+    keys.send.aad = garbage;
+    let version = keys.send.encrypt(&[], false);
+    stream.write_all(&[&keys.send_terminator[..], &version].concat())?;
+
+    keys.recv.aad = read_garbage(stream, &keys.recv_terminator)?;
+    // Decoys may be sent before the version packet
+    while keys.recv.read_packet(stream)?.is_none() {}
+
+    Ok(Session {
+        send: keys.send,
+        recv: keys.recv,
+        id: keys.session_id,
+    })
+}
+
+/// Contents of the packet of a serialized plaintext message, its header and payload.
+pub fn encode_message(message: &[u8]) -> Vec<u8> {
+    let command = &message[4..4 + V1_COMMAND_LEN];
+    let name_len = command
+        .iter()
+        .position(|b| *b == 0)
+        .unwrap_or(V1_COMMAND_LEN);
+    let name = String::from_utf8_lossy(&command[..name_len]);
+    let payload = &message[MESSAGE_HEADER_SIZE..];
+
+    let mut contents = match SHORT_IDS.iter().position(|id| *id == name) {
+        Some(i) => vec![i as u8 + 1],
+        None => [&[0][..], command].concat(),
+    };
+    contents.extend_from_slice(payload);
+    contents
+}
+
+/// Plaintext serialization of the message in the contents of a packet, so it can be read
+/// like the messages of the plaintext peers.
+pub fn decode_message(contents: &[u8]) -> Result<Vec<u8>, ProtocolError> {
+    let invalid = || ProtocolError::ConnectionError("The peer sent an invalid packet".to_string());
+    let (command, payload) = match contents.first() {
+        Some(0) if contents.len() > V1_COMMAND_LEN => {
+            let mut command = [0u8; V1_COMMAND_LEN];
+            command.copy_from_slice(&contents[1..1 + V1_COMMAND_LEN]);
+            (command, &contents[1 + V1_COMMAND_LEN..])
+        }
+        Some(id) if (1..=SHORT_IDS.len()).contains(&(*id as usize)) => {
+            let name = SHORT_IDS[*id as usize - 1];
+            let mut command = [0u8; V1_COMMAND_LEN];
+            command[..name.len()].copy_from_slice(name.as_bytes());
+            (command, &contents[1..])
+        }
+        _ => return Err(invalid()),
+    };
+
+    let mut message = network().start_string().to_vec();
+    message.extend_from_slice(&command);
+    message.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    message.extend_from_slice(&sha256d::Hash::hash(payload)[..4]);
+    message.extend_from_slice(payload);
+    Ok(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{message::ping::PingMessage, raw_transaction::unhexlify};
+    use std::{net::TcpListener, thread};
+
+    #[test]
+    fn test_messages_keep_their_plaintext_serialization() {
+        let mut ping = vec![];
+        PingMessage::new(7).write_to(&mut ping).unwrap();
+        let contents = encode_message(&ping);
+        assert_eq!(contents[0], 18);
+        assert_eq!(decode_message(&contents).unwrap(), ping);
+
+        let mut verack = network().start_string().to_vec();
+        verack.extend_from_slice(b"verack\0\0\0\0\0\0");
+        verack.extend_from_slice(&0u32.to_le_bytes());
+        verack.extend_from_slice(&sha256d::Hash::hash(&[])[..4]);
+        let contents = encode_message(&verack);
+        assert_eq!(contents.len(), 1 + V1_COMMAND_LEN);
+        assert_eq!(decode_message(&contents).unwrap(), verack);
+    }
+
+    fn ciphers() -> (SendCipher, RecvCipher) {
+        let length_key = "8784398dfad2e85958453feab2ca1d4dce422d63f47cd71bd04841a9a18a4c5e";
+        let packet_key = "97f9b24e69384160a03ffa0955dacf8fb0e39225499432463da1ce9f2d708d15";
+        let cipher = |hex: &str| unhexlify(hex).unwrap().try_into().unwrap();
+        let send = SendCipher {
+            length: FsChaCha20::new(cipher(length_key)),
+            packet: FsChaCha20Poly1305::new(cipher(packet_key)),
+            aad: vec![],
+        };
+        let recv = RecvCipher {
+            length: FsChaCha20::new(cipher(length_key)),
+            packet: FsChaCha20Poly1305::new(cipher(packet_key)),
+            aad: vec![],
+        };
+        (send, recv)
+    }
+
+    #[test]
+    fn test_packets_match_bip324_encoding() {
+        // Packet index, garbage, contents, ignore bit and the packet, computed with the
+        // reference ciphers of BIP 324, past the rekeys at the packets 224 and 448
+        let vectors = [
+            (
+                0,
+                "garbage",
+                "",
+                false,
+                "5d6bfb4c077d08971c5202a14de45677d87b5918",
+            ),
+            (
+                1,
+                "",
+                "120001020304050607",
+                false,
+                "1c205e653f0aa59faf2d5a6a1a9a542291dd8894e913c4cca7fecd368a",
+            ),
+            (
+                223,
+                "",
+                "68656c6c6f",
+                true,
+                "ad17d96557d4ed761edc02a9b4584211b2b31893957ff402c7",
+            ),
+            (
+                224,
+                "",
+                "68656c6c6f",
+                false,
+                "84dea1d8e5ddd71fa7e799b4d8620a779249c5741fdb8b7df9",
+            ),
+            (
+                448,
+                "",
+                "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f2021222324252627",
+                false,
+                "b6b781ceed9cd39a343c890f518f9c0b6071fda597514f09fd47b780e0cd172a1dafef9666926ca5\
+                 70a1e0d8cd2c71d09b4cadec34d8d371d3735e6d",
+            ),
+        ];
+
+        for (index, garbage, contents, ignore, packet) in vectors {
+            let (mut send, mut recv) = ciphers();
+            for _ in 0..index {
+                let previous = send.encrypt(&[], false);
+                recv.read_packet(&mut &previous[..]).unwrap();
+            }
+            send.aad = garbage.as_bytes().to_vec();
+            recv.aad = garbage.as_bytes().to_vec();
+
+            let contents = unhexlify(contents).unwrap();
+            let encrypted = send.encrypt(&contents, ignore);
+            assert_eq!(encrypted, unhexlify(packet).unwrap());
+            let decrypted = recv.read_packet(&mut &encrypted[..]).unwrap();
+            assert_eq!(decrypted, (!ignore).then_some(contents));
+        }
+    }
+
+    #[test]
+    fn test_oversized_packets_are_rejected() {
+        let (mut send, mut recv) = ciphers();
+        let mut length = ((MAX_CONTENTS_LEN + 1) as u32).to_le_bytes()[..LENGTH_LEN].to_vec();
+        send.length.crypt(&mut length);
+        assert!(recv.read_packet(&mut &length[..]).is_err());
+    }
+
+    #[test]
+    fn test_both_ends_agree_on_the_session() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut initiator = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (mut responder, _) = listener.accept().unwrap();
+
+        let responder = thread::spawn(move || {
+            let mut first = [0u8; 16];
+            responder.read_exact(&mut first).unwrap();
+            assert!(!is_v1_prefix(&first));
+            let mut session = handshake(&mut responder, Role::Responder, &first).unwrap();
+            let mut decoys = 0;
+            loop {
+                match session.recv.read_packet(&mut responder).unwrap() {
+                    Some(contents) => return (session.id, decoys, contents),
+                    None => decoys += 1,
+                }
+            }
+        });
+
+        let mut session = handshake(&mut initiator, Role::Initiator, &[]).unwrap();
+        let decoy = session.send.encrypt(b"noise", true);
+        let packet = session.send.encrypt(b"hello", false);
+        initiator.write_all(&[decoy, packet].concat()).unwrap();
+
+        let (id, decoys, contents) = responder.join().unwrap();
+        assert_eq!(id, session.id);
+        assert_eq!((decoys, &contents[..]), (1, &b"hello"[..]));
+    }
+}
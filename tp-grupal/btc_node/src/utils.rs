@@ -1,6 +1,7 @@
-use crate::{constants::P2PKH_BYTE, protocol_error::ProtocolError};
+use crate::{keys::PrivateKey, message_header::network, protocol_error::ProtocolError};
 use bitcoin_hashes::{ripemd160, sha256, sha256d, Hash};
-use secp256k1::{PublicKey, Secp256k1, SecretKey};
+use chrono::DateTime;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
 
 pub fn hash160(bytes: &[u8]) -> [u8; 20] {
     ripemd160::Hash::hash(&sha256::Hash::hash(bytes).to_byte_array()).to_byte_array()
@@ -24,31 +25,13 @@ pub fn bitcoin_address_to_pkhash(addrs: &str) -> Result<Vec<u8>, ProtocolError>
     Ok(address_decoded[1..21].to_vec())
 }
 
-pub fn wif_to_private_key(wif: &str) -> Vec<u8> {
-    let wif_decoded = &bs58::decode(wif).into_vec().unwrap()[..];
-    let private_key = &wif_decoded[1..(wif_decoded.len() - 5)];
-    private_key.to_vec()
-}
-
-pub fn wif_to_bitcoin_address(wif: &str) -> String {
-    let pkhash = wif_to_pkhash(wif).unwrap();
-    let mut addr = [&[P2PKH_BYTE], &pkhash[..]].concat();
-    let checksum = &sha256d::Hash::hash(&addr).to_byte_array()[0..4];
-
-    addr.extend_from_slice(checksum);
-
-    bs58::encode(addr).into_string()
+/// P2PKH address of the key, the one of its compressed or uncompressed public key as the WIF says.
+pub fn wif_to_bitcoin_address(wif: &str) -> Result<String, ProtocolError> {
+    Ok(PrivateKey::from_wif(wif, network())?.address())
 }
 
 pub fn wif_to_pkhash(wif: &str) -> Result<[u8; 20], ProtocolError> {
-    let private_key = crate::utils::wif_to_private_key(wif);
-    let secp = Secp256k1::signing_only();
-    let secret_key = SecretKey::from_slice(&private_key)
-        .map_err(|_| ProtocolError::Error("Converting the wif to a private key".to_string()))?;
-
-    let public_key = PublicKey::from_secret_key(&secp, &secret_key).serialize();
-
-    Ok(hash160(&public_key))
+    Ok(PrivateKey::from_wif(wif, network())?.pkhash())
 }
 
 pub fn bytes_to_hex_string(bytes: &[u8]) -> String {
@@ -56,6 +39,22 @@ pub fn bytes_to_hex_string(bytes: &[u8]) -> String {
     hex_chars.join("").to_lowercase()
 }
 
+/// Formats a unix timestamp as a UTC date.
+pub fn timestamp_to_date(timestamp: u32) -> String {
+    match DateTime::from_timestamp(timestamp as i64, 0) {
+        Some(date) => date.format("%Y-%m-%d %H:%M:%S").to_string(),
+        None => "Unknown".to_string(),
+    }
+}
+
+/// Formats a unix timestamp as the UTC hour and minute.
+pub fn timestamp_to_time(timestamp: u32) -> String {
+    match DateTime::from_timestamp(timestamp as i64, 0) {
+        Some(date) => date.format("%H:%M").to_string(),
+        None => "Unknown".to_string(),
+    }
+}
+
 pub fn decode_hex(s: &str) -> [u8; 32] {
     let mut hash: [u8; 32] = [0; 32];
     for i in 0..64 {
@@ -66,3 +65,22 @@ pub fn decode_hex(s: &str) -> [u8; 32] {
     }
     hash
 }
+
+/// Resolves an addnode style peer: an ip, `ip:port`, `[ipv6]:port`, a hostname or `hostname:port`.
+/// `default_port` is used when the peer doesn't have one.
+pub fn resolve_peer(peer: &str, default_port: u16) -> Result<Vec<SocketAddr>, ProtocolError> {
+    if let Ok(socket) = peer.parse::<SocketAddr>() {
+        return Ok(vec![socket]);
+    }
+    if let Ok(ip) = peer.parse::<IpAddr>() {
+        return Ok(vec![SocketAddr::new(ip, default_port)]);
+    }
+
+    let addrs = match peer.rsplit_once(':') {
+        Some((_, port)) if port.parse::<u16>().is_ok() => peer.to_socket_addrs(),
+        _ => (peer, default_port).to_socket_addrs(),
+    }
+    .map_err(|e| ProtocolError::ConnectionError(format!("Resolving peer {}: {}", peer, e)))?;
+
+    Ok(addrs.collect())
+}
@@ -0,0 +1,63 @@
+use crate::{protocol_error::ProtocolError, script::PubKeyScript};
+
+/// Balance delta of every address touched by a transaction
+pub type WalletEffects = Vec<(String, i64)>;
+
+/// Net change that a transaction makes to the balance of every address it touches:
+/// the value of the outputs paying the address minus the value of the outputs it spends from it.
+/// `spent` and `created` are the (pk_script, value) of the spent and created outputs,
+/// only the addresses that appear in any of them are returned.
+pub fn net_effects(
+    spent: &[(Vec<u8>, i64)],
+    created: &[(Vec<u8>, i64)],
+    addresses: &[String],
+) -> Result<WalletEffects, ProtocolError> {
+    let mut effects = vec![];
+    for addr in addresses.iter() {
+        let mut touched = false;
+        let mut delta = 0;
+        for (script, value) in spent.iter() {
+            if PubKeyScript::can_be_spent_by_address(script, addr)? {
+                touched = true;
+                delta -= value;
+            }
+        }
+        for (script, value) in created.iter() {
+            if PubKeyScript::can_be_spent_by_address(script, addr)? {
+                touched = true;
+                delta += value;
+            }
+        }
+
+        if touched {
+            effects.push((addr.to_string(), delta));
+        }
+    }
+    Ok(effects)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::P2PKH_BYTE;
+
+    fn address(pkhash: [u8; 20]) -> String {
+        let mut bytes = vec![P2PKH_BYTE];
+        bytes.extend(pkhash);
+        bytes.extend([0; 4]);
+        bs58::encode(bytes).into_string()
+    }
+
+    #[test]
+    fn test_payment_with_change_is_a_single_negative_delta() {
+        let ours = PubKeyScript::P2PKH(vec![1; 20]).to_vec();
+        let theirs = PubKeyScript::P2PKH(vec![2; 20]).to_vec();
+        let addresses = vec![address([1; 20]), address([3; 20])];
+
+        let spent = vec![(ours.clone(), 10000)];
+        let created = vec![(theirs, 6000), (ours, 3000)];
+
+        let effects = net_effects(&spent, &created, &addresses).unwrap();
+        assert_eq!(effects, vec![(address([1; 20]), -7000)]);
+    }
+}
@@ -0,0 +1,280 @@
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{BufRead, BufReader, Write},
+    path::Path,
+};
+
+use crate::{
+    address::validate_address, keys::PrivateKey, message_header::network,
+    protocol_error::ProtocolError,
+};
+
+/// Account stored in a wallet file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WalletEntry {
+    pub name: String,
+    pub address: String,
+    pub wif: String,
+}
+
+// Starts the lines of the labels, addresses never start with it
+const LABEL_PREFIX: &str = "label:";
+
+/// Named set of accounts saved in a text file, one `address,wif,name` line per account.
+/// The name goes last so it can contain commas.
+/// The labels go in `label:key,text` lines, keyed by txid in hex or by address.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WalletFile {
+    pub path: String,
+    pub accounts: Vec<WalletEntry>,
+    pub labels: BTreeMap<String, String>,
+}
+
+impl WalletFile {
+    /// Reads the wallet at `path`, a missing file is a new empty wallet.
+    pub fn open(path: &str) -> Result<WalletFile, ProtocolError> {
+        let mut accounts = vec![];
+        let mut labels = BTreeMap::new();
+        if Path::new(path).exists() {
+            let reader = BufReader::new(File::open(path)?);
+            for line in reader.lines() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                if let Some(label) = line.strip_prefix(LABEL_PREFIX) {
+                    if let Some((key, text)) = label.split_once(',') {
+                        labels.insert(key.to_string(), text.to_string());
+                        continue;
+                    }
+                }
+                let mut fields = line.splitn(3, ',');
+                match (fields.next(), fields.next(), fields.next()) {
+                    (Some(address), Some(wif), Some(name)) => accounts.push(WalletEntry {
+                        name: name.to_string(),
+                        address: address.to_string(),
+                        wif: wif.to_string(),
+                    }),
+                    _ => {
+                        return Err(ProtocolError::Error(format!(
+                            "Invalid account in wallet file {}",
+                            path
+                        )))
+                    }
+                }
+            }
+        }
+
+        Ok(WalletFile {
+            path: path.to_string(),
+            accounts,
+            labels,
+        })
+    }
+
+    /// Name shown for the wallet, the file name without its extension.
+    pub fn name(&self) -> String {
+        Path::new(&self.path)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_else(|| self.path.clone())
+    }
+
+    /// Adds the account, replacing the one with the same address, and saves the file.
+    pub fn add_account(&mut self, entry: WalletEntry) -> Result<(), ProtocolError> {
+        self.accounts
+            .retain(|account| account.address != entry.address);
+        self.accounts.push(entry);
+        self.save()
+    }
+
+    /// Removes the account with the address, if any, and saves the file.
+    pub fn remove_account(&mut self, address: &str) -> Result<(), ProtocolError> {
+        self.accounts.retain(|account| account.address != address);
+        self.save()
+    }
+
+    /// Labels the txid or address and saves the file, an empty label removes it.
+    pub fn set_label(&mut self, key: &str, label: &str) -> Result<(), ProtocolError> {
+        // One line per label
+        let label = label.replace(['\n', '\r'], " ");
+        if label.trim().is_empty() {
+            self.labels.remove(key);
+        } else {
+            self.labels.insert(key.to_string(), label);
+        }
+        self.save()
+    }
+
+    /// Saves a copy of the accounts and labels at `path`, the wallet keeps using its own file.
+    pub fn backup_to(&self, path: &str) -> Result<(), ProtocolError> {
+        WalletFile {
+            path: path.to_string(),
+            ..self.clone()
+        }
+        .save()
+    }
+
+    /// Reads a backup, which unlike a wallet has to exist and have the key of every address.
+    pub fn read_backup(path: &str) -> Result<WalletFile, ProtocolError> {
+        if !Path::new(path).exists() {
+            return Err(ProtocolError::Error(format!(
+                "Backup file {} not found",
+                path
+            )));
+        }
+        let backup = WalletFile::open(path)?;
+        for account in backup.accounts.iter() {
+            check_account(account)?;
+        }
+        Ok(backup)
+    }
+
+    /// Adds the accounts and labels of the backup that the wallet doesn't have, the ones it
+    /// has are kept. Saves the file and returns the accounts added.
+    pub fn merge(&mut self, backup: WalletFile) -> Result<Vec<WalletEntry>, ProtocolError> {
+        let mut added = vec![];
+        for account in backup.accounts {
+            if !self.accounts.iter().any(|a| a.address == account.address) {
+                self.accounts.push(account.clone());
+                added.push(account);
+            }
+        }
+        for (key, label) in backup.labels {
+            self.labels.entry(key).or_insert(label);
+        }
+        self.save()?;
+        Ok(added)
+    }
+
+    pub fn save(&self) -> Result<(), ProtocolError> {
+        let mut file = File::create(&self.path)?;
+        for account in self.accounts.iter() {
+            writeln!(file, "{},{},{}", account.address, account.wif, account.name)?;
+        }
+        for (key, label) in self.labels.iter() {
+            writeln!(file, "{}{},{}", LABEL_PREFIX, key, label)?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks that the address is valid and that the wif is its key.
+fn check_account(account: &WalletEntry) -> Result<(), ProtocolError> {
+    validate_address(&account.address)?;
+    let key = PrivateKey::from_wif(&account.wif, network()).map_err(|e| {
+        ProtocolError::Error(format!(
+            "Invalid key for the account {}: {}",
+            account.name, e
+        ))
+    })?;
+    if key.address() != account.address {
+        return Err(ProtocolError::Error(format!(
+            "The key of the account {} isn't the one of its address",
+            account.name
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_accounts_are_saved_and_read_back() {
+        let path =
+            std::env::temp_dir().join(format!("wallet_file_test_{}.wallet", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        let mut wallet = WalletFile::open(path).unwrap();
+        assert!(wallet.accounts.is_empty());
+
+        let entry = WalletEntry {
+            name: "Savings, testnet".to_string(),
+            address: "mnJvq7mbGiPNNhUne4FAqq27Q8xZrAsVun".to_string(),
+            wif: "cRJzHMCgDLsvttTH8R8t6LLcZgMDs1WtgwQXxk8bFFk7E2AJp1tw".to_string(),
+        };
+        wallet.add_account(entry.clone()).unwrap();
+        wallet.add_account(entry.clone()).unwrap();
+
+        let read = WalletFile::open(path).unwrap();
+        assert_eq!(read.accounts, vec![entry.clone()]);
+        assert!(read.name().starts_with("wallet_file_test_"));
+
+        wallet.remove_account(&entry.address).unwrap();
+        assert!(WalletFile::open(path).unwrap().accounts.is_empty());
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_labels_are_saved_and_read_back() {
+        let path =
+            std::env::temp_dir().join(format!("wallet_labels_test_{}.wallet", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        let mut wallet = WalletFile::open(path).unwrap();
+        wallet.set_label("ab01", "rent, march\nsplit").unwrap();
+        wallet
+            .set_label("mnJvq7mbGiPNNhUne4FAqq27Q8xZrAsVun", "exchange withdraw")
+            .unwrap();
+        wallet.set_label("cd02", "").unwrap();
+
+        let read = WalletFile::open(path).unwrap();
+        assert!(read.accounts.is_empty());
+        assert_eq!(read.labels.len(), 2);
+        assert_eq!(read.labels["ab01"], "rent, march split");
+
+        wallet.set_label("ab01", " ").unwrap();
+        assert!(!WalletFile::open(path).unwrap().labels.contains_key("ab01"));
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_backups_are_checked_and_merged() {
+        let dir = std::env::temp_dir();
+        let id = std::process::id();
+        let wallet_path = dir.join(format!("wallet_backup_test_{}.wallet", id));
+        let backup_path = dir.join(format!("wallet_backup_test_{}.backup", id));
+        let (wallet_path, backup_path) =
+            (wallet_path.to_str().unwrap(), backup_path.to_str().unwrap());
+
+        let wif = "cRJzHMCgDLsvttTH8R8t6LLcZgMDs1WtgwQXxk8bFFk7E2AJp1tw";
+        let entry = WalletEntry {
+            name: "Savings".to_string(),
+            address: "mnJvq7mbGiPNNhUne4FAqq27Q8xZrAsVun".to_string(),
+            wif: wif.to_string(),
+        };
+        let mut wallet = WalletFile::open(wallet_path).unwrap();
+        wallet.add_account(entry.clone()).unwrap();
+        wallet.set_label("ab01", "rent").unwrap();
+        wallet.backup_to(backup_path).unwrap();
+        assert_eq!(WalletFile::open(wallet_path).unwrap().path, wallet_path);
+
+        let mut other = WalletFile::open(wallet_path).unwrap();
+        other.remove_account(&entry.address).unwrap();
+        other.set_label("ab01", "rent, march").unwrap();
+        let added = other
+            .merge(WalletFile::read_backup(backup_path).unwrap())
+            .unwrap();
+        assert_eq!(added, vec![entry.clone()]);
+        assert_eq!(other.labels["ab01"], "rent, march");
+
+        let read = WalletFile::open(wallet_path).unwrap();
+        assert_eq!(read.accounts, vec![entry.clone()]);
+        assert!(other.merge(read).unwrap().is_empty());
+
+        // The key of another address
+        let mut wrong = wallet.clone();
+        wrong.accounts[0].address = "mgkPm4UebNCJSRGs2Kp2aVE69G8hUEf4d7".to_string();
+        wrong.backup_to(backup_path).unwrap();
+        assert!(WalletFile::read_backup(backup_path).is_err());
+
+        std::fs::remove_file(wallet_path).unwrap();
+        std::fs::remove_file(backup_path).unwrap();
+        assert!(WalletFile::read_backup(backup_path).is_err());
+    }
+}
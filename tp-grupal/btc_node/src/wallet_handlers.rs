@@ -1,52 +1,291 @@
 use crate::{
-    api::{NodeApi, WalletApi},
+    api::{NodeApi, PaymentOptions, Request, WalletApi},
     bitcoin_node::Node,
-    blockchain::txs::Tx,
+    coin_control::{Coin, CoinId},
+    constants::STATS_WINDOW,
+    log_file::node_log_lines_after,
+    mempool::fee_histogram,
+    ownership_proof,
     protocol_error::ProtocolError,
-    script::PubKeyScript,
+    raw_transaction::RawTransaction,
+    wallet_file::{WalletEntry, WalletFile},
+    wallet_warnings::{change_warning, reuse_warning},
 };
+use chrono::Utc;
 use std::sync::{mpsc::Receiver, Arc};
 
 pub fn handle_wallet_messages(
-    rx: Receiver<WalletApi>,
+    rx: Receiver<Request<WalletApi>>,
     node: Arc<Node>,
 ) -> Result<(), ProtocolError> {
-    node.sender
-        .send(NodeApi::NodeReady)
-        .map_err(|_| ProtocolError::Error("Wallet sender error".to_string()))?;
-
-    for msg in rx {
-        let res = match msg {
-            WalletApi::GetBalance(addr) => get_balance(addr, &node),
-            WalletApi::GetHistory(addr) => get_history(addr, &node),
-            WalletApi::PayTo(wif, addr, amount, fee) => pay_to(wif, addr, amount, fee, &node),
-            WalletApi::AddAddress(addr) => add_address(addr, &node),
-        };
-
-        if let Err(e) = res {
-            node.sender
-                .send(NodeApi::Error(e))
-                .map_err(|_| ProtocolError::Error("Wallet sender error".to_string()))?;
-        }
+    node.sender.send(NodeApi::WalletReady)?;
+
+    for request in rx {
+        // The answers and the error carry the id of the request
+        node.sender
+            .replying_to(request.id, || match handle_request(request.body, &node) {
+                Err(e) => node.sender.send(NodeApi::Error(e)),
+                Ok(()) => Ok(()),
+            })?;
     }
     Ok(())
 }
 
+fn handle_request(msg: WalletApi, node: &Arc<Node>) -> Result<(), ProtocolError> {
+    match msg {
+        WalletApi::GetBalance(addr) => get_balance(addr, node),
+        WalletApi::GetHistory(addr) => get_history(addr, node),
+        WalletApi::PayTo(wif, addr, amount, fee, options) => {
+            pay_to(wif, addr, amount, fee, options, node)
+        }
+        WalletApi::GetCoins(addr) => get_coins(addr, node),
+        WalletApi::SetCoinFrozen(addr, coin, frozen) => set_coin_frozen(addr, coin, frozen, node),
+        WalletApi::AddAddress(addr) => add_address(addr, node),
+        WalletApi::GetChainStats => get_chain_stats(node),
+        WalletApi::GetSupply => get_supply(node),
+        WalletApi::Rescan(addr, timestamp) => rescan(addr, timestamp, node),
+        WalletApi::BumpFee(wif, txid, extra_fee) => bump_fee(wif, txid, extra_fee, node),
+        WalletApi::BumpFeeWithChild(wif, txid, fee) => bump_fee_with_child(wif, txid, fee, node),
+        WalletApi::PauseSync => set_sync_paused(true, node),
+        WalletApi::ResumeSync => set_sync_paused(false, node),
+        WalletApi::OpenWallet(path) => open_wallet(path, node),
+        WalletApi::CloseWallet => close_wallet(node),
+        WalletApi::BackupWallet(path) => backup_wallet(path, node),
+        WalletApi::RestoreWallet(path) => restore_wallet(path, node),
+        WalletApi::SaveAccount(entry) => save_account(entry, node),
+        WalletApi::RemoveAddress(addr) => remove_address(addr, node),
+        WalletApi::SetLabel(key, label) => set_label(key, label, node),
+        WalletApi::TraceScripts(txid) => trace_scripts(txid, node),
+        WalletApi::GetSnapshot(addr) => get_snapshot(addr, node),
+        WalletApi::GetMempool => get_mempool(node),
+        WalletApi::GetBlockTemplate(addr) => get_block_template(addr, node),
+        WalletApi::Resync => resync(node),
+        WalletApi::ReloadConfig => reload_config(node),
+        WalletApi::CreatePaymentRequest(addr, amount, label, expires_in) => {
+            create_payment_request(addr, amount, label, expires_in, node)
+        }
+        WalletApi::ProveOwnership(wif, message) => prove_ownership(wif, message, node),
+        WalletApi::VerifyOwnership(addr, message, proof) => {
+            verify_ownership(addr, message, proof, node)
+        }
+        WalletApi::GetLog(seq) => get_log(seq, node),
+    }
+}
+
 fn get_balance(addr: String, node: &Arc<Node>) -> Result<(), ProtocolError> {
     let pkhash = crate::utils::bitcoin_address_to_pkhash(&addr)?;
     let balance = node.blockchain.lock()?.utxo.get_balance(pkhash);
+    node.sender.send(NodeApi::Balance(balance, addr.clone()))?;
+    get_coins(addr, node)
+}
+
+/// Sends the unspent outputs of the address and how many are confirmed.
+/// The unconfirmed change of its payments is listed with no confirmations.
+pub fn get_coins(addr: String, node: &Arc<Node>) -> Result<(), ProtocolError> {
+    let pkhash = crate::utils::bitcoin_address_to_pkhash(&addr)?;
+    let mut utxo = node.blockchain.lock()?.get_coins(pkhash);
+    let confirmed = utxo.len();
+    utxo.extend(
+        node.unconfirmed_change(&addr)?
+            .into_iter()
+            .map(|(txid, out)| (txid, out, 0)),
+    );
+    let frozen = node.frozen_coins.read()?;
+    let coins = Coin::from_outputs(utxo, &addr, &frozen);
+    drop(frozen);
+
     node.sender
-        .send(NodeApi::Balance(balance, addr))
-        .map_err(|_| ProtocolError::Error("Wallet sender error".to_string()))?;
+        .send(NodeApi::UtxoCount(confirmed, addr.clone()))?;
+    node.sender.send(NodeApi::Coins(coins, addr))?;
     Ok(())
 }
 
-fn get_history(addr: String, node: &Arc<Node>) -> Result<(), ProtocolError> {
+fn set_coin_frozen(
+    addr: String,
+    coin: CoinId,
+    frozen: bool,
+    node: &Arc<Node>,
+) -> Result<(), ProtocolError> {
+    let mut frozen_coins = node.frozen_coins.write()?;
+    if frozen {
+        frozen_coins.insert(coin);
+    } else {
+        frozen_coins.remove(&coin);
+    }
+    drop(frozen_coins);
+    get_coins(addr, node)
+}
+
+pub fn get_history(addr: String, node: &Arc<Node>) -> Result<(), ProtocolError> {
     let pkhash = crate::utils::bitcoin_address_to_pkhash(&addr)?;
     let history = node.blockchain.lock()?.get_tx_history(pkhash);
+    let warning = reuse_warning(&history);
+    node.sender.send(NodeApi::History(history, addr.clone()))?;
+    if let Some(warning) = warning {
+        node.sender.send(NodeApi::Warning(warning, addr))?;
+    }
+    Ok(())
+}
+
+/// Sends the balance and history of every wallet address again, used after the blocks are synced.
+pub fn refresh_wallet_addresses(node: &Arc<Node>) -> Result<(), ProtocolError> {
+    let addresses = node.wallet_addresses.read()?.clone();
+    for addr in addresses {
+        get_balance(addr.clone(), node)?;
+        get_history(addr, node)?;
+    }
+    Ok(())
+}
+
+fn get_snapshot(addr: String, node: &Arc<Node>) -> Result<(), ProtocolError> {
+    let snapshot = node.wallet_snapshot(&addr)?;
+    node.sender.send(NodeApi::Snapshot(snapshot))?;
+    Ok(())
+}
+
+/// Sends the mempool transactions with their fee rates and the fee histogram.
+fn get_mempool(node: &Arc<Node>) -> Result<(), ProtocolError> {
+    let txs = node.mempool_snapshot()?;
+    let histogram = fee_histogram(&txs);
+    let recent_blocks = node.blockchain.lock()?.recent_blocks();
     node.sender
-        .send(NodeApi::History(history, addr))
-        .map_err(|_| ProtocolError::Error("Wallet sender error".to_string()))?;
+        .send(NodeApi::Mempool(txs, histogram, recent_blocks))?;
+    Ok(())
+}
+
+fn get_block_template(addr: String, node: &Arc<Node>) -> Result<(), ProtocolError> {
+    let template = node.block_template(&addr)?;
+    node.sender.send(NodeApi::BlockTemplate(template))?;
+    Ok(())
+}
+
+/// Sends the state the wallet builds from the events again, after it missed some of them.
+fn resync(node: &Arc<Node>) -> Result<(), ProtocolError> {
+    let addresses = node.wallet_addresses.read()?.clone();
+    for addr in addresses {
+        get_snapshot(addr, node)?;
+    }
+    node.sender
+        .send(NodeApi::MempoolUpdate(node.mempool_snapshot()?))?;
+    let requests = node.payment_requests.lock()?.all();
+    node.sender.send(NodeApi::PaymentRequests(requests))?;
+    Ok(())
+}
+
+/// Adds a request to be paid to an address of the wallet, and sends every request.
+fn create_payment_request(
+    addr: String,
+    amount: i64,
+    label: String,
+    expires_in: u32,
+    node: &Arc<Node>,
+) -> Result<(), ProtocolError> {
+    if !node.wallet_addresses.read()?.contains(&addr) {
+        return Err(ProtocolError::Error(format!(
+            "The address {} isn't in the wallet",
+            addr
+        )));
+    }
+    let now = Utc::now().timestamp() as u32;
+    let mut requests = node.payment_requests.lock()?;
+    requests.add(addr, amount, label, now, expires_in)?;
+    node.sender.send(NodeApi::PaymentRequests(requests.all()))?;
+    Ok(())
+}
+
+fn prove_ownership(wif: String, message: String, node: &Arc<Node>) -> Result<(), ProtocolError> {
+    let (address, proof) = ownership_proof::prove_ownership(&wif, &message)?;
+    node.sender
+        .send(NodeApi::OwnershipProof(address, message, proof))?;
+    Ok(())
+}
+
+fn verify_ownership(
+    addr: String,
+    message: String,
+    proof: String,
+    node: &Arc<Node>,
+) -> Result<(), ProtocolError> {
+    let valid = ownership_proof::verify_ownership(&addr, &message, &proof)?;
+    node.sender
+        .send(NodeApi::OwnershipVerified(addr, message, valid))?;
+    Ok(())
+}
+
+fn get_chain_stats(node: &Arc<Node>) -> Result<(), ProtocolError> {
+    let stats = node.blockchain.lock()?.get_chain_stats(STATS_WINDOW);
+    node.sender.send(NodeApi::ChainStats(stats))?;
+    Ok(())
+}
+
+fn get_supply(node: &Arc<Node>) -> Result<(), ProtocolError> {
+    let supply = node.blockchain.lock()?.supply();
+    node.sender.send(NodeApi::Supply(supply))?;
+    Ok(())
+}
+
+fn get_log(seq: u64, node: &Arc<Node>) -> Result<(), ProtocolError> {
+    node.sender.send(NodeApi::Log(node_log_lines_after(seq)))?;
+    Ok(())
+}
+
+fn rescan(addr: String, timestamp: u32, node: &Arc<Node>) -> Result<(), ProtocolError> {
+    node.rescan(timestamp)?;
+    get_balance(addr.clone(), node)?;
+    get_history(addr, node)
+}
+
+fn set_sync_paused(paused: bool, node: &Arc<Node>) -> Result<(), ProtocolError> {
+    if paused {
+        node.sync_control.pause()?;
+    } else {
+        node.sync_control.resume()?;
+    }
+    node.sender.send(NodeApi::SyncPaused(paused))?;
+    Ok(())
+}
+
+fn reload_config(node: &Arc<Node>) -> Result<(), ProtocolError> {
+    let changes = node.reload_config()?;
+    node.sender.send(NodeApi::ConfigReloaded(changes))?;
+    Ok(())
+}
+
+fn bump_fee(
+    wif: String,
+    txid: [u8; 32],
+    extra_fee: i64,
+    node: &Arc<Node>,
+) -> Result<(), ProtocolError> {
+    let payer_address = crate::utils::wif_to_bitcoin_address(&wif)?;
+    let tx = node.bump_fee(&wif, txid, extra_fee)?;
+    node.sender.send(NodeApi::FeeBumped(
+        txid,
+        node.tx_with_fee(&tx)?,
+        payer_address,
+    ))?;
+    Ok(())
+}
+
+fn bump_fee_with_child(
+    wif: String,
+    txid: [u8; 32],
+    fee: i64,
+    node: &Arc<Node>,
+) -> Result<(), ProtocolError> {
+    let address = crate::utils::wif_to_bitcoin_address(&wif)?;
+    let tx = node.pay_fee_with_child(&wif, txid, fee)?;
+    node.sender.send(NodeApi::FeeBumpedByChild(
+        txid,
+        node.tx_with_fee(&tx)?,
+        address,
+    ))?;
+    Ok(())
+}
+
+fn trace_scripts(txid: [u8; 32], node: &Arc<Node>) -> Result<(), ProtocolError> {
+    let traces = node.trace_scripts(txid)?;
+    node.sender.send(NodeApi::ScriptTraces(txid, traces))?;
     Ok(())
 }
 
@@ -55,84 +294,161 @@ fn pay_to(
     addr: String,
     amount: i64,
     fee: i64,
+    options: PaymentOptions,
     node: &Arc<Node>,
 ) -> Result<(), ProtocolError> {
-    let payer_address = crate::utils::wif_to_bitcoin_address(&wif);
-    let tx = node.create_transaction(&wif, &addr, amount, fee)?;
+    let payer_address = crate::utils::wif_to_bitcoin_address(&wif)?;
+    let tx = node.create_transaction(&wif, &addr, amount, fee, &options)?;
     node.wallet_txs
         .write()?
         .insert(tx.get_tx_id(), payer_address.clone());
+    node.rebroadcast
+        .lock()?
+        .add(tx.get_tx_id(), std::time::Instant::now());
 
+    let warning = change_warning(&tx, &payer_address, &addr)?;
     node.broadcast_transaction(tx.clone())?;
-    node.sender
-        .send(NodeApi::PaymentConfirmation(
-            Tx::from_raw_tx(&tx),
-            payer_address,
-            addr,
-            amount,
-        ))
-        .map_err(|_| ProtocolError::Error("Wallet sender error".to_string()))?;
-
-    let addresses = node.wallet_addresses.read()?;
-    for addr in addresses.iter() {
-        for out in &tx.tx_out {
-            if PubKeyScript::can_be_spent_by_address(&out.pk_script, addr)? {
-                node.sender
-                    .send(NodeApi::AddPendingBalance(out.value, addr.to_string()))
-                    .map_err(|_| ProtocolError::Error("Wallet sender error".to_string()))?;
-            };
-        }
+    node.sender.send(NodeApi::PaymentConfirmation(
+        node.tx_with_fee(&tx)?,
+        payer_address.clone(),
+        addr,
+        amount,
+    ))?;
+
+    let addresses = node.wallet_addresses.read()?.clone();
+    node.add_pending_effects(&tx, &addresses)?;
+
+    if let Some(warning) = warning {
+        node.sender.send(NodeApi::Warning(warning, payer_address))?;
     }
+    Ok(())
+}
 
+/// Replaces the open wallet, the accounts of the new one are sent and tracked like added addresses.
+fn open_wallet(path: String, node: &Arc<Node>) -> Result<(), ProtocolError> {
+    let wallet = WalletFile::open(&node.wallet_path(&path))?;
+    close_wallet(node)?;
+
+    node.sender.send(NodeApi::WalletOpened(
+        wallet.name(),
+        wallet.accounts.clone(),
+        wallet.labels.clone(),
+    ))?;
+
+    let addresses: Vec<String> = wallet.accounts.iter().map(|a| a.address.clone()).collect();
+    *node.open_wallet.write()? = Some(wallet);
+    for addr in addresses {
+        add_address(addr, node)?;
+    }
     Ok(())
 }
 
+/// Stops tracking the addresses of the open wallet, if there is one.
+fn close_wallet(node: &Arc<Node>) -> Result<(), ProtocolError> {
+    let wallet = match node.open_wallet.write()?.take() {
+        Some(wallet) => wallet,
+        None => return Ok(()),
+    };
+
+    node.wallet_addresses
+        .write()?
+        .retain(|addr| !wallet.accounts.iter().any(|a| &a.address == addr));
+    node.sender.send(NodeApi::WalletClosed)?;
+    Ok(())
+}
+
+fn backup_wallet(path: String, node: &Arc<Node>) -> Result<(), ProtocolError> {
+    match node.open_wallet.read()?.as_ref() {
+        Some(wallet) => wallet.backup_to(&path)?,
+        None => return Err(no_open_wallet()),
+    }
+    node.sender.send(NodeApi::WalletBackedUp(path))?;
+    Ok(())
+}
+
+/// Merges a checked backup into the open wallet and tracks the accounts added, which sends
+/// their balances and history from the chain.
+fn restore_wallet(path: String, node: &Arc<Node>) -> Result<(), ProtocolError> {
+    let backup = WalletFile::read_backup(&path)?;
+    let (added, labels) = match node.open_wallet.write()?.as_mut() {
+        Some(wallet) => (wallet.merge(backup)?, wallet.labels.clone()),
+        None => return Err(no_open_wallet()),
+    };
+
+    node.sender
+        .send(NodeApi::WalletRestored(added.clone(), labels))?;
+    for account in added {
+        add_address(account.address, node)?;
+    }
+    Ok(())
+}
+
+fn no_open_wallet() -> ProtocolError {
+    ProtocolError::Error("There is no wallet open".to_string())
+}
+
+fn save_account(entry: WalletEntry, node: &Arc<Node>) -> Result<(), ProtocolError> {
+    match node.open_wallet.write()?.as_mut() {
+        Some(wallet) => wallet.add_account(entry),
+        None => Ok(()),
+    }
+}
+
+fn set_label(key: String, label: String, node: &Arc<Node>) -> Result<(), ProtocolError> {
+    match node.open_wallet.write()?.as_mut() {
+        Some(wallet) => wallet.set_label(&key, &label),
+        None => Ok(()),
+    }
+}
+
+fn remove_address(addr: String, node: &Arc<Node>) -> Result<(), ProtocolError> {
+    node.forget_address(&addr)?;
+    match node.open_wallet.write()?.as_mut() {
+        Some(wallet) => wallet.remove_account(&addr),
+        None => Ok(()),
+    }
+}
+
 fn add_address(addr: String, node: &Arc<Node>) -> Result<(), ProtocolError> {
-    node.wallet_addresses.write()?.push(addr.clone());
+    let mut addresses = node.wallet_addresses.write()?;
+    if addresses.contains(&addr) {
+        return Ok(());
+    }
+    addresses.push(addr.clone());
+    drop(addresses);
     let pkhash = crate::utils::bitcoin_address_to_pkhash(&addr)?;
     let chain = node.blockchain.lock()?;
-
     let history = chain.get_tx_history(pkhash.clone());
     let balance = chain.utxo.get_balance(pkhash);
+    // The pending effects below lock the blockchain again
+    drop(chain);
 
-    node.sender
-        .send(NodeApi::Balance(balance, addr.clone()))
-        .map_err(|_| ProtocolError::Error("Wallet sender error".to_string()))?;
+    node.sender.send(NodeApi::Balance(balance, addr.clone()))?;
 
-    node.sender
-        .send(NodeApi::History(history, addr.clone()))
-        .map_err(|_| ProtocolError::Error("Wallet sender error".to_string()))?;
-
-    let mempool = node.mempool.read()?;
-    for tx in mempool.values() {
-        let mut is_spent = false;
-        for out in &tx.tx_out {
-            if PubKeyScript::can_be_spent_by_address(&out.pk_script, &addr)? {
-                node.sender
-                    .send(NodeApi::AddPendingBalance(out.value, addr.to_string()))
-                    .map_err(|_| ProtocolError::Error("Wallet sender error".to_string()))?;
-
-                is_spent = true;
-            };
-        }
+    node.sender.send(NodeApi::History(history, addr.clone()))?;
+
+    get_coins(addr.clone(), node)?;
 
-        if is_spent {
-            let transaction = Tx::from_raw_tx(&tx);
+    let mempool_txs: Vec<Arc<RawTransaction>> = node.mempool.read()?.values().cloned().collect();
+    for tx in mempool_txs {
+        let effects = node.add_pending_effects(&tx, std::slice::from_ref(&addr))?;
+        if effects.is_empty() {
+            continue;
+        }
 
-            let payer_addr = node
-                .blockchain
-                .lock()?
-                .utxo
-                .get_outpoint_address(&transaction.tx_in[0].previous_output);
+        let transaction = node.tx_with_fee(&tx)?;
+        let payer_addr = node
+            .blockchain
+            .lock()?
+            .utxo
+            .get_outpoint_address(&transaction.tx_in[0].previous_output);
 
-            node.sender
-                .send(NodeApi::NewTx(transaction, payer_addr, addr.to_string()))
-                .map_err(|_| ProtocolError::Error("Wallet sender error".to_string()))?;
+        node.sender
+            .send(NodeApi::NewTx(transaction, payer_addr, addr.to_string()))?;
 
-            node.wallet_txs
-                .write()?
-                .insert(tx.get_tx_id(), addr.to_string());
-        }
+        node.wallet_txs
+            .write()?
+            .insert(tx.get_tx_id(), addr.to_string());
     }
 
     Ok(())
@@ -0,0 +1,103 @@
+use crate::{
+    blockchain::history::{HistoryEntry, TxDirection},
+    constants::ADDRESS_REUSE_WARNING,
+    protocol_error::ProtocolError,
+    raw_transaction::RawTransaction,
+    script::PubKeyScript,
+};
+
+/// Privacy problem in the use of a wallet address, every use links more payments to its owner.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WalletWarning {
+    /// The address received this many confirmed transactions
+    AddressReuse(usize),
+    /// A payment of the address sent its change back to it
+    ChangeToSameAddress,
+}
+
+/// Warns when the address received `ADDRESS_REUSE_WARNING` transactions or more.
+pub fn reuse_warning(history: &[HistoryEntry]) -> Option<WalletWarning> {
+    let received = history
+        .iter()
+        .filter(|entry| entry.direction == TxDirection::Received)
+        .count();
+    if received < ADDRESS_REUSE_WARNING {
+        return None;
+    }
+    Some(WalletWarning::AddressReuse(received))
+}
+
+/// Warns when a payment to another address has an output back to the payer.
+pub fn change_warning(
+    tx: &RawTransaction,
+    payer_address: &str,
+    payee_address: &str,
+) -> Result<Option<WalletWarning>, ProtocolError> {
+    if payer_address == payee_address {
+        return Ok(None);
+    }
+    let payer = payer_address.to_string();
+    for txout in tx.tx_out.iter() {
+        if PubKeyScript::can_be_spent_by_address(&txout.pk_script, &payer)? {
+            return Ok(Some(WalletWarning::ChangeToSameAddress));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        blockchain::txs::Tx,
+        raw_transaction::{Outpoint, TxIn, TxOut},
+        utils::bitcoin_address_to_pkhash,
+    };
+
+    const PAYER: &str = "mnJvq7mbGiPNNhUne4FAqq27Q8xZrAsVun";
+    const PAYEE: &str = "mgkPm4UebNCJSRGs2Kp2aVE69G8hUEf4d7";
+
+    fn payment(change: bool) -> RawTransaction {
+        let input = TxIn::new(Outpoint::new([1; 32], 0), vec![]);
+        let mut outputs = vec![TxOut::new(
+            1000,
+            PubKeyScript::from_address(PAYEE).unwrap().to_vec(),
+        )];
+        if change {
+            let pkhash = bitcoin_address_to_pkhash(PAYER).unwrap();
+            outputs.push(TxOut::new(
+                500,
+                PubKeyScript::P2PKH(pkhash.to_vec()).to_vec(),
+            ));
+        }
+        RawTransaction::new(vec![input], outputs)
+    }
+
+    #[test]
+    fn test_change_back_to_the_payer_is_warned() {
+        assert_eq!(
+            change_warning(&payment(true), PAYER, PAYEE).unwrap(),
+            Some(WalletWarning::ChangeToSameAddress)
+        );
+        assert_eq!(change_warning(&payment(false), PAYER, PAYEE).unwrap(), None);
+        // Paying to itself isn't change
+        assert_eq!(change_warning(&payment(true), PAYER, PAYER).unwrap(), None);
+    }
+
+    #[test]
+    fn test_reuse_is_warned_after_enough_received_transactions() {
+        let tx = Tx::from_raw_tx(&payment(false));
+        let received = HistoryEntry::new(tx.clone(), 0, 1000, 0, None, 1);
+        let sent = HistoryEntry::new(tx, 0, 0, 1000, None, 1);
+
+        let mut history = vec![sent.clone(); ADDRESS_REUSE_WARNING];
+        history.extend(vec![received.clone(); ADDRESS_REUSE_WARNING - 1]);
+        assert_eq!(reuse_warning(&history), None);
+
+        history.push(received);
+        assert_eq!(
+            reuse_warning(&history),
+            Some(WalletWarning::AddressReuse(ADDRESS_REUSE_WARNING))
+        );
+    }
+}
@@ -0,0 +1,124 @@
+use std::{
+    panic::{catch_unwind, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+};
+
+use crate::{log_file::log_error, protocol_error::ProtocolError};
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// Fixed number of threads that run the jobs, like reading the messages of a peer, one at
+/// a time each. A job queued while every worker is busy waits for one of them to finish,
+/// so the threads never go over the size of the pool.
+#[derive(Debug)]
+pub struct WorkerPool {
+    sender: Mutex<Option<Sender<Job>>>,
+    workers: Mutex<Vec<JoinHandle<()>>>,
+    busy: Arc<AtomicUsize>,
+    size: usize,
+}
+
+impl WorkerPool {
+    pub fn new(size: usize) -> WorkerPool {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let busy = Arc::new(AtomicUsize::new(0));
+        let workers = (0..size)
+            .map(|_| spawn_worker(Arc::clone(&receiver), Arc::clone(&busy)))
+            .collect();
+
+        WorkerPool {
+            sender: Mutex::new(Some(sender)),
+            workers: Mutex::new(workers),
+            busy,
+            size,
+        }
+    }
+
+    /// Queues the job for the first free worker.
+    pub fn execute(&self, job: impl FnOnce() + Send + 'static) -> Result<(), ProtocolError> {
+        match self.sender.lock()?.as_ref() {
+            Some(sender) => sender
+                .send(Box::new(job))
+                .map_err(|_| ProtocolError::Error("The worker pool is stopped".to_string())),
+            None => Err(ProtocolError::Error(
+                "The worker pool is stopped".to_string(),
+            )),
+        }
+    }
+
+    /// Workers running a job.
+    pub fn busy(&self) -> usize {
+        self.busy.load(Ordering::Relaxed)
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Stops taking jobs and waits for the queued ones to finish.
+    pub fn join(&self) -> Result<(), ProtocolError> {
+        self.sender.lock()?.take();
+        let workers = std::mem::take(&mut *self.workers.lock()?);
+        for worker in workers {
+            let _ = worker.join();
+        }
+        Ok(())
+    }
+}
+
+/// Takes jobs until the pool is stopped. A job that panics doesn't take the worker down.
+fn spawn_worker(receiver: Arc<Mutex<Receiver<Job>>>, busy: Arc<AtomicUsize>) -> JoinHandle<()> {
+    thread::spawn(move || loop {
+        // The lock is released before running the job, so the other workers take the next ones
+        let job = match receiver.lock() {
+            Ok(receiver) => match receiver.recv() {
+                Ok(job) => job,
+                Err(_) => return,
+            },
+            Err(_) => return,
+        };
+
+        busy.fetch_add(1, Ordering::Relaxed);
+        if catch_unwind(AssertUnwindSafe(job)).is_err() {
+            log_error("A worker job panicked".to_string());
+        }
+        busy.fetch_sub(1, Ordering::Relaxed);
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{sync::Barrier, time::Duration};
+
+    #[test]
+    fn test_jobs_wait_for_a_free_worker() {
+        let pool = WorkerPool::new(2);
+        let barrier = Arc::new(Barrier::new(3));
+        for _ in 0..2 {
+            let barrier = Arc::clone(&barrier);
+            pool.execute(move || {
+                barrier.wait();
+            })
+            .unwrap();
+        }
+        let (done, finished) = mpsc::channel();
+        pool.execute(move || done.send(()).unwrap()).unwrap();
+
+        // Both workers are blocked, so the third job is still queued
+        assert!(finished.recv_timeout(Duration::from_millis(100)).is_err());
+        assert_eq!(pool.busy(), 2);
+        barrier.wait();
+        finished.recv_timeout(Duration::from_secs(5)).unwrap();
+
+        pool.execute(|| panic!("job panics")).unwrap();
+        pool.join().unwrap();
+        assert!(pool.execute(|| {}).is_err());
+    }
+}
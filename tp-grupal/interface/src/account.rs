@@ -1,17 +1,47 @@
 use std::collections::HashMap;
 
-use btc_node::blockchain::txs::Tx;
+use btc_node::{
+    api::WalletSnapshot,
+    blockchain::{history::HistoryEntry, txs::Tx},
+    coin_control::{Coin, CoinId},
+};
 
 pub struct Account {
     pub address: String,
     pub wif: String, //private_key
     pub balance: i64,
     pub pending_balance: i64,
-    pub transactions: Vec<Tx>,
+    /// Confirmed unspent outputs, as reported by the node
+    pub utxo_count: usize,
+    pub coins: Vec<Coin>,
+    /// Coins hand-picked to pay the next payment
+    pub selected_coins: Vec<CoinId>,
+    pub transactions: Vec<HistoryEntry>,
     pub pending_tx: HashMap<[u8; 32], (Tx, i64, String, String)>,
     pub name: String,
 }
 
+/// Update of the state of one account, taken from a node message.
+pub enum AccountEvent {
+    Balance(i64),
+    /// Net effect of an unconfirmed transaction on the balance
+    PendingDelta(i64),
+    /// A pending delta that got confirmed
+    ConfirmedDelta(i64),
+    /// Confirmed and pending balances recomputed by the node
+    Reconciled(i64, i64),
+    UtxoCount(usize),
+    Coins(Vec<Coin>),
+    History(Vec<HistoryEntry>),
+    /// Unconfirmed transaction with its payer and payee
+    PendingTx(Tx, String, String),
+    /// The pending transaction with the txid was replaced by a new one
+    ReplacedTx([u8; 32], Tx),
+    ConfirmedTx([u8; 32]),
+    /// Whole state of the account, replaces the one built from the events
+    Snapshot(WalletSnapshot),
+}
+
 impl Account {
     pub fn new(address: String, wif: String, balance: i64, name: String) -> Account {
         Account {
@@ -19,9 +49,109 @@ impl Account {
             wif,
             balance,
             pending_balance: 0,
+            utxo_count: 0,
+            coins: Vec::new(),
+            selected_coins: Vec::new(),
             transactions: Vec::new(),
             pending_tx: HashMap::new(),
             name,
         }
     }
+
+    pub fn total_balance(&self) -> i64 {
+        self.balance + self.pending_balance
+    }
+
+    /// Confirmed balance minus what the pending payments already spend and the frozen coins.
+    /// Pending incoming funds can't be spent yet. With hand-picked coins it is their value.
+    pub fn spendable_balance(&self) -> i64 {
+        if !self.selected_coins.is_empty() {
+            return self.selected_value();
+        }
+        let frozen: i64 = self
+            .coins
+            .iter()
+            .filter(|coin| coin.frozen)
+            .map(|coin| coin.value)
+            .sum();
+        self.balance + self.pending_balance.min(0) - frozen
+    }
+
+    pub fn selected_value(&self) -> i64 {
+        self.coins
+            .iter()
+            .filter(|coin| self.is_selected(coin))
+            .map(|coin| coin.value)
+            .sum()
+    }
+
+    pub fn is_selected(&self, coin: &Coin) -> bool {
+        self.selected_coins.contains(&(coin.txid, coin.index))
+    }
+
+    /// Adds or removes the coin from the selection, frozen coins can't be selected.
+    pub fn select_coin(&mut self, id: CoinId, selected: bool) {
+        self.selected_coins.retain(|selected_id| *selected_id != id);
+        let selectable = self
+            .coins
+            .iter()
+            .any(|coin| (coin.txid, coin.index) == id && !coin.frozen);
+        if selected && selectable {
+            self.selected_coins.push(id);
+        }
+    }
+
+    pub fn apply(&mut self, event: AccountEvent) {
+        match event {
+            AccountEvent::Balance(balance) => self.balance = balance,
+            AccountEvent::PendingDelta(delta) => self.pending_balance += delta,
+            AccountEvent::ConfirmedDelta(delta) => {
+                self.pending_balance -= delta;
+                self.balance += delta;
+            }
+            AccountEvent::Reconciled(balance, pending) => {
+                self.balance = balance;
+                self.pending_balance = pending;
+            }
+            AccountEvent::UtxoCount(count) => self.utxo_count = count,
+            AccountEvent::Coins(coins) => {
+                self.coins = coins;
+                let coins = &self.coins;
+                self.selected_coins.retain(|id| {
+                    coins
+                        .iter()
+                        .any(|coin| (coin.txid, coin.index) == *id && !coin.frozen)
+                });
+            }
+            AccountEvent::History(history) => self.transactions = history,
+            AccountEvent::PendingTx(tx, payer, payee) => {
+                let amount = tx.value_payed_to_address(&payee);
+                self.pending_tx.insert(tx.tx_id, (tx, amount, payer, payee));
+            }
+            AccountEvent::ReplacedTx(old_txid, tx) => {
+                if let Some((_, _, payer, payee)) = self.pending_tx.remove(&old_txid) {
+                    let amount = tx.value_payed_to_address(&payee);
+                    self.pending_tx.insert(tx.tx_id, (tx, amount, payer, payee));
+                }
+            }
+            AccountEvent::ConfirmedTx(txid) => {
+                self.pending_tx.remove(&txid);
+            }
+            AccountEvent::Snapshot(snapshot) => {
+                self.balance = snapshot.balance;
+                self.pending_balance = snapshot.pending_balance;
+                self.utxo_count = snapshot
+                    .coins
+                    .iter()
+                    .filter(|coin| coin.confirmations > 0)
+                    .count();
+                self.apply(AccountEvent::Coins(snapshot.coins));
+                self.transactions = snapshot.history;
+                self.pending_tx.clear();
+                for (tx, payer, payee) in snapshot.pending_txs {
+                    self.apply(AccountEvent::PendingTx(tx, payer, payee));
+                }
+            }
+        }
+    }
 }
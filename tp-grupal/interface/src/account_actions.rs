@@ -0,0 +1,241 @@
+use std::rc::Rc;
+
+use btc_node::{
+    api::{RequestSender, WalletApi},
+    keys::{generate_key, NewKey},
+    message_header::network,
+    wallet_file::WalletEntry,
+};
+use gtk::{
+    ffi::GTK_MESSAGE_WARNING, prelude::*, Builder, Button, CheckButton, ComboBoxText, Entry,
+};
+
+use crate::{
+    create_notification_window,
+    i18n::{tr, tr_fmt},
+    render,
+    wallet_controller::{empty_view, SharedWallet},
+};
+
+/// Renames or removes the account selected in the wallets combo box.
+pub fn account_buttons_on_clicked(builder: &Builder, wallet: &SharedWallet, sender: RequestSender) {
+    let rename_button: Button = builder
+        .object("accounts_page_rename_button")
+        .expect("Failed to retrieve rename account button");
+    let remove_button: Button = builder
+        .object("accounts_page_remove_button")
+        .expect("Failed to retrieve remove account button");
+
+    let rename_builder = builder.clone();
+    let rename_wallet = Rc::clone(wallet);
+    let rename_sender = sender.clone();
+    rename_button
+        .connect_clicked(move |_| rename_account(&rename_builder, &rename_wallet, &rename_sender));
+
+    let builder = builder.clone();
+    let wallet = Rc::clone(wallet);
+    remove_button.connect_clicked(move |_| remove_account(&builder, &wallet, &sender));
+}
+
+fn selected_account(builder: &Builder) -> Option<(ComboBoxText, u32, String)> {
+    let combo_box: ComboBoxText = builder
+        .object("wallets_combo_box")
+        .expect("Failed to get wallet combobox");
+    let index = combo_box.active();
+    let name = combo_box.active_text();
+
+    match (index, name) {
+        (Some(index), Some(name)) => Some((combo_box, index, name.to_string())),
+        _ => {
+            create_notification_window(
+                gtk::MessageType::__Unknown(GTK_MESSAGE_WARNING),
+                "Warning",
+                "You have to select an account first",
+            );
+            None
+        }
+    }
+}
+
+fn rename_account(builder: &Builder, wallet: &SharedWallet, sender: &RequestSender) {
+    let (combo_box, index, name) = match selected_account(builder) {
+        Some(selected) => selected,
+        None => return,
+    };
+    let new_name = match ask_new_name(builder, &name) {
+        Some(new_name) if new_name != name => new_name,
+        _ => return,
+    };
+    if new_name.is_empty() {
+        create_notification_window(
+            gtk::MessageType::__Unknown(GTK_MESSAGE_WARNING),
+            "Warning",
+            "Name is missing",
+        );
+        return;
+    }
+    if wallet.borrow().name_is_used(&new_name) {
+        create_notification_window(
+            gtk::MessageType::__Unknown(GTK_MESSAGE_WARNING),
+            "Warning",
+            "Account name is already used, pick another one",
+        );
+        return;
+    }
+
+    let entry = wallet
+        .borrow_mut()
+        .rename_account(&name, &new_name)
+        .map(|account| WalletEntry {
+            name: account.name.clone(),
+            address: account.address.clone(),
+            wif: account.wif.clone(),
+        });
+    if let Some(entry) = entry {
+        // Replacing the text runs the changed handler, which borrows the wallet again
+        ComboBoxTextExt::remove(&combo_box, index as i32);
+        combo_box.insert_text(index as i32, &new_name);
+        combo_box.set_active(Some(index));
+        sender.send(WalletApi::SaveAccount(entry)).unwrap();
+    }
+}
+
+fn ask_new_name(builder: &Builder, name: &str) -> Option<String> {
+    let parent: gtk::Window = builder.object("app").expect("Failed to get window");
+
+    let dialog = gtk::Dialog::with_buttons(
+        Some(&tr("Rename account")),
+        Some(&parent),
+        gtk::DialogFlags::MODAL,
+        &[
+            (&tr("Rename"), gtk::ResponseType::Accept),
+            (&tr("Cancel"), gtk::ResponseType::Cancel),
+        ],
+    );
+    let entry = Entry::new();
+    entry.set_text(name);
+    dialog.content_area().add(&entry);
+    dialog.show_all();
+
+    let response = dialog.run();
+    let new_name = entry.text().trim().to_string();
+    dialog.close();
+
+    match response {
+        gtk::ResponseType::Accept => Some(new_name),
+        _ => None,
+    }
+}
+
+fn remove_account(builder: &Builder, wallet: &SharedWallet, sender: &RequestSender) {
+    let (combo_box, index, name) = match selected_account(builder) {
+        Some(selected) => selected,
+        None => return,
+    };
+    if !confirm_removal(builder, &name) {
+        return;
+    }
+
+    let address = wallet.borrow_mut().remove_account(&name);
+    if let Some(address) = address {
+        ComboBoxTextExt::remove(&combo_box, index as i32);
+        let remaining = combo_box
+            .model()
+            .map_or(0, |model| model.iter_n_children(None));
+        if remaining > 0 {
+            combo_box.set_active(Some(0));
+        } else {
+            render(builder, empty_view());
+        }
+        sender.send(WalletApi::RemoveAddress(address)).unwrap();
+    }
+}
+
+fn confirm_removal(builder: &Builder, name: &str) -> bool {
+    let parent: gtk::Window = builder.object("app").expect("Failed to get window");
+
+    let dialog = gtk::MessageDialog::new(
+        Some(&parent),
+        gtk::DialogFlags::MODAL,
+        gtk::MessageType::Question,
+        gtk::ButtonsType::YesNo,
+        "",
+    );
+    dialog.set_text(Some(&tr_fmt("Remove the account {}?", &[name.to_string()])));
+    dialog.set_secondary_text(Some(&tr(
+        "The node stops tracking its address and it is removed from the open wallet",
+    )));
+
+    let response = dialog.run();
+    dialog.close();
+    response == gtk::ResponseType::Yes
+}
+
+/// Generates a keypair for a new account, which is created once its WIF is backed up.
+pub fn generate_account_button_on_clicked(builder: &Builder) {
+    let generate_button: Button = builder
+        .object("accounts_page_generate_button")
+        .expect("Failed to retrieve generate account button");
+    let create_account_button: Button = builder
+        .object("accounts_page_frame1_button")
+        .expect("Failed to retrieve create button.");
+    let private_key_entry: Entry = builder
+        .object("private_key_row_entry")
+        .expect("Failed to retrieve private key entry");
+    let address_entry: Entry = builder
+        .object("public_key_row_entry")
+        .expect("Failed to retrieve public key entry");
+
+    let builder = builder.clone();
+    generate_button.connect_clicked(move |_| {
+        // Addresses of other networks would be rejected by the node
+        let key = generate_key(network());
+        if !confirm_backup(&builder, &key) {
+            return;
+        }
+
+        // Creating it validates the name and registers the address like an imported account
+        private_key_entry.set_text(&key.wif);
+        address_entry.set_text(&key.address);
+        create_account_button.clicked();
+    });
+}
+
+fn confirm_backup(builder: &Builder, key: &NewKey) -> bool {
+    let parent: gtk::Window = builder.object("app").expect("Failed to get window");
+
+    let dialog = gtk::Dialog::with_buttons(
+        Some(&tr("New account")),
+        Some(&parent),
+        gtk::DialogFlags::MODAL,
+        &[
+            (&tr("Create"), gtk::ResponseType::Accept),
+            (&tr("Cancel"), gtk::ResponseType::Cancel),
+        ],
+    );
+    let content = dialog.content_area();
+    for (title, value) in [("Private key (WIF)", &key.wif), ("Address", &key.address)] {
+        content.add(&gtk::Label::new(Some(&tr(title))));
+        let label = gtk::Label::new(Some(value));
+        label.set_selectable(true);
+        content.add(&label);
+    }
+    let warning = gtk::Label::new(Some(&tr(
+        "Anyone with the private key can spend the funds of the account, and without it they are lost",
+    )));
+    warning.set_line_wrap(true);
+    content.add(&warning);
+
+    let backup_check = CheckButton::with_label(&tr("I have saved the private key"));
+    content.add(&backup_check);
+    dialog.set_response_sensitive(gtk::ResponseType::Accept, false);
+    let backup_dialog = dialog.clone();
+    backup_check.connect_toggled(move |check| {
+        backup_dialog.set_response_sensitive(gtk::ResponseType::Accept, check.is_active())
+    });
+    dialog.show_all();
+
+    let response = dialog.run();
+    dialog.close();
+    response == gtk::ResponseType::Accept && backup_check.is_active()
+}
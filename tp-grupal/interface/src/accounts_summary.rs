@@ -0,0 +1,70 @@
+use gtk::{prelude::*, Builder, ComboBoxText, ListStore, TreeView};
+
+use crate::wallet_controller::SummaryRow;
+
+pub fn re_set_accounts_summary(builder: &Builder, rows: &[SummaryRow]) {
+    let summary_list_store: ListStore = builder
+        .object("accounts_summary")
+        .expect("Failed to retrieve accounts summary list store");
+    let combo_box: ComboBoxText = builder
+        .object("wallets_combo_box")
+        .expect("Failed to get wallet combobox");
+    let tree_view: TreeView = builder
+        .object("accounts_summary_tree_view")
+        .expect("Failed to get accounts summary tree view");
+
+    let active = combo_box.active_text().map(|name| name.to_string());
+    summary_list_store.clear();
+    for (name, address, balance, pending, utxos) in rows.iter() {
+        let array_of_data: &[(u32, &dyn ToValue)] = &[
+            (0, name),
+            (1, address),
+            (2, &balance.to_string()),
+            (3, &pending.to_string()),
+            (4, &utxos.to_string()),
+        ];
+        let iter = summary_list_store.insert_with_values(None, array_of_data);
+        if active.as_ref() == Some(name) {
+            tree_view.selection().select_iter(&iter);
+        }
+    }
+}
+
+/// Selecting an account in the summary selects it in the wallets combo box,
+/// which renders it and selects the row back.
+pub fn summary_selection_on_changed(builder: &Builder) {
+    let tree_view: TreeView = builder
+        .object("accounts_summary_tree_view")
+        .expect("Failed to get accounts summary tree view");
+    let combo_box: ComboBoxText = builder
+        .object("wallets_combo_box")
+        .expect("Failed to get wallet combobox");
+
+    tree_view.selection().connect_changed(move |selection| {
+        let name = match selection
+            .selected()
+            .and_then(|(model, iter)| model.value(&iter, 0).get::<String>().ok())
+        {
+            Some(name) => name,
+            None => return,
+        };
+        if combo_box.active_text().as_deref() == Some(name.as_str()) {
+            return;
+        }
+
+        let model = match combo_box.model() {
+            Some(model) => model,
+            None => return,
+        };
+        for i in 0..model.iter_n_children(None) {
+            let is_account = model
+                .iter_nth_child(None, i)
+                .and_then(|iter| model.value(&iter, 0).get::<String>().ok())
+                .is_some_and(|account| account == name);
+            if is_account {
+                combo_box.set_active(Some(i as u32));
+                return;
+            }
+        }
+    });
+}
@@ -0,0 +1,82 @@
+//! Charts drawn with text, so they fit in the labels of the Overview page.
+
+use btc_node::blockchain::stats::BlockStats;
+
+use crate::format_bytes;
+
+const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+// Blocks shown by the charts, the value labels fit about this many bars
+const CHART_BLOCKS: usize = 24;
+
+/// One bar per value scaled to the highest one, the missing values are left as spaces.
+pub fn sparkline(values: &[Option<f64>]) -> String {
+    let max = values.iter().flatten().fold(0.0, |max: f64, v| max.max(*v));
+    values
+        .iter()
+        .map(|value| match value {
+            Some(_) if max <= 0.0 => BARS[0],
+            Some(v) => BARS[((v / max) * (BARS.len() - 1) as f64).round() as usize],
+            None => ' ',
+        })
+        .collect()
+}
+
+/// Sizes of the last blocks, followed by the size of the newest one.
+pub fn block_sizes_chart(blocks: &[BlockStats]) -> String {
+    let last = match blocks.last() {
+        Some(block) => block,
+        None => return "-".to_string(),
+    };
+    let sizes: Vec<Option<f64>> = last_blocks(blocks)
+        .iter()
+        .map(|block| Some(block.size as f64))
+        .collect();
+    format!("{} {}", sparkline(&sizes), format_bytes(last.size as u64))
+}
+
+/// Median fee rates of the last blocks, followed by the one of the newest block.
+pub fn block_fees_chart(blocks: &[BlockStats]) -> String {
+    let last = match blocks.last() {
+        Some(block) => block,
+        None => return "-".to_string(),
+    };
+    let rates: Vec<Option<f64>> = last_blocks(blocks)
+        .iter()
+        .map(|block| block.median_fee_rate)
+        .collect();
+    let last_rate = match last.median_fee_rate {
+        Some(rate) => format!("{:.1} sat/vB", rate),
+        None => "-".to_string(),
+    };
+    format!("{} {}", sparkline(&rates), last_rate)
+}
+
+fn last_blocks(blocks: &[BlockStats]) -> &[BlockStats] {
+    &blocks[blocks.len().saturating_sub(CHART_BLOCKS)..]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sparkline_scales_to_the_highest_value() {
+        assert_eq!(sparkline(&[Some(0.0), Some(4.0), None, Some(8.0)]), "▁▅ █");
+        assert_eq!(sparkline(&[Some(0.0), Some(0.0)]), "▁▁");
+        assert_eq!(sparkline(&[]), "");
+
+        let blocks: Vec<BlockStats> = (0..30)
+            .map(|height| BlockStats {
+                height,
+                size: 1024,
+                median_fee_rate: Some(2.0),
+                ..Default::default()
+            })
+            .collect();
+        assert_eq!(
+            block_sizes_chart(&blocks),
+            format!("{} 1.0 KB", "█".repeat(24))
+        );
+        assert_eq!(block_fees_chart(&[]), "-");
+    }
+}
@@ -0,0 +1,131 @@
+use std::rc::Rc;
+
+use btc_node::{
+    api::{RequestSender, WalletApi},
+    coin_control::CoinId,
+    utils::bytes_to_hex_string,
+};
+use gtk::{prelude::*, Builder, Button, CellRendererToggle, Label, ListStore, TreePath};
+
+use crate::{
+    i18n::{tr, tr_fmt},
+    render,
+    wallet_controller::{CoinRow, SharedWallet},
+};
+
+fn outpoint_label(txid: &[u8; 32], index: u32) -> String {
+    format!("{}:{}", bytes_to_hex_string(txid), index)
+}
+
+pub fn re_set_coins(builder: &Builder, rows: &[CoinRow]) {
+    let coins_list_store: ListStore = builder
+        .object("coins_columns")
+        .expect("Failed to retrieve coins list store");
+    let selection_label: Label = builder
+        .object("coins_page_selection_label")
+        .expect("Failed to retrieve coins selection label");
+
+    coins_list_store.clear();
+    for (coin, selected) in rows.iter() {
+        let array_of_data: &[(u32, &dyn ToValue)] = &[
+            (0, selected),
+            (1, &coin.frozen),
+            (2, &outpoint_label(&coin.txid, coin.index)),
+            (3, &coin.value.to_string()),
+            (4, &coin.confirmations.to_string()),
+            (5, &coin.address),
+        ];
+        coins_list_store.insert_with_values(None, array_of_data);
+    }
+
+    let picked: Vec<i64> = rows
+        .iter()
+        .filter(|(_, selected)| *selected)
+        .map(|(coin, _)| coin.value)
+        .collect();
+    let text = if picked.is_empty() {
+        tr("No coins selected, the payment picks them")
+    } else {
+        tr_fmt(
+            "{} coins selected for the next payment, {} sat",
+            &[
+                picked.len().to_string(),
+                picked.iter().sum::<i64>().to_string(),
+            ],
+        )
+    };
+    selection_label.set_text(&text);
+}
+
+/// Hand-picks coins with the spend checkboxes and freezes them in the node with the frozen ones.
+pub fn coins_page_on_toggled(builder: &Builder, wallet: &SharedWallet, sender: RequestSender) {
+    let spend_toggle: CellRendererToggle = builder
+        .object("coins_spend_toggle")
+        .expect("Failed to retrieve spend toggle");
+    let freeze_toggle: CellRendererToggle = builder
+        .object("coins_freeze_toggle")
+        .expect("Failed to retrieve freeze toggle");
+    let clear_button: Button = builder
+        .object("coins_page_clear_button")
+        .expect("Failed to retrieve clear selection button");
+
+    let spend_builder = builder.clone();
+    let spend_wallet = Rc::clone(wallet);
+    spend_toggle.connect_toggled(move |_, path| {
+        let (id, selected, _) = match toggled_coin(&spend_builder, &spend_wallet, &path) {
+            Some(coin) => coin,
+            None => return,
+        };
+        let updates = spend_wallet.borrow_mut().select_coin(id, !selected);
+        render(&spend_builder, updates);
+    });
+
+    let freeze_builder = builder.clone();
+    let freeze_wallet = Rc::clone(wallet);
+    freeze_toggle.connect_toggled(move |_, path| {
+        let (id, _, frozen) = match toggled_coin(&freeze_builder, &freeze_wallet, &path) {
+            Some(coin) => coin,
+            None => return,
+        };
+        let address = match freeze_wallet.borrow().active_account() {
+            Some(account) => account.address.clone(),
+            None => return,
+        };
+        // The node answers with the coins, frozen or not
+        sender
+            .send(WalletApi::SetCoinFrozen(address, id, !frozen))
+            .unwrap();
+    });
+
+    let builder = builder.clone();
+    let wallet = Rc::clone(wallet);
+    clear_button.connect_clicked(move |_| {
+        let updates = wallet.borrow_mut().clear_coin_selection();
+        render(&builder, updates);
+    });
+}
+
+/// Coin of the row, whether it is selected and whether it is frozen.
+fn toggled_coin(
+    builder: &Builder,
+    wallet: &SharedWallet,
+    path: &TreePath,
+) -> Option<(CoinId, bool, bool)> {
+    let coins_list_store: ListStore = builder
+        .object("coins_columns")
+        .expect("Failed to retrieve coins list store");
+    let iter = coins_list_store.iter(path)?;
+    let outpoint = coins_list_store.value(&iter, 2).get::<String>().ok()?;
+
+    let wallet = wallet.borrow();
+    let account = wallet.active_account()?;
+    let coin = account
+        .coins
+        .iter()
+        .find(|coin| outpoint_label(&coin.txid, coin.index) == outpoint)?;
+    Some((
+        (coin.txid, coin.index),
+        account.is_selected(coin),
+        coin.frozen,
+    ))
+}
@@ -0,0 +1,137 @@
+use std::{fs, rc::Rc};
+
+use btc_node::utils::bytes_to_hex_string;
+use gtk::{
+    ffi::{GTK_MESSAGE_INFO, GTK_MESSAGE_WARNING},
+    prelude::*,
+    Builder, Button, FileChooserAction, FileChooserDialog,
+};
+
+use crate::{
+    create_notification_window,
+    i18n::{tr, tr_fmt},
+    wallet_controller::{HistoryRow, SharedWallet},
+};
+
+const CSV_HEADER: &str = "txid,date,direction,amount,fee,confirmations,label";
+
+/// Saves the history of the selected account, with its labels, in a CSV file.
+pub fn export_button_on_clicked(builder: &Builder, wallet: &SharedWallet) {
+    let export_button: Button = builder
+        .object("transactions_page_export_button")
+        .expect("Failed to retrieve export button");
+    let parent: gtk::Window = builder.object("app").expect("Failed to get window");
+    let wallet = Rc::clone(wallet);
+
+    export_button.connect_clicked(move |_| {
+        if wallet.borrow().active_account().is_none() {
+            create_notification_window(
+                gtk::MessageType::__Unknown(GTK_MESSAGE_WARNING),
+                "Warning",
+                "You have to select an account first",
+            );
+            return;
+        }
+        let path = match choose_csv_file(&parent) {
+            Some(path) => path,
+            None => return,
+        };
+
+        let csv = history_csv(&wallet.borrow().history_rows());
+        match fs::write(&path, csv) {
+            Ok(()) => create_notification_window(
+                gtk::MessageType::__Unknown(GTK_MESSAGE_INFO),
+                "History exported",
+                &tr_fmt("The history was saved in {}", &[path]),
+            ),
+            Err(e) => create_notification_window(
+                gtk::MessageType::__Unknown(GTK_MESSAGE_WARNING),
+                "Warning",
+                &tr_fmt("IO Error: {}", &[e.to_string()]),
+            ),
+        }
+    });
+}
+
+fn choose_csv_file(parent: &gtk::Window) -> Option<String> {
+    let dialog = FileChooserDialog::with_buttons(
+        Some(&tr("Export history")),
+        Some(parent),
+        FileChooserAction::Save,
+        &[
+            (&tr("Save"), gtk::ResponseType::Accept),
+            (&tr("Cancel"), gtk::ResponseType::Cancel),
+        ],
+    );
+    dialog.set_do_overwrite_confirmation(true);
+    dialog.set_current_name("history.csv");
+
+    let response = dialog.run();
+    let path = dialog
+        .filename()
+        .map(|path| path.to_string_lossy().to_string());
+    dialog.close();
+
+    match response {
+        gtk::ResponseType::Accept => path,
+        _ => None,
+    }
+}
+
+/// One line per transaction after the header, an unknown fee is left empty.
+pub fn history_csv(rows: &[HistoryRow]) -> String {
+    let mut csv = format!("{}\n", CSV_HEADER);
+    for (entry, label) in rows {
+        let fields = [
+            bytes_to_hex_string(&entry.tx.tx_id),
+            entry.date(),
+            entry.direction.to_string(),
+            entry.amount.to_string(),
+            entry.fee.map(|fee| fee.to_string()).unwrap_or_default(),
+            entry.confirmations.to_string(),
+            csv_field(label),
+        ];
+        csv.push_str(&fields.join(","));
+        csv.push('\n');
+    }
+    csv
+}
+
+/// Quotes the field when it has commas, quotes or line breaks.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use btc_node::{
+        blockchain::{history::HistoryEntry, txs::Tx},
+        raw_transaction::{Outpoint, RawTransaction, TxIn},
+    };
+
+    #[test]
+    fn test_history_csv_quotes_the_labels() {
+        let input = TxIn::new(Outpoint::new([1; 32], 0), vec![]);
+        let tx = Tx::from_raw_tx(&RawTransaction::new(vec![input], vec![]));
+        let txid = bytes_to_hex_string(&tx.tx_id);
+        let rows = vec![
+            (
+                HistoryEntry::new(tx.clone(), 0, 0, 1000, Some(10), 2),
+                "rent, \"march\"".to_string(),
+            ),
+            (HistoryEntry::new(tx, 0, 500, 0, None, 1), String::new()),
+        ];
+
+        let csv = history_csv(&rows);
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], CSV_HEADER);
+        assert!(lines[1].starts_with(&txid));
+        assert!(lines[1].ends_with(",Sent,-1000,10,2,\"rent, \"\"march\"\"\""));
+        assert!(lines[2].ends_with(",Received,500,,1,"));
+    }
+}
@@ -0,0 +1,601 @@
+use std::{env, sync::OnceLock};
+
+use btc_node::protocol_error::ProtocolError;
+use gtk::{prelude::*, Builder, Button, Label, MenuItem, TreeViewColumn, Window};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lang {
+    En,
+    Es,
+}
+
+/// Every text of the interface in English, the key, and in Spanish.
+const CATALOG: &[(&str, &str)] = &[
+    // Menus and pages
+    ("_File", "_Archivo"),
+    ("_Settings", "_Configuración"),
+    ("_Window", "_Ventana"),
+    ("_Help", "A_yuda"),
+    ("Bitcoin Node", "Nodo Bitcoin"),
+    ("Overview", "Resumen"),
+    ("Send", "Enviar"),
+    ("Accounts", "Cuentas"),
+    ("Transactions", "Transacciones"),
+    ("Mempool", "Mempool"),
+    ("Coins", "Monedas"),
+    ("Wallet", "Billetera"),
+    ("Balances", "Saldos"),
+    ("Network", "Red"),
+    ("Pending transactions", "Transacciones pendientes"),
+    ("Add account", "Agregar cuenta"),
+    // Labels and buttons
+    ("Available:", "Disponible:"),
+    ("Pending:", "Pendiente:"),
+    ("Total:", "Total:"),
+    ("Block height:", "Altura de bloque:"),
+    ("Last block:", "Último bloque:"),
+    ("Peers:", "Pares:"),
+    ("Mempool:", "Mempool:"),
+    ("Synced:", "Sincronizado:"),
+    ("Traffic:", "Tráfico:"),
+    ("Supply:", "Emisión:"),
+    ("Block sizes:", "Tamaño de bloques:"),
+    ("Block fees:", "Comisiones de bloques:"),
+    ("Wallet:", "Billetera:"),
+    ("Name:", "Nombre:"),
+    ("Address:", "Dirección:"),
+    ("Wif:", "Wif:"),
+    ("Pay to:", "Pagar a:"),
+    ("Amount:", "Monto:"),
+    ("Fee Amount:", "Comisión:"),
+    ("Satoshis", "Satoshis"),
+    ("sat", "sat"),
+    ("Create", "Crear"),
+    ("Pay", "Pagar"),
+    ("Send max", "Enviar máximo"),
+    ("Suggest fee", "Sugerir comisión"),
+    ("Remaining after sending: {} sat", "Restante después del envío: {} sat"),
+    (
+        "Exceeds the spendable balance by {} sat",
+        "Supera el saldo disponible en {} sat",
+    ),
+    ("Pause", "Pausar"),
+    ("Resume", "Reanudar"),
+    ("Refresh", "Actualizar"),
+    ("Reload node configuration", "Recargar la configuración del nodo"),
+    ("Configuration reloaded", "Configuración recargada"),
+    ("No setting changed", "Ningún ajuste cambió"),
+    ("Open wallet...", "Abrir billetera..."),
+    ("Open wallet", "Abrir billetera"),
+    ("Close wallet", "Cerrar billetera"),
+    ("Backup wallet...", "Respaldar billetera..."),
+    ("Backup wallet", "Respaldar billetera"),
+    ("Restore wallet...", "Restaurar billetera..."),
+    ("Restore wallet", "Restaurar billetera"),
+    ("Wallet backed up", "Billetera respaldada"),
+    ("The wallet was saved to {}", "La billetera se guardó en {}"),
+    ("Wallet restored", "Billetera restaurada"),
+    ("{} accounts were added", "Se agregaron {} cuentas"),
+    ("There is no wallet open", "No hay una billetera abierta"),
+    ("Rename account...", "Renombrar cuenta..."),
+    ("Rename account", "Renombrar cuenta"),
+    ("Rename", "Renombrar"),
+    ("Remove account", "Eliminar cuenta"),
+    ("Payment requests...", "Solicitudes de pago..."),
+    ("Payment requests", "Solicitudes de pago"),
+    ("Amount (sat)", "Monto (sat)"),
+    ("Expires in (minutes)", "Vence en (minutos)"),
+    ("Close", "Cerrar"),
+    ("Request {}", "Solicitud {}"),
+    ("Payment request paid", "Solicitud de pago cobrada"),
+    ("Payment request expired", "Solicitud de pago vencida"),
+    ("{} received {} sat", "{} recibió {} sat"),
+    ("{} received {} of {} sat", "{} recibió {} de {} sat"),
+    ("Prove address ownership...", "Probar propiedad de la dirección..."),
+    ("Verify ownership proof...", "Verificar prueba de propiedad..."),
+    ("Prove address ownership", "Probar propiedad de la dirección"),
+    ("Verify ownership proof", "Verificar prueba de propiedad"),
+    ("Ownership proof", "Prueba de propiedad"),
+    ("Accept", "Aceptar"),
+    ("Message", "Mensaje"),
+    ("Proof", "Prueba"),
+    ("Message:", "Mensaje:"),
+    ("The proof is valid", "La prueba es válida"),
+    ("The proof is not valid", "La prueba no es válida"),
+    ("Generate new account...", "Generar cuenta nueva..."),
+    ("New account", "Cuenta nueva"),
+    ("Private key (WIF)", "Clave privada (WIF)"),
+    (
+        "Anyone with the private key can spend the funds of the account, and without it they are lost",
+        "Cualquiera con la clave privada puede gastar los fondos de la cuenta, y sin ella se pierden",
+    ),
+    ("I have saved the private key", "Guardé la clave privada"),
+    ("No wallet open", "Ninguna billetera abierta"),
+    ("Wallet: {}", "Billetera: {}"),
+    ("Open", "Abrir"),
+    ("Cancel", "Cancelar"),
+    ("Inspect", "Inspeccionar"),
+    ("Bump fee", "Aumentar comisión"),
+    ("Bump", "Aumentar"),
+    ("Pay fee with a child...", "Pagar comisión con una hija..."),
+    ("Pay fee with a child", "Pagar comisión con una hija"),
+    ("Yes", "Sí"),
+    ("No", "No"),
+    ("Unknown", "Desconocido"),
+    ("Preferences...", "Preferencias..."),
+    ("Settings", "Configuración"),
+    ("Apply", "Aplicar"),
+    ("Dark theme", "Tema oscuro"),
+    ("Font scale", "Escala de la fuente"),
+    ("Notifications", "Notificaciones"),
+    ("New transactions", "Transacciones nuevas"),
+    ("Sent payments", "Pagos enviados"),
+    ("Bumped fees", "Comisiones aumentadas"),
+    ("Download progress", "Progreso de la descarga"),
+    ("Privacy warnings", "Avisos de privacidad"),
+    ("Popup", "Ventana"),
+    ("Banner", "Aviso"),
+    ("None", "Ninguna"),
+    ("{} new notifications", "{} notificaciones nuevas"),
+    ("Block explorer URL", "URL del explorador de bloques"),
+    ("Copy tx ID", "Copiar ID de tx"),
+    ("Copy address", "Copiar dirección"),
+    ("Open in block explorer", "Abrir en el explorador de bloques"),
+    ("View details", "Ver detalles"),
+    ("Edit label...", "Editar etiqueta..."),
+    ("Label address...", "Etiquetar dirección..."),
+    ("Edit label", "Editar etiqueta"),
+    ("Save", "Guardar"),
+    ("Empty to remove the label", "Vacía para quitar la etiqueta"),
+    ("Export CSV", "Exportar CSV"),
+    ("Export history", "Exportar historial"),
+    // Table columns
+    ("Tx ID", "ID de tx"),
+    ("Date", "Fecha"),
+    ("Direction", "Dirección"),
+    ("Amount", "Monto"),
+    ("Fee", "Comisión"),
+    ("Confirmations", "Confirmaciones"),
+    ("Label", "Etiqueta"),
+    ("Payer", "Pagador"),
+    ("Payee", "Beneficiario"),
+    ("Size", "Tamaño"),
+    ("Fee rate", "Tasa de comisión"),
+    ("Age", "Antigüedad"),
+    ("Value", "Valor"),
+    ("Name", "Nombre"),
+    ("Address", "Dirección"),
+    ("Confirmed", "Confirmado"),
+    ("Pending", "Pendiente"),
+    ("Replaceable", "Reemplazable"),
+    ("Received", "Recibido"),
+    ("Expires", "Vence"),
+    ("Status", "Estado"),
+    ("Paid", "Cobrada"),
+    ("Expired", "Vencida"),
+    ("UTXOs", "UTXOs"),
+    ("Spend", "Gastar"),
+    ("Frozen", "Congelada"),
+    ("Outpoint", "Salida"),
+    // Sync status
+    ("Connecting to peers...", "Conectando con los pares..."),
+    ("Downloading Blocks...", "Descargando bloques..."),
+    ("Download paused", "Descarga pausada"),
+    ("Syncing headers {}/{}...", "Sincronizando encabezados {}/{}..."),
+    ("Syncing headers...", "Sincronizando encabezados..."),
+    ("Synced", "Sincronizado"),
+    ("Reindexing blocks {}/{}...", "Reindexando bloques {}/{}..."),
+    ("block {} • {} txs • {}", "bloque {} • {} txs • {}"),
+    ("{} • wallet activity", "{} • actividad de la billetera"),
+    ("{} downloaded {}/{} blocks", "{} descargó {}/{} bloques"),
+    ("{} stopped at {}/{} blocks", "{} se detuvo en {}/{} bloques"),
+    ("Finished download!", "¡Descarga finalizada!"),
+    ("Finished downloading blocks", "Terminó la descarga de bloques"),
+    ("The wallet is ready to be used", "La billetera está lista para usarse"),
+    // Dialogs
+    ("Warning", "Advertencia"),
+    ("Addres to pay to is missing", "Falta la dirección a pagar"),
+    (
+        "You have to select or log an account first to pay",
+        "Tiene que seleccionar o ingresar una cuenta para pagar",
+    ),
+    ("Name is missing", "Falta el nombre"),
+    ("Adress is missing", "Falta la dirección"),
+    ("Private key is missing", "Falta la clave privada"),
+    (
+        "Account name is already used, pick another one",
+        "El nombre de cuenta ya está en uso, elija otro",
+    ),
+    ("Account is already logged in", "La cuenta ya fue ingresada"),
+    (
+        "You have to select an account first",
+        "Tiene que seleccionar una cuenta primero",
+    ),
+    ("Remove the account {}?", "¿Eliminar la cuenta {}?"),
+    (
+        "The node stops tracking its address and it is removed from the open wallet",
+        "El nodo deja de seguir su dirección y se elimina de la billetera abierta",
+    ),
+    (
+        "None of the accounts paid this transaction",
+        "Ninguna de las cuentas pagó esta transacción",
+    ),
+    ("Mempool transaction", "Transacción de la mempool"),
+    (
+        "Tx ID: {}\nVirtual size: {} vB\nFee: {}\nFee rate: {}\nAge: {}\nWallet: {}",
+        "ID de tx: {}\nTamaño virtual: {} vB\nComisión: {}\nTasa de comisión: {}\nAntigüedad: {}\nBilletera: {}",
+    ),
+    (
+        "A new transaction related to your account has arrived",
+        "Llegó una nueva transacción relacionada con su cuenta",
+    ),
+    ("Tx ID:{} '\n' Amount {} satoshi ", "ID de tx:{} '\n' Monto {} satoshi "),
+    ("Fee rate: {} sat/vB", "Tasa de comisión: {} sat/vB"),
+    (
+        "It can be replaced until it confirms",
+        "Puede ser reemplazada hasta que se confirme",
+    ),
+    ("Succesful Payment", "Pago exitoso"),
+    ("Payment correctly sent", "Pago enviado correctamente"),
+    ("Fee bumped", "Comisión aumentada"),
+    ("Tx ID: {} was replaced by {}", "La tx {} fue reemplazada por {}"),
+    ("Tx ID: {} is paid for by {}", "La tx {} es pagada por {}"),
+    (
+        "One pending transaction is now confirmed.",
+        "Una transacción pendiente fue confirmada.",
+    ),
+    ("TXID: {}", "TXID: {}"),
+    ("Address reused", "Dirección reutilizada"),
+    (
+        "The address {} received {} transactions, paying to a new address keeps your payments apart",
+        "La dirección {} recibió {} transacciones, recibir en una dirección nueva mantiene separados sus pagos",
+    ),
+    ("Change sent to the same address", "Vuelto enviado a la misma dirección"),
+    (
+        "The change of the payment went back to {}, which links the payment to your other ones",
+        "El vuelto del pago volvió a {}, lo que vincula el pago con los demás",
+    ),
+    ("Transaction details", "Detalles de la transacción"),
+    ("History exported", "Historial exportado"),
+    ("The history was saved in {}", "El historial se guardó en {}"),
+    (
+        "Tx ID: {}\nDate: {}\nAmount: {} sat\nFee: {}\nConfirmations: {}\nOutputs:\n{}",
+        "ID de tx: {}\nFecha: {}\nMonto: {} sat\nComisión: {}\nConfirmaciones: {}\nSalidas:\n{}",
+    ),
+    (
+        "Tx ID: {}\nAmount: {} sat\nPayer: {}\nPayee: {}\nOutputs:\n{}",
+        "ID de tx: {}\nMonto: {} sat\nPagador: {}\nBeneficiario: {}\nSalidas:\n{}",
+    ),
+    ("Couldn't open {}", "No se pudo abrir {}"),
+    (
+        "The amount and the fee exceed the spendable balance",
+        "El monto y la comisión superan el saldo disponible para gastar",
+    ),
+    ("Clear selection", "Limpiar selección"),
+    ("Advanced", "Avanzado"),
+    ("OP_RETURN data:", "Datos OP_RETURN:"),
+    ("Hex", "Hex"),
+    ("{} / {} bytes", "{} / {} bytes"),
+    (
+        "The data has {} bytes, at most {} fit in the transaction",
+        "Los datos tienen {} bytes, en la transacción entran a lo sumo {}",
+    ),
+    (
+        "Hex data must have an even number of digits",
+        "Los datos hex deben tener una cantidad par de dígitos",
+    ),
+    ("Hex data has invalid digits", "Los datos hex tienen dígitos inválidos"),
+    ("Lock time:", "Lock time:"),
+    ("Sequences:", "Secuencias:"),
+    ("Replaceable (RBF)", "Reemplazable (RBF)"),
+    ("Invalid sequence: {}", "Secuencia inválida: {}"),
+    ("Sighash:", "Sighash:"),
+    ("Anyone can pay", "Cualquiera puede pagar"),
+    ("Spend unconfirmed change", "Gastar cambio sin confirmar"),
+    (
+        "No coins selected, the payment picks them",
+        "No hay monedas seleccionadas, el pago las elige",
+    ),
+    (
+        "{} coins selected for the next payment, {} sat",
+        "{} monedas seleccionadas para el próximo pago, {} sat",
+    ),
+    // Errors sent by the node
+    ("IO Error: {}", "Error de E/S: {}"),
+    ("Connection failed: {}", "Falló la conexión: {}"),
+    ("Message header: {}", "Encabezado de mensaje: {}"),
+    ("Config file error: {}", "Error en el archivo de configuración: {}"),
+    ("Insufficient balance", "Saldo insuficiente"),
+    ("No coins were selected", "No se seleccionaron monedas"),
+    (
+        "The lock time hasn't been reached yet",
+        "Todavía no se alcanzó el lock time",
+    ),
+    ("Unknown sighash type", "Tipo de sighash desconocido"),
+    (
+        "SIGHASH_SINGLE needs an output for every input",
+        "SIGHASH_SINGLE necesita una salida por cada entrada",
+    ),
+    ("A selected coin is frozen", "Una moneda seleccionada está congelada"),
+    (
+        "A selected coin is not an unspent output of the account",
+        "Una moneda seleccionada no es una salida sin gastar de la cuenta",
+    ),
+    (
+        "The selected coins are not enough to pay the amount and the fee",
+        "Las monedas seleccionadas no alcanzan para pagar el monto y la comisión",
+    ),
+    ("Address is invalid", "La dirección es inválida"),
+    ("Address has invalid checksum", "La dirección tiene un checksum inválido"),
+    ("Address has invalid characters", "La dirección tiene caracteres inválidos"),
+    ("Address has an invalid length", "La dirección tiene un largo inválido"),
+    (
+        "Address is for mainnet, the node runs on testnet",
+        "La dirección es de mainnet, el nodo corre en testnet",
+    ),
+    (
+        "Address is for testnet, the node runs on mainnet",
+        "La dirección es de testnet, el nodo corre en mainnet",
+    ),
+    ("Address has an unknown prefix", "La dirección tiene un prefijo desconocido"),
+    (
+        "Private key has invalid characters",
+        "La clave privada tiene caracteres inválidos",
+    ),
+    (
+        "Private key has an invalid length",
+        "La clave privada tiene un largo inválido",
+    ),
+    (
+        "Private key has invalid checksum",
+        "La clave privada tiene un checksum inválido",
+    ),
+    (
+        "Private key is for another network",
+        "La clave privada es de otra red",
+    ),
+    (
+        "Private key has an unknown prefix",
+        "La clave privada tiene un prefijo desconocido",
+    ),
+    (
+        "Private key has an invalid compression flag",
+        "La clave privada tiene un indicador de compresión inválido",
+    ),
+    ("Private key is out of range", "La clave privada está fuera de rango"),
+    (
+        "The private key is the one of the address {}",
+        "La clave privada es la de la dirección {}",
+    ),
+    (
+        "Bech32 addresses are not supported yet",
+        "Las direcciones bech32 todavía no están soportadas",
+    ),
+    ("Invalid address checksum", "Checksum de dirección inválido"),
+    ("Error parsing address base 58", "Error leyendo la dirección en base 58"),
+    ("Error decoding the base58 address", "Error decodificando la dirección base58"),
+    ("Transaction is not valid", "La transacción no es válida"),
+    ("The proof isn't hex", "La prueba no está en hexadecimal"),
+    (
+        "Only P2PKH addresses can prove their ownership",
+        "Solo las direcciones P2PKH pueden probar su propiedad",
+    ),
+    (
+        "Transaction is not in the mempool",
+        "La transacción no está en la mempool",
+    ),
+    (
+        "Only transactions paid by this account can be bumped",
+        "Solo se puede aumentar la comisión de transacciones pagadas por esta cuenta",
+    ),
+    (
+        "The change of the transaction was already spent",
+        "El cambio de la transacción ya fue gastado",
+    ),
+    (
+        "Transaction has no change output",
+        "La transacción no tiene salida de cambio",
+    ),
+    (
+        "The transaction has no unspent output of the account",
+        "La transacción no tiene salidas sin gastar de la cuenta",
+    ),
+    (
+        "The outputs of the transaction are not enough to pay the fee",
+        "Las salidas de la transacción no alcanzan para pagar la comisión",
+    ),
+    (
+        "Change is not enough to pay the fee",
+        "El cambio no alcanza para pagar la comisión",
+    ),
+    (
+        "There are no peers to announce the tx",
+        "No hay pares a los que anunciar la tx",
+    ),
+    (
+        "No connected peer serves full blocks",
+        "Ningún par conectado sirve bloques completos",
+    ),
+    ("Invalid inventory type", "Tipo de inventario inválido"),
+    // Script debugger
+    ("Scripts", "Scripts"),
+    ("Debug", "Depurar"),
+    ("Debug scripts", "Depurar scripts"),
+    ("Back", "Atrás"),
+    ("Next", "Siguiente"),
+    ("Position", "Posición"),
+    ("Item", "Elemento"),
+    ("Top", "Tope"),
+    ("(empty)", "(vacío)"),
+    ("Invalid tx ID", "ID de tx inválido"),
+    ("Transaction not found", "Transacción no encontrada"),
+    (
+        "Load a transaction to debug its scripts",
+        "Cargue una transacción para depurar sus scripts",
+    ),
+    (
+        "The spent output is unknown, its script can't be run",
+        "La salida gastada es desconocida, no se puede ejecutar su script",
+    ),
+    (
+        "Step 0 of {}: nothing was run yet",
+        "Paso 0 de {}: todavía no se ejecutó nada",
+    ),
+    ("Step {} of {}: {}", "Paso {} de {}: {}"),
+    ("The scripts succeed", "Los scripts son válidos"),
+    ("The scripts fail: {}", "Los scripts fallan: {}"),
+    ("Public key script: {}", "Script de clave pública: {}"),
+    ("Input {}: spends {}:{}", "Entrada {}: gasta {}:{}"),
+    ("Log", "Registro"),
+    ("Time", "Hora"),
+    ("Level", "Nivel"),
+    ("Search", "Buscar"),
+    ("All levels", "Todos los niveles"),
+    ("Errors", "Errores"),
+];
+
+static LANG: OnceLock<Lang> = OnceLock::new();
+
+/// Language picked from LC_ALL, LC_MESSAGES or LANG, English unless it is Spanish.
+pub fn lang() -> Lang {
+    *LANG.get_or_init(|| {
+        let locale = ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|var| env::var(var).ok())
+            .find(|value| !value.is_empty())
+            .unwrap_or_default();
+        if locale.starts_with("es") {
+            Lang::Es
+        } else {
+            Lang::En
+        }
+    })
+}
+
+/// Translation of the English text, the text itself when the catalog doesn't have it.
+pub fn translate(lang: Lang, text: &str) -> String {
+    match lang {
+        Lang::En => text.to_string(),
+        Lang::Es => CATALOG
+            .iter()
+            .find(|(en, _)| *en == text)
+            .map(|(_, es)| es.to_string())
+            .unwrap_or_else(|| text.to_string()),
+    }
+}
+
+/// Translation of a message followed by its details, like "Transaction is not valid: ...".
+/// Only the message is translated, the details are the ones of the node.
+pub fn translate_with_details(lang: Lang, text: &str) -> String {
+    let in_catalog = CATALOG.iter().any(|(en, _)| *en == text);
+    match text.split_once(": ") {
+        Some((message, details)) if !in_catalog => {
+            format!("{}: {}", translate(lang, message), details)
+        }
+        _ => translate(lang, text),
+    }
+}
+
+pub fn tr(text: &str) -> String {
+    translate(lang(), text)
+}
+
+/// Translates the template and replaces each of its `{}` by the next argument.
+pub fn tr_fmt(template: &str, args: &[String]) -> String {
+    fill(&tr(template), args)
+}
+
+fn fill(template: &str, args: &[String]) -> String {
+    let mut parts = template.split("{}");
+    let mut text = parts.next().unwrap_or_default().to_string();
+    for (i, part) in parts.enumerate() {
+        text.push_str(args.get(i).map(|arg| arg.as_str()).unwrap_or_default());
+        text.push_str(part);
+    }
+    text
+}
+
+/// Text of an error sent by the node.
+pub fn tr_error(error: &ProtocolError) -> String {
+    match error {
+        ProtocolError::IOError(e) => tr_fmt("IO Error: {}", &[e.to_string()]),
+        ProtocolError::ConnectionError(e) | ProtocolError::BuildingError(e) => {
+            tr_fmt("Connection failed: {}", &[tr(e)])
+        }
+        ProtocolError::MessageHeaderError(e) => tr_fmt("Message header: {}", &[e.to_string()]),
+        ProtocolError::ConfigError(e) => tr_fmt("Config file error: {}", &[e.to_string()]),
+        ProtocolError::Error(e) => translate_with_details(lang(), e),
+    }
+}
+
+/// Translates the labels, buttons, menus and column titles loaded from the glade file.
+pub fn translate_widgets(builder: &Builder) {
+    if lang() == Lang::En {
+        return;
+    }
+
+    for object in builder.objects() {
+        if let Some(label) = object.downcast_ref::<Label>() {
+            label.set_text(&tr(&label.text()));
+        } else if let Some(button) = object.downcast_ref::<Button>() {
+            if let Some(text) = button.label() {
+                button.set_label(&tr(&text));
+            }
+        } else if let Some(item) = object.downcast_ref::<MenuItem>() {
+            if let Some(text) = item.label() {
+                item.set_label(&tr(&text));
+            }
+        } else if let Some(column) = object.downcast_ref::<TreeViewColumn>() {
+            if let Some(title) = column.title() {
+                column.set_title(&tr(&title));
+            }
+        } else if let Some(window) = object.downcast_ref::<Window>() {
+            if let Some(title) = window.title() {
+                window.set_title(&tr(&title));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spanish_catalog_with_english_fallback() {
+        assert_eq!(translate(Lang::Es, "Pay"), "Pagar");
+        assert_eq!(translate(Lang::En, "Pay"), "Pay");
+        assert_eq!(
+            translate(Lang::Es, "Not in the catalog"),
+            "Not in the catalog"
+        );
+    }
+
+    #[test]
+    fn test_templates_are_filled_in_order() {
+        let template = translate(Lang::Es, "Syncing headers {}/{}...");
+        let text = fill(&template, &["10".to_string(), "20".to_string()]);
+        assert_eq!(text, "Sincronizando encabezados 10/20...");
+    }
+
+    #[test]
+    fn test_only_the_message_before_the_details_is_translated() {
+        let text = translate_with_details(Lang::Es, "Transaction is not valid: input 0 failed");
+        assert_eq!(text, "La transacción no es válida: input 0 failed");
+        assert_eq!(
+            translate_with_details(Lang::Es, "Transaction is not valid"),
+            "La transacción no es válida"
+        );
+    }
+
+    #[test]
+    fn test_catalog_keys_are_unique() {
+        for (i, (en, _)) in CATALOG.iter().enumerate() {
+            assert!(
+                !CATALOG[i + 1..].iter().any(|(other, _)| other == en),
+                "{}",
+                en
+            );
+        }
+    }
+}
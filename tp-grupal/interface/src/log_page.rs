@@ -0,0 +1,149 @@
+use std::{cell::RefCell, collections::VecDeque};
+
+use btc_node::{
+    api::{RequestSender, WalletApi},
+    log_file::{LogLevel, LogLine},
+};
+use gtk::{prelude::*, Builder, ComboBoxText, ListStore, SearchEntry, Stack};
+
+use crate::i18n::tr;
+
+// Seconds between the requests of new lines while the page is shown
+const LOG_POLL_INTERVAL: u32 = 2;
+// Lines kept by the page, the node keeps about as many
+const LOG_PAGE_LINES: usize = 1000;
+const ERRORS_ONLY_ID: &str = "errors";
+
+/// Lines received from the node, the last one tells where the next request starts.
+#[derive(Debug, Default)]
+pub struct LogView {
+    lines: VecDeque<LogLine>,
+    last_seq: u64,
+}
+
+impl LogView {
+    /// Adds the lines not seen yet, returns whether there were any.
+    fn append(&mut self, lines: Vec<LogLine>) -> bool {
+        let mut added = false;
+        for line in lines {
+            if line.seq <= self.last_seq {
+                continue;
+            }
+            self.last_seq = line.seq;
+            self.lines.push_back(line);
+            added = true;
+        }
+        while self.lines.len() > LOG_PAGE_LINES {
+            self.lines.pop_front();
+        }
+        added
+    }
+}
+
+thread_local! {
+    static LOG: RefCell<LogView> = RefCell::new(LogView::default());
+}
+
+/// Whether the line passes the level filter and has the searched text, ignoring the case.
+fn matches(line: &LogLine, errors_only: bool, search: &str) -> bool {
+    if errors_only && line.level != LogLevel::Error {
+        return false;
+    }
+    search.is_empty() || line.message.to_lowercase().contains(&search.to_lowercase())
+}
+
+/// Asks the node for its new log lines while the Log page is shown, and filters the lines
+/// again when the level or the search change.
+pub fn log_page_on_activate(builder: &Builder, sender: RequestSender) {
+    let level_combo_box: ComboBoxText = builder
+        .object("log_page_level_combo_box")
+        .expect("Failed to retrieve log level combo box");
+    let search_entry: SearchEntry = builder
+        .object("log_page_search_entry")
+        .expect("Failed to retrieve log search entry");
+    let stack: Stack = builder.object("stack").expect("Failed to get stack");
+
+    search_entry.set_placeholder_text(Some(&tr("Search")));
+    level_combo_box.append(Some("all"), &tr("All levels"));
+    level_combo_box.append(Some(ERRORS_ONLY_ID), &tr("Errors"));
+    level_combo_box.set_active_id(Some("all"));
+
+    let builder_clone = builder.clone();
+    level_combo_box.connect_changed(move |_| render(&builder_clone));
+    let builder_clone = builder.clone();
+    search_entry.connect_search_changed(move |_| render(&builder_clone));
+
+    glib::timeout_add_seconds_local(LOG_POLL_INTERVAL, move || {
+        if stack.visible_child_name().as_deref() == Some("log_page") {
+            let last_seq = LOG.with(|log| log.borrow().last_seq);
+            sender.send(WalletApi::GetLog(last_seq)).unwrap();
+        }
+        glib::Continue(true)
+    });
+}
+
+pub fn handle_log_message(builder: &Builder, lines: Vec<LogLine>) {
+    if LOG.with(|log| log.borrow_mut().append(lines)) {
+        render(builder);
+    }
+}
+
+fn render(builder: &Builder) {
+    let log_table: ListStore = builder
+        .object("log_columns")
+        .expect("Failed retrieving log table");
+    let level_combo_box: ComboBoxText = builder
+        .object("log_page_level_combo_box")
+        .expect("Failed to retrieve log level combo box");
+    let search_entry: SearchEntry = builder
+        .object("log_page_search_entry")
+        .expect("Failed to retrieve log search entry");
+
+    let errors_only = level_combo_box.active_id().as_deref() == Some(ERRORS_ONLY_ID);
+    let search = search_entry.text();
+    log_table.clear();
+    LOG.with(|log| {
+        // The newest lines first, like the mempool
+        for line in log.borrow().lines.iter().rev() {
+            if !matches(line, errors_only, &search) {
+                continue;
+            }
+            let level = match line.level {
+                LogLevel::Info => tr("Info"),
+                LogLevel::Error => tr("Error"),
+            };
+            log_table.insert_with_values(None, &[(0, &line.time), (1, &level), (2, &line.message)]);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(seq: u64, level: LogLevel, message: &str) -> LogLine {
+        LogLine {
+            seq,
+            time: "23-07-16 01:23:51".to_string(),
+            level,
+            message: message.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_lines_are_filtered_and_not_repeated() {
+        let mut log = LogView::default();
+        assert!(log.append(vec![
+            line(1, LogLevel::Info, "Connected to peer 1.2.3.4"),
+            line(2, LogLevel::Error, "ERROR: Peer timed out"),
+        ]));
+        assert!(!log.append(vec![line(2, LogLevel::Error, "ERROR: Peer timed out")]));
+        assert_eq!(log.last_seq, 2);
+        assert_eq!(log.lines.len(), 2);
+
+        assert!(matches(&log.lines[0], false, "PEER"));
+        assert!(!matches(&log.lines[0], true, ""));
+        assert!(matches(&log.lines[1], true, "timed"));
+        assert!(!matches(&log.lines[1], false, "block"));
+    }
+}
@@ -1,56 +1,89 @@
 mod account;
+mod account_actions;
+mod accounts_summary;
+mod charts;
+mod coins_page;
+mod history_export;
+mod i18n;
+mod log_page;
+mod mempool_page;
+mod notifications;
+mod ownership_proofs;
+mod payment_requests;
+mod script_page;
+mod send_form;
+mod settings;
+mod table_menus;
+mod wallet_controller;
+mod wallet_files;
 use account::Account;
 use btc_node::{
-    api::{NodeApi, WalletApi},
+    api::{Event, NodeApi, NodeStats, RequestSender, WalletApi},
     bitcoin_node::Node,
-    blockchain::txs::Tx,
     config::Config,
+    download_progress::PeerDownload,
+    keys::PrivateKey,
+    message_header::network,
     protocol_error::ProtocolError,
-    utils::bytes_to_hex_string,
+    sync_state::SyncState,
+    utils::{timestamp_to_date, timestamp_to_time},
+    wallet_file::WalletEntry,
 };
 use glib::Receiver;
 use gtk::{
     ffi::{GTK_MESSAGE_INFO, GTK_MESSAGE_WARNING},
     prelude::*,
-    Builder, Button, ComboBoxText, Entry, Label, ListStore, ProgressBar, SpinButton, Stack,
-    ToggleButton,
-};
-use std::{
-    cell::RefCell,
-    collections::HashMap,
-    env,
-    rc::Rc,
-    sync::mpsc::{self, Sender},
+    Builder, Button, ComboBoxText, Entry, Label, ListStore, MenuItem, ProgressBar, SpinButton,
+    Stack, ToggleButton,
 };
+use i18n::{tr, tr_error, tr_fmt};
+use mempool_page::MempoolView;
+use notifications::NotificationKind;
+use payment_requests::{RequestsView, SharedRequests};
+use script_page::{ScriptDebugger, SharedDebugger};
+use std::{cell::RefCell, env, rc::Rc, sync::mpsc};
+use wallet_controller::{HistoryRow, PendingRow, SharedWallet, ViewUpdate, WalletController};
+
+// Checks the blockchain file again before starting the node
+const REINDEX_OPTION: &str = "--reindex";
 
 fn main() -> Result<(), ProtocolError> {
-    let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
+    // The options go anywhere in the command line, the only other argument is the configuration.
+    // Without it the whole configuration comes from the BTC_NODE_ environment variables
+    let mut args: Vec<String> = env::args().collect();
+    let reindex = args.iter().any(|arg| arg == REINDEX_OPTION);
+    args.retain(|arg| arg != REINDEX_OPTION);
+    if args.len() > 2 {
         return Err(ProtocolError::Error(
-            "Incorrect amount of arguments were given. Need 1".to_string(),
+            "Incorrect amount of arguments were given. Need at most 1".to_string(),
         ));
     }
 
-    let (sender, receiver) = glib::MainContext::channel::<NodeApi>(glib::PRIORITY_DEFAULT);
+    let (sender, receiver) = glib::MainContext::channel::<Event<NodeApi>>(glib::PRIORITY_DEFAULT);
     let (tx, rx) = mpsc::channel();
 
     let node_thread = std::thread::spawn(move || -> Result<(), ProtocolError> {
-        let config = Config::new(&args[1])?;
-        let mut my_node = Node::new(config, sender)?;
-        my_node.initialize()?;
-        my_node.listen(rx)?;
+        let mut config = match args.get(1) {
+            Some(path) => Config::new(path)?,
+            None => Config::from_env()?,
+        };
+        config.reindex = reindex;
+        let my_node = Node::new(config, sender)?;
+        my_node.start(rx)?;
         Ok(())
     });
 
-    init(receiver, tx);
+    init(receiver, RequestSender::new(tx));
 
     node_thread.join().unwrap()?;
 
     Ok(())
 }
 
-fn init(receiver: Receiver<NodeApi>, sender: Sender<WalletApi>) {
-    let accounts: Rc<RefCell<HashMap<String, Account>>> = Rc::new(RefCell::new(HashMap::new()));
+fn init(receiver: Receiver<Event<NodeApi>>, sender: RequestSender) {
+    let wallet: SharedWallet = Rc::new(RefCell::new(WalletController::new()));
+    let mempool: MempoolView = Rc::new(RefCell::new(Vec::new()));
+    let debugger: SharedDebugger = Rc::new(RefCell::new(ScriptDebugger::default()));
 
     if gtk::init().is_err() {
         println!("Failed to initialize GTK.");
@@ -59,6 +92,8 @@ fn init(receiver: Receiver<NodeApi>, sender: Sender<WalletApi>) {
 
     let glade_src = include_str!("interface.glade");
     let builder = Builder::from_string(glade_src);
+    i18n::translate_widgets(&builder);
+    let requests: SharedRequests = Rc::new(RequestsView::new());
 
     let window: gtk::Window = builder.object("app").expect("Failed to get window");
     window.connect_delete_event(|_, _| {
@@ -67,12 +102,40 @@ fn init(receiver: Receiver<NodeApi>, sender: Sender<WalletApi>) {
     });
 
     set_all_menus(&builder);
-    create_account_button_on_clicked(&builder, sender.clone(), &accounts);
-    pay_button_on_clicked(&builder, &accounts, sender);
-    combo_box_on_changed(&builder, &accounts);
+    settings::settings_menu_on_activate(&builder);
+    create_account_button_on_clicked(&builder, sender.clone(), &wallet);
+    mempool_page::mempool_context_menu(&builder, &mempool, &wallet, sender.clone());
+    table_menus::transactions_context_menus(&builder, &wallet, sender.clone());
+    history_export::export_button_on_clicked(&builder, &wallet);
+    pause_button_on_clicked(&builder, sender.clone());
+    refresh_button_on_clicked(&builder, &wallet, sender.clone());
+    reload_config_menu_on_activate(&builder, sender.clone());
+    pay_button_on_clicked(&builder, &wallet, sender.clone());
+    combo_box_on_changed(&builder, &wallet);
+    accounts_summary::summary_selection_on_changed(&builder);
+    send_form::send_form_on_changed(&builder, &wallet);
+    send_form::suggest_fee_button_on_clicked(&builder, sender.clone());
+    send_form::pay_to_entry_on_changed(&builder);
+    send_form::op_return_on_changed(&builder);
+    account_actions::account_buttons_on_clicked(&builder, &wallet, sender.clone());
+    account_actions::generate_account_button_on_clicked(&builder);
+    coins_page::coins_page_on_toggled(&builder, &wallet, sender.clone());
+    script_page::script_page_on_clicked(&builder, &debugger, sender.clone());
+    log_page::log_page_on_activate(&builder, sender.clone());
+    wallet_files::wallet_buttons_on_clicked(&builder, sender.clone());
+    wallet_files::backup_menus_on_activate(&builder, sender.clone());
+    ownership_proofs::ownership_menus_on_activate(&builder, &wallet, sender.clone());
+    payment_requests::payment_requests_menu_on_activate(
+        &builder,
+        &wallet,
+        &requests,
+        sender.clone(),
+    );
     set_necesary_widgets_during_block_download(&builder);
 
-    attach(receiver, &accounts, &builder);
+    attach(
+        receiver, sender, &wallet, &mempool, &requests, &debugger, &builder,
+    );
     window.show_all();
     gtk::main();
 }
@@ -118,8 +181,48 @@ fn deactivate_necesary_buttons_during_block_download(builder: &Builder) {
     pay_button.set_sensitive(false);
 }
 
-fn combo_box_on_changed(builder: &Builder, accounts: &Rc<RefCell<HashMap<String, Account>>>) {
-    let accounts_clone = Rc::clone(accounts);
+/// The same button pauses and resumes the block download, its label shows the next action.
+fn pause_button_on_clicked(builder: &Builder, sender: RequestSender) {
+    let pause_button: Button = builder
+        .object("overview_page_pause_button")
+        .expect("Failed retrieving pause button");
+
+    pause_button.connect_clicked(move |button| {
+        let msg = match button.label() {
+            Some(label) if label.as_str() == tr("Resume") => WalletApi::ResumeSync,
+            _ => WalletApi::PauseSync,
+        };
+        sender.send(msg).unwrap();
+    });
+}
+
+/// Asks the node again for the balance and the coins of every account.
+fn refresh_button_on_clicked(builder: &Builder, wallet: &SharedWallet, sender: RequestSender) {
+    let refresh_button: Button = builder
+        .object("overview_page_refresh_button")
+        .expect("Failed retrieving refresh button");
+    let wallet = Rc::clone(wallet);
+
+    refresh_button.connect_clicked(move |_| {
+        for address in wallet.borrow().addresses() {
+            sender.send(WalletApi::GetBalance(address)).unwrap();
+        }
+    });
+}
+
+/// The node reads its configuration file again, without restarting the sync.
+fn reload_config_menu_on_activate(builder: &Builder, sender: RequestSender) {
+    let item: MenuItem = builder
+        .object("menu_settings_reload_config")
+        .expect("Failed to get reload configuration menu item");
+
+    item.connect_activate(move |_| {
+        sender.send(WalletApi::ReloadConfig).unwrap();
+    });
+}
+
+fn combo_box_on_changed(builder: &Builder, wallet: &SharedWallet) {
+    let wallet = Rc::clone(wallet);
     let builder_clone = builder.clone();
 
     let combo_box: ComboBoxText = builder
@@ -128,53 +231,63 @@ fn combo_box_on_changed(builder: &Builder, accounts: &Rc<RefCell<HashMap<String,
 
     combo_box.connect_changed(move |combo_box| {
         if let Some(current_account) = combo_box.active_text() {
-            for (_address, account) in accounts_clone.borrow_mut().iter() {
-                let name = account.name.clone();
-                if name == current_account {
-                    actualize_balance_label(&builder_clone, account.balance);
-                    actualize_pending_balance_label(&builder_clone, account.pending_balance);
-                    actualize_total_balance(
-                        &builder_clone,
-                        account.balance,
-                        account.pending_balance,
-                    );
-
-                    re_set_pending_transactions(&builder_clone, &account.pending_tx);
-
-                    re_set_transactions(&builder_clone, &account.transactions);
-                }
-            }
+            let updates = wallet.borrow_mut().select(&current_account);
+            render(&builder_clone, updates);
         }
     });
 }
 
-fn re_set_transactions(builder: &Builder, transactions: &Vec<Tx>) {
+/// Shows the changes of the wallet state in the widgets.
+fn render(builder: &Builder, updates: Vec<ViewUpdate>) {
+    for update in updates {
+        match update {
+            ViewUpdate::Balances {
+                available,
+                pending,
+                total,
+                spendable,
+            } => {
+                actualize_balance_label(builder, available);
+                actualize_pending_balance_label(builder, pending);
+                actualize_total_balance(builder, total);
+                send_form::show_remaining_balance(builder, spendable);
+            }
+            ViewUpdate::PendingTransactions(rows) => re_set_pending_transactions(builder, &rows),
+            ViewUpdate::History(history) => re_set_transactions(builder, &history),
+            ViewUpdate::AccountsSummary(rows) => {
+                accounts_summary::re_set_accounts_summary(builder, &rows)
+            }
+            ViewUpdate::Coins(rows) => coins_page::re_set_coins(builder, &rows),
+            ViewUpdate::Notification(kind, title, message) => {
+                notifications::notify(kind, &title, &message)
+            }
+        }
+    }
+}
+
+fn re_set_transactions(builder: &Builder, transactions: &[HistoryRow]) {
     let transactions_list_store: ListStore = builder
         .object("transactions_columns")
         .expect("Failed to retrieve transactions list store");
 
     transactions_list_store.clear();
-    set_transactions(&transactions, &transactions_list_store);
+    set_transactions(transactions, &transactions_list_store);
 }
 
-fn re_set_pending_transactions(
-    builder: &Builder,
-    pending_tx: &HashMap<[u8; 32], (Tx, i64, String, String)>,
-) {
+fn re_set_pending_transactions(builder: &Builder, pending_tx: &[PendingRow]) {
     let pending_transactions_list_store: ListStore = builder
         .object("pending_transactions")
         .expect("Failed to retrieve pending transactions list store");
 
     pending_transactions_list_store.clear();
-    set_pending_transactions(&pending_tx, &pending_transactions_list_store);
+    set_pending_transactions(pending_tx, &pending_transactions_list_store);
 }
 
-fn actualize_total_balance(builder: &Builder, balance: i64, pending_balance: i64) {
+fn actualize_total_balance(builder: &Builder, total_balance: i64) {
     let total_balance_label: Label = builder
         .object("total_size_label")
         .expect("Failed to get total balance label");
 
-    let total_balance = balance + pending_balance;
     total_balance_label.set_text(&total_balance.to_string());
 }
 
@@ -194,12 +307,8 @@ fn actualize_balance_label(builder: &Builder, balance: i64) {
     balance_label.set_text(&balance.to_string());
 }
 
-fn pay_button_on_clicked(
-    builder: &Builder,
-    accounts: &Rc<RefCell<HashMap<String, Account>>>,
-    sender: Sender<WalletApi>,
-) {
-    let accounts_clone = Rc::clone(accounts);
+fn pay_button_on_clicked(builder: &Builder, wallet: &SharedWallet, sender: RequestSender) {
+    let wallet = Rc::clone(wallet);
 
     let pay_button: Button = builder
         .object("pay_button")
@@ -217,105 +326,118 @@ fn pay_button_on_clicked(
         .object("fee_amount_spin_button")
         .expect("Failed to retrieve name entry");
 
-    let wallets_combo_box: ComboBoxText = builder
-        .object::<ComboBoxText>("wallets_combo_box")
-        .expect("Failed to get wallet combobox");
-
+    let builder = builder.clone();
     pay_button.connect_clicked(move |_pay_button| {
         if validate_text_is_not_empty(&pay_entry, "Addres to pay to is missing") {
             let address_to_pay = pay_entry.text().to_string();
-            let fee_amount = fee_amount_spin_button.value_as_int() as i64;
-            let amount_to_pay = amount_spin_button.value_as_int() as i64;
-
-            let mut wif: String = "".to_string();
-
-            for (_address, account) in accounts_clone.borrow_mut().iter() {
-                if let Some(text) = wallets_combo_box.active_text() {
-                    if account.name == text {
-                        wif = account.wif.clone();
-                    }
-                }
-            }
-
-            if !wif.is_empty() {
+            let fee_amount = fee_amount_spin_button.value() as i64;
+            let amount_to_pay = amount_spin_button.value() as i64;
+
+            let (wif, spendable) = wallet
+                .borrow()
+                .active_account()
+                .map(|account| (account.wif.clone(), account.spendable_balance()))
+                .unwrap_or_default();
+            let selected_coins = wallet.borrow().selected_coins();
+            let options = send_form::payment_options(&builder, selected_coins);
+
+            if wif.is_empty() {
+                create_notification_window(
+                    gtk::MessageType::__Unknown(GTK_MESSAGE_WARNING),
+                    "Warning",
+                    "You have to select or log an account first to pay",
+                );
+            } else if let Some(error) = send_form::address_error(&address_to_pay, true) {
+                create_notification_window(
+                    gtk::MessageType::__Unknown(GTK_MESSAGE_WARNING),
+                    "Warning",
+                    &error,
+                );
+            } else if send_form::check_amount(spendable, amount_to_pay, fee_amount).is_err() {
+                create_notification_window(
+                    gtk::MessageType::__Unknown(GTK_MESSAGE_WARNING),
+                    "Warning",
+                    "The amount and the fee exceed the spendable balance",
+                );
+            } else if let Err(error) = &options {
+                create_notification_window(
+                    gtk::MessageType::__Unknown(GTK_MESSAGE_WARNING),
+                    "Warning",
+                    error,
+                );
+            } else {
                 sender
                     .send(WalletApi::PayTo(
                         wif,
                         address_to_pay,
                         amount_to_pay,
                         fee_amount,
+                        options.unwrap_or_default(),
                     ))
                     .unwrap();
 
                 pay_entry.set_text("");
+                send_form::clear_payment_options(&builder);
                 fee_amount_spin_button.set_value(0 as f64);
                 amount_spin_button.set_value(0 as f64);
-            } else {
-                create_notification_window(
-                    gtk::MessageType::__Unknown(GTK_MESSAGE_WARNING),
-                    "Warning",
-                    "You have to select or log an account first to pay",
-                );
             }
         }
     });
 }
 
-fn set_transactions(transactions: &Vec<Tx>, transactions_table: &gtk::ListStore) {
-    for tx in transactions {
-        let txid = btc_node::utils::bytes_to_hex_string(&tx.tx_id);
+fn set_transactions(transactions: &[HistoryRow], transactions_table: &gtk::ListStore) {
+    for (entry, label) in transactions {
+        let txid = btc_node::utils::bytes_to_hex_string(&entry.tx.tx_id);
+        let fee = match entry.fee {
+            Some(fee) => fee.to_string(),
+            None => tr("Unknown"),
+        };
+
         let data_for_column_1 = txid.to_value();
-        let data_for_column_2 = tx.get_tx_value().to_value();
-        let data_for_column_3 = (tx.tx_out.len() as u32).to_value();
-        let data_for_column_4 = (tx.tx_out.len() as u32).to_value();
+        let data_for_column_2 = entry.date().to_value();
+        let data_for_column_3 = entry.direction.to_string().to_value();
+        let data_for_column_4 = entry.amount.to_string().to_value();
+        let data_for_column_5 = fee.to_value();
+        let data_for_column_6 = entry.confirmations.to_string().to_value();
+        let data_for_column_7 = label.to_value();
 
         let array_of_data: &[(u32, &dyn ToValue)] = &[
             (0, &data_for_column_1),
             (1, &data_for_column_2),
             (2, &data_for_column_3),
             (3, &data_for_column_4),
+            (4, &data_for_column_5),
+            (5, &data_for_column_6),
+            (6, &data_for_column_7),
         ];
         transactions_table.insert_with_values(None, array_of_data);
     }
 }
 
-fn _set_transaction(
-    tx: Tx,
-    payer_address: String,
-    payee_address: String,
-    transactions_table: &gtk::ListStore,
-) {
-    let txid = btc_node::utils::bytes_to_hex_string(&tx.tx_id);
-    let data_for_column_1 = txid.to_value();
-    let data_for_column_2 = tx.value_payed_to_address(&payer_address).to_value();
-    let data_for_column_3 = payer_address.to_value();
-    let data_for_column_4 = payee_address.to_value();
-
-    let array_of_data: &[(u32, &dyn ToValue)] = &[
-        (0, &data_for_column_1),
-        (1, &data_for_column_2),
-        (2, &data_for_column_3),
-        (3, &data_for_column_4),
-    ];
-    transactions_table.insert_with_values(Some(1), array_of_data);
-}
-
-fn set_pending_transactions(
-    pending_tx: &HashMap<[u8; 32], (Tx, i64, String, String)>,
-    pending_transactions_table: &ListStore,
-) {
-    for (tx, amount, payer, payee) in pending_tx.values() {
+fn set_pending_transactions(pending_tx: &[PendingRow], pending_transactions_table: &ListStore) {
+    for (tx, amount, payer, payee) in pending_tx.iter() {
         let txid = btc_node::utils::bytes_to_hex_string(&tx.tx_id);
         let data_for_column_1 = txid.to_value();
         let data_for_column_2 = amount;
         let data_for_column_3 = payer;
         let data_for_column_4 = payee;
+        let data_for_column_5 = match tx.fee_rate() {
+            Some(rate) => format!("{:.2} sat/vB", rate),
+            None => tr("Unknown"),
+        };
+        let data_for_column_6 = if tx.signals_rbf() {
+            tr("Yes")
+        } else {
+            tr("No")
+        };
 
         let array_of_data: &[(u32, &dyn ToValue)] = &[
             (0, &data_for_column_1),
             (1, &data_for_column_2),
             (2, &data_for_column_3),
             (3, &data_for_column_4),
+            (4, &data_for_column_5),
+            (5, &data_for_column_6),
         ];
         pending_transactions_table.insert_with_values(None, array_of_data);
     }
@@ -326,51 +448,32 @@ fn set_all_menus(builder: &Builder) {
         builder.object("stack").expect("Failed to get stack"),
     ));
 
-    let button_overview: ToggleButton = builder
-        .object("menu_button_overview")
-        .expect("Failed to get overviews button");
-    let button_send: ToggleButton = builder
-        .object("menu_button_send")
-        .expect("Failed to get send button");
-    let button_accounts: ToggleButton = builder
-        .object("menu_button_accounts")
-        .expect("Failed to get account button");
-    let button_transactions: ToggleButton = builder
-        .object("menu_button_transactions")
-        .expect("Failed to get transactions button");
-
-    set_menu(
-        &stack,
-        &button_overview,
-        &button_accounts,
-        &button_send,
-        &button_transactions,
-        "overview_page".to_string(),
-    );
-    set_menu(
-        &stack,
-        &button_accounts,
-        &button_overview,
-        &button_send,
-        &button_transactions,
-        "accounts_page".to_string(),
-    );
-    set_menu(
-        &stack,
-        &button_send,
-        &button_accounts,
-        &button_overview,
-        &button_transactions,
-        "send_page".to_string(),
-    );
-    set_menu(
-        &stack,
-        &button_transactions,
-        &button_accounts,
-        &button_overview,
-        &button_send,
-        "transactions_page".to_string(),
-    );
+    let pages = [
+        ("menu_button_overview", "overview_page"),
+        ("menu_button_send", "send_page"),
+        ("menu_button_accounts", "accounts_page"),
+        ("menu_button_transactions", "transactions_page"),
+        ("menu_button_mempool", "mempool_page"),
+        ("menu_button_coins", "coins_page"),
+        ("menu_button_scripts", "script_page"),
+        ("menu_button_log", "log_page"),
+    ];
+
+    let buttons: Vec<ToggleButton> = pages
+        .iter()
+        .map(|(button, _)| builder.object(button).expect("Failed to get menu button"))
+        .collect();
+
+    for (i, (_, page_name)) in pages.iter().enumerate() {
+        let others: Vec<ToggleButton> = buttons
+            .iter()
+            .enumerate()
+            .filter(|(j, _)| *j != i)
+            .map(|(_, button)| button.clone())
+            .collect();
+
+        set_menu(&stack, &buttons[i], others, page_name.to_string());
+    }
 }
 
 fn create_notification_window(notification_type: gtk::MessageType, title: &str, message: &str) {
@@ -388,8 +491,8 @@ fn create_notification_window(notification_type: gtk::MessageType, title: &str,
 
     dialog.set_transient_for(Some(&parent));
     dialog.set_position(gtk::WindowPosition::CenterOnParent);
-    dialog.set_text(Some(title));
-    dialog.set_secondary_text(Some(message));
+    dialog.set_text(Some(&tr(title)));
+    dialog.set_secondary_text(Some(&tr(message)));
 
     dialog.connect_response(|dialog, _| dialog.close());
     dialog.run();
@@ -399,7 +502,7 @@ fn validate_account_creation_info(
     name_entry: &Entry,
     address_entry: &Entry,
     private_key_entry: &Entry,
-    accounts: &Rc<RefCell<HashMap<String, Account>>>,
+    wallet: &SharedWallet,
 ) -> bool {
     if !validate_text_is_not_empty(name_entry, "Name is missing") {
         return false;
@@ -411,28 +514,21 @@ fn validate_account_creation_info(
         return false;
     };
 
-    if !validate_account_not_already_logged_in(address_entry, accounts) {
+    if !validate_private_key(address_entry, private_key_entry) {
         return false;
     };
-    if !validate_name_is_unused(accounts, name_entry) {
+    if !validate_account_not_already_logged_in(address_entry, wallet) {
+        return false;
+    };
+    if !validate_name_is_unused(wallet, name_entry) {
         return false;
     };
 
     true
 }
 
-fn validate_name_is_unused(
-    accounts: &Rc<RefCell<HashMap<String, Account>>>,
-    name_entry: &Entry,
-) -> bool {
-    let accounts_clone = Rc::clone(accounts);
-    let names: Vec<String> = accounts_clone
-        .borrow_mut()
-        .values()
-        .map(|v| v.name.clone())
-        .collect();
-
-    if names.contains(&name_entry.text().to_string()) {
+fn validate_name_is_unused(wallet: &SharedWallet, name_entry: &Entry) -> bool {
+    if wallet.borrow().name_is_used(name_entry.text().as_str()) {
         create_notification_window(
             gtk::MessageType::__Unknown(GTK_MESSAGE_WARNING),
             "Warning",
@@ -445,15 +541,29 @@ fn validate_name_is_unused(
     true
 }
 
-fn validate_account_not_already_logged_in(
-    address_entry: &Entry,
-    accounts: &Rc<RefCell<HashMap<String, Account>>>,
-) -> bool {
-    let accounts_clone = Rc::clone(accounts);
+/// The key has to be a valid WIF whose address, compressed or not, is the one entered.
+fn validate_private_key(address_entry: &Entry, private_key_entry: &Entry) -> bool {
+    let key = PrivateKey::from_wif(private_key_entry.text().as_str(), network());
+    let message = match key {
+        Ok(key) if key.address() == address_entry.text().as_str() => return true,
+        Ok(key) => tr_fmt(
+            "The private key is the one of the address {}",
+            &[key.address()],
+        ),
+        Err(e) => tr_error(&e.into()),
+    };
+    create_notification_window(
+        gtk::MessageType::__Unknown(GTK_MESSAGE_WARNING),
+        "Warning",
+        &message,
+    );
+    false
+}
 
-    if accounts_clone
-        .borrow_mut()
-        .contains_key(&address_entry.text().to_string())
+fn validate_account_not_already_logged_in(address_entry: &Entry, wallet: &SharedWallet) -> bool {
+    if wallet
+        .borrow()
+        .contains_address(address_entry.text().as_str())
     {
         create_notification_window(
             gtk::MessageType::__Unknown(GTK_MESSAGE_WARNING),
@@ -482,14 +592,14 @@ fn validate_text_is_not_empty(text: &Entry, message: &str) -> bool {
 
 fn create_account_button_on_clicked(
     builder: &Builder,
-    sender: Sender<WalletApi>,
-    accounts: &Rc<RefCell<HashMap<String, Account>>>,
+    sender: RequestSender,
+    wallet: &SharedWallet,
 ) {
     let create_account_button: Button = builder
         .object("accounts_page_frame1_button")
         .expect("Failed to retrieve create button.");
 
-    let accounts_clone = Rc::clone(accounts);
+    let wallet = Rc::clone(wallet);
 
     let combo_box_wallets: ComboBoxText = builder
         .object::<ComboBoxText>("wallets_combo_box")
@@ -506,12 +616,8 @@ fn create_account_button_on_clicked(
         .expect("Failed to retrieve public key entry");
 
     create_account_button.connect_clicked(move |_button| {
-        if validate_account_creation_info(
-            &name_entry,
-            &address_entry,
-            &private_key_entry,
-            &accounts_clone,
-        ) {
+        if validate_account_creation_info(&name_entry, &address_entry, &private_key_entry, &wallet)
+        {
             combo_box_wallets.append_text(name_entry.text().as_str());
 
             let address = address_entry.text().to_string();
@@ -522,11 +628,15 @@ fn create_account_button_on_clicked(
                 0,
                 name_entry.text().to_string(),
             );
-            accounts_clone
-                .borrow_mut()
-                .insert(address.clone(), new_account);
+            let entry = WalletEntry {
+                name: new_account.name.clone(),
+                address: new_account.address.clone(),
+                wif: new_account.wif.clone(),
+            };
+            wallet.borrow_mut().add_account(new_account);
 
             sender.send(WalletApi::AddAddress(address)).unwrap();
+            sender.send(WalletApi::SaveAccount(entry)).unwrap();
 
             let index = combo_box_wallets.model().unwrap().iter_n_children(None) - 1;
             combo_box_wallets.set_active(Some(index as u32));
@@ -539,65 +649,139 @@ fn create_account_button_on_clicked(
 }
 
 fn attach(
-    receiver: Receiver<NodeApi>,
-    accounts: &Rc<RefCell<HashMap<String, Account>>>,
+    receiver: Receiver<Event<NodeApi>>,
+    sender: RequestSender,
+    wallet: &SharedWallet,
+    mempool: &MempoolView,
+    requests: &SharedRequests,
+    debugger: &SharedDebugger,
     builder: &Builder,
 ) {
     let builder_clone = builder.clone();
-    let accounts_clone = Rc::clone(accounts);
-
-    receiver.attach(None, move |msg| {
-        let accounts_clone = Rc::clone(&accounts_clone);
+    let wallet = Rc::clone(wallet);
+    let mempool = Rc::clone(mempool);
+    let requests = Rc::clone(requests);
+    let debugger = Rc::clone(debugger);
+
+    let mut last_seq = None;
+    receiver.attach(None, move |event| {
+        // The state built from the missed events is requested whole again
+        if !event.follows(last_seq) {
+            let _ = sender.send(WalletApi::Resync);
+        }
+        last_seq = Some(event.seq);
 
-        match msg {
+        match event.body {
+            NodeApi::WalletReady => handle_wallet_ready_message(&builder_clone),
             NodeApi::NodeReady => handle_node_ready_message(&builder_clone),
-            NodeApi::NewTx(tx, payer_addr, addr) => {
-                handle_new_tx_message(&builder_clone, &accounts_clone, addr, tx, payer_addr)
-            }
-            NodeApi::ConfirmedTx(txid, addr) => {
-                handle_confirmed_tx_message(&builder_clone, &accounts_clone, addr, txid)
-            }
-            NodeApi::Balance(balance, addr) => {
-                handle_balance_message(&builder_clone, &accounts_clone, addr, balance)
+            msg @ (NodeApi::NewTx(..)
+            | NodeApi::ConfirmedTx(..)
+            | NodeApi::Balance(..)
+            | NodeApi::AddPendingBalance(..)
+            | NodeApi::AddConfirmedBalance(..)
+            | NodeApi::ReconciledBalance(..)
+            | NodeApi::UtxoCount(..)
+            | NodeApi::Coins(..)
+            | NodeApi::PaymentConfirmation(..)
+            | NodeApi::History(..)
+            | NodeApi::FeeBumped(..)
+            | NodeApi::FeeBumpedByChild(..)
+            | NodeApi::Snapshot(..)
+            | NodeApi::Warning(..)) => {
+                // The borrow ends before rendering, the notification dialogs run their own loop
+                let updates = wallet.borrow_mut().apply(msg);
+                render(&builder_clone, updates);
             }
-            NodeApi::AddPendingBalance(pending_balance, addr) => {
-                handle_add_pending_balance_message(
+            NodeApi::WalletOpened(name, entries, labels) => {
+                wallet_files::handle_wallet_opened_message(
                     &builder_clone,
-                    &accounts_clone,
-                    addr,
-                    pending_balance,
+                    &wallet,
+                    name,
+                    entries,
+                    labels,
                 )
             }
-            NodeApi::AddConfirmedBalance(confirmed_balance, addr) => {
-                handle_add_confirmed_balance_message(
-                    &builder_clone,
-                    &accounts_clone,
-                    addr,
-                    confirmed_balance,
-                )
+            NodeApi::WalletClosed => {
+                wallet_files::handle_wallet_closed_message(&builder_clone, &wallet)
             }
-            NodeApi::PaymentConfirmation(tx, payer_address, payee_address, amount) => {
-                handle_payment_confirmation_message(
+            NodeApi::WalletBackedUp(path) => wallet_files::handle_wallet_backed_up_message(path),
+            NodeApi::WalletRestored(entries, labels) => {
+                wallet_files::handle_wallet_restored_message(
                     &builder_clone,
-                    &accounts_clone,
-                    tx,
-                    payer_address,
-                    payee_address,
-                    amount,
+                    &wallet,
+                    entries,
+                    labels,
                 )
             }
-            NodeApi::History(txs, addr) => {
-                handle_history_message(&builder_clone, &accounts_clone, txs, addr)
+            NodeApi::ScriptTraces(_, traces) => {
+                script_page::handle_script_traces_message(&builder_clone, &debugger, traces)
             }
             NodeApi::Error(error) => create_notification_window(
                 gtk::MessageType::__Unknown(GTK_MESSAGE_WARNING),
                 "Warning",
-                &format!("{}", error),
+                &tr_error(&error),
             ),
             NodeApi::Loading(progress) => handle_loading_message(&builder_clone, progress),
+            NodeApi::DownloadProgress(downloads) => {
+                handle_download_progress_message(&builder_clone, downloads)
+            }
+            NodeApi::SyncPaused(paused) => handle_sync_paused_message(&builder_clone, paused),
+            NodeApi::SyncStateChanged(state) => {
+                handle_sync_state_changed_message(&builder_clone, state)
+            }
+            NodeApi::NewBlock(height, _, n_txs, timestamp, wallet_relevant) => {
+                handle_new_block_message(&builder_clone, height, n_txs, timestamp, wallet_relevant)
+            }
+            NodeApi::HeaderSyncProgress(height, best_height) => {
+                handle_header_sync_progress_message(&builder_clone, height, best_height)
+            }
+            NodeApi::ReindexProgress(done, total) => {
+                handle_reindex_progress_message(&builder_clone, done, total)
+            }
             NodeApi::FinishedConnectingToPeers => {
                 handle_finished_connecting_to_peers_message(&builder_clone)
             }
+            NodeApi::ConfigReloaded(changes) => {
+                let message = if changes.is_empty() {
+                    "No setting changed".to_string()
+                } else {
+                    changes.join("\n")
+                };
+                create_notification_window(
+                    gtk::MessageType::__Unknown(GTK_MESSAGE_INFO),
+                    "Configuration reloaded",
+                    &message,
+                )
+            }
+            NodeApi::PaymentRequests(list) => {
+                payment_requests::handle_payment_requests_message(&requests, list)
+            }
+            NodeApi::OwnershipProof(address, message, proof) => {
+                ownership_proofs::handle_ownership_proof_message(
+                    &builder_clone,
+                    address,
+                    message,
+                    proof,
+                )
+            }
+            NodeApi::OwnershipVerified(address, message, valid) => {
+                ownership_proofs::handle_ownership_verified_message(address, message, valid)
+            }
+            NodeApi::ChainStats(_) | NodeApi::Supply(_) | NodeApi::BlockTemplate(_) => {}
+            NodeApi::Stats(stats) => handle_stats_message(&builder_clone, stats),
+            NodeApi::MempoolUpdate(snapshot) => {
+                mempool_page::handle_mempool_update_message(&builder_clone, &mempool, snapshot)
+            }
+            NodeApi::Log(lines) => log_page::handle_log_message(&builder_clone, lines),
+            NodeApi::Mempool(snapshot, histogram, recent_blocks) => {
+                send_form::handle_fee_histogram_message(
+                    &builder_clone,
+                    &wallet,
+                    &histogram,
+                    &recent_blocks,
+                );
+                mempool_page::handle_mempool_update_message(&builder_clone, &mempool, snapshot)
+            }
         }
         glib::Continue(true)
     });
@@ -620,238 +804,218 @@ fn handle_finished_connecting_to_peers_message(builder: &Builder) {
         .object("accounts_page_progress_bar_label")
         .expect("Failed to get accounts page progress bar label");
 
-    overview_page_label.set_text("Downloading Blocks...");
-    send_page_label.set_text("Downloading Blocks...");
-    transactions_page_label.set_text("Downloading Blocks...");
-    accounts_page_label.set_text("Downloading Blocks...");
+    overview_page_label.set_text(&tr("Downloading Blocks..."));
+    send_page_label.set_text(&tr("Downloading Blocks..."));
+    transactions_page_label.set_text(&tr("Downloading Blocks..."));
+    accounts_page_label.set_text(&tr("Downloading Blocks..."));
 }
 
-fn handle_loading_message(builder: &Builder, progress: f64) {
-    let overview_prog_bar: ProgressBar = builder
-        .object("overview_page_progress_bar")
-        .expect("Failed to get overview page progressbar");
-
-    let send_prog_bar: ProgressBar = builder
-        .object("send_page_progress_bar")
-        .expect("Failed to get send page progressbar");
-
-    let transactions_prog_bar: ProgressBar = builder
-        .object("transactions_page_progress_bar")
-        .expect("Failed to get transactions page progressbar");
-
-    let accounts_prog_bar: ProgressBar = builder
-        .object("accounts_page_progress_bar")
-        .expect("Failed to get accounts page progressbar");
+/// First phase of the sync, the block download reuses the progress bars afterwards.
+fn handle_sync_paused_message(builder: &Builder, paused: bool) {
+    let pause_button: Button = builder
+        .object("overview_page_pause_button")
+        .expect("Failed retrieving pause button");
+
+    if paused {
+        pause_button.set_label(&tr("Resume"));
+        set_all_downloading_blocks_labels_to(builder, "Download paused");
+    } else {
+        pause_button.set_label(&tr("Pause"));
+        set_all_downloading_blocks_labels_to(builder, "Downloading Blocks...");
+    }
+}
 
-    overview_prog_bar.set_fraction(progress);
-    send_prog_bar.set_fraction(progress);
-    transactions_prog_bar.set_fraction(progress);
-    accounts_prog_bar.set_fraction(progress);
+fn handle_sync_state_changed_message(builder: &Builder, state: SyncState) {
+    let text = match state {
+        SyncState::Connecting => "Connecting to peers...",
+        SyncState::HeaderSync => "Syncing headers...",
+        SyncState::BlockSync => "Downloading Blocks...",
+        SyncState::Synced => "Synced",
+    };
+    set_all_downloading_blocks_labels_to(builder, text);
 }
 
-fn handle_history_message(
-    builder: &Builder,
-    accounts: &Rc<RefCell<HashMap<String, Account>>>,
-    txs: Vec<Tx>,
-    addr: String,
-) {
-    let transactions_table: gtk::ListStore = builder
-        .object("transactions_columns")
-        .expect("Failed retrieving transaction table");
+fn handle_header_sync_progress_message(builder: &Builder, height: i32, best_height: i32) {
+    set_all_downloading_blocks_labels_to(
+        builder,
+        &tr_fmt(
+            "Syncing headers {}/{}...",
+            &[height.to_string(), best_height.to_string()],
+        ),
+    );
 
-    if let Some(account) = accounts.borrow_mut().get_mut(&addr) {
-        (*account).transactions.extend_from_slice(&txs[..]);
-        transactions_table.clear();
-        set_transactions(&txs, &transactions_table);
+    if best_height > 0 {
+        handle_loading_message(builder, height as f64 / best_height as f64);
     }
 }
 
-fn handle_payment_confirmation_message(
+/// Shows the last block in the status labels, like "block 2,543,123 • 1,234 txs • 17:05".
+fn handle_new_block_message(
     builder: &Builder,
-    accounts: &Rc<RefCell<HashMap<String, Account>>>,
-    tx: Tx,
-    payer_address: String,
-    payee_address: String,
-    amount: i64,
+    height: i32,
+    n_txs: usize,
+    timestamp: u32,
+    wallet_relevant: bool,
 ) {
-    let pending_transactions_table: gtk::ListStore = builder
-        .object("pending_transactions")
-        .expect("Failed retrieving pending transaction table");
-
-    if let Some(account) = accounts.borrow_mut().get_mut(&payer_address) {
-        (account).pending_tx.insert(
-            tx.tx_id,
-            (
-                tx.clone(),
-                tx.value_payed_to_address(&payee_address),
-                payer_address,
-                payee_address,
-            ),
-        );
-
-        create_notification_window(
-            gtk::MessageType::__Unknown(GTK_MESSAGE_INFO),
-            "Succesful Payment",
-            "Payment correctly sent",
-        );
-
-        account.balance -= amount;
-        actualize_balance_label(builder, account.balance);
-        actualize_pending_balance_label(builder, account.pending_balance);
-
-        pending_transactions_table.clear();
-        set_pending_transactions(&(*account).pending_tx, &pending_transactions_table);
+    let mut text = tr_fmt(
+        "block {} • {} txs • {}",
+        &[
+            group_thousands(height as u64),
+            group_thousands(n_txs as u64),
+            timestamp_to_time(timestamp),
+        ],
+    );
+    if wallet_relevant {
+        text = tr_fmt("{} • wallet activity", &[text]);
     }
+    set_all_downloading_blocks_labels_to(builder, &text);
 }
 
-fn handle_add_confirmed_balance_message(
-    builder: &Builder,
-    accounts: &Rc<RefCell<HashMap<String, Account>>>,
-    addr: String,
-    confirmed_balance: i64,
-) {
-    let pending_btc_label: Label = builder
-        .object("pending_row_size")
-        .expect("Failed retrieving avaliable btc label");
-
-    let available_btc_label: Label = builder
-        .object("available_row_size")
-        .expect("Failed retrieving avaliable btc label");
-
-    let total_btc_label: Label = builder
-        .object("total_size_label")
-        .expect("Failed retrieving avaliable btc label");
+/// The number with a comma every three digits.
+fn group_thousands(n: u64) -> String {
+    let digits = n.to_string();
+    let mut grouped = String::new();
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    grouped
+}
 
-    if let Some(account) = accounts.borrow_mut().get_mut(&addr) {
-        (*account).pending_balance -= confirmed_balance;
-        (*account).balance += confirmed_balance;
-        let total = account.pending_balance + account.balance;
+fn handle_reindex_progress_message(builder: &Builder, done: usize, total: usize) {
+    set_all_downloading_blocks_labels_to(
+        builder,
+        &tr_fmt(
+            "Reindexing blocks {}/{}...",
+            &[done.to_string(), total.to_string()],
+        ),
+    );
 
-        pending_btc_label.set_text(&(account).pending_balance.to_string());
-        available_btc_label.set_text(&(account).balance.to_string());
-        total_btc_label.set_text(&total.to_string());
+    if total > 0 {
+        handle_loading_message(builder, done as f64 / total as f64);
     }
 }
 
-fn handle_add_pending_balance_message(
-    builder: &Builder,
-    accounts: &Rc<RefCell<HashMap<String, Account>>>,
-    addr: String,
-    pending_balance: i64,
-) {
-    let balance_btc_label: Label = builder
-        .object("available_row_size")
-        .expect("Failed to get balance label");
+fn handle_loading_message(builder: &Builder, progress: f64) {
+    let overview_prog_bar: ProgressBar = builder
+        .object("overview_page_progress_bar")
+        .expect("Failed to get overview page progressbar");
 
-    let pending_btc_label: Label = builder
-        .object("pending_row_size")
-        .expect("Failed retrieving avaliable btc label");
+    let send_prog_bar: ProgressBar = builder
+        .object("send_page_progress_bar")
+        .expect("Failed to get send page progressbar");
 
-    let total_btc_label: Label = builder
-        .object("total_size_label")
-        .expect("Failed retrieving avaliable btc label");
+    let transactions_prog_bar: ProgressBar = builder
+        .object("transactions_page_progress_bar")
+        .expect("Failed to get transactions page progressbar");
 
-    if let Some(account) = accounts.borrow_mut().get_mut(&addr) {
-        (account).pending_balance += pending_balance;
-        (account).balance -= pending_balance;
-        let total = (account).balance + pending_balance;
+    let accounts_prog_bar: ProgressBar = builder
+        .object("accounts_page_progress_bar")
+        .expect("Failed to get accounts page progressbar");
 
-        pending_btc_label.set_text(&account.pending_balance.to_string());
-        balance_btc_label.set_text(&account.balance.to_string());
-        total_btc_label.set_text(&total.to_string());
-    }
+    overview_prog_bar.set_fraction(progress);
+    send_prog_bar.set_fraction(progress);
+    transactions_prog_bar.set_fraction(progress);
+    accounts_prog_bar.set_fraction(progress);
 }
 
-fn handle_balance_message(
-    builder: &Builder,
-    accounts: &Rc<RefCell<HashMap<String, Account>>>,
-    addr: String,
-    balance: i64,
-) {
-    let available_btc_label: Label = builder
-        .object("available_row_size")
-        .expect("Failed retrieving avaliable btc label");
-
-    let total_btc_label: Label = builder
-        .object("total_size_label")
-        .expect("Failed retrieving avaliable btc label");
-
-    if let Some(account) = accounts.borrow_mut().get_mut(&addr) {
-        (account).balance = balance;
-        available_btc_label.set_text(&balance.to_string());
-        let total = balance + (account).pending_balance;
-        total_btc_label.set_text(&total.to_string());
+/// The blocks of every peer go in the tooltip of the progress bars.
+fn handle_download_progress_message(builder: &Builder, downloads: Vec<PeerDownload>) {
+    let lines: Vec<String> = downloads
+        .iter()
+        .map(|download| {
+            let template = if download.failed {
+                "{} stopped at {}/{} blocks"
+            } else {
+                "{} downloaded {}/{} blocks"
+            };
+            tr_fmt(
+                template,
+                &[
+                    download.peer.to_string(),
+                    download.downloaded.to_string(),
+                    download.total.to_string(),
+                ],
+            )
+        })
+        .collect();
+    let tooltip = lines.join("\n");
+
+    for id in [
+        "overview_page_progress_bar",
+        "send_page_progress_bar",
+        "transactions_page_progress_bar",
+        "accounts_page_progress_bar",
+    ] {
+        let prog_bar: ProgressBar = builder.object(id).expect("Failed to get the progressbar");
+        prog_bar.set_tooltip_text(Some(&tooltip));
     }
 }
 
-fn handle_confirmed_tx_message(
-    builder: &Builder,
-    accounts: &Rc<RefCell<HashMap<String, Account>>>,
-    addr: String,
-    txid: [u8; 32],
-) {
-    let transactions_table: gtk::ListStore = builder
-        .object("transactions_columns")
-        .expect("Failed retrieving transaction table");
-
-    let pending_transactions_table: gtk::ListStore = builder
-        .object("pending_transactions")
-        .expect("Failed retrieving pending transaction table");
+fn handle_stats_message(builder: &Builder, stats: NodeStats) {
+    let set_value = |id: &str, value: String| {
+        let label: Label = builder
+            .object(id)
+            .expect("Failed to get overview page stats label");
+        label.set_text(&value);
+    };
 
-    create_notification_window(
-        gtk::MessageType::__Unknown(GTK_MESSAGE_INFO),
-        "One pending transaction is now confirmed.",
-        &format!("TXID: {}", bytes_to_hex_string(&txid)),
+    set_value("height_row_value", stats.chain.height.to_string());
+    set_value(
+        "last_block_row_value",
+        timestamp_to_date(stats.chain.last_block_time),
     );
+    set_value(
+        "peers_row_value",
+        format!(
+            "{} ({} inbound, {} refused, {} evicted)",
+            stats.peers, stats.inbound.connected, stats.inbound.rejected, stats.inbound.evicted
+        ),
+    );
+    set_value("mempool_row_value", format!("{} txs", stats.mempool_size));
+    set_value(
+        "sync_row_value",
+        format!("{:.2}%", stats.sync_progress * 100.0),
+    );
+    set_value(
+        "supply_row_value",
+        format!("{:.8} BTC", stats.chain.supply as f64 / 100_000_000.0),
+    );
+    set_value(
+        "traffic_row_value",
+        format!(
+            "{} sent / {} received",
+            format_bytes(stats.traffic.sent),
+            format_bytes(stats.traffic.received)
+        ),
+    );
+    set_value(
+        "block_sizes_row_value",
+        charts::block_sizes_chart(&stats.recent_blocks),
+    );
+    set_value(
+        "block_fees_row_value",
+        charts::block_fees_chart(&stats.recent_blocks),
+    );
+}
 
-    if let Some(account) = accounts.borrow_mut().get_mut(&addr) {
-        if let Some((tx, _, _, _)) = (account).pending_tx.remove(&txid) {
-            (account).transactions.push(tx);
-            transactions_table.clear();
-            set_transactions(&(account).transactions, &transactions_table);
-
-            pending_transactions_table.clear();
-            set_pending_transactions(&(account).pending_tx, &pending_transactions_table);
-        };
+fn format_bytes(bytes: u64) -> String {
+    match bytes {
+        0..=1023 => format!("{} B", bytes),
+        1024..=1048575 => format!("{:.1} KB", bytes as f64 / 1024.0),
+        _ => format!("{:.1} MB", bytes as f64 / 1048576.0),
     }
 }
 
-fn handle_new_tx_message(
-    builder: &Builder,
-    accounts: &Rc<RefCell<HashMap<String, Account>>>,
-    addr: String,
-    tx: Tx,
-    payer_addr: String,
-) {
-    let accounts_clone = Rc::clone(accounts);
-
-    let pending_transactions_table: gtk::ListStore = builder
-        .object("pending_transactions")
-        .expect("Failed retrieving pending transaction table");
-
-    if let Some(account) = accounts_clone.borrow_mut().get_mut(&addr) {
-        create_notification_window(
-            gtk::MessageType::__Unknown(GTK_MESSAGE_INFO),
-            "A new transaction related to your account has arrived",
-            &format!(
-                "Tx ID:{} '\n' Amount {} satoshi ",
-                bytes_to_hex_string(&tx.tx_id),
-                tx.get_tx_value()
-            ),
-        );
-        (account).pending_tx.insert(
-            tx.tx_id,
-            (
-                tx.clone(),
-                tx.value_payed_to_address(&addr),
-                payer_addr,
-                addr,
-            ),
-        );
+/// Accounts can be added while the blocks download, their balances are refreshed after the sync.
+fn handle_wallet_ready_message(builder: &Builder) {
+    let create_account_button: Button = builder
+        .object("accounts_page_frame1_button")
+        .expect("Failed retrieving create account button");
 
-        pending_transactions_table.clear();
-        set_pending_transactions(&(*account).pending_tx, &pending_transactions_table);
-    };
+    create_account_button.set_sensitive(true);
 }
 
 fn handle_node_ready_message(builder: &Builder) {
@@ -868,10 +1032,14 @@ fn handle_node_ready_message(builder: &Builder) {
     set_spinner_to(builder, false);
 
     set_all_downloading_blocks_labels_to(&builder, "Finished download!");
+    let pause_button: Button = builder
+        .object("overview_page_pause_button")
+        .expect("Failed retrieving pause button");
+    pause_button.set_sensitive(false);
     handle_loading_message(&builder, 1 as f64); //Makes all progress bars look full
 
-    create_notification_window(
-        gtk::MessageType::__Unknown(GTK_MESSAGE_INFO),
+    notifications::notify(
+        NotificationKind::Sync,
         "Finished downloading blocks",
         "The wallet is ready to be used",
     );
@@ -894,24 +1062,19 @@ fn set_all_downloading_blocks_labels_to(builder: &Builder, text: &str) {
         .object("accounts_page_progress_bar_label")
         .expect("Failed retrieving accounts page progress bar label");
 
-    overview_label.set_text(text);
-    send_label.set_text(text);
-    transactions_label.set_text(text);
-    accounts_label.set_text(text);
+    let text = tr(text);
+    overview_label.set_text(&text);
+    send_label.set_text(&text);
+    transactions_label.set_text(&text);
+    accounts_label.set_text(&text);
 }
 
 fn set_menu(
     stack: &Rc<RefCell<gtk::Stack>>,
     active: &gtk::ToggleButton,
-    other1: &gtk::ToggleButton,
-    other2: &gtk::ToggleButton,
-    other3: &gtk::ToggleButton,
+    others: Vec<gtk::ToggleButton>,
     page_name: String,
 ) {
-    let other1 = other1.clone();
-    let other2 = other2.clone();
-    let other3 = other3.clone();
-
     let stack_clone = stack.clone();
 
     let default_page = "default_page".to_string();
@@ -922,9 +1085,9 @@ fn set_menu(
 
     active.connect_toggled(move |toggle_button| {
         if toggle_button.is_active() {
-            other1.set_active(false);
-            other2.set_active(false);
-            other3.set_active(false);
+            for other in others.iter() {
+                other.set_active(false);
+            }
         }
 
         let stack = stack_clone.borrow_mut();
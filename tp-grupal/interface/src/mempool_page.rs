@@ -0,0 +1,214 @@
+use std::{
+    cell::RefCell,
+    rc::Rc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use btc_node::{
+    api::{RequestSender, WalletApi},
+    mempool::MempoolTxInfo,
+    utils::bytes_to_hex_string,
+};
+use gtk::{
+    ffi::{GTK_MESSAGE_INFO, GTK_MESSAGE_WARNING},
+    prelude::*,
+    Builder, Inhibit, ListStore, Menu, MenuItem, SpinButton, TreeView,
+};
+
+use crate::{
+    create_notification_window,
+    i18n::{tr, tr_fmt},
+    script_page::debug_transaction,
+    table_menus::clicked_txid,
+    wallet_controller::SharedWallet,
+};
+
+/// Last mempool snapshot received from the node.
+pub type MempoolView = Rc<RefCell<Vec<MempoolTxInfo>>>;
+
+fn now() -> u32 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as u32)
+        .unwrap_or(0)
+}
+
+fn format_age(seconds: u32) -> String {
+    match seconds {
+        0..=59 => format!("{}s", seconds),
+        60..=3599 => format!("{}m", seconds / 60),
+        _ => format!("{}h {}m", seconds / 3600, (seconds % 3600) / 60),
+    }
+}
+
+fn format_fee_rate(info: &MempoolTxInfo) -> String {
+    match info.fee_rate() {
+        Some(rate) => format!("{:.2} sat/vB", rate),
+        None => "Unknown".to_string(),
+    }
+}
+
+pub fn handle_mempool_update_message(
+    builder: &Builder,
+    mempool: &MempoolView,
+    mut snapshot: Vec<MempoolTxInfo>,
+) {
+    let mempool_table: ListStore = builder
+        .object("mempool_columns")
+        .expect("Failed retrieving mempool table");
+
+    snapshot.sort_by_key(|info| std::cmp::Reverse(info.time));
+
+    let now = now();
+    mempool_table.clear();
+    for info in snapshot.iter() {
+        let txid = bytes_to_hex_string(&info.txid).to_value();
+        let size = format!("{} vB", info.vsize).to_value();
+        let fee_rate = format_fee_rate(info).to_value();
+        let age = format_age(info.age(now)).to_value();
+        let wallet = tr(if info.wallet_relevant { "Yes" } else { "No" }).to_value();
+
+        let array_of_data: &[(u32, &dyn ToValue)] = &[
+            (0, &txid),
+            (1, &size),
+            (2, &fee_rate),
+            (3, &age),
+            (4, &wallet),
+        ];
+        mempool_table.insert_with_values(None, array_of_data);
+    }
+
+    *mempool.borrow_mut() = snapshot;
+}
+
+/// Shows the Inspect, Debug scripts and Bump fee actions when a mempool row is right clicked.
+pub fn mempool_context_menu(
+    builder: &Builder,
+    mempool: &MempoolView,
+    wallet: &SharedWallet,
+    sender: RequestSender,
+) {
+    let tree_view: TreeView = builder
+        .object("mempool_page_tree_view")
+        .expect("Failed to get mempool tree view");
+
+    let builder = builder.clone();
+    let mempool = Rc::clone(mempool);
+    let wallet = Rc::clone(wallet);
+
+    tree_view.connect_button_press_event(move |tree_view, event| {
+        let txid = match clicked_txid(tree_view, event) {
+            Some(txid) => txid,
+            None => return Inhibit(false),
+        };
+        let info = match mempool
+            .borrow()
+            .iter()
+            .find(|info| bytes_to_hex_string(&info.txid) == txid)
+        {
+            Some(info) => info.clone(),
+            None => return Inhibit(false),
+        };
+
+        let menu = Menu::new();
+        let inspect_item = MenuItem::with_label(&tr("Inspect"));
+        let debug_item = MenuItem::with_label(&tr("Debug scripts"));
+        let bump_item = MenuItem::with_label(&tr("Bump fee"));
+        bump_item.set_sensitive(info.own);
+        menu.append(&inspect_item);
+        menu.append(&debug_item);
+        menu.append(&bump_item);
+
+        let inspected = info.clone();
+        inspect_item.connect_activate(move |_| inspect_transaction(&inspected));
+
+        let debug_builder = builder.clone();
+        debug_item.connect_activate(move |_| debug_transaction(&debug_builder, &txid));
+
+        let builder = builder.clone();
+        let wallet = Rc::clone(&wallet);
+        let sender = sender.clone();
+        bump_item.connect_activate(move |_| bump_fee(&builder, &wallet, &sender, &info));
+
+        menu.show_all();
+        menu.popup_at_pointer(None);
+        Inhibit(true)
+    });
+}
+
+fn inspect_transaction(info: &MempoolTxInfo) {
+    let fee = match info.fee {
+        Some(fee) => format!("{} sat", fee),
+        None => tr("Unknown"),
+    };
+
+    create_notification_window(
+        gtk::MessageType::__Unknown(GTK_MESSAGE_INFO),
+        "Mempool transaction",
+        &tr_fmt(
+            "Tx ID: {}\nVirtual size: {} vB\nFee: {}\nFee rate: {}\nAge: {}\nWallet: {}",
+            &[
+                bytes_to_hex_string(&info.txid),
+                info.vsize.to_string(),
+                fee,
+                format_fee_rate(info),
+                format_age(info.age(now())),
+                tr(if info.wallet_relevant { "Yes" } else { "No" }),
+            ],
+        ),
+    );
+}
+
+fn bump_fee(
+    builder: &Builder,
+    wallet: &SharedWallet,
+    sender: &RequestSender,
+    info: &MempoolTxInfo,
+) {
+    let wif = wallet.borrow().payer_wif(&info.txid);
+
+    let wif = match wif {
+        Some(wif) => wif,
+        None => {
+            create_notification_window(
+                gtk::MessageType::__Unknown(GTK_MESSAGE_WARNING),
+                "Warning",
+                "None of the accounts paid this transaction",
+            );
+            return;
+        }
+    };
+
+    if let Some(extra_fee) = ask_fee(builder, "Bump fee") {
+        sender
+            .send(WalletApi::BumpFee(wif, info.txid, extra_fee))
+            .unwrap();
+    }
+}
+
+/// Asks the satoshis of a fee in a dialog with the title.
+pub fn ask_fee(builder: &Builder, title: &str) -> Option<i64> {
+    let parent: gtk::Window = builder.object("app").expect("Failed to get window");
+
+    let dialog = gtk::Dialog::with_buttons(
+        Some(&tr(title)),
+        Some(&parent),
+        gtk::DialogFlags::MODAL,
+        &[
+            (&tr("Bump"), gtk::ResponseType::Accept),
+            (&tr("Cancel"), gtk::ResponseType::Cancel),
+        ],
+    );
+    let spin_button = SpinButton::with_range(1.0, 100_000_000.0, 1.0);
+    dialog.content_area().add(&spin_button);
+    dialog.show_all();
+
+    let response = dialog.run();
+    let extra_fee = spin_button.value_as_int() as i64;
+    dialog.close();
+
+    match response {
+        gtk::ResponseType::Accept => Some(extra_fee),
+        _ => None,
+    }
+}
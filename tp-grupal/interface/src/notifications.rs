@@ -0,0 +1,325 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use gtk::{ffi::GTK_MESSAGE_INFO, prelude::*, Builder, Label};
+
+use crate::{
+    create_notification_window,
+    i18n::{tr, tr_fmt},
+};
+
+// Seconds that have to pass between two popups of the same kind
+const POPUP_INTERVAL: u64 = 10;
+// Milliseconds the banners are batched before being shown together
+const BANNER_BATCH_INTERVAL: u64 = 2000;
+// Seconds a toast stays on screen
+const TOAST_DURATION: u32 = 4;
+
+/// Events the interface notifies about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NotificationKind {
+    IncomingTx,
+    Payment,
+    FeeBump,
+    Confirmation,
+    Sync,
+    /// Privacy warnings about the use of an address
+    Privacy,
+}
+
+impl NotificationKind {
+    pub const ALL: [NotificationKind; 6] = [
+        NotificationKind::IncomingTx,
+        NotificationKind::Payment,
+        NotificationKind::FeeBump,
+        NotificationKind::Confirmation,
+        NotificationKind::Sync,
+        NotificationKind::Privacy,
+    ];
+
+    /// Name used in the settings file.
+    pub fn key(&self) -> &'static str {
+        match self {
+            NotificationKind::IncomingTx => "incoming_tx",
+            NotificationKind::Payment => "payment",
+            NotificationKind::FeeBump => "fee_bump",
+            NotificationKind::Confirmation => "confirmation",
+            NotificationKind::Sync => "sync",
+            NotificationKind::Privacy => "privacy",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            NotificationKind::IncomingTx => "New transactions",
+            NotificationKind::Payment => "Sent payments",
+            NotificationKind::FeeBump => "Bumped fees",
+            NotificationKind::Confirmation => "Confirmations",
+            NotificationKind::Sync => "Download progress",
+            NotificationKind::Privacy => "Privacy warnings",
+        }
+    }
+}
+
+/// How a kind of notification is shown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delivery {
+    /// Modal dialog, rate limited.
+    Popup,
+    /// Non-modal toast, batched with the other banners.
+    Banner,
+    None,
+}
+
+impl Delivery {
+    pub const ALL: [Delivery; 3] = [Delivery::Popup, Delivery::Banner, Delivery::None];
+
+    pub fn key(&self) -> &'static str {
+        match self {
+            Delivery::Popup => "popup",
+            Delivery::Banner => "banner",
+            Delivery::None => "none",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<Delivery> {
+        Delivery::ALL
+            .into_iter()
+            .find(|delivery| delivery.key() == key)
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Delivery::Popup => "Popup",
+            Delivery::Banner => "Banner",
+            Delivery::None => "None",
+        }
+    }
+}
+
+/// Delivery of each kind of notification, routine events default to banners.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NotificationPreferences {
+    deliveries: HashMap<NotificationKind, Delivery>,
+}
+
+impl Default for NotificationPreferences {
+    fn default() -> Self {
+        let mut preferences = NotificationPreferences {
+            deliveries: HashMap::new(),
+        };
+        preferences.set(NotificationKind::IncomingTx, Delivery::Banner);
+        preferences.set(NotificationKind::Payment, Delivery::Popup);
+        preferences.set(NotificationKind::FeeBump, Delivery::Popup);
+        preferences.set(NotificationKind::Confirmation, Delivery::Banner);
+        preferences.set(NotificationKind::Sync, Delivery::Popup);
+        preferences.set(NotificationKind::Privacy, Delivery::Banner);
+        preferences
+    }
+}
+
+impl NotificationPreferences {
+    pub fn get(&self, kind: NotificationKind) -> Delivery {
+        self.deliveries
+            .get(&kind)
+            .copied()
+            .unwrap_or(Delivery::Popup)
+    }
+
+    pub fn set(&mut self, kind: NotificationKind, delivery: Delivery) {
+        self.deliveries.insert(kind, delivery);
+    }
+}
+
+/// Decides how each notification is shown. Popups of a kind are limited to one
+/// every `POPUP_INTERVAL` seconds, the rest wait in the banner batch.
+#[derive(Default)]
+pub struct NotificationManager {
+    preferences: NotificationPreferences,
+    last_popup: HashMap<NotificationKind, u64>,
+    batch: Vec<(NotificationKind, String, String)>,
+}
+
+impl NotificationManager {
+    pub fn set_preferences(&mut self, preferences: NotificationPreferences) {
+        self.preferences = preferences;
+    }
+
+    /// Returns the title and message to show in a popup now, if any.
+    pub fn notify(
+        &mut self,
+        kind: NotificationKind,
+        title: String,
+        message: String,
+        now: u64,
+    ) -> Option<(String, String)> {
+        match self.preferences.get(kind) {
+            Delivery::None => None,
+            Delivery::Popup if self.popup_allowed(kind, now) => {
+                self.last_popup.insert(kind, now);
+                Some((title, message))
+            }
+            _ => {
+                self.batch.push((kind, title, message));
+                None
+            }
+        }
+    }
+
+    fn popup_allowed(&self, kind: NotificationKind, now: u64) -> bool {
+        match self.last_popup.get(&kind) {
+            Some(last) => now.saturating_sub(*last) >= POPUP_INTERVAL,
+            None => true,
+        }
+    }
+
+    /// Empties the batch into a single banner, summarized when there is more than one.
+    pub fn take_banner(&mut self) -> Option<(String, String)> {
+        let mut batch = std::mem::take(&mut self.batch);
+        if batch.len() <= 1 {
+            return batch
+                .pop()
+                .map(|(_, title, message)| (tr(&title), tr(&message)));
+        }
+
+        let mut counts: Vec<(String, usize)> = vec![];
+        for (_, title, _) in batch.iter() {
+            match counts.iter_mut().find(|(other, _)| other == title) {
+                Some((_, count)) => *count += 1,
+                None => counts.push((title.clone(), 1)),
+            }
+        }
+        let message = counts
+            .iter()
+            .map(|(title, count)| format!("{} x {}", count, tr(title)))
+            .collect::<Vec<String>>()
+            .join("\n");
+        Some((
+            tr_fmt("{} new notifications", &[batch.len().to_string()]),
+            message,
+        ))
+    }
+}
+
+thread_local! {
+    static MANAGER: RefCell<NotificationManager> = RefCell::new(NotificationManager::default());
+    static TOAST_PARENT: RefCell<Option<gtk::Window>> = const { RefCell::new(None) };
+}
+
+/// Starts the batching of banners, shown as toasts over the main window.
+pub fn init(builder: &Builder, preferences: NotificationPreferences) {
+    set_preferences(preferences);
+    let parent: gtk::Window = builder.object("app").expect("Failed to get window");
+    TOAST_PARENT.with(|toast_parent| *toast_parent.borrow_mut() = Some(parent));
+
+    glib::timeout_add_local(Duration::from_millis(BANNER_BATCH_INTERVAL), || {
+        // The borrow ends before showing the toast
+        let banner = MANAGER.with(|manager| manager.borrow_mut().take_banner());
+        if let Some((title, message)) = banner {
+            show_toast(&title, &message);
+        }
+        glib::Continue(true)
+    });
+}
+
+pub fn set_preferences(preferences: NotificationPreferences) {
+    MANAGER.with(|manager| manager.borrow_mut().set_preferences(preferences));
+}
+
+/// Shows the notification the way the user prefers for its kind.
+pub fn notify(kind: NotificationKind, title: &str, message: &str) {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default();
+    let popup = MANAGER.with(|manager| {
+        manager
+            .borrow_mut()
+            .notify(kind, title.to_string(), message.to_string(), now)
+    });
+    if let Some((title, message)) = popup {
+        create_notification_window(
+            gtk::MessageType::__Unknown(GTK_MESSAGE_INFO),
+            &title,
+            &message,
+        );
+    }
+}
+
+fn show_toast(title: &str, message: &str) {
+    let toast = gtk::Window::new(gtk::WindowType::Toplevel);
+    TOAST_PARENT.with(|parent| {
+        if let Some(parent) = parent.borrow().as_ref() {
+            toast.set_transient_for(Some(parent));
+        }
+    });
+    toast.set_decorated(false);
+    toast.set_accept_focus(false);
+    toast.set_resizable(false);
+    toast.set_position(gtk::WindowPosition::CenterOnParent);
+
+    let label = Label::new(None);
+    label.set_markup(&format!(
+        "<b>{}</b>\n{}",
+        glib::markup_escape_text(title),
+        glib::markup_escape_text(message)
+    ));
+    label.set_line_wrap(true);
+    toast.add(&label);
+    toast.show_all();
+
+    glib::timeout_add_seconds_local(TOAST_DURATION, move || {
+        toast.close();
+        glib::Continue(false)
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn notify(manager: &mut NotificationManager, kind: NotificationKind, now: u64) -> bool {
+        manager
+            .notify(kind, "title".to_string(), "message".to_string(), now)
+            .is_some()
+    }
+
+    #[test]
+    fn test_popups_are_rate_limited_and_the_rest_batched() {
+        let mut manager = NotificationManager::default();
+
+        assert!(notify(&mut manager, NotificationKind::Payment, 100));
+        assert!(!notify(&mut manager, NotificationKind::Payment, 105));
+        assert!(notify(&mut manager, NotificationKind::FeeBump, 105));
+        assert!(notify(&mut manager, NotificationKind::Payment, 110));
+
+        assert!(manager.take_banner().is_some());
+        assert!(manager.take_banner().is_none());
+    }
+
+    #[test]
+    fn test_banners_are_summarized_in_one() {
+        let mut manager = NotificationManager::default();
+        for _ in 0..3 {
+            assert!(!notify(&mut manager, NotificationKind::IncomingTx, 100));
+        }
+
+        let (title, message) = manager.take_banner().unwrap();
+        assert!(title.contains('3'));
+        assert_eq!(message.lines().count(), 1);
+    }
+
+    #[test]
+    fn test_disabled_notifications_are_dropped() {
+        let mut preferences = NotificationPreferences::default();
+        preferences.set(NotificationKind::Confirmation, Delivery::None);
+        let mut manager = NotificationManager::default();
+        manager.set_preferences(preferences);
+
+        assert!(!notify(&mut manager, NotificationKind::Confirmation, 100));
+        assert!(manager.take_banner().is_none());
+    }
+}
@@ -0,0 +1,160 @@
+use std::rc::Rc;
+
+use btc_node::api::{RequestSender, WalletApi};
+use gtk::{
+    ffi::{GTK_MESSAGE_INFO, GTK_MESSAGE_WARNING},
+    prelude::*,
+    Builder, Entry, MenuItem, TextView,
+};
+
+use crate::{create_notification_window, i18n::tr, wallet_controller::SharedWallet};
+
+/// Proves the ownership of the address of the selected account, or checks the proof of
+/// another one, from the File menu.
+pub fn ownership_menus_on_activate(
+    builder: &Builder,
+    wallet: &SharedWallet,
+    sender: RequestSender,
+) {
+    let prove_item: MenuItem = builder
+        .object("menu_file_prove_ownership")
+        .expect("Failed to get prove ownership menu item");
+    let verify_item: MenuItem = builder
+        .object("menu_file_verify_ownership")
+        .expect("Failed to get verify ownership menu item");
+
+    let prove_builder = builder.clone();
+    let prove_wallet = Rc::clone(wallet);
+    let prove_sender = sender.clone();
+    prove_item.connect_activate(move |_| {
+        let wif = match prove_wallet.borrow().active_account() {
+            Some(account) => account.wif.clone(),
+            None => {
+                create_notification_window(
+                    gtk::MessageType::__Unknown(GTK_MESSAGE_WARNING),
+                    "Warning",
+                    "You have to select an account first",
+                );
+                return;
+            }
+        };
+        if let Some([message]) = ask_fields(&prove_builder, "Prove address ownership", ["Message"])
+        {
+            prove_sender
+                .send(WalletApi::ProveOwnership(wif, message))
+                .unwrap();
+        }
+    });
+
+    let builder = builder.clone();
+    verify_item.connect_activate(move |_| {
+        let fields = ask_fields(
+            &builder,
+            "Verify ownership proof",
+            ["Address", "Message", "Proof"],
+        );
+        if let Some([address, message, proof]) = fields {
+            sender
+                .send(WalletApi::VerifyOwnership(address, message, proof))
+                .unwrap();
+        }
+    });
+}
+
+/// Asks the text of every field in a dialog with the title, None if it was cancelled.
+fn ask_fields<const N: usize>(
+    builder: &Builder,
+    title: &str,
+    fields: [&str; N],
+) -> Option<[String; N]> {
+    let parent: gtk::Window = builder.object("app").expect("Failed to get window");
+
+    let dialog = gtk::Dialog::with_buttons(
+        Some(&tr(title)),
+        Some(&parent),
+        gtk::DialogFlags::MODAL,
+        &[
+            (&tr("Accept"), gtk::ResponseType::Accept),
+            (&tr("Cancel"), gtk::ResponseType::Cancel),
+        ],
+    );
+    let content = dialog.content_area();
+    let entries = fields.map(|field| {
+        content.add(&gtk::Label::new(Some(&tr(field))));
+        let entry = Entry::new();
+        entry.set_width_chars(60);
+        content.add(&entry);
+        entry
+    });
+    dialog.show_all();
+
+    let response = dialog.run();
+    let texts = entries.map(|entry| entry.text().to_string());
+    dialog.close();
+
+    match response {
+        gtk::ResponseType::Accept => Some(texts),
+        _ => None,
+    }
+}
+
+/// Shows the proof, selectable to copy it.
+pub fn handle_ownership_proof_message(
+    builder: &Builder,
+    address: String,
+    message: String,
+    proof: String,
+) {
+    let parent: gtk::Window = builder.object("app").expect("Failed to get window");
+
+    let dialog = gtk::Dialog::with_buttons(
+        Some(&tr("Ownership proof")),
+        Some(&parent),
+        gtk::DialogFlags::MODAL,
+        &[(&tr("Close"), gtk::ResponseType::Close)],
+    );
+    let content = dialog.content_area();
+    content.add(&gtk::Label::new(Some(&format!(
+        "{} {}\n{} {}",
+        tr("Address:"),
+        address,
+        tr("Message:"),
+        message
+    ))));
+
+    let proof_view = TextView::new();
+    proof_view.set_editable(false);
+    proof_view.set_wrap_mode(gtk::WrapMode::Char);
+    proof_view.set_size_request(500, 120);
+    if let Some(buffer) = proof_view.buffer() {
+        buffer.set_text(&proof);
+    }
+    content.add(&proof_view);
+    dialog.show_all();
+
+    dialog.run();
+    dialog.close();
+}
+
+pub fn handle_ownership_verified_message(address: String, message: String, valid: bool) {
+    let details = format!(
+        "{} {}\n{} {}",
+        tr("Address:"),
+        address,
+        tr("Message:"),
+        message
+    );
+    if valid {
+        create_notification_window(
+            gtk::MessageType::__Unknown(GTK_MESSAGE_INFO),
+            "The proof is valid",
+            &details,
+        );
+    } else {
+        create_notification_window(
+            gtk::MessageType::__Unknown(GTK_MESSAGE_WARNING),
+            "The proof is not valid",
+            &details,
+        );
+    }
+}
@@ -0,0 +1,241 @@
+use std::{cell::RefCell, rc::Rc};
+
+use btc_node::{
+    api::{RequestSender, WalletApi},
+    keys::generate_key,
+    message_header::network,
+    payment_requests::{PaymentRequest, RequestStatus},
+    utils::timestamp_to_date,
+    wallet_file::WalletEntry,
+};
+use gtk::{
+    ffi::GTK_MESSAGE_WARNING, prelude::*, Builder, CellRendererText, ComboBoxText, Entry,
+    ListStore, MenuItem, ScrolledWindow, SpinButton, TreeView, TreeViewColumn,
+};
+
+use crate::{
+    account::Account,
+    create_notification_window,
+    i18n::{tr, tr_fmt},
+    notifications::{self, NotificationKind},
+    wallet_controller::SharedWallet,
+};
+
+const COLUMNS: [&str; 6] = [
+    "Label", "Address", "Amount", "Received", "Expires", "Status",
+];
+// Minutes a new request waits for its payment by default, and at most a week
+const DEFAULT_EXPIRY_MINUTES: f64 = 60.0;
+const MAX_EXPIRY_MINUTES: f64 = 7.0 * 24.0 * 60.0;
+
+/// Payment requests reported by the node, with the rows shown in the requests dialog.
+pub struct RequestsView {
+    requests: RefCell<Vec<PaymentRequest>>,
+    store: ListStore,
+}
+
+pub type SharedRequests = Rc<RequestsView>;
+
+impl RequestsView {
+    pub fn new() -> RequestsView {
+        RequestsView {
+            requests: RefCell::new(Vec::new()),
+            store: ListStore::new(&[String::static_type(); COLUMNS.len()]),
+        }
+    }
+}
+
+fn status_text(status: RequestStatus) -> &'static str {
+    match status {
+        RequestStatus::Pending => "Pending",
+        RequestStatus::Fulfilled => "Paid",
+        RequestStatus::Expired => "Expired",
+    }
+}
+
+/// Replaces the requests with the ones reported by the node, notifying the ones that were
+/// paid or expired since the last report.
+pub fn handle_payment_requests_message(view: &SharedRequests, requests: Vec<PaymentRequest>) {
+    let previous = view.requests.replace(requests.clone());
+    for request in requests.iter() {
+        let was_pending = previous
+            .iter()
+            .any(|r| r.id == request.id && r.status == RequestStatus::Pending);
+        if !was_pending {
+            continue;
+        }
+        match request.status {
+            RequestStatus::Fulfilled => notifications::notify(
+                NotificationKind::Payment,
+                &tr("Payment request paid"),
+                &tr_fmt(
+                    "{} received {} sat",
+                    &[request.label.clone(), request.received.to_string()],
+                ),
+            ),
+            RequestStatus::Expired => notifications::notify(
+                NotificationKind::Payment,
+                &tr("Payment request expired"),
+                &tr_fmt(
+                    "{} received {} of {} sat",
+                    &[
+                        request.label.clone(),
+                        request.received.to_string(),
+                        request.amount.to_string(),
+                    ],
+                ),
+            ),
+            RequestStatus::Pending => {}
+        }
+    }
+
+    view.store.clear();
+    for request in requests.iter().rev() {
+        view.store.insert_with_values(
+            None,
+            &[
+                (0, &request.label),
+                (1, &request.address),
+                (2, &request.amount.to_string()),
+                (3, &request.received.to_string()),
+                (4, &timestamp_to_date(request.expiry)),
+                (5, &tr(status_text(request.status))),
+            ],
+        );
+    }
+}
+
+/// Opens the payment requests dialog from the File menu.
+pub fn payment_requests_menu_on_activate(
+    builder: &Builder,
+    wallet: &SharedWallet,
+    view: &SharedRequests,
+    sender: RequestSender,
+) {
+    let item: MenuItem = builder
+        .object("menu_file_payment_requests")
+        .expect("Failed to get payment requests menu item");
+
+    let builder = builder.clone();
+    let wallet = Rc::clone(wallet);
+    let view = Rc::clone(view);
+    item.connect_activate(move |_| show_payment_requests(&builder, &wallet, &view, &sender));
+}
+
+fn requests_tree(store: &ListStore) -> TreeView {
+    let tree = TreeView::with_model(store);
+    for (i, title) in COLUMNS.iter().enumerate() {
+        let column = TreeViewColumn::new();
+        let cell = CellRendererText::new();
+        column.set_title(&tr(title));
+        CellLayoutExt::pack_start(&column, &cell, true);
+        CellLayoutExt::add_attribute(&column, &cell, "text", i as i32);
+        tree.append_column(&column);
+    }
+    tree
+}
+
+/// Lists the requests and creates new ones until the dialog is closed, the list follows the
+/// node reports while it is open.
+fn show_payment_requests(
+    builder: &Builder,
+    wallet: &SharedWallet,
+    view: &SharedRequests,
+    sender: &RequestSender,
+) {
+    let parent: gtk::Window = builder.object("app").expect("Failed to get window");
+
+    let dialog = gtk::Dialog::with_buttons(
+        Some(&tr("Payment requests")),
+        Some(&parent),
+        gtk::DialogFlags::MODAL,
+        &[
+            (&tr("Create"), gtk::ResponseType::Accept),
+            (&tr("Close"), gtk::ResponseType::Close),
+        ],
+    );
+    let content = dialog.content_area();
+
+    let label_entry = Entry::new();
+    let amount_button = SpinButton::with_range(1.0, 2_100_000_000_000_000.0, 1.0);
+    let expiry_button = SpinButton::with_range(1.0, MAX_EXPIRY_MINUTES, 1.0);
+    expiry_button.set_value(DEFAULT_EXPIRY_MINUTES);
+    for (title, widget) in [
+        ("Label", label_entry.upcast_ref::<gtk::Widget>()),
+        ("Amount (sat)", amount_button.upcast_ref()),
+        ("Expires in (minutes)", expiry_button.upcast_ref()),
+    ] {
+        let row = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+        row.pack_start(&gtk::Label::new(Some(&tr(title))), true, true, 0);
+        row.pack_end(widget, false, false, 0);
+        content.add(&row);
+    }
+
+    let scrolled = ScrolledWindow::builder().min_content_height(200).build();
+    scrolled.add(&requests_tree(&view.store));
+    content.add(&scrolled);
+    dialog.show_all();
+
+    while dialog.run() == gtk::ResponseType::Accept {
+        let label = label_entry.text().to_string();
+        let amount = amount_button.value_as_int() as i64;
+        let expires_in = expiry_button.value_as_int() as u32 * 60;
+        if create_request(builder, wallet, sender, label, amount, expires_in) {
+            label_entry.set_text("");
+        }
+    }
+    dialog.close();
+}
+
+/// Asks to be paid to a fresh address, saved as a new account named after the label.
+fn create_request(
+    builder: &Builder,
+    wallet: &SharedWallet,
+    sender: &RequestSender,
+    label: String,
+    amount: i64,
+    expires_in: u32,
+) -> bool {
+    let key = generate_key(network());
+    let name = if label.is_empty() {
+        tr_fmt("Request {}", std::slice::from_ref(&key.address))
+    } else {
+        label
+    };
+    if wallet.borrow().name_is_used(&name) {
+        create_notification_window(
+            gtk::MessageType::__Unknown(GTK_MESSAGE_WARNING),
+            "Warning",
+            "Account name is already used, pick another one",
+        );
+        return false;
+    }
+
+    let combo_box_wallets: ComboBoxText = builder
+        .object("wallets_combo_box")
+        .expect("Failed to get wallet combobox");
+    combo_box_wallets.append_text(&name);
+
+    let entry = WalletEntry {
+        name: name.clone(),
+        address: key.address.clone(),
+        wif: key.wif.clone(),
+    };
+    wallet
+        .borrow_mut()
+        .add_account(Account::new(key.address.clone(), key.wif, 0, name.clone()));
+
+    sender
+        .send(WalletApi::AddAddress(key.address.clone()))
+        .unwrap();
+    sender.send(WalletApi::SaveAccount(entry)).unwrap();
+    sender
+        .send(WalletApi::CreatePaymentRequest(
+            key.address,
+            amount,
+            name,
+            expires_in,
+        ))
+        .unwrap();
+    true
+}
@@ -0,0 +1,337 @@
+use std::{cell::RefCell, rc::Rc};
+
+use btc_node::{
+    api::{InputTrace, RequestSender, WalletApi},
+    raw_transaction::unhexlify,
+    script::ScriptTrace,
+    utils::bytes_to_hex_string,
+};
+use gtk::{
+    ffi::GTK_MESSAGE_WARNING, prelude::*, Builder, Button, ComboBoxText, Entry, Label, ListStore,
+    ToggleButton,
+};
+
+use crate::{
+    create_notification_window,
+    i18n::{tr, tr_fmt},
+};
+
+pub type SharedDebugger = Rc<RefCell<ScriptDebugger>>;
+
+/// Input of a transaction whose scripts are stepped through, one operation at a time.
+#[derive(Debug, Default)]
+pub struct ScriptDebugger {
+    traces: Vec<InputTrace>,
+    input: usize,
+    /// Operations run so far, 0 before the first one
+    step: usize,
+}
+
+impl ScriptDebugger {
+    pub fn load(&mut self, traces: Vec<InputTrace>) {
+        self.traces = traces;
+        self.input = 0;
+        self.step = 0;
+    }
+
+    pub fn select_input(&mut self, input: usize) {
+        if input < self.traces.len() {
+            self.input = input;
+            self.step = 0;
+        }
+    }
+
+    fn trace(&self) -> Option<&ScriptTrace> {
+        self.traces.get(self.input)?.trace.as_ref()
+    }
+
+    fn steps(&self) -> usize {
+        self.trace().map_or(0, |trace| trace.steps.len())
+    }
+
+    pub fn next(&mut self) {
+        self.step = (self.step + 1).min(self.steps());
+    }
+
+    pub fn back(&mut self) {
+        self.step = self.step.saturating_sub(1);
+    }
+
+    pub fn can_go_next(&self) -> bool {
+        self.step < self.steps()
+    }
+
+    pub fn can_go_back(&self) -> bool {
+        self.step > 0
+    }
+
+    /// Stack left by the operations run so far, with the top first.
+    pub fn stack(&self) -> Vec<Vec<u8>> {
+        let mut stack = match (self.trace(), self.step) {
+            (Some(trace), step) if step > 0 => trace.steps[step - 1].stack.clone(),
+            _ => vec![],
+        };
+        stack.reverse();
+        stack
+    }
+
+    pub fn step_text(&self) -> String {
+        let trace = match (self.traces.get(self.input), self.trace()) {
+            (None, _) => return tr("Load a transaction to debug its scripts"),
+            (Some(_), None) => return tr("The spent output is unknown, its script can't be run"),
+            (Some(_), Some(trace)) => trace,
+        };
+        match self.step {
+            0 => tr_fmt(
+                "Step 0 of {}: nothing was run yet",
+                &[trace.steps.len().to_string()],
+            ),
+            step => tr_fmt(
+                "Step {} of {}: {}",
+                &[
+                    step.to_string(),
+                    trace.steps.len().to_string(),
+                    trace.steps[step - 1].operation.clone(),
+                ],
+            ),
+        }
+    }
+
+    /// Result of the scripts, shown once every operation was stepped through.
+    pub fn result_text(&self) -> String {
+        match self.trace() {
+            Some(trace) if self.step == trace.steps.len() => match trace.result {
+                Ok(()) => tr("The scripts succeed"),
+                Err(e) => tr_fmt("The scripts fail: {}", &[e.code().to_string()]),
+            },
+            _ => String::new(),
+        }
+    }
+
+    pub fn pubkey_script(&self) -> String {
+        match self.traces.get(self.input) {
+            Some(input) if !input.pubkey_script.is_empty() => tr_fmt(
+                "Public key script: {}",
+                &[bytes_to_hex_string(&input.pubkey_script)],
+            ),
+            _ => String::new(),
+        }
+    }
+}
+
+fn render_debugger(builder: &Builder, debugger: &ScriptDebugger) {
+    let stack_list_store: ListStore = builder
+        .object("script_stack_columns")
+        .expect("Failed to retrieve script stack list store");
+    let script_label: Label = builder
+        .object("script_page_script_label")
+        .expect("Failed to retrieve script label");
+    let step_label: Label = builder
+        .object("script_page_step_label")
+        .expect("Failed to retrieve step label");
+    let result_label: Label = builder
+        .object("script_page_result_label")
+        .expect("Failed to retrieve result label");
+    let back_button: Button = builder
+        .object("script_page_back_button")
+        .expect("Failed to retrieve back button");
+    let next_button: Button = builder
+        .object("script_page_next_button")
+        .expect("Failed to retrieve next button");
+
+    stack_list_store.clear();
+    for (i, item) in debugger.stack().iter().enumerate() {
+        let position = if i == 0 { tr("Top") } else { i.to_string() };
+        let item = if item.is_empty() {
+            tr("(empty)")
+        } else {
+            bytes_to_hex_string(item)
+        };
+        let array_of_data: &[(u32, &dyn ToValue)] = &[(0, &position), (1, &item)];
+        stack_list_store.insert_with_values(None, array_of_data);
+    }
+
+    script_label.set_text(&debugger.pubkey_script());
+    step_label.set_text(&debugger.step_text());
+    result_label.set_text(&debugger.result_text());
+    back_button.set_sensitive(debugger.can_go_back());
+    next_button.set_sensitive(debugger.can_go_next());
+}
+
+/// Requests the traces of the typed txid and steps through the scripts of the selected input.
+pub fn script_page_on_clicked(builder: &Builder, debugger: &SharedDebugger, sender: RequestSender) {
+    let load_button: Button = builder
+        .object("script_page_load_button")
+        .expect("Failed to retrieve debug button");
+    let txid_entry: Entry = builder
+        .object("script_page_txid_entry")
+        .expect("Failed to retrieve tx ID entry");
+    let combo_box: ComboBoxText = builder
+        .object("script_page_input_combo_box")
+        .expect("Failed to retrieve input combo box");
+    let back_button: Button = builder
+        .object("script_page_back_button")
+        .expect("Failed to retrieve back button");
+    let next_button: Button = builder
+        .object("script_page_next_button")
+        .expect("Failed to retrieve next button");
+
+    load_button.connect_clicked(move |_| match parse_txid(&txid_entry.text()) {
+        Some(txid) => {
+            sender.send(WalletApi::TraceScripts(txid)).unwrap();
+        }
+        None => create_notification_window(
+            gtk::MessageType::__Unknown(GTK_MESSAGE_WARNING),
+            "Warning",
+            "Invalid tx ID",
+        ),
+    });
+
+    let combo_builder = builder.clone();
+    let combo_debugger = Rc::clone(debugger);
+    combo_box.connect_changed(move |combo_box| {
+        if let Some(input) = combo_box.active() {
+            combo_debugger.borrow_mut().select_input(input as usize);
+            render_debugger(&combo_builder, &combo_debugger.borrow());
+        }
+    });
+
+    let back_builder = builder.clone();
+    let back_debugger = Rc::clone(debugger);
+    back_button.connect_clicked(move |_| {
+        back_debugger.borrow_mut().back();
+        render_debugger(&back_builder, &back_debugger.borrow());
+    });
+
+    let builder = builder.clone();
+    let debugger = Rc::clone(debugger);
+    next_button.connect_clicked(move |_| {
+        debugger.borrow_mut().next();
+        render_debugger(&builder, &debugger.borrow());
+    });
+}
+
+fn parse_txid(text: &str) -> Option<[u8; 32]> {
+    unhexlify(text.trim()).ok()?.try_into().ok()
+}
+
+pub fn handle_script_traces_message(
+    builder: &Builder,
+    debugger: &SharedDebugger,
+    traces: Vec<InputTrace>,
+) {
+    let combo_box: ComboBoxText = builder
+        .object("script_page_input_combo_box")
+        .expect("Failed to retrieve input combo box");
+
+    let inputs: Vec<String> = traces
+        .iter()
+        .enumerate()
+        .map(|(i, input)| {
+            let (txid, index) = input.previous_output;
+            tr_fmt(
+                "Input {}: spends {}:{}",
+                &[i.to_string(), bytes_to_hex_string(&txid), index.to_string()],
+            )
+        })
+        .collect();
+    debugger.borrow_mut().load(traces);
+
+    // Filling the combo box runs its changed handler, which borrows the debugger again
+    combo_box.remove_all();
+    for input in inputs.iter() {
+        combo_box.append_text(input);
+    }
+    combo_box.set_active(Some(0));
+    render_debugger(builder, &debugger.borrow());
+}
+
+/// Opens the script debugger on a transaction, from the other pages.
+pub fn debug_transaction(builder: &Builder, txid: &str) {
+    let txid_entry: Entry = builder
+        .object("script_page_txid_entry")
+        .expect("Failed to retrieve tx ID entry");
+    let load_button: Button = builder
+        .object("script_page_load_button")
+        .expect("Failed to retrieve debug button");
+    let menu_button: ToggleButton = builder
+        .object("menu_button_scripts")
+        .expect("Failed to get menu button");
+
+    txid_entry.set_text(txid);
+    load_button.clicked();
+    if !menu_button.is_active() {
+        menu_button.set_active(true);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use btc_node::script::{ScriptError, TraceStep};
+
+    fn step(operation: &str, stack: Vec<Vec<u8>>) -> TraceStep {
+        TraceStep {
+            operation: operation.to_string(),
+            stack,
+        }
+    }
+
+    fn debugger() -> ScriptDebugger {
+        let trace = ScriptTrace {
+            steps: vec![
+                step("1", vec![vec![1]]),
+                step("OP_DUP", vec![vec![1], vec![1]]),
+            ],
+            result: Err(ScriptError::OpReturn),
+        };
+        let mut debugger = ScriptDebugger::default();
+        debugger.load(vec![
+            InputTrace {
+                previous_output: ([1; 32], 0),
+                pubkey_script: vec![118, 106],
+                trace: Some(trace),
+            },
+            InputTrace {
+                previous_output: ([0; 32], u32::MAX),
+                pubkey_script: vec![],
+                trace: None,
+            },
+        ]);
+        debugger
+    }
+
+    #[test]
+    fn test_steps_stay_within_the_trace() {
+        let mut debugger = debugger();
+        assert!(!debugger.can_go_back());
+        assert!(debugger.stack().is_empty());
+
+        debugger.next();
+        debugger.next();
+        debugger.next();
+        assert!(!debugger.can_go_next());
+        assert_eq!(debugger.stack(), vec![vec![1], vec![1]]);
+        assert!(debugger.step_text().ends_with("OP_DUP"));
+        assert!(debugger.result_text().ends_with("OP_RETURN"));
+
+        debugger.back();
+        assert_eq!(debugger.stack(), vec![vec![1]]);
+        assert_eq!(debugger.result_text(), "");
+    }
+
+    #[test]
+    fn test_inputs_without_a_known_output_have_no_steps() {
+        let mut debugger = debugger();
+        debugger.next();
+        debugger.select_input(1);
+        debugger.next();
+        assert!(!debugger.can_go_next());
+        assert!(debugger.stack().is_empty());
+        assert_eq!(debugger.pubkey_script(), "");
+
+        // Inputs out of range keep the selected one
+        debugger.select_input(5);
+        assert_eq!(debugger.pubkey_script(), "");
+    }
+}
@@ -0,0 +1,418 @@
+use std::rc::Rc;
+
+use btc_node::{
+    address::validate_address,
+    api::{PaymentOptions, RequestSender, WalletApi},
+    blockchain::stats::BlockStats,
+    coin_control::CoinId,
+    constants::{
+        MAX_OP_RETURN_SIZE, SIGHASH_ALL, SIGHASH_ANYONECANPAY, SIGHASH_NONE, SIGHASH_SINGLE,
+    },
+    mempool::{estimate_fee_rate, FeeBucket},
+    raw_transaction::{estimated_p2pkh_vsize, TxOptions},
+};
+use gtk::{
+    prelude::*, Builder, Button, CheckButton, ComboBoxText, Entry, Inhibit, Label, SpinButton,
+};
+
+use crate::{
+    i18n::{tr, tr_error, tr_fmt},
+    wallet_controller::SharedWallet,
+};
+
+// Sat/vB used when the fee is left empty
+const DEFAULT_FEE_RATE: i64 = 2;
+// Shortest base58 address, shorter texts are still being typed
+const MIN_ADDRESS_LEN: usize = 26;
+
+/// Fee used for "Send max" when the user didn't enter one, for a payment spending `inputs` coins.
+pub fn estimated_fee(inputs: usize, outputs: usize) -> i64 {
+    fee_at_rate(inputs, outputs, DEFAULT_FEE_RATE as f64)
+}
+
+/// Fee of a payment spending `inputs` coins at `rate` sat/vB, rounded up.
+pub fn fee_at_rate(inputs: usize, outputs: usize, rate: f64) -> i64 {
+    (estimated_p2pkh_vsize(inputs.max(1), outputs) as f64 * rate).ceil() as i64
+}
+
+/// Balance left after paying the amount and the fee, or by how much they exceed it.
+pub fn check_amount(spendable: i64, amount: i64, fee: i64) -> Result<i64, i64> {
+    let remaining = spendable - amount - fee;
+    if remaining < 0 {
+        Err(-remaining)
+    } else {
+        Ok(remaining)
+    }
+}
+
+/// Largest amount that can be paid with the fee.
+pub fn max_amount(spendable: i64, fee: i64) -> i64 {
+    (spendable - fee).max(0)
+}
+
+/// Keeps the remaining balance label up to date and fills the amount with "Send max".
+pub fn send_form_on_changed(builder: &Builder, wallet: &SharedWallet) {
+    let amount_spin_button: SpinButton = builder
+        .object("amount_spin_button")
+        .expect("Failed to retrieve amount spin button");
+    let fee_amount_spin_button: SpinButton = builder
+        .object("fee_amount_spin_button")
+        .expect("Failed to retrieve fee spin button");
+    let send_max_button: Button = builder
+        .object("send_max_button")
+        .expect("Failed to retrieve send max button");
+
+    for spin_button in [&amount_spin_button, &fee_amount_spin_button] {
+        let builder = builder.clone();
+        let wallet = Rc::clone(wallet);
+        spin_button.connect_value_changed(move |_| {
+            let spendable = spendable_balance(&wallet);
+            show_remaining_balance(&builder, spendable);
+        });
+    }
+
+    let wallet = Rc::clone(wallet);
+    send_max_button.connect_clicked(move |_| {
+        let mut fee = fee_amount_spin_button.value() as i64;
+        if fee == 0 {
+            // Sending the max spends every spendable coin and leaves no change
+            fee = estimated_fee(spent_coins(&wallet), 1);
+            fee_amount_spin_button.set_value(fee as f64);
+        }
+        let amount = max_amount(spendable_balance(&wallet), fee);
+        amount_spin_button.set_value(amount as f64);
+    });
+}
+
+/// Asks the node for the mempool, its fee histogram fills the fee when it arrives.
+pub fn suggest_fee_button_on_clicked(builder: &Builder, sender: RequestSender) {
+    let suggest_fee_button: Button = builder
+        .object("suggest_fee_button")
+        .expect("Failed to retrieve suggest fee button");
+
+    suggest_fee_button.connect_clicked(move |_| {
+        sender.send(WalletApi::GetMempool).unwrap();
+    });
+}
+
+/// Fills the fee with the rate that gets a payment with change in the next block.
+pub fn handle_fee_histogram_message(
+    builder: &Builder,
+    wallet: &SharedWallet,
+    histogram: &[FeeBucket],
+    recent_blocks: &[BlockStats],
+) {
+    let fee_amount_spin_button: SpinButton = builder
+        .object("fee_amount_spin_button")
+        .expect("Failed to retrieve fee spin button");
+
+    let inputs = wallet
+        .borrow()
+        .active_account()
+        .map_or(1, |account| account.selected_coins.len());
+    let rate = estimate_fee_rate(histogram, recent_blocks);
+    fee_amount_spin_button.set_value(fee_at_rate(inputs, 2, rate) as f64);
+}
+
+/// Validates the pay to address while it is typed and when the entry loses the focus.
+pub fn pay_to_entry_on_changed(builder: &Builder) {
+    let pay_entry: Entry = builder
+        .object("pay_to_entry")
+        .expect("Failed to retrieve pay entry");
+    let error_label: Label = builder
+        .object("pay_to_error_label")
+        .expect("Failed to retrieve pay to error label");
+
+    let label = error_label.clone();
+    pay_entry.connect_changed(move |entry| show_address_feedback(entry, &label, false));
+    pay_entry.connect_focus_out_event(move |entry, _| {
+        show_address_feedback(entry, &error_label, true);
+        Inhibit(false)
+    });
+}
+
+/// Error of the address, None while it is empty or, unless finished, too short to judge.
+pub fn address_error(address: &str, finished: bool) -> Option<String> {
+    if address.is_empty() || (!finished && address.len() < MIN_ADDRESS_LEN) {
+        return None;
+    }
+    validate_address(address).err().map(|e| tr_error(&e))
+}
+
+fn show_address_feedback(entry: &Entry, error_label: &Label, finished: bool) {
+    let style = entry.style_context();
+    match address_error(&entry.text(), finished) {
+        Some(error) => {
+            style.add_class("error");
+            error_label.set_text(&error);
+        }
+        None => {
+            style.remove_class("error");
+            error_label.set_text("");
+        }
+    }
+}
+
+/// Bytes of the OP_RETURN data typed as text or as hex, None when it is empty.
+pub fn data_payload(text: &str, hex: bool) -> Result<Option<Vec<u8>>, String> {
+    if text.is_empty() {
+        return Ok(None);
+    }
+
+    let data = if hex {
+        decode_hex_data(text)?
+    } else {
+        text.as_bytes().to_vec()
+    };
+    if data.len() > MAX_OP_RETURN_SIZE {
+        return Err(tr_fmt(
+            "The data has {} bytes, at most {} fit in the transaction",
+            &[data.len().to_string(), MAX_OP_RETURN_SIZE.to_string()],
+        ));
+    }
+    Ok(Some(data))
+}
+
+fn decode_hex_data(text: &str) -> Result<Vec<u8>, String> {
+    let digits: Vec<char> = text.chars().filter(|c| !c.is_whitespace()).collect();
+    if !digits.len().is_multiple_of(2) {
+        return Err(tr("Hex data must have an even number of digits"));
+    }
+    digits
+        .chunks(2)
+        .map(|pair| {
+            let byte: String = pair.iter().collect();
+            u8::from_str_radix(&byte, 16).map_err(|_| tr("Hex data has invalid digits"))
+        })
+        .collect()
+}
+
+/// Sequences of the inputs separated by commas, in decimal or in hex with 0x.
+pub fn parse_sequences(text: &str) -> Result<Vec<u32>, String> {
+    text.split(',')
+        .map(|value| value.trim())
+        .filter(|value| !value.is_empty())
+        .map(|value| {
+            let parsed = match value.strip_prefix("0x") {
+                Some(hex) => u32::from_str_radix(hex, 16),
+                None => value.parse::<u32>(),
+            };
+            parsed.map_err(|_| tr_fmt("Invalid sequence: {}", &[value.to_string()]))
+        })
+        .collect()
+}
+
+/// Sighash type of the id chosen in the sighash combo box, ALL when none is chosen.
+pub fn sighash_type(id: Option<&str>, anyone_can_pay: bool) -> u8 {
+    let base = match id {
+        Some("none") => SIGHASH_NONE,
+        Some("single") => SIGHASH_SINGLE,
+        _ => SIGHASH_ALL,
+    };
+    if anyone_can_pay {
+        base | SIGHASH_ANYONECANPAY
+    } else {
+        base
+    }
+}
+
+/// Builds the options of the payment from the advanced section of the Send page.
+pub fn payment_options(
+    builder: &Builder,
+    selected_coins: Option<Vec<CoinId>>,
+) -> Result<PaymentOptions, String> {
+    let lock_time_spin_button: SpinButton = builder
+        .object("lock_time_spin_button")
+        .expect("Failed to retrieve lock time spin button");
+    let sequences_entry: Entry = builder
+        .object("sequences_entry")
+        .expect("Failed to retrieve sequences entry");
+    let rbf_check: CheckButton = builder
+        .object("rbf_check")
+        .expect("Failed to retrieve RBF check");
+    let sighash_combo_box: ComboBoxText = builder
+        .object("sighash_combo_box")
+        .expect("Failed to retrieve sighash combo box");
+    let anyonecanpay_check: CheckButton = builder
+        .object("anyonecanpay_check")
+        .expect("Failed to retrieve anyone can pay check");
+    let unconfirmed_check: CheckButton = builder
+        .object("spend_unconfirmed_check")
+        .expect("Failed to retrieve spend unconfirmed check");
+
+    Ok(PaymentOptions {
+        selected_coins,
+        data: op_return_data(builder)?,
+        spend_unconfirmed: unconfirmed_check.is_active(),
+        tx: TxOptions {
+            lock_time: lock_time_spin_button.value() as u32,
+            rbf: rbf_check.is_active(),
+            sequences: parse_sequences(&sequences_entry.text())?,
+            sighash_type: sighash_type(
+                sighash_combo_box.active_id().as_deref(),
+                anyonecanpay_check.is_active(),
+            ),
+        },
+    })
+}
+
+/// Empties the advanced section once the payment is sent.
+pub fn clear_payment_options(builder: &Builder) {
+    for id in ["op_return_entry", "sequences_entry"] {
+        let entry: Entry = builder.object(id).expect("Failed to retrieve entry");
+        entry.set_text("");
+    }
+    let lock_time_spin_button: SpinButton = builder
+        .object("lock_time_spin_button")
+        .expect("Failed to retrieve lock time spin button");
+    lock_time_spin_button.set_value(0.0);
+}
+
+/// Reads the OP_RETURN data of the advanced section of the Send page.
+pub fn op_return_data(builder: &Builder) -> Result<Option<Vec<u8>>, String> {
+    let data_entry: Entry = builder
+        .object("op_return_entry")
+        .expect("Failed to retrieve OP_RETURN entry");
+    let hex_check: CheckButton = builder
+        .object("op_return_hex_check")
+        .expect("Failed to retrieve OP_RETURN hex check");
+
+    data_payload(&data_entry.text(), hex_check.is_active())
+}
+
+/// Shows the size of the OP_RETURN data, or why it can't be attached, while it is typed.
+pub fn op_return_on_changed(builder: &Builder) {
+    let data_entry: Entry = builder
+        .object("op_return_entry")
+        .expect("Failed to retrieve OP_RETURN entry");
+    let hex_check: CheckButton = builder
+        .object("op_return_hex_check")
+        .expect("Failed to retrieve OP_RETURN hex check");
+
+    let entry_builder = builder.clone();
+    data_entry.connect_changed(move |_| show_data_size(&entry_builder));
+    let builder = builder.clone();
+    hex_check.connect_toggled(move |_| show_data_size(&builder));
+}
+
+fn show_data_size(builder: &Builder) {
+    let size_label: Label = builder
+        .object("op_return_size_label")
+        .expect("Failed to retrieve OP_RETURN size label");
+
+    let text = match op_return_data(builder) {
+        Ok(None) => String::new(),
+        Ok(Some(data)) => tr_fmt(
+            "{} / {} bytes",
+            &[data.len().to_string(), MAX_OP_RETURN_SIZE.to_string()],
+        ),
+        Err(error) => error,
+    };
+    size_label.set_text(&text);
+}
+
+fn spendable_balance(wallet: &SharedWallet) -> i64 {
+    wallet
+        .borrow()
+        .active_account()
+        .map(|account| account.spendable_balance())
+        .unwrap_or_default()
+}
+
+/// Coins spent by paying the whole spendable balance.
+fn spent_coins(wallet: &SharedWallet) -> usize {
+    let wallet = wallet.borrow();
+    let account = match wallet.active_account() {
+        Some(account) => account,
+        None => return 0,
+    };
+    if !account.selected_coins.is_empty() {
+        return account.selected_coins.len();
+    }
+    account.coins.iter().filter(|coin| !coin.frozen).count()
+}
+
+pub fn show_remaining_balance(builder: &Builder, spendable: i64) {
+    let amount_spin_button: SpinButton = builder
+        .object("amount_spin_button")
+        .expect("Failed to retrieve amount spin button");
+    let fee_amount_spin_button: SpinButton = builder
+        .object("fee_amount_spin_button")
+        .expect("Failed to retrieve fee spin button");
+    let remaining_label: Label = builder
+        .object("send_page_remaining_label")
+        .expect("Failed to retrieve remaining balance label");
+
+    let amount = amount_spin_button.value() as i64;
+    let fee = fee_amount_spin_button.value() as i64;
+    let text = match check_amount(spendable, amount, fee) {
+        Ok(remaining) => tr_fmt("Remaining after sending: {} sat", &[remaining.to_string()]),
+        Err(excess) => tr_fmt(
+            "Exceeds the spendable balance by {} sat",
+            &[excess.to_string()],
+        ),
+    };
+    remaining_label.set_text(&text);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estimated_fee_grows_with_the_coins_spent() {
+        assert_eq!(estimated_fee(1, 2), 226 * DEFAULT_FEE_RATE);
+        assert_eq!(estimated_fee(0, 1), estimated_fee(1, 1));
+        assert!(estimated_fee(3, 1) > estimated_fee(2, 1));
+    }
+
+    #[test]
+    fn test_amounts_over_the_spendable_balance_are_rejected() {
+        assert_eq!(check_amount(1000, 600, 400), Ok(0));
+        assert_eq!(check_amount(1000, 700, 400), Err(100));
+    }
+
+    #[test]
+    fn test_short_addresses_are_judged_only_when_finished() {
+        assert_eq!(address_error("mnJvq7", false), None);
+        assert!(address_error("mnJvq7", true).is_some());
+        assert_eq!(address_error("", true), None);
+        assert_eq!(
+            address_error("mnJvq7mbGiPNNhUne4FAqq27Q8xZrAsVun", false),
+            None
+        );
+    }
+
+    #[test]
+    fn test_data_payload_is_text_or_hex() {
+        assert_eq!(data_payload("", false), Ok(None));
+        assert_eq!(data_payload("hi", false), Ok(Some(b"hi".to_vec())));
+        assert_eq!(data_payload("68 69", true), Ok(Some(b"hi".to_vec())));
+        assert!(data_payload("686", true).is_err());
+        assert!(data_payload("zz", true).is_err());
+        assert!(data_payload(&"a".repeat(MAX_OP_RETURN_SIZE), false).is_ok());
+        assert!(data_payload(&"a".repeat(MAX_OP_RETURN_SIZE + 1), false).is_err());
+    }
+
+    #[test]
+    fn test_sequences_are_decimal_or_hex() {
+        assert_eq!(parse_sequences(""), Ok(vec![]));
+        assert_eq!(parse_sequences("1, 0xfffffffd,"), Ok(vec![1, 0xfffffffd]));
+        assert!(parse_sequences("1, x").is_err());
+        assert!(parse_sequences("4294967296").is_err());
+    }
+
+    #[test]
+    fn test_sighash_type_of_the_combo_box() {
+        assert_eq!(sighash_type(None, false), SIGHASH_ALL);
+        assert_eq!(sighash_type(Some("none"), false), SIGHASH_NONE);
+        assert_eq!(sighash_type(Some("single"), true), 0x83);
+    }
+
+    #[test]
+    fn test_max_amount_leaves_the_fee() {
+        assert_eq!(max_amount(1000, 452), 548);
+        assert_eq!(max_amount(100, 452), 0);
+        assert_eq!(check_amount(1000, max_amount(1000, 452), 452), Ok(0));
+    }
+}
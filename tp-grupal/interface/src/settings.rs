@@ -0,0 +1,296 @@
+use std::{fs, rc::Rc};
+
+use gtk::{
+    prelude::*, Builder, CheckButton, ComboBoxText, CssProvider, MenuItem, SpinButton, StyleContext,
+};
+
+use crate::{
+    i18n::tr,
+    notifications::{self, Delivery, NotificationKind, NotificationPreferences},
+};
+
+const SETTINGS_FILE: &str = "interface.conf";
+// Font size in points that the font scale multiplies
+const BASE_FONT_SIZE: f64 = 10.0;
+const MIN_FONT_SCALE: f64 = 0.5;
+const MAX_FONT_SCALE: f64 = 2.0;
+// The txid replaces the {} of the template
+const DEFAULT_EXPLORER_URL: &str = "https://blockstream.info/testnet/tx/{}";
+
+const DARK_CSS: &str = "
+window, dialog, .background, scrolledwindow, viewport, fixed, frame {
+    background-color: #2b2b2b;
+    color: #e6e6e6;
+}
+label { color: #e6e6e6; }
+entry, spinbutton, combobox, treeview, textview {
+    background-color: #363636;
+    color: #e6e6e6;
+}
+treeview:selected { background-color: #1f5a8a; }
+button { color: #e6e6e6; }
+";
+
+/// Appearance and notification preferences of the interface, saved as `key=value` lines.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Settings {
+    pub dark_theme: bool,
+    pub font_scale: f64,
+    pub notifications: NotificationPreferences,
+    /// URL of a transaction in the block explorer, with `{}` in place of the txid.
+    pub explorer_url: String,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            dark_theme: false,
+            font_scale: 1.0,
+            notifications: NotificationPreferences::default(),
+            explorer_url: DEFAULT_EXPLORER_URL.to_string(),
+        }
+    }
+}
+
+impl Settings {
+    /// Unknown keys and invalid values are ignored, keeping the default.
+    pub fn parse(contents: &str) -> Settings {
+        let mut settings = Settings::default();
+        for line in contents.lines() {
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => continue,
+            };
+            match key {
+                "dark_theme" => {
+                    if let Ok(dark) = value.parse() {
+                        settings.dark_theme = dark;
+                    }
+                }
+                "font_scale" => {
+                    if let Ok(scale) = value.parse::<f64>() {
+                        settings.font_scale = scale.clamp(MIN_FONT_SCALE, MAX_FONT_SCALE);
+                    }
+                }
+                "explorer_url" => {
+                    if value.contains("{}") {
+                        settings.explorer_url = value.to_string();
+                    }
+                }
+                _ => {
+                    let kind = key.strip_prefix("notify_").and_then(|kind| {
+                        NotificationKind::ALL.into_iter().find(|k| k.key() == kind)
+                    });
+                    if let (Some(kind), Some(delivery)) = (kind, Delivery::from_key(value)) {
+                        settings.notifications.set(kind, delivery);
+                    }
+                }
+            }
+        }
+        settings
+    }
+
+    pub fn to_contents(&self) -> String {
+        let mut contents = format!(
+            "dark_theme={}\nfont_scale={:.1}\nexplorer_url={}\n",
+            self.dark_theme, self.font_scale, self.explorer_url
+        );
+        for kind in NotificationKind::ALL {
+            let delivery = self.notifications.get(kind);
+            contents.push_str(&format!("notify_{}={}\n", kind.key(), delivery.key()));
+        }
+        contents
+    }
+
+    /// Block explorer URL of the transaction.
+    pub fn explorer_url_for(&self, txid: &str) -> String {
+        self.explorer_url.replace("{}", txid)
+    }
+
+    pub fn load() -> Settings {
+        fs::read_to_string(SETTINGS_FILE)
+            .map(|contents| Settings::parse(&contents))
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) {
+        if let Err(e) = fs::write(SETTINGS_FILE, self.to_contents()) {
+            eprintln!("Couldn't save the interface settings: {}", e);
+        }
+    }
+
+    fn css(&self) -> String {
+        let mut css = format!(
+            "* {{ font-size: {:.1}pt; }}\n",
+            BASE_FONT_SIZE * self.font_scale
+        );
+        if self.dark_theme {
+            css.push_str(DARK_CSS);
+        }
+        css
+    }
+}
+
+/// Applies the settings with a single css provider for the whole screen.
+pub struct Appearance {
+    provider: CssProvider,
+}
+
+impl Appearance {
+    pub fn new() -> Appearance {
+        let provider = CssProvider::new();
+        if let Some(screen) = gtk::gdk::Screen::default() {
+            StyleContext::add_provider_for_screen(
+                &screen,
+                &provider,
+                gtk::STYLE_PROVIDER_PRIORITY_APPLICATION,
+            );
+        }
+        Appearance { provider }
+    }
+
+    pub fn apply(&self, settings: &Settings) {
+        if let Some(gtk_settings) = gtk::Settings::default() {
+            gtk_settings.set_property("gtk-application-prefer-dark-theme", settings.dark_theme);
+        }
+        if let Err(e) = self.provider.load_from_data(settings.css().as_bytes()) {
+            eprintln!("Invalid interface style: {}", e);
+        }
+    }
+}
+
+/// Loads the saved settings and opens the settings dialog from the Settings menu.
+pub fn settings_menu_on_activate(builder: &Builder) {
+    let settings = Settings::load();
+    let appearance = Rc::new(Appearance::new());
+    appearance.apply(&settings);
+    notifications::init(builder, settings.notifications);
+
+    let item: MenuItem = builder
+        .object("menu_settings_preferences")
+        .expect("Failed to get settings menu item");
+    let parent: gtk::Window = builder.object("app").expect("Failed to get window");
+
+    item.connect_activate(move |_| {
+        if let Some(settings) = ask_settings(&parent, &Settings::load()) {
+            appearance.apply(&settings);
+            notifications::set_preferences(settings.notifications.clone());
+            settings.save();
+        }
+    });
+}
+
+fn ask_settings(parent: &gtk::Window, current: &Settings) -> Option<Settings> {
+    let dialog = gtk::Dialog::with_buttons(
+        Some(&tr("Settings")),
+        Some(parent),
+        gtk::DialogFlags::MODAL,
+        &[
+            (&tr("Apply"), gtk::ResponseType::Accept),
+            (&tr("Cancel"), gtk::ResponseType::Cancel),
+        ],
+    );
+
+    let dark_button = CheckButton::with_label(&tr("Dark theme"));
+    dark_button.set_active(current.dark_theme);
+    let scale_label = gtk::Label::new(Some(&tr("Font scale")));
+    let scale_button = SpinButton::with_range(MIN_FONT_SCALE, MAX_FONT_SCALE, 0.1);
+    scale_button.set_digits(1);
+    scale_button.set_value(current.font_scale);
+
+    let content = dialog.content_area();
+    content.add(&dark_button);
+    content.add(&scale_label);
+    content.add(&scale_button);
+
+    let explorer_label = gtk::Label::new(Some(&tr("Block explorer URL")));
+    let explorer_entry = gtk::Entry::new();
+    explorer_entry.set_text(&current.explorer_url);
+    content.add(&explorer_label);
+    content.add(&explorer_entry);
+
+    let notifications_label = gtk::Label::new(Some(&tr("Notifications")));
+    content.add(&notifications_label);
+    let mut delivery_combos = vec![];
+    for kind in NotificationKind::ALL {
+        let row = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+        let combo = ComboBoxText::new();
+        for delivery in Delivery::ALL {
+            combo.append(Some(delivery.key()), &tr(delivery.description()));
+        }
+        combo.set_active_id(Some(current.notifications.get(kind).key()));
+        row.pack_start(
+            &gtk::Label::new(Some(&tr(kind.description()))),
+            true,
+            true,
+            0,
+        );
+        row.pack_end(&combo, false, false, 0);
+        content.add(&row);
+        delivery_combos.push((kind, combo));
+    }
+    dialog.show_all();
+
+    let response = dialog.run();
+    let mut settings = Settings {
+        dark_theme: dark_button.is_active(),
+        font_scale: scale_button.value(),
+        notifications: current.notifications.clone(),
+        explorer_url: current.explorer_url.clone(),
+    };
+    let explorer_url = explorer_entry.text();
+    if explorer_url.contains("{}") {
+        settings.explorer_url = explorer_url.to_string();
+    }
+    for (kind, combo) in delivery_combos {
+        let delivery = combo.active_id().and_then(|id| Delivery::from_key(&id));
+        if let Some(delivery) = delivery {
+            settings.notifications.set(kind, delivery);
+        }
+    }
+    dialog.close();
+
+    match response {
+        gtk::ResponseType::Accept => Some(settings),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_settings_round_trip() {
+        let mut settings = Settings {
+            dark_theme: true,
+            font_scale: 1.3,
+            notifications: NotificationPreferences::default(),
+            explorer_url: "https://example.com/tx/{}".to_string(),
+        };
+        settings
+            .notifications
+            .set(NotificationKind::IncomingTx, Delivery::None);
+        assert_eq!(Settings::parse(&settings.to_contents()), settings);
+    }
+
+    #[test]
+    fn test_invalid_values_keep_the_defaults() {
+        let settings = Settings::parse("dark_theme=maybe\nfont_scale=9\nunknown=1");
+        assert!(!settings.dark_theme);
+        assert_eq!(settings.font_scale, MAX_FONT_SCALE);
+        assert_eq!(
+            Settings::parse("explorer_url=https://no.placeholder").explorer_url,
+            DEFAULT_EXPLORER_URL
+        );
+    }
+
+    #[test]
+    fn test_explorer_url_has_the_txid() {
+        let settings = Settings::default();
+        assert_eq!(
+            settings.explorer_url_for("abcd"),
+            "https://blockstream.info/testnet/tx/abcd"
+        );
+    }
+}
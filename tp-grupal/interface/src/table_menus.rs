@@ -0,0 +1,297 @@
+use std::rc::Rc;
+
+use btc_node::{
+    api::{RequestSender, WalletApi},
+    blockchain::{history::TxDirection, txs::Tx},
+    utils::bytes_to_hex_string,
+};
+use gtk::{
+    ffi::{GTK_MESSAGE_INFO, GTK_MESSAGE_WARNING},
+    gdk,
+    prelude::*,
+    Builder, Clipboard, Entry, Inhibit, Menu, MenuItem, TreeView,
+};
+
+use crate::{
+    account::Account,
+    create_notification_window,
+    i18n::{tr, tr_fmt},
+    mempool_page::ask_fee,
+    render,
+    settings::Settings,
+    wallet_controller::SharedWallet,
+};
+
+/// What the context menu of a table row acts on.
+struct RowInfo {
+    txid: String,
+    /// Address that received the payment.
+    address: String,
+    details: String,
+    /// Wif of the account that receives the unconfirmed payment and its txid,
+    /// the account can pay its fee with a child
+    fee_child: Option<(String, [u8; 32])>,
+}
+
+/// Selects the right clicked row and returns its txid, the first column of the tables.
+pub fn clicked_txid(tree_view: &TreeView, event: &gdk::EventButton) -> Option<String> {
+    if event.button() != 3 {
+        return None;
+    }
+
+    let (x, y) = event.position();
+    let path = match tree_view.path_at_pos(x as i32, y as i32) {
+        Some((Some(path), _, _, _)) => path,
+        _ => return None,
+    };
+    let model = tree_view.model()?;
+    let iter = model.iter(&path)?;
+    tree_view.selection().select_iter(&iter);
+
+    model.value(&iter, 0).get::<String>().ok()
+}
+
+/// Copy, block explorer, label and details actions on the transactions and pending tables.
+pub fn transactions_context_menus(builder: &Builder, wallet: &SharedWallet, sender: RequestSender) {
+    table_context_menu(
+        builder,
+        wallet,
+        sender.clone(),
+        "transactions_page_tree_view",
+        history_row,
+    );
+    table_context_menu(
+        builder,
+        wallet,
+        sender,
+        "pending_transactions_tree_view",
+        pending_row,
+    );
+}
+
+fn table_context_menu(
+    builder: &Builder,
+    wallet: &SharedWallet,
+    sender: RequestSender,
+    tree_view_id: &str,
+    find_row: fn(&Account, &str) -> Option<RowInfo>,
+) {
+    let tree_view: TreeView = builder
+        .object(tree_view_id)
+        .expect("Failed to get transactions tree view");
+
+    let parent: gtk::Window = builder.object("app").expect("Failed to get window");
+    let builder = builder.clone();
+    let wallet = Rc::clone(wallet);
+
+    tree_view.connect_button_press_event(move |tree_view, event| {
+        let txid = match clicked_txid(tree_view, event) {
+            Some(txid) => txid,
+            None => return Inhibit(false),
+        };
+        let row = match wallet
+            .borrow()
+            .active_account()
+            .and_then(|account| find_row(account, &txid))
+        {
+            Some(row) => Rc::new(row),
+            None => return Inhibit(false),
+        };
+
+        let menu = Menu::new();
+        let copy_txid_item = MenuItem::with_label(&tr("Copy tx ID"));
+        let copy_address_item = MenuItem::with_label(&tr("Copy address"));
+        let explorer_item = MenuItem::with_label(&tr("Open in block explorer"));
+        let details_item = MenuItem::with_label(&tr("View details"));
+        let label_tx_item = MenuItem::with_label(&tr("Edit label..."));
+        let label_address_item = MenuItem::with_label(&tr("Label address..."));
+        menu.append(&copy_txid_item);
+        menu.append(&copy_address_item);
+        menu.append(&explorer_item);
+        menu.append(&details_item);
+        menu.append(&label_tx_item);
+        menu.append(&label_address_item);
+        if let Some((wif, txid)) = row.fee_child.clone() {
+            let child_item = MenuItem::with_label(&tr("Pay fee with a child..."));
+            menu.append(&child_item);
+            let builder = builder.clone();
+            let sender = sender.clone();
+            child_item.connect_activate(move |_| pay_fee_with_child(&builder, &sender, &wif, txid));
+        }
+
+        let copied = Rc::clone(&row);
+        copy_txid_item.connect_activate(move |_| copy_to_clipboard(&copied.txid));
+        let copied = Rc::clone(&row);
+        copy_address_item.connect_activate(move |_| copy_to_clipboard(&copied.address));
+        let opened = Rc::clone(&row);
+        let parent = parent.clone();
+        explorer_item.connect_activate(move |_| open_in_explorer(&parent, &opened.txid));
+        for (item, key) in [
+            (&label_tx_item, row.txid.clone()),
+            (&label_address_item, row.address.clone()),
+        ] {
+            let builder = builder.clone();
+            let wallet = Rc::clone(&wallet);
+            let sender = sender.clone();
+            item.connect_activate(move |_| edit_label(&builder, &wallet, &sender, &key));
+        }
+        details_item.connect_activate(move |_| {
+            create_notification_window(
+                gtk::MessageType::__Unknown(GTK_MESSAGE_INFO),
+                "Transaction details",
+                &row.details,
+            )
+        });
+
+        menu.show_all();
+        menu.popup_at_pointer(None);
+        Inhibit(true)
+    });
+}
+
+/// Asks the fee of a child that spends the received output, so the payment confirms sooner.
+fn pay_fee_with_child(builder: &Builder, sender: &RequestSender, wif: &str, txid: [u8; 32]) {
+    if let Some(fee) = ask_fee(builder, "Pay fee with a child") {
+        sender
+            .send(WalletApi::BumpFeeWithChild(wif.to_string(), txid, fee))
+            .unwrap();
+    }
+}
+
+/// Asks the label of the txid or address, saves it in the wallet file and shows it in the history.
+fn edit_label(builder: &Builder, wallet: &SharedWallet, sender: &RequestSender, key: &str) {
+    let current = wallet.borrow().label(key).unwrap_or_default().to_string();
+    let label = match ask_label(builder, key, &current) {
+        Some(label) if label != current => label,
+        _ => return,
+    };
+
+    let updates = wallet.borrow_mut().set_label(key, &label);
+    render(builder, updates);
+    sender
+        .send(WalletApi::SetLabel(key.to_string(), label))
+        .unwrap();
+}
+
+fn ask_label(builder: &Builder, key: &str, current: &str) -> Option<String> {
+    let parent: gtk::Window = builder.object("app").expect("Failed to get window");
+
+    let dialog = gtk::Dialog::with_buttons(
+        Some(&tr("Edit label")),
+        Some(&parent),
+        gtk::DialogFlags::MODAL,
+        &[
+            (&tr("Save"), gtk::ResponseType::Accept),
+            (&tr("Cancel"), gtk::ResponseType::Cancel),
+        ],
+    );
+    let content = dialog.content_area();
+    content.add(&gtk::Label::new(Some(key)));
+    let entry = Entry::new();
+    entry.set_text(current);
+    entry.set_placeholder_text(Some(&tr("Empty to remove the label")));
+    content.add(&entry);
+    dialog.show_all();
+
+    let response = dialog.run();
+    let label = entry.text().trim().to_string();
+    dialog.close();
+
+    match response {
+        gtk::ResponseType::Accept => Some(label),
+        _ => None,
+    }
+}
+
+fn history_row(account: &Account, txid: &str) -> Option<RowInfo> {
+    let entry = account
+        .transactions
+        .iter()
+        .find(|entry| bytes_to_hex_string(&entry.tx.tx_id) == txid)?;
+
+    let address = match entry.direction {
+        TxDirection::Received => account.address.clone(),
+        TxDirection::Sent => payee_address(&entry.tx, &account.address),
+    };
+    let fee = match entry.fee {
+        Some(fee) => format!("{} sat", fee),
+        None => tr("Unknown"),
+    };
+    let details = tr_fmt(
+        "Tx ID: {}\nDate: {}\nAmount: {} sat\nFee: {}\nConfirmations: {}\nOutputs:\n{}",
+        &[
+            txid.to_string(),
+            entry.date(),
+            entry.amount.to_string(),
+            fee,
+            entry.confirmations.to_string(),
+            output_lines(&entry.tx),
+        ],
+    );
+
+    Some(RowInfo {
+        txid: txid.to_string(),
+        address,
+        details,
+        fee_child: None,
+    })
+}
+
+fn pending_row(account: &Account, txid: &str) -> Option<RowInfo> {
+    let (tx, amount, payer, payee) = account
+        .pending_tx
+        .values()
+        .find(|(tx, _, _, _)| bytes_to_hex_string(&tx.tx_id) == txid)?;
+
+    let details = tr_fmt(
+        "Tx ID: {}\nAmount: {} sat\nPayer: {}\nPayee: {}\nOutputs:\n{}",
+        &[
+            txid.to_string(),
+            amount.to_string(),
+            payer.clone(),
+            payee.clone(),
+            output_lines(tx),
+        ],
+    );
+
+    // Our own payments are bumped by replacing them, from the mempool page
+    let received = payee == &account.address && payer != &account.address;
+    Some(RowInfo {
+        txid: txid.to_string(),
+        address: payee.clone(),
+        details,
+        fee_child: received.then(|| (account.wif.clone(), tx.tx_id)),
+    })
+}
+
+/// First output that doesn't go back to the account, the account itself when there is none.
+fn payee_address(tx: &Tx, own_address: &str) -> String {
+    tx.tx_out
+        .iter()
+        .map(|output| output.pkscript.get_address())
+        .find(|address| address != own_address)
+        .unwrap_or_else(|| own_address.to_string())
+}
+
+fn output_lines(tx: &Tx) -> String {
+    tx.tx_out
+        .iter()
+        .map(|output| format!("{} sat -> {}", output.value, output.pkscript.get_address()))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn copy_to_clipboard(text: &str) {
+    Clipboard::get(&gdk::SELECTION_CLIPBOARD).set_text(text);
+}
+
+fn open_in_explorer(parent: &gtk::Window, txid: &str) {
+    let url = Settings::load().explorer_url_for(txid);
+    if gtk::show_uri_on_window(Some(parent), &url, gtk::current_event_time()).is_err() {
+        create_notification_window(
+            gtk::MessageType::__Unknown(GTK_MESSAGE_WARNING),
+            "Warning",
+            &tr_fmt("Couldn't open {}", &[url]),
+        );
+    }
+}
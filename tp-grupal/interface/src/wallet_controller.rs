@@ -0,0 +1,762 @@
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap, HashSet},
+    mem::{discriminant, Discriminant},
+    rc::Rc,
+};
+
+use btc_node::{
+    api::NodeApi,
+    blockchain::{history::HistoryEntry, txs::Tx},
+    coin_control::{Coin, CoinId},
+    utils::bytes_to_hex_string,
+    wallet_warnings::WalletWarning,
+};
+
+use crate::{
+    account::{Account, AccountEvent},
+    i18n::{tr, tr_fmt},
+    notifications::NotificationKind,
+};
+
+/// Pending transaction as shown in the table: the tx, the amount, the payer and the payee.
+pub type PendingRow = (Tx, i64, String, String);
+
+/// Row of the accounts summary: the name, the address, the confirmed and pending
+/// balances and the number of unspent outputs.
+pub type SummaryRow = (String, String, i64, i64, usize);
+
+/// Confirmed transaction as shown in the history table, with its label.
+pub type HistoryRow = (HistoryEntry, String);
+
+/// Coin of the selected account and whether it is hand-picked for the next payment.
+pub type CoinRow = (Coin, bool);
+
+pub type SharedWallet = Rc<RefCell<WalletController>>;
+
+/// Change that the GTK layer has to render after a node event.
+pub enum ViewUpdate {
+    Balances {
+        available: i64,
+        pending: i64,
+        total: i64,
+        spendable: i64,
+    },
+    PendingTransactions(Vec<PendingRow>),
+    History(Vec<HistoryRow>),
+    AccountsSummary(Vec<SummaryRow>),
+    Coins(Vec<CoinRow>),
+    Notification(NotificationKind, String, String),
+}
+
+/// State of the logged accounts, independent from the widgets.
+/// The node events are applied to the account they are addressed to and
+/// only the ones of the selected account produce updates of the view.
+#[derive(Default)]
+pub struct WalletController {
+    accounts: HashMap<String, Account>,
+    active: Option<String>,
+    /// Warnings already shown for each address, each kind is shown once
+    warned: HashSet<(String, Discriminant<WalletWarning>)>,
+    /// Labels of the open wallet, keyed by txid in hex or by address
+    labels: BTreeMap<String, String>,
+}
+
+impl WalletController {
+    pub fn new() -> WalletController {
+        WalletController::default()
+    }
+
+    pub fn add_account(&mut self, account: Account) {
+        self.accounts.insert(account.address.clone(), account);
+    }
+
+    pub fn contains_address(&self, address: &str) -> bool {
+        self.accounts.contains_key(address)
+    }
+
+    pub fn addresses(&self) -> Vec<String> {
+        self.accounts.keys().cloned().collect()
+    }
+
+    pub fn name_is_used(&self, name: &str) -> bool {
+        self.accounts.values().any(|account| account.name == name)
+    }
+
+    pub fn active_account(&self) -> Option<&Account> {
+        self.accounts.get(self.active.as_ref()?)
+    }
+
+    /// Forgets every account, like when the wallet file is closed, and empties the view.
+    pub fn clear(&mut self) -> Vec<ViewUpdate> {
+        self.accounts.clear();
+        self.active = None;
+        self.warned.clear();
+        self.labels.clear();
+        empty_view()
+    }
+
+    /// Replaces the labels, like when a wallet file is opened.
+    pub fn set_labels(&mut self, labels: BTreeMap<String, String>) -> Vec<ViewUpdate> {
+        self.labels = labels;
+        self.active_history()
+    }
+
+    /// Labels the txid or address, an empty label removes it, and returns the updated history.
+    pub fn set_label(&mut self, key: &str, label: &str) -> Vec<ViewUpdate> {
+        let label = label.trim();
+        if label.is_empty() {
+            self.labels.remove(key);
+        } else {
+            self.labels.insert(key.to_string(), label.to_string());
+        }
+        self.active_history()
+    }
+
+    pub fn label(&self, key: &str) -> Option<&str> {
+        self.labels.get(key).map(|label| label.as_str())
+    }
+
+    /// History of the selected account with the labels, for the CSV export.
+    pub fn history_rows(&self) -> Vec<HistoryRow> {
+        match self.active_account() {
+            Some(account) => history_rows(account, &self.labels),
+            None => vec![],
+        }
+    }
+
+    fn active_history(&self) -> Vec<ViewUpdate> {
+        match self.active_account() {
+            Some(account) => vec![ViewUpdate::History(history_rows(account, &self.labels))],
+            None => vec![],
+        }
+    }
+
+    /// Forgets the account with the name and returns its address.
+    pub fn remove_account(&mut self, name: &str) -> Option<String> {
+        let address = self
+            .accounts
+            .values()
+            .find(|account| account.name == name)?
+            .address
+            .clone();
+        self.accounts.remove(&address);
+        if self.active.as_deref() == Some(address.as_str()) {
+            self.active = None;
+        }
+        Some(address)
+    }
+
+    /// Renames the account and returns it, None when there is no account with the name.
+    pub fn rename_account(&mut self, name: &str, new_name: &str) -> Option<&Account> {
+        let account = self
+            .accounts
+            .values_mut()
+            .find(|account| account.name == name)?;
+        account.name = new_name.to_string();
+        Some(account)
+    }
+
+    /// Wif of the account that has the transaction pending, used to bump its fee.
+    pub fn payer_wif(&self, txid: &[u8; 32]) -> Option<String> {
+        self.accounts
+            .values()
+            .find(|account| account.pending_tx.contains_key(txid))
+            .map(|account| account.wif.clone())
+    }
+
+    /// Selects the account with the name and returns its whole view.
+    pub fn select(&mut self, name: &str) -> Vec<ViewUpdate> {
+        self.active = self
+            .accounts
+            .values()
+            .find(|account| account.name == name)
+            .map(|account| account.address.clone());
+
+        match self.active_account() {
+            None => vec![],
+            Some(account) => vec![
+                balances(account),
+                pending_transactions(account),
+                ViewUpdate::History(history_rows(account, &self.labels)),
+                coin_rows(account),
+                self.summary(),
+            ],
+        }
+    }
+
+    /// Hand-picks or unpicks a coin of the selected account.
+    pub fn select_coin(&mut self, id: CoinId, selected: bool) -> Vec<ViewUpdate> {
+        match self.active_account_mut() {
+            Some(account) => {
+                account.select_coin(id, selected);
+                vec![coin_rows(account), balances(account)]
+            }
+            None => vec![],
+        }
+    }
+
+    pub fn clear_coin_selection(&mut self) -> Vec<ViewUpdate> {
+        match self.active_account_mut() {
+            Some(account) => {
+                account.selected_coins.clear();
+                vec![coin_rows(account), balances(account)]
+            }
+            None => vec![],
+        }
+    }
+
+    /// Coins to spend in the next payment of the selected account, None to let the node pick them.
+    pub fn selected_coins(&self) -> Option<Vec<CoinId>> {
+        let account = self.active_account()?;
+        if account.selected_coins.is_empty() {
+            None
+        } else {
+            Some(account.selected_coins.clone())
+        }
+    }
+
+    fn active_account_mut(&mut self) -> Option<&mut Account> {
+        self.accounts.get_mut(self.active.as_ref()?)
+    }
+
+    /// Every account sorted by name.
+    pub fn summary(&self) -> ViewUpdate {
+        let mut rows: Vec<SummaryRow> = self
+            .accounts
+            .values()
+            .map(|account| {
+                (
+                    account.name.clone(),
+                    account.address.clone(),
+                    account.balance,
+                    account.pending_balance,
+                    account.utxo_count,
+                )
+            })
+            .collect();
+        rows.sort();
+        ViewUpdate::AccountsSummary(rows)
+    }
+
+    /// Applies the account related messages of the node, the rest produce no updates.
+    pub fn apply(&mut self, msg: NodeApi) -> Vec<ViewUpdate> {
+        match msg {
+            NodeApi::Balance(balance, addr) => self.route(&addr, AccountEvent::Balance(balance)),
+            NodeApi::AddPendingBalance(delta, addr) => {
+                self.route(&addr, AccountEvent::PendingDelta(delta))
+            }
+            NodeApi::AddConfirmedBalance(delta, addr) => {
+                self.route(&addr, AccountEvent::ConfirmedDelta(delta))
+            }
+            NodeApi::ReconciledBalance(balance, pending, addr) => {
+                // Only a balance that drifted from the node one is corrected
+                let drifted = self.accounts.get(&addr).is_some_and(|account| {
+                    (account.balance, account.pending_balance) != (balance, pending)
+                });
+                if !drifted {
+                    return vec![];
+                }
+                self.route(&addr, AccountEvent::Reconciled(balance, pending))
+            }
+            NodeApi::UtxoCount(count, addr) => self.route(&addr, AccountEvent::UtxoCount(count)),
+            NodeApi::Coins(coins, addr) => self.route(&addr, AccountEvent::Coins(coins)),
+            NodeApi::History(history, addr) => self.route(&addr, AccountEvent::History(history)),
+            NodeApi::Snapshot(snapshot) => {
+                let addr = snapshot.address.clone();
+                self.route(&addr, AccountEvent::Snapshot(snapshot))
+            }
+            NodeApi::NewTx(tx, payer_addr, addr) => {
+                let mut message = tr_fmt(
+                    "Tx ID:{} '\n' Amount {} satoshi ",
+                    &[
+                        bytes_to_hex_string(&tx.tx_id),
+                        tx.get_tx_value().to_string(),
+                    ],
+                );
+                if let Some(rate) = tx.fee_rate() {
+                    message.push('\n');
+                    message.push_str(&tr_fmt("Fee rate: {} sat/vB", &[format!("{:.2}", rate)]));
+                }
+                // Until it confirms, a replaceable payment can be taken back by the payer
+                if tx.signals_rbf() {
+                    message.push('\n');
+                    message.push_str(&tr("It can be replaced until it confirms"));
+                }
+                let notification = ViewUpdate::Notification(
+                    NotificationKind::IncomingTx,
+                    "A new transaction related to your account has arrived".to_string(),
+                    message,
+                );
+                self.route_and_notify(
+                    &addr.clone(),
+                    AccountEvent::PendingTx(tx, payer_addr, addr),
+                    notification,
+                )
+            }
+            NodeApi::PaymentConfirmation(tx, payer_address, payee_address, _amount) => {
+                // The hand-picked coins were spent by this payment
+                if let Some(account) = self.accounts.get_mut(&payer_address) {
+                    account.selected_coins.clear();
+                }
+                let notification = ViewUpdate::Notification(
+                    NotificationKind::Payment,
+                    "Succesful Payment".to_string(),
+                    "Payment correctly sent".to_string(),
+                );
+                self.route_and_notify(
+                    &payer_address.clone(),
+                    AccountEvent::PendingTx(tx, payer_address, payee_address),
+                    notification,
+                )
+            }
+            NodeApi::FeeBumped(old_txid, tx, payer_address) => {
+                let notification = ViewUpdate::Notification(
+                    NotificationKind::FeeBump,
+                    "Fee bumped".to_string(),
+                    tr_fmt(
+                        "Tx ID: {} was replaced by {}",
+                        &[
+                            bytes_to_hex_string(&old_txid),
+                            bytes_to_hex_string(&tx.tx_id),
+                        ],
+                    ),
+                );
+                let mut updates =
+                    self.route(&payer_address, AccountEvent::ReplacedTx(old_txid, tx));
+                updates.push(notification);
+                updates
+            }
+            NodeApi::FeeBumpedByChild(parent_txid, tx, addr) => {
+                let notification = ViewUpdate::Notification(
+                    NotificationKind::FeeBump,
+                    "Fee bumped".to_string(),
+                    tr_fmt(
+                        "Tx ID: {} is paid for by {}",
+                        &[
+                            bytes_to_hex_string(&parent_txid),
+                            bytes_to_hex_string(&tx.tx_id),
+                        ],
+                    ),
+                );
+                self.route_and_notify(
+                    &addr.clone(),
+                    AccountEvent::PendingTx(tx, addr.clone(), addr),
+                    notification,
+                )
+            }
+            NodeApi::ConfirmedTx(txid, addr) => {
+                // The node follows up with the updated history, which includes this transaction.
+                let mut updates = vec![ViewUpdate::Notification(
+                    NotificationKind::Confirmation,
+                    "One pending transaction is now confirmed.".to_string(),
+                    tr_fmt("TXID: {}", &[bytes_to_hex_string(&txid)]),
+                )];
+                updates.extend(self.route(&addr, AccountEvent::ConfirmedTx(txid)));
+                updates
+            }
+            NodeApi::Warning(warning, addr) => self.warn(warning, addr),
+            _ => vec![],
+        }
+    }
+
+    fn warn(&mut self, warning: WalletWarning, addr: String) -> Vec<ViewUpdate> {
+        if !self.contains_address(&addr)
+            || !self.warned.insert((addr.clone(), discriminant(&warning)))
+        {
+            return vec![];
+        }
+        let (title, message) = match warning {
+            WalletWarning::AddressReuse(received) => (
+                "Address reused",
+                tr_fmt(
+                    "The address {} received {} transactions, paying to a new address keeps your payments apart",
+                    &[addr, received.to_string()],
+                ),
+            ),
+            WalletWarning::ChangeToSameAddress => (
+                "Change sent to the same address",
+                tr_fmt(
+                    "The change of the payment went back to {}, which links the payment to your other ones",
+                    &[addr],
+                ),
+            ),
+        };
+        vec![ViewUpdate::Notification(
+            NotificationKind::Privacy,
+            title.to_string(),
+            message,
+        )]
+    }
+
+    fn route_and_notify(
+        &mut self,
+        addr: &str,
+        event: AccountEvent,
+        notification: ViewUpdate,
+    ) -> Vec<ViewUpdate> {
+        if !self.contains_address(addr) {
+            return vec![];
+        }
+        let mut updates = vec![notification];
+        updates.extend(self.route(addr, event));
+        updates
+    }
+
+    fn route(&mut self, addr: &str, event: AccountEvent) -> Vec<ViewUpdate> {
+        let is_active = self.active.as_deref() == Some(addr);
+        let account = match self.accounts.get_mut(addr) {
+            Some(account) => account,
+            None => return vec![],
+        };
+
+        let shows_balances = matches!(
+            event,
+            AccountEvent::Balance(_)
+                | AccountEvent::Reconciled(..)
+                | AccountEvent::PendingDelta(_)
+                | AccountEvent::ConfirmedDelta(_)
+        );
+        let shows_history = matches!(event, AccountEvent::History(_));
+        let shows_utxos = matches!(event, AccountEvent::UtxoCount(_));
+        let shows_coins = matches!(event, AccountEvent::Coins(_));
+        let shows_all = matches!(event, AccountEvent::Snapshot(_));
+        account.apply(event);
+
+        let mut updates = vec![];
+        if is_active && shows_all {
+            updates.push(balances(account));
+            updates.push(ViewUpdate::History(history_rows(account, &self.labels)));
+            updates.push(pending_transactions(account));
+            updates.push(coin_rows(account));
+        } else if is_active && shows_balances {
+            updates.push(balances(account));
+        } else if is_active && shows_history {
+            updates.push(ViewUpdate::History(history_rows(account, &self.labels)));
+        } else if is_active && shows_coins {
+            // Frozen coins change the spendable balance
+            updates.push(coin_rows(account));
+            updates.push(balances(account));
+        } else if is_active && !shows_utxos {
+            updates.push(pending_transactions(account));
+        }
+        // The summary lists every account, selected or not
+        if shows_balances || shows_utxos || shows_all {
+            updates.push(self.summary());
+        }
+        updates
+    }
+}
+
+/// View with no accounts.
+pub fn empty_view() -> Vec<ViewUpdate> {
+    vec![
+        ViewUpdate::Balances {
+            available: 0,
+            pending: 0,
+            total: 0,
+            spendable: 0,
+        },
+        ViewUpdate::PendingTransactions(vec![]),
+        ViewUpdate::History(vec![]),
+        ViewUpdate::AccountsSummary(vec![]),
+        ViewUpdate::Coins(vec![]),
+    ]
+}
+
+fn balances(account: &Account) -> ViewUpdate {
+    ViewUpdate::Balances {
+        available: account.balance,
+        pending: account.pending_balance,
+        total: account.total_balance(),
+        spendable: account.spendable_balance(),
+    }
+}
+
+fn coin_rows(account: &Account) -> ViewUpdate {
+    ViewUpdate::Coins(
+        account
+            .coins
+            .iter()
+            .map(|coin| (coin.clone(), account.is_selected(coin)))
+            .collect(),
+    )
+}
+
+/// Each transaction with its label, or else the one of the first labelled address it pays to.
+fn history_rows(account: &Account, labels: &BTreeMap<String, String>) -> Vec<HistoryRow> {
+    account
+        .transactions
+        .iter()
+        .map(|entry| {
+            let label = labels
+                .get(&bytes_to_hex_string(&entry.tx.tx_id))
+                .or_else(|| {
+                    entry
+                        .tx
+                        .tx_out
+                        .iter()
+                        .find_map(|output| labels.get(&output.pkscript.get_address()))
+                })
+                .cloned()
+                .unwrap_or_default();
+            (entry.clone(), label)
+        })
+        .collect()
+}
+
+fn pending_transactions(account: &Account) -> ViewUpdate {
+    ViewUpdate::PendingTransactions(account.pending_tx.values().cloned().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use btc_node::{
+        api::WalletSnapshot,
+        raw_transaction::{Outpoint, RawTransaction, TxIn, TxOut},
+        script::PubKeyScript,
+    };
+
+    fn controller() -> WalletController {
+        let mut controller = WalletController::new();
+        for (addr, name) in [("addr1", "first"), ("addr2", "second")] {
+            let account = Account::new(addr.to_string(), "wif".to_string(), 0, name.to_string());
+            controller.add_account(account);
+        }
+        controller
+    }
+
+    fn shown_balances(updates: &[ViewUpdate]) -> Option<(i64, i64, i64)> {
+        updates.iter().find_map(|update| match update {
+            ViewUpdate::Balances {
+                available,
+                pending,
+                total,
+                ..
+            } => Some((*available, *pending, *total)),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn test_each_warning_is_shown_once_per_account() {
+        let mut controller = controller();
+        let warning =
+            |reused| NodeApi::Warning(WalletWarning::AddressReuse(reused), "addr1".to_string());
+
+        assert_eq!(controller.apply(warning(5)).len(), 1);
+        assert!(controller.apply(warning(6)).is_empty());
+        let change = NodeApi::Warning(WalletWarning::ChangeToSameAddress, "addr1".to_string());
+        assert_eq!(controller.apply(change).len(), 1);
+
+        let unknown = NodeApi::Warning(WalletWarning::ChangeToSameAddress, "addr3".to_string());
+        assert!(controller.apply(unknown).is_empty());
+    }
+
+    #[test]
+    fn test_only_the_selected_account_updates_the_view() {
+        let mut controller = controller();
+        controller.select("first");
+
+        let updates = controller.apply(NodeApi::Balance(5000, "addr2".to_string()));
+        assert_eq!(shown_balances(&updates), None);
+
+        let updates = controller.apply(NodeApi::Balance(1000, "addr1".to_string()));
+        assert_eq!(shown_balances(&updates), Some((1000, 0, 1000)));
+
+        let updates = controller.select("second");
+        assert_eq!(shown_balances(&updates), Some((5000, 0, 5000)));
+    }
+
+    #[test]
+    fn test_pending_delta_is_moved_to_the_balance_on_confirmation() {
+        let mut controller = controller();
+        controller.select("first");
+        controller.apply(NodeApi::Balance(1000, "addr1".to_string()));
+
+        let updates = controller.apply(NodeApi::AddPendingBalance(-400, "addr1".to_string()));
+        assert_eq!(shown_balances(&updates), Some((1000, -400, 600)));
+        assert_eq!(
+            controller.active_account().unwrap().spendable_balance(),
+            600
+        );
+
+        let updates = controller.apply(NodeApi::AddConfirmedBalance(-400, "addr1".to_string()));
+        assert_eq!(shown_balances(&updates), Some((600, 0, 600)));
+    }
+
+    #[test]
+    fn test_only_drifted_balances_are_corrected() {
+        let mut controller = controller();
+        controller.select("first");
+        controller.apply(NodeApi::Balance(1000, "addr1".to_string()));
+
+        let same = NodeApi::ReconciledBalance(1000, 0, "addr1".to_string());
+        assert!(controller.apply(same).is_empty());
+
+        let drifted = NodeApi::ReconciledBalance(800, -100, "addr1".to_string());
+        let updates = controller.apply(drifted);
+        assert_eq!(shown_balances(&updates), Some((800, -100, 700)));
+    }
+
+    #[test]
+    fn test_clear_forgets_the_accounts() {
+        let mut controller = controller();
+        controller.select("first");
+
+        let updates = controller.clear();
+        assert_eq!(shown_balances(&updates), Some((0, 0, 0)));
+        assert!(controller.active_account().is_none());
+        assert!(!controller.contains_address("addr1"));
+        assert!(controller
+            .apply(NodeApi::Balance(10, "addr1".to_string()))
+            .is_empty());
+    }
+
+    #[test]
+    fn test_removed_accounts_stop_receiving_events() {
+        let mut controller = controller();
+        controller.select("first");
+
+        assert_eq!(
+            controller.remove_account("first"),
+            Some("addr1".to_string())
+        );
+        assert!(controller.active_account().is_none());
+        assert!(controller
+            .apply(NodeApi::Balance(10, "addr1".to_string()))
+            .is_empty());
+        assert_eq!(controller.remove_account("first"), None);
+        assert!(controller.contains_address("addr2"));
+    }
+
+    #[test]
+    fn test_renamed_accounts_keep_their_state() {
+        let mut controller = controller();
+        controller.apply(NodeApi::Balance(300, "addr2".to_string()));
+
+        assert!(controller.rename_account("second", "savings").is_some());
+        assert!(!controller.name_is_used("second"));
+        let updates = controller.select("savings");
+        assert_eq!(shown_balances(&updates), Some((300, 0, 300)));
+    }
+
+    #[test]
+    fn test_summary_lists_every_account() {
+        let mut controller = controller();
+        controller.select("first");
+        controller.apply(NodeApi::AddPendingBalance(-50, "addr1".to_string()));
+
+        let updates = controller.apply(NodeApi::UtxoCount(3, "addr2".to_string()));
+        match &updates[..] {
+            [ViewUpdate::AccountsSummary(rows)] => assert_eq!(
+                rows,
+                &vec![
+                    ("first".to_string(), "addr1".to_string(), 0, -50, 0),
+                    ("second".to_string(), "addr2".to_string(), 0, 0, 3),
+                ]
+            ),
+            _ => panic!("Expected only the summary"),
+        }
+    }
+
+    fn coin(id: u8, value: i64, frozen: bool) -> Coin {
+        Coin {
+            txid: [id; 32],
+            index: 0,
+            value,
+            confirmations: 1,
+            address: "addr1".to_string(),
+            frozen,
+        }
+    }
+
+    #[test]
+    fn test_hand_picked_coins_are_the_spendable_balance() {
+        let mut controller = controller();
+        controller.select("first");
+        controller.apply(NodeApi::Balance(700, "addr1".to_string()));
+        let coins = vec![coin(1, 100, false), coin(2, 200, true), coin(3, 400, false)];
+        let updates = controller.apply(NodeApi::Coins(coins.clone(), "addr1".to_string()));
+        assert_eq!(shown_balances(&updates), Some((700, 0, 700)));
+        assert_eq!(
+            controller.active_account().unwrap().spendable_balance(),
+            500
+        );
+
+        controller.select_coin(([2; 32], 0), true);
+        assert_eq!(controller.selected_coins(), None);
+        let updates = controller.select_coin(([3; 32], 0), true);
+        assert_eq!(controller.selected_coins(), Some(vec![([3; 32], 0)]));
+        assert!(updates
+            .iter()
+            .any(|update| matches!(update, ViewUpdate::Coins(rows) if rows[2].1)));
+        assert_eq!(
+            controller.active_account().unwrap().spendable_balance(),
+            400
+        );
+
+        // Freezing a picked coin drops it from the selection
+        let mut frozen = coins;
+        frozen[2].frozen = true;
+        controller.apply(NodeApi::Coins(frozen, "addr1".to_string()));
+        assert_eq!(controller.selected_coins(), None);
+    }
+
+    #[test]
+    fn test_history_of_an_unknown_account_is_ignored() {
+        let mut controller = controller();
+        controller.select("first");
+
+        let updates = controller.apply(NodeApi::History(vec![], "addr3".to_string()));
+        assert!(updates.is_empty());
+        let updates = controller.apply(NodeApi::History(vec![], "addr1".to_string()));
+        assert!(matches!(updates[..], [ViewUpdate::History(_)]));
+    }
+
+    #[test]
+    fn test_history_rows_show_the_tx_label_or_the_address_one() {
+        const PAYEE: &str = "mgkPm4UebNCJSRGs2Kp2aVE69G8hUEf4d7";
+        let output = TxOut::new(1000, PubKeyScript::from_address(PAYEE).unwrap().to_vec());
+        let input = TxIn::new(Outpoint::new([1; 32], 0), vec![]);
+        let tx = Tx::from_raw_tx(&RawTransaction::new(vec![input], vec![output]));
+        let txid = bytes_to_hex_string(&tx.tx_id);
+        let history = vec![HistoryEntry::new(tx, 0, 0, 1000, None, 1)];
+
+        let mut controller = controller();
+        controller.select("first");
+        controller.apply(NodeApi::History(history, "addr1".to_string()));
+
+        let label = |updates: Vec<ViewUpdate>| match &updates[..] {
+            [ViewUpdate::History(rows)] => rows[0].1.clone(),
+            _ => panic!("Expected only the history"),
+        };
+        assert_eq!(label(controller.set_label(PAYEE, "exchange")), "exchange");
+        assert_eq!(label(controller.set_label(&txid, " rent ")), "rent");
+        assert_eq!(label(controller.set_label(&txid, "")), "exchange");
+        assert_eq!(controller.label(&txid), None);
+    }
+
+    #[test]
+    fn test_snapshot_replaces_the_state_built_from_the_events() {
+        let mut controller = controller();
+        controller.select("first");
+        controller.apply(NodeApi::Balance(1000, "addr1".to_string()));
+        controller.apply(NodeApi::AddPendingBalance(-400, "addr1".to_string()));
+
+        let snapshot = WalletSnapshot {
+            address: "addr1".to_string(),
+            balance: 700,
+            pending_balance: 50,
+            coins: vec![],
+            history: vec![],
+            pending_txs: vec![],
+        };
+        let updates = controller.apply(NodeApi::Snapshot(snapshot));
+        assert_eq!(shown_balances(&updates), Some((700, 50, 750)));
+        assert!(updates
+            .iter()
+            .any(|update| matches!(update, ViewUpdate::AccountsSummary(_))));
+    }
+}
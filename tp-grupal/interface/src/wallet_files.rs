@@ -0,0 +1,214 @@
+use std::collections::BTreeMap;
+
+use btc_node::{
+    api::{RequestSender, WalletApi},
+    wallet_file::WalletEntry,
+};
+use gtk::{
+    ffi::GTK_MESSAGE_INFO, prelude::*, Builder, Button, ComboBoxText, FileChooserAction,
+    FileChooserDialog, Label, MenuItem,
+};
+
+use crate::{
+    account::Account,
+    create_notification_window,
+    i18n::{tr, tr_fmt},
+    render,
+    wallet_controller::SharedWallet,
+};
+
+/// Opens the wallet file picked in a file chooser, a new name creates an empty wallet.
+/// The node answers with the accounts of the wallet.
+pub fn wallet_buttons_on_clicked(builder: &Builder, sender: RequestSender) {
+    let open_button: Button = builder
+        .object("accounts_page_open_wallet_button")
+        .expect("Failed to retrieve open wallet button");
+    let close_button: Button = builder
+        .object("accounts_page_close_wallet_button")
+        .expect("Failed to retrieve close wallet button");
+    let parent: gtk::Window = builder.object("app").expect("Failed to get window");
+
+    let open_sender = sender.clone();
+    open_button.connect_clicked(move |_| {
+        if let Some(path) = choose_wallet_file(&parent) {
+            open_sender.send(WalletApi::OpenWallet(path)).unwrap();
+        }
+    });
+
+    close_button.connect_clicked(move |_| {
+        sender.send(WalletApi::CloseWallet).unwrap();
+    });
+}
+
+/// Backs up the open wallet to the file picked and restores the accounts of a backup into it,
+/// from the Settings menu.
+pub fn backup_menus_on_activate(builder: &Builder, sender: RequestSender) {
+    let backup_item: MenuItem = builder
+        .object("menu_settings_backup_wallet")
+        .expect("Failed to get backup wallet menu item");
+    let restore_item: MenuItem = builder
+        .object("menu_settings_restore_wallet")
+        .expect("Failed to get restore wallet menu item");
+    let parent: gtk::Window = builder.object("app").expect("Failed to get window");
+
+    let backup_parent = parent.clone();
+    let backup_sender = sender.clone();
+    backup_item.connect_activate(move |_| {
+        let chosen = choose_file(
+            &backup_parent,
+            "Backup wallet",
+            "Save",
+            FileChooserAction::Save,
+            true,
+        );
+        if let Some(path) = chosen {
+            backup_sender.send(WalletApi::BackupWallet(path)).unwrap();
+        }
+    });
+
+    restore_item.connect_activate(move |_| {
+        if let Some(path) = choose_file(
+            &parent,
+            "Restore wallet",
+            "Open",
+            FileChooserAction::Open,
+            false,
+        ) {
+            sender.send(WalletApi::RestoreWallet(path)).unwrap();
+        }
+    });
+}
+
+fn choose_wallet_file(parent: &gtk::Window) -> Option<String> {
+    // Saving lets a new name be typed, which creates an empty wallet
+    choose_file(
+        parent,
+        "Open wallet",
+        "Open",
+        FileChooserAction::Save,
+        false,
+    )
+}
+
+fn choose_file(
+    parent: &gtk::Window,
+    title: &str,
+    accept: &str,
+    action: FileChooserAction,
+    confirm_overwrite: bool,
+) -> Option<String> {
+    let dialog = FileChooserDialog::with_buttons(
+        Some(&tr(title)),
+        Some(parent),
+        action,
+        &[
+            (&tr(accept), gtk::ResponseType::Accept),
+            (&tr("Cancel"), gtk::ResponseType::Cancel),
+        ],
+    );
+    dialog.set_do_overwrite_confirmation(confirm_overwrite);
+
+    let response = dialog.run();
+    let path = dialog
+        .filename()
+        .map(|path| path.to_string_lossy().to_string());
+    dialog.close();
+
+    match response {
+        gtk::ResponseType::Accept => path,
+        _ => None,
+    }
+}
+
+pub fn handle_wallet_opened_message(
+    builder: &Builder,
+    wallet: &SharedWallet,
+    name: String,
+    entries: Vec<WalletEntry>,
+    labels: BTreeMap<String, String>,
+) {
+    add_entries(builder, wallet, &entries, labels);
+    set_open_wallet(builder, Some(&name));
+}
+
+pub fn handle_wallet_backed_up_message(path: String) {
+    create_notification_window(
+        gtk::MessageType::__Unknown(GTK_MESSAGE_INFO),
+        "Wallet backed up",
+        &tr_fmt("The wallet was saved to {}", &[path]),
+    );
+}
+
+/// Adds the accounts restored from a backup, the node sends their balances next.
+pub fn handle_wallet_restored_message(
+    builder: &Builder,
+    wallet: &SharedWallet,
+    entries: Vec<WalletEntry>,
+    labels: BTreeMap<String, String>,
+) {
+    let count = entries.len();
+    add_entries(builder, wallet, &entries, labels);
+    create_notification_window(
+        gtk::MessageType::__Unknown(GTK_MESSAGE_INFO),
+        "Wallet restored",
+        &tr_fmt("{} accounts were added", &[count.to_string()]),
+    );
+}
+
+/// Adds the accounts to the wallet and the combo box, selecting the first one if they are
+/// the only ones, and replaces the labels.
+fn add_entries(
+    builder: &Builder,
+    wallet: &SharedWallet,
+    entries: &[WalletEntry],
+    labels: BTreeMap<String, String>,
+) {
+    let combo_box: ComboBoxText = builder
+        .object("wallets_combo_box")
+        .expect("Failed to get wallet combobox");
+
+    let updates = {
+        let mut wallet = wallet.borrow_mut();
+        for entry in entries.iter() {
+            wallet.add_account(Account::new(
+                entry.address.clone(),
+                entry.wif.clone(),
+                0,
+                entry.name.clone(),
+            ));
+            combo_box.append_text(&entry.name);
+        }
+        wallet.set_labels(labels)
+    };
+    render(builder, updates);
+    // Selecting runs the changed handler, which borrows the wallet again
+    if !entries.is_empty() && combo_box.active().is_none() {
+        combo_box.set_active(Some(0));
+    }
+}
+
+pub fn handle_wallet_closed_message(builder: &Builder, wallet: &SharedWallet) {
+    let combo_box: ComboBoxText = builder
+        .object("wallets_combo_box")
+        .expect("Failed to get wallet combobox");
+    combo_box.remove_all();
+
+    let updates = wallet.borrow_mut().clear();
+    render(builder, updates);
+    set_open_wallet(builder, None);
+}
+
+fn set_open_wallet(builder: &Builder, name: Option<&str>) {
+    let label: Label = builder
+        .object("accounts_page_wallet_label")
+        .expect("Failed to get wallet label");
+    let close_button: Button = builder
+        .object("accounts_page_close_wallet_button")
+        .expect("Failed to retrieve close wallet button");
+
+    match name {
+        Some(name) => label.set_text(&tr_fmt("Wallet: {}", &[name.to_string()])),
+        None => label.set_text(&tr("No wallet open")),
+    }
+    close_button.set_sensitive(name.is_some());
+}